@@ -11,8 +11,20 @@
 //! The native FFI build integration in `flutter` itself is also incomplete and
 //! currently in-flux. See [dart-lang/sdk - vm/ffi: native assets feature #50565](https://github.com/dart-lang/sdk/issues/50565)
 //! for the current status/roadmap for this feature.
+//!
+//! In addition to the codegen itself, this tool also prunes stale generated
+//! files, including the iOS/macOS C headers (e.g. left behind after an ffi
+//! module is deleted or renamed), which would otherwise silently break the
+//! Flutter build. In `--check` mode, stale files are reported instead of
+//! deleted, and the codegen is run twice to catch any nondeterminism in the
+//! generated output.
 
-use std::{path::Path, process::Command};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 use anyhow::{format_err, Context};
 use argh::FromArgs;
@@ -22,12 +34,35 @@ use lib_flutter_rust_bridge_codegen as frb;
 #[derive(FromArgs)]
 pub struct Args {
     /// run codegen in check mode. If the generated files don't match the
-    /// checked-in versions, the tool will return an error. Beware that this
-    /// still modifies the files.
+    /// checked-in versions, if there are any stale generated files, or if
+    /// the codegen output isn't deterministic, the tool will return an error.
+    /// Beware that this still modifies the files.
     #[argh(switch)]
     pub check: bool,
+
+    /// skip the slow dart `build_runner` codegen for `freezed` types, for
+    /// faster iteration during development. Only use this if you haven't
+    /// added or changed any types exposed over ffi. Not allowed with
+    /// `--check`. This is the only step skipped: `flutter_rust_bridge` always
+    /// formats its Rust and Dart output, and this tool doesn't run a global
+    /// `cargo fmt` or touch any Xcode `.xcfilelist`s.
+    #[argh(switch)]
+    pub fast: bool,
 }
 
+/// Every file generated by `flutter_rust_bridge` contains this marker in its
+/// header.
+const FRB_GENERATED_MARKER: &str = "Generated by `flutter_rust_bridge`";
+
+/// We only look for the [`FRB_GENERATED_MARKER`] in the first few lines of
+/// each file.
+const FRB_GENERATED_MARKER_MAX_LINE: usize = 20;
+
+/// The C headers generated by `flutter_rust_bridge` don't contain the
+/// [`FRB_GENERATED_MARKER`], but they always define this dummy method, which
+/// references every ffi symbol so that Xcode doesn't strip them.
+const FRB_GENERATED_HEADER_MARKER: &str = "dummy_method_to_enforce_bundling";
+
 fn find_app_rs_dir() -> Option<&'static Path> {
     let candidates = ["app-rs/Cargo.toml", "public/app-rs/Cargo.toml"];
     for candidate in candidates {
@@ -40,6 +75,127 @@ fn find_app_rs_dir() -> Option<&'static Path> {
     None
 }
 
+/// Recursively walks `dirs` and returns all `.rs`, `.dart`, and `.h` files that
+/// look like `flutter_rust_bridge` outputs but aren't in the set of files we
+/// just generated. Also returns any `<name>.freezed.dart` files whose
+/// `<name>.dart` source is either stale or missing.
+fn find_stale_generated_files(
+    dirs: &[PathBuf],
+    generated_files: &[&Path],
+) -> anyhow::Result<Vec<PathBuf>> {
+    let generated_files = generated_files
+        .iter()
+        .map(|path| normalize_path(path))
+        .collect::<anyhow::Result<BTreeSet<_>>>()?;
+
+    let mut candidates = Vec::new();
+    for dir in dirs {
+        walk_dir(dir, &mut candidates)?;
+    }
+
+    let mut stale_files = BTreeSet::new();
+    let mut freezed_files = Vec::new();
+    for path in candidates {
+        let file_name = match path.file_name().and_then(|s| s.to_str()) {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+
+        if let Some(stem) = file_name.strip_suffix(".freezed.dart") {
+            let source = path.with_file_name(format!("{stem}.dart"));
+            freezed_files.push((path, source));
+            continue;
+        }
+
+        let is_generated = if file_name.ends_with(".h") {
+            is_frb_generated_header(&path)?
+        } else if file_name.ends_with(".rs") || file_name.ends_with(".dart") {
+            is_frb_generated(&path)?
+        } else {
+            false
+        };
+        if !is_generated {
+            continue;
+        }
+
+        if !generated_files.contains(&normalize_path(&path)?) {
+            stale_files.insert(path);
+        }
+    }
+
+    // `freezed` outputs are only stale if their source file is also gone.
+    for (path, source) in freezed_files {
+        if !source.is_file() || stale_files.contains(&source) {
+            stale_files.insert(path);
+        }
+    }
+
+    Ok(stale_files.into_iter().collect())
+}
+
+/// Push all files under `dir` (recursively) onto `out`.
+fn walk_dir(dir: &Path, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries = fs::read_dir(dir).with_context(|| {
+        format!("Failed to read directory: '{}'", dir.display())
+    })?;
+
+    for entry in entries {
+        let entry = entry.with_context(|| {
+            format!("Failed to read directory entry in '{}'", dir.display())
+        })?;
+        let file_type = entry.file_type().context("Failed to get file type")?;
+        let path = entry.path();
+
+        if file_type.is_dir() {
+            walk_dir(&path, out)?;
+        } else if file_type.is_file() {
+            out.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if the file at `path` has the [`FRB_GENERATED_MARKER`] in its
+/// header.
+fn is_frb_generated(path: &Path) -> anyhow::Result<bool> {
+    let contents = fs::read_to_string(path).with_context(|| {
+        format!("Failed to read file: '{}'", path.display())
+    })?;
+    let is_generated = contents
+        .lines()
+        .take(FRB_GENERATED_MARKER_MAX_LINE)
+        .any(|line| line.contains(FRB_GENERATED_MARKER));
+    Ok(is_generated)
+}
+
+/// Returns `true` if the C header at `path` contains the
+/// [`FRB_GENERATED_HEADER_MARKER`].
+fn is_frb_generated_header(path: &Path) -> anyhow::Result<bool> {
+    let contents = fs::read_to_string(path).with_context(|| {
+        format!("Failed to read file: '{}'", path.display())
+    })?;
+    Ok(contents.contains(FRB_GENERATED_HEADER_MARKER))
+}
+
+fn normalize_path(path: &Path) -> anyhow::Result<PathBuf> {
+    fs::canonicalize(path).with_context(|| {
+        format!("Failed to canonicalize path: '{}'", path.display())
+    })
+}
+
+/// Read the current contents of all the `files`.
+fn read_files(files: &[&Path]) -> anyhow::Result<Vec<Vec<u8>>> {
+    files
+        .iter()
+        .map(|path| {
+            fs::read(path).with_context(|| {
+                format!("Failed to read generated file: '{}'", path.display())
+            })
+        })
+        .collect()
+}
+
 fn path_to_string<P: AsRef<Path>>(path: P) -> anyhow::Result<String> {
     let path = path.as_ref();
     path.to_str().map(str::to_owned).ok_or_else(|| {
@@ -49,6 +205,12 @@ fn path_to_string<P: AsRef<Path>>(path: P) -> anyhow::Result<String> {
 
 impl Args {
     pub fn run(self) -> anyhow::Result<()> {
+        if self.check && self.fast {
+            return Err(format_err!(
+                "`--check` can't be combined with `--fast`"
+            ));
+        }
+
         let app_rs_dir = find_app_rs_dir().ok_or_else(|| {
             format_err!(
                 "failed to find app-rs directory. Try running in the base \
@@ -105,6 +267,9 @@ impl Args {
             c_output: Some(vec![path_to_string(&ios_bindings_generated_h)?]),
             extra_c_output_path: Some(vec![path_to_string(macos_path)?]),
 
+            // Skip the slow dart build step in `--fast` mode
+            no_build_runner: self.fast,
+
             // Other options
             dart3: true,
             dart_format_line_length: 80,
@@ -114,32 +279,100 @@ impl Args {
             ..Default::default()
         });
 
-        // read Rust symbols from `src/bindings.rs`.
-        let all_symbols = frb::get_symbols_if_no_duplicates(&configs)
-            .with_context(|| {
-                format!(
-                    "flutter_rust_bridge: failed to read Rust symbols from '{}'",
-                    bindings_rs.display(),
-                )
-            })?;
-        // actually generate dart and rust ffi bindings.
-        for config in configs.iter() {
-            frb::frb_codegen(config, &all_symbols).context(
-                "flutter_rust_bridge: failed to generate Rust+Dart ffi bindings",
-            )?;
+        let generated_files = [
+            bindings_generated_rs.as_path(),
+            bindings_generated_dart.as_path(),
+            bindings_generated_api_dart.as_path(),
+            ios_bindings_generated_h.as_path(),
+            macos_bindings_generated_h.as_path(),
+        ];
+
+        let run_codegen = || -> anyhow::Result<()> {
+            // read Rust symbols from `src/bindings.rs`.
+            let all_symbols = frb::get_symbols_if_no_duplicates(&configs)
+                .with_context(|| {
+                    format!(
+                        "flutter_rust_bridge: failed to read Rust symbols \
+                         from '{}'",
+                        bindings_rs.display(),
+                    )
+                })?;
+            // actually generate dart and rust ffi bindings.
+            for config in configs.iter() {
+                frb::frb_codegen(config, &all_symbols).context(
+                    "flutter_rust_bridge: failed to generate Rust+Dart ffi \
+                     bindings",
+                )?;
+            }
+            Ok(())
+        };
+
+        run_codegen()?;
+
+        // In check mode, run the codegen a second time and make sure the
+        // output doesn't change between runs.
+        if self.check {
+            let first_run = read_files(&generated_files)?;
+            run_codegen()?;
+            let second_run = read_files(&generated_files)?;
+
+            let unstable_files = generated_files
+                .iter()
+                .zip(first_run.iter().zip(second_run.iter()))
+                .filter(|(_, (first, second))| first != second)
+                .map(|(path, _)| path.display().to_string())
+                .collect::<Vec<_>>();
+
+            if !unstable_files.is_empty() {
+                return Err(format_err!(
+                    "codegen output is nondeterministic; these files changed \
+                     between two consecutive runs: {unstable_files:?}"
+                ));
+            }
+        }
+
+        // Look for any leftover generated files that we didn't just generate,
+        // e.g. from a deleted or renamed ffi module.
+        let stale_files = find_stale_generated_files(
+            &[
+                app_rs_dir.join("src"),
+                app_dir.join("lib"),
+                app_dir.join("ios/Runner"),
+                app_dir.join("macos/Runner"),
+            ],
+            &generated_files,
+        )?;
+
+        if self.check {
+            if !stale_files.is_empty() {
+                let stale_files = stale_files
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>();
+                return Err(format_err!(
+                    "found stale generated files: {stale_files:?}"
+                ));
+            }
+        } else {
+            for stale_file in &stale_files {
+                println!(
+                    "app-rs-codegen: removing stale generated file: '{}'",
+                    stale_file.display()
+                );
+                fs::remove_file(stale_file).with_context(|| {
+                    format!(
+                        "Failed to remove stale generated file: '{}'",
+                        stale_file.display()
+                    )
+                })?;
+            }
         }
 
         // run `git diff --exit-code <maybe-changed-files>` to see if any files
         // changed
         if self.check {
             let mut cmd = Command::new("git");
-            cmd.args(["diff", "--exit-code"]).args([
-                &bindings_generated_rs,
-                &bindings_generated_dart,
-                &bindings_generated_api_dart,
-                &ios_bindings_generated_h,
-                &macos_bindings_generated_h,
-            ]);
+            cmd.args(["diff", "--exit-code"]).args(generated_files);
 
             // dbg!(&cmd);
 
@@ -29,8 +29,10 @@ use tracing::{info, instrument, warn};
 use crate::{
     bindings::{Config, DeployEnv, Network},
     ffs::{Ffs, FlatFileFs},
+    outbox::{self, Outbox},
     payments::{self, PaymentDb, PaymentSyncSummary},
     secret_store::SecretStore,
+    settings::{self, SettingsDb},
     storage,
 };
 
@@ -38,6 +40,10 @@ pub struct App {
     gateway_client: GatewayClient,
     node_client: NodeClient,
     payment_db: Mutex<PaymentDb<FlatFileFs>>,
+    /// Requests made while the node was offline, replayed on payment sync.
+    outbox: Mutex<Outbox<FlatFileFs>>,
+    /// User settings, written through to the node to sync across devices.
+    settings_db: Mutex<SettingsDb<FlatFileFs>>,
 
     /// We only want one task syncing payments at a time. Ideally the dart side
     /// shouldn't let this happen, but just to be safe let's add this in.
@@ -76,10 +82,13 @@ impl App {
             config.gateway_url.clone(),
         )
         .context("Failed to build GatewayClient")?;
+        // The app doesn't rotate its root seed yet.
+        let seed_rotation = None;
         let node_client = NodeClient::new(
             rng,
             config.use_sgx,
             &root_seed,
+            seed_rotation,
             config.deploy_env.into(),
             bearer_authenticator,
             gateway_client.clone(),
@@ -95,6 +104,23 @@ impl App {
         let payment_db = PaymentDb::read(payments_ffs)
             .context("Failed to load payment db")?
             .apply(Mutex::new);
+        let outbox_ffs = FlatFileFs::create_dir_all(config.outbox_dir())
+            .context("Could not create outbox ffs")?;
+        let outbox = Outbox::read(outbox_ffs)
+            .context("Failed to load outbox")?
+            .apply(Mutex::new);
+        let settings_ffs = FlatFileFs::create_dir_all(config.settings_db_dir())
+            .context("Could not create settings ffs")?;
+        let settings_db = SettingsDb::read(settings_ffs)
+            .context("Failed to load settings db")?
+            .apply(Mutex::new);
+
+        // Restore cached gateway responses so we don't refetch them on every
+        // launch. The cache is only an optimization, so don't fail on error.
+        match storage::read_gateway_cache(&app_data_ffs) {
+            Ok(entries) => gateway_client.response_cache().extend(entries),
+            Err(e) => warn!("Could not read gateway cache: {e:#}"),
+        }
 
         // See if there is a newer version we haven't provisioned to yet.
         // If so, re-provision to it and update the latest_provisioned file.
@@ -117,6 +143,10 @@ impl App {
             measurement = %latest_release.measurement,
             "latest release",
         );
+        let cache = gateway_client.response_cache();
+        if let Err(e) = storage::write_gateway_cache(&app_data_ffs, cache) {
+            warn!("Could not write gateway cache: {e:#}");
+        }
 
         // TODO(max): Ensure that user has approved this version before
         // proceeding to re-provision.
@@ -172,6 +202,8 @@ impl App {
             gateway_client,
             node_client,
             payment_db,
+            outbox,
+            settings_db,
             payment_sync_lock: Mutex::new(()),
         }))
     }
@@ -241,10 +273,13 @@ impl App {
             config.gateway_url.clone(),
         )
         .context("Failed to build GatewayClient")?;
+        // The app doesn't rotate its root seed yet.
+        let seed_rotation = None;
         let node_client = NodeClient::new(
             rng,
             config.use_sgx,
             &root_seed,
+            seed_rotation,
             config.deploy_env.into(),
             bearer_authenticator,
             gateway_client.clone(),
@@ -259,6 +294,13 @@ impl App {
             FlatFileFs::create_clean_dir_all(config.payment_db_dir())
                 .context("Could not create payments ffs")?;
         let payment_db = Mutex::new(PaymentDb::empty(payments_ffs));
+        let outbox_ffs = FlatFileFs::create_clean_dir_all(config.outbox_dir())
+            .context("Could not create outbox ffs")?;
+        let outbox = Mutex::new(Outbox::empty(outbox_ffs));
+        let settings_ffs =
+            FlatFileFs::create_clean_dir_all(config.settings_db_dir())
+                .context("Could not create settings ffs")?;
+        let settings_db = Mutex::new(SettingsDb::empty(settings_ffs));
 
         // TODO(phlip9): retries?
 
@@ -306,6 +348,8 @@ impl App {
             node_client,
             gateway_client,
             payment_db,
+            outbox,
+            settings_db,
             payment_sync_lock: Mutex::new(()),
         })
     }
@@ -335,6 +379,23 @@ impl App {
                     )),
             };
 
+            // Settings are small, so piggyback on payment sync to pick up
+            // settings changed on other devices (or after a reinstall).
+            self.sync_settings_best_effort().await;
+
+            // Replay any requests made while we were offline before syncing,
+            // so the synced payments reflect them.
+            match outbox::replay(
+                &self.outbox,
+                &self.payment_db,
+                &self.node_client,
+            )
+            .await
+            {
+                Ok(summary) => info!("replayed outbox: {summary:?}"),
+                Err(err) => warn!("failed to replay outbox: {err:#}"),
+            }
+
             payments::sync_payments(
                 &self.payment_db,
                 &self.node_client,
@@ -356,6 +417,42 @@ impl App {
         &self.payment_db
     }
 
+    pub fn outbox(&self) -> &Mutex<Outbox<FlatFileFs>> {
+        &self.outbox
+    }
+
+    pub fn settings_db(&self) -> &Mutex<SettingsDb<FlatFileFs>> {
+        &self.settings_db
+    }
+
+    /// Set a setting locally, then write it through to the node. The setting
+    /// is saved locally even if the node is unreachable; it'll be synced on
+    /// the next successful settings sync.
+    pub async fn update_setting(
+        &self,
+        key: String,
+        value: String,
+    ) -> anyhow::Result<()> {
+        self.settings_db.lock().unwrap().set(key, value)?;
+        self.sync_settings_best_effort().await;
+        Ok(())
+    }
+
+    /// Delete a setting locally, then write the deletion through to the node.
+    pub async fn remove_setting(&self, key: &str) -> anyhow::Result<()> {
+        self.settings_db.lock().unwrap().remove(key)?;
+        self.sync_settings_best_effort().await;
+        Ok(())
+    }
+
+    async fn sync_settings_best_effort(&self) {
+        if let Err(err) =
+            settings::sync(&self.settings_db, &self.node_client).await
+        {
+            warn!("failed to sync settings: {err:#}");
+        }
+    }
+
     /// Provision to the given release and update the "latest_provisioned" file.
     async fn do_provision(
         rng: &mut impl Crng,
@@ -386,6 +483,7 @@ impl App {
             google_auth_code,
             allow_gvfs_access: true,
             encrypted_seed,
+            release_manifest: node_release.release_manifest.clone(),
         };
         node_client
             .provision(node_release.measurement, provision_req)
@@ -414,6 +512,14 @@ impl AppConfig {
         self.app_data_dir.join("payment_db")
     }
 
+    pub fn outbox_dir(&self) -> PathBuf {
+        self.app_data_dir.join("outbox")
+    }
+
+    pub fn settings_db_dir(&self) -> PathBuf {
+        self.app_data_dir.join("settings_db")
+    }
+
     pub fn build_flavor(&self) -> BuildFlavor {
         BuildFlavor {
             deploy_env: self.deploy_env,
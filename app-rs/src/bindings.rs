@@ -67,6 +67,7 @@ use common::{
             PreflightPayInvoiceResponse as PreflightPayInvoiceResponseRs,
             PreflightPayOnchainRequest as PreflightPayOnchainRequestRs,
             PreflightPayOnchainResponse as PreflightPayOnchainResponseRs,
+            RouteControls,
         },
         def::{AppGatewayApi, AppNodeRunApi},
         fiat_rates::FiatRates as FiatRatesRs,
@@ -100,7 +101,7 @@ use secrecy::Zeroize;
 pub use crate::app::App;
 use crate::{
     app::AppConfig, dart_task_handler::LxHandler, ffs::FlatFileFs, form,
-    logger, secret_store::SecretStore, storage,
+    intent, logger, outbox, qr, secret_store::SecretStore, storage,
 };
 
 // TODO(phlip9): land real async support in flutter_rust_bridge
@@ -516,6 +517,7 @@ pub struct Onchain {
     pub amount_sats: Option<u64>,
     pub label: Option<String>,
     pub message: Option<String>,
+    pub payjoin: Option<String>,
 }
 
 impl From<payment_uri::Onchain> for Onchain {
@@ -525,6 +527,7 @@ impl From<payment_uri::Onchain> for Onchain {
             amount_sats: value.amount.map(|amt| amt.sats_u64()),
             label: value.label,
             message: value.message,
+            payjoin: value.payjoin,
         }
     }
 }
@@ -611,7 +614,8 @@ pub fn form_validate_bitcoin_address(
     })
 }
 
-/// Validate whether `password` has an appropriate length.
+/// Validate whether `password` has an appropriate length and is hard enough to
+/// guess (see [`password::PasswordPolicy`]).
 ///
 /// The return type is a bit funky: `Option<String>`. `None` means
 /// `address_str` is valid, while `Some(msg)` means it is not (with given
@@ -620,10 +624,10 @@ pub fn form_validate_bitcoin_address(
 pub fn form_validate_password(
     mut password: String,
 ) -> SyncReturn<Option<String>> {
-    let result = password::validate_password_len(&password);
+    let result = password::PasswordPolicy::default().check(&password);
     password.zeroize();
     SyncReturn(match result {
-        Ok(()) => None,
+        Ok(_strength) => None,
         Err(err) => Some(err.to_string()),
     })
 }
@@ -668,6 +672,7 @@ pub struct PayOnchainRequest {
     pub amount_sats: u64,
     pub priority: ConfirmationPriority,
     pub note: Option<String>,
+    pub payjoin_endpoint: Option<String>,
 }
 
 impl TryFrom<PayOnchainRequest> for PayOnchainRequestRs {
@@ -684,6 +689,7 @@ impl TryFrom<PayOnchainRequest> for PayOnchainRequestRs {
             amount,
             priority: req.priority.into(),
             note: req.note.map(validate_note).transpose()?,
+            payjoin_endpoint: req.payjoin_endpoint,
         })
     }
 }
@@ -780,6 +786,8 @@ impl TryFrom<CreateInvoiceRequest> for CreateInvoiceRequestRs {
                 .map(Amount::try_from_sats_u64)
                 .transpose()?,
             description: value.description,
+            description_hash: None,
+            payment_metadata: None,
         })
     }
 }
@@ -827,6 +835,7 @@ impl TryFrom<PayInvoiceRequest> for PayInvoiceRequestRs {
             invoice,
             fallback_amount,
             note: value.note,
+            route_controls: RouteControls::default(),
         })
     }
 }
@@ -882,6 +891,7 @@ impl TryFrom<PreflightPayInvoiceRequest> for PreflightPayInvoiceRequestRs {
         Ok(Self {
             invoice,
             fallback_amount,
+            route_controls: RouteControls::default(),
         })
     }
 }
@@ -933,6 +943,101 @@ pub fn payment_uri_resolve_best(
         .map(PaymentMethod::from)
 }
 
+/// What the UI should do with a scanned/pasted/opened payment code.
+pub enum PaymentIntent {
+    /// Open the send sheet, prefilled with this payment.
+    Send {
+        method: PaymentMethod,
+        payee_name: Option<String>,
+    },
+    /// Show the user this error message.
+    Error { message: String },
+}
+
+impl From<intent::Intent> for PaymentIntent {
+    fn from(value: intent::Intent) -> Self {
+        match value {
+            intent::Intent::Send { method, preview } => Self::Send {
+                method: PaymentMethod::from(method),
+                payee_name: preview.payee_name,
+            },
+            intent::Intent::Error(err) => Self::Error {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+/// Handle any incoming payment code, whether from a QR scan, clipboard paste,
+/// `bitcoin:`/`lightning:` deep link, or NFC tag. Prefer this over
+/// [`payment_uri_resolve_best`] so all entry points behave the same.
+pub fn handle_payment_intent(
+    network: Network,
+    input: String,
+) -> SyncReturn<PaymentIntent> {
+    SyncReturn(PaymentIntent::from(intent::handle(&input, network.into())))
+}
+
+/// The kind of payload encoded in an animated QR code.
+#[derive(Clone, Copy, Debug)]
+pub enum QrPayloadKind {
+    Psbt,
+    Transaction,
+    Text,
+}
+
+impl From<QrPayloadKind> for qr::BbqrFileType {
+    fn from(kind: QrPayloadKind) -> Self {
+        match kind {
+            QrPayloadKind::Psbt => Self::Psbt,
+            QrPayloadKind::Transaction => Self::Transaction,
+            QrPayloadKind::Text => Self::UnicodeText,
+        }
+    }
+}
+
+/// A page of frames from an animated QR code. See [`qr_animated_frames`].
+#[frb(dart_metadata=("freezed"))]
+pub struct QrFrames {
+    /// The total number of frames in the animated QR code.
+    pub num_frames: usize,
+    /// The requested frames.
+    pub frames: Vec<String>,
+}
+
+impl From<qr::BbqrFrames> for QrFrames {
+    fn from(value: qr::BbqrFrames) -> Self {
+        Self {
+            num_frames: value.num_frames,
+            frames: value.frames,
+        }
+    }
+}
+
+/// Split `data` into the frames of an animated (BBQr) QR code, returning at
+/// most `limit` frames starting at `start_index`. The UI should render each
+/// frame as an alphanumeric-mode QR code and cycle through them, fetching
+/// more frames as it goes. Payloads which fit in one frame return a single,
+/// static frame.
+///
+/// `max_frame_len`: the max # of chars in each frame. Lower values give less
+/// dense QR codes which are easier to scan, at the cost of more frames.
+pub fn qr_animated_frames(
+    kind: QrPayloadKind,
+    data: Vec<u8>,
+    max_frame_len: Option<u32>,
+    start_index: usize,
+    limit: usize,
+) -> anyhow::Result<SyncReturn<QrFrames>> {
+    let mut config = qr::BbqrConfig::default();
+    if let Some(max_frame_len) = max_frame_len {
+        config.max_frame_len = max_frame_len as usize;
+    }
+    qr::bbqr_frames(&data, kind.into(), config, start_index, limit)
+        .map(QrFrames::from)
+        .map(SyncReturn)
+}
+
 /// Init the Rust [`tracing`] logger. Also sets the current `RUST_LOG_TX`
 /// instance, which ships Rust logs over to the dart side for printing.
 ///
@@ -1257,9 +1362,31 @@ impl AppHandle {
     ) -> anyhow::Result<()> {
         let req = UpdatePaymentNoteRs::try_from(req)?;
         // Update remote store first
-        block_on(self.inner.node_client().update_payment_note(req.clone()))
-            .map(|Empty {}| ())
-            .map_err(anyhow::Error::new)?;
+        match block_on(
+            self.inner.node_client().update_payment_note(req.clone()),
+        ) {
+            Ok(Empty {}) => (),
+            // If the node is unreachable, queue the update so we can replay it
+            // on the next payment sync.
+            Err(err) if outbox::is_offline_error(&err) => {
+                let prev_note = {
+                    let db_lock = self.inner.payment_db().lock().unwrap();
+                    let db_state = db_lock.state();
+                    db_state
+                        .get_vec_idx_by_payment_index(&req.index)
+                        .and_then(|idx| db_state.get_payment_by_vec_idx(idx))
+                        .context("Updating non-existent payment")?
+                        .note
+                        .clone()
+                };
+                self.inner
+                    .outbox()
+                    .lock()
+                    .unwrap()
+                    .enqueue_payment_note(req.clone(), prev_note)?;
+            }
+            Err(err) => return Err(anyhow::Error::new(err)),
+        }
         // Update local store after
         self.inner
             .payment_db()
@@ -1267,4 +1394,25 @@ impl AppHandle {
             .unwrap()
             .update_payment_note(req)
     }
+
+    /// Returns the JSON-encoded value of the setting `key`, if it's set.
+    pub fn get_setting(&self, key: String) -> SyncReturn<Option<String>> {
+        let db_lock = self.inner.settings_db().lock().unwrap();
+        db_lock.get(&key).map(str::to_owned).apply(SyncReturn)
+    }
+
+    /// Set the setting `key` to the JSON-encoded `value`, then sync it to the
+    /// node. The setting is saved locally even if the node is unreachable.
+    pub fn update_setting(
+        &self,
+        key: String,
+        value: String,
+    ) -> anyhow::Result<()> {
+        block_on(self.inner.update_setting(key, value))
+    }
+
+    /// Delete the setting `key`, then sync the deletion to the node.
+    pub fn remove_setting(&self, key: String) -> anyhow::Result<()> {
+        block_on(self.inner.remove_setting(&key))
+    }
 }
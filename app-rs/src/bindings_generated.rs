@@ -117,6 +117,52 @@ fn wire_payment_uri_resolve_best_impl(
         },
     )
 }
+fn wire_handle_payment_intent_impl(
+    network: impl Wire2Api<Network> + UnwindSafe,
+    input: impl Wire2Api<String> + UnwindSafe,
+) -> support::WireSyncReturn {
+    FLUTTER_RUST_BRIDGE_HANDLER.wrap_sync(
+        WrapInfo {
+            debug_name: "handle_payment_intent",
+            port: None,
+            mode: FfiCallMode::Sync,
+        },
+        move || {
+            let api_network = network.wire2api();
+            let api_input = input.wire2api();
+            Result::<_, ()>::Ok(handle_payment_intent(api_network, api_input))
+        },
+    )
+}
+fn wire_qr_animated_frames_impl(
+    kind: impl Wire2Api<QrPayloadKind> + UnwindSafe,
+    data: impl Wire2Api<Vec<u8>> + UnwindSafe,
+    max_frame_len: impl Wire2Api<Option<u32>> + UnwindSafe,
+    start_index: impl Wire2Api<usize> + UnwindSafe,
+    limit: impl Wire2Api<usize> + UnwindSafe,
+) -> support::WireSyncReturn {
+    FLUTTER_RUST_BRIDGE_HANDLER.wrap_sync(
+        WrapInfo {
+            debug_name: "qr_animated_frames",
+            port: None,
+            mode: FfiCallMode::Sync,
+        },
+        move || {
+            let api_kind = kind.wire2api();
+            let api_data = data.wire2api();
+            let api_max_frame_len = max_frame_len.wire2api();
+            let api_start_index = start_index.wire2api();
+            let api_limit = limit.wire2api();
+            qr_animated_frames(
+                api_kind,
+                api_data,
+                api_max_frame_len,
+                api_start_index,
+                api_limit,
+            )
+        },
+    )
+}
 fn wire_init_rust_log_stream_impl(
     port_: MessagePort,
     rust_log: impl Wire2Api<String> + UnwindSafe,
@@ -640,6 +686,63 @@ fn wire_update_payment_note__method__AppHandle_impl(
         },
     )
 }
+fn wire_get_setting__method__AppHandle_impl(
+    that: impl Wire2Api<AppHandle> + UnwindSafe,
+    key: impl Wire2Api<String> + UnwindSafe,
+) -> support::WireSyncReturn {
+    FLUTTER_RUST_BRIDGE_HANDLER.wrap_sync(
+        WrapInfo {
+            debug_name: "get_setting__method__AppHandle",
+            port: None,
+            mode: FfiCallMode::Sync,
+        },
+        move || {
+            let api_that = that.wire2api();
+            let api_key = key.wire2api();
+            Result::<_, ()>::Ok(AppHandle::get_setting(&api_that, api_key))
+        },
+    )
+}
+fn wire_update_setting__method__AppHandle_impl(
+    port_: MessagePort,
+    that: impl Wire2Api<AppHandle> + UnwindSafe,
+    key: impl Wire2Api<String> + UnwindSafe,
+    value: impl Wire2Api<String> + UnwindSafe,
+) {
+    FLUTTER_RUST_BRIDGE_HANDLER.wrap::<_, _, _, (), _>(
+        WrapInfo {
+            debug_name: "update_setting__method__AppHandle",
+            port: Some(port_),
+            mode: FfiCallMode::Normal,
+        },
+        move || {
+            let api_that = that.wire2api();
+            let api_key = key.wire2api();
+            let api_value = value.wire2api();
+            move |task_callback| {
+                AppHandle::update_setting(&api_that, api_key, api_value)
+            }
+        },
+    )
+}
+fn wire_remove_setting__method__AppHandle_impl(
+    port_: MessagePort,
+    that: impl Wire2Api<AppHandle> + UnwindSafe,
+    key: impl Wire2Api<String> + UnwindSafe,
+) {
+    FLUTTER_RUST_BRIDGE_HANDLER.wrap::<_, _, _, (), _>(
+        WrapInfo {
+            debug_name: "remove_setting__method__AppHandle",
+            port: Some(port_),
+            mode: FfiCallMode::Normal,
+        },
+        move || {
+            let api_that = that.wire2api();
+            let api_key = key.wire2api();
+            move |task_callback| AppHandle::remove_setting(&api_that, api_key)
+        },
+    )
+}
 // Section: wrapper structs
 
 // Section: static checks
@@ -709,6 +812,16 @@ impl Wire2Api<Network> for i32 {
     }
 }
 
+impl Wire2Api<QrPayloadKind> for i32 {
+    fn wire2api(self) -> QrPayloadKind {
+        match self {
+            0 => QrPayloadKind::Psbt,
+            1 => QrPayloadKind::Transaction,
+            2 => QrPayloadKind::Text,
+            _ => unreachable!("Invalid variant for QrPayloadKind: {}", self),
+        }
+    }
+}
 impl Wire2Api<u32> for u32 {
     fn wire2api(self) -> u32 {
         self
@@ -908,6 +1021,7 @@ impl support::IntoDart for Onchain {
             self.amount_sats.into_dart(),
             self.label.into_dart(),
             self.message.into_dart(),
+            self.payjoin.into_dart(),
         ]
         .into_dart()
     }
@@ -1001,6 +1115,27 @@ impl rust2dart::IntoIntoDart<PaymentIndex> for PaymentIndex {
     }
 }
 
+impl support::IntoDart for PaymentIntent {
+    fn into_dart(self) -> support::DartAbi {
+        match self {
+            Self::Send { method, payee_name } => vec![
+                0.into_dart(),
+                method.into_into_dart().into_dart(),
+                payee_name.into_dart(),
+            ],
+            Self::Error { message } =>
+                vec![1.into_dart(), message.into_into_dart().into_dart()],
+        }
+        .into_dart()
+    }
+}
+impl support::IntoDartExceptPrimitive for PaymentIntent {}
+impl rust2dart::IntoIntoDart<PaymentIntent> for PaymentIntent {
+    fn into_into_dart(self) -> Self {
+        self
+    }
+}
+
 impl support::IntoDart for PaymentKind {
     fn into_dart(self) -> support::DartAbi {
         match self {
@@ -1091,6 +1226,22 @@ impl rust2dart::IntoIntoDart<PreflightPayOnchainResponse>
     }
 }
 
+impl support::IntoDart for QrFrames {
+    fn into_dart(self) -> support::DartAbi {
+        vec![
+            self.num_frames.into_into_dart().into_dart(),
+            self.frames.into_into_dart().into_dart(),
+        ]
+        .into_dart()
+    }
+}
+impl support::IntoDartExceptPrimitive for QrFrames {}
+impl rust2dart::IntoIntoDart<QrFrames> for QrFrames {
+    fn into_into_dart(self) -> Self {
+        self
+    }
+}
+
 impl support::IntoDart for ShortPayment {
     fn into_dart(self) -> support::DartAbi {
         vec![
@@ -1180,6 +1331,31 @@ mod io {
         wire_payment_uri_resolve_best_impl(port_, network, uri_str)
     }
 
+    #[no_mangle]
+    pub extern "C" fn wire_handle_payment_intent(
+        network: i32,
+        input: *mut wire_uint_8_list,
+    ) -> support::WireSyncReturn {
+        wire_handle_payment_intent_impl(network, input)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wire_qr_animated_frames(
+        kind: i32,
+        data: *mut wire_uint_8_list,
+        max_frame_len: *mut u32,
+        start_index: usize,
+        limit: usize,
+    ) -> support::WireSyncReturn {
+        wire_qr_animated_frames_impl(
+            kind,
+            data,
+            max_frame_len,
+            start_index,
+            limit,
+        )
+    }
+
     #[no_mangle]
     pub extern "C" fn wire_init_rust_log_stream(
         port_: i64,
@@ -1440,6 +1616,33 @@ mod io {
         wire_update_payment_note__method__AppHandle_impl(port_, that, req)
     }
 
+    #[no_mangle]
+    pub extern "C" fn wire_get_setting__method__AppHandle(
+        that: *mut wire_AppHandle,
+        key: *mut wire_uint_8_list,
+    ) -> support::WireSyncReturn {
+        wire_get_setting__method__AppHandle_impl(that, key)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wire_update_setting__method__AppHandle(
+        port_: i64,
+        that: *mut wire_AppHandle,
+        key: *mut wire_uint_8_list,
+        value: *mut wire_uint_8_list,
+    ) {
+        wire_update_setting__method__AppHandle_impl(port_, that, key, value)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wire_remove_setting__method__AppHandle(
+        port_: i64,
+        that: *mut wire_AppHandle,
+        key: *mut wire_uint_8_list,
+    ) {
+        wire_remove_setting__method__AppHandle_impl(port_, that, key)
+    }
+
     // Section: allocate functions
 
     #[no_mangle]
@@ -1497,6 +1700,11 @@ mod io {
         )
     }
 
+    #[no_mangle]
+    pub extern "C" fn new_box_autoadd_u32_0(value: u32) -> *mut u32 {
+        support::new_leak_box_ptr(value)
+    }
+
     #[no_mangle]
     pub extern "C" fn new_box_autoadd_u64_0(value: u64) -> *mut u64 {
         support::new_leak_box_ptr(value)
@@ -1607,6 +1815,11 @@ mod io {
             Wire2Api::<PreflightPayOnchainRequest>::wire2api(*wrap).into()
         }
     }
+    impl Wire2Api<u32> for *mut u32 {
+        fn wire2api(self) -> u32 {
+            unsafe { *support::box_from_leak_ptr(self) }
+        }
+    }
     impl Wire2Api<u64> for *mut u64 {
         fn wire2api(self) -> u64 {
             unsafe { *support::box_from_leak_ptr(self) }
@@ -1665,6 +1878,7 @@ mod io {
                 amount_sats: self.amount_sats.wire2api(),
                 priority: self.priority.wire2api(),
                 note: self.note.wire2api(),
+                payjoin_endpoint: self.payjoin_endpoint.wire2api(),
             }
         }
     }
@@ -1768,6 +1982,7 @@ mod io {
         amount_sats: u64,
         priority: i32,
         note: *mut wire_uint_8_list,
+        payjoin_endpoint: *mut wire_uint_8_list,
     }
 
     #[repr(C)]
@@ -1911,6 +2126,7 @@ mod io {
                 amount_sats: Default::default(),
                 priority: Default::default(),
                 note: core::ptr::null_mut(),
+                payjoin_endpoint: core::ptr::null_mut(),
             }
         }
     }
@@ -0,0 +1,167 @@
+//! Turn any incoming payment string into a typed [`Intent`] for the UI.
+//!
+//! Payment codes reach the app in many ways: QR scans, pasted text,
+//! `bitcoin:`/`lightning:` deep links, NFC payloads. All of them should go
+//! through [`handle`] so they're parsed and validated the same way, leaving
+//! the UI to just render the result.
+
+use std::fmt;
+
+use common::cli::Network;
+use payment_uri::{PaymentMethod, PaymentPreview, PaymentUri};
+
+/// What the UI should do with an incoming payment string.
+pub enum Intent {
+    /// Open the send flow, prefilled with the given payment.
+    Send {
+        method: PaymentMethod,
+        preview: PaymentPreview,
+    },
+    /// Show the user an error.
+    Error(IntentError),
+}
+
+/// Why an incoming payment string can't be paid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IntentError {
+    /// Not a payment code we understand.
+    Unrecognized,
+    /// A valid payment code, but for a different network (e.g. testnet).
+    WrongNetwork,
+    /// A valid payment code, but only payable via methods we don't support
+    /// yet (e.g. BOLT12 offers).
+    Unsupported,
+    /// The only payable method is an expired invoice.
+    Expired,
+}
+
+impl fmt::Display for IntentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Unrecognized => "Unrecognized payment code",
+            Self::WrongNetwork =>
+                "This payment code is for a different Bitcoin network",
+            Self::Unsupported =>
+                "Lexe doesn't support this kind of payment code yet",
+            Self::Expired => "This invoice has expired",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Parse and validate an incoming payment string, picking the best method to
+/// pay it with.
+pub fn handle(input: &str, network: Network) -> Intent {
+    let uri = match PaymentUri::parse(input) {
+        Some(uri) => uri,
+        None => return Intent::Error(IntentError::Unrecognized),
+    };
+    let method = match resolve(uri, network) {
+        Ok(method) => method,
+        Err(err) => return Intent::Error(err),
+    };
+    // Only describe the method we'll actually pay.
+    match method.preview() {
+        Ok(preview) => Intent::Send { method, preview },
+        Err(_) => Intent::Error(IntentError::Unsupported),
+    }
+}
+
+/// Pick the best [`PaymentMethod`] in `uri` which we can pay on `network`.
+fn resolve(
+    uri: PaymentUri,
+    network: Network,
+) -> Result<PaymentMethod, IntentError> {
+    // Each check below only fails if it removed every remaining method, so
+    // the user sees the most specific reason their code can't be paid.
+    let mut methods = uri.flatten();
+    if methods.is_empty() {
+        return Err(IntentError::Unrecognized);
+    }
+
+    methods.retain(|method| method.supports_network(network));
+    if methods.is_empty() {
+        return Err(IntentError::WrongNetwork);
+    }
+
+    // TODO(phlip9): remove when BOLT12 support
+    methods.retain(|method| !method.is_offer());
+    if methods.is_empty() {
+        return Err(IntentError::Unsupported);
+    }
+
+    // An expired invoice may still have a usable onchain fallback.
+    methods.retain(|method| match method {
+        PaymentMethod::Invoice(invoice) => !invoice.is_expired(),
+        _ => true,
+    });
+
+    methods
+        .into_iter()
+        .max_by_key(|method| match method {
+            PaymentMethod::Invoice(_) => 2,
+            PaymentMethod::Onchain(_) => 1,
+            PaymentMethod::Offer(_) => 0,
+        })
+        .ok_or(IntentError::Expired)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ADDRESS: &str =
+        "bc1qm9r9x9h2c9wptaz0873vyfv8ckx2lcdx8f48ucttzqft7r0q2yasxkt2lw";
+    const OFFER: &str =
+        "lno1pgqpvggzfyqv8gg09k4q35tc5mkmzr7re2nm20gw5qp5d08r3w5s6zzu4t5q";
+    // Mainnet invoice which expires in the year 2268
+    const INVOICE: &str = "lnbc1gcssw9pdqqpp54dkfmzgm5cqz4hzz24mpl7xtgz55dsuh430ap4rlugvywlm4syhqsp5qqtk8n0x2wa6ajl32mp6hj8u9vs55s5lst4s2rws3he4622w08es9qyysgqcqypt3ffpp36sw424yacusmj3hy32df9g97nlwm0a3e0yxw4nd8uau2zdw85lfl5w0h3mggd5g3qswxr9lje0el8g98vul9yec59gf0zxu3eg9rhda09ducxpupsfh36ks9jez7aamsn7hpkxqpw2xyek";
+
+    fn error(input: &str, network: Network) -> Option<IntentError> {
+        match handle(input, network) {
+            Intent::Send { .. } => None,
+            Intent::Error(err) => Some(err),
+        }
+    }
+
+    fn method(input: &str, network: Network) -> PaymentMethod {
+        match handle(input, network) {
+            Intent::Send { method, .. } => method,
+            Intent::Error(err) => panic!("Unexpected error: {err}"),
+        }
+    }
+
+    #[test]
+    fn intent_errors() {
+        let mainnet = Network::MAINNET;
+        let testnet = Network::TESTNET;
+
+        assert_eq!(error("hello", mainnet), Some(IntentError::Unrecognized));
+        assert_eq!(
+            error(&format!("bitcoin:{ADDRESS}?req-foo=bar"), mainnet),
+            Some(IntentError::Unrecognized),
+        );
+        assert_eq!(error(ADDRESS, testnet), Some(IntentError::WrongNetwork));
+        assert_eq!(error(INVOICE, testnet), Some(IntentError::WrongNetwork));
+        assert_eq!(error(OFFER, mainnet), Some(IntentError::Unsupported));
+        assert_eq!(error(ADDRESS, mainnet), None);
+    }
+
+    #[test]
+    fn intent_prefers_invoice() {
+        let mainnet = Network::MAINNET;
+
+        // QR codes and deep links may be surrounded by whitespace.
+        let input = format!("  bitcoin:{ADDRESS}?lightning={INVOICE}\n");
+        assert!(method(&input, mainnet).is_invoice());
+
+        let input = format!("bitcoin:{ADDRESS}?amount=0.0001&b12={OFFER}");
+        match handle(&input, mainnet) {
+            Intent::Send { method, preview } => {
+                assert!(method.is_onchain());
+                assert_eq!(preview.amount.map(|a| a.sats_u64()), Some(10_000));
+            }
+            Intent::Error(err) => panic!("Unexpected error: {err}"),
+        }
+    }
+}
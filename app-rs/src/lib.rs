@@ -19,12 +19,20 @@ mod dart_task_handler;
 mod ffs;
 /// UI form input helpers.
 mod form;
+/// Parse and validate incoming payment codes from QR scans, deep links, etc.
+mod intent;
 /// Pipe `tracing` log messages from native Rust to Dart.
 mod logger;
+/// Persistent queue of requests made while the node is offline.
+pub mod outbox;
 /// App-local payment db and payment sync from node.
 pub mod payments;
+/// Animated multi-part QR code encoding.
+mod qr;
 /// Securely store and retrieve user credentials to and from each platform's
 /// standard secret storage.
 pub mod secret_store;
+/// App-local settings db, synced across devices via the user node.
+pub mod settings;
 /// Misc utilities related to local app storage.
 pub mod storage;
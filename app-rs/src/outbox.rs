@@ -0,0 +1,356 @@
+//! An app-local outbox of mutating requests made while offline.
+//!
+//! Some requests, like updating a payment's note, are idempotent and don't
+//! need the user node to be online to make sense to the user. When the node is
+//! unreachable, we apply these requests to the local [`PaymentDb`] right away
+//! and queue them in the [`Outbox`], which is persisted to its own [`Ffs`].
+//! The next time we sync payments, we replay the queued requests against the
+//! node.
+//!
+//! ### Conflicts
+//!
+//! Each queued request remembers the value it replaced locally. Before
+//! replaying a request, we fetch the current value from the node. If the node's
+//! value no longer matches the value we replaced, someone else (e.g. another
+//! device) updated it in the meantime. The node is the source-of-truth, so the
+//! node's value wins; we drop the queued request and overwrite our local copy.
+//!
+//! [`PaymentDb`]: crate::payments::PaymentDb
+
+use std::{io, sync::Mutex};
+
+use anyhow::Context;
+use common::{
+    api::{
+        def::AppNodeRunApi,
+        error::{NodeApiError, NodeErrorKind},
+        qs::{GetPaymentsByIds, UpdatePaymentNote},
+    },
+    ln::payments::PaymentIndex,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::{ffs::Ffs, payments::PaymentDb};
+
+/// The filename of the serialized [`OutboxState`] within the outbox [`Ffs`].
+const OUTBOX_FILENAME: &str = "outbox";
+
+/// A persistent queue of requests to replay once the user node is reachable.
+pub struct Outbox<F> {
+    ffs: F,
+    state: OutboxState,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct OutboxState {
+    next_id: u64,
+    entries: Vec<OutboxEntry>,
+}
+
+/// A single queued request.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// Monotonically increasing id, unique within this outbox.
+    pub id: u64,
+    pub request: OutboxRequest,
+}
+
+/// The requests which can be queued in the [`Outbox`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OutboxRequest {
+    UpdatePaymentNote {
+        index: PaymentIndex,
+        /// The note we want the payment to have.
+        note: Option<String>,
+        /// The note the payment had locally before we queued this update.
+        prev_note: Option<String>,
+    },
+}
+
+/// Summary of a [`replay`] run.
+#[derive(Debug)]
+pub struct ReplaySummary {
+    /// Requests successfully applied to the node.
+    pub num_replayed: usize,
+    /// Requests dropped because the node's state had changed underneath us.
+    pub num_conflicts: usize,
+    /// Requests still queued, e.g. because the node went offline again.
+    pub num_remaining: usize,
+}
+
+/// Returns `true` if the error means we couldn't reach the user node at all,
+/// i.e., the request is worth queueing and retrying later.
+pub fn is_offline_error(err: &NodeApiError) -> bool {
+    matches!(
+        err.kind,
+        NodeErrorKind::Connect
+            | NodeErrorKind::Timeout
+            | NodeErrorKind::AtCapacity
+            | NodeErrorKind::Proxy
+    )
+}
+
+impl<F: Ffs> Outbox<F> {
+    /// Read the outbox from the given [`Ffs`]. A missing outbox file is treated
+    /// as an empty outbox.
+    pub fn read(ffs: F) -> anyhow::Result<Self> {
+        let state = match ffs.read(OUTBOX_FILENAME) {
+            Ok(data) => serde_json::from_slice(&data)
+                .context("Failed to deserialize outbox")?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound =>
+                OutboxState::default(),
+            Err(e) => return Err(e).context("Failed to read outbox"),
+        };
+        Ok(Self { ffs, state })
+    }
+
+    pub fn empty(ffs: F) -> Self {
+        Self {
+            ffs,
+            state: OutboxState::default(),
+        }
+    }
+
+    /// All queued entries, oldest first.
+    pub fn pending(&self) -> &[OutboxEntry] {
+        &self.state.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.entries.is_empty()
+    }
+
+    /// Queue a payment note update. If there's already a queued update for the
+    /// same payment, the two are coalesced: we keep the original `prev_note`
+    /// (which is what the node should still have) and the newest `note`.
+    pub fn enqueue_payment_note(
+        &mut self,
+        req: UpdatePaymentNote,
+        prev_note: Option<String>,
+    ) -> anyhow::Result<()> {
+        let existing = self.state.entries.iter_mut().find(|entry| {
+            let OutboxRequest::UpdatePaymentNote { index, .. } = &entry.request;
+            *index == req.index
+        });
+
+        match existing {
+            Some(entry) => {
+                let OutboxRequest::UpdatePaymentNote { note, .. } =
+                    &mut entry.request;
+                *note = req.note;
+            }
+            None => {
+                let id = self.state.next_id;
+                self.state.next_id += 1;
+                self.state.entries.push(OutboxEntry {
+                    id,
+                    request: OutboxRequest::UpdatePaymentNote {
+                        index: req.index,
+                        note: req.note,
+                        prev_note,
+                    },
+                });
+            }
+        }
+
+        self.persist()
+    }
+
+    /// Remove the given entry, if it's still queued and hasn't been coalesced
+    /// with a newer request since it was read.
+    pub fn remove(&mut self, entry: &OutboxEntry) -> anyhow::Result<()> {
+        let len_before = self.state.entries.len();
+        self.state.entries.retain(|e| e != entry);
+        if self.state.entries.len() != len_before {
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    fn persist(&self) -> anyhow::Result<()> {
+        let data = serde_json::to_vec(&self.state)
+            .expect("Failed to serialize outbox");
+        self.ffs
+            .write(OUTBOX_FILENAME, &data)
+            .context("Failed to write outbox")
+    }
+}
+
+/// Replay all queued requests against the user node, oldest first.
+///
+/// Stops early (keeping the remaining entries queued) if the node is still
+/// unreachable. Entries which fail for any other reason are dropped, since
+/// retrying them won't help.
+#[instrument(skip_all, name = "(replay-outbox)")]
+pub async fn replay<F: Ffs, G: Ffs, N: AppNodeRunApi>(
+    outbox: &Mutex<Outbox<F>>,
+    db: &Mutex<PaymentDb<G>>,
+    node: &N,
+) -> anyhow::Result<ReplaySummary> {
+    // Snapshot the entries so we don't hold the lock across `.await`s. Any
+    // entries queued in the meantime will be picked up on the next replay.
+    let entries = outbox.lock().unwrap().pending().to_vec();
+
+    let mut num_replayed = 0;
+    let mut num_conflicts = 0;
+
+    for entry in entries {
+        let res = match &entry.request {
+            OutboxRequest::UpdatePaymentNote {
+                index,
+                note,
+                prev_note,
+            } => replay_payment_note(db, node, index, note, prev_note).await,
+        };
+
+        match res {
+            Ok(ReplayOutcome::Replayed) => num_replayed += 1,
+            Ok(ReplayOutcome::Conflict) => num_conflicts += 1,
+            Err(e) if is_offline_error(&e) => {
+                info!("Node still offline; stopping replay: {e:#}");
+                break;
+            }
+            Err(e) => warn!(id = entry.id, "Dropping outbox entry: {e:#}"),
+        }
+
+        outbox.lock().unwrap().remove(&entry)?;
+    }
+
+    let num_remaining = outbox.lock().unwrap().len();
+    Ok(ReplaySummary {
+        num_replayed,
+        num_conflicts,
+        num_remaining,
+    })
+}
+
+enum ReplayOutcome {
+    Replayed,
+    Conflict,
+}
+
+async fn replay_payment_note<F: Ffs, N: AppNodeRunApi>(
+    db: &Mutex<PaymentDb<F>>,
+    node: &N,
+    index: &PaymentIndex,
+    note: &Option<String>,
+    prev_note: &Option<String>,
+) -> Result<ReplayOutcome, NodeApiError> {
+    let req = GetPaymentsByIds {
+        ids: vec![index.id.to_string()],
+    };
+    let remote_note = match node.get_payments_by_ids(req).await?.pop() {
+        Some(payment) => payment.note,
+        // The node doesn't know about this payment; nothing to update.
+        None => return Ok(ReplayOutcome::Conflict),
+    };
+
+    if remote_note == *note {
+        // Already applied, e.g. the original request made it through after
+        // all. Nothing to do.
+        return Ok(ReplayOutcome::Replayed);
+    }
+
+    if remote_note != *prev_note {
+        // Someone else changed the note since we queued our update. The node
+        // wins; bring our local copy back in line.
+        warn!(%index, "Payment note conflict; keeping the node's note");
+        let req = UpdatePaymentNote {
+            index: *index,
+            note: remote_note,
+        };
+        if let Err(e) = db.lock().unwrap().update_payment_note(req) {
+            warn!(%index, "Failed to update local payment note: {e:#}");
+        }
+        return Ok(ReplayOutcome::Conflict);
+    }
+
+    let req = UpdatePaymentNote {
+        index: *index,
+        note: note.clone(),
+    };
+    node.update_payment_note(req).await?;
+    Ok(ReplayOutcome::Replayed)
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::ffs::FlatFileFs;
+
+    fn payment_index(id: u8) -> PaymentIndex {
+        let hash = format!("{id:02x}").repeat(32);
+        format!("0000001682900000000-ln_{hash}").parse().unwrap()
+    }
+
+    fn update(index: PaymentIndex, note: &str) -> UpdatePaymentNote {
+        UpdatePaymentNote {
+            index,
+            note: Some(note.to_owned()),
+        }
+    }
+
+    #[test]
+    fn outbox_enqueue_coalesce_persist() {
+        let tempdir = tempdir().unwrap();
+        let ffs = || FlatFileFs::new(tempdir.path().to_owned());
+
+        let mut outbox = Outbox::read(ffs()).unwrap();
+        assert!(outbox.is_empty());
+
+        let (idx1, idx2) = (payment_index(1), payment_index(2));
+        outbox
+            .enqueue_payment_note(update(idx1, "a"), None)
+            .unwrap();
+        outbox
+            .enqueue_payment_note(update(idx2, "x"), Some("w".to_owned()))
+            .unwrap();
+        // Coalesces with the first entry, keeping the original `prev_note`.
+        outbox
+            .enqueue_payment_note(update(idx1, "b"), Some("a".to_owned()))
+            .unwrap();
+        assert_eq!(outbox.len(), 2);
+        assert_eq!(
+            outbox.pending()[0].request,
+            OutboxRequest::UpdatePaymentNote {
+                index: idx1,
+                note: Some("b".to_owned()),
+                prev_note: None,
+            }
+        );
+
+        // Reloading from disk gives the same state.
+        let reloaded = Outbox::read(ffs()).unwrap();
+        assert_eq!(reloaded.state, outbox.state);
+
+        // Removing a stale copy of a coalesced entry is a no-op.
+        let entry = outbox.pending()[0].clone();
+        let mut stale = entry.clone();
+        let OutboxRequest::UpdatePaymentNote { note, .. } = &mut stale.request;
+        *note = Some("a".to_owned());
+        outbox.remove(&stale).unwrap();
+        assert_eq!(outbox.len(), 2);
+        outbox.remove(&entry).unwrap();
+        outbox.remove(&entry).unwrap();
+        assert_eq!(outbox.len(), 1);
+        let reloaded = Outbox::read(ffs()).unwrap();
+        assert_eq!(reloaded.state, outbox.state);
+        assert_eq!(reloaded.state.next_id, 2);
+    }
+
+    #[test]
+    fn offline_errors() {
+        let err = |kind| NodeApiError::new(kind, String::new());
+        assert!(is_offline_error(&err(NodeErrorKind::Connect)));
+        assert!(is_offline_error(&err(NodeErrorKind::Proxy)));
+        assert!(!is_offline_error(&err(NodeErrorKind::Command)));
+        assert!(!is_offline_error(&err(NodeErrorKind::Rejection)));
+    }
+}
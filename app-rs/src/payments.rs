@@ -941,17 +941,28 @@ mod test {
     use common::{
         api::{
             command::{
-                CreateInvoiceRequest, CreateInvoiceResponse, NodeInfo,
-                PayInvoiceRequest, PayInvoiceResponse, PayOnchainRequest,
-                PayOnchainResponse, PreflightPayInvoiceRequest,
-                PreflightPayInvoiceResponse, PreflightPayOnchainRequest,
-                PreflightPayOnchainResponse,
+                BumpReceiveRequest, BumpReceiveResponse, ChannelHealthResponse,
+                ChannelOperation, ChannelOperationId, CloseChannelRequest,
+                CreateInvoiceRequest, CreateInvoiceResponse,
+                GDriveStorageStatus, GetAddressForRequest, NodeInfo,
+                OpenChannelRequest, PayInvoiceRequest, PayInvoiceResponse,
+                PayOnchainRequest, PayOnchainResponse,
+                PreflightPayInvoiceRequest, PreflightPayInvoiceResponse,
+                PreflightPayOnchainRequest, PreflightPayOnchainResponse,
+                QueryPayments, QueryPaymentsResponse,
             },
             error::NodeApiError,
+            migration::{
+                DecommissionRequest, ExportStateRequest, ExportStateResponse,
+            },
+            settings::SettingsDoc,
+            user::UserProfile,
+            vfs::RestoreFileVersionRequest,
             Empty,
         },
-        ln::payments::PaymentStatus,
+        ln::{fee_policy::FeePolicy, payments::PaymentStatus},
         rng::{shuffle, RngExt, WeakRng},
+        tls::attestation::evidence::EvidenceBundle,
     };
     use proptest::{
         arbitrary::any,
@@ -1106,9 +1117,49 @@ mod test {
         ) -> Result<PreflightPayOnchainResponse, NodeApiError> {
             unimplemented!();
         }
+        async fn bump_receive(
+            &self,
+            _req: BumpReceiveRequest,
+        ) -> Result<BumpReceiveResponse, NodeApiError> {
+            unimplemented!()
+        }
+        async fn channel_health(
+            &self,
+        ) -> Result<ChannelHealthResponse, NodeApiError> {
+            unimplemented!()
+        }
+        async fn gdrive_storage_status(
+            &self,
+        ) -> Result<GDriveStorageStatus, NodeApiError> {
+            unimplemented!()
+        }
+        async fn open_channel(
+            &self,
+            _req: OpenChannelRequest,
+        ) -> Result<ChannelOperationId, NodeApiError> {
+            unimplemented!()
+        }
+        async fn close_channel(
+            &self,
+            _req: CloseChannelRequest,
+        ) -> Result<ChannelOperationId, NodeApiError> {
+            unimplemented!()
+        }
+        async fn channel_operation(
+            &self,
+            _req: ChannelOperationId,
+        ) -> Result<ChannelOperation, NodeApiError> {
+            unimplemented!()
+        }
         async fn get_address(&self) -> Result<Address, NodeApiError> {
             unimplemented!()
         }
+        async fn get_address_for(
+            &self,
+            _req: GetAddressForRequest,
+        ) -> Result<Address, NodeApiError> {
+            unimplemented!()
+        }
 
         // payment sync methods
 
@@ -1162,6 +1213,13 @@ mod test {
                 .collect::<Vec<_>>())
         }
 
+        async fn query_payments(
+            &self,
+            _req: QueryPayments,
+        ) -> Result<QueryPaymentsResponse, NodeApiError> {
+            unimplemented!()
+        }
+
         /// PUT /app/payments/note [`UpdatePaymentNote`] -> [`()`]
         async fn update_payment_note(
             &self,
@@ -1169,6 +1227,66 @@ mod test {
         ) -> Result<Empty, NodeApiError> {
             unimplemented!()
         }
+
+        async fn get_user_profile(&self) -> Result<UserProfile, NodeApiError> {
+            unimplemented!()
+        }
+
+        async fn update_user_profile(
+            &self,
+            _req: UserProfile,
+        ) -> Result<Empty, NodeApiError> {
+            unimplemented!()
+        }
+
+        async fn get_settings(&self) -> Result<SettingsDoc, NodeApiError> {
+            unimplemented!()
+        }
+
+        async fn sync_settings(
+            &self,
+            _req: SettingsDoc,
+        ) -> Result<SettingsDoc, NodeApiError> {
+            unimplemented!()
+        }
+
+        async fn get_fee_policy(&self) -> Result<FeePolicy, NodeApiError> {
+            unimplemented!()
+        }
+
+        async fn update_fee_policy(
+            &self,
+            _req: FeePolicy,
+        ) -> Result<Empty, NodeApiError> {
+            unimplemented!()
+        }
+
+        async fn export_state(
+            &self,
+            _req: ExportStateRequest,
+        ) -> Result<ExportStateResponse, NodeApiError> {
+            unimplemented!()
+        }
+
+        async fn decommission(
+            &self,
+            _req: DecommissionRequest,
+        ) -> Result<Empty, NodeApiError> {
+            unimplemented!()
+        }
+
+        async fn restore_file_version(
+            &self,
+            _req: RestoreFileVersionRequest,
+        ) -> Result<Empty, NodeApiError> {
+            unimplemented!()
+        }
+
+        async fn get_attestation_evidence(
+            &self,
+        ) -> Result<EvidenceBundle, NodeApiError> {
+            unimplemented!()
+        }
     }
 
     #[test]
@@ -0,0 +1,279 @@
+//! Animated multi-part QR codes.
+//!
+//! Some payloads are too large to fit in a single scannable QR code, e.g.
+//! PSBTs or BOLT12 offers with many blinded paths. For these, we split the
+//! payload into a sequence of [BBQr] frames which the UI cycles through as an
+//! animated QR code. Each frame is a short alphanumeric string, which the UI
+//! renders with QR alphanumeric mode for maximum density.
+//!
+//! Frame format: `B$` + encoding + file type + total parts (2 base36 digits)
+//! + part index (2 base36 digits) + data.
+//!
+//! [BBQr]: https://github.com/coinkite/BBQr/blob/master/BBQr.md
+
+use anyhow::ensure;
+use common::hex;
+
+/// The length of each BBQr frame header.
+const HEADER_LEN: usize = 8;
+
+/// BBQr frame counts and indexes are 2 base36 digits.
+const MAX_PARTS: usize = 36 * 36 - 1;
+
+/// RFC 4648 base32 alphabet.
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// How the payload bytes are encoded into each frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BbqrEncoding {
+    /// Uppercase hex. Widely supported, but 25% larger than base32.
+    Hex,
+    /// RFC 4648 base32, without padding.
+    Base32,
+}
+
+/// What kind of payload the frames contain, so the scanner knows how to
+/// interpret the reassembled bytes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BbqrFileType {
+    Psbt,
+    Transaction,
+    Json,
+    Cbor,
+    /// UTF-8 text, e.g. a BOLT12 offer.
+    UnicodeText,
+}
+
+/// Configuration for [`bbqr_frames`].
+#[derive(Copy, Clone, Debug)]
+pub struct BbqrConfig {
+    pub encoding: BbqrEncoding,
+    /// The maximum length of each frame, including its header. Smaller frames
+    /// render as lower density QR codes, which are easier to scan but require
+    /// more frames.
+    pub max_frame_len: usize,
+}
+
+impl Default for BbqrConfig {
+    fn default() -> Self {
+        Self {
+            encoding: BbqrEncoding::Base32,
+            // Fits in a version 15 QR code (77x77) at medium error correction
+            // in alphanumeric mode, which most phone cameras scan easily.
+            max_frame_len: 500,
+        }
+    }
+}
+
+impl BbqrEncoding {
+    fn code(self) -> char {
+        match self {
+            Self::Hex => 'H',
+            Self::Base32 => '2',
+        }
+    }
+
+    /// Each frame's data (except the last) must be a multiple of this many
+    /// chars, so that frames can be decoded independently.
+    fn chunk_align(self) -> usize {
+        match self {
+            Self::Hex => 2,
+            Self::Base32 => 8,
+        }
+    }
+
+    fn encode(self, data: &[u8]) -> String {
+        match self {
+            Self::Hex => hex::encode(data).to_ascii_uppercase(),
+            Self::Base32 => base32_encode(data),
+        }
+    }
+}
+
+impl BbqrFileType {
+    fn code(self) -> char {
+        match self {
+            Self::Psbt => 'P',
+            Self::Transaction => 'T',
+            Self::Json => 'J',
+            Self::Cbor => 'C',
+            Self::UnicodeText => 'U',
+        }
+    }
+}
+
+/// A page of frames from [`bbqr_frames`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BbqrFrames {
+    /// The total number of frames in the animated QR code.
+    pub num_frames: usize,
+    /// The requested frames, starting at `start_index`.
+    pub frames: Vec<String>,
+}
+
+/// Split `data` into a sequence of BBQr frames, returning at most `limit`
+/// frames starting at `start_index`, so the UI can fetch frames as it cycles
+/// through them instead of holding them all at once. Small payloads produce a
+/// single frame, which can be displayed as a static QR code.
+pub fn bbqr_frames(
+    data: &[u8],
+    file_type: BbqrFileType,
+    config: BbqrConfig,
+    start_index: usize,
+    limit: usize,
+) -> anyhow::Result<BbqrFrames> {
+    let BbqrConfig {
+        encoding,
+        max_frame_len,
+    } = config;
+
+    let align = encoding.chunk_align();
+    let chunk_len = max_frame_len.saturating_sub(HEADER_LEN) / align * align;
+    ensure!(chunk_len > 0, "max_frame_len is too small: {max_frame_len}");
+
+    let encoded = encoding.encode(data);
+    let num_parts = encoded.len().div_ceil(chunk_len).max(1);
+    ensure!(
+        num_parts <= MAX_PARTS,
+        "Payload too large: needs {num_parts} frames, max is {MAX_PARTS}",
+    );
+
+    let header = |index: usize| {
+        format!(
+            "B${}{}{}{}",
+            encoding.code(),
+            file_type.code(),
+            base36(num_parts),
+            base36(index),
+        )
+    };
+
+    ensure!(
+        start_index < num_parts,
+        "Frame index {start_index} out of range: only {num_parts} frames",
+    );
+    let end_index = start_index.saturating_add(limit).min(num_parts);
+
+    // `encoded` is ASCII, so it's safe to split at any byte offset.
+    let frames = (start_index..end_index)
+        .map(|index| {
+            let start = index * chunk_len;
+            let end = (start + chunk_len).min(encoded.len());
+            header(index) + &encoded[start..end]
+        })
+        .collect();
+
+    Ok(BbqrFrames {
+        num_frames: num_parts,
+        frames,
+    })
+}
+
+/// Format `n < 36^2` as two uppercase base36 digits.
+fn base36(n: usize) -> String {
+    const DIGITS: &[u8; 36] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    debug_assert!(n <= MAX_PARTS);
+    let hi = DIGITS[n / 36] as char;
+    let lo = DIGITS[n % 36] as char;
+    format!("{hi}{lo}")
+}
+
+/// RFC 4648 base32 encoding, without padding.
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    for chunk in data.chunks(5) {
+        let mut buf = [0u8; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let bits = buf.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+
+        // Each 5 bits of input produces one output char.
+        let num_chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..num_chars {
+            let index = (bits >> (35 - i * 5)) & 0x1f;
+            out.push(BASE32_ALPHABET[index as usize] as char);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn base32_test_vectors() {
+        // RFC 4648 section 10, without padding
+        let cases = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(base32_encode(input.as_bytes()), expected);
+        }
+    }
+
+    #[test]
+    fn bbqr_frames_split() {
+        let data = [0x42u8; 100];
+        let file_type = BbqrFileType::Psbt;
+
+        let all_frames = |config| {
+            let frames =
+                bbqr_frames(&data, file_type, config, 0, usize::MAX).unwrap();
+            assert_eq!(frames.num_frames, frames.frames.len());
+            frames.frames
+        };
+
+        // Single frame
+        let frames = all_frames(BbqrConfig::default());
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].starts_with("B$2P0100"));
+
+        // 200 hex chars, 48 chars per frame => 5 frames
+        let config = BbqrConfig {
+            encoding: BbqrEncoding::Hex,
+            max_frame_len: HEADER_LEN + 49,
+        };
+        let frames = all_frames(config);
+        assert_eq!(frames.len(), 5);
+        for (index, frame) in frames.iter().enumerate() {
+            assert_eq!(&frame[..HEADER_LEN], format!("B$HP050{index}"));
+        }
+        assert_eq!(frames[0].len(), HEADER_LEN + 48);
+        assert_eq!(frames[4].len(), HEADER_LEN + 8);
+
+        let reassembled = frames
+            .iter()
+            .map(|frame| &frame[HEADER_LEN..])
+            .collect::<String>();
+        assert_eq!(reassembled, hex::encode(&data).to_ascii_uppercase());
+
+        // Paging through the frames
+        let page = bbqr_frames(&data, file_type, config, 1, 2).unwrap();
+        assert_eq!(page.num_frames, 5);
+        assert_eq!(page.frames, frames[1..3]);
+        let page = bbqr_frames(&data, file_type, config, 4, 2).unwrap();
+        assert_eq!(page.frames, frames[4..]);
+        assert!(bbqr_frames(&data, file_type, config, 5, 1).is_err());
+
+        // Base32 frames are aligned to 8 chars
+        let config = BbqrConfig {
+            encoding: BbqrEncoding::Base32,
+            max_frame_len: HEADER_LEN + 20,
+        };
+        let frames = all_frames(config);
+        assert_eq!(frames[0].len(), HEADER_LEN + 16);
+
+        // Frame too small to hold any data
+        let config = BbqrConfig {
+            encoding: BbqrEncoding::Base32,
+            max_frame_len: HEADER_LEN + 7,
+        };
+        assert!(bbqr_frames(&data, file_type, config, 0, 1).is_err());
+    }
+}
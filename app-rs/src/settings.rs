@@ -0,0 +1,173 @@
+//! App-local settings db, synced across devices via the user node.
+//!
+//! The [`SettingsDb`] is write-through: each change is persisted locally right
+//! away, then the whole [`SettingsDoc`] is synced to the user node, which
+//! merges it into its own copy and returns the result. Since merging is
+//! last-writer-wins per key, a failed sync (e.g. because the node is offline)
+//! loses nothing; the changes go out with the next successful sync.
+
+use std::{io, sync::Mutex};
+
+use anyhow::{ensure, Context};
+use common::{
+    api::{
+        def::AppNodeRunApi,
+        settings::{SettingsDoc, MAX_SETTINGS_KEY_LEN},
+    },
+    time::TimestampMs,
+};
+use tracing::{info, instrument};
+
+use crate::ffs::Ffs;
+
+/// The filename of the serialized [`SettingsDoc`] within the settings [`Ffs`].
+const SETTINGS_FILENAME: &str = "settings";
+
+/// The app's settings, persisted to its own [`Ffs`].
+pub struct SettingsDb<F> {
+    ffs: F,
+    doc: SettingsDoc,
+}
+
+impl<F: Ffs> SettingsDb<F> {
+    /// Read the settings from the given [`Ffs`]. A missing settings file is
+    /// treated as empty settings.
+    pub fn read(ffs: F) -> anyhow::Result<Self> {
+        let doc = match ffs.read(SETTINGS_FILENAME) {
+            Ok(data) => serde_json::from_slice(&data)
+                .context("Failed to deserialize settings")?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound =>
+                SettingsDoc::default(),
+            Err(e) => return Err(e).context("Failed to read settings"),
+        };
+        Ok(Self { ffs, doc })
+    }
+
+    pub fn empty(ffs: F) -> Self {
+        Self {
+            ffs,
+            doc: SettingsDoc::default(),
+        }
+    }
+
+    pub fn doc(&self) -> &SettingsDoc {
+        &self.doc
+    }
+
+    /// Returns the JSON-encoded value of the setting `key`, if it's set.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.doc.get(key)
+    }
+
+    /// Set the setting `key` to the JSON-encoded `value`.
+    pub fn set(&mut self, key: String, value: String) -> anyhow::Result<()> {
+        validate_key(&key)?;
+        serde_json::from_str::<serde_json::Value>(&value)
+            .context("Setting value must be valid JSON")?;
+        self.doc.set(key, value, TimestampMs::now());
+        self.persist()
+    }
+
+    /// Delete the setting `key`.
+    pub fn remove(&mut self, key: &str) -> anyhow::Result<()> {
+        validate_key(key)?;
+        self.doc.remove(key, TimestampMs::now());
+        self.persist()
+    }
+
+    /// Merge a [`SettingsDoc`] from the node into our local settings.
+    fn merge(&mut self, doc: &SettingsDoc) -> anyhow::Result<bool> {
+        let version_before = self.doc.version;
+        let changed = self.doc.merge(doc);
+        if changed || self.doc.version != version_before {
+            self.persist()?;
+        }
+        Ok(changed)
+    }
+
+    fn persist(&self) -> anyhow::Result<()> {
+        let data = serde_json::to_vec(&self.doc)
+            .expect("Failed to serialize settings");
+        self.ffs
+            .write(SETTINGS_FILENAME, &data)
+            .context("Failed to write settings")
+    }
+}
+
+fn validate_key(key: &str) -> anyhow::Result<()> {
+    ensure!(
+        !key.is_empty() && key.len() <= MAX_SETTINGS_KEY_LEN,
+        "Setting keys must be 1-{MAX_SETTINGS_KEY_LEN} bytes long"
+    );
+    Ok(())
+}
+
+/// Sync our local settings with the user node, picking up any changes made on
+/// other devices. Returns whether any local settings changed.
+#[instrument(skip_all, name = "(sync-settings)")]
+pub async fn sync<F: Ffs, N: AppNodeRunApi>(
+    db: &Mutex<SettingsDb<F>>,
+    node: &N,
+) -> anyhow::Result<bool> {
+    // Snapshot the doc so we don't hold the lock across the `.await`. Drop
+    // expired tombstones first, or we'd just send them back to the node.
+    let local = {
+        let mut db_lock = db.lock().unwrap();
+        if db_lock.doc.gc_tombstones(TimestampMs::now()) {
+            db_lock.persist()?;
+        }
+        db_lock.doc.clone()
+    };
+    let remote = node
+        .sync_settings(local)
+        .await
+        .context("Failed to sync settings with node")?;
+
+    // Merge rather than overwrite, in case the settings changed locally while
+    // we were syncing.
+    let changed = db.lock().unwrap().merge(&remote)?;
+    info!(version = remote.version, changed, "synced settings");
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::ffs::FlatFileFs;
+
+    #[test]
+    fn settings_db_persist() {
+        let tempdir = tempdir().unwrap();
+        let ffs = || FlatFileFs::new(tempdir.path().to_owned());
+
+        let mut db = SettingsDb::read(ffs()).unwrap();
+        assert_eq!(db.doc(), &SettingsDoc::default());
+
+        db.set("theme".to_owned(), "\"dark\"".to_owned()).unwrap();
+        db.set("fiat".to_owned(), "\"USD\"".to_owned()).unwrap();
+        db.remove("fiat").unwrap();
+        db.set("bad".to_owned(), "not json".to_owned()).unwrap_err();
+        db.set(String::new(), "1".to_owned()).unwrap_err();
+
+        let reloaded = SettingsDb::read(ffs()).unwrap();
+        assert_eq!(reloaded.doc(), db.doc());
+        assert_eq!(reloaded.get("theme"), Some("\"dark\""));
+        assert_eq!(reloaded.get("fiat"), None);
+        assert!(reloaded.doc().tombstones.contains_key("fiat"));
+
+        // Merging a newer doc from the node is persisted too.
+        let mut remote = db.doc().clone();
+        remote.version = 3;
+        remote.set(
+            "theme".to_owned(),
+            "\"light\"".to_owned(),
+            TimestampMs::MAX,
+        );
+        assert!(db.merge(&remote).unwrap());
+        let reloaded = SettingsDb::read(ffs()).unwrap();
+        assert_eq!(reloaded.get("theme"), Some("\"light\""));
+        assert_eq!(reloaded.doc().version, 3);
+    }
+}
@@ -1,12 +1,17 @@
-use std::io;
+use std::{collections::BTreeMap, io};
 
 use anyhow::{anyhow, Context};
-use common::api::models::NodeRelease;
+use common::api::{
+    models::NodeRelease,
+    response_cache::{CachedResponse, ResponseCache},
+};
 
 use crate::ffs::Ffs;
 
 /// The FFS filename for the file storing the latest release we've provisioned.
 const LATEST_PROVISIONED_FILENAME: &str = "latest_provisioned";
+/// The FFS filename for the file storing cached gateway responses.
+const GATEWAY_CACHE_FILENAME: &str = "gateway_cache";
 
 /// Read the latest provisioned [`NodeRelease`].
 /// Returns [`Ok(None)`] if the file didn't exist.
@@ -43,3 +48,28 @@ pub(crate) fn delete_latest_provisioned(
         Err(e) => Err(anyhow!("Ffs::delete failed: {e:#}")),
     }
 }
+
+/// Read the cached gateway responses persisted by [`write_gateway_cache`].
+/// Returns an empty cache if the file didn't exist.
+pub(crate) fn read_gateway_cache(
+    app_data_ffs: &impl Ffs,
+) -> anyhow::Result<BTreeMap<String, CachedResponse>> {
+    match app_data_ffs.read(GATEWAY_CACHE_FILENAME) {
+        Ok(json_bytes) => serde_json::from_slice(&json_bytes)
+            .context("Deserialization failed"),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(anyhow!("Ffs::read failed: {e:#}")),
+    }
+}
+
+/// Persist the gateway's [`ResponseCache`].
+pub(crate) fn write_gateway_cache(
+    app_data_ffs: &impl Ffs,
+    cache: &ResponseCache,
+) -> anyhow::Result<()> {
+    let json_bytes =
+        serde_json::to_vec(&cache.entries()).expect("Serialization failed?");
+    app_data_ffs
+        .write(GATEWAY_CACHE_FILENAME, &json_bytes)
+        .context("Ffs::write failed")
+}
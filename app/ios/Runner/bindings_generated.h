@@ -50,6 +50,7 @@ typedef struct wire_PayOnchainRequest {
   uint64_t amount_sats;
   int32_t priority;
   struct wire_uint_8_list *note;
+  struct wire_uint_8_list *payjoin_endpoint;
 } wire_PayOnchainRequest;
 
 typedef struct wire_PreflightPayOnchainRequest {
@@ -108,6 +109,14 @@ void wire_payment_uri_resolve_best(int64_t port_,
                                    int32_t network,
                                    struct wire_uint_8_list *uri_str);
 
+WireSyncReturn wire_handle_payment_intent(int32_t network, struct wire_uint_8_list *input);
+
+WireSyncReturn wire_qr_animated_frames(int32_t kind,
+                                      struct wire_uint_8_list *data,
+                                      uint32_t *max_frame_len,
+                                      uintptr_t start_index,
+                                      uintptr_t limit);
+
 void wire_init_rust_log_stream(int64_t port_, struct wire_uint_8_list *rust_log);
 
 WireSyncReturn wire_debug_delete_secret_store(struct wire_Config *config);
@@ -195,6 +204,18 @@ void wire_update_payment_note__method__AppHandle(int64_t port_,
                                                  struct wire_AppHandle *that,
                                                  struct wire_UpdatePaymentNote *req);
 
+WireSyncReturn wire_get_setting__method__AppHandle(struct wire_AppHandle *that,
+                                                   struct wire_uint_8_list *key);
+
+void wire_update_setting__method__AppHandle(int64_t port_,
+                                            struct wire_AppHandle *that,
+                                            struct wire_uint_8_list *key,
+                                            struct wire_uint_8_list *value);
+
+void wire_remove_setting__method__AppHandle(int64_t port_,
+                                            struct wire_AppHandle *that,
+                                            struct wire_uint_8_list *key);
+
 struct wire_App new_App(void);
 
 struct wire_AppHandle *new_box_autoadd_app_handle_0(void);
@@ -213,6 +234,8 @@ struct wire_PreflightPayInvoiceRequest *new_box_autoadd_preflight_pay_invoice_re
 
 struct wire_PreflightPayOnchainRequest *new_box_autoadd_preflight_pay_onchain_request_0(void);
 
+uint32_t *new_box_autoadd_u32_0(uint32_t value);
+
 uint64_t *new_box_autoadd_u64_0(uint64_t value);
 
 struct wire_UpdatePaymentNote *new_box_autoadd_update_payment_note_0(void);
@@ -233,6 +256,8 @@ static int64_t dummy_method_to_enforce_bundling(void) {
     dummy_var ^= ((int64_t) (void*) wire_form_validate_bitcoin_address);
     dummy_var ^= ((int64_t) (void*) wire_form_validate_password);
     dummy_var ^= ((int64_t) (void*) wire_payment_uri_resolve_best);
+    dummy_var ^= ((int64_t) (void*) wire_handle_payment_intent);
+    dummy_var ^= ((int64_t) (void*) wire_qr_animated_frames);
     dummy_var ^= ((int64_t) (void*) wire_init_rust_log_stream);
     dummy_var ^= ((int64_t) (void*) wire_debug_delete_secret_store);
     dummy_var ^= ((int64_t) (void*) wire_debug_delete_latest_provisioned);
@@ -264,6 +289,9 @@ static int64_t dummy_method_to_enforce_bundling(void) {
     dummy_var ^= ((int64_t) (void*) wire_get_num_pending_not_junk_payments__method__AppHandle);
     dummy_var ^= ((int64_t) (void*) wire_get_num_finalized_not_junk_payments__method__AppHandle);
     dummy_var ^= ((int64_t) (void*) wire_update_payment_note__method__AppHandle);
+    dummy_var ^= ((int64_t) (void*) wire_get_setting__method__AppHandle);
+    dummy_var ^= ((int64_t) (void*) wire_update_setting__method__AppHandle);
+    dummy_var ^= ((int64_t) (void*) wire_remove_setting__method__AppHandle);
     dummy_var ^= ((int64_t) (void*) new_App);
     dummy_var ^= ((int64_t) (void*) new_box_autoadd_app_handle_0);
     dummy_var ^= ((int64_t) (void*) new_box_autoadd_config_0);
@@ -273,6 +301,7 @@ static int64_t dummy_method_to_enforce_bundling(void) {
     dummy_var ^= ((int64_t) (void*) new_box_autoadd_payment_index_0);
     dummy_var ^= ((int64_t) (void*) new_box_autoadd_preflight_pay_invoice_request_0);
     dummy_var ^= ((int64_t) (void*) new_box_autoadd_preflight_pay_onchain_request_0);
+    dummy_var ^= ((int64_t) (void*) new_box_autoadd_u32_0);
     dummy_var ^= ((int64_t) (void*) new_box_autoadd_u64_0);
     dummy_var ^= ((int64_t) (void*) new_box_autoadd_update_payment_note_0);
     dummy_var ^= ((int64_t) (void*) new_uint_8_list_0);
@@ -139,6 +139,55 @@ class AppRsImpl implements AppRs {
         argNames: ["network", "uriStr"],
       );
 
+  PaymentIntent handlePaymentIntent(
+      {required Network network, required String input, dynamic hint}) {
+    var arg0 = api2wire_network(network);
+    var arg1 = _platform.api2wire_String(input);
+    return _platform.executeSync(FlutterRustBridgeSyncTask(
+      callFfi: () => _platform.inner.wire_handle_payment_intent(arg0, arg1),
+      parseSuccessData: _wire2api_payment_intent,
+      parseErrorData: null,
+      constMeta: kHandlePaymentIntentConstMeta,
+      argValues: [network, input],
+      hint: hint,
+    ));
+  }
+
+  FlutterRustBridgeTaskConstMeta get kHandlePaymentIntentConstMeta =>
+      const FlutterRustBridgeTaskConstMeta(
+        debugName: "handle_payment_intent",
+        argNames: ["network", "input"],
+      );
+
+  QrFrames qrAnimatedFrames(
+      {required QrPayloadKind kind,
+      required Uint8List data,
+      int? maxFrameLen,
+      required int startIndex,
+      required int limit,
+      dynamic hint}) {
+    var arg0 = api2wire_qr_payload_kind(kind);
+    var arg1 = _platform.api2wire_uint_8_list(data);
+    var arg2 = _platform.api2wire_opt_box_autoadd_u32(maxFrameLen);
+    var arg3 = api2wire_usize(startIndex);
+    var arg4 = api2wire_usize(limit);
+    return _platform.executeSync(FlutterRustBridgeSyncTask(
+      callFfi: () => _platform.inner
+          .wire_qr_animated_frames(arg0, arg1, arg2, arg3, arg4),
+      parseSuccessData: _wire2api_qr_frames,
+      parseErrorData: _wire2api_FrbAnyhowException,
+      constMeta: kQrAnimatedFramesConstMeta,
+      argValues: [kind, data, maxFrameLen, startIndex, limit],
+      hint: hint,
+    ));
+  }
+
+  FlutterRustBridgeTaskConstMeta get kQrAnimatedFramesConstMeta =>
+      const FlutterRustBridgeTaskConstMeta(
+        debugName: "qr_animated_frames",
+        argNames: ["kind", "data", "maxFrameLen", "startIndex", "limit"],
+      );
+
   Stream<String> initRustLogStream({required String rustLog, dynamic hint}) {
     var arg0 = _platform.api2wire_String(rustLog);
     return _platform.executeStream(FlutterRustBridgeTask(
@@ -802,6 +851,73 @@ class AppRsImpl implements AppRs {
             argNames: ["that", "req"],
           );
 
+  String? getSettingMethodAppHandle(
+      {required AppHandle that, required String key, dynamic hint}) {
+    var arg0 = _platform.api2wire_box_autoadd_app_handle(that);
+    var arg1 = _platform.api2wire_String(key);
+    return _platform.executeSync(FlutterRustBridgeSyncTask(
+      callFfi: () =>
+          _platform.inner.wire_get_setting__method__AppHandle(arg0, arg1),
+      parseSuccessData: _wire2api_opt_String,
+      parseErrorData: null,
+      constMeta: kGetSettingMethodAppHandleConstMeta,
+      argValues: [that, key],
+      hint: hint,
+    ));
+  }
+
+  FlutterRustBridgeTaskConstMeta get kGetSettingMethodAppHandleConstMeta =>
+      const FlutterRustBridgeTaskConstMeta(
+        debugName: "get_setting__method__AppHandle",
+        argNames: ["that", "key"],
+      );
+
+  Future<void> updateSettingMethodAppHandle(
+      {required AppHandle that,
+      required String key,
+      required String value,
+      dynamic hint}) {
+    var arg0 = _platform.api2wire_box_autoadd_app_handle(that);
+    var arg1 = _platform.api2wire_String(key);
+    var arg2 = _platform.api2wire_String(value);
+    return _platform.executeNormal(FlutterRustBridgeTask(
+      callFfi: (port_) => _platform.inner
+          .wire_update_setting__method__AppHandle(port_, arg0, arg1, arg2),
+      parseSuccessData: _wire2api_unit,
+      parseErrorData: _wire2api_FrbAnyhowException,
+      constMeta: kUpdateSettingMethodAppHandleConstMeta,
+      argValues: [that, key, value],
+      hint: hint,
+    ));
+  }
+
+  FlutterRustBridgeTaskConstMeta get kUpdateSettingMethodAppHandleConstMeta =>
+      const FlutterRustBridgeTaskConstMeta(
+        debugName: "update_setting__method__AppHandle",
+        argNames: ["that", "key", "value"],
+      );
+
+  Future<void> removeSettingMethodAppHandle(
+      {required AppHandle that, required String key, dynamic hint}) {
+    var arg0 = _platform.api2wire_box_autoadd_app_handle(that);
+    var arg1 = _platform.api2wire_String(key);
+    return _platform.executeNormal(FlutterRustBridgeTask(
+      callFfi: (port_) => _platform.inner
+          .wire_remove_setting__method__AppHandle(port_, arg0, arg1),
+      parseSuccessData: _wire2api_unit,
+      parseErrorData: _wire2api_FrbAnyhowException,
+      constMeta: kRemoveSettingMethodAppHandleConstMeta,
+      argValues: [that, key],
+      hint: hint,
+    ));
+  }
+
+  FlutterRustBridgeTaskConstMeta get kRemoveSettingMethodAppHandleConstMeta =>
+      const FlutterRustBridgeTaskConstMeta(
+        debugName: "remove_setting__method__AppHandle",
+        argNames: ["that", "key"],
+      );
+
   DropFnType get dropOpaqueApp => _platform.inner.drop_opaque_App;
   ShareFnType get shareOpaqueApp => _platform.inner.share_opaque_App;
   OpaqueTypeFinalizer get AppFinalizer => _platform.AppFinalizer;
@@ -823,6 +939,10 @@ class AppRsImpl implements AppRs {
     return raw as String;
   }
 
+  List<String> _wire2api_StringList(dynamic raw) {
+    return (raw as List<dynamic>).cast<String>();
+  }
+
   AppHandle _wire2api_app_handle(dynamic raw) {
     final arr = raw as List<dynamic>;
     if (arr.length != 1)
@@ -872,6 +992,10 @@ class AppRsImpl implements AppRs {
     return _wire2api_payment(raw);
   }
 
+  PaymentMethod _wire2api_box_autoadd_payment_method(dynamic raw) {
+    return _wire2api_payment_method(raw);
+  }
+
   ShortPaymentAndIndex _wire2api_box_autoadd_short_payment_and_index(
       dynamic raw) {
     return _wire2api_short_payment_and_index(raw);
@@ -984,13 +1108,14 @@ class AppRsImpl implements AppRs {
 
   Onchain _wire2api_onchain(dynamic raw) {
     final arr = raw as List<dynamic>;
-    if (arr.length != 4)
-      throw Exception('unexpected arr length: expect 4 but see ${arr.length}');
+    if (arr.length != 5)
+      throw Exception('unexpected arr length: expect 5 but see ${arr.length}');
     return Onchain(
       address: _wire2api_String(arr[0]),
       amountSats: _wire2api_opt_box_autoadd_u64(arr[1]),
       label: _wire2api_opt_String(arr[2]),
       message: _wire2api_opt_String(arr[3]),
+      payjoin: _wire2api_opt_String(arr[4]),
     );
   }
 
@@ -1085,6 +1210,22 @@ class AppRsImpl implements AppRs {
     );
   }
 
+  PaymentIntent _wire2api_payment_intent(dynamic raw) {
+    switch (raw[0]) {
+      case 0:
+        return PaymentIntent_Send(
+          method: _wire2api_box_autoadd_payment_method(raw[1]),
+          payeeName: _wire2api_opt_String(raw[2]),
+        );
+      case 1:
+        return PaymentIntent_Error(
+          message: _wire2api_String(raw[1]),
+        );
+      default:
+        throw Exception("unreachable");
+    }
+  }
+
   PaymentKind _wire2api_payment_kind(dynamic raw) {
     return PaymentKind.values[raw as int];
   }
@@ -1133,6 +1274,16 @@ class AppRsImpl implements AppRs {
     );
   }
 
+  QrFrames _wire2api_qr_frames(dynamic raw) {
+    final arr = raw as List<dynamic>;
+    if (arr.length != 2)
+      throw Exception('unexpected arr length: expect 2 but see ${arr.length}');
+    return QrFrames(
+      numFrames: _wire2api_usize(arr[0]),
+      frames: _wire2api_StringList(arr[1]),
+    );
+  }
+
   ShortPayment _wire2api_short_payment(dynamic raw) {
     final arr = raw as List<dynamic>;
     if (arr.length != 7)
@@ -1210,6 +1361,11 @@ int api2wire_network(Network raw) {
   return api2wire_i32(raw.index);
 }
 
+@protected
+int api2wire_qr_payload_kind(QrPayloadKind raw) {
+  return api2wire_i32(raw.index);
+}
+
 @protected
 int api2wire_u32(int raw) {
   return raw;
@@ -1307,6 +1463,11 @@ class AppRsPlatform extends FlutterRustBridgeBase<AppRsWire> {
     return ptr;
   }
 
+  @protected
+  ffi.Pointer<ffi.Uint32> api2wire_box_autoadd_u32(int raw) {
+    return inner.new_box_autoadd_u32_0(api2wire_u32(raw));
+  }
+
   @protected
   ffi.Pointer<ffi.Uint64> api2wire_box_autoadd_u64(int raw) {
     return inner.new_box_autoadd_u64_0(api2wire_u64(raw));
@@ -1325,6 +1486,11 @@ class AppRsPlatform extends FlutterRustBridgeBase<AppRsWire> {
     return raw == null ? ffi.nullptr : api2wire_String(raw);
   }
 
+  @protected
+  ffi.Pointer<ffi.Uint32> api2wire_opt_box_autoadd_u32(int? raw) {
+    return raw == null ? ffi.nullptr : api2wire_box_autoadd_u32(raw);
+  }
+
   @protected
   ffi.Pointer<ffi.Uint64> api2wire_opt_box_autoadd_u64(int? raw) {
     return raw == null ? ffi.nullptr : api2wire_box_autoadd_u64(raw);
@@ -1448,6 +1614,7 @@ class AppRsPlatform extends FlutterRustBridgeBase<AppRsWire> {
     wireObj.amount_sats = api2wire_u64(apiObj.amountSats);
     wireObj.priority = api2wire_confirmation_priority(apiObj.priority);
     wireObj.note = api2wire_opt_String(apiObj.note);
+    wireObj.payjoin_endpoint = api2wire_opt_String(apiObj.payjoinEndpoint);
   }
 
   void _api_fill_to_wire_payment_index(
@@ -1665,6 +1832,52 @@ class AppRsWire implements FlutterRustBridgeWireBase {
   late final _wire_payment_uri_resolve_best = _wire_payment_uri_resolve_bestPtr
       .asFunction<void Function(int, int, ffi.Pointer<wire_uint_8_list>)>();
 
+  WireSyncReturn wire_handle_payment_intent(
+    int network,
+    ffi.Pointer<wire_uint_8_list> input,
+  ) {
+    return _wire_handle_payment_intent(
+      network,
+      input,
+    );
+  }
+
+  late final _wire_handle_payment_intentPtr = _lookup<
+      ffi.NativeFunction<
+          WireSyncReturn Function(ffi.Int32,
+              ffi.Pointer<wire_uint_8_list>)>>('wire_handle_payment_intent');
+  late final _wire_handle_payment_intent =
+      _wire_handle_payment_intentPtr.asFunction<
+          WireSyncReturn Function(int, ffi.Pointer<wire_uint_8_list>)>();
+
+  WireSyncReturn wire_qr_animated_frames(
+    int kind,
+    ffi.Pointer<wire_uint_8_list> data,
+    ffi.Pointer<ffi.Uint32> max_frame_len,
+    int start_index,
+    int limit,
+  ) {
+    return _wire_qr_animated_frames(
+      kind,
+      data,
+      max_frame_len,
+      start_index,
+      limit,
+    );
+  }
+
+  late final _wire_qr_animated_framesPtr = _lookup<
+      ffi.NativeFunction<
+          WireSyncReturn Function(
+              ffi.Int32,
+              ffi.Pointer<wire_uint_8_list>,
+              ffi.Pointer<ffi.Uint32>,
+              ffi.UintPtr,
+              ffi.UintPtr)>>('wire_qr_animated_frames');
+  late final _wire_qr_animated_frames = _wire_qr_animated_framesPtr.asFunction<
+      WireSyncReturn Function(int, ffi.Pointer<wire_uint_8_list>,
+          ffi.Pointer<ffi.Uint32>, int, int)>();
+
   void wire_init_rust_log_stream(
     int port_,
     ffi.Pointer<wire_uint_8_list> rust_log,
@@ -2261,6 +2474,75 @@ class AppRsWire implements FlutterRustBridgeWireBase {
           void Function(int, ffi.Pointer<wire_AppHandle>,
               ffi.Pointer<wire_UpdatePaymentNote>)>();
 
+  WireSyncReturn wire_get_setting__method__AppHandle(
+    ffi.Pointer<wire_AppHandle> that,
+    ffi.Pointer<wire_uint_8_list> key,
+  ) {
+    return _wire_get_setting__method__AppHandle(
+      that,
+      key,
+    );
+  }
+
+  late final _wire_get_setting__method__AppHandlePtr = _lookup<
+          ffi.NativeFunction<
+              WireSyncReturn Function(ffi.Pointer<wire_AppHandle>,
+                  ffi.Pointer<wire_uint_8_list>)>>(
+      'wire_get_setting__method__AppHandle');
+  late final _wire_get_setting__method__AppHandle =
+      _wire_get_setting__method__AppHandlePtr.asFunction<
+          WireSyncReturn Function(
+              ffi.Pointer<wire_AppHandle>, ffi.Pointer<wire_uint_8_list>)>();
+
+  void wire_update_setting__method__AppHandle(
+    int port_,
+    ffi.Pointer<wire_AppHandle> that,
+    ffi.Pointer<wire_uint_8_list> key,
+    ffi.Pointer<wire_uint_8_list> value,
+  ) {
+    return _wire_update_setting__method__AppHandle(
+      port_,
+      that,
+      key,
+      value,
+    );
+  }
+
+  late final _wire_update_setting__method__AppHandlePtr = _lookup<
+          ffi.NativeFunction<
+              ffi.Void Function(
+                  ffi.Int64,
+                  ffi.Pointer<wire_AppHandle>,
+                  ffi.Pointer<wire_uint_8_list>,
+                  ffi.Pointer<wire_uint_8_list>)>>(
+      'wire_update_setting__method__AppHandle');
+  late final _wire_update_setting__method__AppHandle =
+      _wire_update_setting__method__AppHandlePtr.asFunction<
+          void Function(int, ffi.Pointer<wire_AppHandle>,
+              ffi.Pointer<wire_uint_8_list>, ffi.Pointer<wire_uint_8_list>)>();
+
+  void wire_remove_setting__method__AppHandle(
+    int port_,
+    ffi.Pointer<wire_AppHandle> that,
+    ffi.Pointer<wire_uint_8_list> key,
+  ) {
+    return _wire_remove_setting__method__AppHandle(
+      port_,
+      that,
+      key,
+    );
+  }
+
+  late final _wire_remove_setting__method__AppHandlePtr = _lookup<
+          ffi.NativeFunction<
+              ffi.Void Function(ffi.Int64, ffi.Pointer<wire_AppHandle>,
+                  ffi.Pointer<wire_uint_8_list>)>>(
+      'wire_remove_setting__method__AppHandle');
+  late final _wire_remove_setting__method__AppHandle =
+      _wire_remove_setting__method__AppHandlePtr.asFunction<
+          void Function(int, ffi.Pointer<wire_AppHandle>,
+              ffi.Pointer<wire_uint_8_list>)>();
+
   wire_App new_App() {
     return _new_App();
   }
@@ -2361,6 +2643,20 @@ class AppRsWire implements FlutterRustBridgeWireBase {
       _new_box_autoadd_preflight_pay_onchain_request_0Ptr.asFunction<
           ffi.Pointer<wire_PreflightPayOnchainRequest> Function()>();
 
+  ffi.Pointer<ffi.Uint32> new_box_autoadd_u32_0(
+    int value,
+  ) {
+    return _new_box_autoadd_u32_0(
+      value,
+    );
+  }
+
+  late final _new_box_autoadd_u32_0Ptr =
+      _lookup<ffi.NativeFunction<ffi.Pointer<ffi.Uint32> Function(ffi.Uint32)>>(
+          'new_box_autoadd_u32_0');
+  late final _new_box_autoadd_u32_0 = _new_box_autoadd_u32_0Ptr
+      .asFunction<ffi.Pointer<ffi.Uint32> Function(int)>();
+
   ffi.Pointer<ffi.Uint64> new_box_autoadd_u64_0(
     int value,
   ) {
@@ -2496,6 +2792,8 @@ final class wire_PayOnchainRequest extends ffi.Struct {
   external int priority;
 
   external ffi.Pointer<wire_uint_8_list> note;
+
+  external ffi.Pointer<wire_uint_8_list> payjoin_endpoint;
 }
 
 final class wire_PreflightPayOnchainRequest extends ffi.Struct {
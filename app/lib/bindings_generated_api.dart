@@ -57,6 +57,32 @@ abstract class AppRs {
 
   FlutterRustBridgeTaskConstMeta get kPaymentUriResolveBestConstMeta;
 
+  /// Handle any incoming payment code, whether from a QR scan, clipboard paste,
+  /// `bitcoin:`/`lightning:` deep link, or NFC tag. Prefer this over
+  /// [`payment_uri_resolve_best`] so all entry points behave the same.
+  PaymentIntent handlePaymentIntent(
+      {required Network network, required String input, dynamic hint});
+
+  FlutterRustBridgeTaskConstMeta get kHandlePaymentIntentConstMeta;
+
+  /// Split `data` into the frames of an animated (BBQr) QR code, returning at
+  /// most `limit` frames starting at `start_index`. The UI should render each
+  /// frame as an alphanumeric-mode QR code and cycle through them, fetching
+  /// more frames as it goes. Payloads which fit in one frame return a single,
+  /// static frame.
+  ///
+  /// `max_frame_len`: the max # of chars in each frame. Lower values give less
+  /// dense QR codes which are easier to scan, at the cost of more frames.
+  QrFrames qrAnimatedFrames(
+      {required QrPayloadKind kind,
+      required Uint8List data,
+      int? maxFrameLen,
+      required int startIndex,
+      required int limit,
+      dynamic hint});
+
+  FlutterRustBridgeTaskConstMeta get kQrAnimatedFramesConstMeta;
+
   /// Init the Rust [`tracing`] logger. Also sets the current `RUST_LOG_TX`
   /// instance, which ships Rust logs over to the dart side for printing.
   ///
@@ -261,6 +287,24 @@ abstract class AppRs {
 
   FlutterRustBridgeTaskConstMeta get kUpdatePaymentNoteMethodAppHandleConstMeta;
 
+  String? getSettingMethodAppHandle(
+      {required AppHandle that, required String key, dynamic hint});
+
+  FlutterRustBridgeTaskConstMeta get kGetSettingMethodAppHandleConstMeta;
+
+  Future<void> updateSettingMethodAppHandle(
+      {required AppHandle that,
+      required String key,
+      required String value,
+      dynamic hint});
+
+  FlutterRustBridgeTaskConstMeta get kUpdateSettingMethodAppHandleConstMeta;
+
+  Future<void> removeSettingMethodAppHandle(
+      {required AppHandle that, required String key, dynamic hint});
+
+  FlutterRustBridgeTaskConstMeta get kRemoveSettingMethodAppHandleConstMeta;
+
   DropFnType get dropOpaqueApp;
   ShareFnType get shareOpaqueApp;
   OpaqueTypeFinalizer get AppFinalizer;
@@ -455,6 +499,30 @@ class AppHandle {
         that: this,
         req: req,
       );
+
+  /// Returns the JSON-encoded value of the setting `key`, if it's set.
+  String? getSetting({required String key, dynamic hint}) =>
+      bridge.getSettingMethodAppHandle(
+        that: this,
+        key: key,
+      );
+
+  /// Set the setting `key` to the JSON-encoded `value`, then sync it to the
+  /// node. The setting is saved locally even if the node is unreachable.
+  Future<void> updateSetting(
+          {required String key, required String value, dynamic hint}) =>
+      bridge.updateSettingMethodAppHandle(
+        that: this,
+        key: key,
+        value: value,
+      );
+
+  /// Delete the setting `key`, then sync the deletion to the node.
+  Future<void> removeSetting({required String key, dynamic hint}) =>
+      bridge.removeSettingMethodAppHandle(
+        that: this,
+        key: key,
+      );
 }
 
 @freezed
@@ -580,6 +648,7 @@ class Onchain with _$Onchain {
     int? amountSats,
     String? label,
     String? message,
+    String? payjoin,
   }) = _Onchain;
 }
 
@@ -611,6 +680,7 @@ class PayOnchainRequest with _$PayOnchainRequest {
     required int amountSats,
     required ConfirmationPriority priority,
     String? note,
+    String? payjoinEndpoint,
   }) = _PayOnchainRequest;
 }
 
@@ -656,6 +726,20 @@ class PaymentIndex with _$PaymentIndex {
   }) = _PaymentIndex;
 }
 
+/// What the UI should do with a scanned/pasted/opened payment code.
+@freezed
+sealed class PaymentIntent with _$PaymentIntent {
+  /// Open the send sheet, prefilled with this payment.
+  const factory PaymentIntent.send({
+    required PaymentMethod method,
+    String? payeeName,
+  }) = PaymentIntent_Send;
+  /// Show the user this error message.
+  const factory PaymentIntent.error({
+    required String message,
+  }) = PaymentIntent_Error;
+}
+
 enum PaymentKind {
   Onchain,
   Invoice,
@@ -716,6 +800,25 @@ class PreflightPayOnchainResponse with _$PreflightPayOnchainResponse {
   }) = _PreflightPayOnchainResponse;
 }
 
+/// A page of frames from an animated QR code. See [`qr_animated_frames`].
+@freezed
+class QrFrames with _$QrFrames {
+  const factory QrFrames({
+    /// The total number of frames in the animated QR code.
+    required int numFrames,
+
+    /// The requested frames.
+    required List<String> frames,
+  }) = _QrFrames;
+}
+
+/// The kind of payload encoded in an animated QR code.
+enum QrPayloadKind {
+  Psbt,
+  Transaction,
+  Text,
+}
+
 /// Just the info we need to display an entry in the payments list UI.
 @freezed
 class ShortPayment with _$ShortPayment {
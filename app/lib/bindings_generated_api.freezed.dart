@@ -585,13 +585,18 @@ mixin _$Onchain {
   int? get amountSats => throw _privateConstructorUsedError;
   String? get label => throw _privateConstructorUsedError;
   String? get message => throw _privateConstructorUsedError;
+  String? get payjoin => throw _privateConstructorUsedError;
 }
 
 /// @nodoc
 
 class _$OnchainImpl implements _Onchain {
   const _$OnchainImpl(
-      {required this.address, this.amountSats, this.label, this.message});
+      {required this.address,
+      this.amountSats,
+      this.label,
+      this.message,
+      this.payjoin});
 
   @override
   final String address;
@@ -601,10 +606,12 @@ class _$OnchainImpl implements _Onchain {
   final String? label;
   @override
   final String? message;
+  @override
+  final String? payjoin;
 
   @override
   String toString() {
-    return 'Onchain(address: $address, amountSats: $amountSats, label: $label, message: $message)';
+    return 'Onchain(address: $address, amountSats: $amountSats, label: $label, message: $message, payjoin: $payjoin)';
   }
 
   @override
@@ -616,12 +623,13 @@ class _$OnchainImpl implements _Onchain {
             (identical(other.amountSats, amountSats) ||
                 other.amountSats == amountSats) &&
             (identical(other.label, label) || other.label == label) &&
-            (identical(other.message, message) || other.message == message));
+            (identical(other.message, message) || other.message == message) &&
+            (identical(other.payjoin, payjoin) || other.payjoin == payjoin));
   }
 
   @override
   int get hashCode =>
-      Object.hash(runtimeType, address, amountSats, label, message);
+      Object.hash(runtimeType, address, amountSats, label, message, payjoin);
 }
 
 abstract class _Onchain implements Onchain {
@@ -629,7 +637,8 @@ abstract class _Onchain implements Onchain {
       {required final String address,
       final int? amountSats,
       final String? label,
-      final String? message}) = _$OnchainImpl;
+      final String? message,
+      final String? payjoin}) = _$OnchainImpl;
 
   @override
   String get address;
@@ -639,6 +648,8 @@ abstract class _Onchain implements Onchain {
   String? get label;
   @override
   String? get message;
+  @override
+  String? get payjoin;
 }
 
 /// @nodoc
@@ -741,6 +752,7 @@ mixin _$PayOnchainRequest {
   int get amountSats => throw _privateConstructorUsedError;
   ConfirmationPriority get priority => throw _privateConstructorUsedError;
   String? get note => throw _privateConstructorUsedError;
+  String? get payjoinEndpoint => throw _privateConstructorUsedError;
 }
 
 /// @nodoc
@@ -751,7 +763,8 @@ class _$PayOnchainRequestImpl implements _PayOnchainRequest {
       required this.address,
       required this.amountSats,
       required this.priority,
-      this.note});
+      this.note,
+      this.payjoinEndpoint});
 
   @override
   final ClientPaymentId cid;
@@ -763,10 +776,12 @@ class _$PayOnchainRequestImpl implements _PayOnchainRequest {
   final ConfirmationPriority priority;
   @override
   final String? note;
+  @override
+  final String? payjoinEndpoint;
 
   @override
   String toString() {
-    return 'PayOnchainRequest(cid: $cid, address: $address, amountSats: $amountSats, priority: $priority, note: $note)';
+    return 'PayOnchainRequest(cid: $cid, address: $address, amountSats: $amountSats, priority: $priority, note: $note, payjoinEndpoint: $payjoinEndpoint)';
   }
 
   @override
@@ -780,12 +795,14 @@ class _$PayOnchainRequestImpl implements _PayOnchainRequest {
                 other.amountSats == amountSats) &&
             (identical(other.priority, priority) ||
                 other.priority == priority) &&
-            (identical(other.note, note) || other.note == note));
+            (identical(other.note, note) || other.note == note) &&
+            (identical(other.payjoinEndpoint, payjoinEndpoint) ||
+                other.payjoinEndpoint == payjoinEndpoint));
   }
 
   @override
-  int get hashCode =>
-      Object.hash(runtimeType, cid, address, amountSats, priority, note);
+  int get hashCode => Object.hash(runtimeType, cid, address, amountSats,
+      priority, note, payjoinEndpoint);
 }
 
 abstract class _PayOnchainRequest implements PayOnchainRequest {
@@ -794,7 +811,8 @@ abstract class _PayOnchainRequest implements PayOnchainRequest {
       required final String address,
       required final int amountSats,
       required final ConfirmationPriority priority,
-      final String? note}) = _$PayOnchainRequestImpl;
+      final String? note,
+      final String? payjoinEndpoint}) = _$PayOnchainRequestImpl;
 
   @override
   ClientPaymentId get cid;
@@ -806,6 +824,8 @@ abstract class _PayOnchainRequest implements PayOnchainRequest {
   ConfirmationPriority get priority;
   @override
   String? get note;
+  @override
+  String? get payjoinEndpoint;
 }
 
 /// @nodoc
@@ -1037,6 +1057,79 @@ abstract class _PaymentIndex implements PaymentIndex {
   String get field0;
 }
 
+/// @nodoc
+mixin _$PaymentIntent {}
+
+/// @nodoc
+
+class _$PaymentIntent_SendImpl implements PaymentIntent_Send {
+  const _$PaymentIntent_SendImpl({required this.method, this.payeeName});
+
+  @override
+  final PaymentMethod method;
+  @override
+  final String? payeeName;
+
+  @override
+  String toString() {
+    return 'PaymentIntent.send(method: $method, payeeName: $payeeName)';
+  }
+
+  @override
+  bool operator ==(Object other) {
+    return identical(this, other) ||
+        (other.runtimeType == runtimeType &&
+            other is _$PaymentIntent_SendImpl &&
+            (identical(other.method, method) || other.method == method) &&
+            (identical(other.payeeName, payeeName) ||
+                other.payeeName == payeeName));
+  }
+
+  @override
+  int get hashCode => Object.hash(runtimeType, method, payeeName);
+}
+
+abstract class PaymentIntent_Send implements PaymentIntent {
+  const factory PaymentIntent_Send(
+      {required final PaymentMethod method,
+      final String? payeeName}) = _$PaymentIntent_SendImpl;
+
+  PaymentMethod get method;
+  String? get payeeName;
+}
+
+/// @nodoc
+
+class _$PaymentIntent_ErrorImpl implements PaymentIntent_Error {
+  const _$PaymentIntent_ErrorImpl({required this.message});
+
+  @override
+  final String message;
+
+  @override
+  String toString() {
+    return 'PaymentIntent.error(message: $message)';
+  }
+
+  @override
+  bool operator ==(Object other) {
+    return identical(this, other) ||
+        (other.runtimeType == runtimeType &&
+            other is _$PaymentIntent_ErrorImpl &&
+            (identical(other.message, message) || other.message == message));
+  }
+
+  @override
+  int get hashCode => Object.hash(runtimeType, message);
+}
+
+abstract class PaymentIntent_Error implements PaymentIntent {
+  const factory PaymentIntent_Error({required final String message}) =
+      _$PaymentIntent_ErrorImpl;
+
+  String get message;
+}
+
 /// @nodoc
 mixin _$PaymentMethod {}
 
@@ -1332,6 +1425,72 @@ abstract class _PreflightPayOnchainResponse
   FeeEstimate get background;
 }
 
+/// @nodoc
+mixin _$QrFrames {
+  /// The total number of frames in the animated QR code.
+  int get numFrames => throw _privateConstructorUsedError;
+
+  /// The requested frames.
+  List<String> get frames => throw _privateConstructorUsedError;
+}
+
+/// @nodoc
+
+class _$QrFramesImpl implements _QrFrames {
+  const _$QrFramesImpl(
+      {required this.numFrames, required final List<String> frames})
+      : _frames = frames;
+
+  /// The total number of frames in the animated QR code.
+  @override
+  final int numFrames;
+
+  /// The requested frames.
+  final List<String> _frames;
+
+  /// The requested frames.
+  @override
+  List<String> get frames {
+    if (_frames is EqualUnmodifiableListView) return _frames;
+    // ignore: implicit_dynamic_type
+    return EqualUnmodifiableListView(_frames);
+  }
+
+  @override
+  String toString() {
+    return 'QrFrames(numFrames: $numFrames, frames: $frames)';
+  }
+
+  @override
+  bool operator ==(Object other) {
+    return identical(this, other) ||
+        (other.runtimeType == runtimeType &&
+            other is _$QrFramesImpl &&
+            (identical(other.numFrames, numFrames) ||
+                other.numFrames == numFrames) &&
+            const DeepCollectionEquality().equals(other._frames, _frames));
+  }
+
+  @override
+  int get hashCode => Object.hash(
+      runtimeType, numFrames, const DeepCollectionEquality().hash(_frames));
+}
+
+abstract class _QrFrames implements QrFrames {
+  const factory _QrFrames(
+      {required final int numFrames,
+      required final List<String> frames}) = _$QrFramesImpl;
+
+  @override
+
+  /// The total number of frames in the animated QR code.
+  int get numFrames;
+  @override
+
+  /// The requested frames.
+  List<String> get frames;
+}
+
 /// @nodoc
 mixin _$ShortPayment {
   PaymentIndex get index => throw _privateConstructorUsedError;
@@ -627,6 +627,24 @@ class MockAppHandle extends AppHandle {
   Future<void> updatePaymentNote(
           {required UpdatePaymentNote req, dynamic hint}) =>
       Future.delayed(const Duration(milliseconds: 1000), () => ());
+
+  final Map<String, String> settings = {};
+
+  @override
+  String? getSetting({required String key, dynamic hint}) => this.settings[key];
+
+  @override
+  Future<void> updateSetting(
+          {required String key, required String value, dynamic hint}) =>
+      Future.delayed(const Duration(milliseconds: 1000), () {
+        this.settings[key] = value;
+      });
+
+  @override
+  Future<void> removeSetting({required String key, dynamic hint}) =>
+      Future.delayed(const Duration(milliseconds: 1000), () {
+        this.settings.remove(key);
+      });
 }
 
 /// An [AppHandle] that usually errors first.
@@ -3,6 +3,8 @@
 /// A page for manipulating app internals during development.
 library;
 
+import 'dart:convert' show jsonEncode;
+
 import 'package:flutter/material.dart';
 
 import 'package:lexeapp/bindings.dart' show api;
@@ -13,6 +15,9 @@ import 'package:lexeapp/logger.dart' show error, info;
 import 'package:lexeapp/result.dart';
 import 'package:lexeapp/style.dart' show LxColors, Space;
 
+/// The synced setting holding the user's preferred fiat currency, e.g. "USD".
+const String fiatPreferenceSettingKey = "fiat_currency";
+
 class DebugPage extends StatelessWidget {
   const DebugPage({
     super.key,
@@ -43,6 +48,20 @@ class DebugPage extends StatelessWidget {
         .inspectErr((err) => error(err.message));
   }
 
+  Future<void> doSetFiatPreference(String fiat) async {
+    info("Setting fiat preference: $fiat");
+    (await Result.tryFfiAsync(() => this.app.updateSetting(
+            key: fiatPreferenceSettingKey, value: jsonEncode(fiat))))
+        .inspectErr((err) => error(err.message));
+  }
+
+  Future<void> doResetFiatPreference() async {
+    info("Resetting fiat preference");
+    (await Result.tryFfiAsync(
+            () => this.app.removeSetting(key: fiatPreferenceSettingKey)))
+        .inspectErr((err) => error(err.message));
+  }
+
   @override
   Widget build(BuildContext context) {
     const bodyPadding = EdgeInsets.symmetric(horizontal: Space.s600);
@@ -77,6 +96,25 @@ class DebugPage extends StatelessWidget {
             ),
             onTap: this.doDeleteLatestProvisionedFile,
           ),
+          ListTile(
+            contentPadding: EdgeInsets.zero,
+            title: const Text("Use EUR as fiat currency"),
+            subtitle: const Text(
+              "Synced to your other devices. Takes effect when the wallet page "
+              "is reopened.",
+              style: TextStyle(color: LxColors.fgTertiary),
+            ),
+            onTap: () => this.doSetFiatPreference("EUR"),
+          ),
+          ListTile(
+            contentPadding: EdgeInsets.zero,
+            title: const Text("Reset fiat currency"),
+            subtitle: const Text(
+              "Deletes the synced fiat currency setting, reverting to USD.",
+              style: TextStyle(color: LxColors.fgTertiary),
+            ),
+            onTap: this.doResetFiatPreference,
+          ),
           ListTile(
             contentPadding: EdgeInsets.zero,
             title: const Text("Delete SecretStore & RootSeed"),
@@ -261,6 +261,7 @@ class SendState_Preflighted implements SendState {
       amountSats: preflighted.amountSats,
       priority: confPriority,
       note: note,
+      payjoinEndpoint: preflighted.onchain.payjoin,
     );
 
     return (await Result.tryFfiAsync(() async => this.app.payOnchain(req: req)))
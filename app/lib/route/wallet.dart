@@ -1,6 +1,7 @@
 // The primary wallet page.
 
 import 'dart:async' show StreamController, Timer, unawaited;
+import 'dart:convert' show jsonDecode;
 
 import 'package:flutter/material.dart';
 import 'package:freezed_annotation/freezed_annotation.dart' show freezed;
@@ -31,7 +32,8 @@ import 'package:lexeapp/currency_format.dart' as currency_format;
 import 'package:lexeapp/date_format.dart' as date_format;
 import 'package:lexeapp/logger.dart';
 import 'package:lexeapp/result.dart';
-import 'package:lexeapp/route/debug.dart' show DebugPage;
+import 'package:lexeapp/route/debug.dart'
+    show DebugPage, fiatPreferenceSettingKey;
 import 'package:lexeapp/route/payment_detail.dart' show PaymentDetailPage;
 import 'package:lexeapp/route/receive.dart' show ReceivePaymentPage;
 import 'package:lexeapp/route/scan.dart';
@@ -92,9 +94,8 @@ class WalletPageState extends State<WalletPage> {
   final StateSubject<BalanceState> balanceStates =
       StateSubject(BalanceState.placeholder);
 
-  // TODO(phlip9): get from user preferences
-  final String fiatPreference = "USD";
-  // final String fiatPreference = "EUR";
+  /// The user's preferred fiat currency, synced across their devices.
+  late final String fiatPreference = this.readFiatPreference();
 
   @override
   void initState() {
@@ -239,6 +240,25 @@ class WalletPageState extends State<WalletPage> {
     }
   }
 
+  String readFiatPreference() {
+    final res = Result.tryFfi(
+        () => this.widget.app.getSetting(key: fiatPreferenceSettingKey));
+    switch (res) {
+      case Ok(:final ok):
+        if (ok == null) return "USD";
+        // Fall back to the default rather than throwing if the stored value
+        // is corrupt.
+        final decoded =
+            Result<Object?, FormatException>.try_(() => jsonDecode(ok)).ok;
+        if (decoded is String) return decoded;
+        error("Invalid fiat preference: $ok");
+        return "USD";
+      case Err(:final err):
+        error("Failed to read fiat preference: $err");
+        return "USD";
+    }
+  }
+
   Future<void> fetchFiatRates() async {
     final res = await Result.tryFfiAsync(this.widget.app.fiatRates);
 
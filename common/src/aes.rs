@@ -67,6 +67,41 @@
 //! 7. return output
 //! ```
 //!
+//! ## Deterministic mode
+//!
+//! Sometimes we need a stable but reversible lookup key, e.g. to store a file
+//! under an encrypted payment id instead of the plaintext id, which would leak
+//! metadata to the storage provider. Random key ids don't work here since we
+//! need to find the file again from the plaintext.
+//!
+//! [`AesMasterKey::encrypt_deterministic`] is a SIV-style construction on top
+//! of the scheme above: instead of sampling the key id randomly, it's an HMAC
+//! of the plaintext (the "synthetic IV"). Equal plaintexts therefore encrypt to
+//! equal ciphertexts, while distinct plaintexts still get distinct single-use
+//! keys. Decryption recomputes the HMAC and checks it against the key id.
+//! Every use case passes its own `domain`, which is bound into both the HMAC
+//! and the AAD, so lookup keys for one use case can't be confused with (or
+//! correlated against) those of another.
+//!
+//! ```text
+//! mac-key := HKDF-Expand(
+//!         prk=master-key,
+//!         info="LEXE-REALM::AesDeterministicMacKey",
+//!    )
+//!
+//! EncryptDeterministic(master-key, domain, plaintext) :=
+//! 1. version := 1_u8
+//! 2. key-id := HMAC-SHA256(
+//!         mac-key,
+//!         bcs::to_bytes({ version, domain, plaintext }),
+//!    )
+//! 3. aad := Aad(version, key-id, [domain])
+//! 4. ..steps 4-7 of Encrypt
+//! ```
+//!
+//! Only use this for short, unique-per-domain values like ids: it reveals
+//! whether two plaintexts in the same domain are equal.
+//!
 //! ## References
 //!
 //! * [(2017) GueronLindel](https://eprint.iacr.org/2017/702.pdf) ([video](https://www.youtube.com/watch?v=WEJ451rmhk4))
@@ -85,7 +120,7 @@ use bytes::BufMut;
 use ref_cast::RefCast;
 use ring::{
     aead::{self, BoundKey},
-    hkdf,
+    hkdf, hmac,
 };
 use serde::Serialize;
 use thiserror::Error;
@@ -98,6 +133,12 @@ use crate::{
 /// serialized version length
 const VERSION_LEN: usize = 1;
 
+/// The version byte for randomized encryption.
+const RANDOM_VERSION: u8 = 0;
+
+/// The version byte for deterministic encryption.
+const DETERMINISTIC_VERSION: u8 = 1;
+
 /// serialized [`KeyId`] length
 const KEY_ID_LEN: usize = 32;
 
@@ -116,7 +157,11 @@ pub const fn encrypted_len(plaintext_len: usize) -> usize {
 /// `RootSeed` -- derive("vfs master key") --> `AesMasterKey`
 // We store the salted+extracted PRK directly to avoid recomputing it every
 // time we encrypt something.
-pub struct AesMasterKey(hkdf::Prk);
+pub struct AesMasterKey {
+    prk: hkdf::Prk,
+    /// Derives synthetic key ids for deterministic encryption.
+    mac_key: hmac::Key,
+}
 
 /// `KeyId` is the value used to derive the single-use message
 /// encryption/decryption key from the [`AesMasterKey`] HKDF.
@@ -148,6 +193,15 @@ struct EncryptKey(aead::SealingKey<ZeroNonce>);
 
 struct DecryptKey(aead::OpeningKey<ZeroNonce>);
 
+/// The HMAC input used to derive the synthetic [`KeyId`] for deterministic
+/// encryption.
+#[derive(Serialize)]
+struct SivInput<'a> {
+    version: u8,
+    domain: &'a [u8],
+    plaintext: &'a [u8],
+}
+
 /// A single-use, all-zero nonce that panics if used to encrypt or decrypt data
 /// more than once (for a particular instance).
 struct ZeroNonce(Option<aead::Nonce>);
@@ -164,17 +218,22 @@ impl fmt::Debug for AesMasterKey {
 
 impl AesMasterKey {
     const HKDF_SALT: [u8; 32] = array::pad(*b"LEXE-REALM::AesMasterKey");
+    /// NOTE: Must not be 32 bytes long, so it can't collide with a [`KeyId`].
+    const MAC_KEY_INFO: &'static [u8] = b"LEXE-REALM::AesDeterministicMacKey";
 
     pub fn new(root_seed_derived_secret: &[u8; 32]) -> Self {
-        Self(
-            hkdf::Salt::new(hkdf::HKDF_SHA256, &Self::HKDF_SALT)
-                .extract(root_seed_derived_secret),
-        )
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &Self::HKDF_SALT)
+            .extract(root_seed_derived_secret);
+        let mac_key = hmac::Key::from(
+            prk.expand(&[Self::MAC_KEY_INFO], hmac::HMAC_SHA256)
+                .expect("This should never fail"),
+        );
+        Self { prk, mac_key }
     }
 
     fn derive_unbound_key(&self, key_id: &KeyId) -> aead::UnboundKey {
         aead::UnboundKey::from(
-            self.0
+            self.prk
                 .expand(&[key_id.as_slice()], &aead::AES_256_GCM)
                 .expect("This should never fail"),
         )
@@ -203,7 +262,7 @@ impl AesMasterKey {
         // See tests as well as node / lsp `encrypt_*` for examples.
         write_data_cb: &dyn Fn(&mut Vec<u8>),
     ) -> Vec<u8> {
-        let version = 0;
+        let version = RANDOM_VERSION;
         let key_id = KeyId::gen(rng);
 
         let aad = Aad {
@@ -263,7 +322,7 @@ impl AesMasterKey {
             (version[0], key_id)
         };
 
-        if version != 0 {
+        if version != RANDOM_VERSION {
             return Err(DecryptError);
         }
         let key_id = KeyId::from_ref(key_id);
@@ -287,6 +346,115 @@ impl AesMasterKey {
 
         Ok(data)
     }
+
+    /// Deterministically encrypts `plaintext`, so that the same `domain` and
+    /// `plaintext` always give the same output. Useful for deriving stable,
+    /// reversible lookup keys; see the module docs for caveats.
+    ///
+    /// `domain` should be a unique constant for each use case, e.g.
+    /// `b"payment_id"`.
+    pub fn encrypt_deterministic(
+        &self,
+        domain: &[u8],
+        plaintext: &[u8],
+    ) -> Vec<u8> {
+        let version = DETERMINISTIC_VERSION;
+        let siv_input = SivInput {
+            version,
+            domain,
+            plaintext,
+        }
+        .serialize();
+        let tag = hmac::sign(&self.mac_key, &siv_input);
+        let key_id = KeyId(
+            <[u8; KEY_ID_LEN]>::try_from(tag.as_ref())
+                .expect("HMAC-SHA256 tags are 32 bytes"),
+        );
+
+        let aad = Aad {
+            version,
+            key_id: &key_id,
+            aad: &[domain],
+        }
+        .serialize();
+
+        let mut data = Vec::with_capacity(encrypted_len(plaintext.len()));
+        data.put_u8(version);
+        data.put(key_id.as_slice());
+        let plaintext_offset = data.len();
+        data.put(plaintext);
+
+        // data := [version] || [key_id] || [plaintext]
+
+        self.derive_encrypt_key(&key_id).encrypt_in_place(
+            aad.as_slice(),
+            &mut data,
+            plaintext_offset,
+        );
+
+        // data := [version] || [key_id] || [ciphertext] || [tag]
+
+        data
+    }
+
+    /// Decrypts the output of [`Self::encrypt_deterministic`] for the same
+    /// `domain`.
+    pub fn decrypt_deterministic(
+        &self,
+        domain: &[u8],
+        mut data: Vec<u8>,
+    ) -> Result<Vec<u8>, DecryptError> {
+        // data := [version] || [key_id] || [ciphertext] || [tag]
+
+        const MIN_DATA_LEN: usize = encrypted_len(0 /* plaintext len */);
+        if data.len() < MIN_DATA_LEN {
+            return Err(DecryptError);
+        }
+
+        let (version, key_id) = {
+            let (version, data) = data
+                .split_first_chunk::<VERSION_LEN>()
+                .expect("data.len() checked above");
+            let (key_id, _) = data
+                .split_first_chunk::<KEY_ID_LEN>()
+                .expect("data.len() checked above");
+            (version[0], KeyId(*key_id))
+        };
+
+        if version != DETERMINISTIC_VERSION {
+            return Err(DecryptError);
+        }
+        let decrypt_key = self.derive_decrypt_key(&key_id);
+
+        let aad = Aad {
+            version,
+            key_id: &key_id,
+            aad: &[domain],
+        }
+        .serialize();
+
+        let ciphertext_and_tag_offset = VERSION_LEN + KEY_ID_LEN;
+        decrypt_key.decrypt_in_place(
+            &aad,
+            &mut data,
+            ciphertext_and_tag_offset,
+        )?;
+
+        // data := [plaintext]
+
+        // Check the synthetic IV, i.e. that this is the key id we would have
+        // derived for this plaintext.
+        let siv_input = SivInput {
+            version,
+            domain,
+            plaintext: &data,
+        }
+        .serialize();
+        hmac::verify(&self.mac_key, &siv_input, key_id.as_slice())
+            .map_err(|_| DecryptError)?;
+
+        Ok(data)
+    }
 }
 
 impl EncryptKey {
@@ -368,6 +536,13 @@ impl<'data, 'aad> Aad<'data, 'aad> {
     }
 }
 
+impl SivInput<'_> {
+    fn serialize(&self) -> Vec<u8> {
+        bcs::to_bytes(self)
+            .expect("Serializing the SIV input should never fail")
+    }
+}
+
 impl ZeroNonce {
     fn new() -> Self {
         Self(Some(aead::Nonce::assume_unique_for_key([0u8; 12])))
@@ -528,4 +703,59 @@ mod test {
             prop_assert!(encrypted != encrypted2);
         });
     }
+
+    #[test]
+    fn test_deterministic_roundtrip() {
+        proptest!(|(
+            mut rng in any::<WeakRng>(),
+            domain in vec(any::<u8>(), 0..=16),
+            plaintext1 in vec(any::<u8>(), 0..=64),
+            plaintext2 in vec(any::<u8>(), 0..=64),
+        )| {
+            let root_seed = RootSeed::from_rng(&mut rng);
+            let vfs_key = root_seed.derive_vfs_master_key();
+
+            let encrypted1 =
+                vfs_key.encrypt_deterministic(&domain, &plaintext1);
+            let encrypted2 =
+                vfs_key.encrypt_deterministic(&domain, &plaintext2);
+            prop_assert_eq!(encrypted1.len(), encrypted_len(plaintext1.len()));
+            prop_assert_eq!(plaintext1 == plaintext2, encrypted1 == encrypted2);
+
+            let decrypted = vfs_key
+                .decrypt_deterministic(&domain, encrypted1.clone())
+                .unwrap();
+            prop_assert_eq!(&plaintext1, &decrypted);
+
+            // Other domains and the randomized mode can't decrypt it.
+            let mut other_domain = domain.clone();
+            other_domain.push(0);
+            prop_assert!(vfs_key
+                .decrypt_deterministic(&other_domain, encrypted1.clone())
+                .is_err());
+            prop_assert!(vfs_key.decrypt(&[&domain], encrypted1).is_err());
+        });
+    }
+
+    #[test]
+    fn test_deterministic_rejects_tampering() {
+        let mut rng = WeakRng::from_u64(123);
+        let vfs_key = RootSeed::from_rng(&mut rng).derive_vfs_master_key();
+        let domain = b"payment_id".as_slice();
+        let encrypted = vfs_key.encrypt_deterministic(domain, b"my id");
+
+        for idx in 0..encrypted.len() {
+            let mut tampered = encrypted.clone();
+            tampered[idx] ^= 0x01;
+            vfs_key.decrypt_deterministic(domain, tampered).unwrap_err();
+        }
+
+        // Randomized ciphertexts are rejected too.
+        let randomized = vfs_key.encrypt(&mut rng, &[domain], None, &|out| {
+            out.put(b"my id".as_slice())
+        });
+        vfs_key
+            .decrypt_deterministic(domain, randomized)
+            .unwrap_err();
+    }
 }
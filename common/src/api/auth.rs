@@ -324,6 +324,7 @@ impl BearerAuthenticator {
             .map_err(|err| BackendApiError {
                 kind: BackendErrorKind::Building,
                 msg: format!("Error signing auth request: {err:#}"),
+                data: None,
             })?;
 
         let resp = api.bearer_auth(signed_req.cloned()).await?;
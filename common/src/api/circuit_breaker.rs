@@ -0,0 +1,261 @@
+//! A per-host circuit breaker for [`RestClient`].
+//!
+//! When a remote service goes down, clients which keep sending requests that
+//! each wait out the full request timeout make the outage worse and (on
+//! mobile) drain the battery. The [`CircuitBreaker`] tracks consecutive
+//! transport-level failures per host:
+//!
+//! - **Closed**: Requests are sent as usual. After
+//!   [`CircuitBreakerConfig::failure_threshold`] consecutive failures, the
+//!   breaker opens.
+//! - **Open**: Requests fail fast with a [`CommonErrorKind::Connect`] error
+//!   without touching the network. After
+//!   [`CircuitBreakerConfig::open_duration`], the breaker becomes half-open.
+//! - **Half-open**: A single probe request is let through. If it succeeds, the
+//!   breaker closes; if it fails, the breaker opens again.
+//!
+//! Only connect and timeout errors count as failures. If the server returned
+//! any response at all (including an error response), the host is reachable.
+//!
+//! [`RestClient`]: crate::api::rest::RestClient
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::api::error::{CommonApiError, CommonErrorKind};
+
+/// Configuration for a [`CircuitBreaker`].
+#[derive(Copy, Clone, Debug)]
+pub struct CircuitBreakerConfig {
+    /// The number of consecutive failed requests to a host after which the
+    /// breaker opens.
+    pub failure_threshold: u32,
+    /// How long the breaker stays open before letting a probe request through.
+    pub open_duration: Duration,
+}
+
+/// Tracks the health of each remote host and fails requests fast while a host
+/// appears to be down. See the module docs for details.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    hosts: Mutex<HashMap<String, BreakerState>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum BreakerState {
+    Closed {
+        consecutive_failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A probe request was let through at `since`. If the probe never reports
+    /// back (e.g. its future was dropped), we allow another probe after
+    /// `open_duration`.
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(10),
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check whether we should send a request to `host`. Returns an error if
+    /// the breaker for this host is open.
+    pub fn check(&self, host: &str) -> Result<(), CommonApiError> {
+        self.check_at(host, Instant::now())
+    }
+
+    /// Record the outcome of a request to `host`.
+    pub fn record(&self, host: &str, is_failure: bool) {
+        self.record_at(host, is_failure, Instant::now())
+    }
+
+    /// Whether a request error indicates that the remote host is unreachable
+    /// or unresponsive, i.e. whether it should count towards opening the
+    /// breaker.
+    pub fn is_failure(error: &CommonApiError) -> bool {
+        matches!(
+            error.kind,
+            CommonErrorKind::Connect | CommonErrorKind::Timeout
+        )
+    }
+
+    fn check_at(&self, host: &str, now: Instant) -> Result<(), CommonApiError> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = match hosts.get_mut(host) {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+
+        let allow_probe = match *state {
+            BreakerState::Closed { .. } => return Ok(()),
+            BreakerState::Open { until } => now >= until,
+            BreakerState::HalfOpen { since } =>
+                now >= since + self.config.open_duration,
+        };
+
+        if allow_probe {
+            *state = BreakerState::HalfOpen { since: now };
+            Ok(())
+        } else {
+            let kind = CommonErrorKind::Connect;
+            let msg = format!(
+                "Circuit breaker open for '{host}' after repeated failures; \
+                 failing fast"
+            );
+            Err(CommonApiError::new(kind, msg))
+        }
+    }
+
+    fn record_at(&self, host: &str, is_failure: bool, now: Instant) {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        if !is_failure {
+            // Don't bother tracking healthy hosts.
+            hosts.remove(host);
+            return;
+        }
+
+        let state =
+            hosts
+                .entry(host.to_owned())
+                .or_insert(BreakerState::Closed {
+                    consecutive_failures: 0,
+                });
+
+        *state = match *state {
+            BreakerState::Closed {
+                consecutive_failures,
+            } => {
+                let consecutive_failures = consecutive_failures + 1;
+                if consecutive_failures >= self.config.failure_threshold {
+                    BreakerState::Open {
+                        until: now + self.config.open_duration,
+                    }
+                } else {
+                    BreakerState::Closed {
+                        consecutive_failures,
+                    }
+                }
+            }
+            // The probe failed (or a request sent before the breaker opened
+            // failed); (re-)open the breaker.
+            BreakerState::HalfOpen { .. } | BreakerState::Open { .. } =>
+                BreakerState::Open {
+                    until: now + self.config.open_duration,
+                },
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HOST: &str = "gateway.lexe.app";
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 3,
+            open_duration: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn opens_after_threshold() {
+        let breaker = breaker();
+        let now = Instant::now();
+
+        for _ in 0..2 {
+            assert!(breaker.check_at(HOST, now).is_ok());
+            breaker.record_at(HOST, true, now);
+        }
+        // Still closed after 2 failures
+        assert!(breaker.check_at(HOST, now).is_ok());
+        breaker.record_at(HOST, true, now);
+
+        // Open after the 3rd failure
+        let err = breaker.check_at(HOST, now).unwrap_err();
+        assert!(matches!(err.kind, CommonErrorKind::Connect));
+
+        // Other hosts are unaffected
+        assert!(breaker.check_at("other.lexe.app", now).is_ok());
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let breaker = breaker();
+        let now = Instant::now();
+
+        breaker.record_at(HOST, true, now);
+        breaker.record_at(HOST, true, now);
+        breaker.record_at(HOST, false, now);
+        breaker.record_at(HOST, true, now);
+        breaker.record_at(HOST, true, now);
+
+        assert!(breaker.check_at(HOST, now).is_ok());
+    }
+
+    #[test]
+    fn half_open_probe() {
+        let breaker = breaker();
+        let now = Instant::now();
+        for _ in 0..3 {
+            breaker.record_at(HOST, true, now);
+        }
+        assert!(breaker.check_at(HOST, now).is_err());
+
+        // After the open duration, exactly one probe is let through.
+        let t1 = now + Duration::from_secs(10);
+        assert!(breaker.check_at(HOST, t1).is_ok());
+        assert!(breaker.check_at(HOST, t1).is_err());
+
+        // Failed probe => open again
+        breaker.record_at(HOST, true, t1);
+        assert!(breaker.check_at(HOST, t1 + Duration::from_secs(1)).is_err());
+
+        // Successful probe => closed
+        let t2 = t1 + Duration::from_secs(10);
+        assert!(breaker.check_at(HOST, t2).is_ok());
+        breaker.record_at(HOST, false, t2);
+        assert!(breaker.check_at(HOST, t2).is_ok());
+        assert!(breaker.check_at(HOST, t2).is_ok());
+    }
+
+    #[test]
+    fn abandoned_probe_eventually_retried() {
+        let breaker = breaker();
+        let now = Instant::now();
+        for _ in 0..3 {
+            breaker.record_at(HOST, true, now);
+        }
+
+        // Probe is let through but never reports back
+        let t1 = now + Duration::from_secs(10);
+        assert!(breaker.check_at(HOST, t1).is_ok());
+        assert!(breaker.check_at(HOST, t1 + Duration::from_secs(5)).is_err());
+
+        // Another probe is allowed after another open duration
+        let t2 = t1 + Duration::from_secs(10);
+        assert!(breaker.check_at(HOST, t2).is_ok());
+    }
+}
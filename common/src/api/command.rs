@@ -1,13 +1,25 @@
+use anyhow::ensure;
 use bitcoin::Address;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    api::NodePk,
+    api::{NodePk, Scid, UserPk},
     enclave::Measurement,
+    hexstr_or_bytes, hexstr_or_bytes_opt,
     ln::{
-        amount::Amount, balance::Balance, channel::ChannelId, hashes::LxTxid,
-        invoice::LxInvoice, payments::ClientPaymentId, ConfirmationPriority,
+        amount::Amount,
+        balance::Balance,
+        channel::ChannelId,
+        hashes::LxTxid,
+        invoice::LxInvoice,
+        payments::{
+            BasicPayment, ClientPaymentId, PaymentDirection, PaymentIndex,
+            PaymentKind, PaymentStatus,
+        },
+        ConfirmationPriority,
     },
+    metered::QueueMetrics,
+    test_event::RecordedTestEvent,
     time::TimestampMs,
 };
 
@@ -27,6 +39,82 @@ pub struct NodeInfo {
     pub pending_monitor_updates: usize,
 }
 
+/// Operational metrics for a single user node, for the Lexe operators.
+/// Counters are cumulative since [`NodeMetrics::started_at`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeMetrics {
+    pub user_pk: UserPk,
+    /// When the node started running.
+    pub started_at: TimestampMs,
+    /// Requests received by the app server, including rate limited requests.
+    pub app_requests: u64,
+    /// Payments successfully created or updated in Lexe's DB.
+    pub payments_persisted: u64,
+    /// Successful persists of new or updated channel monitors. Updates which
+    /// were superseded by a newer queued update aren't counted.
+    pub channel_monitor_persists: u64,
+    /// Successful persists of all other VFS files, e.g. the channel manager,
+    /// wallet db.
+    pub file_persists: u64,
+    /// GDrive backup files which failed a periodic read-back verification.
+    /// Any non-zero value should be investigated.
+    pub backup_verification_failures: u64,
+    /// Periodic GDrive storage checks which found the user's Drive nearly
+    /// full. Backups fail once it is full, so the user should be told.
+    pub gdrive_nearly_full: u64,
+    pub num_channels: usize,
+    pub num_usable_channels: usize,
+    pub num_peers: usize,
+    /// The number of recent task panics in this process, which may also run
+    /// other nodes. Recorded panics are never cleared, so they're reported
+    /// here rather than failing the node's status. See
+    /// [`task::recent_crashes`].
+    ///
+    /// [`task::recent_crashes`]: crate::task::recent_crashes
+    pub num_task_crashes: usize,
+    /// The queue of channel monitor updates awaiting persistence to Lexe's DB
+    /// and GDrive. A growing `depth` or `oldest_age_ms` means persistence
+    /// isn't keeping up, and channels are paused in the meantime.
+    pub channel_monitor_queue: QueueMetrics,
+}
+
+/// An LDK event which repeatedly failed to be handled and was set aside so
+/// that it wouldn't block the node. See `lexe_ln::event::DeadLetterQueue`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantinedEvent {
+    /// See `lexe_ln::event::event_id`.
+    #[serde(with = "hexstr_or_bytes")]
+    pub id: [u8; 32],
+    pub event_name: String,
+    /// The # of failed attempts to handle this event, including any failed
+    /// re-injections after it was quarantined.
+    pub attempts: u32,
+    pub first_failed_at: TimestampMs,
+    pub quarantined_at: TimestampMs,
+    /// The LDK-serialized event.
+    #[serde(with = "hexstr_or_bytes")]
+    pub event: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuarantinedEvents {
+    pub events: Vec<QuarantinedEvent>,
+}
+
+/// The node's recent internal events, oldest first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventHistory {
+    pub events: Vec<RecordedTestEvent>,
+}
+
+/// Re-injects a [`QuarantinedEvent`] into the node's event handler.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReinjectEventRequest {
+    pub user_pk: UserPk,
+    #[serde(with = "hexstr_or_bytes")]
+    pub id: [u8; 32],
+}
+
 /// The information required for the user node to open a channel to the LSP.
 #[derive(Serialize, Deserialize)]
 pub struct OpenChannelRequest {
@@ -44,6 +132,58 @@ pub struct CreateInvoiceRequest {
     /// string (""), as lightning _requires_ a description (or description
     /// hash) to be set.
     pub description: Option<String>,
+    /// The SHA-256 hash of a description which the invoice should commit to
+    /// instead of including it directly (BOLT11 `h` field), e.g. for a
+    /// merchant's order details which would make the invoice too large.
+    ///
+    /// Can't be set together with `description`.
+    #[serde(default, with = "hexstr_or_bytes_opt")]
+    pub description_hash: Option<[u8; 32]>,
+    /// Arbitrary metadata to include in the invoice (BOLT11 `m` field). This
+    /// is visible to the payer, whose node echoes it back to us when paying.
+    #[serde(default, with = "hexstr_or_bytes_opt")]
+    pub payment_metadata: Option<Vec<u8>>,
+}
+
+impl CreateInvoiceRequest {
+    /// The maximum invoice expiry we'll accept: 180 days. Longer-lived
+    /// invoices would just bloat the payments DB.
+    pub const MAX_EXPIRY_SECS: u32 = 180 * 24 * 60 * 60;
+    /// BOLT11 descriptions are limited to 639 bytes.
+    pub const MAX_DESCRIPTION_LEN: usize = 639;
+    /// The maximum length of [`Self::payment_metadata`], in bytes.
+    pub const MAX_PAYMENT_METADATA_LEN: usize = 256;
+
+    /// Check that this request can produce a valid (and reasonably sized)
+    /// invoice.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(self.expiry_secs > 0, "Invoice expiry must be non-zero");
+        ensure!(
+            self.expiry_secs <= Self::MAX_EXPIRY_SECS,
+            "Invoice expiry can be at most {} secs",
+            Self::MAX_EXPIRY_SECS,
+        );
+        ensure!(
+            !(self.description.is_some() && self.description_hash.is_some()),
+            "Can't set both a description and a description hash"
+        );
+        if let Some(description) = &self.description {
+            ensure!(
+                description.len() <= Self::MAX_DESCRIPTION_LEN,
+                "Description can be at most {} bytes",
+                Self::MAX_DESCRIPTION_LEN,
+            );
+        }
+        if let Some(metadata) = &self.payment_metadata {
+            ensure!(!metadata.is_empty(), "Payment metadata is empty");
+            ensure!(
+                metadata.len() <= Self::MAX_PAYMENT_METADATA_LEN,
+                "Payment metadata can be at most {} bytes",
+                Self::MAX_PAYMENT_METADATA_LEN,
+            );
+        }
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -61,6 +201,9 @@ pub struct PayInvoiceRequest {
     /// An optional personal note for this payment, useful if the
     /// receiver-provided description is insufficient.
     pub note: Option<String>,
+    /// Optional constraints on how the payment is routed.
+    #[serde(default)]
+    pub route_controls: RouteControls,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -78,6 +221,27 @@ pub struct PreflightPayInvoiceRequest {
     /// Specifies the amount we will pay if the invoice to be paid is
     /// amountless. This field must be [`Some`] for amountless invoices.
     pub fallback_amount: Option<Amount>,
+    /// Optional constraints on how the payment is routed.
+    #[serde(default)]
+    pub route_controls: RouteControls,
+}
+
+/// Per-payment constraints on how an invoice payment is routed. The default
+/// value places no constraints on the route.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteControls {
+    /// Don't route through any of these nodes.
+    #[serde(default)]
+    pub exclude_nodes: Vec<NodePk>,
+    /// Don't route through any of these channels.
+    #[serde(default)]
+    pub exclude_channels: Vec<Scid>,
+    /// If set, the payment must leave our node through this channel. Useful
+    /// for users with multiple channels who want to manage their balances.
+    /// Payments which exclude any of our own channels or channel peers, or
+    /// which pin the first hop, are sent without retries.
+    #[serde(default)]
+    pub first_hop: Option<ChannelId>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -106,6 +270,11 @@ pub struct PayOnchainRequest {
     pub priority: ConfirmationPriority,
     /// An optional personal note for this payment.
     pub note: Option<String>,
+    /// The receiver's BIP78 payjoin endpoint, i.e. the `pj` param of the
+    /// BIP21 URI being paid. If set, we'll try to payjoin, falling back to a
+    /// normal send if the payjoin fails.
+    #[serde(default)]
+    pub payjoin_endpoint: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -145,6 +314,182 @@ pub struct FeeEstimate {
     pub amount: Amount,
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct BumpReceiveRequest {
+    /// The txid of the unconfirmed onchain receive we want to speed up.
+    pub txid: LxTxid,
+    /// The feerate (in sats/vbyte) that the receive tx and our child tx should
+    /// pay together.
+    pub fee_rate_sat_per_vb: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BumpReceiveResponse {
+    /// The txid of the child tx we just submitted to the mempool.
+    pub txid: LxTxid,
+    /// The fees paid by the child tx, which come out of the received amount.
+    pub fees: Amount,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct GetAddressForRequest {
+    /// The amount we expect to receive at the new address.
+    pub amount: Amount,
+    /// An optional label for the deposit, e.g. a merchant's order id. It is
+    /// attached to the onchain receive which pays the address.
+    pub label: Option<String>,
+}
+
+impl GetAddressForRequest {
+    /// The maximum length of [`Self::label`], in bytes.
+    pub const MAX_LABEL_LEN: usize = 256;
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(self.amount > Amount::ZERO, "Amount must be non-zero");
+        if let Some(label) = &self.label {
+            ensure!(
+                label.len() <= Self::MAX_LABEL_LEN,
+                "Label can be at most {} bytes",
+                Self::MAX_LABEL_LEN,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Query the user's payment history, filtered inside the node (i.e. after the
+/// payments have been decrypted). Like [`GetNewPayments`], results are returned
+/// in ascending `(created_at, payment_id)` order.
+///
+/// [`GetNewPayments`]: crate::api::qs::GetNewPayments
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryPayments {
+    /// Optional [`PaymentIndex`] at which the results should start, exclusive.
+    /// Pass the [`QueryPaymentsResponse::next_index`] of the previous page to
+    /// continue a query.
+    pub start_index: Option<PaymentIndex>,
+    /// (Optional) the maximum number of results that can be returned.
+    pub limit: Option<u16>,
+    /// Only payments matching this filter are returned.
+    #[serde(default)]
+    pub filter: PaymentFilter,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryPaymentsResponse {
+    /// The matching payments, in ascending `(created_at, payment_id)` order.
+    pub payments: Vec<BasicPayment>,
+    /// If there may be more matching payments, the `start_index` at which the
+    /// next page should start. [`None`] if all payments have been searched.
+    ///
+    /// NOTE: A page may contain fewer than `limit` payments (even none) while
+    /// there are still more to search, since the node only scans a bounded
+    /// number of payments per request.
+    pub next_index: Option<PaymentIndex>,
+}
+
+/// Filters for a [`QueryPayments`]. Every field is optional, and a payment
+/// must match all of the fields which are set.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PaymentFilter {
+    pub status: Option<PaymentStatus>,
+    pub kind: Option<PaymentKind>,
+    pub direction: Option<PaymentDirection>,
+    /// Only payments created at or after this time.
+    pub created_after: Option<TimestampMs>,
+    /// Only payments created strictly before this time.
+    pub created_before: Option<TimestampMs>,
+    /// Only payments with an amount of at least this much. Payments without
+    /// an amount never match.
+    pub min_amount: Option<Amount>,
+    /// Only payments with an amount of at most this much. Payments without an
+    /// amount never match.
+    pub max_amount: Option<Amount>,
+    /// Only payments whose note (or invoice description, if there is no note)
+    /// contains this text, ignoring case.
+    pub note_contains: Option<String>,
+}
+
+impl PaymentFilter {
+    /// Whether the given payment matches this filter.
+    pub fn matches(&self, payment: &BasicPayment) -> bool {
+        let created_at = payment.created_at();
+        let amount_in_range = |amount: Option<Amount>| match amount {
+            Some(amount) =>
+                self.min_amount.map_or(true, |min| amount >= min)
+                    && self.max_amount.map_or(true, |max| amount <= max),
+            None => self.min_amount.is_none() && self.max_amount.is_none(),
+        };
+
+        self.status.map_or(true, |status| payment.status == status)
+            && self.kind.map_or(true, |kind| payment.kind == kind)
+            && self
+                .direction
+                .map_or(true, |direction| payment.direction == direction)
+            && self.created_after.map_or(true, |after| created_at >= after)
+            && self
+                .created_before
+                .map_or(true, |before| created_at < before)
+            && amount_in_range(payment.amount)
+            && self.note_contains.as_deref().map_or(true, |text| {
+                let text = text.to_lowercase();
+                payment
+                    .note_or_description()
+                    .is_some_and(|note| note.to_lowercase().contains(&text))
+            })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChannelHealthResponse {
+    pub channels: Vec<ChannelHealth>,
+}
+
+/// Whether a channel is still worth keeping open, and what closing it would
+/// cost.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelHealth {
+    pub channel_id: ChannelId,
+    pub counterparty_node_id: NodePk,
+    pub channel_value: Amount,
+    /// Our balance in the channel.
+    pub balance: Amount,
+    /// When we last saw our balance in this channel change, i.e. when it was
+    /// last used for a payment. [`None`] if the node hasn't checked this
+    /// channel yet.
+    pub last_activity: Option<TimestampMs>,
+    /// When we were last connected to our counterparty. [`None`] if we
+    /// haven't been connected to them since we started tracking the channel.
+    pub last_online: Option<TimestampMs>,
+    pub issues: Vec<ChannelIssue>,
+    pub recommendation: ChannelRecommendation,
+    /// The estimated on-chain fees to cooperatively close the channel at the
+    /// current feerate.
+    pub est_coop_close_fee: Amount,
+    /// The estimated on-chain fees to force close the channel and sweep our
+    /// balance at the current feerate.
+    pub est_force_close_fee: Amount,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ChannelIssue {
+    /// The channel hasn't been used for a payment in months.
+    Inactive,
+    /// We haven't been able to connect to our counterparty in weeks.
+    PeerOffline,
+    /// Our balance is so small that closing the channel would cost a large
+    /// fraction of it in fees.
+    Uneconomic,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ChannelRecommendation {
+    Keep,
+    CooperativeClose,
+    /// Our counterparty is offline, so a cooperative close isn't possible.
+    ForceClose,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CloseChannelRequest {
     /// The id of the channel we want to close.
@@ -162,6 +507,111 @@ pub struct CloseChannelRequest {
     pub maybe_counterparty: Option<NodePk>,
 }
 
+/// Identifies a channel open or close started by the app, whose progress can
+/// be tracked with `channel_operation`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ChannelOperationId {
+    #[serde(with = "hexstr_or_bytes")]
+    pub id: [u8; 16],
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ChannelOperationKind {
+    Open,
+    Close,
+}
+
+/// The progress of a channel open or close.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ChannelOperationState {
+    /// (Open) We're connecting to our counterparty and negotiating the
+    /// channel with them.
+    Negotiating,
+    /// (Open) The funding tx has been broadcast but hasn't confirmed yet.
+    FundingBroadcast { funding_txid: LxTxid },
+    /// (Open) The funding tx has confirmed, but not deeply enough for the
+    /// channel to be usable yet.
+    Confirming { confirmations: u32, required: u32 },
+    /// (Open) The channel is ready to use.
+    Active,
+    /// (Close) The close has been initiated, but the channel hasn't been
+    /// closed yet, e.g. because we're waiting on our counterparty.
+    Closing,
+    /// (Close) The channel has been closed. The closing tx may not have
+    /// confirmed yet.
+    Closed { reason: String },
+    /// The operation failed, e.g. the channel was closed before it opened.
+    Failed { error: String },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ChannelOperation {
+    pub kind: ChannelOperationKind,
+    /// The channel being opened or closed, if known yet. For opens, this is
+    /// only known once funding has been negotiated.
+    pub channel_id: Option<ChannelId>,
+    pub state: ChannelOperationState,
+    pub started_at: TimestampMs,
+}
+
+impl ChannelOperationState {
+    /// Whether the operation is over, i.e. the state won't change anymore.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            Self::Active | Self::Closed { .. } | Self::Failed { .. }
+        )
+    }
+}
+
+/// How much Google Drive storage the user's Lexe backups are using, and how
+/// much the user has left. Once the user's Drive is full, backups fail.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GDriveStorageStatus {
+    /// The total size of this node's GDrive files, including trashed files,
+    /// in bytes.
+    pub lexe_bytes: u64,
+    /// The # of this node's GDrive files, including trashed files.
+    pub num_files: usize,
+    /// This node's largest GDrive files, largest first.
+    pub largest_files: Vec<GDriveFileSize>,
+    /// The user's total storage usage across all Google services, in bytes.
+    pub quota_used_bytes: u64,
+    /// The user's storage limit, in bytes, or [`None`] if unlimited.
+    pub quota_limit_bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GDriveFileSize {
+    /// The name of the file in GDrive.
+    pub name: String,
+    pub bytes: u64,
+}
+
+impl GDriveStorageStatus {
+    /// If the user has less than this much storage left, it's nearly full.
+    pub const NEARLY_FULL_BYTES: u64 = 100 * 1024 * 1024;
+    /// If the user has less than this fraction (in percent) of their storage
+    /// left, it's nearly full.
+    pub const NEARLY_FULL_PERCENT: u64 = 5;
+
+    /// The storage the user has left, in bytes, or [`None`] if unlimited.
+    pub fn quota_remaining_bytes(&self) -> Option<u64> {
+        self.quota_limit_bytes
+            .map(|limit| limit.saturating_sub(self.quota_used_bytes))
+    }
+
+    /// Whether the user is about to run out of storage.
+    pub fn is_nearly_full(&self) -> bool {
+        match (self.quota_limit_bytes, self.quota_remaining_bytes()) {
+            (Some(limit), Some(remaining)) =>
+                remaining < Self::NEARLY_FULL_BYTES
+                    || remaining < limit / 100 * Self::NEARLY_FULL_PERCENT,
+            _ => false,
+        }
+    }
+}
+
 #[cfg(any(test, feature = "test-utils"))]
 mod arbitrary {
     use proptest::{
@@ -193,4 +643,73 @@ mod test {
     fn preflight_pay_onchain_roundtrip() {
         query_string_roundtrip_proptest::<PreflightPayOnchainRequest>();
     }
+
+    #[test]
+    fn create_invoice_request_validate() {
+        let valid = CreateInvoiceRequest {
+            expiry_secs: 3600,
+            description_hash: Some([0x42; 32]),
+            payment_metadata: Some(vec![0x69; 32]),
+            ..Default::default()
+        };
+        valid.validate().unwrap();
+
+        let invalid = [
+            CreateInvoiceRequest {
+                expiry_secs: 0,
+                ..Default::default()
+            },
+            CreateInvoiceRequest {
+                expiry_secs: CreateInvoiceRequest::MAX_EXPIRY_SECS + 1,
+                ..Default::default()
+            },
+            CreateInvoiceRequest {
+                expiry_secs: 3600,
+                description: Some("coffee".to_owned()),
+                description_hash: Some([0x42; 32]),
+                ..Default::default()
+            },
+            CreateInvoiceRequest {
+                expiry_secs: 3600,
+                payment_metadata: Some(vec![0x69; 257]),
+                ..Default::default()
+            },
+        ];
+        for req in invalid {
+            assert!(req.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn create_invoice_request_backwards_compat() {
+        // Requests from older apps don't include the new fields.
+        let json = r#"{"expiry_secs":3600,"amount":null,"description":"hi"}"#;
+        let req: CreateInvoiceRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(req.description_hash, None);
+        assert_eq!(req.payment_metadata, None);
+        req.validate().unwrap();
+    }
+
+    #[test]
+    fn gdrive_storage_nearly_full() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        let status = |used, limit| GDriveStorageStatus {
+            lexe_bytes: 1024,
+            num_files: 1,
+            largest_files: Vec::new(),
+            quota_used_bytes: used,
+            quota_limit_bytes: limit,
+        };
+
+        assert!(!status(GIB, Some(15 * GIB)).is_nearly_full());
+        assert!(!status(100 * GIB, None).is_nearly_full());
+        // Less than 5% left
+        assert!(status(15 * GIB - GIB / 2, Some(15 * GIB)).is_nearly_full());
+        // Less than 100 MiB left
+        assert!(status(GIB - GIB / 20, Some(GIB)).is_nearly_full());
+        // Over quota
+        let over = status(16 * GIB, Some(15 * GIB));
+        assert_eq!(over.quota_remaining_bytes(), Some(0));
+        assert!(over.is_nearly_full());
+    }
 }
@@ -33,31 +33,51 @@ use crate::{
             UserSignupRequest,
         },
         command::{
-            CreateInvoiceRequest, CreateInvoiceResponse, NodeInfo,
+            BumpReceiveRequest, BumpReceiveResponse, ChannelHealthResponse,
+            ChannelOperation, ChannelOperationId, CloseChannelRequest,
+            CreateInvoiceRequest, CreateInvoiceResponse, EventHistory,
+            GDriveStorageStatus, GetAddressForRequest, NodeInfo, NodeMetrics,
             OpenChannelRequest, PayInvoiceRequest, PayInvoiceResponse,
             PayOnchainRequest, PayOnchainResponse, PreflightPayInvoiceRequest,
             PreflightPayInvoiceResponse, PreflightPayOnchainRequest,
-            PreflightPayOnchainResponse,
+            PreflightPayOnchainResponse, QuarantinedEvents, QueryPayments,
+            QueryPaymentsResponse, ReinjectEventRequest,
         },
         error::{
             BackendApiError, GatewayApiError, LspApiError, NodeApiError,
             RunnerApiError,
         },
+        event_history::SignedEventHistoryRequest,
         fiat_rates::FiatRates,
+        log_levels::SignedLogLevels,
+        migration::{
+            DecommissionRequest, ExportStateRequest, ExportStateResponse,
+            ImportStateRequest,
+        },
         models::NodeRelease,
-        ports::Ports,
-        provision::{NodeProvisionRequest, SealedSeed, SealedSeedId},
+        ports::{NodeQuiesced, Ports},
+        provision::{
+            AttestChallengeRequest, AttestChallengeResponse,
+            NodeProvisionRequest, SealedSeed, SealedSeedId,
+        },
         qs::{
             GetNewPayments, GetPaymentByIndex, GetPaymentsByIds,
             UpdatePaymentNote,
         },
-        vfs::{VfsDirectory, VfsFile, VfsFileId},
+        remote_config::SignedRemoteConfig,
+        settings::SettingsDoc,
+        user::UserProfile,
+        vfs::{RestoreFileVersionRequest, VfsDirectory, VfsFile, VfsFileId},
         Empty, NodePk, Scid, User, UserPk,
     },
     ed25519,
     enclave::Measurement,
-    ln::payments::{BasicPayment, DbPayment, LxPaymentId},
+    ln::{
+        fee_policy::FeePolicy,
+        payments::{BasicPayment, DbPayment, LxPaymentId},
+    },
     test_event::TestEventOp,
+    tls::attestation::evidence::EvidenceBundle,
 };
 
 /// Defines the api that the backend exposes to the node.
@@ -144,6 +164,15 @@ pub trait NodeBackendApi {
         auth: BearerAuthToken,
     ) -> Result<Vec<VfsFile>, BackendApiError>;
 
+    /// GET /node/v1/remote_config [`Empty`] -> [`Option<SignedRemoteConfig>`]
+    ///
+    /// Returns the latest Lexe-signed remote config, if one exists. The node
+    /// must verify the signature before using it.
+    async fn get_remote_config(
+        &self,
+        auth: BearerAuthToken,
+    ) -> Result<Option<SignedRemoteConfig>, BackendApiError>;
+
     /// GET /node/v1/payments [`GetPaymentByIndex`] -> [`Option<DbPayment>`]
     async fn get_payment(
         &self,
@@ -188,6 +217,17 @@ pub trait NodeBackendApi {
         auth: BearerAuthToken,
     ) -> Result<Vec<DbPayment>, BackendApiError>;
 
+    /// POST /node/v1/payments/delete [`GetPaymentsByIds`] -> [`Empty`]
+    ///
+    /// ACID endpoint for deleting a batch of finalized payments, e.g. after
+    /// they have been archived elsewhere. Pending payments are never deleted.
+    // We use POST for the same reason as `get_payments_by_ids`.
+    async fn delete_payments(
+        &self,
+        req: GetPaymentsByIds,
+        auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError>;
+
     /// GET /node/v1/payments/new [`GetNewPayments`] -> [`Vec<DbPayment>`]
     ///
     /// Sync a batch of new payments to local storage, optionally starting from
@@ -260,6 +300,16 @@ pub trait NodeLspApi {
 pub trait NodeRunnerApi {
     /// POST /node/ready [`Ports`] -> [`Empty`]
     async fn ready(&self, ports: &Ports) -> Result<Empty, RunnerApiError>;
+
+    /// POST /node/quiesced [`NodeQuiesced`] -> [`Empty`]
+    ///
+    /// Tells the runner that the node has stopped serving requests, flushed
+    /// its state, and released its fencing token, so it is now safe to start
+    /// the node on another meganode.
+    async fn quiesced(
+        &self,
+        data: &NodeQuiesced,
+    ) -> Result<Empty, RunnerApiError>;
 }
 
 /// Defines the API the node exposes to the Lexe operators at run time.
@@ -269,8 +319,55 @@ pub trait NodeRunnerApi {
 #[async_trait]
 pub trait LexeNodeRunApi {
     /// GET /lexe/status [`GetByUserPk`] -> [`Empty`]
+    ///
+    /// Returns `Ok` if the node is running for the given user. Task panics
+    /// don't fail this check; they are counted in
+    /// [`NodeMetrics::num_task_crashes`] instead. For liveness and readiness
+    /// probes, use the node's standard [`health`] endpoints.
+    ///
+    /// [`health`]: crate::api::server::health
     async fn status(&self, user_pk: UserPk) -> Result<Empty, NodeApiError>;
 
+    /// GET /lexe/metrics [`GetByUserPk`] -> [`NodeMetrics`]
+    ///
+    /// Returns counters and stats used to monitor this node's resource usage.
+    async fn metrics(
+        &self,
+        user_pk: UserPk,
+    ) -> Result<NodeMetrics, NodeApiError>;
+
+    /// GET /lexe/quarantined_events [`GetByUserPk`] -> [`QuarantinedEvents`]
+    ///
+    /// Lists the events which were quarantined after repeatedly failing to be
+    /// handled.
+    async fn quarantined_events(
+        &self,
+        user_pk: UserPk,
+    ) -> Result<QuarantinedEvents, NodeApiError>;
+
+    /// POST /lexe/event_history [`SignedEventHistoryRequest`]
+    ///                          -> [`EventHistory`]
+    ///
+    /// Dumps the node's internal events (syncs, persists, payments, etc) from
+    /// the last hour with their timestamps, to help diagnose user reports.
+    /// The history is kept in memory, so it starts over when the node
+    /// restarts. The request must be signed by Lexe and addressed to this
+    /// node.
+    async fn event_history(
+        &self,
+        req: SignedEventHistoryRequest,
+    ) -> Result<EventHistory, NodeApiError>;
+
+    /// POST /lexe/reinject_event [`ReinjectEventRequest`] -> [`Empty`]
+    ///
+    /// Hands a quarantined event to the event handler again, e.g. after
+    /// deploying a fix. The event stays quarantined until it's handled
+    /// successfully. Returns before the event has been handled.
+    async fn reinject_event(
+        &self,
+        req: ReinjectEventRequest,
+    ) -> Result<Empty, NodeApiError>;
+
     /// POST /lexe/resync [`Empty`] -> [`Empty`]
     ///
     /// Triggers an immediate resync of BDK and LDK.
@@ -303,6 +400,23 @@ pub trait LexeNodeRunApi {
         &self,
         user_pk: UserPk,
     ) -> Result<Empty, NodeApiError>;
+
+    /// GET /lexe/quiesce [`GetByUserPk`] -> [`Empty`]
+    ///
+    /// Prepares the node to be migrated to another meganode: the node stops
+    /// accepting new app requests, shuts down (flushing all persistence),
+    /// releases its fencing token, then notifies the runner via
+    /// [`NodeRunnerApi::quiesced`]. Returns once the quiesce has started.
+    async fn quiesce(&self, user_pk: UserPk) -> Result<Empty, NodeApiError>;
+
+    /// POST /lexe/log_levels [`SignedLogLevels`] -> [`Empty`]
+    ///
+    /// Changes the log levels of specific targets until the node restarts.
+    /// The command must be signed by Lexe and addressed to this node.
+    async fn set_log_levels(
+        &self,
+        req: SignedLogLevels,
+    ) -> Result<Empty, NodeApiError>;
 }
 
 /// Defines the API the node exposes to the Lexe operators at provision time.
@@ -323,6 +437,21 @@ pub trait LexeNodeProvisionApi {
 /// Defines the api that the node exposes to the app during provisioning.
 #[async_trait]
 pub trait AppNodeProvisionApi {
+    /// Challenge a provisioning node to produce a fresh quote which commits to
+    /// the given nonce, proving its remote attestation isn't being replayed.
+    /// [`NodeClient`] does this (and checks the response) automatically
+    /// before provisioning or importing state.
+    ///
+    /// POST /app/attest [`AttestChallengeRequest`] ->
+    /// [`AttestChallengeResponse`]
+    ///
+    /// [`NodeClient`]: crate::client::NodeClient
+    async fn attest(
+        &self,
+        measurement: Measurement,
+        data: AttestChallengeRequest,
+    ) -> Result<AttestChallengeResponse, NodeApiError>;
+
     /// Provision a node with the given [`Measurement`]. The provisioning node's
     /// remote attestation will be checked against the given [`Measurement`].
     ///
@@ -332,6 +461,17 @@ pub trait AppNodeProvisionApi {
         measurement: Measurement,
         data: NodeProvisionRequest,
     ) -> Result<Empty, NodeApiError>;
+
+    /// Import a node state archive, e.g. one previously exported via
+    /// [`AppNodeRunApi::export_state`]. Fails if the user already has
+    /// Lightning state in Lexe's DB.
+    ///
+    /// POST /app/import_state [`ImportStateRequest`] -> [`Empty`]
+    async fn import_state(
+        &self,
+        measurement: Measurement,
+        data: ImportStateRequest,
+    ) -> Result<Empty, NodeApiError>;
 }
 
 /// Defines the api that the node exposes to the app during normal operation.
@@ -383,12 +523,88 @@ pub trait AppNodeRunApi {
         req: PreflightPayOnchainRequest,
     ) -> Result<PreflightPayOnchainResponse, NodeApiError>;
 
+    /// POST /app/bump_receive [`BumpReceiveRequest`] -> [`BumpReceiveResponse`]
+    ///
+    /// Speed up the confirmation of an unconfirmed onchain receive by
+    /// broadcasting a child tx which spends our output(s) back to ourselves
+    /// (child-pays-for-parent). The fees are paid from the received amount.
+    async fn bump_receive(
+        &self,
+        req: BumpReceiveRequest,
+    ) -> Result<BumpReceiveResponse, NodeApiError>;
+
     /// POST /app/get_address [`Empty`] -> [`bitcoin::Address`]
     ///
     /// Returns an address which can be used to receive funds. It is unused
     /// unless there is an incoming tx and BDK hasn't detected it yet.
     async fn get_address(&self) -> Result<bitcoin::Address, NodeApiError>;
 
+    /// POST /app/get_address_for [`GetAddressForRequest`]
+    ///                           -> [`bitcoin::Address`]
+    ///
+    /// Returns a fresh address for a deposit of a specific amount. The
+    /// onchain receive which pays it is tagged with the expected amount and
+    /// label, and flagged if it over- or underpays. [`get_address`] never
+    /// returns an address issued here.
+    ///
+    /// [`get_address`]: AppNodeRunApi::get_address
+    async fn get_address_for(
+        &self,
+        req: GetAddressForRequest,
+    ) -> Result<bitcoin::Address, NodeApiError>;
+
+    /// GET /app/channel_health [`Empty`] -> [`ChannelHealthResponse`]
+    ///
+    /// Flags channels which are inactive, whose counterparty is persistently
+    /// offline, or whose balance is too small to be worth much after fees,
+    /// along with a close recommendation and estimated close fees for each.
+    /// Channels with the LSP aren't included.
+    async fn channel_health(
+        &self,
+    ) -> Result<ChannelHealthResponse, NodeApiError>;
+
+    /// GET /app/gdrive_storage_status [`Empty`] -> [`GDriveStorageStatus`]
+    ///
+    /// Reports how much Google Drive storage the user's backups are using, and
+    /// how much the user has left. Backups fail once the user's Drive is full.
+    async fn gdrive_storage_status(
+        &self,
+    ) -> Result<GDriveStorageStatus, NodeApiError>;
+
+    /// POST /app/open_channel [`OpenChannelRequest`] -> [`ChannelOperationId`]
+    ///
+    /// Starts opening a channel to the LSP. Returns immediately; track the
+    /// progress of the open with [`channel_operation`].
+    ///
+    /// [`channel_operation`]: AppNodeRunApi::channel_operation
+    async fn open_channel(
+        &self,
+        req: OpenChannelRequest,
+    ) -> Result<ChannelOperationId, NodeApiError>;
+
+    /// POST /app/close_channel [`CloseChannelRequest`]
+    ///                         -> [`ChannelOperationId`]
+    ///
+    /// Initiates a channel close. Track the progress of the close with
+    /// [`channel_operation`].
+    ///
+    /// [`channel_operation`]: AppNodeRunApi::channel_operation
+    async fn close_channel(
+        &self,
+        req: CloseChannelRequest,
+    ) -> Result<ChannelOperationId, NodeApiError>;
+
+    /// GET /app/channel_operation [`ChannelOperationId`] ->
+    /// [`ChannelOperation`]
+    ///
+    /// Reports the progress of a channel open or close, so that the app can
+    /// show it without polling `list_channels` and guessing. Operations are
+    /// only tracked in memory, so they are forgotten when the node restarts.
+    async fn channel_operation(
+        &self,
+        req: ChannelOperationId,
+    ) -> Result<ChannelOperation, NodeApiError>;
+
     /// POST /v1/payments/ids [`GetPaymentsByIds`] -> [`Vec<DbPayment>`]
     ///
     /// Fetch a batch of payments by their [`LxPaymentId`]s. This is typically
@@ -408,11 +624,112 @@ pub trait AppNodeRunApi {
         req: GetNewPayments,
     ) -> Result<Vec<BasicPayment>, NodeApiError>;
 
+    /// POST /app/payments/query [`QueryPayments`] -> [`QueryPaymentsResponse`]
+    ///
+    /// Search the user's payments by status, kind, direction, time, amount,
+    /// and note. Filtering happens inside the node, since the payments are
+    /// only decrypted there.
+    async fn query_payments(
+        &self,
+        req: QueryPayments,
+    ) -> Result<QueryPaymentsResponse, NodeApiError>;
+
     /// PUT /app/payments/note [`UpdatePaymentNote`] -> [`Empty`]
     async fn update_payment_note(
         &self,
         req: UpdatePaymentNote,
     ) -> Result<Empty, NodeApiError>;
+
+    /// GET /app/profile [`Empty`] -> [`UserProfile`]
+    ///
+    /// Returns the user's profile, or the default (empty) profile if none has
+    /// been set yet.
+    async fn get_user_profile(&self) -> Result<UserProfile, NodeApiError>;
+
+    /// PUT /app/profile [`UserProfile`] -> [`Empty`]
+    ///
+    /// Validates and persists the user's profile, replacing any existing one.
+    async fn update_user_profile(
+        &self,
+        req: UserProfile,
+    ) -> Result<Empty, NodeApiError>;
+
+    /// GET /app/settings [`Empty`] -> [`SettingsDoc`]
+    ///
+    /// Returns the user's synced app settings, or an empty doc if none have
+    /// been synced yet.
+    async fn get_settings(&self) -> Result<SettingsDoc, NodeApiError>;
+
+    /// PUT /app/settings [`SettingsDoc`] -> [`SettingsDoc`]
+    ///
+    /// Merges the app's settings into the node's copy, persists the result,
+    /// and returns it so the app can pick up changes from other devices.
+    async fn sync_settings(
+        &self,
+        req: SettingsDoc,
+    ) -> Result<SettingsDoc, NodeApiError>;
+
+    /// GET /app/fee_policy [`Empty`] -> [`FeePolicy`]
+    ///
+    /// Returns the user's limits on outbound Lightning payment fees.
+    async fn get_fee_policy(&self) -> Result<FeePolicy, NodeApiError>;
+
+    /// PUT /app/fee_policy [`FeePolicy`] -> [`Empty`]
+    ///
+    /// Replaces the user's fee policy. Payments whose fees exceed the policy
+    /// fail with [`NodeErrorKind::FeeExceedsPolicy`], with the fees and limit
+    /// given by [`NodeApiError::as_fee_exceeds_policy`]. If the policy has no
+    /// default limit, the node applies Lexe's default.
+    ///
+    /// [`NodeErrorKind::FeeExceedsPolicy`]: crate::api::error::NodeErrorKind::FeeExceedsPolicy
+    async fn update_fee_policy(
+        &self,
+        req: FeePolicy,
+    ) -> Result<Empty, NodeApiError>;
+
+    /// POST /app/export_state [`ExportStateRequest`] -> [`ExportStateResponse`]
+    ///
+    /// Exports the node's full state as a password-encrypted archive. If
+    /// [`ExportStateRequest::decommission`] is set, the node first stops all
+    /// Lightning activity and then only serves exports and
+    /// [`decommission`](Self::decommission) until it restarts.
+    async fn export_state(
+        &self,
+        req: ExportStateRequest,
+    ) -> Result<ExportStateResponse, NodeApiError>;
+
+    /// POST /app/decommission [`DecommissionRequest`] -> [`Empty`]
+    ///
+    /// Permanently stops the node after a decommissioning export. Only accepted
+    /// with the hash of the last archive exported by the node, so that the
+    /// node can't be decommissioned before the app has its final state.
+    async fn decommission(
+        &self,
+        req: DecommissionRequest,
+    ) -> Result<Empty, NodeApiError>;
+
+    /// POST /app/restore_file_version [`RestoreFileVersionRequest`] ->
+    /// [`Empty`]
+    ///
+    /// Replaces a versioned file, e.g. the channel manager, with one of its
+    /// previous versions, then restarts the node so that it runs from the
+    /// restored file. For manual recovery from a corrupt write only: restoring
+    /// a stale channel manager may cause channels to be force closed.
+    async fn restore_file_version(
+        &self,
+        req: RestoreFileVersionRequest,
+    ) -> Result<Empty, NodeApiError>;
+
+    /// GET /app/attestation_evidence [`Empty`] -> [`EvidenceBundle`]
+    ///
+    /// Returns the node enclave's remote attestation evidence, which can be
+    /// checked with [`verify_evidence_bundle`] to learn which measurement the
+    /// node is running.
+    ///
+    /// [`verify_evidence_bundle`]: crate::tls::attestation::evidence::verify_evidence_bundle
+    async fn get_attestation_evidence(
+        &self,
+    ) -> Result<EvidenceBundle, NodeApiError>;
 }
 
 /// Defines the api that the gateway directly exposes to the app.
@@ -4,7 +4,7 @@
 // Deny suspicious match names that are probably non-existent variants.
 #![deny(non_snake_case)]
 
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, time::Duration};
 
 use axum::response::IntoResponse;
 use http::status::StatusCode;
@@ -14,15 +14,19 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use super::{auth, NodePk, UserPk};
-use crate::api::server;
 #[cfg(any(test, feature = "test-utils"))]
 use crate::test_utils::arbitrary;
+use crate::{api::server, ln::fee_policy::FeeExceedsPolicy};
 
 // Associated constants can't be imported.
 pub const CLIENT_400_BAD_REQUEST: StatusCode = StatusCode::BAD_REQUEST;
 pub const CLIENT_401_UNAUTHORIZED: StatusCode = StatusCode::UNAUTHORIZED;
 pub const CLIENT_404_NOT_FOUND: StatusCode = StatusCode::NOT_FOUND;
 pub const CLIENT_409_CONFLICT: StatusCode = StatusCode::CONFLICT;
+pub const CLIENT_413_PAYLOAD_TOO_LARGE: StatusCode =
+    StatusCode::PAYLOAD_TOO_LARGE;
+pub const CLIENT_429_TOO_MANY_REQUESTS: StatusCode =
+    StatusCode::TOO_MANY_REQUESTS;
 pub const SERVER_500_INTERNAL_SERVER_ERROR: StatusCode =
     StatusCode::INTERNAL_SERVER_ERROR;
 pub const SERVER_502_BAD_GATEWAY: StatusCode = StatusCode::BAD_GATEWAY;
@@ -45,6 +49,22 @@ pub struct ErrorResponse {
     pub code: ErrorCode,
     #[cfg_attr(test, proptest(strategy = "arbitrary::any_string()"))]
     pub msg: String,
+    /// Structured details about the error, for errors which have them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<ErrorData>,
+}
+
+/// Structured details attached to some errors, so that clients don't have to
+/// parse them out of the error message.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "test-utils"), derive(Arbitrary))]
+#[serde(tag = "type")]
+pub enum ErrorData {
+    /// Details for [`NodeErrorKind::FeeExceedsPolicy`] errors.
+    FeeExceedsPolicy(FeeExceedsPolicy),
+    /// Details added in a later version which we don't know about.
+    #[serde(other)]
+    Unknown,
 }
 
 /// A 'trait alias' defining all the supertraits an API error type must impl
@@ -149,26 +169,38 @@ macro_rules! api_error {
                 proptest(strategy = "arbitrary::any_string()")
             )]
             pub msg: String,
+            pub data: Option<ErrorData>,
+        }
+
+        impl $api_error {
+            /// An error without any structured [`ErrorData`].
+            pub fn new(kind: $api_error_kind, msg: String) -> Self {
+                Self {
+                    kind,
+                    msg,
+                    data: None,
+                }
+            }
         }
 
         impl From<ErrorResponse> for $api_error {
-            fn from(ErrorResponse { code, msg }: ErrorResponse) -> Self {
+            fn from(ErrorResponse { code, msg, data }: ErrorResponse) -> Self {
                 let kind = $api_error_kind::from_code(code);
-                Self { kind, msg }
+                Self { kind, msg, data }
             }
         }
 
         impl From<$api_error> for ErrorResponse {
-            fn from($api_error { kind, msg }: $api_error) -> Self {
+            fn from($api_error { kind, msg, data }: $api_error) -> Self {
                 let code = kind.to_code();
-                Self { code, msg }
+                Self { code, msg, data }
             }
         }
 
         impl From<CommonApiError> for $api_error {
             fn from(CommonApiError { kind, msg }: CommonApiError) -> Self {
                 let kind = $api_error_kind::from(kind);
-                Self { kind, msg }
+                Self::new(kind, msg)
             }
         }
 
@@ -420,6 +452,8 @@ pub enum CommonErrorKind {
     Rejection = 7,
     /// Server is currently at capacity; retry later
     AtCapacity = 8,
+    /// Request body is larger than the server or endpoint allows
+    BodyTooLarge = 9,
     // NOTE: If adding a variant, be sure to also update Self::KINDS!
 }
 
@@ -435,6 +469,7 @@ impl ToHttpStatus for CommonErrorKind {
             Server => SERVER_500_INTERNAL_SERVER_ERROR,
             Rejection => CLIENT_400_BAD_REQUEST,
             AtCapacity => SERVER_503_SERVICE_UNAVAILABLE,
+            BodyTooLarge => CLIENT_413_PAYLOAD_TOO_LARGE,
         }
     }
 }
@@ -464,6 +499,8 @@ api_error_kind! {
         Rejection = 7,
         /// Server is at capacity
         AtCapacity = 8,
+        /// Request body is too large
+        BodyTooLarge = 9,
 
         // --- Backend --- //
 
@@ -502,6 +539,7 @@ impl ToHttpStatus for BackendErrorKind {
             Server => SERVER_500_INTERNAL_SERVER_ERROR,
             Rejection => CLIENT_400_BAD_REQUEST,
             AtCapacity => SERVER_503_SERVICE_UNAVAILABLE,
+            BodyTooLarge => CLIENT_413_PAYLOAD_TOO_LARGE,
 
             Database => SERVER_500_INTERNAL_SERVER_ERROR,
             NotFound => CLIENT_404_NOT_FOUND,
@@ -541,6 +579,8 @@ api_error_kind! {
         Rejection = 7,
         /// Server is at capacity
         AtCapacity = 8,
+        /// Request body is too large
+        BodyTooLarge = 9,
 
         // --- Gateway --- //
 
@@ -563,6 +603,7 @@ impl ToHttpStatus for GatewayErrorKind {
             Server => SERVER_500_INTERNAL_SERVER_ERROR,
             Rejection => CLIENT_400_BAD_REQUEST,
             AtCapacity => SERVER_503_SERVICE_UNAVAILABLE,
+            BodyTooLarge => CLIENT_413_PAYLOAD_TOO_LARGE,
 
             FiatRatesMissing => SERVER_500_INTERNAL_SERVER_ERROR,
         }
@@ -594,6 +635,8 @@ api_error_kind! {
         Rejection = 7,
         /// Server is at capacity
         AtCapacity = 8,
+        /// Request body is too large
+        BodyTooLarge = 9,
 
         // --- LSP --- //
 
@@ -620,6 +663,7 @@ impl ToHttpStatus for LspErrorKind {
             Server => SERVER_500_INTERNAL_SERVER_ERROR,
             Rejection => CLIENT_400_BAD_REQUEST,
             AtCapacity => SERVER_503_SERVICE_UNAVAILABLE,
+            BodyTooLarge => CLIENT_413_PAYLOAD_TOO_LARGE,
 
             Provision => SERVER_500_INTERNAL_SERVER_ERROR,
             Scid => SERVER_500_INTERNAL_SERVER_ERROR,
@@ -653,6 +697,8 @@ api_error_kind! {
         Rejection = 7,
         /// Server is at capacity
         AtCapacity = 8,
+        /// Request body is too large
+        BodyTooLarge = 9,
 
         // --- Node --- //
 
@@ -670,6 +716,14 @@ api_error_kind! {
         Proxy = 105,
         /// Error while executing command
         Command = 106,
+        /// Client is sending too many requests; retry later
+        RateLimited = 107,
+        /// Client doesn't support the requested API revision
+        UnsupportedRevision = 108,
+        /// Node is being migrated to another meganode; retry shortly
+        Migrating = 109,
+        /// Payment fees exceed the user's fee policy
+        FeeExceedsPolicy = 110,
     }
 }
 
@@ -687,6 +741,7 @@ impl ToHttpStatus for NodeErrorKind {
             Server => SERVER_500_INTERNAL_SERVER_ERROR,
             Rejection => CLIENT_400_BAD_REQUEST,
             AtCapacity => SERVER_503_SERVICE_UNAVAILABLE,
+            BodyTooLarge => CLIENT_413_PAYLOAD_TOO_LARGE,
 
             WrongUserPk => CLIENT_400_BAD_REQUEST,
             WrongNodePk => CLIENT_400_BAD_REQUEST,
@@ -695,6 +750,10 @@ impl ToHttpStatus for NodeErrorKind {
             BadAuth => CLIENT_401_UNAUTHORIZED,
             Proxy => SERVER_502_BAD_GATEWAY,
             Command => SERVER_500_INTERNAL_SERVER_ERROR,
+            RateLimited => CLIENT_429_TOO_MANY_REQUESTS,
+            UnsupportedRevision => CLIENT_400_BAD_REQUEST,
+            Migrating => SERVER_503_SERVICE_UNAVAILABLE,
+            FeeExceedsPolicy => CLIENT_400_BAD_REQUEST,
         }
     }
 }
@@ -724,6 +783,8 @@ api_error_kind! {
         Rejection = 7,
         /// Server is at capacity
         AtCapacity = 8,
+        /// Request body is too large
+        BodyTooLarge = 9,
 
         // --- Runner --- //
 
@@ -757,6 +818,7 @@ impl ToHttpStatus for RunnerErrorKind {
             Server => SERVER_500_INTERNAL_SERVER_ERROR,
             Rejection => CLIENT_400_BAD_REQUEST,
             AtCapacity => SERVER_503_SERVICE_UNAVAILABLE,
+            BodyTooLarge => CLIENT_413_PAYLOAD_TOO_LARGE,
 
             Runner => SERVER_500_INTERNAL_SERVER_ERROR,
             UnknownMeasurement => CLIENT_404_NOT_FOUND,
@@ -792,6 +854,7 @@ impl CommonErrorKind {
         Self::Server,
         Self::Rejection,
         Self::AtCapacity,
+        Self::BodyTooLarge,
     ];
 
     #[inline]
@@ -830,7 +893,11 @@ impl From<reqwest::Error> for CommonApiError {
 impl From<CommonApiError> for ErrorResponse {
     fn from(CommonApiError { kind, msg }: CommonApiError) -> Self {
         let code = kind.to_code();
-        Self { code, msg }
+        Self {
+            code,
+            msg,
+            data: None,
+        }
     }
 }
 
@@ -848,43 +915,43 @@ impl BackendApiError {
     pub fn unauthorized_user() -> Self {
         let kind = BackendErrorKind::Unauthorized;
         let msg = "current user is not authorized".to_owned();
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn unauthenticated(error: impl fmt::Display) -> Self {
         let kind = BackendErrorKind::Unauthenticated;
         let msg = format!("{error:#}");
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn invalid_parsed_req(msg: impl Into<String>) -> Self {
         let kind = BackendErrorKind::InvalidParsedRequest;
         let msg = msg.into();
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn bcs_serialize(err: bcs::Error) -> Self {
         let kind = BackendErrorKind::Building;
         let msg = format!("Failed to serialize bcs request: {err:#}");
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn batch_size_too_large() -> Self {
         let kind = BackendErrorKind::BatchSizeOverLimit;
         let msg = kind.to_msg().to_owned();
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn conversion(error: impl fmt::Display) -> Self {
         let kind = BackendErrorKind::Conversion;
         let msg = format!("{error:#}");
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn database(error: impl fmt::Display) -> Self {
         let kind = BackendErrorKind::Database;
         let msg = format!("{error:#}");
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 }
 
@@ -896,7 +963,7 @@ impl From<auth::Error> for BackendApiError {
             _ => BackendErrorKind::Unauthenticated,
         };
         let msg = format!("{error:#}");
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 }
 
@@ -904,7 +971,7 @@ impl GatewayApiError {
     pub fn fiat_rates_missing() -> Self {
         let kind = GatewayErrorKind::FiatRatesMissing;
         let msg = kind.to_string();
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 }
 
@@ -912,19 +979,19 @@ impl LspApiError {
     pub fn provision(error: impl fmt::Display) -> Self {
         let msg = format!("{error:#}");
         let kind = LspErrorKind::Provision;
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn scid(error: impl fmt::Display) -> Self {
         let msg = format!("{error:#}");
         let kind = LspErrorKind::Scid;
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn command(error: impl fmt::Display) -> Self {
         let msg = format!("{error:#}");
         let kind = LspErrorKind::Command;
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 }
 
@@ -935,7 +1002,7 @@ impl NodeApiError {
         let msg =
             format!("Node has UserPk '{current_pk}' but received '{given_pk}'");
         let kind = NodeErrorKind::WrongUserPk;
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn wrong_node_pk(derived_pk: NodePk, given_pk: NodePk) -> Self {
@@ -944,25 +1011,59 @@ impl NodeApiError {
         let msg =
             format!("Derived NodePk '{derived_pk}' but received '{given_pk}'");
         let kind = NodeErrorKind::WrongNodePk;
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn proxy(error: impl fmt::Display) -> Self {
         let msg = format!("{error:#}");
         let kind = NodeErrorKind::Proxy;
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn provision(error: impl fmt::Display) -> Self {
         let msg = format!("{error:#}");
         let kind = NodeErrorKind::Provision;
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn command(error: impl fmt::Display) -> Self {
         let msg = format!("{error:#}");
         let kind = NodeErrorKind::Command;
-        Self { kind, msg }
+        Self::new(kind, msg)
+    }
+
+    pub fn rate_limited(retry_after: Duration) -> Self {
+        let secs = retry_after.as_secs_f64();
+        let msg = format!("Too many requests; retry in {secs:.1}s");
+        let kind = NodeErrorKind::RateLimited;
+        Self::new(kind, msg)
+    }
+
+    pub fn unsupported_revision(error: impl fmt::Display) -> Self {
+        let msg = format!("{error:#}");
+        let kind = NodeErrorKind::UnsupportedRevision;
+        Self::new(kind, msg)
+    }
+
+    pub fn migrating() -> Self {
+        let msg = "Node is being migrated; retry shortly".to_owned();
+        let kind = NodeErrorKind::Migrating;
+        Self::new(kind, msg)
+    }
+
+    pub fn fee_exceeds_policy(error: FeeExceedsPolicy) -> Self {
+        let msg = format!("{error:#}");
+        let kind = NodeErrorKind::FeeExceedsPolicy;
+        let data = Some(ErrorData::FeeExceedsPolicy(error));
+        Self { kind, msg, data }
+    }
+
+    /// The structured details of a [`NodeErrorKind::FeeExceedsPolicy`] error.
+    pub fn as_fee_exceeds_policy(&self) -> Option<&FeeExceedsPolicy> {
+        match &self.data {
+            Some(ErrorData::FeeExceedsPolicy(error)) => Some(error),
+            _ => None,
+        }
     }
 }
 
@@ -970,19 +1071,19 @@ impl RunnerApiError {
     pub fn at_capacity(error: impl fmt::Display) -> Self {
         let kind = RunnerErrorKind::AtCapacity;
         let msg = format!("{error:#}");
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn temporarily_unavailable(error: impl fmt::Display) -> Self {
         let kind = RunnerErrorKind::TemporarilyUnavailable;
         let msg = format!("{error:#}");
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 
     pub fn service_unavailable(error: impl fmt::Display) -> Self {
         let kind = RunnerErrorKind::ServiceUnavailable;
         let msg = format!("{error:#}");
-        Self { kind, msg }
+        Self::new(kind, msg)
     }
 }
 
@@ -1084,7 +1185,11 @@ pub mod invariants {
         )| {
             let code = kind.to_code();
             let msg = main_msg.clone();
-            let err_resp = ErrorResponse { code, msg };
+            let err_resp = ErrorResponse {
+                code,
+                msg,
+                data: None,
+            };
             let api_error = E::from(err_resp);
             let kind_name = kind.to_name();
             let kind_msg = kind.to_msg();
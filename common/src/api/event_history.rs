@@ -0,0 +1,68 @@
+//! Lexe-signed requests for a running node's recent internal event history.
+//!
+//! The event history includes payment hashes, amounts, and channel activity,
+//! so it isn't enough for a request to reach the node's Lexe operator
+//! listener; the request must be a [`SignedOperatorCommand`] for that node.
+
+use std::time::Duration;
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{
+        operator_command::{OperatorCommand, SignedOperatorCommand},
+        UserPk,
+    },
+    array, ed25519,
+    time::TimestampMs,
+};
+
+/// How far in the future an [`EventHistoryRequest`] can expire.
+pub const MAX_EVENT_HISTORY_REQUEST_VALIDITY: Duration =
+    Duration::from_secs(10 * 60);
+
+/// Requests the recent internal event history of a single node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct EventHistoryRequest {
+    /// The node this request is for.
+    pub user_pk: UserPk,
+    /// The node rejects this request after this time.
+    pub expires_at: TimestampMs,
+}
+
+/// A signed [`EventHistoryRequest`], as sent over the wire.
+pub type SignedEventHistoryRequest = SignedOperatorCommand<EventHistoryRequest>;
+
+// --- impl EventHistoryRequest --- //
+
+impl ed25519::Signable for EventHistoryRequest {
+    const DOMAIN_SEPARATOR: [u8; 32] =
+        array::pad(*b"LEXE-REALM::EventHistoryRequest");
+}
+
+impl OperatorCommand for EventHistoryRequest {
+    const NAME: &'static str = "Event history request";
+    const MAX_VALIDITY: Duration = MAX_EVENT_HISTORY_REQUEST_VALIDITY;
+
+    fn user_pk(&self) -> &UserPk {
+        &self.user_pk
+    }
+
+    fn expires_at(&self) -> TimestampMs {
+        self.expires_at
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::roundtrip;
+
+    #[test]
+    fn event_history_request_signed_roundtrip() {
+        roundtrip::signed_roundtrip_proptest::<EventHistoryRequest>();
+    }
+}
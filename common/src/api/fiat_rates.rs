@@ -13,7 +13,7 @@ use crate::time::TimestampMs;
 /// ### Examples
 ///
 /// `"USD", "EUR", "DKK", "CNY", ...`
-#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
 #[serde(transparent)]
 pub struct FiatCode(pub String);
 
@@ -0,0 +1,304 @@
+//! Per-request log capture for error reports.
+//!
+//! While a request is being handled, [`LogCaptureLayer`] copies every log
+//! event emitted within the request's task into a bounded per-request buffer.
+//! If the request fails with a 5xx, the server middleware either attaches the
+//! captured lines to the error response (dev / staging) or sends them as an
+//! [`ErrorReport`] to an internal error-report channel (prod). This makes
+//! "what happened during this failed request?" answerable without scanning the
+//! global log stream.
+//!
+//! The buffer is stored in a tokio task local, so events emitted from tasks
+//! spawned by the handler are not captured.
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write},
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use http::{header::CONTENT_LENGTH, HeaderValue, Method, StatusCode};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::{
+    error,
+    field::{Field, Visit},
+    warn, Event, Subscriber,
+};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::{
+    api::error::ErrorResponse, env::DeployEnv, shutdown::ShutdownChannel,
+    task::LxTask,
+};
+
+/// The maximum # of lines captured per request. Once reached, the earliest
+/// lines are dropped, since the last lines are usually the most relevant.
+pub const MAX_CAPTURED_LINES: usize = 128;
+/// Captured lines longer than this many bytes are truncated.
+const MAX_LINE_LEN: usize = 1024;
+/// The capacity of the channel returned by [`error_report_channel`].
+const ERROR_REPORT_CHANNEL_SIZE: usize = 64;
+
+tokio::task_local! {
+    /// The log buffer of the request currently being handled by this task.
+    static CAPTURED: Arc<Mutex<CapturedLogs>>;
+}
+
+/// What to do with the logs captured during a request which failed with a 5xx.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LogCapture {
+    /// Append the captured logs to the `msg` of the [`ErrorResponse`].
+    /// Logs may contain sensitive info, so only use this in dev / staging.
+    Attach,
+    /// Send an [`ErrorReport`] to an internal error-report channel.
+    Report(ErrorReportTx),
+}
+
+/// Log lines captured while handling a single request.
+#[derive(Clone, Debug)]
+pub struct CapturedLogs {
+    /// When the capture started.
+    start: Instant,
+    /// The captured lines, oldest first.
+    pub lines: VecDeque<String>,
+    /// The # of earliest lines dropped to stay within [`MAX_CAPTURED_LINES`].
+    pub dropped: usize,
+}
+
+/// The logs captured during a request which failed with a 5xx.
+#[derive(Clone, Debug)]
+pub struct ErrorReport {
+    pub method: Method,
+    pub path: String,
+    pub status: StatusCode,
+    pub logs: CapturedLogs,
+}
+
+/// The sending half of an internal error-report channel.
+#[derive(Clone, Debug)]
+pub struct ErrorReportTx(mpsc::Sender<ErrorReport>);
+
+/// A [`Layer`] which copies each log event into the log buffer of the request
+/// currently being handled, if any. Add this to the subscriber (with the same
+/// filter as the stdout layer) for [`LogCapture`] to have anything to report.
+pub struct LogCaptureLayer;
+
+/// Formats the fields of an [`Event`] into a single line.
+struct LineVisitor<'a>(&'a mut String);
+
+impl LogCapture {
+    /// Attach captured logs to error responses in dev and staging, and report
+    /// them to `report_tx` in prod.
+    pub fn for_env(deploy_env: DeployEnv, report_tx: ErrorReportTx) -> Self {
+        match deploy_env {
+            DeployEnv::Dev | DeployEnv::Staging => Self::Attach,
+            DeployEnv::Prod => Self::Report(report_tx),
+        }
+    }
+}
+
+impl CapturedLogs {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            lines: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    fn push(&mut self, line: String) {
+        if self.lines.len() >= MAX_CAPTURED_LINES {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+        self.lines.push_back(line);
+    }
+}
+
+impl fmt::Display for CapturedLogs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.dropped > 0 {
+            writeln!(f, "({} earlier lines dropped)", self.dropped)?;
+        }
+        for line in &self.lines {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl ErrorReportTx {
+    /// Sends an [`ErrorReport`], dropping it if the channel is full.
+    pub fn send(&self, report: ErrorReport) {
+        if let Err(e) = self.0.try_send(report) {
+            warn!("Dropped error report: {e:#}");
+        }
+    }
+}
+
+impl PartialEq for ErrorReportTx {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.same_channel(&other.0)
+    }
+}
+
+impl Eq for ErrorReportTx {}
+
+impl<S: Subscriber> Layer<S> for LogCaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // Does nothing (and skips formatting) outside of a capture scope.
+        let _ = CAPTURED.try_with(|captured| {
+            let mut captured = captured.lock().unwrap();
+            let line = format_event(captured.start, event);
+            captured.push(line);
+        });
+    }
+}
+
+impl Visit for LineVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let _ = if field.name() == "message" {
+            write!(self.0, " {value:?}")
+        } else {
+            write!(self.0, " {}={value:?}", field.name())
+        };
+    }
+}
+
+/// Runs `fut`, capturing the log events it emits.
+pub async fn capture<F: Future>(fut: F) -> (F::Output, CapturedLogs) {
+    let captured = Arc::new(Mutex::new(CapturedLogs::new()));
+    let output = CAPTURED.scope(captured.clone(), fut).await;
+    let logs = captured.lock().unwrap().clone();
+    (output, logs)
+}
+
+/// Creates an internal error-report channel.
+pub fn error_report_channel() -> (ErrorReportTx, mpsc::Receiver<ErrorReport>) {
+    let (tx, rx) = mpsc::channel(ERROR_REPORT_CHANNEL_SIZE);
+    (ErrorReportTx(tx), rx)
+}
+
+/// Spawns a task which logs each [`ErrorReport`] as a single event, so that
+/// all logs for a failed request can be found in one place.
+pub fn spawn_error_report_logger(
+    mut report_rx: mpsc::Receiver<ErrorReport>,
+    mut shutdown: ShutdownChannel,
+) -> LxTask<()> {
+    LxTask::spawn_named("error report logger", async move {
+        loop {
+            tokio::select! {
+                Some(report) = report_rx.recv() => {
+                    let ErrorReport {
+                        method,
+                        path,
+                        status,
+                        logs,
+                    } = report;
+                    error!(
+                        %method, %path, %status,
+                        "Request failed; captured logs:\n{logs}"
+                    );
+                }
+                () = shutdown.recv() => break,
+            }
+        }
+    })
+}
+
+/// Appends `logs` to the `msg` of the [`ErrorResponse`] contained in
+/// `response`. Responses whose body isn't an [`ErrorResponse`] are returned
+/// unmodified.
+pub(super) async fn attach_to_response(
+    response: http::Response<axum::body::Body>,
+    logs: &CapturedLogs,
+) -> http::Response<axum::body::Body> {
+    let (mut parts, body) = response.into_parts();
+    // Error response bodies are already in memory, so this can't really fail.
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read error response body: {e:#}");
+            return http::Response::from_parts(
+                parts,
+                axum::body::Body::empty(),
+            );
+        }
+    };
+
+    let mut error_response =
+        match serde_json::from_slice::<ErrorResponse>(&bytes) {
+            Ok(er) => er,
+            Err(_) => return http::Response::from_parts(parts, bytes.into()),
+        };
+    let _ = write!(error_response.msg, "\n\nCaptured logs:\n{logs}");
+    let json_bytes = serde_json::to_vec(&error_response)
+        .expect("Serializing ErrorResponse really shouldn't fail");
+
+    parts
+        .headers
+        .insert(CONTENT_LENGTH, HeaderValue::from(json_bytes.len()));
+    http::Response::from_parts(parts, json_bytes.into())
+}
+
+/// Formats an [`Event`] as e.g. `+12ms INFO node::run: Started node key=val`.
+fn format_event(start: Instant, event: &Event<'_>) -> String {
+    let metadata = event.metadata();
+    let elapsed_ms = start.elapsed().as_millis();
+    let mut line = format!(
+        "+{elapsed_ms}ms {} {}:",
+        metadata.level(),
+        metadata.target(),
+    );
+    event.record(&mut LineVisitor(&mut line));
+
+    if line.len() > MAX_LINE_LEN {
+        let mut end = MAX_LINE_LEN;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        line.truncate(end);
+        line.push_str("...");
+    }
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use tracing::{info, subscriber};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn capture_is_scoped_and_bounded() {
+        let subscriber = tracing_subscriber::registry().with(LogCaptureLayer);
+        let _guard = subscriber::set_default(subscriber);
+
+        info!("before");
+        let ((), logs) = capture(async {
+            info!(answer = 42, "hello");
+            for i in 0..MAX_CAPTURED_LINES {
+                info!("line {i}");
+            }
+        })
+        .await;
+        info!("after");
+
+        assert_eq!(logs.lines.len(), MAX_CAPTURED_LINES);
+        assert_eq!(logs.dropped, 1);
+        let last = logs.lines.back().unwrap();
+        assert!(last.ends_with(&format!("line {}", MAX_CAPTURED_LINES - 1)));
+        assert!(logs.lines.iter().all(|l| !l.contains("before")));
+
+        let ((), logs) = capture(async { info!(answer = 42, "hello") }).await;
+        let line = logs.lines.front().unwrap();
+        assert!(line.contains("INFO"), "{line}");
+        assert!(line.ends_with("hello answer=42"), "{line}");
+    }
+}
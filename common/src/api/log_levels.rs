@@ -0,0 +1,193 @@
+//! Lexe-signed commands which change a running node's log levels.
+//!
+//! When a single node misbehaves, we often want more detailed logs from just
+//! one or two targets (e.g. `lightning::routing`) without redeploying it or
+//! turning up logging everywhere. Since the node runs in an enclave, we can't
+//! just set `RUST_LOG`; instead, an operator sends that node a signed
+//! [`LogLevels`] command (see [`SignedOperatorCommand`]), and the node applies
+//! the new levels on top of its configured filter until it restarts.
+//!
+//! `TRACE` logs may include sensitive data, so prod nodes only accept levels
+//! up to `DEBUG`.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::ensure;
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+use serde::{Deserialize, Serialize};
+use tracing::level_filters::LevelFilter;
+
+use crate::{
+    api::{
+        operator_command::{OperatorCommand, SignedOperatorCommand},
+        UserPk,
+    },
+    array, ed25519,
+    env::DeployEnv,
+    time::TimestampMs,
+};
+
+/// The max # of targets a [`LogLevels`] command can change.
+pub const MAX_LOG_LEVEL_TARGETS: usize = 16;
+/// The max length of a target in a [`LogLevels`] command, in bytes.
+pub const MAX_LOG_TARGET_LEN: usize = 128;
+/// How far in the future a [`LogLevels`] command can expire.
+pub const MAX_LOG_LEVELS_VALIDITY: Duration = Duration::from_secs(60 * 60);
+
+/// A log level, or [`LogLevel::Off`] to silence a target entirely.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Changes the log levels of specific targets on a single node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct LogLevels {
+    /// The node this command is for.
+    pub user_pk: UserPk,
+    /// The node rejects this command after this time.
+    pub expires_at: TimestampMs,
+    /// The new level for each target, e.g. `"lightning::routing"`. `None`
+    /// removes a previously set level, reverting the target to the node's
+    /// configured filter.
+    pub levels: BTreeMap<String, Option<LogLevel>>,
+}
+
+/// A signed [`LogLevels`] command, as sent over the wire.
+pub type SignedLogLevels = SignedOperatorCommand<LogLevels>;
+
+// --- impl LogLevel --- //
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+// --- impl LogLevels --- //
+
+impl ed25519::Signable for LogLevels {
+    const DOMAIN_SEPARATOR: [u8; 32] = array::pad(*b"LEXE-REALM::LogLevels");
+}
+
+impl OperatorCommand for LogLevels {
+    const NAME: &'static str = "Log levels command";
+    const MAX_VALIDITY: Duration = MAX_LOG_LEVELS_VALIDITY;
+
+    fn user_pk(&self) -> &UserPk {
+        &self.user_pk
+    }
+
+    fn expires_at(&self) -> TimestampMs {
+        self.expires_at
+    }
+
+    /// Checks that the requested levels are allowed in the given
+    /// [`DeployEnv`] and are within our size limits.
+    fn validate(&self, deploy_env: DeployEnv) -> anyhow::Result<()> {
+        ensure!(
+            self.levels.len() <= MAX_LOG_LEVEL_TARGETS,
+            "Can change at most {MAX_LOG_LEVEL_TARGETS} targets at once"
+        );
+        for (target, level) in &self.levels {
+            ensure!(
+                !target.is_empty() && target.len() <= MAX_LOG_TARGET_LEN,
+                "Log targets must be 1-{MAX_LOG_TARGET_LEN} bytes long"
+            );
+            let valid_chars = target
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+            ensure!(valid_chars, "Invalid log target: '{target}'");
+            if deploy_env == DeployEnv::Prod {
+                ensure!(
+                    *level != Some(LogLevel::Trace),
+                    "TRACE logs are not allowed in prod: '{target}'"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        api::remote_config::DEV_REMOTE_CONFIG_SIGNER_SEED,
+        test_utils::roundtrip,
+    };
+
+    #[test]
+    fn log_levels_signed_roundtrip() {
+        roundtrip::signed_roundtrip_proptest::<LogLevels>();
+    }
+
+    #[test]
+    fn verify_log_levels() {
+        let user_pk = UserPk::from_u64(1);
+        let now = TimestampMs::from(10_000_000);
+        let log_levels = LogLevels {
+            user_pk,
+            expires_at: TimestampMs::from(10_060_000),
+            levels: BTreeMap::from_iter([
+                ("lightning::routing".to_owned(), Some(LogLevel::Trace)),
+                ("node".to_owned(), None),
+            ]),
+        };
+
+        let dev_key_pair =
+            ed25519::KeyPair::from_seed(&DEV_REMOTE_CONFIG_SIGNER_SEED);
+        let signed = log_levels.sign(&dev_key_pair).unwrap();
+        let verified = signed.verify(DeployEnv::Dev, &user_pk, now).unwrap();
+        assert_eq!(verified, log_levels);
+
+        // Verification also validates the levels
+        let log_levels = LogLevels {
+            levels: BTreeMap::from_iter([("a=b".to_owned(), None)]),
+            ..log_levels
+        };
+        let signed = log_levels.sign(&dev_key_pair).unwrap();
+        signed.verify(DeployEnv::Dev, &user_pk, now).unwrap_err();
+    }
+
+    #[test]
+    fn validate_log_levels() {
+        let mut log_levels = LogLevels {
+            user_pk: UserPk::from_u64(1),
+            expires_at: TimestampMs::MAX,
+            levels: BTreeMap::from_iter([(
+                "lightning::routing".to_owned(),
+                Some(LogLevel::Debug),
+            )]),
+        };
+        log_levels.validate(DeployEnv::Prod).unwrap();
+
+        // No TRACE in prod
+        log_levels
+            .levels
+            .insert("lightning".to_owned(), Some(LogLevel::Trace));
+        log_levels.validate(DeployEnv::Staging).unwrap();
+        log_levels.validate(DeployEnv::Prod).unwrap_err();
+        log_levels.levels.remove("lightning");
+
+        log_levels.levels.insert("a=b".to_owned(), None);
+        log_levels.validate(DeployEnv::Dev).unwrap_err();
+    }
+}
@@ -0,0 +1,253 @@
+//! Password-encrypted archives of a node's VFS state.
+//!
+//! A [`StateArchive`] bundles every VFS file the node persists in Lexe's DB
+//! (channel manager, channel monitors, wallet db, etc) along with a
+//! [`StateManifest`] describing its contents. It is used to migrate a node off
+//! of Lexe's infrastructure (e.g. to a self-hosted node) or to escrow a copy
+//! for disaster recovery.
+//!
+//! The files are archived exactly as they are stored, i.e. they remain
+//! encrypted under the user's VFS master key, which is derived from their
+//! [`RootSeed`]. The archive as a whole is additionally encrypted under a
+//! user-supplied password using [`password::encrypt`], so the encoded archive
+//! is `salt || password_ciphertext(json(archive))`.
+//!
+//! ### Safety
+//!
+//! Running two nodes from the same Lightning state is catastrophic: whichever
+//! node broadcasts an old commitment tx can be punished by the counterparty.
+//! Exporting with [`ExportStateRequest::decommission`] set first stops all of
+//! the exporting node's Lightning activity, so that the archive is its final
+//! state. Once the app has stored the archive, it sends a
+//! [`DecommissionRequest`] to permanently stop the exporting node from running
+//! again. Importing refuses to overwrite existing Lightning state.
+//!
+//! [`RootSeed`]: crate::root_seed::RootSeed
+
+use anyhow::{ensure, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{
+        vfs::{VfsFile, VfsFileId},
+        UserPk,
+    },
+    hexstr_or_bytes, password,
+    rng::{Crng, RngExt},
+    root_seed::RootSeed,
+    sha256,
+    time::TimestampMs,
+};
+
+/// The current version of the [`StateArchive`] format.
+pub const STATE_ARCHIVE_VERSION: u16 = 1;
+
+/// The app sends this to a running node to export its state.
+#[derive(Serialize, Deserialize)]
+pub struct ExportStateRequest {
+    /// The password under which the archive will be encrypted.
+    pub password: String,
+    /// Whether to freeze this node's Lightning state before the export, in
+    /// preparation for a [`DecommissionRequest`], so that the exported state
+    /// can be safely run elsewhere. Exports without this set are only
+    /// suitable for escrow, since the node keeps updating its state.
+    pub decommission: bool,
+}
+
+/// The app sends this to permanently stop a node after a decommissioning
+/// export, once it has stored the exported archive.
+#[derive(Serialize, Deserialize)]
+pub struct DecommissionRequest {
+    /// The SHA-256 hash of the [`ExportStateResponse::archive`], which proves
+    /// that the app received the archive.
+    #[serde(with = "hexstr_or_bytes")]
+    pub archive_sha256: [u8; 32],
+}
+
+/// The encrypted [`StateArchive`] returned by the node.
+#[derive(Serialize, Deserialize)]
+pub struct ExportStateResponse {
+    #[serde(with = "hexstr_or_bytes")]
+    pub archive: Vec<u8>,
+}
+
+/// The app sends this to a provisioning node to import a [`StateArchive`].
+#[derive(Serialize, Deserialize)]
+pub struct ImportStateRequest {
+    /// The root seed of the user whose state is being imported, used to
+    /// authenticate to Lexe's DB.
+    pub root_seed: RootSeed,
+    /// The password the archive was encrypted under.
+    pub password: String,
+    /// The encrypted archive, as returned in [`ExportStateResponse`].
+    #[serde(with = "hexstr_or_bytes")]
+    pub archive: Vec<u8>,
+}
+
+/// A node's full VFS state. See the module docs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateArchive {
+    pub manifest: StateManifest,
+    pub files: Vec<VfsFile>,
+}
+
+/// Describes the contents of a [`StateArchive`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateManifest {
+    /// The [`STATE_ARCHIVE_VERSION`] this archive was created with.
+    pub version: u16,
+    /// The user whose state this is.
+    pub user_pk: UserPk,
+    /// When the archive was created.
+    pub created_at: TimestampMs,
+    /// One entry per archived file, in the same order as
+    /// [`StateArchive::files`].
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub id: VfsFileId,
+    /// The SHA-256 hash of the (VFS-encrypted) file data.
+    #[serde(with = "hexstr_or_bytes")]
+    pub sha256: [u8; 32],
+}
+
+impl StateArchive {
+    pub fn new(user_pk: UserPk, files: Vec<VfsFile>) -> Self {
+        let entries = files
+            .iter()
+            .map(|file| ManifestEntry {
+                id: file.id.clone(),
+                sha256: sha256::digest(&file.data).into_inner(),
+            })
+            .collect();
+        let manifest = StateManifest {
+            version: STATE_ARCHIVE_VERSION,
+            user_pk,
+            created_at: TimestampMs::now(),
+            files: entries,
+        };
+        Self { manifest, files }
+    }
+
+    /// Serialize and encrypt this archive under the given password.
+    pub fn password_encrypt(
+        &self,
+        rng: &mut impl Crng,
+        password: &str,
+    ) -> anyhow::Result<Vec<u8>> {
+        // Sample a completely random salt for maximum security.
+        let salt = rng.gen_bytes();
+
+        let json = serde_json::to_vec(self).expect("Failed to serialize");
+        let mut aes_ciphertext = password::encrypt(rng, password, &salt, &json)
+            .context("Password encryption failed")?;
+
+        // Final value is `salt || aes_ciphertext`
+        let mut combined = Vec::from(salt);
+        combined.append(&mut aes_ciphertext);
+        Ok(combined)
+    }
+
+    /// Decrypt an archive returned by [`password_encrypt`] and check that its
+    /// contents match its manifest.
+    ///
+    /// [`password_encrypt`]: Self::password_encrypt
+    pub fn password_decrypt(
+        password: &str,
+        mut combined: Vec<u8>,
+    ) -> anyhow::Result<Self> {
+        ensure!(combined.len() > 32, "Archive is too short");
+
+        // Split `salt || aes_ciphertext` into component parts
+        let aes_ciphertext = combined.split_off(32);
+        let salt = <[u8; 32]>::try_from(combined.as_slice())
+            .expect("We split off at 32, so there are exactly 32 bytes");
+
+        let json = password::decrypt(password, &salt, aes_ciphertext)
+            .context("Failed to decrypt archive")?;
+        let archive = serde_json::from_slice::<Self>(&json)
+            .context("Failed to deserialize archive")?;
+        archive.validate()?;
+
+        Ok(archive)
+    }
+
+    /// Check that the archived files match the manifest.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let manifest = &self.manifest;
+        ensure!(
+            manifest.version == STATE_ARCHIVE_VERSION,
+            "Unsupported archive version: {}",
+            manifest.version,
+        );
+        ensure!(
+            manifest.files.len() == self.files.len(),
+            "Manifest lists {} files but archive contains {}",
+            manifest.files.len(),
+            self.files.len(),
+        );
+        for (entry, file) in manifest.files.iter().zip(&self.files) {
+            let dirname = &entry.id.dir.dirname;
+            let filename = &entry.id.filename;
+            ensure!(
+                entry.id == file.id,
+                "Mismatched file: {dirname}/{filename}"
+            );
+            ensure!(
+                entry.sha256 == sha256::digest(&file.data).into_inner(),
+                "Corrupted file: {dirname}/{filename}",
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rng::WeakRng;
+
+    fn archive() -> StateArchive {
+        let user_pk = UserPk::from_u64(42);
+        let files = vec![
+            VfsFile::new(".", "channel_manager", vec![1, 2, 3]),
+            VfsFile::new("channel_monitors", "deadbeef_0", vec![4, 5]),
+        ];
+        StateArchive::new(user_pk, files)
+    }
+
+    #[test]
+    fn password_encrypt_roundtrip() {
+        let mut rng = WeakRng::from_u64(20240815);
+        let password = "correct horse battery staple";
+        let archive = archive();
+
+        let encrypted = archive.password_encrypt(&mut rng, password).unwrap();
+        let decrypted =
+            StateArchive::password_decrypt(password, encrypted.clone())
+                .unwrap();
+        assert_eq!(decrypted, archive);
+
+        let wrong = "incorrect horse battery staple";
+        assert!(StateArchive::password_decrypt(wrong, encrypted).is_err());
+    }
+
+    #[test]
+    fn validate_detects_tampering() {
+        archive().validate().unwrap();
+
+        let mut corrupted = archive();
+        corrupted.files[1].data.push(6);
+        assert!(corrupted.validate().is_err());
+
+        let mut missing = archive();
+        missing.files.pop();
+        assert!(missing.validate().is_err());
+
+        let mut reordered = archive();
+        reordered.files.swap(0, 1);
+        assert!(reordered.validate().is_err());
+    }
+}
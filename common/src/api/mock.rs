@@ -0,0 +1,802 @@
+//! Scriptable mock implementations of the API traits in [`def`].
+//!
+//! A [`MockApi`] implements every API trait for its service, so downstream
+//! crates can test code which is generic over (or takes a `dyn`) API trait
+//! without spinning up the real service:
+//!
+//! - [`MockBackend`]: [`NodeBackendApi`], [`AppBackendApi`], and
+//!   [`BearerAuthBackendApi`]
+//! - [`MockGateway`]: [`AppGatewayApi`]
+//! - [`MockNode`]: [`AppNodeRunApi`], [`AppNodeProvisionApi`],
+//!   [`LexeNodeRunApi`], and [`LexeNodeProvisionApi`]
+//! - [`MockLsp`]: [`NodeLspApi`]
+//! - [`MockRunner`]: [`NodeRunnerApi`]
+//!
+//! Responses are scripted per method name, either as one-shot responses with
+//! [`MockApi::respond`] or with a handler closure via
+//! [`MockApi::respond_with`]. Every call is recorded, and the requests of each
+//! method can be inspected afterwards with [`MockApi::requests`]. Calling a
+//! method which has no scripted response panics.
+//!
+//! ```ignore
+//! let node = MockNode::new();
+//! node.respond("node_info", Ok(node_info));
+//! node.respond_with("get_new_payments", |req: &GetNewPayments| Ok(vec![]));
+//!
+//! do_something(&node).await;
+//!
+//! assert_eq!(node.calls(), ["node_info", "get_new_payments"]);
+//! let reqs = node.requests::<GetNewPayments>("get_new_payments");
+//! ```
+//!
+//! Requests are recorded without their [`BearerAuthToken`]s. Methods without
+//! a request record `()`, and methods with more than one request argument
+//! record a tuple of them, e.g. `(Measurement, NodeProvisionRequest)`.
+//!
+//! [`def`]: crate::api::def
+
+use std::{
+    any::{self, Any},
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    api::{
+        auth::{
+            BearerAuthRequest, BearerAuthResponse, BearerAuthToken,
+            UserSignupRequest,
+        },
+        command::{
+            BumpReceiveRequest, BumpReceiveResponse, ChannelHealthResponse,
+            ChannelOperation, ChannelOperationId, CloseChannelRequest,
+            CreateInvoiceRequest, CreateInvoiceResponse, EventHistory,
+            GDriveStorageStatus, GetAddressForRequest, NodeInfo, NodeMetrics,
+            OpenChannelRequest, PayInvoiceRequest, PayInvoiceResponse,
+            PayOnchainRequest, PayOnchainResponse, PreflightPayInvoiceRequest,
+            PreflightPayInvoiceResponse, PreflightPayOnchainRequest,
+            PreflightPayOnchainResponse, QuarantinedEvents, QueryPayments,
+            QueryPaymentsResponse, ReinjectEventRequest,
+        },
+        def::{
+            AppBackendApi, AppGatewayApi, AppNodeProvisionApi, AppNodeRunApi,
+            BearerAuthBackendApi, LexeNodeProvisionApi, LexeNodeRunApi,
+            NodeBackendApi, NodeLspApi, NodeRunnerApi,
+        },
+        error::{
+            BackendApiError, GatewayApiError, LspApiError, NodeApiError,
+            RunnerApiError,
+        },
+        event_history::SignedEventHistoryRequest,
+        fiat_rates::FiatRates,
+        log_levels::SignedLogLevels,
+        migration::{
+            DecommissionRequest, ExportStateRequest, ExportStateResponse,
+            ImportStateRequest,
+        },
+        models::NodeRelease,
+        ports::{NodeQuiesced, Ports},
+        provision::{
+            AttestChallengeRequest, AttestChallengeResponse,
+            NodeProvisionRequest, SealedSeed, SealedSeedId,
+        },
+        qs::{
+            GetNewPayments, GetPaymentByIndex, GetPaymentsByIds,
+            UpdatePaymentNote,
+        },
+        remote_config::SignedRemoteConfig,
+        settings::SettingsDoc,
+        user::UserProfile,
+        vfs::{RestoreFileVersionRequest, VfsDirectory, VfsFile, VfsFileId},
+        Empty, NodePk, Scid, User, UserPk,
+    },
+    ed25519,
+    enclave::Measurement,
+    ln::{
+        fee_policy::FeePolicy,
+        payments::{BasicPayment, DbPayment, LxPaymentId},
+    },
+    test_event::TestEventOp,
+    tls::attestation::evidence::EvidenceBundle,
+};
+
+/// Mocks the backend APIs.
+pub type MockBackend = MockApi<BackendApiError>;
+/// Mocks the gateway APIs.
+pub type MockGateway = MockApi<GatewayApiError>;
+/// Mocks the user node APIs.
+pub type MockNode = MockApi<NodeApiError>;
+/// Mocks the LSP APIs.
+pub type MockLsp = MockApi<LspApiError>;
+/// Mocks the runner APIs.
+pub type MockRunner = MockApi<RunnerApiError>;
+
+type AnyBox = Box<dyn Any + Send>;
+type Handler = Box<dyn Fn(&AnyBox) -> AnyBox + Send>;
+
+/// A mock of a service whose API methods return errors of type `E`.
+pub struct MockApi<E> {
+    inner: Mutex<MockApiInner>,
+    error: std::marker::PhantomData<fn() -> E>,
+}
+
+#[derive(Default)]
+struct MockApiInner {
+    /// One-shot responses for each method, which take priority over handlers.
+    responses: HashMap<&'static str, VecDeque<AnyBox>>,
+    /// Handlers for each method, used once the one-shot responses run out.
+    handlers: HashMap<&'static str, Handler>,
+    /// Every call made, in order, along with its request.
+    calls: Vec<(&'static str, AnyBox)>,
+}
+
+impl<E: Send + 'static> MockApi<E> {
+    /// A mock with no scripted responses.
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(MockApiInner::default()),
+            error: std::marker::PhantomData,
+        }
+    }
+
+    /// Queue a response for the next call to `method`. Queued responses are
+    /// returned in order, before falling back to any handler.
+    pub fn respond<T: Send + 'static>(
+        &self,
+        method: &'static str,
+        response: Result<T, E>,
+    ) {
+        self.inner
+            .lock()
+            .unwrap()
+            .responses
+            .entry(method)
+            .or_default()
+            .push_back(Box::new(response));
+    }
+
+    /// Respond to all calls to `method` (after any queued responses) using
+    /// `handler`, replacing any previous handler for `method`.
+    pub fn respond_with<Req, T, F>(&self, method: &'static str, handler: F)
+    where
+        Req: 'static,
+        T: Send + 'static,
+        F: Fn(&Req) -> Result<T, E> + Send + 'static,
+    {
+        let handler = move |req: &AnyBox| -> AnyBox {
+            let req = req.downcast_ref::<Req>().unwrap_or_else(|| {
+                panic!(
+                    "`{method}` handler expects a `{}` request",
+                    any::type_name::<Req>()
+                )
+            });
+            Box::new(handler(req))
+        };
+        self.inner
+            .lock()
+            .unwrap()
+            .handlers
+            .insert(method, Box::new(handler));
+    }
+
+    /// The methods called so far, in order.
+    pub fn calls(&self) -> Vec<&'static str> {
+        let inner = self.inner.lock().unwrap();
+        inner.calls.iter().map(|(method, _)| *method).collect()
+    }
+
+    /// Removes and returns the requests of every call made to `method` so far.
+    ///
+    /// Panics if `Req` isn't the request type for `method`.
+    pub fn requests<Req: 'static>(&self, method: &'static str) -> Vec<Req> {
+        let mut inner = self.inner.lock().unwrap();
+        let (matching, rest) = std::mem::take(&mut inner.calls)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(m, _)| *m == method);
+        inner.calls = rest;
+        matching
+            .into_iter()
+            .map(|(_, req)| match req.downcast::<Req>() {
+                Ok(req) => *req,
+                Err(_) => panic!(
+                    "`{method}` requests are not `{}`",
+                    any::type_name::<Req>()
+                ),
+            })
+            .collect()
+    }
+
+    /// Record a call to `method` and return its scripted response.
+    fn call<Req, T>(&self, method: &'static str, req: Req) -> Result<T, E>
+    where
+        Req: Send + 'static,
+        T: 'static,
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let req: AnyBox = Box::new(req);
+
+        let queued = inner
+            .responses
+            .get_mut(method)
+            .and_then(VecDeque::pop_front);
+        let response = match queued {
+            Some(response) => response,
+            None => match inner.handlers.get(method) {
+                Some(handler) => handler(&req),
+                None => panic!("No response scripted for `{method}`"),
+            },
+        };
+        inner.calls.push((method, req));
+
+        match response.downcast::<Result<T, E>>() {
+            Ok(response) => *response,
+            Err(_) => panic!(
+                "`{method}` returns `{}`",
+                any::type_name::<Result<T, E>>()
+            ),
+        }
+    }
+}
+
+impl<E: Send + 'static> Default for MockApi<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> fmt::Debug for MockApi<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        let calls = inner.calls.iter().map(|(m, _)| m).collect::<Vec<_>>();
+        f.debug_struct("MockApi").field("calls", &calls).finish()
+    }
+}
+
+// --- Backend --- //
+
+#[async_trait]
+impl NodeBackendApi for MockBackend {
+    async fn get_user(
+        &self,
+        user_pk: UserPk,
+    ) -> Result<Option<User>, BackendApiError> {
+        self.call("get_user", user_pk)
+    }
+
+    async fn get_sealed_seed(
+        &self,
+        data: &SealedSeedId,
+    ) -> Result<Option<SealedSeed>, BackendApiError> {
+        self.call("get_sealed_seed", data.clone())
+    }
+
+    async fn create_sealed_seed(
+        &self,
+        data: &SealedSeed,
+        _auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        self.call("create_sealed_seed", data.clone())
+    }
+
+    async fn delete_sealed_seeds(
+        &self,
+        measurement: Measurement,
+        _auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        self.call("delete_sealed_seeds", measurement)
+    }
+
+    async fn get_scid(
+        &self,
+        node_pk: NodePk,
+        _auth: BearerAuthToken,
+    ) -> Result<Option<Scid>, BackendApiError> {
+        self.call("get_scid", node_pk)
+    }
+
+    async fn get_file(
+        &self,
+        file_id: &VfsFileId,
+        _auth: BearerAuthToken,
+    ) -> Result<Option<VfsFile>, BackendApiError> {
+        self.call("get_file", file_id.clone())
+    }
+
+    async fn create_file(
+        &self,
+        file: &VfsFile,
+        _auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        self.call("create_file", file.clone())
+    }
+
+    async fn upsert_file(
+        &self,
+        file: &VfsFile,
+        _auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        self.call("upsert_file", file.clone())
+    }
+
+    async fn delete_file(
+        &self,
+        file_id: &VfsFileId,
+        _auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        self.call("delete_file", file_id.clone())
+    }
+
+    async fn get_directory(
+        &self,
+        dir: &VfsDirectory,
+        _auth: BearerAuthToken,
+    ) -> Result<Vec<VfsFile>, BackendApiError> {
+        self.call("get_directory", dir.clone())
+    }
+
+    async fn get_remote_config(
+        &self,
+        _auth: BearerAuthToken,
+    ) -> Result<Option<SignedRemoteConfig>, BackendApiError> {
+        self.call("get_remote_config", ())
+    }
+
+    async fn get_payment(
+        &self,
+        req: GetPaymentByIndex,
+        _auth: BearerAuthToken,
+    ) -> Result<Option<DbPayment>, BackendApiError> {
+        self.call("get_payment", req)
+    }
+
+    async fn create_payment(
+        &self,
+        payment: DbPayment,
+        _auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        self.call("create_payment", payment)
+    }
+
+    async fn upsert_payment(
+        &self,
+        payment: DbPayment,
+        _auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        self.call("upsert_payment", payment)
+    }
+
+    async fn upsert_payment_batch(
+        &self,
+        payments: Vec<DbPayment>,
+        _auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        self.call("upsert_payment_batch", payments)
+    }
+
+    async fn get_payments_by_ids(
+        &self,
+        req: GetPaymentsByIds,
+        _auth: BearerAuthToken,
+    ) -> Result<Vec<DbPayment>, BackendApiError> {
+        self.call("get_payments_by_ids", req)
+    }
+
+    async fn delete_payments(
+        &self,
+        req: GetPaymentsByIds,
+        _auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        self.call("delete_payments", req)
+    }
+
+    async fn get_new_payments(
+        &self,
+        req: GetNewPayments,
+        _auth: BearerAuthToken,
+    ) -> Result<Vec<DbPayment>, BackendApiError> {
+        self.call("get_new_payments", req)
+    }
+
+    async fn get_pending_payments(
+        &self,
+        _auth: BearerAuthToken,
+    ) -> Result<Vec<DbPayment>, BackendApiError> {
+        self.call("get_pending_payments", ())
+    }
+
+    async fn get_finalized_payment_ids(
+        &self,
+        _auth: BearerAuthToken,
+    ) -> Result<Vec<LxPaymentId>, BackendApiError> {
+        self.call("get_finalized_payment_ids", ())
+    }
+}
+
+#[async_trait]
+impl AppBackendApi for MockBackend {
+    async fn signup(
+        &self,
+        signed_req: ed25519::Signed<UserSignupRequest>,
+    ) -> Result<Empty, BackendApiError> {
+        self.call("signup", signed_req)
+    }
+}
+
+#[async_trait]
+impl BearerAuthBackendApi for MockBackend {
+    async fn bearer_auth(
+        &self,
+        signed_req: ed25519::Signed<BearerAuthRequest>,
+    ) -> Result<BearerAuthResponse, BackendApiError> {
+        self.call("bearer_auth", signed_req)
+    }
+}
+
+// --- Gateway --- //
+
+#[async_trait]
+impl AppGatewayApi for MockGateway {
+    async fn get_fiat_rates(&self) -> Result<FiatRates, GatewayApiError> {
+        self.call("get_fiat_rates", ())
+    }
+
+    async fn latest_release(&self) -> Result<NodeRelease, GatewayApiError> {
+        self.call("latest_release", ())
+    }
+}
+
+// --- Node --- //
+
+#[async_trait]
+impl AppNodeRunApi for MockNode {
+    async fn node_info(&self) -> Result<NodeInfo, NodeApiError> {
+        self.call("node_info", ())
+    }
+
+    async fn create_invoice(
+        &self,
+        req: CreateInvoiceRequest,
+    ) -> Result<CreateInvoiceResponse, NodeApiError> {
+        self.call("create_invoice", req)
+    }
+
+    async fn pay_invoice(
+        &self,
+        req: PayInvoiceRequest,
+    ) -> Result<PayInvoiceResponse, NodeApiError> {
+        self.call("pay_invoice", req)
+    }
+
+    async fn preflight_pay_invoice(
+        &self,
+        req: PreflightPayInvoiceRequest,
+    ) -> Result<PreflightPayInvoiceResponse, NodeApiError> {
+        self.call("preflight_pay_invoice", req)
+    }
+
+    async fn pay_onchain(
+        &self,
+        req: PayOnchainRequest,
+    ) -> Result<PayOnchainResponse, NodeApiError> {
+        self.call("pay_onchain", req)
+    }
+
+    async fn preflight_pay_onchain(
+        &self,
+        req: PreflightPayOnchainRequest,
+    ) -> Result<PreflightPayOnchainResponse, NodeApiError> {
+        self.call("preflight_pay_onchain", req)
+    }
+
+    async fn bump_receive(
+        &self,
+        req: BumpReceiveRequest,
+    ) -> Result<BumpReceiveResponse, NodeApiError> {
+        self.call("bump_receive", req)
+    }
+
+    async fn get_address(&self) -> Result<bitcoin::Address, NodeApiError> {
+        self.call("get_address", ())
+    }
+
+    async fn get_address_for(
+        &self,
+        req: GetAddressForRequest,
+    ) -> Result<bitcoin::Address, NodeApiError> {
+        self.call("get_address_for", req)
+    }
+
+    async fn channel_health(
+        &self,
+    ) -> Result<ChannelHealthResponse, NodeApiError> {
+        self.call("channel_health", ())
+    }
+
+    async fn gdrive_storage_status(
+        &self,
+    ) -> Result<GDriveStorageStatus, NodeApiError> {
+        self.call("gdrive_storage_status", ())
+    }
+
+    async fn open_channel(
+        &self,
+        req: OpenChannelRequest,
+    ) -> Result<ChannelOperationId, NodeApiError> {
+        // Distinct from `LexeNodeRunApi::open_channel`, which `MockNode` also
+        // implements.
+        self.call("app_open_channel", req)
+    }
+
+    async fn close_channel(
+        &self,
+        req: CloseChannelRequest,
+    ) -> Result<ChannelOperationId, NodeApiError> {
+        self.call("close_channel", req)
+    }
+
+    async fn channel_operation(
+        &self,
+        req: ChannelOperationId,
+    ) -> Result<ChannelOperation, NodeApiError> {
+        self.call("channel_operation", req)
+    }
+
+    async fn get_payments_by_ids(
+        &self,
+        req: GetPaymentsByIds,
+    ) -> Result<Vec<BasicPayment>, NodeApiError> {
+        self.call("get_payments_by_ids", req)
+    }
+
+    async fn get_new_payments(
+        &self,
+        req: GetNewPayments,
+    ) -> Result<Vec<BasicPayment>, NodeApiError> {
+        self.call("get_new_payments", req)
+    }
+
+    async fn query_payments(
+        &self,
+        req: QueryPayments,
+    ) -> Result<QueryPaymentsResponse, NodeApiError> {
+        self.call("query_payments", req)
+    }
+
+    async fn update_payment_note(
+        &self,
+        req: UpdatePaymentNote,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("update_payment_note", req)
+    }
+
+    async fn get_user_profile(&self) -> Result<UserProfile, NodeApiError> {
+        self.call("get_user_profile", ())
+    }
+
+    async fn update_user_profile(
+        &self,
+        req: UserProfile,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("update_user_profile", req)
+    }
+
+    async fn get_settings(&self) -> Result<SettingsDoc, NodeApiError> {
+        self.call("get_settings", ())
+    }
+
+    async fn sync_settings(
+        &self,
+        req: SettingsDoc,
+    ) -> Result<SettingsDoc, NodeApiError> {
+        self.call("sync_settings", req)
+    }
+
+    async fn get_fee_policy(&self) -> Result<FeePolicy, NodeApiError> {
+        self.call("get_fee_policy", ())
+    }
+
+    async fn update_fee_policy(
+        &self,
+        req: FeePolicy,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("update_fee_policy", req)
+    }
+
+    async fn export_state(
+        &self,
+        req: ExportStateRequest,
+    ) -> Result<ExportStateResponse, NodeApiError> {
+        self.call("export_state", req)
+    }
+
+    async fn decommission(
+        &self,
+        req: DecommissionRequest,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("decommission", req)
+    }
+
+    async fn restore_file_version(
+        &self,
+        req: RestoreFileVersionRequest,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("restore_file_version", req)
+    }
+
+    async fn get_attestation_evidence(
+        &self,
+    ) -> Result<EvidenceBundle, NodeApiError> {
+        self.call("get_attestation_evidence", ())
+    }
+}
+
+#[async_trait]
+impl AppNodeProvisionApi for MockNode {
+    async fn attest(
+        &self,
+        measurement: Measurement,
+        data: AttestChallengeRequest,
+    ) -> Result<AttestChallengeResponse, NodeApiError> {
+        self.call("attest", (measurement, data))
+    }
+
+    async fn provision(
+        &self,
+        measurement: Measurement,
+        data: NodeProvisionRequest,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("provision", (measurement, data))
+    }
+
+    async fn import_state(
+        &self,
+        measurement: Measurement,
+        data: ImportStateRequest,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("import_state", (measurement, data))
+    }
+}
+
+#[async_trait]
+impl LexeNodeRunApi for MockNode {
+    async fn status(&self, user_pk: UserPk) -> Result<Empty, NodeApiError> {
+        self.call("status", user_pk)
+    }
+
+    async fn metrics(
+        &self,
+        user_pk: UserPk,
+    ) -> Result<NodeMetrics, NodeApiError> {
+        self.call("metrics", user_pk)
+    }
+
+    async fn quarantined_events(
+        &self,
+        user_pk: UserPk,
+    ) -> Result<QuarantinedEvents, NodeApiError> {
+        self.call("quarantined_events", user_pk)
+    }
+
+    async fn event_history(
+        &self,
+        req: SignedEventHistoryRequest,
+    ) -> Result<EventHistory, NodeApiError> {
+        self.call("event_history", req)
+    }
+
+    async fn reinject_event(
+        &self,
+        req: ReinjectEventRequest,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("reinject_event", req)
+    }
+
+    async fn resync(&self) -> Result<Empty, NodeApiError> {
+        self.call("resync", ())
+    }
+
+    async fn open_channel(
+        &self,
+        req: OpenChannelRequest,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("open_channel", req)
+    }
+
+    async fn test_event(&self, op: TestEventOp) -> Result<(), NodeApiError> {
+        self.call("test_event", op)
+    }
+
+    async fn shutdown_run(
+        &self,
+        user_pk: UserPk,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("shutdown_run", user_pk)
+    }
+
+    async fn quiesce(&self, user_pk: UserPk) -> Result<Empty, NodeApiError> {
+        self.call("quiesce", user_pk)
+    }
+
+    async fn set_log_levels(
+        &self,
+        req: SignedLogLevels,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("set_log_levels", req)
+    }
+}
+
+#[async_trait]
+impl LexeNodeProvisionApi for MockNode {
+    async fn shutdown_provision(
+        &self,
+        measurement: Measurement,
+    ) -> Result<Empty, NodeApiError> {
+        self.call("shutdown_provision", measurement)
+    }
+}
+
+// --- LSP and runner --- //
+
+#[async_trait]
+impl NodeLspApi for MockLsp {
+    async fn get_new_scid(&self, node_pk: NodePk) -> Result<Scid, LspApiError> {
+        self.call("get_new_scid", node_pk)
+    }
+}
+
+#[async_trait]
+impl NodeRunnerApi for MockRunner {
+    async fn ready(&self, ports: &Ports) -> Result<Empty, RunnerApiError> {
+        self.call("ready", ports.clone())
+    }
+
+    async fn quiesced(
+        &self,
+        data: &NodeQuiesced,
+    ) -> Result<Empty, RunnerApiError> {
+        self.call("quiesced", *data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn scripted_responses() {
+        let node = MockNode::new();
+        node.respond("resync", Ok(Empty {}));
+        node.respond("resync", Err(NodeApiError::command("Failed")));
+        node.respond_with("status", |_: &UserPk| Ok(Empty {}));
+
+        // Queued responses are returned in order
+        node.resync().await.unwrap();
+        node.resync().await.unwrap_err();
+
+        // Handlers respond to every call
+        let user_pk = UserPk::from_u64(1);
+        node.status(user_pk).await.unwrap();
+        node.status(user_pk).await.unwrap();
+
+        assert_eq!(node.calls(), ["resync", "resync", "status", "status"]);
+        assert_eq!(node.requests::<UserPk>("status"), [user_pk, user_pk]);
+        assert_eq!(node.calls(), ["resync", "resync"]);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "No response scripted for `resync`")]
+    async fn unscripted_call_panics() {
+        let _ = MockNode::new().resync().await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "`resync` returns")]
+    async fn wrong_response_type_panics() {
+        let node = MockNode::new();
+        node.respond("resync", Ok(()));
+        let _ = node.resync().await;
+    }
+}
@@ -29,30 +29,61 @@ use crate::{
 // `rest`, `server`, `trace`. Only some Lexe crates actually need these.
 /// Authentication and User Signup.
 pub mod auth;
+/// A per-host circuit breaker for `RestClient`.
+pub mod circuit_breaker;
 /// Data types used in APIs for top level commands.
 pub mod command;
 /// Traits defining the various REST API interfaces.
 pub mod def;
 /// Enums for the API errors returned by the various services.
 pub mod error;
+/// Lexe-signed requests for a running node's recent internal event history.
+pub mod event_history;
 /// Data types returned from the fiat exchange rate API.
 pub mod fiat_rates;
+/// Per-request log capture for error reports.
+pub mod log_capture;
+/// Lexe-signed commands which change a running node's log levels.
+pub mod log_levels;
+/// Password-encrypted archives of a node's VFS state.
+pub mod migration;
+/// Scriptable mock implementations of the API traits in [`def`].
+#[cfg(any(test, feature = "test-utils"))]
+pub mod mock;
 /// API models which don't fit anywhere else.
 pub mod models;
+/// Lexe-signed, expiring commands for a single running node.
+pub mod operator_command;
 /// `Port`, `Ports`, `RunPorts`, etc.
 pub mod ports;
 /// Data types specific to provisioning.
 pub mod provision;
 /// Data types used to serialize / deserialize query strings.
 pub mod qs;
+/// A keyed token-bucket rate limiter for API servers.
+pub mod rate_limit;
+/// Release manifests attested by multiple independent builders.
+pub mod release_manifest;
+/// Lexe-signed remote configuration for user nodes.
+pub mod remote_config;
+/// A client-side cache for immutable or slowly-changing API responses.
+pub mod response_cache;
 /// A client and helpers that enforce common REST semantics across Lexe crates.
 pub mod rest;
+/// `ScidPool`, the rotating set of fake SCIDs used in our route hints.
+pub mod scid_pool;
 /// Webserver utilities.
 pub mod server;
+/// App settings, synced across the user's devices.
+pub mod settings;
 /// API tracing utilities for both client and server.
 pub mod trace;
+/// User profile metadata.
+pub mod user;
 /// Data types implementing vfs-based node persistence.
 pub mod vfs;
+/// Webhook event payloads and signature verification.
+pub mod webhook;
 
 /// A struct denoting an empty API request or response.
 ///
@@ -2,9 +2,11 @@
 use proptest_derive::Arbitrary;
 use serde::{Deserialize, Serialize};
 
-use crate::enclave::Measurement;
 #[cfg(test)]
 use crate::test_utils::arbitrary;
+use crate::{
+    api::release_manifest::SignedReleaseManifest, enclave::Measurement,
+};
 
 /// The semver version and measurement of a node release.
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -14,6 +16,9 @@ pub struct NodeRelease {
     #[cfg_attr(test, proptest(strategy = "arbitrary::any_semver_version()"))]
     pub version: semver::Version,
     pub measurement: Measurement,
+    /// The builders' attestations for this release, if any have signed it.
+    #[serde(default)]
+    pub release_manifest: Option<SignedReleaseManifest>,
 }
 
 #[cfg(test)]
@@ -0,0 +1,181 @@
+//! Lexe-signed commands for a single running node.
+//!
+//! Some operator endpoints (changing log levels, dumping the event history)
+//! are too sensitive to authorize by reaching the node's Lexe operator
+//! listener alone. Instead, an operator signs the command with the
+//! [`remote_config_signer`] key and sends it as a [`SignedOperatorCommand`].
+//!
+//! Every [`OperatorCommand`] is bound to a single [`UserPk`] and expires
+//! shortly after being signed, so a captured command can't be replayed
+//! against other nodes or later on.
+
+use std::{fmt, marker::PhantomData, time::Duration};
+
+use anyhow::{ensure, Context};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    api::{remote_config::remote_config_signer, UserPk},
+    ed25519,
+    env::DeployEnv,
+    hexstr_or_bytes,
+    time::TimestampMs,
+};
+
+/// A command which a Lexe operator signs for a single node.
+pub trait OperatorCommand:
+    ed25519::Signable + Serialize + DeserializeOwned
+{
+    /// A human-readable name for this command, used in error messages.
+    const NAME: &'static str;
+    /// How far in the future this command can expire.
+    const MAX_VALIDITY: Duration;
+
+    /// The node this command is for.
+    fn user_pk(&self) -> &UserPk;
+
+    /// The node rejects this command after this time.
+    fn expires_at(&self) -> TimestampMs;
+
+    /// Checks the contents of the command once its signature, user, and
+    /// expiry have been verified. Accepts everything by default.
+    fn validate(&self, _deploy_env: DeployEnv) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Sign this command, returning the [`SignedOperatorCommand`] to send to
+    /// the node.
+    fn sign(
+        &self,
+        key_pair: &ed25519::KeyPair,
+    ) -> Result<SignedOperatorCommand<Self>, bcs::Error> {
+        let (signed_bcs, _) = key_pair.sign_struct(self)?;
+        Ok(SignedOperatorCommand::new(signed_bcs))
+    }
+}
+
+/// A BCS-serialized [`ed25519::Signed<T>`], as sent over the wire.
+/// Must be verified with [`Self::verify`] before use.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct SignedOperatorCommand<T> {
+    #[serde(with = "hexstr_or_bytes")]
+    pub signed_bcs: Vec<u8>,
+    #[serde(skip)]
+    command: PhantomData<fn() -> T>,
+}
+
+// --- impl SignedOperatorCommand --- //
+
+impl<T> SignedOperatorCommand<T> {
+    pub fn new(signed_bcs: Vec<u8>) -> Self {
+        Self {
+            signed_bcs,
+            command: PhantomData,
+        }
+    }
+}
+
+impl<T: OperatorCommand> SignedOperatorCommand<T> {
+    /// Verify that this command was signed by the [`remote_config_signer`]
+    /// for the given [`DeployEnv`], is for the node with the given [`UserPk`],
+    /// hasn't expired, and passes [`OperatorCommand::validate`], returning
+    /// the contained command.
+    pub fn verify(
+        &self,
+        deploy_env: DeployEnv,
+        user_pk: &UserPk,
+        now: TimestampMs,
+    ) -> anyhow::Result<T> {
+        let name = T::NAME;
+        let signer = remote_config_signer(deploy_env);
+        let signed = signer
+            .verify_self_signed_struct::<T>(&self.signed_bcs)
+            .with_context(|| format!("Invalid {name} signature"))?;
+        let (_signer, _sig, command) = signed.into_parts();
+
+        ensure!(
+            command.user_pk() == user_pk,
+            "{name} is for a different user: {}",
+            command.user_pk(),
+        );
+        let expires_at = command.expires_at();
+        ensure!(now < expires_at, "{name} has expired");
+        let validity = expires_at
+            .into_duration()
+            .saturating_sub(now.into_duration());
+        ensure!(
+            validity <= T::MAX_VALIDITY,
+            "{name} expires too far in the future"
+        );
+
+        command.validate(deploy_env)?;
+        Ok(command)
+    }
+}
+
+// Manual impls so that `T` needn't implement these traits itself.
+
+impl<T> Clone for SignedOperatorCommand<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.signed_bcs.clone())
+    }
+}
+
+impl<T> fmt::Debug for SignedOperatorCommand<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignedOperatorCommand")
+            .field("signed_bcs", &self.signed_bcs)
+            .finish()
+    }
+}
+
+impl<T> PartialEq for SignedOperatorCommand<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.signed_bcs == other.signed_bcs
+    }
+}
+
+impl<T> Eq for SignedOperatorCommand<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{
+        event_history::EventHistoryRequest,
+        remote_config::DEV_REMOTE_CONFIG_SIGNER_SEED,
+    };
+
+    #[test]
+    fn verify_operator_command() {
+        let user_pk = UserPk::from_u64(1);
+        let now = TimestampMs::from(10_000_000);
+        let expires_at = TimestampMs::from(10_060_000);
+        let req = EventHistoryRequest {
+            user_pk,
+            expires_at,
+        };
+
+        let dev_key_pair =
+            ed25519::KeyPair::from_seed(&DEV_REMOTE_CONFIG_SIGNER_SEED);
+        let signed = req.sign(&dev_key_pair).unwrap();
+        let verified = signed.verify(DeployEnv::Dev, &user_pk, now).unwrap();
+        assert_eq!(verified, req);
+
+        // Wrong signer, wrong user, or expired
+        signed
+            .verify(DeployEnv::Staging, &user_pk, now)
+            .unwrap_err();
+        let other_pk = UserPk::from_u64(2);
+        signed.verify(DeployEnv::Dev, &other_pk, now).unwrap_err();
+        signed
+            .verify(DeployEnv::Dev, &user_pk, expires_at)
+            .unwrap_err();
+
+        // Commands can't be valid for too long
+        let way_before = TimestampMs::from(1);
+        signed
+            .verify(DeployEnv::Dev, &user_pk, way_before)
+            .unwrap_err();
+    }
+}
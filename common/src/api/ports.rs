@@ -93,3 +93,14 @@ impl Ports {
         }
     }
 }
+
+/// Sent by a run node to the runner once it has quiesced in preparation for
+/// being migrated to another meganode. Used to (de)serialize /quiesced
+/// requests.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct NodeQuiesced {
+    pub user_pk: UserPk,
+    /// The fencing epoch which this node held and has now released. The next
+    /// instance of this node will run with a strictly greater epoch.
+    pub fencing_epoch: u64,
+}
@@ -9,7 +9,7 @@ use serde::{Deserialize, Serialize};
 #[cfg(test)]
 use crate::test_utils::arbitrary;
 use crate::{
-    api::UserPk,
+    api::{release_manifest::SignedReleaseManifest, UserPk},
     array,
     cli::Network,
     ed25519,
@@ -83,6 +83,39 @@ pub struct NodeProvisionRequest {
     /// [`allow_gvfs_access`]: Self::allow_gvfs_access
     #[serde(with = "hexstr_or_bytes_opt")]
     pub encrypted_seed: Option<Vec<u8>>,
+    /// A [`SignedReleaseManifest`] attesting to the measurement of the node
+    /// release being provisioned.
+    /// - If [`Some`], the node will only approve its current version if the
+    ///   manifest is signed by enough trusted builders and lists the node's
+    ///   version with the node's measurement; provisioning fails otherwise.
+    /// - If [`None`], the node approves its current version without any
+    ///   external attestation, i.e. the user trusts Lexe's release. This is
+    ///   rejected if the user provisioned with a manifest before, or if
+    ///   manifests are mandatory in this env.
+    #[serde(default)]
+    pub release_manifest: Option<SignedReleaseManifest>,
+}
+
+/// Before sending any secrets, the client challenges the provisioning node to
+/// prove that its remote attestation is fresh.
+///
+/// See [`AttestationChallenge`] for details.
+///
+/// [`AttestationChallenge`]: crate::tls::attestation::verifier::AttestationChallenge
+#[derive(Serialize, Deserialize)]
+pub struct AttestChallengeRequest {
+    /// The client's random challenge nonce.
+    #[serde(with = "hexstr_or_bytes")]
+    pub nonce: [u8; 32],
+}
+
+/// The provisioning node's response to an [`AttestChallengeRequest`].
+#[derive(Serialize, Deserialize)]
+pub struct AttestChallengeResponse {
+    /// A fresh quote (or dummy report outside of SGX) committing to the node's
+    /// attestation cert pk and the challenge nonce.
+    #[serde(with = "hexstr_or_bytes")]
+    pub quote: Vec<u8>,
 }
 
 /// Uniquely identifies a sealed seed using its primary key fields.
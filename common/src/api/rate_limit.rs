@@ -0,0 +1,163 @@
+//! A keyed token-bucket rate limiter for API servers.
+//!
+//! Each key (e.g. a client identity) gets its own bucket holding up to
+//! [`RateLimitConfig::burst`] tokens. Every request takes a token; tokens are
+//! refilled one at a time every [`RateLimitConfig::refill_interval`]. When a
+//! bucket is empty, [`RateLimiter::check`] returns how long the client should
+//! wait before retrying, which servers should return in a `Retry-After` header.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+use serde::{Deserialize, Serialize};
+
+/// Configuration for a [`RateLimiter`].
+#[cfg_attr(test, derive(Arbitrary))]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// The maximum number of requests a client can make in a burst.
+    pub burst: u32,
+    /// How often a single token is returned to each bucket. The sustained
+    /// request rate is one request per `refill_interval`.
+    pub refill_interval: Duration,
+}
+
+/// Rate limits requests per key. See the module docs for details.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Bucket {
+    tokens: u32,
+    last_refill: Instant,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            burst: 60,
+            // 10 requests per second sustained.
+            refill_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        assert!(config.burst > 0, "burst must be non-zero");
+        assert!(
+            !config.refill_interval.is_zero(),
+            "interval must be non-zero"
+        );
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token from `key`'s bucket. If the bucket is empty, returns the
+    /// time until the next token becomes available.
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let RateLimitConfig {
+            burst,
+            refill_interval,
+        } = self.config;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.to_owned()).or_insert(Bucket {
+            tokens: burst,
+            last_refill: now,
+        });
+
+        // Refill any tokens earned since the last refill.
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        let earned = elapsed.as_nanos() / refill_interval.as_nanos();
+        if earned >= u128::from(burst - bucket.tokens) {
+            bucket.tokens = burst;
+            bucket.last_refill = now;
+        } else if earned > 0 {
+            // Safe: `earned < burst <= u32::MAX`
+            let earned = earned as u32;
+            bucket.tokens += earned;
+            bucket.last_refill += refill_interval * earned;
+        }
+
+        if bucket.tokens == 0 {
+            let since_refill =
+                now.saturating_duration_since(bucket.last_refill);
+            return Err(refill_interval.saturating_sub(since_refill));
+        }
+
+        bucket.tokens -= 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: &str = "client";
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            burst: 3,
+            refill_interval: Duration::from_secs(1),
+        })
+    }
+
+    #[test]
+    fn burst_then_refill() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            limiter.check_at(KEY, now).unwrap();
+        }
+        let retry_after = limiter.check_at(KEY, now).unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(1));
+
+        // Other keys are unaffected
+        limiter.check_at("other", now).unwrap();
+
+        // Partway through the refill interval
+        let t1 = now + Duration::from_millis(400);
+        let retry_after = limiter.check_at(KEY, t1).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(600));
+
+        // One token refilled
+        let t2 = now + Duration::from_millis(1400);
+        limiter.check_at(KEY, t2).unwrap();
+        let retry_after = limiter.check_at(KEY, t2).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(600));
+    }
+
+    #[test]
+    fn refill_caps_at_burst() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            limiter.check_at(KEY, now).unwrap();
+        }
+
+        // A long idle period only refills up to `burst`.
+        let t1 = now + Duration::from_secs(3600);
+        for _ in 0..3 {
+            limiter.check_at(KEY, t1).unwrap();
+        }
+        assert!(limiter.check_at(KEY, t1).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! Release manifests attested by multiple independent builders.
+//!
+//! Our node releases are reproducible, so anyone can rebuild a release and
+//! check that its measurement matches the one Lexe publishes. The reproducible
+//! build pipeline emits a [`ReleaseManifest`] mapping each release version to
+//! its expected measurement, which each builder signs after rebuilding. Users
+//! who delegate version approval to their node can then attach the
+//! [`SignedReleaseManifest`] when provisioning, and the node will only approve
+//! the current version if at least [`manifest_threshold`] of the
+//! [`manifest_signers`] agree on its measurement, rather than trusting Lexe
+//! alone.
+
+use std::collections::BTreeMap;
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+use crate::test_utils::arbitrary;
+use crate::{
+    array, ed25519, enclave::Measurement, env::DeployEnv, hex, hexstr_or_bytes,
+};
+
+/// The keys of the builders who attest to releases in dev. The corresponding
+/// seeds are public (`DEV_MANIFEST_SIGNER_SEEDS`), so these keys provide no
+/// security.
+const DEV_MANIFEST_SIGNERS: [ed25519::PublicKey; 3] = [
+    ed25519::PublicKey::new(hex::decode_const(
+        b"22fc297792f0b6ffc0bfcfdb7edb0c0aa14e025a365ec0e342e86e3829cb74b6",
+    )),
+    ed25519::PublicKey::new(hex::decode_const(
+        b"d759793bbc13a2819a827c76adb6fba8a49aee007f49f2d0992d99b825ad2c48",
+    )),
+    ed25519::PublicKey::new(hex::decode_const(
+        b"6355691c178a8ff91007a7478afb955ef7352c63e7b25703984cf78b26e21a56",
+    )),
+];
+/// The keys of the builders who attest to releases in staging. Empty until
+/// the staging builders' keys have been generated; until then, manifests can't
+/// be verified in staging and aren't required.
+const STAGING_MANIFEST_SIGNERS: [ed25519::PublicKey; 0] = [];
+/// The keys of the builders who attest to releases in prod. Empty until the
+/// prod builders' keys have been generated; until then, manifests can't be
+/// verified in prod and aren't required.
+const PROD_MANIFEST_SIGNERS: [ed25519::PublicKey; 0] = [];
+
+/// The seeds for the dev [`ReleaseManifest`] signing keys, so that dev and
+/// test code can produce valid manifests.
+#[cfg(any(test, feature = "test-utils"))]
+pub const DEV_MANIFEST_SIGNER_SEEDS: [[u8; 32]; 3] =
+    [[0x43; 32], [0x44; 32], [0x45; 32]];
+
+/// Get the [`ed25519::PublicKey`]s of the builders whose signatures on a
+/// [`ReleaseManifest`] are counted in the given [`DeployEnv`].
+pub fn manifest_signers(
+    deploy_env: DeployEnv,
+) -> &'static [ed25519::PublicKey] {
+    match deploy_env {
+        DeployEnv::Dev => &DEV_MANIFEST_SIGNERS,
+        DeployEnv::Staging => &STAGING_MANIFEST_SIGNERS,
+        DeployEnv::Prod => &PROD_MANIFEST_SIGNERS,
+    }
+}
+
+/// The minimum number of distinct [`manifest_signers`] which must have signed
+/// a [`ReleaseManifest`] for it to be accepted in the given [`DeployEnv`].
+pub fn manifest_threshold(deploy_env: DeployEnv) -> usize {
+    match deploy_env {
+        DeployEnv::Dev | DeployEnv::Staging | DeployEnv::Prod => 2,
+    }
+}
+
+/// Whether every approval in the given [`DeployEnv`] requires a
+/// [`SignedReleaseManifest`], regardless of whether the user opted in.
+/// Manifests are mandatory in staging and prod once builders' keys exist.
+pub fn manifest_required(deploy_env: DeployEnv) -> bool {
+    deploy_env != DeployEnv::Dev && !manifest_signers(deploy_env).is_empty()
+}
+
+/// The expected measurement of each node release, as reproduced by a builder.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct ReleaseManifest {
+    #[cfg_attr(
+        test,
+        proptest(strategy = "arbitrary::any_release_measurements()")
+    )]
+    pub releases: BTreeMap<semver::Version, Measurement>,
+}
+
+/// A [`ReleaseManifest`] along with one or more builders' signatures on it.
+/// Must be verified with [`Self::verify`] before use.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct SignedReleaseManifest {
+    /// Each builder's BCS-serialized [`ed25519::Signed<ReleaseManifest>`].
+    pub signatures: Vec<ManifestSignature>,
+}
+
+/// A single builder's signed copy of a [`ReleaseManifest`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct ManifestSignature {
+    #[serde(with = "hexstr_or_bytes")]
+    pub signed_bcs: Vec<u8>,
+}
+
+/// Errors returned by [`SignedReleaseManifest::verify`].
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("Trusted signers signed different release manifests")]
+    Conflicting,
+    #[error("Release manifest has {found} trusted signatures, need {needed}")]
+    NotEnoughSigners { found: usize, needed: usize },
+}
+
+// --- impl ReleaseManifest --- //
+
+impl ed25519::Signable for ReleaseManifest {
+    const DOMAIN_SEPARATOR: [u8; 32] =
+        array::pad(*b"LEXE-REALM::ReleaseManifest");
+}
+
+impl ReleaseManifest {
+    /// The expected measurement of the given release, if it's in the manifest.
+    pub fn measurement(
+        &self,
+        version: &semver::Version,
+    ) -> Option<Measurement> {
+        self.releases.get(version).copied()
+    }
+
+    /// Sign this manifest, returning a [`ManifestSignature`] which can be
+    /// added to a [`SignedReleaseManifest`].
+    pub fn sign(
+        &self,
+        key_pair: &ed25519::KeyPair,
+    ) -> Result<ManifestSignature, bcs::Error> {
+        let (signed_bcs, _) = key_pair.sign_struct(self)?;
+        Ok(ManifestSignature { signed_bcs })
+    }
+}
+
+// --- impl SignedReleaseManifest --- //
+
+impl SignedReleaseManifest {
+    /// Verify that at least [`manifest_threshold`] distinct
+    /// [`manifest_signers`] signed the same [`ReleaseManifest`] for the given
+    /// [`DeployEnv`], returning the manifest.
+    ///
+    /// Signatures which are invalid or from unknown signers are ignored, so
+    /// that adding a new builder doesn't break older nodes.
+    pub fn verify(
+        &self,
+        deploy_env: DeployEnv,
+    ) -> Result<ReleaseManifest, ManifestError> {
+        let trusted = manifest_signers(deploy_env);
+        let needed = manifest_threshold(deploy_env);
+
+        let mut signers = Vec::with_capacity(self.signatures.len());
+        let mut manifest = None;
+        for signature in &self.signatures {
+            let is_trusted = |signer: &ed25519::PublicKey| {
+                trusted.contains(signer) && !signers.contains(signer)
+            };
+            let signed = match ed25519::verify_signed_struct::<ReleaseManifest, _>(
+                is_trusted,
+                &signature.signed_bcs,
+            ) {
+                Ok(signed) => signed,
+                Err(_) => continue,
+            };
+            let (signer, _sig, signed_manifest) = signed.into_parts();
+
+            match &manifest {
+                None => manifest = Some(signed_manifest),
+                Some(manifest) if *manifest == signed_manifest => (),
+                Some(_) => return Err(ManifestError::Conflicting),
+            }
+            signers.push(signer);
+        }
+
+        let found = signers.len();
+        match manifest {
+            Some(manifest) if found >= needed => Ok(manifest),
+            _ => Err(ManifestError::NotEnoughSigners { found, needed }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::roundtrip;
+
+    fn dev_key_pairs() -> Vec<ed25519::KeyPair> {
+        DEV_MANIFEST_SIGNER_SEEDS
+            .iter()
+            .map(ed25519::KeyPair::from_seed)
+            .collect()
+    }
+
+    fn manifest(measurement: Measurement) -> ReleaseManifest {
+        let version = semver::Version::new(0, 1, 0);
+        ReleaseManifest {
+            releases: BTreeMap::from_iter([(version, measurement)]),
+        }
+    }
+
+    #[test]
+    fn release_manifest_signed_roundtrip() {
+        roundtrip::signed_roundtrip_proptest::<ReleaseManifest>();
+    }
+
+    #[test]
+    fn signed_release_manifest_json_roundtrip() {
+        roundtrip::json_value_roundtrip_proptest::<SignedReleaseManifest>();
+    }
+
+    #[test]
+    fn dev_signers_match_seeds() {
+        let pubkeys = dev_key_pairs()
+            .iter()
+            .map(|key_pair| *key_pair.public_key())
+            .collect::<Vec<_>>();
+        assert_eq!(pubkeys, DEV_MANIFEST_SIGNERS);
+    }
+
+    #[test]
+    fn verify_requires_threshold() {
+        let key_pairs = dev_key_pairs();
+        let manifest = manifest(Measurement::new([0x69; 32]));
+        let sign = |key_pair| manifest.sign(key_pair).unwrap();
+        let verify = |signatures: Vec<ManifestSignature>| {
+            SignedReleaseManifest { signatures }.verify(DeployEnv::Dev)
+        };
+
+        // 2-of-3 and 3-of-3 are accepted
+        let two = vec![sign(&key_pairs[0]), sign(&key_pairs[2])];
+        assert_eq!(verify(two.clone()).unwrap(), manifest);
+        let three = key_pairs.iter().map(sign).collect::<Vec<_>>();
+        assert_eq!(verify(three).unwrap(), manifest);
+
+        // Dev signatures don't count in other envs
+        let signed = SignedReleaseManifest { signatures: two };
+        assert!(signed.verify(DeployEnv::Prod).is_err());
+
+        // A single signer, even repeated, isn't enough
+        let one = vec![sign(&key_pairs[0]), sign(&key_pairs[0])];
+        assert!(matches!(
+            verify(one),
+            Err(ManifestError::NotEnoughSigners {
+                found: 1,
+                needed: 2
+            })
+        ));
+
+        // Untrusted signers aren't counted
+        let other = ed25519::KeyPair::from_seed(&[0x69; 32]);
+        let untrusted = vec![sign(&key_pairs[1]), sign(&other)];
+        assert!(matches!(
+            verify(untrusted),
+            Err(ManifestError::NotEnoughSigners {
+                found: 1,
+                needed: 2
+            })
+        ));
+
+        // Trusted signers which disagree are rejected
+        let other_manifest = manifest(Measurement::new([0x42; 32]));
+        let conflicting = other_manifest.sign(&key_pairs[1]).unwrap();
+        let signatures = vec![sign(&key_pairs[0]), conflicting];
+        assert!(matches!(
+            verify(signatures),
+            Err(ManifestError::Conflicting)
+        ));
+    }
+}
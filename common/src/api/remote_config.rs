@@ -0,0 +1,182 @@
+//! Lexe-signed remote configuration for user nodes.
+//!
+//! Some node defaults (fee defaults, esplora endpoints) need to
+//! change more often than we ship enclave releases. Rather than baking these
+//! into the node binary, Lexe signs a [`RemoteConfig`] with an offline key
+//! whose public half *is* baked into the node ([`remote_config_signer`]). The
+//! node fetches the signed blob from the backend, verifies it, and persists it
+//! in the VFS so that it is still available if the backend is unreachable.
+//!
+//! Since the blob is signed, the backend (and anyone else who handles it)
+//! cannot tamper with its contents. To prevent rolling back to an older config,
+//! nodes only ever move to a config with a higher [`RemoteConfig::version`].
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+use serde::{Deserialize, Serialize};
+
+use crate::{array, ed25519, env::DeployEnv, hex, hexstr_or_bytes};
+
+/// The key used to sign [`RemoteConfig`]s in dev. The corresponding seed is
+/// public (`DEV_REMOTE_CONFIG_SIGNER_SEED`), so this key provides no security.
+const DEV_REMOTE_CONFIG_SIGNER: ed25519::PublicKey =
+    ed25519::PublicKey::new(hex::decode_const(
+        b"2152f8d19b791d24453242e15f2eab6cb7cffa7b6a5ed30097960e069881db12",
+    ));
+/// The key used to sign [`RemoteConfig`]s in staging.
+const STAGING_REMOTE_CONFIG_SIGNER: ed25519::PublicKey =
+    ed25519::PublicKey::new(hex::decode_const(
+        b"287a040e1e399d532afac45aad41ff41bbe5eb586149a42b9a66f71c4ee97bc1",
+    ));
+/// The key used to sign [`RemoteConfig`]s in prod.
+const PROD_REMOTE_CONFIG_SIGNER: ed25519::PublicKey =
+    ed25519::PublicKey::new(hex::decode_const(
+        b"c5818fb438f6d11075ba3afb3ab75b9cd53d7dbc196f43cb6ed5fee68eacef25",
+    ));
+
+/// The seed for the dev [`RemoteConfig`] signing key, so that dev and test
+/// code can produce valid configs.
+#[cfg(any(test, feature = "test-utils"))]
+pub const DEV_REMOTE_CONFIG_SIGNER_SEED: [u8; 32] = [0x42; 32];
+
+/// Get the [`ed25519::PublicKey`] which must have signed any [`RemoteConfig`]
+/// accepted in the given [`DeployEnv`].
+pub fn remote_config_signer(
+    deploy_env: DeployEnv,
+) -> &'static ed25519::PublicKey {
+    match deploy_env {
+        DeployEnv::Dev => &DEV_REMOTE_CONFIG_SIGNER,
+        DeployEnv::Staging => &STAGING_REMOTE_CONFIG_SIGNER,
+        DeployEnv::Prod => &PROD_REMOTE_CONFIG_SIGNER,
+    }
+}
+
+/// Node configuration which Lexe can update without an enclave release.
+///
+/// All fields must have sensible defaults, since a node which has never
+/// fetched a config uses [`RemoteConfig::default`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct RemoteConfig {
+    /// Monotonically increasing; nodes never replace a config with one that
+    /// has a lower or equal version. The default config has version 0.
+    pub version: u64,
+    /// Fee defaults.
+    pub fee_config: RemoteFeeConfig,
+    /// Esplora urls which are allowed in addition to the built-in whitelist
+    /// for the current network.
+    pub esplora_urls: Vec<String>,
+}
+
+/// Fee defaults, see [`RemoteConfig::fee_config`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct RemoteFeeConfig {
+    /// A floor applied to on-chain fee estimates, in sats per 1000 weight
+    /// units.
+    pub min_feerate_sat_per_kw: u32,
+    /// The maximum routing fee we'll pay for an outbound Lightning payment,
+    /// in parts per million of the amount sent. Only applies to users who
+    /// haven't set a default limit in their own fee policy.
+    pub max_routing_fee_ppm: u32,
+}
+
+/// A BCS-serialized [`ed25519::Signed<RemoteConfig>`], as sent over the wire
+/// and persisted in the VFS. Must be verified with [`Self::verify`] before use.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedRemoteConfig {
+    #[serde(with = "hexstr_or_bytes")]
+    pub signed_bcs: Vec<u8>,
+}
+
+// --- impl RemoteConfig --- //
+
+impl ed25519::Signable for RemoteConfig {
+    const DOMAIN_SEPARATOR: [u8; 32] = array::pad(*b"LEXE-REALM::RemoteConfig");
+}
+
+impl RemoteConfig {
+    /// Whether the given esplora url was allowed by this config.
+    pub fn allows_esplora_url(&self, esplora_url: &str) -> bool {
+        self.esplora_urls.iter().any(|url| url == esplora_url)
+    }
+
+    /// Sign this config, returning the [`SignedRemoteConfig`] to serve to
+    /// nodes.
+    pub fn sign(
+        &self,
+        key_pair: &ed25519::KeyPair,
+    ) -> Result<SignedRemoteConfig, bcs::Error> {
+        let (signed_bcs, _) = key_pair.sign_struct(self)?;
+        Ok(SignedRemoteConfig { signed_bcs })
+    }
+}
+
+// --- impl RemoteFeeConfig --- //
+
+impl Default for RemoteFeeConfig {
+    fn default() -> Self {
+        Self {
+            // LDK's `FEERATE_FLOOR_SATS_PER_KW`
+            min_feerate_sat_per_kw: 253,
+            // 1%
+            max_routing_fee_ppm: 10_000,
+        }
+    }
+}
+
+// --- impl SignedRemoteConfig --- //
+
+impl SignedRemoteConfig {
+    /// Verify that this config was signed by the [`remote_config_signer`] for
+    /// the given [`DeployEnv`], returning the contained [`RemoteConfig`].
+    pub fn verify(
+        &self,
+        deploy_env: DeployEnv,
+    ) -> Result<RemoteConfig, ed25519::Error> {
+        let signer = remote_config_signer(deploy_env);
+        let signed = signer
+            .verify_self_signed_struct::<RemoteConfig>(&self.signed_bcs)?;
+        let (_signer, _sig, config) = signed.into_parts();
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::roundtrip;
+
+    #[test]
+    fn remote_config_signed_roundtrip() {
+        roundtrip::signed_roundtrip_proptest::<RemoteConfig>();
+    }
+
+    #[test]
+    fn dev_signer_matches_seed() {
+        let key_pair =
+            ed25519::KeyPair::from_seed(&DEV_REMOTE_CONFIG_SIGNER_SEED);
+        assert_eq!(key_pair.public_key(), &DEV_REMOTE_CONFIG_SIGNER);
+    }
+
+    #[test]
+    fn verify_checks_signer() {
+        let config = RemoteConfig {
+            version: 1,
+            fee_config: RemoteFeeConfig::default(),
+            esplora_urls: vec!["https://esplora.lexe.app".to_owned()],
+        };
+
+        let dev_key_pair =
+            ed25519::KeyPair::from_seed(&DEV_REMOTE_CONFIG_SIGNER_SEED);
+        let signed = config.sign(&dev_key_pair).unwrap();
+        assert_eq!(signed.verify(DeployEnv::Dev).unwrap(), config);
+        assert!(signed.verify(DeployEnv::Staging).is_err());
+        assert!(signed.verify(DeployEnv::Prod).is_err());
+
+        // Configs signed by any other key are rejected
+        let other_key_pair = ed25519::KeyPair::from_seed(&[0x69; 32]);
+        let signed = config.sign(&other_key_pair).unwrap();
+        assert!(signed.verify(DeployEnv::Dev).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! A client-side cache for immutable or slowly-changing API responses.
+//!
+//! While an entry is younger than the caller's TTL,
+//! [`RestClient::send_cached`] returns the cached body without making a
+//! request. Once it's stale, the request is sent with the entry's `ETag`, and
+//! if the server responds `304 Not Modified`, the cached body is reused and
+//! the entry's TTL restarts.
+//!
+//! Entries are serializable so that clients (e.g. the app) can persist the
+//! cache and skip refetching identical responses after every restart.
+//!
+//! [`RestClient::send_cached`]: crate::api::rest::RestClient::send_cached
+
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{api::rest::BodyFormat, hexstr_or_bytes, time::TimestampMs};
+
+/// The max # of entries in a [`ResponseCache`]. The oldest entries are
+/// evicted first.
+pub const MAX_RESPONSE_CACHE_ENTRIES: usize = 32;
+/// Responses with larger bodies aren't cached.
+pub const MAX_CACHED_BODY_SIZE: usize = 1024 * 1024;
+
+/// Cached response bodies, keyed by request url.
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    entries: Mutex<BTreeMap<String, CachedResponse>>,
+}
+
+/// A cached success response.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub format: BodyFormat,
+    #[serde(with = "hexstr_or_bytes")]
+    pub body: Vec<u8>,
+    /// The response's `ETag`, used to revalidate the entry once it's stale.
+    pub etag: Option<String>,
+    /// When the response was last fetched or revalidated.
+    pub fetched_at: TimestampMs,
+}
+
+impl ResponseCache {
+    /// Add entries previously returned by [`Self::entries`], e.g. after
+    /// reading them from storage.
+    pub fn extend(&self, entries: BTreeMap<String, CachedResponse>) {
+        for (key, entry) in entries {
+            self.insert(key, entry);
+        }
+    }
+
+    /// A snapshot of all entries, e.g. for persisting.
+    pub fn entries(&self) -> BTreeMap<String, CachedResponse> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    /// Insert or replace the entry for `key`, evicting the oldest entry if the
+    /// cache is full. Entries with oversized bodies are ignored.
+    pub fn insert(&self, key: String, entry: CachedResponse) {
+        if entry.body.len() > MAX_CACHED_BODY_SIZE {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key, entry);
+        while entries.len() > MAX_RESPONSE_CACHE_ENTRIES {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.fetched_at)
+                .map(|(key, _)| key.clone())
+                .expect("Cache is non-empty");
+            entries.remove(&oldest);
+        }
+    }
+
+    /// Remove all entries.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl CachedResponse {
+    /// Whether this entry can be used without revalidating it.
+    pub fn is_fresh(&self, ttl: Duration, now: TimestampMs) -> bool {
+        let age = now
+            .into_duration()
+            .saturating_sub(self.fetched_at.into_duration());
+        age < ttl
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(fetched_at: u32) -> CachedResponse {
+        CachedResponse {
+            format: BodyFormat::Json,
+            body: b"{}".to_vec(),
+            etag: Some("\"v1\"".to_owned()),
+            fetched_at: TimestampMs::from(fetched_at),
+        }
+    }
+
+    #[test]
+    fn freshness() {
+        let ttl = Duration::from_secs(60);
+        let entry = entry(1_000);
+        assert!(entry.is_fresh(ttl, TimestampMs::from(1_000)));
+        assert!(entry.is_fresh(ttl, TimestampMs::from(60_999)));
+        assert!(!entry.is_fresh(ttl, TimestampMs::from(61_000)));
+        assert!(!entry.is_fresh(Duration::ZERO, TimestampMs::from(1_000)));
+    }
+
+    #[test]
+    fn evicts_oldest() {
+        let cache = ResponseCache::default();
+        let num_entries = MAX_RESPONSE_CACHE_ENTRIES as u32 + 1;
+        for i in 0..num_entries {
+            // Insert out of order so eviction doesn't just follow the keys.
+            let fetched_at = (i * 7) % num_entries;
+            cache.insert(format!("url{i}"), entry(fetched_at));
+        }
+        let entries = cache.entries();
+        assert_eq!(entries.len(), MAX_RESPONSE_CACHE_ENTRIES);
+        assert!(entries
+            .values()
+            .all(|e| e.fetched_at != TimestampMs::from(0)));
+
+        let mut big = entry(100);
+        big.body = vec![0; MAX_CACHED_BODY_SIZE + 1];
+        cache.insert("big".to_owned(), big);
+        assert!(cache.get("big").is_none());
+
+        // Entries survive a roundtrip through persistence.
+        let json = serde_json::to_string(&entries).unwrap();
+        let restored = ResponseCache::default();
+        restored.extend(serde_json::from_str(&json).unwrap());
+        assert_eq!(restored.entries(), entries);
+    }
+}
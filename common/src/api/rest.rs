@@ -1,30 +1,49 @@
-use std::time::Duration;
+use std::{str::FromStr, sync::Arc, time::Duration};
 
 use bytes::Bytes;
 use http::{
-    header::{HeaderValue, CONTENT_TYPE},
-    Method,
+    header::{
+        HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE, ETAG,
+        IF_NONE_MATCH,
+    },
+    Method, StatusCode,
 };
 use reqwest::IntoUrl;
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tracing::{debug, info, warn, Instrument};
 
 use super::trace::TraceId;
 use crate::{
     api::{
+        circuit_breaker::{CircuitBreaker, CircuitBreakerConfig},
         error::{
             ApiError, CommonApiError, CommonErrorKind, ErrorCode, ErrorResponse,
         },
+        response_cache::{CachedResponse, ResponseCache},
         trace::{self, DisplayMs},
     },
     backoff, ed25519,
+    time::TimestampMs,
 };
 
 /// The CONTENT-TYPE header for signed BCS-serialized structs.
 pub static CONTENT_TYPE_ED25519_BCS: HeaderValue =
     HeaderValue::from_static("application/ed25519-bcs");
+/// The CONTENT-TYPE header for JSON-serialized bodies.
+pub static CONTENT_TYPE_JSON: HeaderValue =
+    HeaderValue::from_static("application/json");
+/// The CONTENT-TYPE header for CBOR-serialized bodies.
+pub static CONTENT_TYPE_CBOR: HeaderValue =
+    HeaderValue::from_static("application/cbor");
+/// The header in which clients tell servers how long (in milliseconds) they
+/// will wait for a response. Servers use it to bound their own work, so they
+/// don't keep working on requests whose client has already given up.
+pub static REQUEST_TIMEOUT_HEADER: HeaderName =
+    HeaderName::from_static("lexe-timeout-ms");
 
 // Default parameters
+/// The default request timeout, which can be changed per request with
+/// [`reqwest::RequestBuilder::timeout`].
 pub const API_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
 
 // Avoid `Method::` prefix. Associated constants can't be imported
@@ -33,6 +52,43 @@ pub const PUT: Method = Method::PUT;
 pub const POST: Method = Method::POST;
 pub const DELETE: Method = Method::DELETE;
 
+/// Encodes a request timeout as a [`REQUEST_TIMEOUT_HEADER`] value.
+pub fn timeout_header_value(timeout: Duration) -> HeaderValue {
+    let millis = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+    HeaderValue::from(millis)
+}
+
+/// Reads the client's request timeout from the [`REQUEST_TIMEOUT_HEADER`], if
+/// it was set and is valid.
+pub fn timeout_from_headers(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(&REQUEST_TIMEOUT_HEADER)?.to_str().ok()?;
+    u64::from_str(value).ok().map(Duration::from_millis)
+}
+
+/// The serialization format of a success response body.
+///
+/// All endpoints speak JSON. High-volume endpoints (e.g. payment sync) can
+/// additionally respond with CBOR, which is considerably cheaper to encode and
+/// decode, if the client asked for it via the `Accept` header. Since servers
+/// which don't support CBOR simply ignore the `Accept` header, clients always
+/// decode according to the response's `Content-Type`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BodyFormat {
+    #[default]
+    Json,
+    Cbor,
+}
+
+/// A success response whose body hasn't been deserialized yet.
+struct RawResponse {
+    format: BodyFormat,
+    bytes: Bytes,
+    /// The response's `ETag` header, if any.
+    etag: Option<String>,
+    /// The server returned `304 Not Modified`, so `bytes` is empty.
+    not_modified: bool,
+}
+
 /// A generic RestClient which conforms to Lexe's API.
 #[derive(Clone)]
 pub struct RestClient {
@@ -41,6 +97,9 @@ pub struct RestClient {
     from: &'static str,
     /// The process that this [`RestClient`] is calling, e.g. "node-run"
     to: &'static str,
+    /// If set, requests to hosts which appear to be down fail fast instead of
+    /// waiting out the full request timeout.
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
 }
 
 impl RestClient {
@@ -63,7 +122,7 @@ impl RestClient {
             .https_only(true)
             .build()
             .expect("Failed to build reqwest Client");
-        Self { client, from, to }
+        Self::from_inner(client, from, to)
     }
 
     /// [`RestClient::new`] but without TLS.
@@ -73,17 +132,17 @@ impl RestClient {
             .https_only(false)
             .build()
             .expect("Failed to build reqwest Client");
-        Self { client, from, to }
+        Self::from_inner(client, from, to)
     }
 
     /// Get a [`reqwest::ClientBuilder`] with some defaults set.
     /// NOTE that for safety, `https_only` is set to `true`, but you can
     /// override it if needed.
+    ///
+    /// No client-wide timeout is set, since the [`RestClient`] sets a timeout
+    /// on each request instead.
     pub fn client_builder(from: &'static str) -> reqwest::ClientBuilder {
-        reqwest::Client::builder()
-            .user_agent(from)
-            .https_only(true)
-            .timeout(API_REQUEST_TIMEOUT)
+        reqwest::Client::builder().user_agent(from).https_only(true)
     }
 
     /// Construct a [`RestClient`] from a [`reqwest::Client`].
@@ -92,7 +151,22 @@ impl RestClient {
         from: &'static str,
         to: &'static str,
     ) -> Self {
-        Self { client, from, to }
+        Self {
+            client,
+            from,
+            to,
+            circuit_breaker: None,
+        }
+    }
+
+    /// Enable a per-host [`CircuitBreaker`] for this client, so that requests
+    /// to a host which appears to be down fail fast.
+    pub fn with_circuit_breaker(
+        mut self,
+        config: CircuitBreakerConfig,
+    ) -> Self {
+        self.circuit_breaker = Some(Arc::new(CircuitBreaker::new(config)));
+        self
     }
 
     // --- RequestBuilder helpers --- //
@@ -193,6 +267,70 @@ impl RestClient {
         Self::convert_rest_response(response)
     }
 
+    /// Like [`RestClient::send`], but returns the response cached under the
+    /// request url if it's younger than `ttl`, and otherwise revalidates the
+    /// cached response using its `ETag`. Successful responses are cached.
+    ///
+    /// Only use this for GET requests whose responses rarely change.
+    pub async fn send_cached<T, E>(
+        &self,
+        request_builder: reqwest::RequestBuilder,
+        cache: &ResponseCache,
+        ttl: Duration,
+    ) -> Result<T, E>
+    where
+        T: DeserializeOwned,
+        E: ApiError,
+    {
+        let mut request =
+            request_builder.build().map_err(CommonApiError::from)?;
+        let key = request.url().to_string();
+        let now = TimestampMs::now();
+
+        let maybe_cached = cache.get(&key);
+        if let Some(cached) = &maybe_cached {
+            if cached.is_fresh(ttl, now) {
+                return Ok(cached.format.deserialize::<T>(&cached.body)?);
+            }
+            let maybe_etag = cached
+                .etag
+                .as_deref()
+                .and_then(|etag| HeaderValue::from_str(etag).ok());
+            if let Some(etag) = maybe_etag {
+                request.headers_mut().insert(IF_NONE_MATCH, etag);
+            }
+        }
+
+        let (request_span, trace_id) =
+            trace::client::request_span(&request, self.from, self.to);
+        let raw = match self
+            .send_inner(request, &trace_id)
+            .instrument(request_span)
+            .await
+        {
+            Ok(Ok(raw)) => raw,
+            Ok(Err(err_api)) => return Err(E::from(err_api)),
+            Err(err_client) => return Err(E::from(err_client)),
+        };
+
+        let entry = match maybe_cached {
+            Some(cached) if raw.not_modified => CachedResponse {
+                fetched_at: now,
+                ..cached
+            },
+            _ => CachedResponse {
+                format: raw.format,
+                body: raw.bytes.to_vec(),
+                etag: raw.etag,
+                fetched_at: now,
+            },
+        };
+        // Only cache responses which we can actually deserialize.
+        let value = entry.format.deserialize::<T>(&entry.body)?;
+        cache.insert(key, entry);
+        Ok(value)
+    }
+
     // the `send_inner` and `send_with_retries_inner` intentionally use zero
     // generics in their function signatures to minimize code bloat.
 
@@ -202,7 +340,7 @@ impl RestClient {
         retries: usize,
         stop_codes: &[ErrorCode],
         trace_id: &TraceId,
-    ) -> Result<Result<Bytes, ErrorResponse>, CommonApiError> {
+    ) -> Result<Result<RawResponse, ErrorResponse>, CommonApiError> {
         let mut backoff_durations = backoff::get_backoff_iter();
         let mut attempts_left = retries + 1;
 
@@ -232,7 +370,7 @@ impl RestClient {
             // send the request and look for any error codes in the response
             // that we should bail on and stop retrying.
             match self.send_inner(request_clone, trace_id).await {
-                Ok(Ok(bytes)) => return Ok(Ok(bytes)),
+                Ok(Ok(body)) => return Ok(Ok(body)),
                 Ok(Err(api_error)) =>
                     if stop_codes.contains(&api_error.code) {
                         return Ok(Err(api_error));
@@ -257,10 +395,36 @@ impl RestClient {
     }
 
     async fn send_inner(
+        &self,
+        request: reqwest::Request,
+        trace_id: &TraceId,
+    ) -> Result<Result<RawResponse, ErrorResponse>, CommonApiError> {
+        let circuit_breaker = match &self.circuit_breaker {
+            Some(circuit_breaker) => circuit_breaker,
+            None => return self.send_inner_unguarded(request, trace_id).await,
+        };
+
+        let host = request.url().host_str().unwrap_or_default().to_owned();
+        circuit_breaker.check(&host).inspect_err(|e| {
+            warn!(target: trace::TARGET, "Done (error)(circuit open) {}", e.msg);
+        })?;
+
+        let result = self.send_inner_unguarded(request, trace_id).await;
+
+        let is_failure = match &result {
+            Ok(_) => false,
+            Err(e) => CircuitBreaker::is_failure(e),
+        };
+        circuit_breaker.record(&host, is_failure);
+
+        result
+    }
+
+    async fn send_inner_unguarded(
         &self,
         mut request: reqwest::Request,
         trace_id: &TraceId,
-    ) -> Result<Result<Bytes, ErrorResponse>, CommonApiError> {
+    ) -> Result<Result<RawResponse, ErrorResponse>, CommonApiError> {
         let start = tokio::time::Instant::now().into_std();
         // This message should mirror `LxOnRequest`.
         debug!(target: trace::TARGET, "New client request");
@@ -275,6 +439,14 @@ impl RestClient {
             Err(e) => warn!(target: trace::TARGET, "Header map full?: {e:#}"),
         }
 
+        // Apply our default timeout if the request didn't set its own, and
+        // tell the server how long we're willing to wait for it.
+        let timeout = *request.timeout_mut().get_or_insert(API_REQUEST_TIMEOUT);
+        request.headers_mut().insert(
+            REQUEST_TIMEOUT_HEADER.clone(),
+            timeout_header_value(timeout),
+        );
+
         // send the request, await the response headers
         let resp = self.client.execute(request).await.inspect_err(|e| {
             let req_time = DisplayMs(start.elapsed());
@@ -288,8 +460,16 @@ impl RestClient {
         // add the response http status to the current request span
         let status = resp.status().as_u16();
 
-        if resp.status().is_success() {
+        // We only get a 304 if we asked to revalidate a cached response.
+        let not_modified = resp.status() == StatusCode::NOT_MODIFIED;
+        if resp.status().is_success() || not_modified {
             // success => await response body
+            let format = BodyFormat::from_content_type(resp.headers());
+            let etag = resp
+                .headers()
+                .get(ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map(str::to_owned);
             let bytes = resp.bytes().await.inspect_err(|e| {
                 let req_time = DisplayMs(start.elapsed());
                 warn!(
@@ -303,7 +483,12 @@ impl RestClient {
 
             let req_time = DisplayMs(start.elapsed());
             info!(target: trace::TARGET, %req_time, %status, "Done (success)");
-            Ok(Ok(bytes))
+            Ok(Ok(RawResponse {
+                format,
+                bytes,
+                etag,
+                not_modified,
+            }))
         } else {
             // http error => await response json and convert to ErrorResponse
             let error =
@@ -334,32 +519,105 @@ impl RestClient {
     /// Converts the concrete, non-generic Rest response result to the specific
     /// API's result type.
     ///
-    /// On success, this json deserializes the response body. On error, this
+    /// On success, this deserializes the response body according to its
+    /// [`BodyFormat`]. On error, this
     /// converts the generic [`ErrorResponse`] or [`CommonApiError`] into the
     /// specific API error type, like [`BackendApiError`].
     ///
     /// [`BackendApiError`]: crate::api::error::BackendApiError
     fn convert_rest_response<T, E>(
-        response: Result<Result<Bytes, ErrorResponse>, CommonApiError>,
+        response: Result<Result<RawResponse, ErrorResponse>, CommonApiError>,
     ) -> Result<T, E>
     where
         T: DeserializeOwned,
         E: ApiError,
     {
         match response {
-            Ok(Ok(bytes)) =>
-                Ok(serde_json::from_slice::<T>(&bytes).map_err(|err| {
-                    let kind = CommonErrorKind::Decode;
-                    let msg =
-                        format!("Failed to deser response as json: {err:#}");
-                    CommonApiError::new(kind, msg)
-                })?),
+            Ok(Ok(raw)) => Ok(raw.format.deserialize::<T>(&raw.bytes)?),
             Ok(Err(err_api)) => Err(E::from(err_api)),
             Err(err_client) => Err(E::from(err_client)),
         }
     }
 }
 
+// -- impl BodyFormat -- //
+
+impl BodyFormat {
+    /// Get the format of a body from its `Content-Type` header. Anything other
+    /// than CBOR is assumed to be JSON.
+    pub fn from_content_type(headers: &HeaderMap) -> Self {
+        match headers.get(CONTENT_TYPE) {
+            Some(value) if value == CONTENT_TYPE_CBOR => Self::Cbor,
+            _ => Self::Json,
+        }
+    }
+
+    /// Get the format a client would like to receive from its `Accept` header.
+    /// Defaults to JSON unless the client explicitly accepts CBOR.
+    pub fn from_accept(headers: &HeaderMap) -> Self {
+        let accepts_cbor = headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|media_range| media_range.split(';').next())
+            .any(|media_type| media_type.trim() == "application/cbor");
+        if accepts_cbor {
+            Self::Cbor
+        } else {
+            Self::Json
+        }
+    }
+
+    /// The `Content-Type` header value for this format.
+    pub fn content_type(self) -> &'static HeaderValue {
+        match self {
+            Self::Json => &CONTENT_TYPE_JSON,
+            Self::Cbor => &CONTENT_TYPE_CBOR,
+        }
+    }
+
+    /// Serialize `value` in this format.
+    pub fn serialize<T: Serialize + ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<Vec<u8>, CommonApiError> {
+        let result = match self {
+            Self::Json =>
+                serde_json::to_vec(value).map_err(|e| format!("{e:#}")),
+            Self::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map(|()| bytes)
+                    .map_err(|e| format!("{e:#}"))
+            }
+        };
+        result.map_err(|err| {
+            let kind = CommonErrorKind::Server;
+            let msg = format!("Failed to serialize as {self:?}: {err}");
+            CommonApiError::new(kind, msg)
+        })
+    }
+
+    /// Deserialize a `T` from `bytes` in this format.
+    pub fn deserialize<T: DeserializeOwned>(
+        self,
+        bytes: &[u8],
+    ) -> Result<T, CommonApiError> {
+        let result = match self {
+            Self::Json =>
+                serde_json::from_slice::<T>(bytes).map_err(|e| format!("{e:#}")),
+            Self::Cbor => ciborium::from_reader::<T, _>(bytes)
+                .map_err(|e| format!("{e:#}")),
+        };
+        result.map_err(|err| {
+            let kind = CommonErrorKind::Decode;
+            let msg = format!("Failed to deser response as {self:?}: {err}");
+            CommonApiError::new(kind, msg)
+        })
+    }
+}
+
 // -- impl RequestBuilderExt -- //
 
 /// Extension trait on [`reqwest::RequestBuilder`] for easily modifying requests
@@ -373,6 +631,10 @@ pub trait RequestBuilderExt: Sized {
     ) -> Result<Self, bcs::Error>
     where
         T: ed25519::Signable + Serialize;
+
+    /// Ask the server to respond with CBOR instead of JSON if it can.
+    /// [`RestClient::send`] decodes either format transparently.
+    fn accept_cbor(self) -> Self;
 }
 
 impl RequestBuilderExt for reqwest::RequestBuilder {
@@ -387,4 +649,59 @@ impl RequestBuilderExt for reqwest::RequestBuilder {
             .header(CONTENT_TYPE, CONTENT_TYPE_ED25519_BCS.clone())
             .body(signed_bcs.serialize()?))
     }
+
+    fn accept_cbor(self) -> Self {
+        self.header(ACCEPT, CONTENT_TYPE_CBOR.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn body_format_from_headers() {
+        let headers = |name, value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, HeaderValue::from_static(value));
+            headers
+        };
+
+        assert_eq!(
+            BodyFormat::from_accept(&HeaderMap::new()),
+            BodyFormat::Json
+        );
+        let cases = [
+            ("application/cbor", BodyFormat::Cbor),
+            ("application/json, application/cbor;q=0.9", BodyFormat::Cbor),
+            ("application/json", BodyFormat::Json),
+            ("*/*", BodyFormat::Json),
+        ];
+        for (accept, expected) in cases {
+            let accept_headers = headers(ACCEPT, accept);
+            assert_eq!(BodyFormat::from_accept(&accept_headers), expected);
+        }
+
+        let cbor = headers(CONTENT_TYPE, "application/cbor");
+        assert_eq!(BodyFormat::from_content_type(&cbor), BodyFormat::Cbor);
+        let json = headers(CONTENT_TYPE, "application/json");
+        assert_eq!(BodyFormat::from_content_type(&json), BodyFormat::Json);
+        assert_eq!(
+            BodyFormat::from_content_type(&HeaderMap::new()),
+            BodyFormat::Json
+        );
+    }
+
+    #[test]
+    fn timeout_header_roundtrip() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(timeout_from_headers(&headers), None);
+
+        let timeout = Duration::from_millis(12_345);
+        headers.insert(&REQUEST_TIMEOUT_HEADER, timeout_header_value(timeout));
+        assert_eq!(timeout_from_headers(&headers), Some(timeout));
+
+        headers.insert(&REQUEST_TIMEOUT_HEADER, HeaderValue::from_static("-1"));
+        assert_eq!(timeout_from_headers(&headers), None);
+    }
 }
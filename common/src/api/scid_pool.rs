@@ -0,0 +1,189 @@
+//! The rotating pool of fake SCIDs used in our invoice route hints.
+//!
+//! Invoices for private channels include a route hint with a fake SCID which
+//! the LSP maps back to the user's node. Using one SCID forever lets anyone
+//! holding two of a user's invoices link them together, so the node
+//! periodically asks the LSP for a new SCID and uses it for new invoices.
+//!
+//! Old SCIDs can't be dropped right away though: invoices created before the
+//! rotation are still payable until they expire. A retired SCID is kept around
+//! for [`SCID_RETIRED_LIFETIME`], the longest an invoice can be valid for,
+//! after which it is expired and removed from the pool.
+
+use std::time::Duration;
+
+use anyhow::ensure;
+#[cfg(any(test, feature = "test-utils"))]
+use proptest_derive::Arbitrary;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{command::CreateInvoiceRequest, Scid},
+    time::TimestampMs,
+};
+
+/// How often the node should rotate to a new SCID.
+pub const SCID_ROTATION_INTERVAL: Duration =
+    Duration::from_secs(30 * 24 * 60 * 60);
+/// How long a retired SCID stays in the pool: the max invoice expiry.
+pub const SCID_RETIRED_LIFETIME: Duration =
+    Duration::from_secs(CreateInvoiceRequest::MAX_EXPIRY_SECS as u64);
+/// The max # of SCIDs in a [`ScidPool`]. If a rotation would exceed this, the
+/// oldest retired SCIDs are expired early.
+pub const MAX_SCID_POOL_SIZE: usize = 8;
+
+/// The node's current SCID, along with any retired SCIDs which may still be
+/// used by unexpired invoices.
+#[cfg_attr(any(test, feature = "test-utils"), derive(Arbitrary))]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScidPool {
+    /// The SCID used in new route hints.
+    pub current: ScidEntry,
+    /// Previously used SCIDs, oldest first.
+    pub retired: Vec<ScidEntry>,
+}
+
+#[cfg_attr(any(test, feature = "test-utils"), derive(Arbitrary))]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScidEntry {
+    pub scid: Scid,
+    /// When the LSP allocated this SCID to the node.
+    pub allocated_at: TimestampMs,
+    /// When this SCID was replaced by a newer one, if it has been.
+    pub retired_at: Option<TimestampMs>,
+}
+
+impl ScidPool {
+    pub fn new(scid: Scid, now: TimestampMs) -> Self {
+        Self {
+            current: ScidEntry {
+                scid,
+                allocated_at: now,
+                retired_at: None,
+            },
+            retired: Vec::new(),
+        }
+    }
+
+    /// The SCID to use in new route hints.
+    pub fn current(&self) -> Scid {
+        self.current.scid
+    }
+
+    /// All SCIDs which may still be used by unexpired invoices, including the
+    /// current one.
+    pub fn scids(&self) -> impl Iterator<Item = Scid> + '_ {
+        self.retired
+            .iter()
+            .map(|entry| entry.scid)
+            .chain(std::iter::once(self.current.scid))
+    }
+
+    /// Whether the current SCID is due for rotation.
+    pub fn needs_rotation(&self, now: TimestampMs) -> bool {
+        elapsed(self.current.allocated_at, now) >= SCID_ROTATION_INTERVAL
+    }
+
+    /// Replaces the current SCID with `new_scid` and expires any retired SCIDs
+    /// which are no longer needed. Returns the expired SCIDs.
+    pub fn rotate(
+        &mut self,
+        new_scid: Scid,
+        now: TimestampMs,
+    ) -> anyhow::Result<Vec<Scid>> {
+        ensure!(
+            self.scids().all(|scid| scid != new_scid),
+            "SCID {new_scid} is already in the pool"
+        );
+
+        let mut retired = std::mem::replace(
+            &mut self.current,
+            ScidEntry {
+                scid: new_scid,
+                allocated_at: now,
+                retired_at: None,
+            },
+        );
+        retired.retired_at = Some(now);
+        self.retired.push(retired);
+
+        let mut expired = self.expire(now);
+        // Make room for the current SCID.
+        let excess = self.retired.len().saturating_sub(MAX_SCID_POOL_SIZE - 1);
+        expired.extend(self.retired.drain(..excess).map(|entry| entry.scid));
+        Ok(expired)
+    }
+
+    /// Removes retired SCIDs which have outlived [`SCID_RETIRED_LIFETIME`].
+    /// Returns the expired SCIDs.
+    pub fn expire(&mut self, now: TimestampMs) -> Vec<Scid> {
+        let mut expired = Vec::new();
+        self.retired.retain(|entry| {
+            let retired_at = entry.retired_at.unwrap_or(entry.allocated_at);
+            let keep = elapsed(retired_at, now) < SCID_RETIRED_LIFETIME;
+            if !keep {
+                expired.push(entry.scid);
+            }
+            keep
+        });
+        expired
+    }
+}
+
+fn elapsed(since: TimestampMs, now: TimestampMs) -> Duration {
+    now.into_duration().saturating_sub(since.into_duration())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::roundtrip;
+
+    fn ts(duration: Duration) -> TimestampMs {
+        TimestampMs::try_from(duration).unwrap()
+    }
+
+    #[test]
+    fn scid_pool_json_roundtrip() {
+        roundtrip::json_value_roundtrip_proptest::<ScidPool>();
+    }
+
+    #[test]
+    fn rotate_and_expire() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let mut pool = ScidPool::new(Scid(1), ts(Duration::ZERO));
+        assert!(!pool.needs_rotation(ts(29 * day)));
+        assert!(pool.needs_rotation(ts(SCID_ROTATION_INTERVAL)));
+
+        // Can't rotate to an SCID we already have.
+        pool.rotate(Scid(1), ts(30 * day)).unwrap_err();
+
+        // The old SCID is retired but still valid.
+        let expired = pool.rotate(Scid(2), ts(30 * day)).unwrap();
+        assert!(expired.is_empty());
+        assert_eq!(pool.current(), Scid(2));
+        assert_eq!(pool.scids().collect::<Vec<_>>(), [Scid(1), Scid(2)]);
+
+        // It's expired once no invoice could still be using it.
+        let almost = ts(30 * day + SCID_RETIRED_LIFETIME - day);
+        assert!(pool.expire(almost).is_empty());
+        let expiry = ts(30 * day + SCID_RETIRED_LIFETIME);
+        assert_eq!(pool.expire(expiry), [Scid(1)]);
+        assert_eq!(pool.scids().collect::<Vec<_>>(), [Scid(2)]);
+    }
+
+    #[test]
+    fn pool_size_is_bounded() {
+        let mut pool = ScidPool::new(Scid(0), ts(Duration::ZERO));
+        let mut all_expired = Vec::new();
+        for i in 1..20 {
+            // Rotate quickly enough that nothing expires on its own.
+            let now = ts(Duration::from_secs(i));
+            all_expired.extend(pool.rotate(Scid(i), now).unwrap());
+            assert!(pool.scids().count() <= MAX_SCID_POOL_SIZE);
+        }
+        assert_eq!(pool.current(), Scid(19));
+        assert_eq!(pool.scids().count(), MAX_SCID_POOL_SIZE);
+        assert_eq!(all_expired, (0..12).map(Scid).collect::<Vec<_>>());
+    }
+}
@@ -18,20 +18,32 @@
 //!
 //! - [`LxJson`] to deserialize from HTTP body JSON
 //! - [`LxQuery`] to deserialize from query strings
+//! - [`LxAccept`] to get the [`BodyFormat`] the client would like to receive
 //!
 //! # [`IntoResponse`] types / impls for building Lexe API-conformant responses:
 //!
 //! - [`LxJson`] type for returning success responses as JSON
+//! - [`LxBody`] type for returning success responses as JSON or CBOR
 //! - All [`ApiError`]s and [`CommonApiError`] impl [`IntoResponse`]
 //! - [`LxRejection`] for notifying clients of bad JSON, query strings, etc.
 //!
+//! # Health checks
+//!
+//! - [`HealthChecks`] serves standard `/healthz` (liveness) and `/readyz`
+//!   (readiness) endpoints, running any dependency checks the service
+//!   registered.
+//!
 //! [`ApiError`]: crate::api::error::ApiError
 //! [`CommonApiError`]: crate::api::error::CommonApiError
 //! [`Router`]: axum::Router
 //! [`IntoResponse`]: axum::response::IntoResponse
 //! [`LxJson`]: crate::api::server::LxJson
+//! [`LxBody`]: crate::api::server::LxBody
+//! [`LxAccept`]: crate::api::server::extract::LxAccept
+//! [`BodyFormat`]: crate::api::rest::BodyFormat
 //! [`LxQuery`]: crate::api::server::extract::LxQuery
 //! [`LxRejection`]: crate::api::server::LxRejection
+//! [`HealthChecks`]: crate::api::server::health::HealthChecks
 //! [`build_server_fut`]: crate::api::server::build_server_fut
 //! [`build_server_fut_with_listener`]: crate::api::server::build_server_fut_with_listener
 //! [`spawn_server_task`]: crate::api::server::spawn_server_task
@@ -62,12 +74,11 @@ use axum::{
     Router, ServiceExt as AxumServiceExt,
 };
 use axum_server::tls_rustls::RustlsConfig;
-use http::{header::CONTENT_TYPE, HeaderValue, StatusCode, Version};
+use http::{header::CONTENT_TYPE, StatusCode, Version};
 use serde::{de::DeserializeOwned, Serialize};
 use tower::{
     buffer::BufferLayer, limit::ConcurrencyLimitLayer,
-    load_shed::LoadShedLayer, timeout::TimeoutLayer, util::MapRequestLayer,
-    Layer,
+    load_shed::LoadShedLayer, util::MapRequestLayer, Layer,
 };
 use tracing::{debug, error, info, warn, Instrument};
 
@@ -75,6 +86,8 @@ use super::auth;
 use crate::{
     api::{
         error::{CommonApiError, CommonErrorKind, ErrorResponse, ToHttpStatus},
+        log_capture::{self, ErrorReport, LogCapture},
+        rest::{self, BodyFormat},
         trace,
     },
     const_assert, ed25519,
@@ -94,6 +107,10 @@ const_assert!(
     SHUTDOWN_GRACE_PERIOD.as_secs() < SERVER_SHUTDOWN_TIMEOUT.as_secs()
 );
 
+/// How much of the client's timeout is reserved for sending our response back.
+/// See [`middleware::bound_handling_time`].
+const CLIENT_TIMEOUT_MARGIN: Duration = Duration::from_millis(250);
+
 /// A configuration object for Axum / Tower middleware.
 ///
 /// Defaults:
@@ -110,6 +127,7 @@ const_assert!(
 ///         concurrency: Some(4096),
 ///         handling_timeout: Some(Duration::from_secs(15)),
 ///         default_fallback: true,
+///         log_capture: None,
 ///     }
 /// );
 /// ```
@@ -131,6 +149,12 @@ pub struct LayerConfig {
     /// The maximum time a server can spend handling a request.
     /// ([`None`] to disable). Helps prevent degenerate cases which take
     /// abnormally long to process from crowding out normal workloads.
+    ///
+    /// Read-only (`GET`/`HEAD`) requests are additionally bounded by the
+    /// client's own timeout, if it sent one in the [`REQUEST_TIMEOUT_HEADER`],
+    /// even if this is [`None`].
+    ///
+    /// [`REQUEST_TIMEOUT_HEADER`]: crate::api::rest::REQUEST_TIMEOUT_HEADER
     pub handling_timeout: Option<Duration>,
     /// Whether to add Lexe's default [`Router::fallback`] to the [`Router`].
     /// The [`Router::fallback`] is called if no routes were matched;
@@ -142,6 +166,9 @@ pub struct LayerConfig {
     /// NOTE, however, that the caller is responsible for ensuring that the
     /// [`Router`] has a fallback configured in this case.
     pub default_fallback: bool,
+    /// What to do with the logs captured while handling requests which fail
+    /// with a 5xx ([`None`] to disable capture). See [`log_capture`].
+    pub log_capture: Option<LogCapture>,
 }
 
 impl Default for LayerConfig {
@@ -157,6 +184,7 @@ impl Default for LayerConfig {
             concurrency: Some(4096),
             handling_timeout: Some(Duration::from_secs(15)),
             default_fallback: true,
+            log_capture: None,
         }
     }
 }
@@ -275,6 +303,13 @@ pub fn build_server_fut_with_listener(
     // We put most of the layers here because it is a lot easier to work with
     // axum types; moving these outside quickly degenerates into type hell.
     let inner_middleware = tower::ServiceBuilder::new()
+        .check_service::<AxumService, AxumReq, AxumResp, Infallible>()
+        // Capture the logs emitted while handling the request. This is the
+        // outermost inner layer so that e.g. timeouts are reported too.
+        .layer(axum::middleware::from_fn_with_state(
+            layer_config.log_capture.clone(),
+            middleware::capture_logs,
+        ))
         .check_service::<AxumService, AxumReq, AxumResp, Infallible>()
         // Immediately reject anything with a CONTENT_LENGTH over the limit.
         .layer(axum::middleware::map_request_with_state(
@@ -320,17 +355,14 @@ pub fn build_server_fut_with_listener(
         // Helps prevent the CPU from maxing out, resulting in thrashing.
         .option_layer(layer_config.concurrency.map(ConcurrencyLimitLayer::new))
         .check_service::<AxumService, AxumReq, AxumResp, Infallible>()
-        // Handles errors generated by the timeout layer.
-        .layer(HandleErrorLayer::new(|error| async move {
-            CommonApiError {
-                kind: CommonErrorKind::Server,
-                msg: format!("Server timed out handling request: {error:#}"),
-            }
-        }))
         // Returns an error if the inner service takes longer than the timeout
-        // to handle the request. Prevents degenerate cases which take
-        // abnormally long to process from crowding out normal workloads.
-        .option_layer(layer_config.handling_timeout.map(TimeoutLayer::new))
+        // (or the client's remaining time) to handle the request. Prevents
+        // degenerate cases which take abnormally long to process from crowding
+        // out normal workloads, and stops work nobody is waiting for anymore.
+        .layer(axum::middleware::from_fn_with_state(
+            layer_config.handling_timeout,
+            middleware::bound_handling_time,
+        ))
         .check_service::<AxumService, AxumReq, AxumResp, Infallible>();
 
     // Apply inner middleware
@@ -527,6 +559,42 @@ impl<T: PartialEq> PartialEq for LxJson<T> {
     }
 }
 
+// --- LxBody --- //
+
+/// A success response serialized in the [`BodyFormat`] negotiated with the
+/// client (see [`LxAccept`]). Use this instead of [`LxJson`] for high-volume
+/// endpoints whose clients may prefer a binary format.
+///
+/// Like [`LxJson`], this must only be used for *success* responses.
+///
+/// [`LxAccept`]: extract::LxAccept
+pub struct LxBody<T> {
+    pub format: BodyFormat,
+    pub value: T,
+}
+
+impl<T: Serialize> IntoResponse for LxBody<T> {
+    fn into_response(self) -> http::Response<axum::body::Body> {
+        match self.format {
+            BodyFormat::Json =>
+                build_json_response(StatusCode::OK, &self.value),
+            BodyFormat::Cbor => match self.format.serialize(&self.value) {
+                Ok(bytes) => build_response(StatusCode::OK, self.format, bytes),
+                Err(e) => {
+                    error!(target: "http", "{}", e.msg);
+                    e.into_response()
+                }
+            },
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LxBody<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(&self.value, f)
+    }
+}
+
 // --- LxRejection --- //
 
 /// Our own [`axum::extract::rejection`] type with an [`IntoResponse`] impl
@@ -589,8 +657,15 @@ impl LxRejection {
 
 impl From<BytesRejection> for LxRejection {
     fn from(bytes_rejection: BytesRejection) -> Self {
+        // The body exceeded the limit while it was being buffered.
+        let kind = if bytes_rejection.status() == StatusCode::PAYLOAD_TOO_LARGE
+        {
+            LxRejectionKind::BodyLengthOverLimit
+        } else {
+            LxRejectionKind::Bytes
+        };
         Self {
-            kind: LxRejectionKind::Bytes,
+            kind,
             source_msg: bytes_rejection.body_text(),
         }
     }
@@ -607,8 +682,14 @@ impl From<HostRejection> for LxRejection {
 
 impl From<JsonRejection> for LxRejection {
     fn from(json_rejection: JsonRejection) -> Self {
+        // The body exceeded the limit while it was being buffered.
+        let kind = if json_rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            LxRejectionKind::BodyLengthOverLimit
+        } else {
+            LxRejectionKind::Json
+        };
         Self {
-            kind: LxRejectionKind::Json,
+            kind,
             source_msg: json_rejection.body_text(),
         }
     }
@@ -625,7 +706,11 @@ impl From<QueryRejection> for LxRejection {
 
 impl IntoResponse for LxRejection {
     fn into_response(self) -> http::Response<axum::body::Body> {
-        let kind = CommonErrorKind::Rejection;
+        let kind = match self.kind {
+            LxRejectionKind::BodyLengthOverLimit =>
+                CommonErrorKind::BodyTooLarge,
+            _ => CommonErrorKind::Rejection,
+        };
         // "Bad JSON: Failed to deserialize the JSON body into the target type"
         let kind_msg = self.kind.to_msg();
         let source_msg = &self.source_msg;
@@ -700,6 +785,23 @@ pub mod extract {
         }
     }
 
+    /// Extracts the [`BodyFormat`] the client would like to receive from the
+    /// `Accept` header. Never rejects; defaults to [`BodyFormat::Json`].
+    #[derive(Copy, Clone, Debug)]
+    pub struct LxAccept(pub BodyFormat);
+
+    #[async_trait]
+    impl<S: Send + Sync> FromRequestParts<S> for LxAccept {
+        type Rejection = Infallible;
+
+        async fn from_request_parts(
+            parts: &mut http::request::Parts,
+            _state: &S,
+        ) -> Result<Self, Self::Rejection> {
+            Ok(Self(BodyFormat::from_accept(&parts.headers)))
+        }
+    }
+
     /// Lexe API-compliant version of [`axum::extract::Host`].
     ///
     /// The `Host` and `X-Forwarded-Host` headers may be set by a malicious
@@ -765,6 +867,119 @@ pub mod middleware {
         Ok(request)
     }
 
+    /// Lowers the request body limit for the routes it is applied to, e.g.
+    ///
+    /// ```ignore
+    /// post(handler).layer(from_fn_with_state(4096, middleware::limit_body))
+    /// ```
+    ///
+    /// Requests over the limit are rejected with a 413 before the handler
+    /// runs. Since [`LayerConfig::body_limit`] applies to every route, this can
+    /// only lower a route's limit. To raise the limit for a few routes (e.g.
+    /// file uploads), raise the server-wide limit and use this to lower it for
+    /// the rest.
+    pub async fn limit_body(
+        State(limit): State<usize>,
+        request: http::Request<axum::body::Body>,
+        next: axum::middleware::Next,
+    ) -> Result<axum::response::Response, LxRejection> {
+        let request =
+            check_content_length_header(State(Some(limit)), request).await?;
+        // Enforce the limit for requests without a CONTENT_LENGTH header, or
+        // whose header lies about the body length.
+        let request = request.map(|body| {
+            axum::body::Body::new(http_body_util::Limited::new(body, limit))
+        });
+        Ok(next.run(request).await)
+    }
+
+    /// Captures the logs emitted while handling a request, and attaches them
+    /// to or reports them for requests which fail with a 5xx, depending on
+    /// [`LayerConfig::log_capture`].
+    pub(super) async fn capture_logs(
+        // `LayerConfig::log_capture`
+        State(log_capture): State<Option<LogCapture>>,
+        request: http::Request<axum::body::Body>,
+        next: axum::middleware::Next,
+    ) -> http::Response<axum::body::Body> {
+        let log_capture = match log_capture {
+            Some(lc) => lc,
+            None => return next.run(request).await,
+        };
+
+        let method = request.method().clone();
+        let path = request.uri().path().to_owned();
+        let (response, logs) = log_capture::capture(next.run(request)).await;
+
+        let status = response.status();
+        if !status.is_server_error() || logs.is_empty() {
+            return response;
+        }
+        match log_capture {
+            LogCapture::Attach =>
+                log_capture::attach_to_response(response, &logs).await,
+            LogCapture::Report(report_tx) => {
+                report_tx.send(ErrorReport {
+                    method,
+                    path,
+                    status,
+                    logs,
+                });
+                response
+            }
+        }
+    }
+
+    /// Bounds the time spent handling a request by the smaller of
+    /// [`LayerConfig::handling_timeout`] and the client's own timeout from the
+    /// [`REQUEST_TIMEOUT_HEADER`], returning a timeout error if exceeded.
+    ///
+    /// The client's timeout only applies to read-only requests. Mutating
+    /// handlers generally aren't cancel-safe (e.g. a payment may be half-way
+    /// persisted), so they keep running up to the server's own timeout even if
+    /// the client has given up.
+    ///
+    /// [`REQUEST_TIMEOUT_HEADER`]: rest::REQUEST_TIMEOUT_HEADER
+    pub(super) async fn bound_handling_time(
+        // `LayerConfig::handling_timeout`
+        State(handling_timeout): State<Option<Duration>>,
+        request: http::Request<axum::body::Body>,
+        next: axum::middleware::Next,
+    ) -> http::Response<axum::body::Body> {
+        let is_read_only =
+            matches!(*request.method(), http::Method::GET | http::Method::HEAD);
+        // Leave the client some time to receive our timeout error, so it
+        // learns what happened instead of timing out itself. Short client
+        // timeouts still get at least half of their time for handling.
+        let client_timeout = rest::timeout_from_headers(request.headers())
+            .filter(|_| is_read_only)
+            .filter(|timeout| !timeout.is_zero())
+            .map(|timeout| {
+                timeout
+                    .saturating_sub(CLIENT_TIMEOUT_MARGIN)
+                    .max(timeout / 2)
+            });
+        let timeout = match (handling_timeout, client_timeout) {
+            (Some(ours), Some(theirs)) => ours.min(theirs),
+            (ours, theirs) => match ours.or(theirs) {
+                Some(timeout) => timeout,
+                None => return next.run(request).await,
+            },
+        };
+
+        match tokio::time::timeout(timeout, next.run(request)).await {
+            Ok(response) => response,
+            Err(_) => CommonApiError {
+                kind: CommonErrorKind::Server,
+                msg: format!(
+                    "Server timed out handling request after {}ms",
+                    timeout.as_millis()
+                ),
+            }
+            .into_response(),
+        }
+    }
+
     /// A post-processor which can be used to modify the [`http::Response`]s
     /// returned by an [`axum::Router`]. This is done by signalling the desired
     /// modification in a fake [`POST_PROCESS_HEADER`] which is also removed
@@ -795,6 +1010,221 @@ pub mod middleware {
     }
 }
 
+// --- Health checks --- //
+
+/// Standard liveness (`/healthz`) and readiness (`/readyz`) endpoints.
+///
+/// Liveness only says that the server is up and handling requests; a service
+/// which fails it should be restarted. Readiness additionally runs the
+/// dependency checks registered by the service (backend reachable, auth
+/// working, etc.); a service which fails it should stop receiving traffic
+/// until its dependencies recover, but restarting it likely won't help.
+pub mod health {
+    use std::{pin::Pin, time::Instant};
+
+    use axum::{extract::State, routing::get};
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::api::Empty;
+
+    /// How long a single readiness check can take before it fails.
+    pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+    type CheckFut = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+    type CheckFn = Arc<dyn Fn() -> CheckFut + Send + Sync>;
+
+    /// The dependency checks run on each `/readyz` request.
+    #[derive(Clone, Default)]
+    pub struct HealthChecks {
+        checks: Vec<(&'static str, CheckFn)>,
+    }
+
+    /// The response body of `/readyz`.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct ReadinessReport {
+        /// Whether all checks passed.
+        pub ready: bool,
+        pub checks: Vec<CheckResult>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CheckResult {
+        pub name: String,
+        /// The error, if the check failed.
+        pub error: Option<String>,
+        pub elapsed_ms: u64,
+    }
+
+    impl HealthChecks {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Register a readiness check. Checks run on every `/readyz` request,
+        /// so they should be cheap.
+        pub fn with_check<F, Fut>(
+            mut self,
+            name: &'static str,
+            check: F,
+        ) -> Self
+        where
+            F: Fn() -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+        {
+            let check: CheckFn = Arc::new(move || Box::pin(check()));
+            self.checks.push((name, check));
+            self
+        }
+
+        /// Like [`with_check`](Self::with_check), but reuses the check's last
+        /// result for up to `max_age`, so that frequent probes don't hit e.g.
+        /// the backend on every request. Concurrent probes share one run.
+        pub fn with_cached_check<F, Fut>(
+            self,
+            name: &'static str,
+            max_age: Duration,
+            check: F,
+        ) -> Self
+        where
+            F: Fn() -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+        {
+            type Cached = Option<(Instant, Result<(), String>)>;
+            let cache = Arc::new(tokio::sync::Mutex::new(Cached::None));
+            let check = Arc::new(check);
+            self.with_check(name, move || {
+                let cache = cache.clone();
+                let check = check.clone();
+                async move {
+                    let mut cached = cache.lock().await;
+                    let result = match cached.as_ref() {
+                        Some((checked_at, result))
+                            if checked_at.elapsed() < max_age =>
+                            result.clone(),
+                        _ => {
+                            let result =
+                                check().await.map_err(|e| format!("{e:#}"));
+                            *cached = Some((Instant::now(), result.clone()));
+                            result
+                        }
+                    };
+                    result.map_err(anyhow::Error::msg)
+                }
+            })
+        }
+
+        /// Run all checks concurrently, each bounded by
+        /// [`HEALTH_CHECK_TIMEOUT`].
+        pub async fn run(&self) -> ReadinessReport {
+            let check_futs = self.checks.iter().map(|(name, check)| async {
+                let start = Instant::now();
+                let result =
+                    tokio::time::timeout(HEALTH_CHECK_TIMEOUT, check()).await;
+                let error = match result {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some(format!("{e:#}")),
+                    Err(_) => Some("Timed out".to_owned()),
+                };
+                let elapsed_ms =
+                    u64::try_from(start.elapsed().as_millis()).unwrap_or(0);
+                CheckResult {
+                    name: (*name).to_owned(),
+                    error,
+                    elapsed_ms,
+                }
+            });
+            let checks = futures::future::join_all(check_futs).await;
+            let ready = checks.iter().all(|check| check.error.is_none());
+            ReadinessReport { ready, checks }
+        }
+
+        /// A [`Router`] serving `GET /healthz` and `GET /readyz`, to be merged
+        /// into the service's router.
+        pub fn into_router(self) -> Router<()> {
+            Router::new()
+                .route("/healthz", get(healthz))
+                .route("/readyz", get(readyz))
+                .with_state(Arc::new(self))
+        }
+    }
+
+    /// Liveness: succeeds as long as the server is handling requests.
+    pub async fn healthz() -> LxJson<Empty> {
+        LxJson(Empty {})
+    }
+
+    /// Readiness: runs the [`HealthChecks`], and responds with a
+    /// [`ReadinessReport`] and a 503 status if any check failed.
+    pub async fn readyz(
+        State(checks): State<Arc<HealthChecks>>,
+    ) -> http::Response<axum::body::Body> {
+        let report = checks.run().await;
+        let status = if report.ready {
+            StatusCode::OK
+        } else {
+            for check in &report.checks {
+                if let Some(error) = &check.error {
+                    let name = &check.name;
+                    warn!(%name, "Readiness check failed: {error}");
+                }
+            }
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        build_json_response(status, &report)
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::sync::atomic::Ordering;
+
+        use anyhow::anyhow;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn readiness_report() {
+            let checks =
+                HealthChecks::new().with_check("ok", || async { Ok(()) });
+            let report = checks.run().await;
+            assert!(report.ready);
+            assert_eq!(report.checks.len(), 1);
+
+            let checks = checks.with_check("broken", || async {
+                Err(anyhow!("Backend down"))
+            });
+            let report = checks.run().await;
+            assert!(!report.ready);
+            assert_eq!(report.checks[0].error, None);
+            assert_eq!(report.checks[1].name, "broken");
+            assert_eq!(report.checks[1].error.as_deref(), Some("Backend down"));
+        }
+
+        #[tokio::test]
+        async fn cached_check() {
+            let runs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let runs_clone = runs.clone();
+            let max_age = Duration::from_secs(60);
+            let check = move || {
+                let runs = runs_clone.clone();
+                async move {
+                    runs.fetch_add(1, Ordering::Relaxed);
+                    Err(anyhow!("Backend down"))
+                }
+            };
+            let checks = HealthChecks::new()
+                .with_cached_check("backend", max_age, check);
+
+            for _ in 0..3 {
+                let report = checks.run().await;
+                let error = report.checks[0].error.as_deref();
+                assert_eq!(error, Some("Backend down"));
+            }
+            assert_eq!(runs.load(Ordering::Relaxed), 1);
+        }
+    }
+}
+
 // --- Helpers --- //
 
 /// Lexe's default fallback [`Handler`](axum::handler::Handler).
@@ -833,29 +1263,37 @@ pub fn build_json_response(
                 let error_kind = CommonErrorKind::Server;
                 let code = error_kind.to_code();
                 let status = error_kind.to_http_status();
-                let err_resp = ErrorResponse { code, msg };
+                let err_resp = ErrorResponse {
+                    code,
+                    msg,
+                    data: None,
+                };
                 let json_bytes = serde_json::to_vec(&err_resp)
                     .expect("Serializing ErrorResponse really shouldn't fail");
                 (status, json_bytes)
             }
         };
 
-        let bytes = bytes::Bytes::from(json_bytes);
-        let http_body = http_body_util::Full::new(bytes);
-        let axum_body = axum::body::Body::new(http_body);
-
-        http::Response::builder()
-            .header(
-                CONTENT_TYPE,
-                // Can do `HeaderValue::from_static(mime::APPLICATION_JSON)`
-                // if we ever have a non-trivial need for the `mime` crate
-                HeaderValue::from_static("application/json"),
-            )
-            .status(status)
-            .version(HTTP_VERSION)
-            .body(axum_body)
-            .expect("All operations here should be infallible")
+        build_response(status, BodyFormat::Json, json_bytes)
     }
 
     build_json_response_inner(status, serde_json::to_vec(data))
 }
+
+/// Constructs a response from an already-serialized body.
+fn build_response(
+    status: StatusCode,
+    format: BodyFormat,
+    bytes: Vec<u8>,
+) -> http::Response<axum::body::Body> {
+    let bytes = bytes::Bytes::from(bytes);
+    let http_body = http_body_util::Full::new(bytes);
+    let axum_body = axum::body::Body::new(http_body);
+
+    http::Response::builder()
+        .header(CONTENT_TYPE, format.content_type().clone())
+        .status(status)
+        .version(HTTP_VERSION)
+        .body(axum_body)
+        .expect("All operations here should be infallible")
+}
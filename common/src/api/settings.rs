@@ -0,0 +1,261 @@
+//! App settings, synced across the user's devices.
+//!
+//! The app keeps its settings in a [`SettingsDoc`], a map from setting key to
+//! (JSON-encoded) value. Local changes are written through to the user node,
+//! which persists the doc (encrypted) in its VFS, so users who reinstall the
+//! app or use a second device keep their preferences.
+//!
+//! ### Conflict resolution
+//!
+//! Devices may change settings concurrently or while offline, so docs are
+//! merged rather than overwritten. Each key is resolved independently and the
+//! most recent write wins. Deleting a key leaves a tombstone recording when it
+//! was deleted, so that merging with a device which still has the old value
+//! doesn't bring it back. Ties are broken deterministically, so all devices
+//! converge on the same doc regardless of the order in which they sync.
+//!
+//! Tombstones are dropped once they're older than [`TOMBSTONE_TTL`], which
+//! both the node and the app do before syncing, so that deleted keys don't
+//! accumulate forever. A device which hasn't synced for longer than that may
+//! bring back a setting which was deleted in the meantime.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::ensure;
+use serde::{Deserialize, Serialize};
+
+use crate::time::TimestampMs;
+
+/// The maximum length of a setting key, in bytes.
+pub const MAX_SETTINGS_KEY_LEN: usize = 64;
+/// The maximum size of a serialized [`SettingsDoc`], in bytes.
+pub const MAX_SETTINGS_DOC_SIZE: usize = 64 * 1024;
+/// How long a deleted key's tombstone is kept.
+pub const TOMBSTONE_TTL: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+/// The user's app settings.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SettingsDoc {
+    /// Incremented by the node each time it persists a changed doc.
+    pub version: u64,
+    /// The current settings, by key.
+    pub entries: BTreeMap<String, SettingsEntry>,
+    /// The keys which have been deleted, and when.
+    pub tombstones: BTreeMap<String, TimestampMs>,
+}
+
+/// A single setting.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SettingsEntry {
+    /// The JSON-encoded setting value.
+    pub value: String,
+    /// When the setting was last changed, according to the changing device.
+    pub updated_at: TimestampMs,
+}
+
+impl SettingsDoc {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(|entry| entry.value.as_str())
+    }
+
+    /// Sets `key` to `value`, as of `now`.
+    pub fn set(&mut self, key: String, value: String, now: TimestampMs) {
+        self.tombstones.remove(&key);
+        let entry = SettingsEntry {
+            value,
+            updated_at: now,
+        };
+        self.entries.insert(key, entry);
+    }
+
+    /// Deletes `key` as of `now`, leaving a tombstone.
+    pub fn remove(&mut self, key: &str, now: TimestampMs) {
+        self.entries.remove(key);
+        self.tombstones.insert(key.to_owned(), now);
+    }
+
+    /// Merges `other` into this doc, keeping the latest write for each key.
+    /// Returns whether any settings changed.
+    pub fn merge(&mut self, other: &Self) -> bool {
+        self.version = self.version.max(other.version);
+
+        let mut changed = false;
+        for (key, theirs) in &other.entries {
+            if self.latest(key)
+                < Some((theirs.updated_at, false, theirs.value.as_str()))
+            {
+                self.tombstones.remove(key);
+                self.entries.insert(key.clone(), theirs.clone());
+                changed = true;
+            }
+        }
+        for (key, deleted_at) in &other.tombstones {
+            if self.latest(key) < Some((*deleted_at, true, "")) {
+                self.entries.remove(key);
+                self.tombstones.insert(key.clone(), *deleted_at);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Drops tombstones older than [`TOMBSTONE_TTL`] as of `now`. Returns
+    /// whether any were dropped.
+    pub fn gc_tombstones(&mut self, now: TimestampMs) -> bool {
+        let now = now.into_duration();
+        let num_tombstones = self.tombstones.len();
+        self.tombstones.retain(|_key, deleted_at| {
+            now.saturating_sub(deleted_at.into_duration()) < TOMBSTONE_TTL
+        });
+        self.tombstones.len() != num_tombstones
+    }
+
+    /// Checks that the doc is within our size limits.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let keys = self.entries.keys().chain(self.tombstones.keys());
+        for key in keys {
+            ensure!(
+                !key.is_empty() && key.len() <= MAX_SETTINGS_KEY_LEN,
+                "Setting keys must be 1-{MAX_SETTINGS_KEY_LEN} bytes long"
+            );
+        }
+        let size = serde_json::to_vec(self)
+            .expect("Serializing a SettingsDoc can't fail")
+            .len();
+        ensure!(
+            size <= MAX_SETTINGS_DOC_SIZE,
+            "Settings are too large: {size} > {MAX_SETTINGS_DOC_SIZE} bytes"
+        );
+        Ok(())
+    }
+
+    /// The latest write to `key` as `(time, is_deletion, value)`, which orders
+    /// writes for last-writer-wins. At the same time, deletions win over sets,
+    /// and otherwise the greater value wins.
+    fn latest(&self, key: &str) -> Option<(TimestampMs, bool, &str)> {
+        match (self.entries.get(key), self.tombstones.get(key)) {
+            (Some(entry), _) =>
+                Some((entry.updated_at, false, entry.value.as_str())),
+            (None, Some(deleted_at)) => Some((*deleted_at, true, "")),
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::{
+        arbitrary::any, collection::vec, option, prop_assert_eq, proptest,
+        strategy::Strategy,
+    };
+
+    use super::*;
+
+    fn ts(ms: u32) -> TimestampMs {
+        TimestampMs::from(ms)
+    }
+
+    /// Docs built from random sets and deletes of a few keys, with plenty of
+    /// timestamp ties.
+    fn any_doc() -> impl Strategy<Value = SettingsDoc> {
+        let any_op = (0..4u8, option::of(0..4u8), 0..8u32);
+        (any::<u64>(), vec(any_op, 0..16)).prop_map(|(version, ops)| {
+            let mut doc = SettingsDoc {
+                version,
+                ..Default::default()
+            };
+            for (key, value, time) in ops {
+                let key = format!("key{key}");
+                match value {
+                    Some(value) => doc.set(key, value.to_string(), ts(time)),
+                    None => doc.remove(&key, ts(time)),
+                }
+            }
+            doc
+        })
+    }
+
+    #[test]
+    fn last_writer_wins() {
+        let mut a = SettingsDoc::default();
+        a.set("theme".to_owned(), "\"dark\"".to_owned(), ts(10));
+        a.set("fiat".to_owned(), "\"USD\"".to_owned(), ts(10));
+
+        let mut b = SettingsDoc::default();
+        b.set("theme".to_owned(), "\"light\"".to_owned(), ts(20));
+        b.set("fiat".to_owned(), "\"EUR\"".to_owned(), ts(5));
+        b.remove("fiat", ts(30));
+
+        // Newer set and newer deletion both win
+        assert!(a.merge(&b));
+        assert_eq!(a.get("theme"), Some("\"light\""));
+        assert_eq!(a.get("fiat"), None);
+        assert_eq!(a.tombstones.get("fiat"), Some(&ts(30)));
+        assert!(!a.merge(&b));
+
+        // A stale value doesn't resurrect a deleted key
+        let mut c = SettingsDoc::default();
+        c.set("fiat".to_owned(), "\"USD\"".to_owned(), ts(25));
+        assert!(!a.merge(&c));
+        assert_eq!(a.get("fiat"), None);
+
+        // But a newer one does
+        c.set("fiat".to_owned(), "\"CAD\"".to_owned(), ts(40));
+        assert!(a.merge(&c));
+        assert_eq!(a.get("fiat"), Some("\"CAD\""));
+        assert!(a.tombstones.is_empty());
+    }
+
+    #[test]
+    fn merge_converges() {
+        proptest!(|(a in any_doc(), b in any_doc())| {
+            let mut ab = a.clone();
+            ab.merge(&b);
+            let mut ba = b.clone();
+            ba.merge(&a);
+            prop_assert_eq!(&ab, &ba);
+
+            // Merging is idempotent
+            let mut abb = ab.clone();
+            prop_assert_eq!(abb.merge(&b), false);
+            prop_assert_eq!(abb, ab);
+        });
+    }
+
+    #[test]
+    fn gc_tombstones() {
+        // `ms` milliseconds after the TTL has passed since the epoch
+        let after_ttl = |ms: u64| {
+            TimestampMs::try_from(TOMBSTONE_TTL + Duration::from_millis(ms))
+                .unwrap()
+        };
+        let mut doc = SettingsDoc::default();
+        doc.set("theme".to_owned(), "\"dark\"".to_owned(), ts(1));
+        doc.remove("fiat", ts(10));
+        doc.remove("lang", ts(20));
+
+        // Nothing is old enough yet
+        assert!(!doc.gc_tombstones(after_ttl(9)));
+        assert_eq!(doc.tombstones.len(), 2);
+
+        // Only the expired tombstone is dropped, and entries are kept
+        assert!(doc.gc_tombstones(after_ttl(10)));
+        assert_eq!(doc.tombstones.keys().collect::<Vec<_>>(), ["lang"]);
+        assert_eq!(doc.get("theme"), Some("\"dark\""));
+    }
+
+    #[test]
+    fn validate_limits() {
+        let mut doc = SettingsDoc::default();
+        doc.set("theme".to_owned(), "\"dark\"".to_owned(), ts(1));
+        doc.validate().unwrap();
+
+        let mut bad = doc.clone();
+        bad.remove(&"k".repeat(MAX_SETTINGS_KEY_LEN + 1), ts(2));
+        bad.validate().unwrap_err();
+
+        let big = "x".repeat(MAX_SETTINGS_DOC_SIZE);
+        doc.set("big".to_owned(), big, ts(3));
+        doc.validate().unwrap_err();
+    }
+}
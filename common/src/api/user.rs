@@ -0,0 +1,156 @@
+//! User profile metadata.
+//!
+//! A [`UserProfile`] holds the user-facing details of a Lexe user, e.g. their
+//! display name or preferred fiat currency. Profiles are set by the app and
+//! persisted (encrypted) in the user node's VFS, so they are only ever visible
+//! to the user and their node.
+
+use anyhow::ensure;
+#[cfg(any(test, feature = "test-utils"))]
+use proptest_derive::Arbitrary;
+use serde::{Deserialize, Serialize};
+
+use crate::{api::fiat_rates::FiatCode, hexstr_or_bytes_opt};
+
+/// The maximum length of [`UserProfile::display_name`], in chars.
+pub const MAX_DISPLAY_NAME_LEN: usize = 64;
+/// The maximum length of [`UserProfile::bip353_username`], in bytes.
+/// DNS labels are limited to 63 bytes.
+pub const MAX_BIP353_USERNAME_LEN: usize = 63;
+
+/// User-facing metadata for a Lexe user. All fields are optional so that new
+/// fields can be added in a backwards-compatible way.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "test-utils"), derive(Arbitrary))]
+pub struct UserProfile {
+    /// The name the user would like to be shown as, e.g. "Satoshi".
+    pub display_name: Option<String>,
+    /// The SHA-256 hash of the user's avatar image. The image itself is
+    /// stored separately and addressed by this hash.
+    #[serde(default, with = "hexstr_or_bytes_opt")]
+    pub avatar_hash: Option<[u8; 32]>,
+    /// The fiat currency in which the app should display amounts.
+    pub default_currency: Option<FiatCode>,
+    /// The user part of the user's BIP353 human-readable name, i.e. `satoshi`
+    /// in `₿satoshi@lexe.app`.
+    pub bip353_username: Option<String>,
+}
+
+impl UserProfile {
+    /// Check that all fields are well-formed. Nodes should reject profiles
+    /// which fail validation rather than persisting them.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(display_name) = &self.display_name {
+            ensure!(!display_name.trim().is_empty(), "Display name is empty");
+            ensure!(
+                display_name.chars().count() <= MAX_DISPLAY_NAME_LEN,
+                "Display name is longer than {MAX_DISPLAY_NAME_LEN} chars"
+            );
+            ensure!(
+                !display_name.chars().any(char::is_control),
+                "Display name contains control characters"
+            );
+        }
+
+        if let Some(FiatCode(code)) = &self.default_currency {
+            ensure!(
+                code.len() == 3 && code.bytes().all(|b| b.is_ascii_uppercase()),
+                "Default currency must be an ISO 4217 code"
+            );
+        }
+
+        if let Some(username) = &self.bip353_username {
+            validate_bip353_username(username)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// BIP353 usernames become a DNS label
+/// (`<user>.user._bitcoin-payment.<domain>`) so we restrict them to lowercase
+/// letters, digits, `-`, and `_`, and don't allow them to start or end with
+/// `-`.
+fn validate_bip353_username(username: &str) -> anyhow::Result<()> {
+    ensure!(!username.is_empty(), "BIP353 username is empty");
+    ensure!(
+        username.len() <= MAX_BIP353_USERNAME_LEN,
+        "BIP353 username is longer than {MAX_BIP353_USERNAME_LEN} bytes"
+    );
+    ensure!(
+        username.bytes().all(|b| b.is_ascii_lowercase()
+            || b.is_ascii_digit()
+            || b == b'-'
+            || b == b'_'),
+        "BIP353 username may only contain a-z, 0-9, '-', and '_'"
+    );
+    ensure!(
+        !username.starts_with('-') && !username.ends_with('-'),
+        "BIP353 username can't start or end with '-'"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::roundtrip;
+
+    #[test]
+    fn user_profile_roundtrip() {
+        roundtrip::json_value_roundtrip_proptest::<UserProfile>();
+        roundtrip::bcs_roundtrip_proptest::<UserProfile>();
+    }
+
+    #[test]
+    fn user_profile_missing_fields() {
+        let profile = serde_json::from_str::<UserProfile>("{}").unwrap();
+        assert_eq!(profile, UserProfile::default());
+    }
+
+    #[test]
+    fn user_profile_validate() {
+        let profile = UserProfile {
+            display_name: Some("Satoshi".to_owned()),
+            avatar_hash: Some([0x69; 32]),
+            default_currency: Some(FiatCode("USD".to_owned())),
+            bip353_username: Some("satoshi_n".to_owned()),
+        };
+        profile.validate().unwrap();
+        UserProfile::default().validate().unwrap();
+
+        let bad_profiles = [
+            UserProfile {
+                display_name: Some("  ".to_owned()),
+                ..Default::default()
+            },
+            UserProfile {
+                display_name: Some("a".repeat(MAX_DISPLAY_NAME_LEN + 1)),
+                ..Default::default()
+            },
+            UserProfile {
+                display_name: Some("bad\nname".to_owned()),
+                ..Default::default()
+            },
+            UserProfile {
+                default_currency: Some(FiatCode("usd".to_owned())),
+                ..Default::default()
+            },
+            UserProfile {
+                bip353_username: Some("Satoshi".to_owned()),
+                ..Default::default()
+            },
+            UserProfile {
+                bip353_username: Some("-satoshi".to_owned()),
+                ..Default::default()
+            },
+            UserProfile {
+                bip353_username: Some("a".repeat(MAX_BIP353_USERNAME_LEN + 1)),
+                ..Default::default()
+            },
+        ];
+        for profile in bad_profiles {
+            assert!(profile.validate().is_err(), "{profile:?}");
+        }
+    }
+}
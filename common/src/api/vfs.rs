@@ -13,6 +13,10 @@
 //!
 //! Growable or shrinkable collections of objects (e.g. channel monitors), are
 //! stored in their own "directory", e.g. `channel_monitors/<funding_txo>`.
+//!
+//! Some critical singleton files also keep up to [`MAX_FILE_VERSIONS`] previous
+//! versions alongside them, at `<filename>.v<n>` (see [`VfsFileId::version`]),
+//! so that a bad write doesn't destroy the only copy of the file.
 
 use std::{fmt, fmt::Display};
 
@@ -20,6 +24,9 @@ use serde::{Deserialize, Serialize};
 
 use crate::hexstr_or_bytes;
 
+/// The max # of previous versions kept for a versioned file.
+pub const MAX_FILE_VERSIONS: u8 = 3;
+
 /// Uniquely identifies a directory in the virtual file system.
 ///
 /// This struct exists mainly so that `serde_qs` can use it as a query parameter
@@ -54,6 +61,18 @@ pub struct VfsFile {
     pub data: Vec<u8>,
 }
 
+/// The app sends this to restore a versioned singleton file from one of its
+/// previous versions, e.g. after a corrupt write.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct RestoreFileVersionRequest {
+    /// The name of the versioned file, e.g. `channel_manager`.
+    pub filename: String,
+    /// Which previous version to restore, in `1..=`[`MAX_FILE_VERSIONS`],
+    /// where 1 is the most recent.
+    pub version: u8,
+}
+
 impl VfsDirectory {
     pub fn new(dirname: impl Into<String>) -> Self {
         Self {
@@ -74,6 +93,17 @@ impl VfsFileId {
             filename: filename.into(),
         }
     }
+
+    /// The id of the `n`th most recent previous version of this file, where
+    /// `n` is in `1..=MAX_FILE_VERSIONS`.
+    ///
+    /// A version holds a copy of the file's data as it was persisted, so it
+    /// must be decrypted as *this* file, not as the version's own id.
+    pub fn version(&self, n: u8) -> Self {
+        debug_assert!((1..=MAX_FILE_VERSIONS).contains(&n));
+        let filename = &self.filename;
+        Self::new(self.dir.dirname.clone(), format!("{filename}.v{n}"))
+    }
 }
 
 impl VfsFile {
@@ -151,4 +181,13 @@ mod test {
     fn vfs_file_id_roundtrip() {
         roundtrip::query_string_roundtrip_proptest::<VfsFileId>();
     }
+
+    #[test]
+    fn file_version_ids() {
+        let file_id = VfsFileId::new(".", "channel_manager");
+        let v1 = file_id.version(1);
+        assert_eq!(v1.dir, file_id.dir);
+        assert_eq!(v1.filename, "channel_manager.v1");
+        assert_ne!(file_id.version(MAX_FILE_VERSIONS), v1);
+    }
 }
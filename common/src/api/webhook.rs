@@ -0,0 +1,259 @@
+//! Webhook event payloads and signatures.
+//!
+//! Every component which sends webhooks, and every consumer which receives
+//! them, should use these types so that they agree on a single schema. Each
+//! webhook is an HTTP POST whose JSON body is a [`WebhookEvent`], signed with
+//! a secret shared between the sender and the receiver.
+//!
+//! The signature is sent in the [`SIGNATURE_HEADER`] header with the format
+//! `t=<unix timestamp secs>,v1=<hex HMAC-SHA256>`, where the HMAC is computed
+//! over `<timestamp>.<body>`. Receivers should call
+//! [`verify_webhook_signature`] with the raw body bytes *before* parsing them.
+//! Multiple `v1` signatures may be present while a secret is being rotated.
+
+use std::time::Duration;
+
+use http::HeaderMap;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::NodePk,
+    hex, hexstr_or_bytes,
+    ln::{amount::Amount, channel::ChannelId, payments::BasicPayment},
+    time::TimestampMs,
+};
+
+/// The current version of the [`WebhookEvent`] schema.
+pub const WEBHOOK_EVENT_VERSION: u16 = 1;
+
+/// The HTTP header containing the webhook signature.
+pub const SIGNATURE_HEADER: &str = "lexe-signature";
+
+/// Webhooks signed further than this from the current time are rejected, to
+/// limit the window in which a captured webhook can be replayed.
+pub const TIMESTAMP_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+/// The JSON body of a webhook.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    /// The [`WEBHOOK_EVENT_VERSION`] this event was created with.
+    pub version: u16,
+    /// A unique id for this event. Webhooks may be delivered more than once,
+    /// so receivers should use this to deduplicate.
+    #[serde(with = "hexstr_or_bytes")]
+    pub id: [u8; 16],
+    /// When the event occurred.
+    pub created_at: TimestampMs,
+    #[serde(flatten)]
+    pub data: WebhookEventData,
+}
+
+/// The event-specific contents of a [`WebhookEvent`], serialized as
+/// `"type": "<event type>", "data": { ... }`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum WebhookEventData {
+    /// A payment was created or its status changed.
+    #[serde(rename = "payment.updated")]
+    PaymentUpdated(BasicPayment),
+    /// A channel is ready to be used.
+    #[serde(rename = "channel.opened")]
+    ChannelOpened {
+        channel_id: ChannelId,
+        counterparty_node_id: NodePk,
+        channel_value: Amount,
+    },
+    /// A channel was closed.
+    #[serde(rename = "channel.closed")]
+    ChannelClosed {
+        channel_id: ChannelId,
+        /// A human-readable description of why the channel was closed.
+        reason: String,
+    },
+}
+
+/// Errors returned by [`verify_webhook_signature`].
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("Missing {SIGNATURE_HEADER} header")]
+    MissingSignature,
+    #[error("Malformed {SIGNATURE_HEADER} header")]
+    MalformedSignature,
+    #[error("Webhook timestamp is outside the tolerance window")]
+    StaleTimestamp,
+    #[error("No matching webhook signature")]
+    BadSignature,
+}
+
+impl WebhookEvent {
+    pub fn new(id: [u8; 16], data: WebhookEventData) -> Self {
+        Self {
+            version: WEBHOOK_EVENT_VERSION,
+            id,
+            created_at: TimestampMs::now(),
+            data,
+        }
+    }
+}
+
+/// Compute the [`SIGNATURE_HEADER`] value for a webhook with the given body,
+/// signed at `timestamp`.
+pub fn sign_webhook(
+    secret: &[u8],
+    timestamp: TimestampMs,
+    body: &[u8],
+) -> String {
+    let timestamp_secs = timestamp.into_duration().as_secs();
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let tag = hmac::sign(&key, &signed_payload(timestamp_secs, body));
+    format!("t={timestamp_secs},v1={}", hex::encode(tag.as_ref()))
+}
+
+/// Check that a received webhook was signed with `secret` within the last
+/// [`TIMESTAMP_TOLERANCE`].
+pub fn verify_webhook_signature(
+    secret: &[u8],
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), WebhookError> {
+    verify_webhook_signature_at(secret, headers, body, TimestampMs::now())
+}
+
+fn verify_webhook_signature_at(
+    secret: &[u8],
+    headers: &HeaderMap,
+    body: &[u8],
+    now: TimestampMs,
+) -> Result<(), WebhookError> {
+    let header = headers
+        .get(SIGNATURE_HEADER)
+        .ok_or(WebhookError::MissingSignature)?
+        .to_str()
+        .map_err(|_| WebhookError::MalformedSignature)?;
+
+    let mut timestamp_secs = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", t)) =>
+                timestamp_secs = Some(
+                    t.parse::<u64>()
+                        .map_err(|_| WebhookError::MalformedSignature)?,
+                ),
+            Some(("v1", sig)) => signatures.push(
+                hex::decode(sig)
+                    .map_err(|_| WebhookError::MalformedSignature)?,
+            ),
+            // Ignore unknown schemes so we can add new ones later.
+            Some(_) => (),
+            None => return Err(WebhookError::MalformedSignature),
+        }
+    }
+    let timestamp_secs =
+        timestamp_secs.ok_or(WebhookError::MalformedSignature)?;
+    if signatures.is_empty() {
+        return Err(WebhookError::MalformedSignature);
+    }
+
+    let now_secs = now.into_duration().as_secs();
+    if now_secs.abs_diff(timestamp_secs) > TIMESTAMP_TOLERANCE.as_secs() {
+        return Err(WebhookError::StaleTimestamp);
+    }
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let payload = signed_payload(timestamp_secs, body);
+    signatures
+        .iter()
+        .any(|sig| hmac::verify(&key, &payload, sig).is_ok())
+        .then_some(())
+        .ok_or(WebhookError::BadSignature)
+}
+
+/// The bytes which are signed: `<timestamp secs>.<body>`.
+fn signed_payload(timestamp_secs: u64, body: &[u8]) -> Vec<u8> {
+    let mut payload = format!("{timestamp_secs}.").into_bytes();
+    payload.extend_from_slice(body);
+    payload
+}
+
+#[cfg(test)]
+mod test {
+    use http::HeaderValue;
+
+    use super::*;
+
+    fn headers(signature: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let value = HeaderValue::from_str(signature).unwrap();
+        headers.insert(SIGNATURE_HEADER, value);
+        headers
+    }
+
+    #[test]
+    fn webhook_event_json() {
+        let event = WebhookEvent {
+            version: WEBHOOK_EVENT_VERSION,
+            id: [0x42; 16],
+            created_at: TimestampMs::try_from(1_700_000_000_000_i64).unwrap(),
+            data: WebhookEventData::ChannelClosed {
+                channel_id: ChannelId([0x69; 32]),
+                reason: "Cooperative close".to_owned(),
+            },
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["id"], "42".repeat(16));
+        assert_eq!(json["type"], "channel.closed");
+        assert_eq!(json["data"]["reason"], "Cooperative close");
+
+        let roundtripped = serde_json::from_value::<WebhookEvent>(json);
+        assert_eq!(roundtripped.unwrap(), event);
+    }
+
+    #[test]
+    fn webhook_signature() {
+        let secret = b"whsec_test";
+        let body = br#"{"version":1}"#;
+        let signed_at = TimestampMs::try_from(1_700_000_000_000_i64).unwrap();
+        let signature = sign_webhook(secret, signed_at, body);
+        let later = |secs: u64| {
+            TimestampMs::try_from(signed_at.into_u64() + secs * 1000).unwrap()
+        };
+
+        let verify = |secret: &[u8], headers: &HeaderMap, body: &[u8], now| {
+            verify_webhook_signature_at(secret, headers, body, now)
+        };
+
+        verify(secret, &headers(&signature), body, signed_at).unwrap();
+        verify(secret, &headers(&signature), body, later(300)).unwrap();
+
+        assert!(matches!(
+            verify(secret, &headers(&signature), body, later(301)),
+            Err(WebhookError::StaleTimestamp)
+        ));
+        assert!(matches!(
+            verify(b"wrong", &headers(&signature), body, signed_at),
+            Err(WebhookError::BadSignature)
+        ));
+        assert!(matches!(
+            verify(secret, &headers(&signature), b"{}", signed_at),
+            Err(WebhookError::BadSignature)
+        ));
+        assert!(matches!(
+            verify(secret, &HeaderMap::new(), body, signed_at),
+            Err(WebhookError::MissingSignature)
+        ));
+        assert!(matches!(
+            verify(secret, &headers("t=1700000000"), body, signed_at),
+            Err(WebhookError::MalformedSignature)
+        ));
+
+        // Any matching signature is accepted, e.g. during secret rotation.
+        let old = sign_webhook(b"old_secret", signed_at, body);
+        let old_sig = old.split_once(",v1=").unwrap().1;
+        let rotated = format!("{signature},v1={old_sig}");
+        verify(secret, &headers(&rotated), body, signed_at).unwrap();
+        verify(b"old_secret", &headers(&rotated), body, signed_at).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 use std::{fmt, fmt::Display, path::Path, process::Command, str::FromStr};
 
-use anyhow::{bail, ensure, Context};
+use anyhow::{ensure, Context};
 use bitcoin::{
     blockdata::constants::{self, ChainHash},
     hash_types::BlockHash,
@@ -16,7 +16,10 @@ use serde::{Deserialize, Serialize};
 use crate::test_utils::arbitrary;
 use crate::{
     api::{NodePk, Scid},
-    constants::{MAINNET_ESPLORA_WHITELIST, TESTNET_ESPLORA_WHITELIST},
+    constants::{
+        MAINNET_ESPLORA_WHITELIST, SIGNET_ESPLORA_WHITELIST,
+        TESTNET_ESPLORA_WHITELIST,
+    },
     ln::{addr::LxSocketAddress, peer::ChannelPeer},
 };
 
@@ -142,7 +145,10 @@ impl Network {
                 TESTNET_ESPLORA_WHITELIST.contains(&esplora_url),
                 "Esplora url not contained in testnet whitelist",
             ),
-            Self::SIGNET => bail!("Missing signet esplora whitelist"),
+            Self::SIGNET => ensure!(
+                SIGNET_ESPLORA_WHITELIST.contains(&esplora_url),
+                "Esplora url not contained in signet whitelist",
+            ),
             // Regtest can use whatever
             Self::REGTEST => (),
         }
@@ -339,6 +345,28 @@ mod test {
         roundtrip::fromstr_display_roundtrip_proptest::<OAuthConfig>();
     }
 
+    #[test]
+    fn validate_esplora_url() {
+        use crate::constants::{
+            MAINNET_BLOCKSTREAM_ESPLORA, SIGNET_MEMPOOL_ESPLORA,
+            TESTNET_BLOCKSTREAM_ESPLORA,
+        };
+
+        let cases = [
+            (Network::MAINNET, MAINNET_BLOCKSTREAM_ESPLORA, true),
+            (Network::MAINNET, TESTNET_BLOCKSTREAM_ESPLORA, false),
+            (Network::TESTNET, TESTNET_BLOCKSTREAM_ESPLORA, true),
+            (Network::TESTNET, SIGNET_MEMPOOL_ESPLORA, false),
+            (Network::SIGNET, SIGNET_MEMPOOL_ESPLORA, true),
+            (Network::SIGNET, TESTNET_BLOCKSTREAM_ESPLORA, false),
+            (Network::REGTEST, "http://localhost:3002", true),
+        ];
+        for (network, url, valid) in cases {
+            let res = network.validate_esplora_url(url);
+            assert_eq!(res.is_ok(), valid, "{network}, {url}");
+        }
+    }
+
     // Sanity check that Hash(genesis_block) == precomputed hash
     #[test]
     fn check_precomputed_genesis_block_hashes() {
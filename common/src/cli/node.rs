@@ -4,6 +4,7 @@ use std::{
     str::FromStr,
 };
 
+use anyhow::ensure;
 #[cfg(test)]
 use proptest_derive::Arbitrary;
 use serde::{Deserialize, Serialize};
@@ -11,7 +12,7 @@ use serde::{Deserialize, Serialize};
 #[cfg(test)]
 use crate::test_utils::arbitrary;
 use crate::{
-    api::UserPk,
+    api::{rate_limit::RateLimitConfig, UserPk},
     cli::{LspInfo, Network, OAuthConfig, ToCommand},
     env::DeployEnv,
 };
@@ -63,12 +64,90 @@ pub struct RunArgs {
     #[cfg_attr(test, proptest(strategy = "arbitrary::any_simple_string()"))]
     pub esplora_url: String,
 
+    /// protocol://host:port of a rapid gossip sync server, used to quickly
+    /// populate the network graph. We rely on P2P gossip alone if not
+    /// supplied.
+    #[cfg_attr(
+        test,
+        proptest(strategy = "arbitrary::any_option_simple_string()")
+    )]
+    pub rgs_url: Option<String>,
+
     /// info relating to Lexe's LSP.
     pub lsp: LspInfo,
 
     /// The current deploy environment passed to us by Lexe (or someone in
     /// Lexe's cloud). This input should be treated as untrusted.
     pub untrusted_deploy_env: DeployEnv,
+
+    /// Limits applied to the app server, e.g. rate limits.
+    #[serde(default)]
+    pub app_limits: AppLimitsConfig,
+
+    /// Where to get fee rate estimates from. Defaults to `esplora_url`.
+    #[serde(default)]
+    pub fee_oracle: FeeOracleConfig,
+}
+
+/// Where the node gets its fee rate estimates from. Whichever source is used,
+/// every estimate is clamped to sane bounds before use.
+#[cfg_attr(test, derive(Arbitrary))]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum FeeOracleConfig {
+    /// Lexe's Esplora server, i.e. `RunArgs::esplora_url`.
+    #[default]
+    Esplora,
+    /// A mempool.space instance, e.g. "https://mempool.space/api". Shares
+    /// the Esplora client's pinned TLS roots.
+    MempoolSpace {
+        #[cfg_attr(
+            test,
+            proptest(strategy = "arbitrary::any_simple_string()")
+        )]
+        url: String,
+    },
+    /// A fixed fee rate (in sat/vB) for every confirmation target. Only
+    /// intended as a manual override if the other sources are misbehaving.
+    Fixed { sat_per_vb: u32 },
+}
+
+/// Limits applied to the node's app server.
+#[cfg_attr(test, derive(Arbitrary))]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AppLimitsConfig {
+    /// Rate limit applied across all app endpoints.
+    pub rate_limit: RateLimitConfig,
+    /// The maximum # of concurrent requests to each expensive endpoint, e.g.
+    /// preflights, which may run pathfinding or coin selection.
+    pub max_concurrent_expensive: usize,
+}
+
+impl AppLimitsConfig {
+    /// Errors if these limits would reject every request.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.rate_limit.burst > 0,
+            "Rate limit burst must be non-zero"
+        );
+        ensure!(
+            !self.rate_limit.refill_interval.is_zero(),
+            "Rate limit refill interval must be non-zero"
+        );
+        ensure!(
+            self.max_concurrent_expensive > 0,
+            "Max concurrent expensive requests must be non-zero"
+        );
+        Ok(())
+    }
+}
+
+impl Default for AppLimitsConfig {
+    fn default() -> Self {
+        Self {
+            rate_limit: RateLimitConfig::default(),
+            max_concurrent_expensive: 2,
+        }
+    }
 }
 
 #[cfg(any(test, feature = "test-utils"))]
@@ -85,9 +164,12 @@ impl Default for RunArgs {
             backend_url: Some(DUMMY_BACKEND_URL.to_owned()),
             runner_url: Some(DUMMY_RUNNER_URL.to_owned()),
             esplora_url: DUMMY_ESPLORA_URL.to_owned(),
+            rgs_url: None,
             lsp: LspInfo::dummy(),
             allow_mock: false,
             untrusted_deploy_env: DeployEnv::Dev,
+            app_limits: AppLimitsConfig::default(),
+            fee_oracle: FeeOracleConfig::default(),
         }
     }
 }
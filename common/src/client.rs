@@ -9,13 +9,14 @@
 use std::{
     panic::{RefUnwindSafe, UnwindSafe},
     sync::Arc,
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
 use anyhow::Context;
 use async_trait::async_trait;
 use bitcoin::Address;
 use reqwest::Url;
+use rustls::pki_types::UnixTime;
 
 use crate::{
     api::{
@@ -23,12 +24,16 @@ use crate::{
             BearerAuthRequest, BearerAuthResponse, BearerAuthenticator,
             UserSignupRequest,
         },
+        circuit_breaker::CircuitBreakerConfig,
         command::{
-            CreateInvoiceRequest, CreateInvoiceResponse, NodeInfo,
+            BumpReceiveRequest, BumpReceiveResponse, ChannelHealthResponse,
+            ChannelOperation, ChannelOperationId, CloseChannelRequest,
+            CreateInvoiceRequest, CreateInvoiceResponse, GDriveStorageStatus,
+            GetAddressForRequest, NodeInfo, OpenChannelRequest,
             PayInvoiceRequest, PayInvoiceResponse, PayOnchainRequest,
             PayOnchainResponse, PreflightPayInvoiceRequest,
             PreflightPayInvoiceResponse, PreflightPayOnchainRequest,
-            PreflightPayOnchainResponse,
+            PreflightPayOnchainResponse, QueryPayments, QueryPaymentsResponse,
         },
         def::{
             AppBackendApi, AppGatewayApi, AppNodeProvisionApi, AppNodeRunApi,
@@ -38,27 +43,58 @@ use crate::{
             BackendApiError, GatewayApiError, NodeApiError, NodeErrorKind,
         },
         fiat_rates::FiatRates,
+        migration::{
+            DecommissionRequest, ExportStateRequest, ExportStateResponse,
+            ImportStateRequest,
+        },
         models::NodeRelease,
-        provision::NodeProvisionRequest,
+        provision::{
+            AttestChallengeRequest, AttestChallengeResponse,
+            NodeProvisionRequest,
+        },
         qs::{GetNewPayments, GetPaymentsByIds, UpdatePaymentNote},
+        response_cache::ResponseCache,
         rest::{RequestBuilderExt, RestClient, GET, POST},
+        settings::SettingsDoc,
+        user::UserProfile,
+        vfs::RestoreFileVersionRequest,
         Empty,
     },
     constants::{self, node_provision_dns},
     ed25519,
     enclave::Measurement,
     env::DeployEnv,
-    ln::payments::BasicPayment,
-    rng::Crng,
+    ln::{fee_policy::FeePolicy, payments::BasicPayment},
+    rng::{Crng, SysRng},
     root_seed::RootSeed,
-    tls::{self, lexe_ca},
+    tls::{
+        self,
+        attestation::{
+            evidence::EvidenceBundle,
+            verifier::{self, AttestationChallenge, EnclavePolicy},
+            ProvisionCertPk,
+        },
+        lexe_ca,
+        shared_seed::rotation::SeedRotationState,
+    },
 };
 
+/// The timeout for payments and payment preflights, which may need to find a
+/// route or query the fee estimator. Matches the node's handling timeout.
+const LONG_REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// The timeout for cheap status checks, which the app would rather retry than
+/// wait on.
+const SHORT_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a cached [`NodeRelease`] is used before revalidating it.
+const LATEST_RELEASE_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
 /// The client to the gateway itself, i.e. requests terminate at the gateway.
 #[derive(Clone)]
 pub struct GatewayClient {
     rest: RestClient,
     gateway_url: String,
+    /// Caches slowly-changing responses like the latest release.
+    response_cache: Arc<ResponseCache>,
 }
 
 /// The client to the user node.
@@ -96,8 +132,19 @@ impl GatewayClient {
         gateway_url: String,
     ) -> anyhow::Result<Self> {
         let tls_config = lexe_ca::app_gateway_client_config(deploy_env);
-        let rest = RestClient::new("app", "gateway", tls_config);
-        Ok(Self { rest, gateway_url })
+        let rest = RestClient::new("app", "gateway", tls_config)
+            .with_circuit_breaker(CircuitBreakerConfig::default());
+        Ok(Self {
+            rest,
+            gateway_url,
+            response_cache: Arc::new(ResponseCache::default()),
+        })
+    }
+
+    /// The cache of slowly-changing gateway responses, which callers may
+    /// persist across restarts. Shared with all clones of this client.
+    pub fn response_cache(&self) -> &ResponseCache {
+        &self.response_cache
     }
 }
 
@@ -148,7 +195,9 @@ impl AppGatewayApi for GatewayClient {
         let req = self
             .rest
             .get(format!("{gateway_url}/app/v1/latest_release"), &Empty {});
-        self.rest.send(req).await
+        self.rest
+            .send_cached(req, &self.response_cache, LATEST_RELEASE_CACHE_TTL)
+            .await
     }
 }
 
@@ -214,6 +263,7 @@ impl NodeClient {
         rng: &mut impl Crng,
         use_sgx: bool,
         root_seed: &RootSeed,
+        seed_rotation: Option<&SeedRotationState>,
         deploy_env: DeployEnv,
         authenticator: Arc<BearerAuthenticator>,
         gateway_client: GatewayClient,
@@ -230,7 +280,10 @@ impl NodeClient {
             .context("Invalid proxy config")?;
 
             let tls_config = tls::shared_seed::app_node_run_client_config(
-                rng, deploy_env, root_seed,
+                rng,
+                deploy_env,
+                root_seed,
+                seed_rotation,
             )?;
 
             let (from, to) = ("app", "node-run");
@@ -241,6 +294,7 @@ impl NodeClient {
                 .context("Failed to build client")?;
 
             RestClient::from_inner(reqwest_client, from, to)
+                .with_circuit_breaker(CircuitBreakerConfig::default())
         };
 
         Ok(Self {
@@ -339,17 +393,19 @@ impl NodeClient {
                 NodeApiError {
                     kind: NodeErrorKind::BadAuth,
                     msg: format!("{err:#}"),
+                    data: None,
                 }
             })
     }
 
     /// Builds a Provision-specific [`RestClient`] which can be used to make a
-    /// provision request to a provisioning node.
+    /// provision request to a provisioning node. Also returns the
+    /// [`ProvisionCertPk`] which the client records during its TLS handshake.
     fn provision_rest_client(
         &self,
         measurement: Measurement,
         provision_url: &str,
-    ) -> anyhow::Result<RestClient> {
+    ) -> anyhow::Result<(RestClient, ProvisionCertPk)> {
         let proxy = Self::proxy_config(
             &self.gateway_client.gateway_url,
             provision_url,
@@ -357,11 +413,12 @@ impl NodeClient {
         )
         .context("Invalid proxy config")?;
 
-        let tls_config = tls::attestation::app_node_provision_client_config(
-            self.use_sgx,
-            self.deploy_env,
-            measurement,
-        );
+        let (tls_config, cert_pk) =
+            tls::attestation::app_node_provision_client_config(
+                self.use_sgx,
+                self.deploy_env,
+                measurement,
+            );
 
         let (from, to) = ("app", "node-provision");
         let reqwest_client = RestClient::client_builder(from)
@@ -372,32 +429,119 @@ impl NodeClient {
 
         let provision_rest = RestClient::from_inner(reqwest_client, from, to);
 
+        Ok((provision_rest, cert_pk))
+    }
+
+    /// Builds a Provision-specific [`RestClient`], then checks that the
+    /// provisioning node's remote attestation is fresh by challenging it to
+    /// quote a random nonce. Only send secrets with the returned client.
+    async fn attested_provision_rest_client(
+        &self,
+        measurement: Measurement,
+        provision_url: &str,
+    ) -> Result<RestClient, NodeApiError> {
+        let (provision_rest, cert_pk) = self
+            .provision_rest_client(measurement, provision_url)
+            .context("Failed to build provision rest client")
+            .map_err(NodeApiError::provision)?;
+
+        let challenge =
+            AttestationChallenge::new(&mut SysRng::new(), UnixTime::now());
+        let data = AttestChallengeRequest {
+            nonce: challenge.nonce,
+        };
+        self.ensure_authed().await?;
+        let req =
+            provision_rest.post(format!("{provision_url}/app/attest"), &data);
+        let resp: AttestChallengeResponse = provision_rest.send(req).await?;
+
+        // The TLS handshake has completed by now, recording the cert pk.
+        let cert_pk = cert_pk
+            .get()
+            .context("Missing provisioning node attestation cert pk")
+            .map_err(NodeApiError::provision)?;
+        let enclave_policy = EnclavePolicy::trust_measurements_with_signer(
+            self.use_sgx,
+            self.deploy_env,
+            vec![measurement],
+        );
+        verifier::verify_challenge_quote(
+            &resp.quote,
+            &challenge,
+            cert_pk,
+            !self.use_sgx,
+            &enclave_policy,
+            UnixTime::now(),
+        )
+        .context("Provisioning node failed attestation challenge")
+        .map_err(NodeApiError::provision)?;
+
         Ok(provision_rest)
     }
 }
 
 #[async_trait]
 impl AppNodeProvisionApi for NodeClient {
-    async fn provision(
+    async fn attest(
         &self,
         measurement: Measurement,
-        data: NodeProvisionRequest,
-    ) -> Result<Empty, NodeApiError> {
+        data: AttestChallengeRequest,
+    ) -> Result<AttestChallengeResponse, NodeApiError> {
         let mr_short = measurement.short();
         let provision_dns = node_provision_dns(&mr_short);
         let provision_url = format!("https://{provision_dns}");
 
         // Create rest client on the fly
-        let provision_rest = self
+        let (provision_rest, _cert_pk) = self
             .provision_rest_client(measurement, &provision_url)
             .context("Failed to build provision rest client")
             .map_err(NodeApiError::provision)?;
 
         self.ensure_authed().await?;
+        let req =
+            provision_rest.post(format!("{provision_url}/app/attest"), &data);
+        provision_rest.send(req).await
+    }
+
+    async fn provision(
+        &self,
+        measurement: Measurement,
+        data: NodeProvisionRequest,
+    ) -> Result<Empty, NodeApiError> {
+        let mr_short = measurement.short();
+        let provision_dns = node_provision_dns(&mr_short);
+        let provision_url = format!("https://{provision_dns}");
+
+        // Create rest client on the fly, checking the node's attestation is
+        // fresh before sending it any secrets.
+        let provision_rest = self
+            .attested_provision_rest_client(measurement, &provision_url)
+            .await?;
+
         let req = provision_rest
             .post(format!("{provision_url}/app/provision"), &data);
         provision_rest.send(req).await
     }
+
+    async fn import_state(
+        &self,
+        measurement: Measurement,
+        data: ImportStateRequest,
+    ) -> Result<Empty, NodeApiError> {
+        let mr_short = measurement.short();
+        let provision_dns = node_provision_dns(&mr_short);
+        let provision_url = format!("https://{provision_dns}");
+
+        // Create rest client on the fly, checking the node's attestation is
+        // fresh before sending it any secrets.
+        let provision_rest = self
+            .attested_provision_rest_client(measurement, &provision_url)
+            .await?;
+
+        let req = provision_rest
+            .post(format!("{provision_url}/app/import_state"), &data);
+        provision_rest.send(req).await
+    }
 }
 
 #[async_trait]
@@ -406,7 +550,10 @@ impl AppNodeRunApi for NodeClient {
         self.ensure_authed().await?;
         let run_url = &self.run_url;
         let url = format!("{run_url}/app/node_info");
-        let req = self.run_rest.builder(GET, url);
+        let req = self
+            .run_rest
+            .builder(GET, url)
+            .timeout(SHORT_REQUEST_TIMEOUT);
         self.run_rest.send(req).await
     }
 
@@ -428,7 +575,7 @@ impl AppNodeRunApi for NodeClient {
         self.ensure_authed().await?;
         let run_url = &self.run_url;
         let url = format!("{run_url}/app/pay_invoice");
-        let req = self.run_rest.post(url, &req);
+        let req = self.run_rest.post(url, &req).timeout(LONG_REQUEST_TIMEOUT);
         self.run_rest.send(req).await
     }
 
@@ -439,7 +586,7 @@ impl AppNodeRunApi for NodeClient {
         self.ensure_authed().await?;
         let run_url = &self.run_url;
         let url = format!("{run_url}/app/preflight_pay_invoice");
-        let req = self.run_rest.post(url, &req);
+        let req = self.run_rest.post(url, &req).timeout(LONG_REQUEST_TIMEOUT);
         self.run_rest.send(req).await
     }
 
@@ -450,7 +597,7 @@ impl AppNodeRunApi for NodeClient {
         self.ensure_authed().await?;
         let run_url = &self.run_url;
         let url = format!("{run_url}/app/pay_onchain");
-        let req = self.run_rest.post(url, &req);
+        let req = self.run_rest.post(url, &req).timeout(LONG_REQUEST_TIMEOUT);
         self.run_rest.send(req).await
     }
 
@@ -461,6 +608,17 @@ impl AppNodeRunApi for NodeClient {
         self.ensure_authed().await?;
         let run_url = &self.run_url;
         let url = format!("{run_url}/app/preflight_pay_onchain");
+        let req = self.run_rest.post(url, &req).timeout(LONG_REQUEST_TIMEOUT);
+        self.run_rest.send(req).await
+    }
+
+    async fn bump_receive(
+        &self,
+        req: BumpReceiveRequest,
+    ) -> Result<BumpReceiveResponse, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/bump_receive");
         let req = self.run_rest.post(url, &req);
         self.run_rest.send(req).await
     }
@@ -473,6 +631,73 @@ impl AppNodeRunApi for NodeClient {
         self.run_rest.send(req).await
     }
 
+    async fn get_address_for(
+        &self,
+        req: GetAddressForRequest,
+    ) -> Result<Address, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/get_address_for");
+        let req = self.run_rest.post(url, &req);
+        self.run_rest.send(req).await
+    }
+
+    async fn channel_health(
+        &self,
+    ) -> Result<ChannelHealthResponse, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/channel_health");
+        let req = self
+            .run_rest
+            .builder(GET, url)
+            .timeout(SHORT_REQUEST_TIMEOUT);
+        self.run_rest.send(req).await
+    }
+
+    async fn gdrive_storage_status(
+        &self,
+    ) -> Result<GDriveStorageStatus, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/gdrive_storage_status");
+        let req = self.run_rest.builder(GET, url);
+        self.run_rest.send(req).await
+    }
+
+    async fn open_channel(
+        &self,
+        req: OpenChannelRequest,
+    ) -> Result<ChannelOperationId, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/open_channel");
+        let req = self.run_rest.post(url, &req);
+        self.run_rest.send(req).await
+    }
+
+    async fn close_channel(
+        &self,
+        req: CloseChannelRequest,
+    ) -> Result<ChannelOperationId, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/close_channel");
+        let req = self.run_rest.post(url, &req);
+        self.run_rest.send(req).await
+    }
+
+    async fn channel_operation(
+        &self,
+        req: ChannelOperationId,
+    ) -> Result<ChannelOperation, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/channel_operation");
+        let req = self.run_rest.get(url, &req);
+        self.run_rest.send(req).await
+    }
+
     async fn get_payments_by_ids(
         &self,
         req: GetPaymentsByIds,
@@ -480,7 +705,7 @@ impl AppNodeRunApi for NodeClient {
         self.ensure_authed().await?;
         let run_url = &self.run_url;
         let url = format!("{run_url}/app/payments/ids");
-        let req = self.run_rest.post(url, &req);
+        let req = self.run_rest.post(url, &req).accept_cbor();
         self.run_rest.send(req).await
     }
 
@@ -491,7 +716,18 @@ impl AppNodeRunApi for NodeClient {
         self.ensure_authed().await?;
         let run_url = &self.run_url;
         let url = format!("{run_url}/app/payments/new");
-        let req = self.run_rest.get(url, &req);
+        let req = self.run_rest.get(url, &req).accept_cbor();
+        self.run_rest.send(req).await
+    }
+
+    async fn query_payments(
+        &self,
+        req: QueryPayments,
+    ) -> Result<QueryPaymentsResponse, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/payments/query");
+        let req = self.run_rest.post(url, &req).accept_cbor();
         self.run_rest.send(req).await
     }
 
@@ -505,6 +741,106 @@ impl AppNodeRunApi for NodeClient {
         let req = self.run_rest.put(url, &req);
         self.run_rest.send(req).await
     }
+
+    async fn get_user_profile(&self) -> Result<UserProfile, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/profile");
+        let req = self.run_rest.builder(GET, url);
+        self.run_rest.send(req).await
+    }
+
+    async fn update_user_profile(
+        &self,
+        req: UserProfile,
+    ) -> Result<Empty, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/profile");
+        let req = self.run_rest.put(url, &req);
+        self.run_rest.send(req).await
+    }
+
+    async fn get_settings(&self) -> Result<SettingsDoc, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/settings");
+        let req = self.run_rest.builder(GET, url);
+        self.run_rest.send(req).await
+    }
+
+    async fn sync_settings(
+        &self,
+        req: SettingsDoc,
+    ) -> Result<SettingsDoc, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/settings");
+        let req = self.run_rest.put(url, &req);
+        self.run_rest.send(req).await
+    }
+
+    async fn get_fee_policy(&self) -> Result<FeePolicy, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/fee_policy");
+        let req = self.run_rest.builder(GET, url);
+        self.run_rest.send(req).await
+    }
+
+    async fn update_fee_policy(
+        &self,
+        req: FeePolicy,
+    ) -> Result<Empty, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/fee_policy");
+        let req = self.run_rest.put(url, &req);
+        self.run_rest.send(req).await
+    }
+
+    async fn export_state(
+        &self,
+        req: ExportStateRequest,
+    ) -> Result<ExportStateResponse, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/export_state");
+        let req = self.run_rest.post(url, &req);
+        self.run_rest.send(req).await
+    }
+
+    async fn decommission(
+        &self,
+        req: DecommissionRequest,
+    ) -> Result<Empty, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/decommission");
+        let req = self.run_rest.post(url, &req);
+        self.run_rest.send(req).await
+    }
+
+    async fn restore_file_version(
+        &self,
+        req: RestoreFileVersionRequest,
+    ) -> Result<Empty, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/restore_file_version");
+        let req = self.run_rest.post(url, &req);
+        self.run_rest.send(req).await
+    }
+
+    async fn get_attestation_evidence(
+        &self,
+    ) -> Result<EvidenceBundle, NodeApiError> {
+        self.ensure_authed().await?;
+        let run_url = &self.run_url;
+        let url = format!("{run_url}/app/attestation_evidence");
+        let req = self.run_rest.builder(GET, url);
+        self.run_rest.send(req).await
+    }
 }
 
 fn url_base_eq(u1: &Url, u2: &Url) -> bool {
@@ -53,6 +53,10 @@ pub const TESTNET_ESPLORA_WHITELIST: [&str; 4] = [
     TESTNET_LEXE_ESPLORA,
 ];
 
+// Signet Esplora urls
+pub const SIGNET_MEMPOOL_ESPLORA: &str = "https://mempool.space/signet/api";
+pub const SIGNET_ESPLORA_WHITELIST: [&str; 1] = [SIGNET_MEMPOOL_ESPLORA];
+
 /// Fake DNS names used by the reverse proxy to route requests to user nodes.
 /// Provision mode uses "{mr_short}.provision.lexe.app" and run mode uses
 /// "run.lexe.app". These DNS names don't actually resolve.
@@ -46,7 +46,7 @@ use thiserror::Error;
 use x509_parser::x509::SubjectPublicKeyInfo;
 
 use crate::{
-    const_assert_usize_eq, const_ref_cast,
+    array, const_assert_usize_eq, const_ref_cast,
     hex::{self, FromHex},
     rng::{Crng, RngExt},
     sha256,
@@ -64,6 +64,17 @@ pub const SIGNATURE_LEN: usize = 64;
 /// struct size.
 pub const SIGNED_STRUCT_OVERHEAD: usize = PUBLIC_KEY_LEN + SIGNATURE_LEN;
 
+/// The maximum length of a signing context, as in Ed25519ctx (RFC 8032).
+pub const MAX_CONTEXT_LEN: usize = 255;
+
+/// The HKDF salt used to derive child key pairs in [`KeyPair::derive_child`].
+const CHILD_KEY_HKDF_SALT: [u8; 32] =
+    array::pad(*b"LEXE-REALM::Ed25519KeyPair");
+/// Prefixed to context-separated messages before signing, so that they can't
+/// be confused with the pre-hashed messages signed by
+/// [`KeyPair::sign_struct`].
+const CONTEXT_SIG_PREFIX: &[u8] = b"LEXE-REALM::Ed25519Context";
+
 /// An ed25519 secret key and public key.
 ///
 /// Applications should always sign with a *key pair* rather than passing in
@@ -133,6 +144,35 @@ pub enum Error {
 #[error("invalid signature")]
 pub struct InvalidSignature;
 
+/// The purposes for which child key pairs are derived from a root identity key
+/// pair with [`KeyPair::derive_for`].
+///
+/// Each purpose has its own derivation label, so that a child key leaked or
+/// misused for one purpose can't be used to impersonate the root identity or
+/// the other child keys.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum KeyPurpose {
+    /// Authenticating against Lexe APIs.
+    ApiAuth,
+    /// Signing outgoing webhooks.
+    WebhookSigning,
+    /// Signing arbitrary user messages.
+    MessageSigning,
+}
+
+impl KeyPurpose {
+    /// The derivation label for this purpose.
+    ///
+    /// NOTE: Changing these changes the derived keys!
+    pub const fn label(self) -> &'static [u8] {
+        match self {
+            Self::ApiAuth => b"api auth",
+            Self::WebhookSigning => b"webhook signing",
+            Self::MessageSigning => b"message signing",
+        }
+    }
+}
+
 /// `Signable` types are types that can be signed with
 /// [`ed25519::KeyPair::sign_struct`](KeyPair::sign_struct).
 ///
@@ -223,6 +263,17 @@ fn deserialize_signed_struct(
     Ok((signer, sig, ser_struct))
 }
 
+/// Pre-hashes a context-separated message. The context length is included so
+/// that `(context, msg)` pairs can't be shifted into one another.
+fn context_digest(context: &[u8], msg: &[u8]) -> sha256::Hash {
+    assert!(
+        context.len() <= MAX_CONTEXT_LEN,
+        "Signing context must be at most {MAX_CONTEXT_LEN} bytes",
+    );
+    let context_len = [context.len() as u8];
+    sha256::digest_many(&[CONTEXT_SIG_PREFIX, &context_len, context, msg])
+}
+
 fn verify_signed_struct_inner(
     signer: &PublicKey,
     sig: &Signature,
@@ -326,6 +377,36 @@ impl KeyPair {
         Self::from_seed(&seed)
     }
 
+    /// Derive a child `ed25519::KeyPair` from this key pair's secret key, using
+    /// HKDF-SHA256 with the given `label`. The same `label` always derives the
+    /// same child, and different labels derive unrelated children.
+    pub fn derive_child(&self, label: &[&[u8]]) -> Self {
+        struct SeedLength;
+
+        impl ring::hkdf::KeyType for SeedLength {
+            fn len(&self) -> usize {
+                SECRET_KEY_LEN
+            }
+        }
+
+        let salt = ring::hkdf::Salt::new(
+            ring::hkdf::HKDF_SHA256,
+            CHILD_KEY_HKDF_SALT.as_slice(),
+        );
+        let mut seed = [0u8; SECRET_KEY_LEN];
+        salt.extract(&self.seed)
+            .expand(label, SeedLength)
+            .expect("should not fail")
+            .fill(&mut seed)
+            .expect("should not fail");
+        Self::from_seed_owned(seed)
+    }
+
+    /// Derive the child `ed25519::KeyPair` for a specific [`KeyPurpose`].
+    pub fn derive_for(&self, purpose: KeyPurpose) -> Self {
+        self.derive_child(&[purpose.label()])
+    }
+
     /// Serialize the `ed25519::KeyPair` into a PKCS#8 document.
     pub fn serialize_pkcs8(&self) -> [u8; PKCS_LEN] {
         serialize_pkcs8(&self.seed, self.public_key().as_inner())
@@ -357,6 +438,19 @@ impl KeyPair {
         sig
     }
 
+    /// Sign a message with this `KeyPair`, binding the signature to `context`,
+    /// e.g. `b"LEXE-REALM::WebhookPayload"`. A signature made for one context
+    /// won't verify for any other context, which prevents a signature from
+    /// being reused across protocols.
+    ///
+    /// Verify with [`PublicKey::verify_with_context`].
+    ///
+    /// Panics if `context` is longer than [`MAX_CONTEXT_LEN`].
+    pub fn sign_with_context(&self, context: &[u8], msg: &[u8]) -> Signature {
+        let msg = context_digest(context, msg);
+        self.sign_raw(msg.as_slice())
+    }
+
     /// Canonically serialize and then sign a [`Signable`] struct `T` with this
     /// `ed25519::KeyPair`.
     ///
@@ -466,6 +560,19 @@ impl PublicKey {
         .map_err(|_| InvalidSignature)
     }
 
+    /// Verify a signature made with [`KeyPair::sign_with_context`].
+    ///
+    /// Panics if `context` is longer than [`MAX_CONTEXT_LEN`].
+    pub fn verify_with_context(
+        &self,
+        context: &[u8],
+        msg: &[u8],
+        sig: &Signature,
+    ) -> Result<(), InvalidSignature> {
+        let msg = context_digest(context, msg);
+        self.verify_raw(msg.as_slice(), sig)
+    }
+
     /// Like [`ed25519::verify_signed_struct`](verify_signed_struct) but only
     /// allows signatures produced by this `ed25519::PublicKey`.
     pub fn verify_self_signed_struct<'msg, T: Signable + Deserialize<'msg>>(
@@ -1019,4 +1126,71 @@ mod test {
             signer.verify_self_signed_struct::<Bar>(&sig).unwrap_err();
         });
     }
+
+    #[test]
+    fn test_sign_verify_with_context() {
+        proptest!(|(
+            key_pair in arb_key_pair(),
+            msg in any::<Vec<u8>>(),
+            context1 in any::<Vec<u8>>(),
+            context2 in any::<Vec<u8>>(),
+        )| {
+            prop_assume!(context1 != context2);
+            let pubkey = key_pair.public_key();
+
+            let sig = key_pair.sign_with_context(&context1, &msg);
+            pubkey.verify_with_context(&context1, &msg, &sig).unwrap();
+
+            // The signature is bound to its context.
+            pubkey.verify_with_context(&context2, &msg, &sig).unwrap_err();
+            pubkey.verify_raw(&msg, &sig).unwrap_err();
+        });
+    }
+
+    /// Check that derived child keys and context signatures are the same as a
+    /// snapshot from the same root key pair.
+    ///
+    /// ```
+    /// $ cargo test -p common derivation_snapshot_test -- --show-output
+    /// ```
+    #[test]
+    fn derivation_snapshot_test() {
+        let root = KeyPair::from_seed(&[0x42; 32]);
+        let pubkey = |key_pair: KeyPair| key_pair.public_key().to_string();
+
+        // Uncomment to regenerate
+        // for purpose in [
+        //     KeyPurpose::ApiAuth,
+        //     KeyPurpose::WebhookSigning,
+        //     KeyPurpose::MessageSigning,
+        // ] {
+        //     println!("{purpose:?}: {}", pubkey(root.derive_for(purpose)));
+        // }
+        // let sig = root.sign_with_context(b"lexe message", b"hello");
+        // println!("{sig}");
+
+        assert_eq!(
+            root.public_key().to_string(),
+            "2152f8d19b791d24453242e15f2eab6cb7cffa7b6a5ed30097960e069881db12",
+        );
+        assert_eq!(
+            pubkey(root.derive_for(KeyPurpose::ApiAuth)),
+            "a11aa6f18b7c7e345cfdab51fc03ae64ef693f37b5b9d07d4614b8969ad96dba",
+        );
+        assert_eq!(
+            pubkey(root.derive_for(KeyPurpose::WebhookSigning)),
+            "3f0d2b7a6eefa706d1cc66de7aeb12ba626b1901f8b96c81f28cbc688adb9077",
+        );
+        assert_eq!(
+            pubkey(root.derive_for(KeyPurpose::MessageSigning)),
+            "fa48ed7e50816a9e30747d6474a67cd48d3f3815873d6824f055e634ba35cfb4",
+        );
+
+        let sig = root.sign_with_context(b"lexe message", b"hello");
+        assert_eq!(
+            sig.to_string(),
+            "d4b4e283b953f2744e61acdb57660bbd5068d2f78886743a6a121d3a09dc399f\
+             8327bc707b5224b92afd8aaad2a2b0882447a28f2e9f033d76d5cd06469eb20c",
+        );
+    }
 }
@@ -0,0 +1,576 @@
+//! Layered configuration loading.
+//!
+//! Services read their configuration from up to three sources: an optional
+//! TOML file, env vars, and CLI args. [`ConfigLayers`] merges these into a
+//! single set of key-value pairs, with later layers overriding earlier ones.
+//! By convention, layers are added in order of increasing precedence:
+//!
+//! ```ignore
+//! let layers = ConfigLayers::new()
+//!     .with_toml_file(maybe_config_path)?
+//!     .with_env("LEXE_")
+//!     .with_args(std::env::args().skip(1))?;
+//! let (config, effective) = layers.load::<MyConfig>()?;
+//! info!("Effective config:\n{effective}");
+//! ```
+//!
+//! Keys are normalized to `snake_case`, so `--backend-url` (CLI),
+//! `LEXE_BACKEND_URL` (env), and `backend_url` (TOML) all set `backend_url`.
+//! Unknown keys are rejected, except for env vars, since the environment is
+//! shared with other programs that may use the same prefix.
+//!
+//! Typed configs implement [`Config`]. Parsing is done through a
+//! [`ConfigReader`] which records *every* missing or malformed field, so that
+//! the resulting [`ConfigErrors`] lists all problems at once rather than
+//! making the operator fix them one restart at a time.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fmt::{self, Display},
+    fs,
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, ensure, Context};
+
+/// Printed in place of secret values.
+const REDACTED: &str = "<redacted>";
+
+/// A typed configuration struct which can be loaded from [`ConfigLayers`].
+pub trait Config: Sized {
+    /// Read all fields from the [`ConfigReader`]. Implementations should read
+    /// every field *before* returning early on any missing one, so that all
+    /// errors are reported:
+    ///
+    /// ```ignore
+    /// fn read(r: &mut ConfigReader<'_>) -> Option<Self> {
+    ///     let backend_url = r.required("backend_url");
+    ///     let timeout_secs = r.optional_or("timeout_secs", 5);
+    ///     Some(Self { backend_url: backend_url?, timeout_secs: timeout_secs? })
+    /// }
+    /// ```
+    fn read(reader: &mut ConfigReader<'_>) -> Option<Self>;
+
+    /// Check invariants between fields, recording any violations with
+    /// [`ConfigErrors::push`]. Called only if [`Config::read`] succeeded.
+    fn validate(&self, _errors: &mut ConfigErrors) {}
+}
+
+/// Where a config value came from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// The default value given to [`ConfigReader::optional_or`].
+    Default,
+    /// A TOML config file.
+    File,
+    /// An env var.
+    Env,
+    /// A CLI arg.
+    Cli,
+}
+
+/// Key-value pairs merged from multiple config sources. See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigLayers {
+    values: BTreeMap<String, (String, ConfigSource)>,
+}
+
+/// Reads typed fields out of [`ConfigLayers`], accumulating errors.
+pub struct ConfigReader<'a> {
+    layers: &'a ConfigLayers,
+    /// Keys which were read, so we can detect unknown keys.
+    read_keys: BTreeSet<String>,
+    effective: EffectiveConfig,
+    errors: ConfigErrors,
+}
+
+/// The final value of every config field along with its [`ConfigSource`],
+/// with secrets redacted. Intended to be logged at startup.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EffectiveConfig {
+    /// key -> (display value, source)
+    entries: BTreeMap<String, (Option<String>, ConfigSource)>,
+}
+
+/// Every problem found while loading a [`Config`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigErrors {
+    /// (key, error message)
+    errors: Vec<(String, String)>,
+}
+
+// --- impl ConfigSource --- //
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Default => "default",
+            Self::File => "file",
+            Self::Env => "env",
+            Self::Cli => "cli",
+        };
+        f.write_str(s)
+    }
+}
+
+// --- impl ConfigLayers --- //
+
+impl ConfigLayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the values in the TOML file at `path`, if given.
+    pub fn with_toml_file(
+        self,
+        maybe_path: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let path = match maybe_path {
+            Some(path) => path,
+            None => return Ok(self),
+        };
+        let toml_str = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        self.with_toml_str(&toml_str)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Add the values in the given TOML document. Only top-level keys with
+    /// string, integer, float, or boolean values are supported.
+    pub fn with_toml_str(mut self, toml_str: &str) -> anyhow::Result<Self> {
+        let table = toml::from_str::<toml::Table>(toml_str)
+            .context("Couldn't parse TOML")?;
+        for (key, value) in table {
+            let value = match value {
+                toml::Value::String(s) => s,
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                _ => bail!("'{key}': only scalar values are supported"),
+            };
+            self.insert(&key, value, ConfigSource::File);
+        }
+        Ok(self)
+    }
+
+    /// Add all env vars starting with `prefix`, e.g. `LEXE_BACKEND_URL` sets
+    /// `backend_url` given the prefix `LEXE_`. Env vars which aren't valid
+    /// unicode are skipped.
+    pub fn with_env(self, prefix: &str) -> Self {
+        let vars = env::vars_os().filter_map(|(key, value)| {
+            Some((key.into_string().ok()?, value.into_string().ok()?))
+        });
+        self.with_env_vars(prefix, vars)
+    }
+
+    /// [`Self::with_env`] but with the given env vars, useful for testing.
+    pub fn with_env_vars(
+        mut self,
+        prefix: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        for (key, value) in vars {
+            if let Some(key) = key.strip_prefix(prefix) {
+                self.insert(key, value, ConfigSource::Env);
+            }
+        }
+        self
+    }
+
+    /// Add CLI args of the form `--key value` or `--key=value`. A `--flag`
+    /// which isn't followed by a value (i.e. the next arg is another flag or
+    /// there are no more args) is set to `true`.
+    pub fn with_args(
+        mut self,
+        args: impl IntoIterator<Item = String>,
+    ) -> anyhow::Result<Self> {
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            let flag = arg
+                .strip_prefix("--")
+                .ok_or_else(|| anyhow!("Unexpected positional arg '{arg}'"))?;
+            ensure!(!flag.is_empty(), "Empty flag '--'");
+
+            let (key, value) = match flag.split_once('=') {
+                Some((key, value)) => (key, value.to_owned()),
+                None => match args.next_if(|next| !next.starts_with("--")) {
+                    Some(value) => (flag, value),
+                    None => (flag, "true".to_owned()),
+                },
+            };
+            self.insert(key, value, ConfigSource::Cli);
+        }
+        Ok(self)
+    }
+
+    /// Read a typed [`Config`] from these layers, returning either the config
+    /// and its [`EffectiveConfig`], or every error found.
+    pub fn load<C: Config>(
+        &self,
+    ) -> Result<(C, EffectiveConfig), ConfigErrors> {
+        let mut reader = ConfigReader {
+            layers: self,
+            read_keys: BTreeSet::new(),
+            effective: EffectiveConfig::default(),
+            errors: ConfigErrors::default(),
+        };
+
+        let maybe_config = C::read(&mut reader);
+
+        // Unknown keys are most likely typos, which would otherwise silently
+        // leave the intended field at its default. Env vars are the exception:
+        // other programs and libraries may read vars with the same prefix.
+        for (key, (_, source)) in &self.values {
+            if *source == ConfigSource::Env {
+                continue;
+            }
+            if !reader.read_keys.contains(key) {
+                reader
+                    .errors
+                    .push(key, format!("Unknown key (from {source})"));
+            }
+        }
+
+        let mut errors = reader.errors;
+        match maybe_config {
+            Some(config) => {
+                config.validate(&mut errors);
+                if errors.is_empty() {
+                    Ok((config, reader.effective))
+                } else {
+                    Err(errors)
+                }
+            }
+            None => {
+                // `read` should've recorded why it failed
+                if errors.is_empty() {
+                    errors.push("<config>", "Failed to read config");
+                }
+                Err(errors)
+            }
+        }
+    }
+
+    /// Get the raw value for a key, if it was set by any layer.
+    pub fn get(&self, key: &str) -> Option<(&str, ConfigSource)> {
+        self.values
+            .get(key)
+            .map(|(value, source)| (value.as_str(), *source))
+    }
+
+    fn insert(&mut self, key: &str, value: String, source: ConfigSource) {
+        self.values.insert(normalize_key(key), (value, source));
+    }
+}
+
+// --- impl ConfigReader --- //
+
+impl<'a> ConfigReader<'a> {
+    /// Read and parse a field which must be set.
+    pub fn required<T>(&mut self, key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.read_required(key, false)
+    }
+
+    /// Read and parse a field which may be unset. Returns `Some(None)` if the
+    /// field is unset, or `None` if it is set but invalid.
+    pub fn optional<T>(&mut self, key: &str) -> Option<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.layers.get(key) {
+            Some(_) => self.read(key, false).map(Some),
+            None => {
+                self.read_keys.insert(key.to_owned());
+                Some(None)
+            }
+        }
+    }
+
+    /// Read and parse a field, using `default` if it is unset.
+    pub fn optional_or<T>(&mut self, key: &str, default: T) -> Option<T>
+    where
+        T: FromStr + Display,
+        T::Err: Display,
+    {
+        match self.layers.get(key) {
+            Some(_) => self.read(key, false),
+            None => {
+                self.read_keys.insert(key.to_owned());
+                self.effective.entries.insert(
+                    key.to_owned(),
+                    (Some(default.to_string()), ConfigSource::Default),
+                );
+                Some(default)
+            }
+        }
+    }
+
+    /// Like [`Self::required`], but the value is redacted when displayed.
+    pub fn secret<T>(&mut self, key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.read_required(key, true)
+    }
+
+    /// Record an error for `key`, e.g. if it parsed but is out of range.
+    pub fn error(&mut self, key: &str, msg: impl Display) {
+        self.errors.push(key, msg);
+    }
+
+    fn read_required<T>(&mut self, key: &str, is_secret: bool) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if self.layers.get(key).is_none() {
+            self.read_keys.insert(key.to_owned());
+            self.errors.push(key, "Missing required value");
+            return None;
+        }
+        self.read(key, is_secret)
+    }
+
+    fn read<T>(&mut self, key: &str, is_secret: bool) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        debug_assert_eq!(key, normalize_key(key), "Keys must be snake_case");
+        self.read_keys.insert(key.to_owned());
+
+        let (value_str, source) = self.layers.get(key)?;
+        match T::from_str(value_str) {
+            Ok(value) => {
+                let display = (!is_secret).then(|| value_str.to_owned());
+                self.effective
+                    .entries
+                    .insert(key.to_owned(), (display, source));
+                Some(value)
+            }
+            Err(e) => {
+                // Don't leak secrets into logs via the parse error.
+                let msg = if is_secret {
+                    format!("Invalid value (from {source})")
+                } else {
+                    format!("Invalid value '{value_str}' (from {source}): {e}")
+                };
+                self.errors.push(key, msg);
+                None
+            }
+        }
+    }
+}
+
+// --- impl EffectiveConfig --- //
+
+impl Display for EffectiveConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, (maybe_value, source)) in &self.entries {
+            let value = maybe_value.as_deref().unwrap_or(REDACTED);
+            writeln!(f, "{key} = {value} ({source})")?;
+        }
+        Ok(())
+    }
+}
+
+// --- impl ConfigErrors --- //
+
+impl ConfigErrors {
+    pub fn push(&mut self, key: &str, msg: impl Display) {
+        self.errors.push((key.to_owned(), msg.to_string()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+}
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let num_errors = self.errors.len();
+        write!(f, "Invalid config ({num_errors} errors):")?;
+        for (key, msg) in &self.errors {
+            write!(f, "\n- {key}: {msg}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// `--Backend-URL` and `BACKEND_URL` -> `backend_url`
+fn normalize_key(key: &str) -> String {
+    key.trim_start_matches('-')
+        .to_ascii_lowercase()
+        .replace('-', "_")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct TestConfig {
+        backend_url: String,
+        timeout_secs: u64,
+        allow_mock: bool,
+        api_key: String,
+        esplora_url: Option<String>,
+    }
+
+    impl Config for TestConfig {
+        fn read(r: &mut ConfigReader<'_>) -> Option<Self> {
+            let backend_url = r.required("backend_url");
+            let timeout_secs = r.optional_or("timeout_secs", 5);
+            let allow_mock = r.optional_or("allow_mock", false);
+            let api_key = r.secret("api_key");
+            let esplora_url = r.optional("esplora_url");
+            Some(Self {
+                backend_url: backend_url?,
+                timeout_secs: timeout_secs?,
+                allow_mock: allow_mock?,
+                api_key: api_key?,
+                esplora_url: esplora_url?,
+            })
+        }
+
+        fn validate(&self, errors: &mut ConfigErrors) {
+            if self.timeout_secs == 0 {
+                errors.push("timeout_secs", "Must be non-zero");
+            }
+        }
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn layer_precedence() {
+        let toml = r#"
+            backend_url = "https://file.lexe.app"
+            timeout_secs = 10
+            api_key = "file-secret"
+        "#;
+        let env = vars(&[
+            ("LEXE_BACKEND_URL", "https://env.lexe.app"),
+            ("LEXE_TIMEOUT_SECS", "20"),
+            ("OTHER_TIMEOUT_SECS", "999"),
+            // Unknown env vars are ignored
+            ("LEXE_SOME_OTHER_VAR", "1"),
+        ]);
+        let args = args(&["--timeout-secs", "30", "--allow-mock"]);
+
+        let layers = ConfigLayers::new()
+            .with_toml_str(toml)
+            .unwrap()
+            .with_env_vars("LEXE_", env)
+            .with_args(args)
+            .unwrap();
+        let (config, effective) = layers.load::<TestConfig>().unwrap();
+
+        assert_eq!(
+            config,
+            TestConfig {
+                backend_url: "https://env.lexe.app".to_owned(),
+                timeout_secs: 30,
+                allow_mock: true,
+                api_key: "file-secret".to_owned(),
+                esplora_url: None,
+            }
+        );
+
+        let effective = effective.to_string();
+        assert!(!effective.contains("file-secret"));
+        assert!(effective.contains("api_key = <redacted> (file)"));
+        assert!(effective.contains("backend_url = https://env.lexe.app (env)"));
+        assert!(effective.contains("timeout_secs = 30 (cli)"));
+    }
+
+    #[test]
+    fn reports_all_errors() {
+        let args = args(&[
+            "--timeout-secs=soon",
+            "--allow-mock",
+            "maybe",
+            "--backend-urll",
+            "https://typo.lexe.app",
+        ]);
+        let layers = ConfigLayers::new().with_args(args).unwrap();
+        let errors = layers.load::<TestConfig>().unwrap_err();
+
+        let keys = errors
+            .errors
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<BTreeSet<_>>();
+        let expected = BTreeSet::from_iter([
+            "backend_url",
+            "timeout_secs",
+            "allow_mock",
+            "api_key",
+            "backend_urll",
+        ]);
+        assert_eq!(keys, expected, "{errors}");
+    }
+
+    #[test]
+    fn validate_runs_after_read() {
+        let args = args(&[
+            "--backend-url=https://lexe.app",
+            "--api-key=hunter2",
+            "--timeout-secs=0",
+        ]);
+        let layers = ConfigLayers::new().with_args(args).unwrap();
+        let errors = layers.load::<TestConfig>().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors
+            .to_string()
+            .contains("timeout_secs: Must be non-zero"));
+    }
+
+    #[test]
+    fn secrets_not_leaked_in_errors() {
+        let layers = ConfigLayers::new()
+            .with_args(args(&["--api-key=hunter2"]))
+            .unwrap();
+        let mut reader = ConfigReader {
+            layers: &layers,
+            read_keys: BTreeSet::new(),
+            effective: EffectiveConfig::default(),
+            errors: ConfigErrors::default(),
+        };
+        let _: Option<u64> = reader.secret("api_key");
+        assert_eq!(reader.errors.len(), 1);
+        assert!(!reader.errors.to_string().contains("hunter2"));
+    }
+
+    #[test]
+    fn bad_inputs() {
+        assert!(ConfigLayers::new()
+            .with_args(args(&["positional"]))
+            .is_err());
+        assert!(ConfigLayers::new().with_args(args(&["--"])).is_err());
+        assert!(ConfigLayers::new().with_toml_str("a = [1, 2]").is_err());
+        assert!(ConfigLayers::new().with_toml_str("not toml").is_err());
+    }
+}
@@ -10,6 +10,9 @@ use strum::VariantArray;
 
 use crate::{cli::Network, Apply};
 
+/// Layered configuration loading from CLI args, env vars, and TOML files.
+pub mod config;
+
 /// Represents a validated `DEPLOY_ENVIRONMENT` configuration.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 #[derive(SerializeDisplay, DeserializeFromStr, VariantArray)]
@@ -58,12 +61,15 @@ impl DeployEnv {
     pub fn validate_network(&self, network: Network) -> anyhow::Result<()> {
         match self {
             Self::Dev => ensure!(
-                matches!(network, Network::REGTEST | Network::TESTNET),
-                "Dev environment can only be regtest or testnet!"
+                matches!(
+                    network,
+                    Network::REGTEST | Network::TESTNET | Network::SIGNET
+                ),
+                "Dev environment can only be regtest, testnet, or signet!"
             ),
             Self::Staging => ensure!(
-                matches!(network, Network::TESTNET),
-                "Staging environment can only be testnet!"
+                matches!(network, Network::TESTNET | Network::SIGNET),
+                "Staging environment can only be testnet or signet!"
             ),
             Self::Prod => ensure!(
                 matches!(network, Network::MAINNET),
@@ -93,7 +99,9 @@ impl DeployEnv {
         proptest::prop_oneof![
             Just((Self::Dev, Network::REGTEST)),
             Just((Self::Dev, Network::TESTNET)),
+            Just((Self::Dev, Network::SIGNET)),
             Just((Self::Staging, Network::TESTNET)),
+            Just((Self::Staging, Network::SIGNET)),
             Just((Self::Prod, Network::MAINNET)),
         ]
     }
@@ -1,6 +1,9 @@
 #![allow(clippy::wrong_self_convention)]
 
-use std::cmp;
+use std::{
+    cmp,
+    fmt::{self, Display},
+};
 
 /// [`Iterator`] extension trait
 pub trait IteratorExt: Iterator {
@@ -64,5 +67,68 @@ pub trait IteratorExt: Iterator {
     {
         self.map(f).is_strict_total_order()
     }
+
+    /// Collects an iterator of [`Result`]s into a [`Vec`], returning the first
+    /// error if there is one. Saves spelling out the full type in
+    /// `.collect::<anyhow::Result<Vec<T>>>()`.
+    ///
+    /// ### Examples
+    ///
+    /// ```rust
+    /// use common::iter::IteratorExt;
+    ///
+    /// let oks = [Ok(1), Ok(2)].into_iter().try_collect_vec::<_, ()>();
+    /// assert_eq!(oks, Ok(vec![1, 2]));
+    /// let errs = [Ok(1), Err("a"), Err("b")].into_iter().try_collect_vec();
+    /// assert_eq!(errs, Err("a"));
+    /// ```
+    fn try_collect_vec<T, E>(self) -> Result<Vec<T>, E>
+    where
+        Self: Sized + Iterator<Item = Result<T, E>>,
+    {
+        self.collect()
+    }
+
+    /// Returns a [`Display`] adapter which displays each item separated by
+    /// `sep`, without allocating an intermediate [`Vec`] or [`String`].
+    ///
+    /// ### Examples
+    ///
+    /// ```rust
+    /// use common::iter::IteratorExt;
+    ///
+    /// let msgs = ["a", "b", "c"];
+    /// assert_eq!(msgs.iter().join_display("; ").to_string(), "a; b; c");
+    /// assert_eq!([0u8; 0].iter().join_display(", ").to_string(), "");
+    /// ```
+    fn join_display(self, sep: &str) -> JoinDisplay<'_, Self>
+    where
+        Self: Sized + Clone,
+        Self::Item: Display,
+    {
+        JoinDisplay { iter: self, sep }
+    }
 }
 impl<I: Iterator> IteratorExt for I {}
+
+/// See [`IteratorExt::join_display`].
+pub struct JoinDisplay<'a, I> {
+    iter: I,
+    sep: &'a str,
+}
+
+impl<I> Display for JoinDisplay<'_, I>
+where
+    I: Iterator + Clone,
+    I::Item: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, item) in self.iter.clone().enumerate() {
+            if i != 0 {
+                f.write_str(self.sep)?;
+            }
+            Display::fmt(&item, f)?;
+        }
+        Ok(())
+    }
+}
@@ -34,7 +34,7 @@ pub mod dotenv;
 pub mod ed25519;
 /// SGX types.
 pub mod enclave;
-/// `DeployEnv`.
+/// `DeployEnv` and layered configuration loading.
 pub mod env;
 /// Hex utils
 pub mod hex;
@@ -46,6 +46,8 @@ pub mod hexstr_or_bytes_opt;
 pub mod iter;
 /// Bitcoin / Lightning Lexe newtypes which can't go in lexe-ln
 pub mod ln;
+/// An `mpsc` channel which records queue depth and latency metrics.
+pub mod metered;
 /// Networking utilities.
 pub mod net;
 /// A channel for sending deduplicated notifications with no data attached.
@@ -68,6 +70,8 @@ pub mod test_event;
 pub mod time;
 /// TLS certs and configurations.
 pub mod tls;
+/// API revision negotiation.
+pub mod version;
 
 /// Feature-gated test utilities that can be shared across crate boundaries.
 #[cfg(any(test, feature = "test-utils"))]
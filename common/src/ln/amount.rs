@@ -43,6 +43,13 @@
 //! println!("{btc:.8} BTC");
 //! ```
 //!
+//! ### Fiat conversions
+//!
+//! Use [`FiatAmount::from_amount`] to get the fiat value of an [`Amount`] and
+//! [`Amount::try_from_fiat`] for the reverse conversion. Both use banker's
+//! rounding (round half to even) on the exact [`Decimal`] value rather than
+//! floating point math, so conversions are reproducible across platforms.
+//!
 //! [`Display`]: std::fmt::Display
 //! [`FromStr`]: std::str::FromStr
 //! [`Amount`]: crate::ln::amount::Amount
@@ -59,15 +66,21 @@
 
 use std::{
     fmt::{self, Display},
+    num::NonZeroU16,
     ops::{Add, Div, Mul, Sub},
     str::FromStr,
 };
 
 use anyhow::format_err;
-use rust_decimal::{prelude::ToPrimitive, Decimal};
+use rust_decimal::{
+    prelude::{FromPrimitive, ToPrimitive},
+    Decimal, RoundingStrategy,
+};
 use rust_decimal_macros::dec;
 use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::api::fiat_rates::{FiatBtcPrice, FiatCode};
+
 /// Errors that can occur when attempting to construct an [`Amount`].
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -75,6 +88,8 @@ pub enum Error {
     Negative,
     #[error("Amount is too large")]
     TooLarge,
+    #[error("Fiat exchange rate is not a positive, finite number")]
+    InvalidFiatRate,
 }
 
 /// A Bitcoin amount, internally represented as a satoshi [`Decimal`], which
@@ -214,6 +229,52 @@ impl Amount {
         Self::try_from_inner(inner).ok()
     }
 
+    /// Adds two [`Amount`]s, saturating at [`Amount::MAX`].
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        self.checked_add(rhs).unwrap_or(Self::MAX)
+    }
+
+    /// Subtracts `rhs` from `self`, saturating at [`Amount::ZERO`].
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        self.checked_sub(rhs).unwrap_or(Self::ZERO)
+    }
+
+    /// Splits this [`Amount`] into `n` parts which differ by at most one msat
+    /// and sum to exactly the original amount. Any sub-msat precision is
+    /// dropped, and the remainder msats go to the first parts.
+    ///
+    /// `n` is a [`NonZeroU16`] to bound the size of the returned [`Vec`].
+    pub fn split_evenly(self, n: NonZeroU16) -> Vec<Self> {
+        let msat = self.msat();
+        let n = u64::from(n.get());
+        let base = msat / n;
+        let remainder = msat % n;
+
+        (0..n)
+            .map(|i| {
+                let extra = u64::from(i < remainder);
+                Self::from_msat(base + extra)
+            })
+            .collect()
+    }
+
+    // --- Fiat conversions --- //
+
+    /// Construct an [`Amount`] from a fiat value given the BTC price in that
+    /// fiat currency, using banker's rounding to the nearest msat.
+    pub fn try_from_fiat(
+        fiat_value: Decimal,
+        price: FiatBtcPrice,
+    ) -> Result<Self, Error> {
+        let price = fiat_btc_price_to_decimal(price)?;
+        let sats = fiat_value
+            .checked_div(price)
+            .and_then(|btc| btc.checked_mul(dec!(1_0000_0000)))
+            .ok_or(Error::TooLarge)?
+            .round_dp_with_strategy(3, RoundingStrategy::MidpointNearestEven);
+        Self::try_from_inner(sats)
+    }
+
     /// Checks all internal invariants, returning [`Self`] if all were OK.
     #[inline]
     fn try_from_inner(inner: Decimal) -> Result<Self, Error> {
@@ -234,10 +295,7 @@ impl<'de> Deserialize<'de> for Amount {
     {
         let inner: Decimal = Deserialize::deserialize(deserializer)?;
 
-        Self::try_from_inner(inner).map_err(|e| match e {
-            Error::Negative => serde::de::Error::custom("Amount was negative"),
-            Error::TooLarge => serde::de::Error::custom("Amount was too large"),
-        })
+        Self::try_from_inner(inner).map_err(serde::de::Error::custom)
     }
 }
 
@@ -259,6 +317,59 @@ impl FromStr for Amount {
     }
 }
 
+// --- FiatAmount --- //
+
+/// An [`Amount`] paired with its value in some fiat currency.
+///
+/// The fiat value is computed from the exact [`Decimal`] BTC value and rounded
+/// to [`FiatAmount::DECIMAL_PLACES`] using banker's rounding.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FiatAmount {
+    /// The bitcoin amount.
+    pub amount: Amount,
+    /// The fiat currency, e.g. "USD".
+    pub fiat_code: FiatCode,
+    /// The value of `amount` in `fiat_code`, e.g. `12.34` (dollars).
+    pub fiat_value: Decimal,
+}
+
+impl FiatAmount {
+    /// The number of decimal places we round fiat values to.
+    pub const DECIMAL_PLACES: u32 = 2;
+
+    /// Compute the fiat value of `amount` given the BTC price in `fiat_code`.
+    pub fn from_amount(
+        amount: Amount,
+        fiat_code: FiatCode,
+        price: FiatBtcPrice,
+    ) -> Result<Self, Error> {
+        let price = fiat_btc_price_to_decimal(price)?;
+        let fiat_value = amount
+            .btc()
+            .checked_mul(price)
+            .ok_or(Error::TooLarge)?
+            .round_dp_with_strategy(
+                Self::DECIMAL_PLACES,
+                RoundingStrategy::MidpointNearestEven,
+            );
+        Ok(Self {
+            amount,
+            fiat_code,
+            fiat_value,
+        })
+    }
+}
+
+/// Convert a [`FiatBtcPrice`] into a [`Decimal`], ensuring it's positive and
+/// finite.
+fn fiat_btc_price_to_decimal(price: FiatBtcPrice) -> Result<Decimal, Error> {
+    let price = Decimal::from_f64(price.0).ok_or(Error::InvalidFiatRate)?;
+    if price.is_sign_negative() || price.is_zero() {
+        return Err(Error::InvalidFiatRate);
+    }
+    Ok(price)
+}
+
 // --- bitcoin::Amount conversions --- //
 // `bitcoin::Amount` is represented as u64 *satoshis*, so a conversion *to*
 // their type is infallible, while a conversion *from* their type is not.
@@ -482,6 +593,81 @@ mod test {
         })
     }
 
+    /// Test the saturating ops and `split_evenly`.
+    #[test]
+    fn amount_saturating_and_split() {
+        assert_eq!(
+            Amount::MAX.saturating_add(Amount::from_msat(1)),
+            Amount::MAX
+        );
+        assert_eq!(
+            Amount::ZERO.saturating_sub(Amount::from_msat(1)),
+            Amount::ZERO
+        );
+
+        let parts =
+            Amount::from_msat(10).split_evenly(NonZeroU16::new(3).unwrap());
+        let parts_msat = parts.iter().map(Amount::msat).collect::<Vec<_>>();
+        assert_eq!(parts_msat, vec![4, 3, 3]);
+
+        proptest!(|(amount in any::<Amount>(), n in 1_u16..=64)| {
+            let parts = amount.split_evenly(NonZeroU16::new(n).unwrap());
+            prop_assert_eq!(parts.len(), usize::from(n));
+
+            let sum = parts
+                .iter()
+                .fold(Amount::ZERO, |acc, part| acc + *part);
+            prop_assert_eq!(sum.msat(), amount.msat());
+
+            let max = parts.iter().max().unwrap().msat();
+            let min = parts.iter().min().unwrap().msat();
+            prop_assert!(max - min <= 1);
+        })
+    }
+
+    /// Test fiat conversions and banker's rounding.
+    #[test]
+    fn amount_fiat_conversions() {
+        let usd = || FiatCode("USD".to_owned());
+        let price = FiatBtcPrice(50_000.0);
+
+        // 0.0001 BTC @ $50,000 => $5.00
+        let amount = Amount::from_sats_u32(10_000);
+        let fiat = FiatAmount::from_amount(amount, usd(), price).unwrap();
+        assert_eq!(fiat.fiat_value, dec!(5.00));
+        assert_eq!(
+            Amount::try_from_fiat(fiat.fiat_value, price).unwrap(),
+            amount
+        );
+
+        // 1 sat @ $50,000 => $0.0005 => $0.00
+        let amount = Amount::from_sats_u32(1);
+        let fiat = FiatAmount::from_amount(amount, usd(), price).unwrap();
+        assert_eq!(fiat.fiat_value, dec!(0.00));
+
+        // Midpoints round half to even:
+        // 30 sats @ $50,000 => $0.015 => $0.02
+        // 50 sats @ $50,000 => $0.025 => $0.02
+        // 70 sats @ $50,000 => $0.035 => $0.04
+        for (sats, expected) in
+            [(30, dec!(0.02)), (50, dec!(0.02)), (70, dec!(0.04))]
+        {
+            let amount = Amount::from_sats_u32(sats);
+            let fiat = FiatAmount::from_amount(amount, usd(), price).unwrap();
+            assert_eq!(fiat.fiat_value, expected);
+        }
+
+        // Invalid rates are rejected
+        for bad_rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let bad_price = FiatBtcPrice(bad_rate);
+            assert!(FiatAmount::from_amount(amount, usd(), bad_price).is_err());
+            assert!(Amount::try_from_fiat(dec!(1), bad_price).is_err());
+        }
+
+        // Negative fiat values are rejected
+        assert!(Amount::try_from_fiat(dec!(-1), price).is_err());
+    }
+
     /// Test rounding to the nearest satoshi.
     #[test]
     fn amount_round_sat_btc() {
@@ -0,0 +1,251 @@
+//! The user's limits on the routing fees paid for outbound Lightning payments.
+//!
+//! A [`FeePolicy`] has a default [`FeeLimit`] plus optional tiers which apply
+//! different limits to smaller payments. This lets users say e.g. "never pay
+//! more than 1%, except allow up to 10 sats for payments under 1000 sats",
+//! since a percentage limit alone would make small payments unroutable.
+
+use std::cmp;
+
+use anyhow::ensure;
+#[cfg(any(test, feature = "test-utils"))]
+use proptest_derive::Arbitrary;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::ln::amount::Amount;
+
+/// The max # of tiers in a [`FeePolicy`].
+pub const MAX_FEE_POLICY_TIERS: usize = 8;
+
+/// Parts per million; [`FeeLimit::max_fee_ppm`] can't be more than 100%.
+const ONE_MILLION: u32 = 1_000_000;
+
+/// Limits on the routing fees paid for outbound Lightning payments.
+///
+/// The default policy has no limits of its own; nodes fall back to the
+/// remote config's `max_routing_fee_ppm`, see
+/// [`FeePolicy::or_default_max_fee_ppm`].
+#[cfg_attr(any(test, feature = "test-utils"), derive(Arbitrary))]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeePolicy {
+    /// The limit for payments larger than every tier.
+    pub default: FeeLimit,
+    /// Limits for smaller payments. A payment uses the tier with the smallest
+    /// `up_to` which is at least the payment amount.
+    pub tiers: Vec<FeeTier>,
+}
+
+/// A [`FeeLimit`] for payments of at most `up_to`.
+#[cfg_attr(any(test, feature = "test-utils"), derive(Arbitrary))]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub up_to: Amount,
+    pub limit: FeeLimit,
+}
+
+/// A limit on the fees paid for a payment. If both fields are set, the fees
+/// must satisfy both.
+#[cfg_attr(any(test, feature = "test-utils"), derive(Arbitrary))]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeLimit {
+    /// The max fees for a payment, regardless of the payment amount.
+    pub max_fee: Option<Amount>,
+    /// The max fees for a payment as parts per million of the payment amount,
+    /// e.g. 10,000 for 1%.
+    pub max_fee_ppm: Option<u32>,
+}
+
+/// Returned when the fees for a payment exceed the user's [`FeePolicy`].
+#[cfg_attr(any(test, feature = "test-utils"), derive(Arbitrary))]
+#[derive(Clone, Debug, Error, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[error(
+    "Fees of {fees} sats exceed your fee policy, which allows at most \
+     {max_fee} sats for a {amount} sat payment"
+)]
+pub struct FeeExceedsPolicy {
+    /// The payment amount, excluding fees.
+    pub amount: Amount,
+    /// The fees we would have paid.
+    pub fees: Amount,
+    /// The max fees allowed by the policy for this payment amount.
+    pub max_fee: Amount,
+}
+
+impl FeePolicy {
+    /// Returns the [`FeeLimit`] which applies to a payment of `amount`.
+    pub fn limit_for(&self, amount: Amount) -> &FeeLimit {
+        self.tiers
+            .iter()
+            .filter(|tier| amount <= tier.up_to)
+            .min_by_key(|tier| tier.up_to)
+            .map(|tier| &tier.limit)
+            .unwrap_or(&self.default)
+    }
+
+    /// Checks that paying `fees` for a payment of `amount` is allowed.
+    pub fn check(
+        &self,
+        amount: Amount,
+        fees: Amount,
+    ) -> Result<(), FeeExceedsPolicy> {
+        match self.limit_for(amount).max_fee_for(amount) {
+            Some(max_fee) if fees > max_fee => Err(FeeExceedsPolicy {
+                amount,
+                fees,
+                max_fee,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Limits the fees to `max_fee_ppm` by default if the user hasn't set a
+    /// default limit of their own. Tiers are kept as is.
+    pub fn or_default_max_fee_ppm(mut self, max_fee_ppm: u32) -> Self {
+        let default = &mut self.default;
+        if default.max_fee.is_none() && default.max_fee_ppm.is_none() {
+            default.max_fee_ppm = Some(cmp::min(max_fee_ppm, ONE_MILLION));
+        }
+        self
+    }
+
+    /// Checks that the policy is well-formed.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.tiers.len() <= MAX_FEE_POLICY_TIERS,
+            "Fee policy can have at most {MAX_FEE_POLICY_TIERS} tiers",
+        );
+        self.default.validate()?;
+        for (i, tier) in self.tiers.iter().enumerate() {
+            tier.limit.validate()?;
+            let duplicate =
+                self.tiers[..i].iter().any(|t| t.up_to == tier.up_to);
+            ensure!(!duplicate, "Duplicate fee tier for {} sats", tier.up_to);
+        }
+        Ok(())
+    }
+}
+
+impl FeeLimit {
+    /// The max fees allowed for a payment of `amount`, if limited.
+    pub fn max_fee_for(&self, amount: Amount) -> Option<Amount> {
+        let ppm_max_fee = self.max_fee_ppm.map(|ppm| {
+            let msat = u128::from(amount.msat()) * u128::from(ppm)
+                / u128::from(ONE_MILLION);
+            // ppm <= 1M, so this is at most `amount`.
+            Amount::from_msat(u64::try_from(msat).unwrap_or(u64::MAX))
+        });
+        match (self.max_fee, ppm_max_fee) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(ppm) = self.max_fee_ppm {
+            ensure!(ppm <= ONE_MILLION, "Max fee can't be more than 100%");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::{arbitrary::any, prop_assert, proptest};
+
+    use super::*;
+    use crate::test_utils::roundtrip;
+
+    fn sats(sats: u32) -> Amount {
+        Amount::from_sats_u32(sats)
+    }
+
+    #[test]
+    fn fee_policy_json_roundtrip() {
+        roundtrip::json_value_roundtrip_proptest::<FeePolicy>();
+    }
+
+    #[test]
+    fn tiered_limits() {
+        // Max 1% or 1000 sats, but allow up to 10 sats below 1000 sats.
+        let policy = FeePolicy {
+            default: FeeLimit {
+                max_fee: Some(sats(1000)),
+                max_fee_ppm: Some(10_000),
+            },
+            tiers: vec![FeeTier {
+                up_to: sats(1000),
+                limit: FeeLimit {
+                    max_fee: Some(sats(10)),
+                    max_fee_ppm: None,
+                },
+            }],
+        };
+        policy.validate().unwrap();
+
+        // Small payments use the tier.
+        policy.check(sats(500), sats(10)).unwrap();
+        policy.check(sats(1000), sats(10)).unwrap();
+        let err = policy.check(sats(500), sats(11)).unwrap_err();
+        assert_eq!(err.max_fee, sats(10));
+
+        // Larger payments use the default: 1%, capped at 1000 sats.
+        policy.check(sats(10_000), sats(100)).unwrap();
+        let err = policy.check(sats(10_000), sats(101)).unwrap_err();
+        assert_eq!(err.max_fee, sats(100));
+        let err = policy.check(sats(1_000_000), sats(1001)).unwrap_err();
+        assert_eq!(err.max_fee, sats(1000));
+
+        // No limits by default.
+        FeePolicy::default()
+            .check(sats(1), sats(1_000_000))
+            .unwrap();
+    }
+
+    #[test]
+    fn default_max_fee_ppm() {
+        // Users without a default limit get the given one.
+        let policy = FeePolicy::default().or_default_max_fee_ppm(10_000);
+        policy.check(sats(10_000), sats(100)).unwrap();
+        policy.check(sats(10_000), sats(101)).unwrap_err();
+
+        // The user's own default limit takes precedence.
+        let policy = FeePolicy {
+            default: FeeLimit {
+                max_fee: Some(sats(500)),
+                max_fee_ppm: None,
+            },
+            tiers: Vec::new(),
+        };
+        let policy = policy.or_default_max_fee_ppm(10_000);
+        policy.check(sats(10_000), sats(500)).unwrap();
+    }
+
+    #[test]
+    fn validate_policy() {
+        let mut policy = FeePolicy::default();
+        policy.default.max_fee_ppm = Some(ONE_MILLION + 1);
+        policy.validate().unwrap_err();
+
+        let tier = FeeTier {
+            up_to: sats(1000),
+            limit: FeeLimit::default(),
+        };
+        let policy = FeePolicy {
+            default: FeeLimit::default(),
+            tiers: vec![tier.clone(), tier],
+        };
+        policy.validate().unwrap_err();
+    }
+
+    #[test]
+    fn ppm_limit_never_exceeds_amount() {
+        proptest!(|(amount in any::<Amount>(), ppm in 0..=ONE_MILLION)| {
+            let limit = FeeLimit {
+                max_fee: None,
+                max_fee_ppm: Some(ppm),
+            };
+            prop_assert!(limit.max_fee_for(amount).unwrap() <= amount);
+        });
+    }
+}
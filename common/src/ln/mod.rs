@@ -22,6 +22,8 @@ pub mod amount_or_all;
 pub mod balance;
 /// Channel outpoint, details, counterparty
 pub mod channel;
+/// `FeePolicy`, the user's limits on outbound payment fees.
+pub mod fee_policy;
 /// Bitcoin hash types, such as `LxTxid`.
 pub mod hashes;
 /// `LxInvoice`, a wrapper around LDK's BOLT11 invoice type.
@@ -102,6 +102,11 @@ pub struct BasicPayment {
     pub note: Option<String>,
 
     pub finalized_at: Option<TimestampMs>,
+
+    /// (Onchain receives only) The expected deposit which this payment paid,
+    /// if any.
+    #[serde(default)]
+    pub deposit: Option<DepositMatch>,
 }
 
 /// An encrypted payment, as represented in the DB.
@@ -149,6 +154,39 @@ pub enum PaymentStatus {
     Failed,
 }
 
+/// Links an onchain receive to the deposit it paid, if it paid an address
+/// which was issued for an expected amount (see `get_address_for`). This lets
+/// merchants match deposits to orders without any fuzzy matching.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "test-utils"), derive(Arbitrary))]
+pub struct DepositMatch {
+    /// The amount we asked for when the address was issued.
+    pub expected: Amount,
+    /// The amount which the tx actually paid to the address. This may differ
+    /// from the total amount of the onchain receive if the tx also paid other
+    /// addresses in our wallet.
+    pub received: Amount,
+    /// The label given when the address was issued, e.g. an order id.
+    #[cfg_attr(
+        any(test, feature = "test-utils"),
+        proptest(strategy = "arbitrary::any_option_string()")
+    )]
+    pub label: Option<String>,
+    /// Whether the deposit was paid in full.
+    pub status: DepositStatus,
+}
+
+/// Whether a [`DepositMatch`] received exactly the expected amount.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "test-utils"), derive(Arbitrary))]
+#[cfg_attr(test, derive(strum::VariantArray))]
+#[serde(rename_all = "snake_case")]
+pub enum DepositStatus {
+    Exact,
+    Overpaid,
+    Underpaid,
+}
+
 // --- Lexe newtypes --- //
 
 /// A payment identifier which (1) retains uniqueness per payment and (2) is
@@ -302,6 +340,28 @@ impl PartialOrd for BasicPayment {
     }
 }
 
+// --- impl DepositMatch --- //
+
+impl DepositMatch {
+    pub fn new(
+        expected: Amount,
+        received: Amount,
+        label: Option<String>,
+    ) -> Self {
+        let status = match received.cmp(&expected) {
+            Ordering::Equal => DepositStatus::Exact,
+            Ordering::Greater => DepositStatus::Overpaid,
+            Ordering::Less => DepositStatus::Underpaid,
+        };
+        Self {
+            expected,
+            received,
+            label,
+            status,
+        }
+    }
+}
+
 // --- impl PaymentIndex --- //
 
 impl PaymentIndex {
@@ -694,7 +754,10 @@ impl PartialOrd for LxPaymentId {
 
 #[cfg(test)]
 mod test {
-    use proptest::{arbitrary::any, prop_assert_eq, proptest};
+    use proptest::{
+        arbitrary::any, collection::vec, prop_assert_eq, proptest,
+        test_runner::Config,
+    };
 
     use super::*;
     use crate::test_utils::roundtrip;
@@ -711,12 +774,35 @@ mod test {
         );
         let expected_ser = r#"["onchain","invoice","spontaneous"]"#;
         roundtrip::json_unit_enum_backwards_compat::<PaymentKind>(expected_ser);
+        let expected_ser = r#"["exact","overpaid","underpaid"]"#;
+        roundtrip::json_unit_enum_backwards_compat::<DepositStatus>(
+            expected_ser,
+        );
 
         roundtrip::fromstr_display_roundtrip_proptest::<PaymentDirection>();
         roundtrip::fromstr_display_roundtrip_proptest::<PaymentStatus>();
         roundtrip::fromstr_display_roundtrip_proptest::<PaymentKind>();
     }
 
+    #[test]
+    fn deposit_match_status() {
+        let expected = Amount::from_sats_u32(10_000);
+        let status = |received| {
+            DepositMatch::new(expected, Amount::from_sats_u32(received), None)
+                .status
+        };
+        assert_eq!(status(10_000), DepositStatus::Exact);
+        assert_eq!(status(10_001), DepositStatus::Overpaid);
+        assert_eq!(status(9_999), DepositStatus::Underpaid);
+    }
+
+    #[test]
+    fn basic_payment_body_format_roundtrip() {
+        let config = Config::with_cases(16);
+        let strategy = vec(any::<BasicPayment>(), 0..8);
+        roundtrip::body_format_custom(strategy, config);
+    }
+
     #[test]
     fn newtype_serde_roundtrip() {
         roundtrip::json_string_roundtrip_proptest::<PaymentIndex>();
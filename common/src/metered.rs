@@ -0,0 +1,383 @@
+//! # `metered` channel
+//!
+//! A thin wrapper around [`tokio::sync::mpsc`] which records how backed up the
+//! channel is, so that we can see a queue building up before something
+//! downstream times out:
+//!
+//! - the current and maximum queue depth,
+//! - how long senders were blocked waiting for capacity,
+//! - how long the oldest message still in the queue has been waiting, and how
+//!   long the slowest received message waited.
+//!
+//! [`MeteredSender::send`] can optionally fail with [`SendError::Timeout`] if
+//! the channel stays full past a deadline, rather than blocking forever.
+//!
+//! Get a [`QueueMetrics`] snapshot with [`MeteredSender::metrics`] or
+//! [`MeteredReceiver::metrics`].
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+pub use tokio::sync::mpsc::error::{TryRecvError, TrySendError};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::warn;
+
+/// Create a new bounded `metered` channel. `name` is used in logs.
+pub fn channel<T>(
+    name: &'static str,
+    buffer: usize,
+) -> (MeteredSender<T>, MeteredReceiver<T>) {
+    let (tx, rx) = mpsc::channel(buffer);
+    let meter = Arc::new(Meter::new(name));
+    let tx = MeteredSender {
+        tx,
+        meter: meter.clone(),
+        deadline: None,
+    };
+    let rx = MeteredReceiver { rx, meter };
+    (tx, rx)
+}
+
+/// `metered` sender, analogous to [`mpsc::Sender<T>`].
+pub struct MeteredSender<T> {
+    tx: mpsc::Sender<Envelope<T>>,
+    meter: Arc<Meter>,
+    deadline: Option<Duration>,
+}
+
+/// `metered` receiver, analogous to [`mpsc::Receiver<T>`].
+pub struct MeteredReceiver<T> {
+    rx: mpsc::Receiver<Envelope<T>>,
+    meter: Arc<Meter>,
+}
+
+/// Errors returned by [`MeteredSender::send`]. Both variants return the value
+/// which couldn't be sent.
+#[derive(Debug, Error)]
+pub enum SendError<T> {
+    #[error("Channel closed")]
+    Closed(T),
+    #[error("Channel was still full after the send deadline")]
+    Timeout(T),
+}
+
+/// A snapshot of the metrics of a `metered` channel.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueMetrics {
+    /// The # of messages currently in the queue.
+    pub depth: usize,
+    /// The maximum # of messages that were ever in the queue at once.
+    pub max_depth: usize,
+    /// The # of messages sent since the channel was created.
+    pub sent: u64,
+    /// The total time senders spent blocked waiting for capacity.
+    pub send_blocked_ms: u64,
+    /// The longest time a single send was blocked waiting for capacity.
+    pub max_send_blocked_ms: u64,
+    /// The # of sends which failed because the channel was full, including
+    /// sends which hit their deadline.
+    pub full_errors: u64,
+    /// How long the oldest message currently in the queue has been waiting.
+    pub oldest_age_ms: u64,
+    /// The longest time any received message waited in the queue.
+    pub max_wait_ms: u64,
+}
+
+/// A message along with when it was enqueued.
+struct Envelope<T> {
+    seq: u64,
+    enqueued_at: Instant,
+    value: T,
+}
+
+/// The metrics state shared by a channel's senders and receiver.
+struct Meter {
+    name: &'static str,
+    depth: AtomicUsize,
+    max_depth: AtomicUsize,
+    next_seq: AtomicU64,
+    send_blocked_ms: AtomicU64,
+    max_send_blocked_ms: AtomicU64,
+    full_errors: AtomicU64,
+    max_wait_ms: AtomicU64,
+    /// The enqueue time of every message currently in the queue, by sequence
+    /// number, so we can find the oldest one.
+    in_queue: Mutex<BTreeMap<u64, Instant>>,
+    /// Set once the receiver is dropped, after which queued messages will
+    /// never be received. Only modified while holding the `in_queue` lock.
+    closed: AtomicBool,
+}
+
+// --- impl MeteredSender --- //
+
+impl<T> MeteredSender<T> {
+    /// Make [`Self::send`] fail with [`SendError::Timeout`] if the channel is
+    /// still full after `deadline`.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sends a value, waiting for capacity if the channel is full. If a
+    /// deadline was set with [`Self::with_deadline`], gives up once it elapses.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let start = Instant::now();
+        let reserve_res = match self.deadline {
+            Some(deadline) =>
+                match tokio::time::timeout(deadline, self.tx.reserve()).await {
+                    Ok(res) => res,
+                    Err(_) => {
+                        self.meter.full_errors.fetch_add(1, Ordering::Relaxed);
+                        let name = self.meter.name;
+                        warn!("{name} channel still full after {deadline:?}");
+                        return Err(SendError::Timeout(value));
+                    }
+                },
+            None => self.tx.reserve().await,
+        };
+        let permit = match reserve_res {
+            Ok(permit) => permit,
+            Err(_) => return Err(SendError::Closed(value)),
+        };
+        self.meter.record_send_blocked(start.elapsed());
+
+        permit.send(self.meter.enqueue(value));
+        Ok(())
+    }
+
+    /// Attempts to immediately send a value.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let permit = match self.tx.try_reserve() {
+            Ok(permit) => permit,
+            Err(TrySendError::Full(())) => {
+                self.meter.full_errors.fetch_add(1, Ordering::Relaxed);
+                return Err(TrySendError::Full(value));
+            }
+            Err(TrySendError::Closed(())) =>
+                return Err(TrySendError::Closed(value)),
+        };
+        permit.send(self.meter.enqueue(value));
+        Ok(())
+    }
+
+    /// A snapshot of this channel's metrics.
+    pub fn metrics(&self) -> QueueMetrics {
+        self.meter.snapshot()
+    }
+}
+
+impl<T> Clone for MeteredSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            meter: self.meter.clone(),
+            deadline: self.deadline,
+        }
+    }
+}
+
+// --- impl MeteredReceiver --- //
+
+impl<T> MeteredReceiver<T> {
+    /// Receives the next value, or [`None`] if all senders have been dropped.
+    /// Cancel-safe, like [`mpsc::Receiver::recv`].
+    pub async fn recv(&mut self) -> Option<T> {
+        let envelope = self.rx.recv().await?;
+        Some(self.meter.dequeue(envelope))
+    }
+
+    /// Attempts to receive the next value without waiting.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let envelope = self.rx.try_recv()?;
+        Ok(self.meter.dequeue(envelope))
+    }
+
+    /// A snapshot of this channel's metrics.
+    pub fn metrics(&self) -> QueueMetrics {
+        self.meter.snapshot()
+    }
+}
+
+impl<T> Drop for MeteredReceiver<T> {
+    fn drop(&mut self) {
+        self.meter.close();
+    }
+}
+
+// --- impl Meter --- //
+
+impl Meter {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            depth: AtomicUsize::new(0),
+            max_depth: AtomicUsize::new(0),
+            next_seq: AtomicU64::new(0),
+            send_blocked_ms: AtomicU64::new(0),
+            max_send_blocked_ms: AtomicU64::new(0),
+            full_errors: AtomicU64::new(0),
+            max_wait_ms: AtomicU64::new(0),
+            in_queue: Mutex::new(BTreeMap::new()),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn record_send_blocked(&self, blocked: Duration) {
+        let blocked_ms = duration_ms(blocked);
+        self.send_blocked_ms
+            .fetch_add(blocked_ms, Ordering::Relaxed);
+        self.max_send_blocked_ms
+            .fetch_max(blocked_ms, Ordering::Relaxed);
+    }
+
+    /// Called with a reserved permit, so the send can't fail.
+    fn enqueue<T>(&self, value: T) -> Envelope<T> {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let enqueued_at = Instant::now();
+
+        // A sender may still hold a permit after the receiver is dropped.
+        // Its message will never be received, so don't track it.
+        let mut in_queue = self.in_queue.lock().unwrap();
+        if !self.closed.load(Ordering::Relaxed) {
+            in_queue.insert(seq, enqueued_at);
+            let depth = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+            self.max_depth.fetch_max(depth, Ordering::Relaxed);
+        }
+        drop(in_queue);
+
+        Envelope {
+            seq,
+            enqueued_at,
+            value,
+        }
+    }
+
+    fn dequeue<T>(&self, envelope: Envelope<T>) -> T {
+        self.in_queue.lock().unwrap().remove(&envelope.seq);
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+
+        let wait_ms = duration_ms(envelope.enqueued_at.elapsed());
+        self.max_wait_ms.fetch_max(wait_ms, Ordering::Relaxed);
+
+        envelope.value
+    }
+
+    /// Stops tracking queued messages once the receiver is dropped, since
+    /// they'll never be dequeued.
+    fn close(&self) {
+        let mut in_queue = self.in_queue.lock().unwrap();
+        self.closed.store(true, Ordering::Relaxed);
+        in_queue.clear();
+        self.depth.store(0, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> QueueMetrics {
+        // Sequence numbers are assigned in enqueue order, so the first entry
+        // is the oldest message.
+        let oldest_age_ms = self
+            .in_queue
+            .lock()
+            .unwrap()
+            .first_key_value()
+            .map(|(_seq, enqueued_at)| duration_ms(enqueued_at.elapsed()))
+            .unwrap_or(0);
+
+        QueueMetrics {
+            depth: self.depth.load(Ordering::Relaxed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+            sent: self.next_seq.load(Ordering::Relaxed),
+            send_blocked_ms: self.send_blocked_ms.load(Ordering::Relaxed),
+            max_send_blocked_ms: self
+                .max_send_blocked_ms
+                .load(Ordering::Relaxed),
+            full_errors: self.full_errors.load(Ordering::Relaxed),
+            oldest_age_ms,
+            max_wait_ms: self.max_wait_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn duration_ms(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn metrics() {
+        let (tx, mut rx) = channel::<u32>("test", 2);
+
+        tx.send(1).await.unwrap();
+        tokio::time::advance(Duration::from_millis(100)).await;
+        tx.try_send(2).unwrap();
+        assert!(matches!(tx.try_send(3), Err(TrySendError::Full(3))));
+
+        let metrics = tx.metrics();
+        assert_eq!(metrics.depth, 2);
+        assert_eq!(metrics.max_depth, 2);
+        assert_eq!(metrics.sent, 2);
+        assert_eq!(metrics.full_errors, 1);
+        assert_eq!(metrics.oldest_age_ms, 100);
+
+        assert_eq!(rx.recv().await, Some(1));
+        let metrics = rx.metrics();
+        assert_eq!(metrics.depth, 1);
+        assert_eq!(metrics.oldest_age_ms, 0);
+        assert_eq!(metrics.max_wait_ms, 100);
+
+        assert_eq!(rx.try_recv().unwrap(), 2);
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Empty)));
+        assert_eq!(rx.metrics().depth, 0);
+        assert_eq!(rx.metrics().max_depth, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn send_deadline() {
+        let (tx, mut rx) = channel::<u32>("test", 1);
+        let tx = tx.with_deadline(Duration::from_secs(1));
+
+        tx.send(1).await.unwrap();
+        let res = tx.send(2).await;
+        assert!(matches!(res, Err(SendError::Timeout(2))));
+        assert_eq!(tx.metrics().full_errors, 1);
+
+        // A blocked send completes once there's capacity
+        let send = tx.send(3);
+        tokio::pin!(send);
+        tokio::select! {
+            _ = &mut send => panic!("Channel should be full"),
+            _ = tokio::time::sleep(Duration::from_millis(200)) => (),
+        }
+        assert_eq!(rx.recv().await, Some(1));
+        send.await.unwrap();
+        assert_eq!(rx.recv().await, Some(3));
+        assert_eq!(tx.metrics().max_send_blocked_ms, 200);
+
+        drop(rx);
+        assert!(matches!(tx.send(4).await, Err(SendError::Closed(4))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_receiver() {
+        let (tx, rx) = channel::<u32>("test", 2);
+        tx.send(1).await.unwrap();
+        tokio::time::advance(Duration::from_millis(100)).await;
+
+        // Messages left in the queue are no longer counted
+        drop(rx);
+        let metrics = tx.metrics();
+        assert_eq!(metrics.depth, 0);
+        assert_eq!(metrics.oldest_age_ms, 0);
+        assert_eq!(metrics.max_depth, 1);
+    }
+}
@@ -16,12 +16,28 @@
 //!   all senders have been dropped, this future will never resolve.
 //! - Just do `rx.clear()` instead of `while self.rx.try_recv().is_ok() {}` to
 //!   clear out pending notifications on the channel.
+//! - Just do `rx.recv_timeout(duration)` instead of wrapping `rx.recv()` in a
+//!   [`tokio::time::timeout`].
+//!
+//! ### Cancel safety
+//!
+//! [`Receiver::recv`] (and the [`Notified`] future it returns) is cancel-safe:
+//! a notification is only consumed when the future completes, so dropping it
+//! early, e.g. because another branch of a `tokio::select!` completed first,
+//! never loses a notification. The same goes for [`Receiver::recv_timeout`].
 //!
 //! This can also be used as a [`oneshot::channel::<()>()`]
 //!
 //! [`Receiver::recv`]: crate::notify::Receiver::recv
 //! [`oneshot::channel::<()>()`]: tokio::sync::oneshot::channel
 
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
 use tokio::sync::mpsc;
 
 /// Create a new `notify` channel returning a [`Sender`] (cloneable) and
@@ -38,6 +54,10 @@ pub struct Sender(mpsc::Sender<()>);
 /// `notify` receiver, analogous to `mpsc::Receiver<()>`.
 pub struct Receiver(mpsc::Receiver<()>);
 
+/// The future returned by [`Receiver::recv`]. Cancel-safe; see module docs.
+#[must_use = "futures do nothing unless polled"]
+pub struct Notified<'a>(&'a mut Receiver);
+
 impl Sender {
     /// Sends a notification to the [`Receiver`].
     pub fn send(&self) {
@@ -49,11 +69,16 @@ impl Receiver {
     /// Waits until a notification is received over the channel. Completes
     /// immediately if a notification has already been sent. NOTE: If all
     /// [`Sender`]s have been dropped, this future never completes!
-    pub async fn recv(&mut self) {
-        match self.0.recv().await {
-            Some(()) => (),
-            None => std::future::pending().await,
-        }
+    ///
+    /// This future is cancel-safe.
+    pub fn recv(&mut self) -> Notified<'_> {
+        Notified(self)
+    }
+
+    /// Waits until a notification is received or `timeout` elapses, returning
+    /// `true` if we were notified. Cancel-safe.
+    pub async fn recv_timeout(&mut self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, self.recv()).await.is_ok()
     }
 
     /// Immediately returns whether a notification has been sent.
@@ -67,3 +92,57 @@ impl Receiver {
         while self.0.try_recv().is_ok() {}
     }
 }
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // `mpsc::Receiver::poll_recv` only takes a message when it returns
+        // `Ready`, which is what makes this future cancel-safe.
+        match self.0 .0.poll_recv(cx) {
+            Poll::Ready(Some(())) => Poll::Ready(()),
+            // All senders dropped; never complete, like `future::pending()`.
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::{assert_pending, assert_ready, task};
+
+    use super::*;
+
+    #[test]
+    fn notified_is_cancel_safe() {
+        let (tx, mut rx) = channel();
+
+        // Dropping a pending `Notified` doesn't lose the next notification.
+        {
+            let mut notified = task::spawn(rx.recv());
+            assert_pending!(notified.poll());
+        }
+        tx.send();
+        tx.send();
+        let mut notified = task::spawn(rx.recv());
+        assert_ready!(notified.poll());
+        drop(notified);
+
+        // Duplicate notifications were coalesced.
+        assert!(!rx.try_recv());
+
+        // Never completes once all senders are dropped.
+        drop(tx);
+        let mut notified = task::spawn(rx.recv());
+        assert_pending!(notified.poll());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recv_timeout() {
+        let (tx, mut rx) = channel();
+        assert!(!rx.recv_timeout(Duration::from_secs(1)).await);
+        tx.send();
+        assert!(rx.recv_timeout(Duration::from_secs(1)).await);
+        assert!(!rx.try_recv());
+    }
+}
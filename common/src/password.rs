@@ -17,8 +17,12 @@
 //!
 //! The main entrypoints to this module are [`password::encrypt`] and
 //! [`password::decrypt`]. See the respective function docs for details.
+//!
+//! Since a password-encrypted ciphertext can be attacked offline, new passwords
+//! should also be checked with [`PasswordPolicy::check`], which uses
+//! [`estimate_strength`] to reject passwords which are easy to guess.
 
-use std::num::NonZeroU32;
+use std::{fmt, num::NonZeroU32};
 
 use ring::pbkdf2;
 use secrecy::Zeroize;
@@ -57,6 +61,8 @@ pub enum Error {
     PasswordTooLong,
     #[error("Decryption error: {0}")]
     AesDecrypt(#[from] aes::DecryptError),
+    #[error("Password is too weak: {}", .0.feedback())]
+    PasswordTooWeak(PasswordStrength),
 }
 
 /// Password-encrypt some binary `data` to a [`Vec<u8>`] ciphertext.
@@ -74,7 +80,7 @@ pub enum Error {
 ///   "password1234", "123456123456", and "111111111111" are all valid
 ///   passwords. It is the responsibility of the client to enforce that the
 ///   given password has sufficient entropy to prevent dictionary or other
-///   brute-force attacks.
+///   brute-force attacks, e.g. with [`PasswordPolicy::check`].
 ///
 /// [minimum]: MIN_PASSWORD_LENGTH
 /// [maximum]: MAX_PASSWORD_LENGTH
@@ -121,7 +127,7 @@ pub fn decrypt(
 
 /// Validate the length of the given password which the caller intends to use
 /// for password encryption. We don't check that the password has enough
-/// entropy; this should be done by the client.
+/// entropy; this should be done by the client with [`PasswordPolicy::check`].
 pub fn validate_password_len(password: &str) -> Result<(), Error> {
     let password_length = password.chars().count();
     if password_length < MIN_PASSWORD_LENGTH {
@@ -150,6 +156,490 @@ fn derive_aes_key(password: &str, salt: &[u8; 32]) -> AesMasterKey {
     aes_key
 }
 
+// --- Strength estimation --- //
+
+/// The # of guesses per second we assume an offline attacker can make against
+/// a password-encrypted ciphertext. Each guess costs [`PBKDF2_ITERATIONS`]
+/// iterations of HMAC-SHA256, so this is roughly a handful of GPUs.
+const OFFLINE_GUESSES_PER_SEC: f64 = 1e5;
+
+/// Common passwords and words, most common first. Not exhaustive; just enough
+/// to catch the guesses that attackers will try first.
+const COMMON_WORDS: &[&str] = &[
+    "password",
+    "123456",
+    "qwerty",
+    "letmein",
+    "welcome",
+    "monkey",
+    "dragon",
+    "iloveyou",
+    "admin",
+    "login",
+    "master",
+    "sunshine",
+    "princess",
+    "football",
+    "baseball",
+    "shadow",
+    "superman",
+    "trustno1",
+    "abc123",
+    "secret",
+    "hello",
+    "freedom",
+    "whatever",
+    "starwars",
+    "pokemon",
+    "charlie",
+    "michael",
+    "jennifer",
+    "jordan",
+    "hunter",
+    "ranger",
+    "buster",
+    "soccer",
+    "hockey",
+    "killer",
+    "george",
+    "andrew",
+    "michelle",
+    "tigger",
+    "computer",
+    "summer",
+    "winter",
+    "spring",
+    "autumn",
+    "love",
+    "money",
+    "god",
+    "bitcoin",
+    "satoshi",
+    "nakamoto",
+    "lightning",
+    "wallet",
+    "crypto",
+    "blockchain",
+    "lexe",
+    "backup",
+    "seed",
+    "pass",
+    "test",
+    "user",
+];
+
+/// Keyboard rows, without shift, used to detect keyboard patterns.
+const KEYBOARD_ROWS: &[&str] = &[
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+
+/// The result of [`estimate_strength`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasswordStrength {
+    /// From 0 (trivially guessable) to 4 (very unguessable), like zxcvbn.
+    pub score: u8,
+    /// log10 of the estimated # of guesses needed to find the password.
+    pub guesses_log10: f64,
+    /// The estimated # of seconds an offline attacker needs to find the
+    /// password. See [`Self::crack_time_display`].
+    pub crack_time_secs: f64,
+    /// The patterns found which make the password easier to guess, in the
+    /// order they were found, without duplicates.
+    pub warnings: Vec<PasswordWarning>,
+}
+
+/// A pattern which makes a password easier to guess.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PasswordWarning {
+    CommonWord,
+    PersonalInfo,
+    Repeat,
+    Sequence,
+    KeyboardPattern,
+    Year,
+}
+
+/// Requirements that a new password must meet before it is used to encrypt
+/// e.g. a [`RootSeed`] backup.
+///
+/// [`RootSeed`]: crate::root_seed::RootSeed
+#[derive(Clone, Debug)]
+pub struct PasswordPolicy {
+    /// The minimum [`PasswordStrength::score`].
+    pub min_score: u8,
+    /// Words the user is likely to include in their password, e.g. their
+    /// username or email, which should be treated as easy to guess.
+    pub user_inputs: Vec<String>,
+}
+
+/// A pattern found at some position in the password.
+struct Match {
+    /// The # of chars covered by the match.
+    len: usize,
+    /// The estimated entropy of the matched chars.
+    bits: f64,
+    warning: PasswordWarning,
+}
+
+/// Estimate how hard `password` is to guess with a compact zxcvbn-style
+/// heuristic: the password is split into common words, repeats, sequences,
+/// keyboard patterns, and years, each of which costs far fewer guesses than
+/// the same # of random characters. `user_inputs` are treated like the most
+/// common words.
+pub fn estimate_strength(
+    password: &str,
+    user_inputs: &[&str],
+) -> PasswordStrength {
+    let chars = password.chars().collect::<Vec<_>>();
+    let lower = chars
+        .iter()
+        .map(char::to_ascii_lowercase)
+        .collect::<Vec<_>>();
+    let unleet = lower.iter().copied().map(unleet).collect::<Vec<_>>();
+    let user_inputs = user_inputs
+        .iter()
+        .map(|input| input.to_ascii_lowercase())
+        .filter(|input| input.chars().count() >= 3)
+        .collect::<Vec<_>>();
+    let char_bits = charset_bits(&chars);
+
+    let mut bits = 0.0;
+    let mut warnings = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let candidates =
+            [
+                match_words(&chars, &lower, &unleet, i, COMMON_WORDS.iter())
+                    .map(|m| Match {
+                        warning: PasswordWarning::CommonWord,
+                        ..m
+                    }),
+                match_words(&chars, &lower, &unleet, i, user_inputs.iter())
+                    .map(|m| Match {
+                        warning: PasswordWarning::PersonalInfo,
+                        ..m
+                    }),
+                match_repeat(&lower, i, char_bits),
+                match_sequence(&lower, i),
+                match_keyboard(&lower, i),
+                match_year(&lower, i),
+            ];
+        // Prefer the longest match, then the cheapest one. Only use it if it's
+        // actually cheaper than brute forcing the same chars.
+        let best = candidates
+            .into_iter()
+            .flatten()
+            .filter(|m| m.bits < m.len as f64 * char_bits)
+            .min_by(|a, b| b.len.cmp(&a.len).then(a.bits.total_cmp(&b.bits)));
+
+        match best {
+            Some(m) => {
+                bits += m.bits;
+                if !warnings.contains(&m.warning) {
+                    warnings.push(m.warning);
+                }
+                i += m.len;
+            }
+            None => {
+                bits += char_bits;
+                i += 1;
+            }
+        }
+    }
+
+    let guesses_log10 = bits * std::f64::consts::LOG10_2;
+    let score = match guesses_log10 {
+        x if x < 3.0 => 0,
+        x if x < 6.0 => 1,
+        x if x < 8.0 => 2,
+        x if x < 10.0 => 3,
+        _ => 4,
+    };
+    let crack_time_secs = 10f64.powf(guesses_log10) / OFFLINE_GUESSES_PER_SEC;
+
+    PasswordStrength {
+        score,
+        guesses_log10,
+        crack_time_secs,
+        warnings,
+    }
+}
+
+/// The entropy of a single random char drawn from the character classes
+/// present in the password.
+fn charset_bits(chars: &[char]) -> f64 {
+    let has = |f: fn(&char) -> bool| chars.iter().any(f);
+    let mut size = 0u32;
+    if has(char::is_ascii_lowercase) {
+        size += 26;
+    }
+    if has(char::is_ascii_uppercase) {
+        size += 26;
+    }
+    if has(char::is_ascii_digit) {
+        size += 10;
+    }
+    if has(|c| c.is_ascii_punctuation() || *c == ' ') {
+        size += 33;
+    }
+    if has(|c| !c.is_ascii()) {
+        size += 100;
+    }
+    f64::from(size.max(2)).log2()
+}
+
+/// Undo common l33t substitutions, e.g. "p4ssw0rd" -> "password".
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' => 't',
+        c => c,
+    }
+}
+
+/// Find the longest word in `words` (most common first) at position `i`.
+fn match_words<S: AsRef<str>>(
+    chars: &[char],
+    lower: &[char],
+    unleet: &[char],
+    i: usize,
+    words: impl Iterator<Item = S>,
+) -> Option<Match> {
+    let mut best: Option<Match> = None;
+    for (rank, word) in words.enumerate() {
+        let word = word.as_ref().chars().collect::<Vec<_>>();
+        let len = word.len();
+        if len < 3 || i + len > lower.len() {
+            continue;
+        }
+        if best.as_ref().is_some_and(|best| best.len >= len) {
+            continue;
+        }
+        let is_plain = lower[i..i + len] == word[..];
+        if !is_plain && unleet[i..i + len] != word[..] {
+            continue;
+        }
+
+        let rank_bits = (rank as f64 + 2.0).log2();
+        let leet_bits = if is_plain { 0.0 } else { 1.0 };
+        let bits = rank_bits + leet_bits + uppercase_bits(&chars[i..i + len]);
+        best = Some(Match {
+            len,
+            bits,
+            warning: PasswordWarning::CommonWord,
+        });
+    }
+    best
+}
+
+/// The extra entropy from capitalizing some of a word's letters.
+fn uppercase_bits(word: &[char]) -> f64 {
+    let num_upper = word.iter().filter(|c| c.is_ascii_uppercase()).count();
+    let first_only = num_upper == 1 && word[0].is_ascii_uppercase();
+    if num_upper == 0 {
+        0.0
+    } else if first_only || num_upper == word.len() {
+        1.0
+    } else {
+        num_upper as f64
+    }
+}
+
+/// Repeated chars ("aaa") or repeated chunks ("abcabc") at position `i`.
+fn match_repeat(lower: &[char], i: usize, char_bits: f64) -> Option<Match> {
+    let rest = &lower[i..];
+    let mut best: Option<Match> = None;
+    for chunk_len in 1..=rest.len() / 2 {
+        let chunk = &rest[..chunk_len];
+        let reps = rest
+            .chunks_exact(chunk_len)
+            .take_while(|next| *next == chunk)
+            .count();
+        let len = reps * chunk_len;
+        let min_reps = if chunk_len == 1 { 3 } else { 2 };
+        if reps < min_reps || best.as_ref().is_some_and(|b| b.len >= len) {
+            continue;
+        }
+        best = Some(Match {
+            len,
+            bits: chunk_len as f64 * char_bits + (reps as f64).log2(),
+            warning: PasswordWarning::Repeat,
+        });
+    }
+    best
+}
+
+/// Ascending or descending runs of letters ("abc") or digits ("6543") at
+/// position `i`.
+fn match_sequence(lower: &[char], i: usize) -> Option<Match> {
+    let class_size = |c: char| match c {
+        'a'..='z' => Some(26.0),
+        '0'..='9' => Some(10.0),
+        _ => None,
+    };
+    let first = lower[i];
+    let class = class_size(first)?;
+    let delta = |a: char, b: char| b as i32 - a as i32;
+    let step = delta(first, *lower.get(i + 1)?);
+    if step.abs() != 1 {
+        return None;
+    }
+
+    let len = 1 + lower[i..]
+        .windows(2)
+        .take_while(|pair| {
+            delta(pair[0], pair[1]) == step
+                && class_size(pair[1]) == Some(class)
+        })
+        .count();
+    (len >= 3).then(|| Match {
+        len,
+        // The start, the direction, and the length
+        bits: f64::log2(class) + 1.0 + (len as f64).log2(),
+        warning: PasswordWarning::Sequence,
+    })
+}
+
+/// Runs of adjacent keys on the same keyboard row ("qwerty", "lkjh") at
+/// position `i`.
+fn match_keyboard(lower: &[char], i: usize) -> Option<Match> {
+    let position = |c: char| {
+        KEYBOARD_ROWS.iter().enumerate().find_map(|(row, keys)| {
+            keys.chars().position(|key| key == c).map(|col| (row, col))
+        })
+    };
+    let (row, col) = position(lower[i])?;
+    let (next_row, next_col) = position(*lower.get(i + 1)?)?;
+    let step = next_col as i32 - col as i32;
+    if row != next_row || step.abs() != 1 {
+        return None;
+    }
+
+    let len = 1 + lower[i..]
+        .windows(2)
+        .take_while(|pair| match (position(pair[0]), position(pair[1])) {
+            (Some((r0, c0)), Some((r1, c1))) =>
+                r0 == r1 && c1 as i32 - c0 as i32 == step,
+            _ => false,
+        })
+        .count();
+    let num_keys = KEYBOARD_ROWS.iter().map(|row| row.len()).sum::<usize>();
+    (len >= 3).then(|| Match {
+        len,
+        // The start, the direction, and the length
+        bits: (num_keys as f64).log2() + 1.0 + (len as f64).log2(),
+        warning: PasswordWarning::KeyboardPattern,
+    })
+}
+
+/// A year from 1900 to 2099 at position `i`.
+fn match_year(lower: &[char], i: usize) -> Option<Match> {
+    let digits = lower.get(i..i + 4)?;
+    let year = digits.iter().collect::<String>().parse::<u16>().ok()?;
+    (1900..=2099).contains(&year).then(|| Match {
+        len: 4,
+        bits: 200f64.log2(),
+        warning: PasswordWarning::Year,
+    })
+}
+
+// --- impl PasswordStrength --- //
+
+impl PasswordStrength {
+    /// A short suggestion for how to improve the password.
+    pub fn feedback(&self) -> &'static str {
+        match self.warnings.first() {
+            Some(warning) => warning.message(),
+            None => "Add more words or characters",
+        }
+    }
+
+    /// A human-readable [`Self::crack_time_secs`], e.g. "3 hours".
+    pub fn crack_time_display(&self) -> String {
+        const MINUTE: f64 = 60.0;
+        const HOUR: f64 = 60.0 * MINUTE;
+        const DAY: f64 = 24.0 * HOUR;
+        const MONTH: f64 = 31.0 * DAY;
+        const YEAR: f64 = 12.0 * MONTH;
+        const CENTURY: f64 = 100.0 * YEAR;
+
+        let secs = self.crack_time_secs;
+        let (amount, unit) = match secs {
+            s if s < 1.0 => return "less than a second".to_owned(),
+            s if s < MINUTE => (s, "second"),
+            s if s < HOUR => (s / MINUTE, "minute"),
+            s if s < DAY => (s / HOUR, "hour"),
+            s if s < MONTH => (s / DAY, "day"),
+            s if s < YEAR => (s / MONTH, "month"),
+            s if s < CENTURY => (s / YEAR, "year"),
+            _ => return "centuries".to_owned(),
+        };
+        let amount = amount.round() as u64;
+        let plural = if amount == 1 { "" } else { "s" };
+        format!("{amount} {unit}{plural}")
+    }
+}
+
+// --- impl PasswordWarning --- //
+
+impl PasswordWarning {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::CommonWord => "Avoid common passwords and words",
+            Self::PersonalInfo => "Avoid your name, username, or email",
+            Self::Repeat =>
+                "Repeats like \"aaa\" or \"abcabc\" are easy to guess",
+            Self::Sequence =>
+                "Sequences like \"abc\" or \"6543\" are easy to guess",
+            Self::KeyboardPattern =>
+                "Keyboard patterns like \"qwerty\" are easy to guess",
+            Self::Year => "Years are easy to guess",
+        }
+    }
+}
+
+impl fmt::Display for PasswordWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+// --- impl PasswordPolicy --- //
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_score: 3,
+            user_inputs: Vec::new(),
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Check that a new password has a valid length and is strong enough.
+    /// Returns the [`PasswordStrength`] so it can be shown to the user.
+    pub fn check(&self, password: &str) -> Result<PasswordStrength, Error> {
+        validate_password_len(password)?;
+        let user_inputs = self
+            .user_inputs
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let strength = estimate_strength(password, &user_inputs);
+        if strength.score < self.min_score {
+            return Err(Error::PasswordTooWeak(strength));
+        }
+        Ok(strength)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use proptest::{
@@ -180,6 +670,85 @@ mod test {
         })
     }
 
+    #[test]
+    fn strength_weak_passwords() {
+        use PasswordWarning::*;
+        let cases: &[(&str, PasswordWarning)] = &[
+            ("password1234", CommonWord),
+            ("P@ssw0rd1234", CommonWord),
+            ("aaaaaaaaaaaa", Repeat),
+            ("abcabcabcabc", Repeat),
+            ("abcdefghijkl", Sequence),
+            ("987654321098", Sequence),
+            ("qwertyuiop12", KeyboardPattern),
+            ("satoshi19942024", CommonWord),
+        ];
+        for (password, warning) in cases {
+            let strength = estimate_strength(password, &[]);
+            assert!(strength.score < 3, "{password}: {strength:?}");
+            assert!(strength.warnings.contains(warning), "{password}");
+            assert!(PasswordPolicy::default().check(password).is_err());
+        }
+
+        let strength = estimate_strength("satoshi19942024", &[]);
+        assert!(strength.warnings.contains(&Year));
+    }
+
+    #[test]
+    fn strength_personal_info() {
+        let password = "maxfang-lexe1";
+        let weak = estimate_strength(password, &["maxfang"]);
+        let strong = estimate_strength(password, &[]);
+        assert!(weak.guesses_log10 < strong.guesses_log10);
+        assert_eq!(weak.warnings.first(), Some(&PasswordWarning::PersonalInfo));
+
+        let policy = PasswordPolicy {
+            min_score: 4,
+            user_inputs: vec!["MaxFang".to_owned()],
+        };
+        assert!(matches!(
+            policy.check(password),
+            Err(Error::PasswordTooWeak(_))
+        ));
+    }
+
+    #[test]
+    fn strength_strong_passwords() {
+        for password in [
+            "correct horse battery staple",
+            "Tr0ub4dor&3x9!",
+            "vq8#Lk2m!Zp0wR",
+        ] {
+            let strength = PasswordPolicy::default().check(password).unwrap();
+            assert_eq!(strength.score, 4, "{password}: {strength:?}");
+            assert_eq!(strength.crack_time_display(), "centuries");
+        }
+
+        // Length is still enforced
+        assert!(matches!(
+            PasswordPolicy::default().check("x7#Qv!"),
+            Err(Error::PasswordTooShort)
+        ));
+    }
+
+    #[test]
+    fn crack_time_display() {
+        let display = |crack_time_secs| {
+            PasswordStrength {
+                score: 0,
+                guesses_log10: 0.0,
+                crack_time_secs,
+                warnings: Vec::new(),
+            }
+            .crack_time_display()
+        };
+        assert_eq!(display(0.5), "less than a second");
+        assert_eq!(display(1.0), "1 second");
+        assert_eq!(display(90.0), "2 minutes");
+        assert_eq!(display(3.0 * 3600.0), "3 hours");
+        assert_eq!(display(1e12), "centuries");
+    }
+
     /// Tests that updates to the decryption algorithm are backwards-compatible.
     #[test]
     fn decryption_compatibility() {
@@ -1,8 +1,12 @@
 //! Random number generation utilities
 
-use std::num::NonZeroU32;
+use std::{
+    num::NonZeroU32,
+    time::{Duration, Instant, SystemTime},
+};
 
 use bitcoin::secp256k1::{All, Secp256k1, SignOnly};
+use cfg_if::cfg_if;
 #[cfg(any(test, feature = "test-utils"))]
 use proptest::{
     arbitrary::{any, Arbitrary},
@@ -151,6 +155,138 @@ impl lightning::sign::EntropySource for SysRng {
     }
 }
 
+/// Wraps a seeded userspace PRNG, reseeding it from [`SysRng`] whenever its
+/// state may have been duplicated, i.e. when the process was forked or the VM
+/// it runs in was suspended or snapshot-restored.
+///
+/// Duplicated PRNG state means duplicated outputs, which is catastrophic for
+/// e.g. signature nonces. [`SysRng`] holds no userspace state and is always
+/// safe; this wrapper is for host-side components which need a faster seeded
+/// RNG and may run in VMs which get snapshotted.
+///
+/// A snapshot restore or long suspend is detected as a discontinuity between
+/// the wall clock and the monotonic clock, or as a long gap between uses.
+pub struct ReseedingRng<R> {
+    inner: R,
+    sys: SysRng,
+    pid: Option<u32>,
+    last_instant: Instant,
+    last_system_time: SystemTime,
+}
+
+impl<R: SeedableRng + RngCore> ReseedingRng<R> {
+    /// If the wall clock and monotonic clock advance by amounts which differ by
+    /// more than this between two uses, we assume a suspend or restore.
+    const MAX_CLOCK_DIVERGENCE: Duration = Duration::from_secs(1);
+    /// Reseed if the rng hasn't been used for this long. Reseeding is cheap,
+    /// and this catches restores which advance both clocks equally.
+    const MAX_IDLE: Duration = Duration::from_secs(60);
+
+    pub fn new() -> Self {
+        let mut sys = SysRng::new();
+        let inner = Self::seed_inner(&mut sys);
+        Self {
+            inner,
+            sys,
+            pid: current_pid(),
+            last_instant: Instant::now(),
+            last_system_time: SystemTime::now(),
+        }
+    }
+
+    fn seed_inner(sys: &mut SysRng) -> R {
+        R::from_rng(sys).expect("ring SystemRandom failed")
+    }
+
+    /// Reseed the inner rng if its state may have been duplicated since it was
+    /// last used.
+    fn reseed_if_needed(&mut self) {
+        let pid = current_pid();
+        let now = Instant::now();
+        let now_system_time = SystemTime::now();
+
+        let forked = pid != self.pid;
+        let suspended = self.is_discontinuous(now, now_system_time);
+        if forked || suspended {
+            self.inner = Self::seed_inner(&mut self.sys);
+        }
+
+        self.pid = pid;
+        self.last_instant = now;
+        self.last_system_time = now_system_time;
+    }
+
+    fn is_discontinuous(
+        &self,
+        now: Instant,
+        now_system_time: SystemTime,
+    ) -> bool {
+        let monotonic_elapsed =
+            now.saturating_duration_since(self.last_instant);
+        let system_elapsed =
+            match now_system_time.duration_since(self.last_system_time) {
+                Ok(elapsed) => elapsed,
+                // The wall clock went backwards.
+                Err(_) => return true,
+            };
+        let divergence = if system_elapsed > monotonic_elapsed {
+            system_elapsed - monotonic_elapsed
+        } else {
+            monotonic_elapsed - system_elapsed
+        };
+
+        divergence > Self::MAX_CLOCK_DIVERGENCE
+            || monotonic_elapsed > Self::MAX_IDLE
+    }
+}
+
+impl<R: SeedableRng + RngCore> Default for ReseedingRng<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Only a [`CryptoRng`] if the inner rng is.
+impl<R: SeedableRng + RngCore + CryptoRng> CryptoRng for ReseedingRng<R> {}
+
+impl<R: SeedableRng + RngCore> RngCore for ReseedingRng<R> {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        self.reseed_if_needed();
+        self.inner.next_u32()
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        self.reseed_if_needed();
+        self.inner.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.reseed_if_needed();
+        self.inner.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(
+        &mut self,
+        dest: &mut [u8],
+    ) -> Result<(), rand_core::Error> {
+        self.reseed_if_needed();
+        self.inner.try_fill_bytes(dest)
+    }
+}
+
+/// The current process id, or [`None`] inside SGX, where there are no pids.
+fn current_pid() -> Option<u32> {
+    cfg_if! {
+        if #[cfg(target_env = "sgx")] {
+            None
+        } else {
+            Some(std::process::id())
+        }
+    }
+}
+
 /// A small, fast, _non-cryptographic_ rng with decent statistical properties.
 /// Useful for sampling non-security sensitive data or as a deterministic RNG
 /// for tests (instead of the [`SysRng`] above, which uses the global OS RNG).
@@ -287,3 +423,36 @@ where
         xs.swap(i, j);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reseeding_rng_detects_discontinuities() {
+        let mut rng = ReseedingRng::<WeakRng>::new();
+        let (start, start_system_time) =
+            (rng.last_instant, rng.last_system_time);
+        let secs = Duration::from_secs;
+
+        // Clocks advancing together is fine.
+        assert!(
+            !rng.is_discontinuous(start + secs(5), start_system_time + secs(5))
+        );
+        // Wall clock jumped forward, e.g. VM snapshot restored.
+        assert!(rng
+            .is_discontinuous(start + secs(5), start_system_time + secs(3600)));
+        // Wall clock went backwards.
+        assert!(rng.is_discontinuous(start, start_system_time - secs(5)));
+        // Long idle.
+        assert!(rng
+            .is_discontinuous(start + secs(61), start_system_time + secs(61)));
+
+        // A detected fork reseeds the inner rng.
+        let mut before = rng.inner.clone();
+        rng.pid = rng.pid.map(|pid| pid.wrapping_add(1));
+        rng.reseed_if_needed();
+        assert_ne!(rng.inner.next_u64(), before.next_u64());
+        assert_eq!(rng.pid, current_pid());
+    }
+}
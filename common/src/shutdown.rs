@@ -1,6 +1,4 @@
-use std::sync::Arc;
-
-use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
 
 /// A synchronization utility designed for sending / receiving shutdown signals.
 ///
@@ -12,40 +10,48 @@ use tokio::sync::Semaphore;
 /// - Consumers can receive shutdown signals that were sent prior to
 ///   'subscribing' to the channel (unlike [`tokio::sync::broadcast`]);
 /// - It is safe to send a shutdown signal multiple times (e.g. by accident).
+/// - Scoped shutdown: a [`child`] channel receives all shutdown signals sent to
+///   its parent, but a shutdown sent to the child does not propagate up to the
+///   parent (or to the child's siblings).
 ///
-/// The underlying implementation (ab)uses the fact that calling [`acquire`] on
-/// a [`Semaphore`] with 0 permits only returns once the [`Semaphore`] has been
-/// closed. Closing the [`Semaphore`] is equivalent to sending a shutdown
-/// signal, and receiving an [`AcquireError`] (indicating the [`Semaphore`] has
-/// been closed) from a call to [`acquire`] is equivalent to receiving one.
-/// [`ShutdownChannel`]'s methods abstract over these details, of course.
+/// The underlying implementation is a [`CancellationToken`], which
+/// [`ShutdownChannel`] can be converted to and from. Sending a shutdown signal
+/// is equivalent to cancelling the token.
 ///
-/// [`acquire`]: Semaphore::acquire
-/// [`AcquireError`]: tokio::sync::AcquireError
+/// [`child`]: ShutdownChannel::child
 #[derive(Debug)]
 pub struct ShutdownChannel {
-    inner: Arc<Semaphore>,
+    inner: CancellationToken,
     have_recved: bool,
 }
 
 impl ShutdownChannel {
     /// Construct a new [`ShutdownChannel`].
     /// This function should only be called *once* in the lifetime of a program.
+    /// Use [`child`] to derive channels for scoped shutdowns.
+    ///
+    /// [`child`]: ShutdownChannel::child
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let inner = Arc::new(Semaphore::new(0));
-        Self {
-            inner,
-            have_recved: false,
-        }
+        Self::from(CancellationToken::new())
+    }
+
+    /// Derive a child [`ShutdownChannel`]. The child receives any shutdown
+    /// signal sent via this channel (or any of its ancestors), but a shutdown
+    /// signal sent via the child only shuts down the child and its own
+    /// descendants.
+    ///
+    /// If this channel has already been shut down, the child is too.
+    pub fn child(&self) -> Self {
+        Self::from(self.inner.child_token())
     }
 
-    /// Send a shutdown signal, causing all actors waiting on this channel to
-    /// complete their call to [`recv`].
+    /// Send a shutdown signal, causing all actors waiting on this channel (or
+    /// any of its descendants) to complete their call to [`recv`].
     ///
     /// [`recv`]: ShutdownChannel::recv
     pub fn send(&self) {
-        self.inner.close();
+        self.inner.cancel();
     }
 
     /// Wait for a shutdown signal.
@@ -59,11 +65,7 @@ impl ShutdownChannel {
             std::future::pending().await
         } else {
             // wait for a shutdown
-            self.inner
-                .acquire()
-                .await
-                .map_err(|_| ())
-                .expect_err("Shouldn't've been able to acquire a permit");
+            self.inner.cancelled().await;
             // we've seen a shutdown; if this method gets called again, it
             // won't yield.
             self.have_recved = true;
@@ -77,7 +79,7 @@ impl ShutdownChannel {
     /// Immediately returns whether a shutdown signal has been sent.
     #[must_use]
     pub fn try_recv(&self) -> bool {
-        self.inner.is_closed()
+        self.inner.is_cancelled()
     }
 }
 
@@ -92,6 +94,21 @@ impl Clone for ShutdownChannel {
     }
 }
 
+impl From<CancellationToken> for ShutdownChannel {
+    fn from(inner: CancellationToken) -> Self {
+        Self {
+            inner,
+            have_recved: false,
+        }
+    }
+}
+
+impl From<ShutdownChannel> for CancellationToken {
+    fn from(shutdown: ShutdownChannel) -> Self {
+        shutdown.inner
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::time::Duration;
@@ -143,6 +160,53 @@ mod test {
         assert_ready!(recv_task3.poll());
     }
 
+    #[test]
+    fn child_shutdown_is_scoped() {
+        let parent = ShutdownChannel::new();
+        let child1 = parent.child();
+        let child2 = parent.child();
+        let grandchild1 = child1.child();
+
+        // Shutting down a child only affects it and its descendants
+        child1.send();
+        assert!(child1.try_recv());
+        assert!(grandchild1.try_recv());
+        assert!(!parent.try_recv());
+        assert!(!child2.try_recv());
+
+        // Shutting down the parent affects all descendants
+        parent.send();
+        assert!(child2.try_recv());
+
+        // Children derived after shutdown start out shut down
+        assert!(parent.child().try_recv());
+    }
+
+    #[test]
+    fn child_recv_wakes_on_parent_send() {
+        let parent = ShutdownChannel::new();
+        let mut child = parent.child();
+
+        let mut recv_task = tokio_test::task::spawn(child.recv());
+        assert_pending!(recv_task.poll());
+
+        parent.send();
+
+        assert!(recv_task.is_woken());
+        assert_ready!(recv_task.poll());
+    }
+
+    #[test]
+    fn cancellation_token_conversions() {
+        let token = CancellationToken::new();
+        let shutdown = ShutdownChannel::from(token.clone());
+        let child_token = CancellationToken::from(shutdown.child());
+
+        token.cancel();
+        assert!(shutdown.try_recv());
+        assert!(child_token.is_cancelled());
+    }
+
     #[tokio::test(start_paused = true)]
     async fn subscribe_after_close_is_ok() {
         // Basic test: subscribe, wait, shutdown
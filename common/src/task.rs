@@ -1,12 +1,54 @@
 use std::{
+    backtrace::Backtrace,
+    collections::VecDeque,
     future::Future,
+    panic::PanicInfo,
     pin::Pin,
+    sync::{Mutex, Once},
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use tokio::task::{JoinError, JoinHandle};
 use tracing::{error, info, warn, Instrument, Span};
 
+use crate::time::TimestampMs;
+
+/// The maximum number of [`TaskCrash`]es retained by [`recent_crashes`].
+pub const MAX_TASK_CRASHES: usize = 16;
+
+/// [`Budget`] only reads the clock every this many iterations, since reading
+/// the clock is a (slow) usercall inside SGX.
+const BUDGET_CLOCK_CHECK_ITERS: u32 = 8;
+/// A [`Budget`] slice which took this many times `max_time` is an overrun.
+const BUDGET_OVERRUN_FACTOR: u32 = 4;
+/// A [`Budget`] logs a warning every this many overruns.
+const BUDGET_CHRONIC_OVERRUNS: u32 = 8;
+
+tokio::task_local! {
+    /// The name of the [`LxTask`] currently being polled, if any. Lets the
+    /// panic hook attribute panics to the task they happened in.
+    static TASK_NAME: String;
+}
+
+/// The most recent [`TaskCrash`]es, oldest first.
+static TASK_CRASHES: Mutex<VecDeque<TaskCrash>> = Mutex::new(VecDeque::new());
+/// Called with every new [`TaskCrash`]. See [`set_crash_callback`].
+static CRASH_CALLBACK: Mutex<Option<CrashCallback>> = Mutex::new(None);
+
+type CrashCallback = Box<dyn Fn(&TaskCrash) + Send + 'static>;
+
+/// Information about a panic which occurred inside a spawned [`LxTask`].
+#[derive(Clone, Debug)]
+pub struct TaskCrash {
+    /// The name of the task which panicked. Empty for unnamed tasks.
+    pub task_name: String,
+    /// The panic message, including the source location if known.
+    pub message: String,
+    pub backtrace: String,
+    pub timestamp: TimestampMs,
+}
+
 /// A thin wrapper around [`tokio::task::JoinHandle`] that adds the
 /// `#[must_use]` lint to ensure that all spawned tasks are joined or explictly
 /// annotated that no joining is required. Use [`LxTask::detach`] to make it
@@ -83,9 +125,11 @@ impl<T> LxTask<T> {
     {
         // Instrument the future so that the current tracing span propagates
         // past spawn boundaries.
+        let name = name.into();
+        let future = TASK_NAME.scope(name.clone(), future.in_current_span());
         Self {
-            task: tokio::spawn(future.in_current_span()),
-            name: name.into(),
+            task: tokio::spawn(future),
+            name,
         }
     }
 
@@ -179,9 +223,11 @@ impl<T> LxTask<T> {
         F: Future<Output = T> + Send + 'static,
         F::Output: Send + 'static,
     {
+        let name = name.into();
+        let future = TASK_NAME.scope(name.clone(), future);
         Self {
             task: tokio::spawn(future),
-            name: name.into(),
+            name,
         }
     }
 
@@ -247,9 +293,11 @@ impl<T> LxTask<T> {
         F::Output: Send + 'static,
     {
         // Instrument the future with the given tracing span.
+        let name = name.into();
+        let future = TASK_NAME.scope(name.clone(), future.instrument(span));
         Self {
-            task: tokio::spawn(future.instrument(span)),
-            name: name.into(),
+            task: tokio::spawn(future),
+            name,
         }
     }
 
@@ -319,6 +367,75 @@ impl<T> Future for LxTask<T> {
     }
 }
 
+// --- Crash reporting --- //
+
+/// Installs a process-wide panic hook which records panics inside [`LxTask`]s
+/// as [`TaskCrash`]es (see [`recent_crashes`]) and passes them to the callback
+/// registered with [`set_crash_callback`]. The previously installed hook is
+/// still called first, so this can be layered on top of e.g. an SGX backtrace
+/// hook. Calling this more than once has no effect.
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let prev_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            prev_hook(info);
+            // Panics outside of an `LxTask` aren't recorded.
+            if let Ok(task_name) = TASK_NAME.try_with(String::clone) {
+                record_crash(task_name, info);
+            }
+        }));
+    });
+}
+
+/// Registers a callback to be called with each new [`TaskCrash`], replacing
+/// any existing callback. Requires [`install_panic_hook`].
+///
+/// The callback runs inside the panic hook, so it should be quick and MUST
+/// NOT panic, or the process will abort.
+pub fn set_crash_callback(callback: impl Fn(&TaskCrash) + Send + 'static) {
+    *CRASH_CALLBACK.lock().unwrap() = Some(Box::new(callback));
+}
+
+/// Returns the most recent (up to [`MAX_TASK_CRASHES`]) [`TaskCrash`]es,
+/// oldest first.
+pub fn recent_crashes() -> Vec<TaskCrash> {
+    TASK_CRASHES.lock().unwrap().iter().cloned().collect()
+}
+
+fn record_crash(task_name: String, info: &PanicInfo<'_>) {
+    let payload = info.payload();
+    let msg = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("(non-string panic payload)");
+    let message = match info.location() {
+        Some(location) => format!("{msg} (at {location})"),
+        None => msg.to_owned(),
+    };
+
+    let crash = TaskCrash {
+        task_name,
+        message,
+        backtrace: Backtrace::force_capture().to_string(),
+        timestamp: TimestampMs::now(),
+    };
+
+    // Don't panic (and abort) inside the panic hook if a lock was poisoned.
+    if let Ok(callback) = CRASH_CALLBACK.lock() {
+        if let Some(callback) = callback.as_ref() {
+            callback(&crash);
+        }
+    }
+    if let Ok(mut crashes) = TASK_CRASHES.lock() {
+        if crashes.len() >= MAX_TASK_CRASHES {
+            crashes.pop_front();
+        }
+        crashes.push_back(crash);
+    }
+}
+
 /// Helper to log the output of a finished [`LxTaskWithName<()>`]
 ///
 /// Pass `ed = true` if the task finished prematurely.
@@ -395,3 +512,134 @@ impl<T> Future for LxTaskWithName<T> {
         Poll::Ready(result)
     }
 }
+
+/// Lets CPU-heavy async loops, e.g. decrypting a batch of payments or
+/// deserializing channel monitors, periodically yield back to the runtime so
+/// they don't starve other tasks. Our enclave runtime only has two worker
+/// threads, so a single task hogging one for a while stalls everything else.
+///
+/// Call [`Budget::tick`] once per iteration. It yields once `max_iters`
+/// iterations have run or `max_time` has elapsed since the last yield,
+/// whichever comes first.
+///
+/// If the time between yields chronically runs far past `max_time`, i.e. the
+/// individual iterations are too expensive for yielding to help, the budget
+/// logs a warning naming the current [`LxTask`], so that the work can be
+/// split up further or moved to a blocking thread.
+pub struct Budget {
+    max_iters: u32,
+    max_time: Duration,
+    /// Iterations since the last yield.
+    iters: u32,
+    /// When the current slice started, i.e. the last yield.
+    slice_start: Instant,
+    /// The # of slices which took over [`BUDGET_OVERRUN_FACTOR`] × `max_time`.
+    overruns: u32,
+}
+
+impl Budget {
+    pub const DEFAULT_MAX_ITERS: u32 = 32;
+    pub const DEFAULT_MAX_TIME: Duration = Duration::from_micros(500);
+
+    /// A [`Budget`] with the default limits.
+    pub fn new() -> Self {
+        Self::with_limits(Self::DEFAULT_MAX_ITERS, Self::DEFAULT_MAX_TIME)
+    }
+
+    pub fn with_limits(max_iters: u32, max_time: Duration) -> Self {
+        Self {
+            max_iters: max_iters.max(1),
+            max_time,
+            iters: 0,
+            slice_start: Instant::now(),
+            overruns: 0,
+        }
+    }
+
+    /// Records an iteration, yielding to the runtime if the budget is spent.
+    pub async fn tick(&mut self) {
+        self.iters += 1;
+        let iters_spent = self.iters >= self.max_iters;
+        if !iters_spent && self.iters % BUDGET_CLOCK_CHECK_ITERS != 0 {
+            return;
+        }
+
+        let elapsed = self.slice_start.elapsed();
+        if !iters_spent && elapsed < self.max_time {
+            return;
+        }
+        if elapsed > self.max_time * BUDGET_OVERRUN_FACTOR {
+            self.record_overrun(elapsed);
+        }
+
+        tokio::task::yield_now().await;
+        self.iters = 0;
+        self.slice_start = Instant::now();
+    }
+
+    fn record_overrun(&mut self, elapsed: Duration) {
+        self.overruns += 1;
+        if self.overruns % BUDGET_CHRONIC_OVERRUNS != 0 {
+            return;
+        }
+
+        let task_name = TASK_NAME.try_with(String::clone).unwrap_or_default();
+        let overruns = self.overruns;
+        let max_time = self.max_time;
+        warn!(
+            "Task '{task_name}' is chronically over budget: {overruns} slices \
+            took over {BUDGET_OVERRUN_FACTOR}x {max_time:?} between yields \
+            (latest: {elapsed:?})"
+        );
+    }
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn task_crash_is_recorded() {
+        install_panic_hook();
+
+        let task_name = "crashy task";
+        let task = LxTask::spawn_named(task_name, async { panic!("oh no") });
+        // Await the inner `JoinHandle` so the panic isn't propagated here.
+        let join_err = task.task.await.unwrap_err();
+        assert!(join_err.is_panic());
+
+        let crash = recent_crashes()
+            .into_iter()
+            .rev()
+            .find(|crash| crash.task_name == task_name)
+            .expect("Crash should have been recorded");
+        assert!(crash.message.contains("oh no"), "{}", crash.message);
+    }
+
+    #[tokio::test]
+    async fn budget_yields_and_detects_overruns() {
+        // Spends the budget by iterations.
+        let mut budget = Budget::with_limits(4, Duration::from_secs(60));
+        for _ in 0..3 {
+            budget.tick().await;
+        }
+        assert_eq!(budget.iters, 3);
+        budget.tick().await;
+        assert_eq!(budget.iters, 0);
+        assert_eq!(budget.overruns, 0);
+
+        // Each iteration takes far longer than the time budget.
+        let mut budget = Budget::with_limits(1, Duration::from_micros(100));
+        for _ in 0..BUDGET_CHRONIC_OVERRUNS {
+            std::thread::sleep(Duration::from_millis(1));
+            budget.tick().await;
+        }
+        assert_eq!(budget.overruns, BUDGET_CHRONIC_OVERRUNS);
+    }
+}
@@ -2,6 +2,8 @@ use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+use crate::time::TimestampMs;
+
 /// An enum for calling various `TestEventReceiver` methods.
 #[derive(Serialize, Deserialize)]
 pub enum TestEventOp {
@@ -56,6 +58,23 @@ pub enum TestEvent {
     ///
     /// [`SpendableOutputs`]: lightning::events::Event::SpendableOutputs
     SpendableOutputs,
+    /// An invoice payment was expired (inbound) or abandoned (outbound)
+    /// because its invoice lapsed.
+    InvoiceExpired,
+    /// A BDK wallet sync completed successfully.
+    BdkSyncCompleted,
+    /// An LDK chain sync completed successfully.
+    LdkSyncCompleted,
+    /// A channel monitor update was persisted and handed to the chain monitor.
+    ChannelMonitorPersisted,
+}
+
+/// A [`TestEvent`] along with when it was sent, as recorded in the node's
+/// event history.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedTestEvent {
+    pub at: TimestampMs,
+    pub event: TestEvent,
 }
 
 impl From<TestEvent> for Vec<TestEvent> {
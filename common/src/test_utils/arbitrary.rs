@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
     ops::RangeInclusive,
     time::Duration,
@@ -33,6 +34,7 @@ use semver::{BuildMetadata, Prerelease};
 
 use crate::{
     api::NodePk,
+    enclave::Measurement,
     rng::{RngExt, WeakRng},
 };
 
@@ -359,6 +361,17 @@ pub fn any_semver_version() -> impl Strategy<Value = semver::Version> {
     )
 }
 
+/// An `Arbitrary`-like [`Strategy`] for a small map of release versions to
+/// their [`Measurement`]s.
+pub fn any_release_measurements(
+) -> impl Strategy<Value = BTreeMap<semver::Version, Measurement>> {
+    proptest::collection::btree_map(
+        any_semver_version(),
+        any::<Measurement>(),
+        0..4,
+    )
+}
+
 /// An `Arbitrary`-like [`Strategy`] for [`chrono::DateTime<Utc>`].
 /// Does not include leap seconds.
 pub fn any_chrono_datetime() -> impl Strategy<Value = chrono::DateTime<Utc>> {
@@ -11,7 +11,7 @@ use proptest::{
 };
 use serde::{de::DeserializeOwned, Serialize};
 
-use crate::ed25519;
+use crate::{api::rest::BodyFormat, ed25519};
 
 /// Quickly create a BCS roundtrip proptest.
 ///
@@ -121,6 +121,37 @@ where
     });
 }
 
+/// Quickly create a proptest checking that `T` roundtrips through each
+/// [`BodyFormat`], i.e. that clients can request any format for an endpoint
+/// returning `T` and get the same value back.
+///
+/// ```ignore
+/// body_format_roundtrip_proptest::<Vec<BasicPayment>>();
+/// ```
+pub fn body_format_roundtrip_proptest<T>()
+where
+    T: Arbitrary + PartialEq + Serialize + DeserializeOwned,
+{
+    body_format_custom(any::<T>(), Config::default());
+}
+
+/// Create a [`BodyFormat`] roundtrip proptest using a custom strategy and
+/// custom proptest [`Config`].
+pub fn body_format_custom<S, T>(strategy: S, config: Config)
+where
+    S: Strategy<Value = T>,
+    T: PartialEq + Serialize + DeserializeOwned + Debug,
+{
+    proptest!(config, |(value1 in strategy)| {
+        let json = BodyFormat::Json.serialize(&value1).unwrap();
+        let cbor = BodyFormat::Cbor.serialize(&value1).unwrap();
+        let json_value2 = BodyFormat::Json.deserialize::<T>(&json).unwrap();
+        let cbor_value2 = BodyFormat::Cbor.deserialize::<T>(&cbor).unwrap();
+        prop_assert_eq!(&value1, &json_value2);
+        prop_assert_eq!(&value1, &cbor_value2);
+    });
+}
+
 /// Quickly create a roundtrip proptest for some `T` which is url-encodable /
 /// querystring serializable.
 pub fn query_string_roundtrip_proptest<T>()
@@ -64,9 +64,9 @@ impl AttestationCert {
         //
         // Get the quote as an x509 cert extension that we'll embed in our
         // self-signed provisioning cert.
-        let attestation_ext =
-            super::quote::quote_enclave(rng, key_pair.public_key())
-                .context("Failed to quote enclave")?;
+        let report_data = ReportData::from_cert_pk(key_pair.public_key());
+        let attestation_ext = super::quote::quote_enclave(rng, &report_data)
+            .context("Failed to quote enclave")?;
         let cert_ext = attestation_ext.to_cert_extension();
 
         let now = time::OffsetDateTime::now_utc();
@@ -151,14 +151,10 @@ impl<'a> SgxAttestationExtension<'a> {
 
 impl SgxAttestationExtension<'static> {
     /// Build a dummy attestation for testing on non-SGX platforms.
-    pub fn dummy(cert_pk: &ed25519::PublicKey) -> Self {
-        // Use a dummy report as the 'quote'.
+    pub fn dummy(report_data: &ReportData) -> Self {
+        // Use a dummy report as the 'quote', with the given `reportdata`.
         let mut report = enclave::report();
-
-        // Insert the cert pk into the first 32 bytes of the `reportdata` field,
-        // See `ReportData::from_cert_pk` in `attestation::quote::quote_enclave`
-        let report_data = ReportData::from_cert_pk(cert_pk);
-        report.reportdata = report_data.into_inner();
+        report.reportdata = *report_data.as_inner();
 
         Self {
             quote: Cow::Owned(AsRef::<[u8]>::as_ref(&report).to_owned()),
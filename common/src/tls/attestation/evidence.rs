@@ -0,0 +1,159 @@
+//! Export remote attestation evidence as a standalone [`EvidenceBundle`].
+//!
+//! Normally, an enclave's attestation evidence is only checked by the
+//! [`AttestationCertVerifier`] during a TLS handshake. The [`EvidenceBundle`]
+//! packages the same evidence as JSON, so that auditors (and anyone checking
+//! our reproducible builds) can verify which measurement a running node has
+//! with [`verify_evidence_bundle`], without crafting a TLS handshake.
+//!
+//! Like the TLS verifier, we verify the PCK cert chain up to the Intel SGX root
+//! CA embedded in this crate, and don't fetch Intel's TCB info or QE identity
+//! collateral, so the bundle doesn't include them.
+//!
+//! [`AttestationCertVerifier`]: super::verifier::AttestationCertVerifier
+
+use anyhow::{ensure, Context};
+use rustls::pki_types::UnixTime;
+use serde::{Deserialize, Serialize};
+
+use super::verifier::{self, AttestEvidence, EnclavePolicy};
+use crate::{
+    ed25519, enclave::Measurement, hexstr_or_bytes,
+    tls::types::LxCertificateDer,
+};
+
+/// The current version of the [`EvidenceBundle`] format.
+pub const EVIDENCE_BUNDLE_VERSION: u16 = 1;
+
+/// An enclave's remote attestation evidence. See the module docs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvidenceBundle {
+    /// The [`EVIDENCE_BUNDLE_VERSION`] this bundle was created with.
+    pub version: u16,
+    /// The enclave's self-signed attestation cert. The quote commits to this
+    /// cert's public key.
+    #[serde(with = "hexstr_or_bytes")]
+    pub cert_der: Vec<u8>,
+    /// The SGX quote embedded in `cert_der`, duplicated here so it can be fed
+    /// to other quote verification tools without parsing x509.
+    #[serde(with = "hexstr_or_bytes")]
+    pub quote: Vec<u8>,
+    /// The PEM-encoded PCK cert chain from the quote signature, leaf first.
+    /// Empty for the dummy quotes generated outside of SGX.
+    pub pck_cert_chain: Vec<String>,
+}
+
+/// The facts established by a successful [`verify_evidence_bundle`].
+#[derive(Debug)]
+pub struct VerifiedEvidence {
+    /// The enclave measurement (MRENCLAVE).
+    pub measurement: Measurement,
+    /// The enclave signer (MRSIGNER).
+    pub signer: Measurement,
+    /// The public key of the attestation cert the quote commits to.
+    pub cert_pk: ed25519::PublicKey,
+}
+
+impl EvidenceBundle {
+    /// Build a bundle from a DER-encoded attestation cert.
+    pub fn from_cert_der(cert_der: LxCertificateDer) -> anyhow::Result<Self> {
+        let cert_der = cert_der.0;
+        let evidence = AttestEvidence::parse_cert_der(&cert_der)
+            .context("Invalid attestation cert")?;
+        let quote = evidence.quote().to_vec();
+        let pck_cert_chain = if cfg!(target_env = "sgx") {
+            verifier::quote_pck_cert_chain(&quote)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            version: EVIDENCE_BUNDLE_VERSION,
+            cert_der,
+            quote,
+            pck_cert_chain,
+        })
+    }
+}
+
+/// Verify an [`EvidenceBundle`] against an [`EnclavePolicy`], returning the
+/// verified enclave identity. `expect_dummy_quote` should only be set in tests.
+pub fn verify_evidence_bundle(
+    bundle: &EvidenceBundle,
+    expect_dummy_quote: bool,
+    enclave_policy: &EnclavePolicy,
+    now: UnixTime,
+) -> anyhow::Result<VerifiedEvidence> {
+    ensure!(
+        bundle.version == EVIDENCE_BUNDLE_VERSION,
+        "Unsupported evidence bundle version: {}",
+        bundle.version,
+    );
+
+    let evidence = AttestEvidence::parse_cert_der(&bundle.cert_der)
+        .context("Invalid attestation cert")?;
+    ensure!(
+        evidence.quote() == bundle.quote.as_slice(),
+        "Bundle quote doesn't match the quote in the attestation cert",
+    );
+    if !expect_dummy_quote {
+        let pck_cert_chain = verifier::quote_pck_cert_chain(&bundle.quote)?;
+        ensure!(
+            pck_cert_chain == bundle.pck_cert_chain,
+            "Bundle PCK cert chain doesn't match the chain in the quote",
+        );
+    }
+
+    let report = evidence.verify(expect_dummy_quote, enclave_policy, now)?;
+
+    Ok(VerifiedEvidence {
+        measurement: Measurement::new(report.mrenclave),
+        signer: Measurement::new(report.mrsigner),
+        cert_pk: *evidence.cert_pk(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        enclave, rng::WeakRng, tls::attestation::cert::AttestationCert,
+    };
+
+    // SGX generates a real quote
+    #[cfg(not(target_env = "sgx"))]
+    #[test]
+    fn dummy_evidence_bundle_roundtrip() {
+        let mut rng = WeakRng::new();
+        let dns_name = "run.lexe.app".to_owned();
+        let lifetime = Duration::from_secs(60);
+        let cert = AttestationCert::generate(&mut rng, dns_name, lifetime)
+            .unwrap()
+            .serialize_der_self_signed()
+            .unwrap();
+
+        let bundle = EvidenceBundle::from_cert_der(cert).unwrap();
+        let json = serde_json::to_string(&bundle).unwrap();
+        let bundle = serde_json::from_str::<EvidenceBundle>(&json).unwrap();
+
+        let policy = EnclavePolicy::dangerous_trust_any();
+        let now = UnixTime::now();
+        let verified =
+            verify_evidence_bundle(&bundle, true, &policy, now).unwrap();
+        assert_eq!(verified.measurement, enclave::measurement());
+
+        let mut tampered = bundle.clone();
+        tampered.quote[0] ^= 1;
+        assert!(verify_evidence_bundle(&tampered, true, &policy, now).is_err());
+
+        let other = Measurement::new([69; 32]);
+        let policy = EnclavePolicy {
+            allow_debug: true,
+            trusted_mrenclaves: Some(vec![other]),
+            trusted_mrsigner: None,
+        };
+        assert!(verify_evidence_bundle(&bundle, true, &policy, now).is_err());
+    }
+}
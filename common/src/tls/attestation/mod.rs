@@ -42,6 +42,14 @@
 //!
 //! 5) Finally, if all verifications passed, a TLS connection is established.
 //!
+//! Since the [`AttestationCert`] is only generated once per node lifetime, the
+//! app additionally sends an [`AttestationChallenge`] over the provisioning TLS
+//! connection before sending any secrets. The node responds with a fresh quote
+//! committing to both its cert pubkey and the challenge nonce (see
+//! [`node_challenge_quote`]), which the app checks with
+//! [`verify_challenge_quote`]. This protects against replayed attestation
+//! evidence from a platform which has since been compromised.
+//!
 //! [`quote`]: https://phlip9.com/notes/confidential%20computing/intel%20SGX/SGX%20lingo/#quote
 //! [Quoting Enclave]: https://phlip9.com/notes/confidential%20computing/intel%20SGX/SGX%20lingo/#quoting-enclave-qe
 //! [`QE`]: https://phlip9.com/notes/confidential%20computing/intel%20SGX/SGX%20lingo/#quoting-enclave-qe
@@ -62,6 +70,9 @@
 //! [`ServerCertVerifier`]: rustls::client::danger::ServerCertVerifier
 //! [`EnclavePolicy`]: attestation::verifier::EnclavePolicy
 //! [`EnclavePolicy::verify`]: attestation::verifier::EnclavePolicy::verify
+//! [`AttestationChallenge`]: attestation::verifier::AttestationChallenge
+//! [`node_challenge_quote`]: attestation::node_challenge_quote
+//! [`verify_challenge_quote`]: attestation::verifier::verify_challenge_quote
 
 use std::{
     sync::{Arc, OnceLock},
@@ -88,7 +99,7 @@ use crate::api::def::{
     BearerAuthBackendApi, NodeBackendApi, NodeLspApi, NodeRunnerApi,
 };
 use crate::{
-    constants,
+    constants, ed25519,
     enclave::{Measurement, MrShort},
     env::DeployEnv,
     rng::Crng,
@@ -97,6 +108,8 @@ use crate::{
 
 /// Self-signed x509 cert containing enclave remote attestation endorsements.
 pub mod cert;
+/// Export and verify attestation evidence outside of TLS.
+pub mod evidence;
 /// Get a quote for the running node enclave.
 pub mod quote;
 /// Verify remote attestation endorsements directly or embedded in x509 certs.
@@ -126,12 +139,22 @@ pub fn app_node_provision_server_config(
     Ok((config, dns_name))
 }
 
+/// The pk of the attestation cert presented by a provisioning node, recorded
+/// during the first TLS handshake made with an
+/// [`app_node_provision_client_config`]. Later handshakes which present a
+/// different cert are rejected, so the pk can be checked against the quote
+/// returned in response to an [`AttestationChallenge`].
+///
+/// [`AttestationChallenge`]: verifier::AttestationChallenge
+pub type ProvisionCertPk = Arc<OnceLock<ed25519::PublicKey>>;
+
 /// Client-side TLS config for [`AppNodeProvisionApi`].
+/// Also returns the [`ProvisionCertPk`] recorded by the config's verifier.
 pub fn app_node_provision_client_config(
     use_sgx: bool,
     deploy_env: DeployEnv,
     measurement: Measurement,
-) -> rustls::ClientConfig {
+) -> (rustls::ClientConfig, ProvisionCertPk) {
     let enclave_policy = EnclavePolicy::trust_measurements_with_signer(
         use_sgx,
         deploy_env,
@@ -142,10 +165,12 @@ pub fn app_node_provision_client_config(
         enclave_policy,
     };
     let lexe_server_verifier = lexe_ca::lexe_server_verifier(deploy_env);
+    let cert_pk = ProvisionCertPk::default();
 
     let server_cert_verifier = AppNodeProvisionVerifier {
         lexe_server_verifier,
         attestation_verifier,
+        cert_pk: cert_pk.clone(),
     };
 
     let mut config = super::client_config_builder()
@@ -156,7 +181,7 @@ pub fn app_node_provision_client_config(
         .alpn_protocols
         .clone_from(&super::LEXE_ALPN_PROTOCOLS);
 
-    config
+    (config, cert_pk)
 }
 
 /// Client-side TLS config for node->Lexe APIs. This TLS config covers:
@@ -189,6 +214,42 @@ pub fn node_lexe_client_config(
     Ok(config)
 }
 
+/// The [`EvidenceBundle`] for the node's remote attestation cert, i.e. the same
+/// evidence the node presents in its TLS handshakes.
+///
+/// [`EvidenceBundle`]: evidence::EvidenceBundle
+pub fn node_evidence_bundle(
+    rng: &mut impl Crng,
+    node_mode: NodeMode,
+) -> anyhow::Result<evidence::EvidenceBundle> {
+    let (attestation_cert, _) =
+        get_or_generate_node_attestation_cert(rng, node_mode)
+            .context("Failed to get or generate node attestation cert")?;
+    evidence::EvidenceBundle::from_cert_der(attestation_cert.cert_der.clone())
+}
+
+/// Respond to an [`AttestationChallenge`] with a fresh quote which commits to
+/// both the node's remote attestation cert pk and the challenge `nonce`.
+///
+/// [`AttestationChallenge`]: verifier::AttestationChallenge
+pub fn node_challenge_quote(
+    rng: &mut impl Crng,
+    node_mode: NodeMode,
+    nonce: &[u8; 32],
+) -> anyhow::Result<Vec<u8>> {
+    let (attestation_cert, _) =
+        get_or_generate_node_attestation_cert(rng, node_mode)
+            .context("Failed to get or generate node attestation cert")?;
+    let evidence =
+        verifier::AttestEvidence::parse_cert_der(&attestation_cert.cert_der.0)
+            .context("Invalid attestation cert")?;
+    let report_data =
+        quote::ReportData::from_cert_pk_and_nonce(evidence.cert_pk(), nonce);
+    let attestation_ext = quote::quote_enclave(rng, &report_data)
+        .context("Failed to quote enclave")?;
+    Ok(attestation_ext.quote.into_owned())
+}
+
 /// The mode that the user node is currently running in, and associated info.
 #[derive(Copy, Clone)]
 pub enum NodeMode {
@@ -283,6 +344,8 @@ struct AppNodeProvisionVerifier {
     attestation_verifier: verifier::AttestationCertVerifier,
     /// Lexe server verifier - trusts the Lexe CA
     lexe_server_verifier: Arc<WebPkiServerVerifier>,
+    /// The attestation cert pk presented in the first provision handshake.
+    cert_pk: ProvisionCertPk,
 }
 
 impl ServerCertVerifier for AppNodeProvisionVerifier {
@@ -304,13 +367,30 @@ impl ServerCertVerifier for AppNodeProvisionVerifier {
             // Verify remote attestation cert when provisioning node
             Some(dns_name)
                 if dns_name.ends_with(constants::NODE_PROVISION_DNS_SUFFIX) =>
-                self.attestation_verifier.verify_server_cert(
+            {
+                let verified = self.attestation_verifier.verify_server_cert(
                     end_entity,
                     intermediates,
                     server_name,
                     ocsp_response,
                     now,
-                ),
+                )?;
+
+                // Pin the cert pk, so that the challenge quote we check later
+                // is known to come from the enclave we're talking to.
+                let evidence =
+                    verifier::AttestEvidence::parse_cert_der(end_entity)?;
+                let cert_pk = evidence.cert_pk();
+                if self.cert_pk.get_or_init(|| *cert_pk) != cert_pk {
+                    return Err(rustls::Error::General(
+                        "provisioning node presented a different attestation \
+                         cert than before"
+                            .to_owned(),
+                    ));
+                }
+
+                Ok(verified)
+            }
             // Other domains (i.e., node reverse proxy) verify using lexe CA
             _ => self.lexe_server_verifier.verify_server_cert(
                 end_entity,
@@ -385,6 +465,56 @@ mod test {
         assert!(server_result.unwrap_err().contains("Server didn't accept"));
     }
 
+    /// A challenge quote must be fresh and bind both the node's attestation
+    /// cert pk and the challenge nonce.
+    #[cfg(not(target_env = "sgx"))]
+    #[test]
+    fn challenge_quote_roundtrip() {
+        use verifier::{verify_challenge_quote, AttestationChallenge};
+
+        let mut rng = WeakRng::from_u64(20240807);
+        let mr_short = enclave::measurement().short();
+        let node_mode = NodeMode::Provision { mr_short };
+        let policy = EnclavePolicy::dangerous_trust_any();
+        let now = UnixTime::now();
+
+        let challenge = AttestationChallenge::new(&mut rng, now);
+        let quote = node_challenge_quote(&mut rng, node_mode, &challenge.nonce)
+            .unwrap();
+        let (cert, _) =
+            get_or_generate_node_attestation_cert(&mut rng, node_mode).unwrap();
+        let evidence =
+            verifier::AttestEvidence::parse_cert_der(&cert.cert_der.0).unwrap();
+        let cert_pk = evidence.cert_pk();
+
+        verify_challenge_quote(&quote, &challenge, cert_pk, true, &policy, now)
+            .unwrap();
+
+        // Someone else's challenge
+        let other = AttestationChallenge::new(&mut rng, now);
+        verify_challenge_quote(&quote, &other, cert_pk, true, &policy, now)
+            .unwrap_err();
+
+        // Some other cert
+        let other_pk = ed25519::PublicKey::new([42; 32]);
+        verify_challenge_quote(
+            &quote, &challenge, &other_pk, true, &policy, now,
+        )
+        .unwrap_err();
+
+        // Stale response
+        let window = AttestationChallenge::FRESHNESS_WINDOW;
+        let later = UnixTime::since_unix_epoch(
+            Duration::from_secs(now.as_secs())
+                + window
+                + Duration::from_secs(1),
+        );
+        verify_challenge_quote(
+            &quote, &challenge, cert_pk, true, &policy, later,
+        )
+        .unwrap_err();
+    }
+
     // Shorthand to do a App->Node Provision TLS handshake.
     async fn do_app_node_provision_tls_handshake(
         client_measurement: Measurement,
@@ -400,11 +530,12 @@ mod test {
         let expected_dns =
             constants::node_provision_dns(&server_measurement.short());
 
-        let client_config = Arc::new(app_node_provision_client_config(
+        let (client_config, _cert_pk) = app_node_provision_client_config(
             use_sgx,
             deploy_env,
             client_measurement,
-        ));
+        );
+        let client_config = Arc::new(client_config);
 
         let server_config =
             app_node_provision_server_config(&mut rng, &server_measurement)
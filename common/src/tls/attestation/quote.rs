@@ -12,7 +12,9 @@ pub use not_sgx::quote_enclave;
 use crate::ed25519;
 
 /// Small newtype for [`sgx_isa::Report::reportdata`] field.
-/// For now, we only use the first 32 out of 64 bytes to commit to a cert pk.
+/// The first 32 bytes commit to a cert pk. The last 32 bytes contain the
+/// verifier's challenge nonce for quotes made in response to an attestation
+/// challenge, and are zeroed otherwise.
 #[derive(Debug)]
 pub struct ReportData([u8; 64]);
 
@@ -30,6 +32,17 @@ impl ReportData {
         Self(report_data)
     }
 
+    /// Commit to a cert pk and a verifier's challenge nonce, so the verifier
+    /// knows the quote was generated after it issued the challenge.
+    pub fn from_cert_pk_and_nonce(
+        pk: &ed25519::PublicKey,
+        nonce: &[u8; 32],
+    ) -> Self {
+        let mut report_data = Self::from_cert_pk(pk);
+        report_data.0[32..].copy_from_slice(nonce);
+        report_data
+    }
+
     pub fn as_inner(&self) -> &[u8; 64] {
         &self.0
     }
@@ -46,6 +59,11 @@ impl ReportData {
     pub fn contains(&self, cert_pk: &ed25519::PublicKey) -> bool {
         &self.0[..32] == cert_pk.as_slice()
     }
+
+    /// The challenge nonce in the last 32 bytes (all zeroes if none).
+    pub fn nonce(&self) -> &[u8; 32] {
+        self.0[32..].try_into().expect("64 - 32 == 32")
+    }
 }
 
 #[cfg(target_env = "sgx")]
@@ -74,10 +92,11 @@ mod sgx {
         "Intel AES-NI intrinsics must be enabled at compile time via RUSTFLAGS"
     );
 
-    /// TODO(max): Needs docs
+    /// Get a quote for this enclave from the local Intel Quoting Enclave
+    /// which commits to the given [`ReportData`].
     pub fn quote_enclave(
         mut rng: &mut dyn Crng,
-        cert_pk: &ed25519::PublicKey,
+        report_data: &ReportData,
     ) -> anyhow::Result<SgxAttestationExtension<'static>> {
         // TODO(phlip9): AESM retries
 
@@ -135,11 +154,11 @@ mod sgx {
 
         // 4. Build our enclave Report
         //
-        // Bind the cert pk and QE Targetinfo to our enclave Report. When
-        // the verifier checks the attestation evidence, this linkage is
-        // what allows them to then trust the associated certificate.
+        // Bind the cert pk (and any challenge nonce) and QE Targetinfo to our
+        // enclave Report. When the verifier checks the attestation evidence,
+        // this linkage is what allows them to then trust the associated
+        // certificate.
 
-        let report_data = ReportData::from_cert_pk(cert_pk);
         let qe_target_info =
             Targetinfo::try_copy_from(qe_quote_info.target_info())
                 .context("Failed to deserialize QE Quote Targetinfo")?;
@@ -328,8 +347,8 @@ mod not_sgx {
 
     pub fn quote_enclave(
         _rng: &mut dyn Crng,
-        cert_pk: &ed25519::PublicKey,
+        report_data: &ReportData,
     ) -> anyhow::Result<SgxAttestationExtension<'static>> {
-        Ok(SgxAttestationExtension::dummy(cert_pk))
+        Ok(SgxAttestationExtension::dummy(report_data))
     }
 }
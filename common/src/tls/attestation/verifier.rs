@@ -5,6 +5,7 @@ use std::{
     include_bytes,
     io::Cursor,
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{bail, ensure, format_err, Context};
@@ -29,7 +30,9 @@ use crate::{
     ed25519,
     enclave::{self, Measurement},
     env::DeployEnv,
-    hex, sha256,
+    hex,
+    rng::{Crng, RngExt},
+    sha256,
     tls::{self, attestation::cert::SgxAttestationExtension},
 };
 
@@ -167,34 +170,11 @@ impl AttestationCertVerifier {
         // 2. extract enclave attestation quote from the cert
         let evidence = AttestEvidence::parse_cert_der(end_entity)?;
 
-        // 3. verify Quote
-        let enclave_report = if !self.expect_dummy_quote {
-            let quote_verifier = SgxQuoteVerifier;
-            quote_verifier
-                .verify(&evidence.cert_ext.quote, now)
-                .map_err(|err| {
-                    rustls_err(format!("invalid SGX Quote: {err:#}"))
-                })?
-        } else {
-            sgx_isa::Report::try_copy_from(evidence.cert_ext.quote.as_ref())
-                .ok_or_else(|| rustls_err("Could not copy Report"))?
-        };
-
-        // 4. check that this enclave satisfies our enclave policy
-        let reportdata =
-            self.enclave_policy.verify(&enclave_report).map_err(|err| {
-                rustls_err(format!(
-                    "our trust policy rejected the remote enclave: {err:#}"
-                ))
-            })?;
-
-        // 5. check that the pk in the enclave Report matches the one in this
-        //    x509 cert.
-        if !reportdata.contains(&evidence.cert_pk) {
-            return Err(rustls_err(
-                "enclave's report is not binding to the presented x509 cert",
-            ));
-        }
+        // 3. verify the quote, check that this enclave satisfies our enclave
+        //    policy, and that the quote binds to the cert pk.
+        evidence
+            .verify(self.expect_dummy_quote, &self.enclave_policy, now)
+            .map_err(|err| rustls_err(format!("{err:#}")))?;
 
         Ok(cert_verified)
     }
@@ -367,6 +347,167 @@ impl<'a> AttestEvidence<'a> {
 
         Ok(Self { cert_pk, cert_ext })
     }
+
+    /// The raw SGX quote (or dummy [`sgx_isa::Report`] outside of SGX).
+    pub fn quote(&self) -> &[u8] {
+        &self.cert_ext.quote
+    }
+
+    pub fn cert_pk(&self) -> &ed25519::PublicKey {
+        &self.cert_pk
+    }
+
+    /// Verify the quote, check that the quoted enclave satisfies the
+    /// [`EnclavePolicy`], and check that the quote binds to the cert pk.
+    /// Returns the verified application enclave [`sgx_isa::Report`].
+    pub fn verify(
+        &self,
+        expect_dummy_quote: bool,
+        enclave_policy: &EnclavePolicy,
+        now: UnixTime,
+    ) -> anyhow::Result<sgx_isa::Report> {
+        let (enclave_report, reportdata) = verify_quote(
+            &self.cert_ext.quote,
+            expect_dummy_quote,
+            enclave_policy,
+            now,
+        )?;
+
+        // Check that the pk in the enclave Report matches the one in the
+        // x509 cert.
+        ensure!(
+            reportdata.contains(&self.cert_pk),
+            "enclave's report is not binding to the presented x509 cert",
+        );
+
+        Ok(enclave_report)
+    }
+}
+
+/// A verifier-generated challenge, used to check that a remote enclave's
+/// attestation is fresh.
+///
+/// The attestation cert presented during the TLS handshake is generated once
+/// per node lifetime, so its quote alone could have been replayed from a
+/// platform which has since been compromised. Before sending secrets, the
+/// verifier sends the challenge `nonce` to the enclave, which must respond
+/// with a fresh quote committing to both its attestation cert pk and the
+/// nonce. See [`verify_challenge_quote`].
+#[derive(Clone, Debug)]
+pub struct AttestationChallenge {
+    /// The random nonce which the enclave must commit to in its quote.
+    pub nonce: [u8; 32],
+    /// When the verifier issued this challenge.
+    pub issued_at: UnixTime,
+}
+
+impl AttestationChallenge {
+    /// How long after issuing a challenge we'll accept a response to it.
+    /// Quoting can take ~1 sec on a cold machine, so this leaves plenty of
+    /// headroom while keeping the replay window short.
+    pub const FRESHNESS_WINDOW: Duration = Duration::from_secs(60);
+
+    /// Issue a new challenge with a random nonce.
+    pub fn new(rng: &mut impl Crng, now: UnixTime) -> Self {
+        Self {
+            nonce: rng.gen_bytes(),
+            issued_at: now,
+        }
+    }
+
+    /// Check that a response received at `now` is within the
+    /// [`FRESHNESS_WINDOW`](Self::FRESHNESS_WINDOW) of this challenge.
+    pub fn check_fresh(&self, now: UnixTime) -> anyhow::Result<()> {
+        let issued_at = self.issued_at.as_secs();
+        let now = now.as_secs();
+        ensure!(now >= issued_at, "Challenge was issued in the future");
+        let age = Duration::from_secs(now - issued_at);
+        ensure!(
+            age <= Self::FRESHNESS_WINDOW,
+            "Challenge response is stale: {}s > {}s",
+            age.as_secs(),
+            Self::FRESHNESS_WINDOW.as_secs(),
+        );
+        Ok(())
+    }
+}
+
+/// Verify the quote an enclave returned in response to an
+/// [`AttestationChallenge`] received at `now`: the response must be fresh, the
+/// quote must be valid and satisfy the [`EnclavePolicy`], and it must commit
+/// to both the challenge nonce and `cert_pk`, the pk of the attestation cert
+/// the enclave presented in our TLS session.
+/// Returns the verified application enclave [`sgx_isa::Report`].
+pub fn verify_challenge_quote(
+    quote: &[u8],
+    challenge: &AttestationChallenge,
+    cert_pk: &ed25519::PublicKey,
+    expect_dummy_quote: bool,
+    enclave_policy: &EnclavePolicy,
+    now: UnixTime,
+) -> anyhow::Result<sgx_isa::Report> {
+    challenge.check_fresh(now)?;
+
+    let (enclave_report, reportdata) =
+        verify_quote(quote, expect_dummy_quote, enclave_policy, now)?;
+
+    ensure!(
+        reportdata.contains(cert_pk),
+        "enclave's report is not binding to the presented x509 cert",
+    );
+    ensure!(
+        reportdata.nonce() == &challenge.nonce,
+        "enclave's report is not binding to our challenge nonce",
+    );
+
+    Ok(enclave_report)
+}
+
+/// Verify a quote (or dummy [`sgx_isa::Report`]) and check that the quoted
+/// enclave satisfies the [`EnclavePolicy`]. Returns the verified application
+/// enclave [`sgx_isa::Report`] and its [`ReportData`].
+fn verify_quote(
+    quote: &[u8],
+    expect_dummy_quote: bool,
+    enclave_policy: &EnclavePolicy,
+    now: UnixTime,
+) -> anyhow::Result<(sgx_isa::Report, ReportData)> {
+    let enclave_report = if !expect_dummy_quote {
+        SgxQuoteVerifier
+            .verify(quote, now)
+            .context("invalid SGX Quote")?
+    } else {
+        sgx_isa::Report::try_copy_from(quote)
+            .context("Could not copy Report")?
+    };
+
+    let reportdata = enclave_policy
+        .verify(&enclave_report)
+        .context("our trust policy rejected the remote enclave")?;
+
+    Ok((enclave_report, reportdata))
+}
+
+/// Extract the PEM-encoded PCK cert chain (leaf first) from an SGX quote.
+pub(super) fn quote_pck_cert_chain(
+    quote_bytes: &[u8],
+) -> anyhow::Result<Vec<String>> {
+    let quote = Quote::parse(quote_bytes)
+        .map_err(DisplayErr::new)
+        .context("Failed to parse SGX Quote")?;
+    let sig = quote
+        .signature::<Quote3SignatureEcdsaP256>()
+        .map_err(DisplayErr::new)
+        .context("Failed to parse SGX ECDSA Quote signature")?;
+    let certs = sig
+        .certification_data::<Qe3CertDataPckCertChain>()
+        .map_err(DisplayErr::new)
+        .context("Failed to parse PCK cert chain")?
+        .certs
+        .iter()
+        .map(|cert| cert.to_string())
+        .collect();
+    Ok(certs)
 }
 
 /// A verifier for validating SGX [`Quote`]s.
@@ -527,7 +668,7 @@ impl std::error::Error for DisplayErr {
     }
 }
 
-fn parse_cert_pem_to_der(s: &str) -> anyhow::Result<Vec<u8>> {
+pub(super) fn parse_cert_pem_to_der(s: &str) -> anyhow::Result<Vec<u8>> {
     let mut cursor = Cursor::new(s.as_bytes());
 
     let item = rustls_pemfile::read_one(&mut cursor)
@@ -34,7 +34,11 @@ impl SharedSeedCaCert {
 
     /// Deterministically derive the shared seed CA cert from the [`RootSeed`].
     pub fn from_root_seed(root_seed: &RootSeed) -> Self {
-        let key_pair = root_seed.derive_shared_seed_tls_ca_key_pair();
+        Self::from_key_pair(root_seed.derive_shared_seed_tls_ca_key_pair())
+    }
+
+    /// Deterministically derive the shared seed CA cert from its key pair.
+    pub fn from_key_pair(key_pair: ed25519::KeyPair) -> Self {
         // We want the cert to be deterministic, so no expiration
         let not_before = rcgen::date_time_ymd(1975, 1, 1);
         let not_after = rcgen::date_time_ymd(4096, 1, 1);
@@ -22,6 +22,13 @@
 //! shared CA, which could only have been possible if the counterparty was also
 //! able to derive the shared CA cert and keypair.
 //!
+//! When the [`RootSeed`] is rotated, both sides can accept certs from the
+//! previous CA for a while; see [`rotation`].
+//!
+//! The server also issues TLS 1.3 session tickets so that reconnecting clients
+//! can skip the full handshake. See [`ticketer`] for the forward secrecy
+//! implications and how they are bounded.
+//!
 //! [`RootSeed`]: crate::root_seed::RootSeed
 
 use std::sync::Arc;
@@ -35,50 +42,62 @@ use rustls::{
         WebPkiServerVerifier,
     },
     pki_types::{CertificateDer, ServerName, UnixTime},
-    server::WebPkiClientVerifier,
     DigitallySignedStruct, RootCertStore,
 };
 
+use self::rotation::{
+    RotatingClientCertVerifier, RotatingServerCertVerifier, SeedRotationState,
+};
 use super::lexe_ca;
 #[cfg(doc)]
 use crate::api::def::AppNodeRunApi;
-use crate::{constants, env::DeployEnv, rng::Crng, root_seed::RootSeed};
+use crate::{
+    constants, env::DeployEnv, rng::Crng, root_seed::RootSeed,
+    time::TimestampMs,
+};
 
 /// TLS certs for shared [`RootSeed`]-based mTLS.
 pub mod certs;
+/// Accepting certs from the previous CA during a [`RootSeed`] rotation.
+pub mod rotation;
+/// Rotating session ticket keys for TLS 1.3 session resumption.
+pub mod ticketer;
 
 /// Server-side TLS config for [`AppNodeRunApi`].
 /// Also returns the node's DNS name.
+///
+/// Pass the persisted [`SeedRotationState`] if the [`RootSeed`] was rotated.
 pub fn app_node_run_server_config(
     rng: &mut impl Crng,
     root_seed: &RootSeed,
+    seed_rotation: Option<&SeedRotationState>,
 ) -> anyhow::Result<(rustls::ServerConfig, String)> {
     // Derive shared seed CA cert
     let ca_cert = certs::SharedSeedCaCert::from_root_seed(root_seed);
-    let ca_cert_der = ca_cert
-        .serialize_der_self_signed()
-        .context("Failed to sign and serialize shared seed CA cert")?;
+    let previous_ca_cert = active_previous_ca_cert(seed_rotation);
+    let signing_ca_cert = previous_ca_cert.as_ref().unwrap_or(&ca_cert);
 
     // Build shared seed server cert and sign with derived CA
     let dns_name = constants::NODE_RUN_DNS.to_owned();
     let server_cert =
         certs::SharedSeedServerCert::from_rng(rng, dns_name.clone());
     let server_cert_der = server_cert
-        .serialize_der_ca_signed(&ca_cert)
+        .serialize_der_ca_signed(signing_ca_cert)
         .context("Failed to sign and serialize ephemeral server cert")?;
     let server_cert_key_der = server_cert.serialize_key_der();
 
-    // Build our ClientCertVerifier which trusts our derived CA
-    let mut roots = rustls::RootCertStore::empty();
-    roots
-        .add(ca_cert_der.into())
-        .context("rustls failed to deserialize CA cert DER bytes")?;
-    let client_cert_verifier = WebPkiClientVerifier::builder_with_provider(
-        Arc::new(roots),
-        super::LEXE_CRYPTO_PROVIDER.clone(),
-    )
-    .build()
-    .context("Failed to build client cert verifier")?;
+    // Build our ClientCertVerifier which trusts our derived CA(s)
+    let client_cert_verifier = rotation::shared_seed_client_verifier(&ca_cert)?;
+    let client_cert_verifier = match seed_rotation {
+        Some(rotation) => Arc::new(
+            RotatingClientCertVerifier::new(
+                client_cert_verifier,
+                rotation.clone(),
+            )
+            .context("Failed to build rotating client cert verifier")?,
+        ),
+        None => client_cert_verifier,
+    };
 
     let mut config = super::server_config_builder()
         .with_client_cert_verifier(client_cert_verifier)
@@ -90,24 +109,41 @@ pub fn app_node_run_server_config(
     config
         .alpn_protocols
         .clone_from(&super::LEXE_ALPN_PROTOCOLS);
+    // Issue session tickets encrypted under short-lived, enclave-held keys so
+    // reconnecting clients can resume instead of doing a full handshake.
+    config.ticketer = Arc::new(ticketer::RotatingTicketer::new());
 
     Ok((config, dns_name))
 }
 
 /// Client-side TLS config for [`AppNodeRunApi`].
+///
+/// Pass the persisted [`SeedRotationState`] if the [`RootSeed`] was rotated.
 pub fn app_node_run_client_config(
     rng: &mut impl Crng,
     deploy_env: DeployEnv,
     root_seed: &RootSeed,
+    seed_rotation: Option<&SeedRotationState>,
 ) -> anyhow::Result<rustls::ClientConfig> {
     // Derive shared seed CA cert
     let ca_cert = certs::SharedSeedCaCert::from_root_seed(root_seed);
+    let previous_ca_cert = active_previous_ca_cert(seed_rotation);
+    let signing_ca_cert = previous_ca_cert.as_ref().unwrap_or(&ca_cert);
 
     // Build the client's server cert verifier:
-    // - Shared seed verifier trusts the derived CA
+    // - Shared seed verifier trusts the derived CA(s)
     // - Public Lexe verifier trusts the hard-coded Lexe cert.
-    let shared_seed_verifier = shared_seed_verifier(&ca_cert)
-        .context("Failed to build shared seed verifier")?;
+    let shared_seed_verifier: Arc<dyn ServerCertVerifier> = {
+        let verifier = shared_seed_verifier(&ca_cert)
+            .context("Failed to build shared seed verifier")?;
+        match seed_rotation {
+            Some(rotation) => Arc::new(
+                RotatingServerCertVerifier::new(verifier, rotation.clone())
+                    .context("Failed to build rotating shared seed verifier")?,
+            ),
+            None => verifier,
+        }
+    };
     let lexe_server_verifier = lexe_ca::lexe_server_verifier(deploy_env);
     let server_cert_verifier = AppNodeRunVerifier {
         shared_seed_verifier,
@@ -117,7 +153,7 @@ pub fn app_node_run_client_config(
     // Generate shared seed client cert and sign with derived CA
     let client_cert = certs::SharedSeedClientCert::generate_from_rng(rng);
     let client_cert_der = client_cert
-        .serialize_der_ca_signed(&ca_cert)
+        .serialize_der_ca_signed(signing_ca_cert)
         .context("Failed to sign and serialize ephemeral client cert")?;
     let client_cert_key_der = client_cert.serialize_key_der();
 
@@ -143,10 +179,24 @@ pub fn app_node_run_client_config(
     config
         .alpn_protocols
         .clone_from(&super::LEXE_ALPN_PROTOCOLS);
+    // NOTE: The default `Resumption` config caches session tickets in memory,
+    // so reconnects which reuse this config will attempt session resumption.
+    // The node bounds ticket lifetimes; see [`ticketer`].
 
     Ok(config)
 }
 
+/// During an active [`RootSeed`] rotation, we sign our end-entity cert with
+/// the previous CA, which our counterparty trusts whether or not it has
+/// rotated yet.
+fn active_previous_ca_cert(
+    seed_rotation: Option<&SeedRotationState>,
+) -> Option<certs::SharedSeedCaCert> {
+    seed_rotation
+        .filter(|rotation| rotation.is_active(TimestampMs::now()))
+        .map(SeedRotationState::previous_ca_cert)
+}
+
 /// Shorthand to build a [`ServerCertVerifier`] which trusts the derived CA.
 pub fn shared_seed_verifier(
     ca_cert: &certs::SharedSeedCaCert,
@@ -186,8 +236,8 @@ pub fn shared_seed_verifier(
 /// [`NodeClient`]: crate::client::NodeClient
 #[derive(Debug)]
 struct AppNodeRunVerifier {
-    /// `run.lexe.app` shared seed verifier - trusts the derived CA
-    shared_seed_verifier: Arc<WebPkiServerVerifier>,
+    /// `run.lexe.app` shared seed verifier - trusts the derived CA(s)
+    shared_seed_verifier: Arc<dyn ServerCertVerifier>,
     /// Lexe server verifier - trusts the Lexe CA
     lexe_server_verifier: Arc<WebPkiServerVerifier>,
 }
@@ -281,6 +331,50 @@ mod test {
         server_result.unwrap();
     }
 
+    /// During a rotation window, a rotated client or server can talk to an
+    /// unrotated counterparty, or to another rotated counterparty.
+    #[tokio::test]
+    async fn app_node_run_handshake_succeeds_during_rotation() {
+        let old_seed = RootSeed::new(Secret::new([0x42; 32]));
+        let new_seed = RootSeed::new(Secret::new([0x69; 32]));
+        let rotation = SeedRotationState::new(&old_seed, TimestampMs::MAX);
+
+        let cases = [
+            (Some(&rotation), None),
+            (None, Some(&rotation)),
+            (Some(&rotation), Some(&rotation)),
+        ];
+        for (client_rotation, server_rotation) in cases {
+            let client_seed = client_rotation.map_or(&old_seed, |_| &new_seed);
+            let server_seed = server_rotation.map_or(&old_seed, |_| &new_seed);
+            let [client_result, server_result] = do_tls_handshake_inner(
+                (client_seed, client_rotation),
+                (server_seed, server_rotation),
+            )
+            .await;
+
+            client_result.unwrap();
+            server_result.unwrap();
+        }
+    }
+
+    /// After the rotation window, certs from the previous CA are rejected.
+    #[tokio::test]
+    async fn app_node_run_handshake_fails_after_rotation() {
+        let old_seed = RootSeed::new(Secret::new([0x42; 32]));
+        let new_seed = RootSeed::new(Secret::new([0x69; 32]));
+        let rotation = SeedRotationState::new(&old_seed, TimestampMs::MIN);
+
+        let [client_result, server_result] = do_tls_handshake_inner(
+            (&new_seed, Some(&rotation)),
+            (&old_seed, None),
+        )
+        .await;
+
+        assert!(client_result.unwrap_err().contains("Client didn't connect"));
+        assert!(server_result.unwrap_err().contains("Server didn't accept"));
+    }
+
     /// App->Node TLS handshake should fail when using different seeds.
     #[tokio::test]
     async fn app_node_run_handshake_fails_with_different_seeds() {
@@ -298,16 +392,27 @@ mod test {
     async fn do_app_node_run_tls_handshake(
         client_seed: &RootSeed,
         server_seed: &RootSeed,
+    ) -> [Result<(), String>; 2] {
+        do_tls_handshake_inner((client_seed, None), (server_seed, None)).await
+    }
+
+    async fn do_tls_handshake_inner(
+        (client_seed, client_rotation): (&RootSeed, Option<&SeedRotationState>),
+        (server_seed, server_rotation): (&RootSeed, Option<&SeedRotationState>),
     ) -> [Result<(), String>; 2] {
         let mut rng = WeakRng::from_u64(20240514);
         let deploy_env = DeployEnv::Dev;
 
-        let client_config =
-            app_node_run_client_config(&mut rng, deploy_env, client_seed)
-                .map(Arc::new)
-                .unwrap();
+        let client_config = app_node_run_client_config(
+            &mut rng,
+            deploy_env,
+            client_seed,
+            client_rotation,
+        )
+        .map(Arc::new)
+        .unwrap();
         let (server_config, server_dns) =
-            app_node_run_server_config(&mut rng, server_seed)
+            app_node_run_server_config(&mut rng, server_seed, server_rotation)
                 .map(|(c, d)| (Arc::new(c), d))
                 .unwrap();
 
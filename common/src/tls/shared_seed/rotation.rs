@@ -0,0 +1,291 @@
+//! Root seed rotation for shared seed mTLS.
+//!
+//! Rotating the [`RootSeed`] also rotates the derived shared seed CA, and the
+//! app and node can't switch to the new seed at exactly the same time. To
+//! avoid a hard cutover where a rotated app can no longer talk to its
+//! unrotated node (or vice versa), the rotated side persists a
+//! [`SeedRotationState`] containing the *previous* CA key pair. Until the
+//! rotation window ends, the rotated side:
+//!
+//! - signs its own end-entity certs with the previous CA, which its
+//!   counterparty trusts whether or not it has rotated yet, and
+//! - accepts certs signed by either the current or the previous CA.
+//!
+//! Once the window ends, certs signed by the previous CA are rejected and the
+//! state can be deleted.
+//!
+//! [`RootSeed`]: crate::root_seed::RootSeed
+
+use std::{fmt, sync::Arc};
+
+use anyhow::Context;
+use rustls::{
+    client::{
+        danger::{
+            HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+        },
+        WebPkiServerVerifier,
+    },
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    server::{
+        danger::{ClientCertVerified, ClientCertVerifier},
+        WebPkiClientVerifier,
+    },
+    DigitallySignedStruct, DistinguishedName, RootCertStore,
+};
+use serde::{Deserialize, Serialize};
+
+use super::certs::SharedSeedCaCert;
+use crate::{
+    api::vfs::VfsFileId, constants::SINGLETON_DIRECTORY, ed25519,
+    hexstr_or_bytes, root_seed::RootSeed, time::TimestampMs, tls,
+};
+
+/// The VFS filename of the persisted [`SeedRotationState`].
+pub const SEED_ROTATION_FILENAME: &str = "seed_rotation";
+
+/// An in-progress root seed rotation, persisted by the side which rotated.
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "test-utils"), derive(PartialEq))]
+pub struct SeedRotationState {
+    /// The seed of the shared seed CA key pair derived from the previous
+    /// [`RootSeed`].
+    #[serde(with = "hexstr_or_bytes")]
+    previous_ca_key_seed: [u8; 32],
+    /// Certs signed by the previous CA are accepted until this time.
+    pub window_end: TimestampMs,
+}
+
+impl SeedRotationState {
+    /// Start a rotation away from `previous_seed` which ends at `window_end`.
+    pub fn new(previous_seed: &RootSeed, window_end: TimestampMs) -> Self {
+        let key_pair = previous_seed.derive_shared_seed_tls_ca_key_pair();
+        Self {
+            previous_ca_key_seed: *key_pair.secret_key(),
+            window_end,
+        }
+    }
+
+    /// The [`VfsFileId`] under which this state is persisted.
+    pub fn vfs_file_id() -> VfsFileId {
+        VfsFileId::new(SINGLETON_DIRECTORY, SEED_ROTATION_FILENAME)
+    }
+
+    /// Whether the rotation window is still open at `now`.
+    pub fn is_active(&self, now: TimestampMs) -> bool {
+        now < self.window_end
+    }
+
+    /// The shared seed CA derived from the previous [`RootSeed`].
+    pub fn previous_ca_cert(&self) -> SharedSeedCaCert {
+        let key_pair = ed25519::KeyPair::from_seed(&self.previous_ca_key_seed);
+        SharedSeedCaCert::from_key_pair(key_pair)
+    }
+
+    fn is_active_at(&self, now: UnixTime) -> bool {
+        let now_ms = now.as_secs().saturating_mul(1000);
+        now_ms < self.window_end.into_u64()
+    }
+}
+
+impl fmt::Debug for SeedRotationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SeedRotationState")
+            .field("previous_ca_key_seed", &"..")
+            .field("window_end", &self.window_end)
+            .finish()
+    }
+}
+
+/// A [`ServerCertVerifier`] which trusts the current shared seed CA, and the
+/// previous shared seed CA until the end of the rotation window.
+#[derive(Debug)]
+pub(super) struct RotatingServerCertVerifier {
+    current: Arc<WebPkiServerVerifier>,
+    previous: Arc<WebPkiServerVerifier>,
+    rotation: SeedRotationState,
+}
+
+/// A [`ClientCertVerifier`] which trusts the current shared seed CA, and the
+/// previous shared seed CA until the end of the rotation window.
+#[derive(Debug)]
+pub(super) struct RotatingClientCertVerifier {
+    current: Arc<dyn ClientCertVerifier>,
+    previous: Arc<dyn ClientCertVerifier>,
+    rotation: SeedRotationState,
+    /// The subjects of both CAs, which are sent in the CertificateRequest.
+    root_hint_subjects: Vec<DistinguishedName>,
+}
+
+impl RotatingServerCertVerifier {
+    pub(super) fn new(
+        current: Arc<WebPkiServerVerifier>,
+        rotation: SeedRotationState,
+    ) -> anyhow::Result<Self> {
+        let previous =
+            super::shared_seed_verifier(&rotation.previous_ca_cert())
+                .context("Failed to build previous shared seed verifier")?;
+        Ok(Self {
+            current,
+            previous,
+            rotation,
+        })
+    }
+}
+
+impl RotatingClientCertVerifier {
+    pub(super) fn new(
+        current: Arc<dyn ClientCertVerifier>,
+        rotation: SeedRotationState,
+    ) -> anyhow::Result<Self> {
+        let previous = shared_seed_client_verifier(
+            &rotation.previous_ca_cert(),
+        )
+        .context("Failed to build previous shared seed client verifier")?;
+        let root_hint_subjects = current
+            .root_hint_subjects()
+            .iter()
+            .chain(previous.root_hint_subjects())
+            .cloned()
+            .collect();
+        Ok(Self {
+            current,
+            previous,
+            rotation,
+            root_hint_subjects,
+        })
+    }
+}
+
+/// Shorthand to build a [`ClientCertVerifier`] which trusts the derived CA.
+pub(super) fn shared_seed_client_verifier(
+    ca_cert: &SharedSeedCaCert,
+) -> anyhow::Result<Arc<dyn ClientCertVerifier>> {
+    let ca_cert_der = ca_cert
+        .serialize_der_self_signed()
+        .context("Failed to sign and serialize shared seed CA cert")?;
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(ca_cert_der.into())
+        .context("rustls failed to deserialize CA cert DER bytes")?;
+    WebPkiClientVerifier::builder_with_provider(
+        Arc::new(roots),
+        tls::LEXE_CRYPTO_PROVIDER.clone(),
+    )
+    .build()
+    .context("Failed to build client cert verifier")
+}
+
+impl ServerCertVerifier for RotatingServerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer,
+        intermediates: &[CertificateDer],
+        server_name: &ServerName,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verify = |verifier: &WebPkiServerVerifier| {
+            verifier.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            )
+        };
+
+        verify(&self.current).or_else(|err| {
+            if self.rotation.is_active_at(now) {
+                // Return the original error if this fails too.
+                verify(&self.previous).map_err(|_| err)
+            } else {
+                Err(err)
+            }
+        })
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        // We intentionally do not support TLSv1.2.
+        let error = rustls::PeerIncompatible::ServerDoesNotSupportTls12Or13;
+        Err(rustls::Error::PeerIncompatible(error))
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &tls::LEXE_SIGNATURE_ALGORITHMS,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        tls::LEXE_SUPPORTED_VERIFY_SCHEMES.clone()
+    }
+}
+
+impl ClientCertVerifier for RotatingClientCertVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &self.root_hint_subjects
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer,
+        intermediates: &[CertificateDer],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        let verify = |verifier: &dyn ClientCertVerifier| {
+            verifier.verify_client_cert(end_entity, intermediates, now)
+        };
+
+        verify(self.current.as_ref()).or_else(|err| {
+            if self.rotation.is_active_at(now) {
+                // Return the original error if this fails too.
+                verify(self.previous.as_ref()).map_err(|_| err)
+            } else {
+                Err(err)
+            }
+        })
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        // We intentionally do not support TLSv1.2.
+        let error = rustls::PeerIncompatible::ServerDoesNotSupportTls12Or13;
+        Err(rustls::Error::PeerIncompatible(error))
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &tls::LEXE_SIGNATURE_ALGORITHMS,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        tls::LEXE_SUPPORTED_VERIFY_SCHEMES.clone()
+    }
+}
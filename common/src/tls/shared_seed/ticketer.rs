@@ -0,0 +1,213 @@
+//! TLS 1.3 session resumption tickets for shared seed mTLS.
+//!
+//! ## Overview
+//!
+//! App<->node connections are frequently torn down and re-established, e.g.
+//! whenever a mobile device switches networks. Session resumption lets the
+//! client skip the full handshake (and the associated cert generation and
+//! verification) on reconnect by presenting a session ticket previously issued
+//! by the node.
+//!
+//! ## Forward secrecy tradeoff
+//!
+//! Session tickets are encrypted under a ticket key held by the server, so
+//! anyone who compromises a ticket key can decrypt the tickets encrypted under
+//! it, which includes the resumption secrets for those sessions. To bound this:
+//!
+//! - Ticket keys are sampled from the enclave's RNG, live only in enclave
+//!   memory, and are never persisted or derived from the [`RootSeed`].
+//! - Ticket keys are rotated every [`SESSION_TICKET_LIFETIME`]. The previous
+//!   key is retained for one more period so that recently issued tickets remain
+//!   valid, after which it is dropped. Thus a given ticket key is held for at
+//!   most 2 * [`SESSION_TICKET_LIFETIME`].
+//! - Clients are told to discard tickets after [`SESSION_TICKET_LIFETIME`].
+//!
+//! [`RootSeed`]: crate::root_seed::RootSeed
+
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rustls::server::ProducesTickets;
+
+use crate::{
+    aes::AesMasterKey,
+    rng::{RngExt, SysRng},
+};
+
+/// How long issued session tickets are valid for, which is also how often the
+/// ticket encryption key is rotated. Kept short to bound the forward secrecy
+/// tradeoff described in the module docs.
+pub const SESSION_TICKET_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Binds ciphertexts to their use as session tickets.
+const TICKET_AAD: &[u8] = b"LEXE-REALM::SharedSeedSessionTicket";
+
+/// A [`ProducesTickets`] impl which encrypts session tickets under ephemeral,
+/// enclave-held keys that are rotated every `lifetime`.
+pub struct RotatingTicketer {
+    lifetime: Duration,
+    keys: Mutex<TicketKeys>,
+}
+
+struct TicketKeys {
+    current: AesMasterKey,
+    previous: Option<AesMasterKey>,
+    /// When `current` should be rotated into `previous`.
+    rotate_at: Instant,
+}
+
+impl RotatingTicketer {
+    /// A [`RotatingTicketer`] with the default [`SESSION_TICKET_LIFETIME`].
+    pub fn new() -> Self {
+        Self::with_lifetime(SESSION_TICKET_LIFETIME, Instant::now())
+    }
+
+    fn with_lifetime(lifetime: Duration, now: Instant) -> Self {
+        let keys = TicketKeys {
+            current: gen_ticket_key(),
+            previous: None,
+            rotate_at: now + lifetime,
+        };
+        Self {
+            lifetime,
+            keys: Mutex::new(keys),
+        }
+    }
+
+    fn encrypt_at(&self, plain: &[u8], now: Instant) -> Vec<u8> {
+        let mut keys = self.keys.lock().unwrap();
+        keys.maybe_rotate(self.lifetime, now);
+
+        let mut rng = SysRng::new();
+        keys.current.encrypt(
+            &mut rng,
+            &[TICKET_AAD],
+            Some(plain.len()),
+            &|out: &mut Vec<u8>| out.extend_from_slice(plain),
+        )
+    }
+
+    fn decrypt_at(&self, cipher: &[u8], now: Instant) -> Option<Vec<u8>> {
+        let mut keys = self.keys.lock().unwrap();
+        keys.maybe_rotate(self.lifetime, now);
+
+        let aad = &[TICKET_AAD];
+        keys.current.decrypt(aad, cipher.to_vec()).ok().or_else(|| {
+            let previous = keys.previous.as_ref()?;
+            previous.decrypt(aad, cipher.to_vec()).ok()
+        })
+    }
+}
+
+impl Default for RotatingTicketer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RotatingTicketer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RotatingTicketer")
+            .field("lifetime", &self.lifetime)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ProducesTickets for RotatingTicketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        u32::try_from(self.lifetime.as_secs()).unwrap_or(u32::MAX)
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        Some(self.encrypt_at(plain, Instant::now()))
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        self.decrypt_at(cipher, Instant::now())
+    }
+}
+
+impl TicketKeys {
+    fn maybe_rotate(&mut self, lifetime: Duration, now: Instant) {
+        if now < self.rotate_at {
+            return;
+        }
+
+        // If we've been idle for more than a full period past the scheduled
+        // rotation, then even the current key has outlived its window.
+        let current = std::mem::replace(&mut self.current, gen_ticket_key());
+        self.previous = if now < self.rotate_at + lifetime {
+            Some(current)
+        } else {
+            None
+        };
+        self.rotate_at = now + lifetime;
+    }
+}
+
+/// Sample a fresh ticket key. These are intentionally *not* derived from the
+/// root seed; see module docs.
+fn gen_ticket_key() -> AesMasterKey {
+    let mut rng = SysRng::new();
+    AesMasterKey::new(&rng.gen_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LIFETIME: Duration = Duration::from_secs(100);
+
+    #[test]
+    fn ticket_roundtrip() {
+        let now = Instant::now();
+        let ticketer = RotatingTicketer::with_lifetime(LIFETIME, now);
+
+        let ticket = ticketer.encrypt_at(b"session state", now);
+        let plain = ticketer.decrypt_at(&ticket, now).unwrap();
+        assert_eq!(plain, b"session state");
+
+        // Tampered tickets are rejected
+        let mut tampered = ticket.clone();
+        *tampered.last_mut().unwrap() ^= 0x01;
+        assert!(ticketer.decrypt_at(&tampered, now).is_none());
+
+        // Tickets from another ticketer are rejected
+        let other = RotatingTicketer::with_lifetime(LIFETIME, now);
+        assert!(other.decrypt_at(&ticket, now).is_none());
+    }
+
+    #[test]
+    fn tickets_survive_one_rotation_only() {
+        let now = Instant::now();
+        let ticketer = RotatingTicketer::with_lifetime(LIFETIME, now);
+        let ticket = ticketer.encrypt_at(b"hello", now);
+
+        // After one rotation, the ticket is still accepted via the previous key
+        let t1 = now + LIFETIME;
+        assert!(ticketer.decrypt_at(&ticket, t1).is_some());
+
+        // After the second rotation, the original key has been dropped
+        let t2 = t1 + LIFETIME;
+        assert!(ticketer.decrypt_at(&ticket, t2).is_none());
+    }
+
+    #[test]
+    fn long_idle_drops_all_keys() {
+        let now = Instant::now();
+        let ticketer = RotatingTicketer::with_lifetime(LIFETIME, now);
+        let ticket = ticketer.encrypt_at(b"hello", now);
+
+        // No activity for more than two periods: the first access after idling
+        // must not accept the stale ticket.
+        let later = now + LIFETIME * 2 + Duration::from_secs(1);
+        assert!(ticketer.decrypt_at(&ticket, later).is_none());
+    }
+}
@@ -0,0 +1,151 @@
+//! API revision negotiation.
+//!
+//! - [`ApiRevisions`]: the range of API revisions a client or server supports,
+//!   and [`ApiRevisions::negotiate`] to pick the highest mutually supported
+//!   revision.
+//! - [`APP_NODE_REVISIONS`]: the revisions of the app <-> node API served by
+//!   the current node, each under its own `/v{N}/app/...` route tree.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use anyhow::{ensure, Context};
+use http::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+/// The header used to exchange [`ApiRevisions`], formatted like "1-2".
+///
+/// - Clients may send the revisions they understand, in which case the server
+///   rejects requests to any other revision instead of returning a response the
+///   client might misinterpret.
+/// - Servers return the revisions they serve in every response, so clients can
+///   tell when they need to upgrade.
+pub static API_REVISIONS_HEADER: HeaderName =
+    HeaderName::from_static("lexe-api-revisions");
+
+/// The first revision of the app <-> node API. Also served at the unprefixed
+/// `/app/...` paths used by apps which predate API versioning.
+pub const APP_NODE_R1: ApiRevision = ApiRevision(1);
+/// The second revision of the app <-> node API. Identical to [`APP_NODE_R1`]
+/// until an endpoint needs a breaking change.
+pub const APP_NODE_R2: ApiRevision = ApiRevision(2);
+/// The revisions of the app <-> node API served by the current node.
+pub const APP_NODE_REVISIONS: ApiRevisions = ApiRevisions {
+    min: APP_NODE_R1,
+    max: APP_NODE_R2,
+};
+/// App <-> node API revisions which are still served, but which apps should
+/// stop using. Responses to these revisions include a `deprecation: true`
+/// header. A revision should stay deprecated for long enough that most users
+/// have upgraded before it is removed from [`APP_NODE_REVISIONS`].
+pub const APP_NODE_DEPRECATED: &[ApiRevision] = &[];
+
+/// A numbered revision of an API. Bumped on every breaking change.
+///
+/// Request and response models which change in revision N get a `V{N}` suffix
+/// (e.g. `BasicPaymentV2`), and are only used by revision N's routes, so that
+/// older revisions keep returning the models older clients understand.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Serialize, Deserialize)]
+pub struct ApiRevision(pub u16);
+
+/// The inclusive range of [`ApiRevision`]s supported by a client or server.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiRevisions {
+    pub min: ApiRevision,
+    pub max: ApiRevision,
+}
+
+// --- impl ApiRevisions --- //
+
+impl ApiRevisions {
+    pub fn new(min: ApiRevision, max: ApiRevision) -> Self {
+        debug_assert!(min <= max, "Empty range: {min:?} > {max:?}");
+        Self { min, max }
+    }
+
+    pub fn contains(&self, revision: ApiRevision) -> bool {
+        self.min <= revision && revision <= self.max
+    }
+
+    /// Pick the highest revision supported by both `self` and `other`, or
+    /// [`None`] if the ranges don't overlap.
+    pub fn negotiate(&self, other: &Self) -> Option<ApiRevision> {
+        let highest = self.max.min(other.max);
+        let lowest = self.min.max(other.min);
+        (lowest <= highest).then_some(highest)
+    }
+}
+
+impl ApiRevision {
+    /// The path prefix of this revision's route tree, e.g. "/v2".
+    pub fn path_prefix(&self) -> String {
+        format!("/v{}", self.0)
+    }
+}
+
+impl Display for ApiRevision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
+impl ApiRevisions {
+    /// Format as an [`API_REVISIONS_HEADER`] value.
+    pub fn to_header_value(&self) -> HeaderValue {
+        let value = format!("{}-{}", self.min.0, self.max.0);
+        HeaderValue::try_from(value).expect("Digits and '-' are valid")
+    }
+
+    /// Parse an [`API_REVISIONS_HEADER`] value.
+    pub fn from_header_value(value: &HeaderValue) -> anyhow::Result<Self> {
+        value
+            .to_str()
+            .context("Header is not ASCII")?
+            .parse::<Self>()
+    }
+}
+
+impl FromStr for ApiRevisions {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s.split_once('-').context("Missing '-'")?;
+        let min = ApiRevision(min.parse().context("Invalid min revision")?);
+        let max = ApiRevision(max.parse().context("Invalid max revision")?);
+        ensure!(min <= max, "Empty range: {min} > {max}");
+        Ok(Self { min, max })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn api_revision_negotiate() {
+        let revs =
+            |min, max| ApiRevisions::new(ApiRevision(min), ApiRevision(max));
+
+        assert_eq!(revs(1, 3).negotiate(&revs(2, 5)), Some(ApiRevision(3)));
+        assert_eq!(revs(2, 5).negotiate(&revs(1, 3)), Some(ApiRevision(3)));
+        assert_eq!(revs(1, 1).negotiate(&revs(1, 1)), Some(ApiRevision(1)));
+        assert_eq!(revs(1, 4).negotiate(&revs(2, 3)), Some(ApiRevision(3)));
+        assert_eq!(revs(1, 2).negotiate(&revs(3, 4)), None);
+    }
+
+    #[test]
+    fn api_revisions_header_roundtrip() {
+        let value = APP_NODE_REVISIONS.to_header_value();
+        assert_eq!(value, "1-2");
+        let parsed = ApiRevisions::from_header_value(&value).unwrap();
+        assert_eq!(parsed, APP_NODE_REVISIONS);
+
+        assert!("2-1".parse::<ApiRevisions>().is_err());
+        assert!("1".parse::<ApiRevisions>().is_err());
+        assert!("r1-r2".parse::<ApiRevisions>().is_err());
+        assert_eq!(APP_NODE_R2.path_prefix(), "/v2");
+    }
+}
@@ -6,7 +6,10 @@ use serde::{de::DeserializeOwned, Serialize};
 use tokio::sync::watch;
 
 use crate::{
-    models::{Empty, GFile, GFileCow, GFileId, ListFiles, ListFilesResponse},
+    models::{
+        About, Empty, GFile, GFileCow, GFileId, GetAbout, GetFile, ListFiles,
+        ListFilesResponse,
+    },
     oauth2::{self, GDriveCredentials, ReqwestClient},
     Error,
 };
@@ -15,6 +18,11 @@ const BASE_URL: &str = "https://www.googleapis.com/drive/v3";
 const BASE_UPLOAD_URL: &str = "https://www.googleapis.com/upload/drive/v3";
 pub(crate) const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
 pub(crate) const BINARY_MIME_TYPE: &str = "application/octet-stream";
+/// The default file fields, plus `size` and `headRevisionId`.
+const FILE_FIELDS: &str = "id,name,mimeType,size,headRevisionId";
+/// [`FILE_FIELDS`] for each file in a `files.list` response.
+const LIST_FILES_FIELDS: &str =
+    "nextPageToken,files(id,name,mimeType,size,headRevisionId)";
 
 /// A crate-private Google Drive API client which:
 ///
@@ -84,7 +92,7 @@ impl GDriveClient {
     }
 
     /// Given the [`GFileId`] of a directory, returns a list of metadatas for
-    /// all of its direct children.
+    /// all of its direct children, including their sizes.
     pub async fn list_direct_children(
         &self,
         parent_id: &GFileId,
@@ -93,6 +101,7 @@ impl GDriveClient {
         let mut data = ListFiles {
             q: q.into(),
             order_by: Some("name".into()),
+            fields: Some(LIST_FILES_FIELDS.into()),
             ..Default::default()
         };
 
@@ -190,7 +199,7 @@ impl GDriveClient {
 
         let method = Method::POST;
         let url = format!("{BASE_UPLOAD_URL}/files");
-        let query = [("uploadType", "multipart")];
+        let query = [("uploadType", "multipart"), ("fields", FILE_FIELDS)];
 
         let metadata = GFileCow {
             id: None,
@@ -242,7 +251,7 @@ impl GDriveClient {
         let req = self
             .client
             .request(method, url)
-            .query(&[("uploadType", "media")])
+            .query(&[("uploadType", "media"), ("fields", FILE_FIELDS)])
             .header("Content-Type", BINARY_MIME_TYPE)
             .header("Content-Length", data.len())
             .body(data);
@@ -250,6 +259,50 @@ impl GDriveClient {
         self.send_and_deserialize(req).await
     }
 
+    /// "files.update":
+    /// PATCH {BASE_URL}/files/{fileId}?addParents=..&removeParents=..
+    ///
+    /// Moves the file with the given [`GFileId`] from `from_parent` into
+    /// `to_parent`, renaming it to `new_name`. The file contents and
+    /// [`GFileId`] are unchanged.
+    ///
+    /// <https://developers.google.com/drive/api/reference/rest/v3/files/update>
+    pub async fn move_file(
+        &self,
+        id: &GFileId,
+        new_name: &str,
+        from_parent: &GFileId,
+        to_parent: &GFileId,
+    ) -> Result<GFile, Error> {
+        let url = format!("{BASE_URL}/files/{id}");
+        let query = [
+            ("addParents", to_parent.0.as_str()),
+            ("removeParents", from_parent.0.as_str()),
+        ];
+        // Only send the name; other `GFileCow` fields would be sent as `null`.
+        let metadata = serde_json::json!({ "name": new_name });
+
+        let req = self.client.patch(url).query(&query).json(&metadata);
+        self.send_and_deserialize(req).await
+    }
+
+    /// "files.get": GET /files/{id}
+    ///
+    /// Fetches the metadata of a file, including its `headRevisionId`.
+    ///
+    /// <https://developers.google.com/drive/api/reference/rest/v3/files/get>
+    pub async fn get_file_metadata(
+        &self,
+        gid: &GFileId,
+    ) -> Result<GFile, Error> {
+        let url = format!("{BASE_URL}/files/{gid}");
+        let data = GetFile {
+            fields: FILE_FIELDS.into(),
+        };
+        let req = self.get(url, &data);
+        self.send_and_deserialize(req).await
+    }
+
     /// "files.get": GET /files/{id}?alt=media
     ///
     /// Downloads a blob file given its ID.
@@ -280,6 +333,20 @@ impl GDriveClient {
         Ok(())
     }
 
+    /// "about.get": GET /about?fields=storageQuota
+    ///
+    /// Returns the user's storage limit and usage.
+    ///
+    /// <https://developers.google.com/drive/api/reference/rest/v3/about/get>
+    pub async fn get_about(&self) -> Result<About, Error> {
+        let url = format!("{BASE_URL}/about");
+        let data = GetAbout {
+            fields: "storageQuota".into(),
+        };
+        let req = self.get(url, &data);
+        self.send_and_deserialize(req).await
+    }
+
     /// Create a GET request and serialize the given data into query params.
     #[inline]
     fn get(
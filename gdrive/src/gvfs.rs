@@ -5,27 +5,72 @@
 // variable names to denote "Google" or "VFS" respectively. 'VFS' refers to the
 // VFS abstraction while 'GVFS' refers to the actual layout of files in GDrive.
 
-use std::{collections::BTreeMap, str::FromStr};
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 use anyhow::{anyhow, bail, ensure, Context};
 use common::{
-    api::vfs::{VfsDirectory, VfsFile, VfsFileId},
+    api::{
+        command::{GDriveFileSize, GDriveStorageStatus},
+        vfs::{VfsDirectory, VfsFile, VfsFileId},
+    },
     cli::Network,
-    constants, Apply,
+    constants,
+    iter::IteratorExt,
+    time::TimestampMs,
+    Apply,
 };
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use tokio::sync::watch;
 use tracing::{instrument, warn};
 
 use crate::{
-    api, api::GDriveClient, gvfs_file_id::GvfsFileId, lexe_dir,
-    models::GFileId, oauth2::GDriveCredentials,
+    api,
+    api::GDriveClient,
+    gvfs_file_id::GvfsFileId,
+    lexe_dir,
+    models::{GFile, GFileId},
+    oauth2::GDriveCredentials,
 };
 
 // Allows tests to assert that these `anyhow::Error`s happened.
 pub const CREATE_DUPE_MSG: &str = "Tried to create duplicate";
 pub const NOT_FOUND_MSG: &str = "not found";
 
+/// The # of files to include in [`GDriveStorageStatus::largest_files`].
+pub const NUM_LARGEST_FILES: usize = 5;
+
+/// The suffix of the GDrive folder which holds this network's deleted files
+/// until they are purged, e.g. "bitcoin.trash". The folder lives next to the
+/// GVFS root in the LexeData dir rather than inside it, since older nodes
+/// refuse to start if the GVFS root contains anything but GVFS files.
+pub const TRASH_DIRNAME_SUFFIX: &str = ".trash";
+/// How long deleted files should be kept in the trash before being purged.
+pub const TRASH_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Returned (inside the [`anyhow::Error`]) when updating a file which another
+/// writer modified since we last wrote it, e.g. because two nodes are running
+/// against the same Google account. Check for it with
+/// `err.downcast_ref::<ConflictError>()`.
+#[derive(Debug, Error)]
+#[error(
+    "{vfile_id} was modified by another writer: expected revision \
+     {expected}, found {}",
+    .actual.as_deref().unwrap_or("none"),
+)]
+pub struct ConflictError {
+    pub vfile_id: VfsFileId,
+    /// The head revision we last wrote or saw.
+    pub expected: String,
+    /// The file's current head revision.
+    pub actual: Option<String>,
+}
+
 /// Opaque object containing info about the GVFS root. Crate users should
 /// persist this and resupply it the next time [`GoogleVfs`] is initialized.
 #[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -42,8 +87,13 @@ pub struct GvfsRoot {
 /// - [`create_file`](Self::create_file)
 /// - [`upsert_file`](Self::upsert_file)
 /// - [`delete_file`](Self::delete_file)
+/// - [`undelete_file`](Self::undelete_file)
+/// - [`purge_trash`](Self::purge_trash)
 /// - [`get_directory`](Self::get_directory)
 ///
+/// It can also report its storage usage with
+/// [`storage_status`](Self::storage_status).
+///
 /// ### Characteristics
 ///
 /// - Initialization takes 1 API round trip (~250ms) in the majority of cases.
@@ -55,16 +105,38 @@ pub struct GvfsRoot {
 /// - The internal cache assumes that this [`GoogleVfs`] instance is the only
 ///   one modifying the underlying data store. DO NOT concurrently access data
 ///   stored in Google Drive from multiple locations.
+/// - To catch violations of the above, the cache also tracks the head revision
+///   of each file. While updating a file, [`upsert_file`] concurrently fetches
+///   its head revision, and returns a [`ConflictError`] if it is neither the
+///   revision we last wrote nor the one we just wrote. The Drive v3 API doesn't
+///   support `If-Match` preconditions, so the other writer's revision may
+///   already have been overwritten by then; Drive keeps it in the file's
+///   revision history. Once a conflict is detected, all further writes are
+///   refused; see [`has_conflict`].
+/// - Revisions are only tracked in memory, so conflicts are only detected
+///   within the lifetime of this instance. [`init`] adopts whatever revisions
+///   it finds, including those written by another writer. Callers which fence
+///   off other writers should call [`rebase_revisions`] once they have done so,
+///   after which any change by another writer is a conflict.
+/// - Deleted files are moved into a trash folder rather than being destroyed,
+///   so that a buggy delete (e.g. of a channel monitor) can be reverted with
+///   [`undelete_file`]. Trashed files are only permanently deleted by
+///   [`purge_trash`] once they are older than the retention period.
 ///
+/// [`init`]: Self::init
 /// [`get_file`]: Self::get_file
 /// [`create_file`]: Self::create_file
 /// [`upsert_file`]: Self::upsert_file
+/// [`has_conflict`]: Self::has_conflict
+/// [`rebase_revisions`]: Self::rebase_revisions
 /// [`delete_file`]: Self::delete_file
+/// [`undelete_file`]: Self::undelete_file
+/// [`purge_trash`]: Self::purge_trash
 /// [`get_directory`]: Self::get_directory
 pub struct GoogleVfs {
     client: GDriveClient,
     gvfs_root: GvfsRoot,
-    /// Caches the [`GFileId`]s of all GVFS files.
+    /// Caches the [`GFileId`]s and head revisions of all GVFS files.
     ///
     /// ### Cache invariants
     ///
@@ -102,7 +174,32 @@ pub struct GoogleVfs {
     /// The reader-writer lock would force thread 2 to wait until thread 1 has
     /// finished its write; thread 2 would then see that the file already
     /// exists and would not create a duplicate.
-    gid_cache: tokio::sync::RwLock<BTreeMap<VfsFileId, GFileId>>,
+    gid_cache: tokio::sync::RwLock<BTreeMap<VfsFileId, CachedGFile>>,
+    /// The [`GFileId`] of the trash folder (see [`TRASH_DIRNAME_SUFFIX`]),
+    /// once we've found it. It is created lazily the first time a file is
+    /// deleted.
+    ///
+    /// To avoid deadlocks, this lock must only be acquired while already
+    /// holding the `gid_cache` lock.
+    trash_gid: tokio::sync::Mutex<Option<GFileId>>,
+    /// Set once we've detected another writer, after which we refuse to write.
+    conflicted: AtomicBool,
+}
+
+/// A GVFS file in the [`GoogleVfs::gid_cache`].
+struct CachedGFile {
+    gid: GFileId,
+    /// The head revision we last wrote or saw. [`None`] if Drive didn't tell
+    /// us, in which case we can't check for conflicts.
+    head_revision_id: Option<String>,
+}
+
+/// A file in the GVFS trash, named `<deleted_at>-<gvfile_id>`.
+struct TrashedGFile {
+    gid: GFileId,
+    gvfile_id: GvfsFileId,
+    deleted_at: TimestampMs,
+    head_revision_id: Option<String>,
 }
 
 impl GoogleVfs {
@@ -230,8 +327,8 @@ impl GoogleVfs {
                 let gvfile_id = GvfsFileId::from_str(&gfile.name)
                     .context("GFile did not have a valid gvfile_id")?;
                 let vfile_id = gvfile_id.to_vfile_id();
-                let vfile_gid = gfile.id;
-                Ok((vfile_id, vfile_gid))
+                let cached = CachedGFile::from(gfile);
+                Ok((vfile_id, cached))
             })
             .collect::<anyhow::Result<BTreeMap<_, _>>>()
             .context("Could not build gid cache")?
@@ -248,11 +345,56 @@ impl GoogleVfs {
             client,
             gvfs_root,
             gid_cache,
+            trash_gid: tokio::sync::Mutex::new(None),
+            conflicted: AtomicBool::new(false),
         };
 
         Ok((myself, gvfs_root_to_persist))
     }
 
+    /// Whether another writer was detected, in which case all writes are
+    /// refused. See the [`GoogleVfs`] docs.
+    pub fn has_conflict(&self) -> bool {
+        self.conflicted.load(Ordering::Acquire)
+    }
+
+    /// Re-reads the head revision of every file, and uses them as the
+    /// baseline for detecting other writers from now on. Call this once any
+    /// other writers have been fenced off. Files which changed since [`init`]
+    /// are logged, since another writer modified them in the meantime.
+    ///
+    /// [`init`]: Self::init
+    #[instrument(skip_all, name = "(gvfs-rebase-revisions)")]
+    pub async fn rebase_revisions(&self) -> anyhow::Result<()> {
+        let mut locked_cache = self.gid_cache.write().await;
+
+        let gfiles = self
+            .client
+            .list_direct_children(&self.gvfs_root.gid)
+            .await
+            .context("list_direct_children")?;
+
+        for gfile in gfiles {
+            let gvfile_id = GvfsFileId::from_str(&gfile.name)
+                .context("GFile did not have a valid gvfile_id")?;
+            let vfile_id = gvfile_id.to_vfile_id();
+            match locked_cache.get_mut(&vfile_id) {
+                Some(cached) => {
+                    if cached.head_revision_id != gfile.head_revision_id {
+                        warn!("{vfile_id} was modified by another writer");
+                    }
+                    cached.head_revision_id = gfile.head_revision_id;
+                }
+                None => {
+                    warn!("{vfile_id} was created by another writer");
+                    locked_cache.insert(vfile_id, CachedGFile::from(gfile));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Whether a file for the given [`VfsFileId`] exists.
     /// This method only reads from the cache so it is essentially free.
     pub async fn file_exists(&self, vfile_id: &VfsFileId) -> bool {
@@ -266,7 +408,7 @@ impl GoogleVfs {
     ) -> anyhow::Result<Option<VfsFile>> {
         let locked_cache = self.gid_cache.read().await;
         let vfile_gid = match locked_cache.get(vfile_id) {
-            Some(gid) => gid.clone(),
+            Some(cached) => cached.gid.clone(),
             // No gid => no file, by cache invariants
             None => return Ok(None),
         };
@@ -290,6 +432,7 @@ impl GoogleVfs {
     #[instrument(skip_all, name = "(gvfs-create-file)")]
     pub async fn create_file(&self, vfile: VfsFile) -> anyhow::Result<()> {
         let mut locked_cache = self.gid_cache.write().await;
+        self.ensure_no_conflict()?;
 
         // First, confirm that the file doesn't already exist.
         if locked_cache.get(&vfile.id).is_some() {
@@ -300,7 +443,7 @@ impl GoogleVfs {
 
         // Upload the blob file into the GVFS root.
         let gvfile_id = GvfsFileId::try_from(&vfile.id)?;
-        let gid = self
+        let gfile = self
             .client
             .create_blob_file(
                 self.gvfs_root.gid.clone(),
@@ -308,9 +451,8 @@ impl GoogleVfs {
                 vfile.data,
             )
             .await
-            .context("create_blob_file")?
-            .id;
-        locked_cache.insert(vfile.id, gid);
+            .context("create_blob_file")?;
+        locked_cache.insert(vfile.id, CachedGFile::from(gfile));
 
         Ok(())
     }
@@ -318,22 +460,41 @@ impl GoogleVfs {
     #[instrument(skip_all, name = "(gvfs-upsert-file)")]
     pub async fn upsert_file(&self, vfile: VfsFile) -> anyhow::Result<()> {
         let mut locked_cache = self.gid_cache.write().await;
-
-        // If the file exists, update it
-        if let Some(gid) = locked_cache.get(&vfile.id) {
-            return self
-                .client
-                .update_blob_file(gid.clone(), vfile.data)
-                .await
-                .map(|_| ())
-                .context("update_blob_file");
+        self.ensure_no_conflict()?;
+
+        // If the file exists, update it, checking that nobody else has. The
+        // head revision is fetched concurrently so the check doesn't add a
+        // round trip.
+        if let Some(cached) = locked_cache.get_mut(&vfile.id) {
+            let (try_current, try_updated) = futures::join!(
+                self.client.get_file_metadata(&cached.gid),
+                self.client.update_blob_file(cached.gid.clone(), vfile.data),
+            );
+            let updated = try_updated.context("update_blob_file")?;
+            let expected = std::mem::replace(
+                &mut cached.head_revision_id,
+                updated.head_revision_id,
+            );
+            return match try_current {
+                Ok(current) => self.check_unmodified(
+                    &vfile.id,
+                    expected,
+                    current.head_revision_id,
+                    cached.head_revision_id.as_ref(),
+                ),
+                // The update went through; we'll check again next time.
+                Err(e) => {
+                    warn!("Couldn't check {} for conflicts: {e:#}", vfile.id);
+                    Ok(())
+                }
+            };
         }
         // From here, we know the file doesn't exist. Create it.
         // NOTE: We don't use `create_file` here in order to avoid a deadlock.
 
         // Upload the blob file into the GVFS root.
         let gvfile_id = GvfsFileId::try_from(&vfile.id)?;
-        let gid = self
+        let gfile = self
             .client
             .create_blob_file(
                 self.gvfs_root.gid.clone(),
@@ -341,13 +502,15 @@ impl GoogleVfs {
                 vfile.data,
             )
             .await
-            .context("create_blob_file")?
-            .id;
-        locked_cache.insert(vfile.id, gid);
+            .context("create_blob_file")?;
+        locked_cache.insert(vfile.id, CachedGFile::from(gfile));
 
         Ok(())
     }
 
+    /// Moves the file into the GVFS trash, where it can be restored with
+    /// [`undelete_file`](Self::undelete_file) until it is purged.
+    ///
     /// The error will contain [`NOT_FOUND_MSG`] if the file was not found.
     #[instrument(skip_all, name = "(gvfs-delete-file)")]
     pub async fn delete_file(
@@ -355,9 +518,10 @@ impl GoogleVfs {
         vfile_id: &VfsFileId,
     ) -> anyhow::Result<()> {
         let mut locked_cache = self.gid_cache.write().await;
+        self.ensure_no_conflict()?;
 
         let gid = match locked_cache.get(vfile_id) {
-            Some(gid) => gid,
+            Some(cached) => &cached.gid,
             None => {
                 let dirname = &vfile_id.dir.dirname;
                 let filename = &vfile_id.filename;
@@ -365,11 +529,19 @@ impl GoogleVfs {
             }
         };
 
+        let trash_gid = self
+            .get_trash_dir(true)
+            .await
+            .context("Failed to get trash dir")?
+            .expect("Created if missing");
+        let gvfile_id = GvfsFileId::try_from(vfile_id)?;
+        let trashed_name = format!("{}-{gvfile_id}", TimestampMs::now());
+
         self.client
-            .delete_file(gid)
+            .move_file(gid, &trashed_name, &self.gvfs_root.gid, &trash_gid)
             .await
             .map(|_| ())
-            .context("Failed to delete gdrive file")?;
+            .context("Failed to move gdrive file to trash")?;
 
         locked_cache
             .remove(vfile_id)
@@ -378,6 +550,103 @@ impl GoogleVfs {
         Ok(())
     }
 
+    /// Restores the most recently deleted version of the given file from the
+    /// GVFS trash. Returns `false` if the trash has no version of the file.
+    ///
+    /// The error will contain [`CREATE_DUPE_MSG`] if the file currently exists.
+    #[instrument(skip_all, name = "(gvfs-undelete-file)")]
+    pub async fn undelete_file(
+        &self,
+        vfile_id: &VfsFileId,
+    ) -> anyhow::Result<bool> {
+        let mut locked_cache = self.gid_cache.write().await;
+        self.ensure_no_conflict()?;
+
+        let dirname = &vfile_id.dir.dirname;
+        let filename = &vfile_id.filename;
+        ensure!(
+            locked_cache.get(vfile_id).is_none(),
+            "{CREATE_DUPE_MSG}: {dirname}/{filename}"
+        );
+
+        let trash_gid = match self.get_trash_dir(false).await? {
+            Some(gid) => gid,
+            None => return Ok(false),
+        };
+        let gvfile_id = GvfsFileId::try_from(vfile_id)?;
+        let maybe_latest = self
+            .list_trash(&trash_gid)
+            .await?
+            .into_iter()
+            .filter(|trashed| {
+                trashed.gvfile_id.as_parts() == gvfile_id.as_parts()
+            })
+            .max_by_key(|trashed| trashed.deleted_at);
+        let latest = match maybe_latest {
+            Some(latest) => latest,
+            None => return Ok(false),
+        };
+
+        self.client
+            .move_file(
+                &latest.gid,
+                &gvfile_id.to_string(),
+                &trash_gid,
+                &self.gvfs_root.gid,
+            )
+            .await
+            .context("Failed to move gdrive file out of trash")?;
+
+        let cached = CachedGFile {
+            gid: latest.gid,
+            head_revision_id: latest.head_revision_id,
+        };
+        locked_cache.insert(vfile_id.clone(), cached);
+
+        Ok(true)
+    }
+
+    /// Permanently deletes all files which have been in the GVFS trash for
+    /// longer than `retention` (usually [`TRASH_RETENTION`]). Returns the
+    /// number of files purged.
+    #[instrument(skip_all, name = "(gvfs-purge-trash)")]
+    pub async fn purge_trash(
+        &self,
+        retention: Duration,
+    ) -> anyhow::Result<usize> {
+        // Hold the cache lock so we don't race with `undelete_file`.
+        let _locked_cache = self.gid_cache.write().await;
+        self.ensure_no_conflict()?;
+
+        let trash_gid = match self.get_trash_dir(false).await? {
+            Some(gid) => gid,
+            // Nothing has ever been deleted.
+            None => return Ok(0),
+        };
+
+        let now = TimestampMs::now().as_i64();
+        let retention_ms = i64::try_from(retention.as_millis())
+            .context("Retention period too long")?;
+        let expired = self
+            .list_trash(&trash_gid)
+            .await?
+            .into_iter()
+            .filter(|trashed| {
+                now.saturating_sub(trashed.deleted_at.as_i64()) > retention_ms
+            })
+            .collect::<Vec<_>>();
+
+        for trashed in expired.iter() {
+            self.client
+                .delete_file(&trashed.gid)
+                .await
+                .with_context(|| trashed.gvfile_id.clone())
+                .context("Failed to purge gdrive file")?;
+        }
+
+        Ok(expired.len())
+    }
+
     #[instrument(skip_all, name = "(gvfs-get-directory)")]
     pub async fn get_directory(
         &self,
@@ -403,13 +672,13 @@ impl GoogleVfs {
         // Collect the gids and gvids of all files in this VFS subdir. Iterate
         // until the dirname no longer matches or there are no more items.
         let mut subdir_gid_gvids = Vec::new();
-        for (vfile_id, gid) in locked_cache.range(lower_bound..) {
+        for (vfile_id, cached) in locked_cache.range(lower_bound..) {
             if vfile_id.dir.dirname != vdir.dirname {
                 break;
             }
             let gvfile_id =
                 GvfsFileId::try_from(vfile_id).expect("Cache invariant");
-            subdir_gid_gvids.push((gid.clone(), gvfile_id));
+            subdir_gid_gvids.push((cached.gid.clone(), gvfile_id));
         }
 
         // Early return if the subdir contained no files
@@ -436,10 +705,190 @@ impl GoogleVfs {
             .apply(futures::future::join_all)
             .await
             .into_iter()
-            .collect::<anyhow::Result<Vec<VfsFile>>>()?;
+            .try_collect_vec()?;
 
         Ok(vfiles)
     }
+
+    /// Reports the size of all files in the GVFS root, including trashed files,
+    /// along with the user's remaining Drive quota. Only this network's GVFS
+    /// root is counted, not the whole LexeData folder.
+    #[instrument(skip_all, name = "(gvfs-storage-status)")]
+    pub async fn storage_status(&self) -> anyhow::Result<GDriveStorageStatus> {
+        // Hold the cache lock so that files aren't moved in or out of the trash
+        // while we're listing them.
+        let _locked_cache = self.gid_cache.read().await;
+        let maybe_trash_gid = self.get_trash_dir(false).await?;
+
+        let list_trash = async {
+            match maybe_trash_gid {
+                Some(trash_gid) =>
+                    self.client.list_direct_children(&trash_gid).await,
+                None => Ok(Vec::new()),
+            }
+        };
+        let (try_gfiles, try_trashed, try_about) = futures::join!(
+            self.client.list_direct_children(&self.gvfs_root.gid),
+            list_trash,
+            self.client.get_about(),
+        );
+        let gfiles = try_gfiles.context("list_direct_children (root)")?;
+        let trashed = try_trashed.context("list_direct_children (trash)")?;
+        let quota = try_about.context("get_about")?.storage_quota;
+
+        let mut file_sizes = gfiles
+            .into_iter()
+            .chain(trashed)
+            .filter_map(|gfile| {
+                let bytes = gfile.size?;
+                Some(GDriveFileSize {
+                    name: gfile.name,
+                    bytes,
+                })
+            })
+            .collect::<Vec<_>>();
+        let num_files = file_sizes.len();
+        let lexe_bytes = file_sizes.iter().map(|file| file.bytes).sum();
+        file_sizes.sort_unstable_by(|a, b| b.bytes.cmp(&a.bytes));
+        file_sizes.truncate(NUM_LARGEST_FILES);
+
+        Ok(GDriveStorageStatus {
+            lexe_bytes,
+            num_files,
+            largest_files: file_sizes,
+            quota_used_bytes: quota.usage,
+            quota_limit_bytes: quota.limit,
+        })
+    }
+
+    /// Errors with a [`ConflictError`] and refuses all further writes if
+    /// `current`, the head revision fetched while we updated a file, is
+    /// neither the revision we last wrote or saw (`expected`) nor the one we
+    /// just wrote (`ours`).
+    fn check_unmodified(
+        &self,
+        vfile_id: &VfsFileId,
+        expected: Option<String>,
+        current: Option<String>,
+        ours: Option<&String>,
+    ) -> anyhow::Result<()> {
+        let expected = match expected {
+            Some(expected) => expected,
+            None => return Ok(()),
+        };
+        if current.as_ref() == Some(&expected) || current.as_ref() == ours {
+            return Ok(());
+        }
+        self.conflicted.store(true, Ordering::Release);
+        Err(ConflictError {
+            vfile_id: vfile_id.clone(),
+            expected,
+            actual: current,
+        }
+        .into())
+    }
+
+    /// Errors if we've detected another writer; see [`Self::has_conflict`].
+    fn ensure_no_conflict(&self) -> anyhow::Result<()> {
+        ensure!(
+            !self.has_conflict(),
+            "Refusing to write to GDrive after detecting another writer"
+        );
+        Ok(())
+    }
+
+    /// Returns the [`GFileId`] of this network's trash folder in the LexeData
+    /// dir, creating it if it doesn't exist and `create` is set.
+    /// Callers must already hold the `gid_cache` lock.
+    async fn get_trash_dir(
+        &self,
+        create: bool,
+    ) -> anyhow::Result<Option<GFileId>> {
+        let mut locked_trash_gid = self.trash_gid.lock().await;
+        if let Some(gid) = locked_trash_gid.as_ref() {
+            return Ok(Some(gid.clone()));
+        }
+
+        let lexe_dir = match lexe_dir::find_lexe_dir(&self.client)
+            .await
+            .context("find_lexe_dir")?
+        {
+            Some(dir) => dir.id,
+            None if create => bail!("LexeData dir {NOT_FOUND_MSG}"),
+            None => return Ok(None),
+        };
+        let dirname =
+            format!("{}{TRASH_DIRNAME_SUFFIX}", self.gvfs_root.network);
+        let maybe_gid = self
+            .client
+            .search_direct_children(&lexe_dir, &dirname)
+            .await
+            .context("search_direct_children")?
+            .map(|gfile| gfile.id);
+        let gid = match maybe_gid {
+            Some(gid) => gid,
+            None if create => self
+                .client
+                .create_child_dir(lexe_dir, &dirname)
+                .await
+                .context("create_child_dir")?,
+            None => return Ok(None),
+        };
+
+        *locked_trash_gid = Some(gid.clone());
+        Ok(Some(gid))
+    }
+
+    /// Lists all files in the trash folder. Files with unrecognized names are
+    /// skipped (and never purged).
+    async fn list_trash(
+        &self,
+        trash_gid: &GFileId,
+    ) -> anyhow::Result<Vec<TrashedGFile>> {
+        let trashed = self
+            .client
+            .list_direct_children(trash_gid)
+            .await
+            .context("list_direct_children (trash)")?
+            .into_iter()
+            .filter_map(|gfile| match TrashedGFile::try_from(gfile) {
+                Ok(trashed) => Some(trashed),
+                Err(e) => {
+                    warn!("Skipping unrecognized file in trash: {e:#}");
+                    None
+                }
+            })
+            .collect();
+        Ok(trashed)
+    }
+}
+
+impl TryFrom<GFile> for TrashedGFile {
+    type Error = anyhow::Error;
+    fn try_from(gfile: GFile) -> anyhow::Result<Self> {
+        let (deleted_at, gvfile_id) = gfile
+            .name
+            .split_once('-')
+            .with_context(|| format!("'{}' had no '-'", gfile.name))?;
+        let deleted_at = TimestampMs::from_str(deleted_at)
+            .context("Invalid deleted_at timestamp")?;
+        let gvfile_id = GvfsFileId::from_str(gvfile_id)?;
+        Ok(Self {
+            gid: gfile.id,
+            gvfile_id,
+            deleted_at,
+            head_revision_id: gfile.head_revision_id,
+        })
+    }
+}
+
+impl From<GFile> for CachedGFile {
+    fn from(gfile: GFile) -> Self {
+        Self {
+            gid: gfile.id,
+            head_revision_id: gfile.head_revision_id,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -447,8 +896,9 @@ mod test {
     use super::*;
 
     /// Test utility to search for the Lexe dir and delete the regtest VFS root
-    /// inside if it exists. We do NOT delete the entire Lexe dir in case there
-    /// are "bitcoin" or "testnet" folders holding real funds.
+    /// and trash folder inside if they exist. We do NOT delete the entire Lexe
+    /// dir in case there are "bitcoin" or "testnet" folders holding real
+    /// funds.
     async fn delete_regtest_vfs_root(client: &GDriveClient) {
         let maybe_lexe_dir = lexe_dir::find_lexe_dir(client)
             .await
@@ -459,19 +909,19 @@ mod test {
         };
 
         let network_str = Network::REGTEST.to_string();
-        let maybe_gvfs_root =
-            lexe_dir::get_gvfs_root_gid(client, &lexe_dir.id, &network_str)
+        let trash_dirname = format!("{network_str}{TRASH_DIRNAME_SUFFIX}");
+        for dirname in [&network_str, &trash_dirname] {
+            let maybe_dir = client
+                .search_direct_children(&lexe_dir.id, dirname)
                 .await
-                .expect("get_vfs_root failed");
-        let regtest_gvfs_root = match maybe_gvfs_root {
-            Some(vr) => vr,
-            None => return,
-        };
-
-        client
-            .delete_file(&regtest_gvfs_root)
-            .await
-            .expect("delete_file failed");
+                .expect("search_direct_children failed");
+            if let Some(dir) = maybe_dir {
+                client
+                    .delete_file(&dir.id)
+                    .await
+                    .expect("delete_file failed");
+            }
+        }
     }
 
     /// ```bash
@@ -531,6 +981,21 @@ mod test {
         let get_dir_resp = gvfs.get_directory(&node_dir).await.unwrap();
         assert_eq!(get_dir_resp, vec![file1_data2.clone(), file2.clone()]);
 
+        // Simulate another writer modifying file2 behind our back.
+        // Upserting file2 should now fail with a conflict. Then put file2's
+        // data back (again behind our back) for the checks below.
+        let file2_gid = gvfs.gid_cache.read().await[&file2.id].gid.clone();
+        gvfs.client
+            .update_blob_file(file2_gid.clone(), vec![9])
+            .await
+            .unwrap();
+        let err = gvfs.upsert_file(file2.clone()).await.unwrap_err();
+        assert!(err.downcast_ref::<ConflictError>().is_some());
+        gvfs.client
+            .update_blob_file(file2_gid, file2.data.clone())
+            .await
+            .unwrap();
+
         // Delete file1.
         // Fetching file1 should return None.
         // Fetching the directory should only return file2.
@@ -543,6 +1008,28 @@ mod test {
         // Attempting to delete file1 again should return a 'NotFound' error
         let err = gvfs.delete_file(&file1_data2.id).await.unwrap_err();
         assert!(err.to_string().contains(NOT_FOUND_MSG));
+
+        // Undelete file1; it should come back with its latest data.
+        // Undeleting it again should fail since it now exists.
+        assert!(gvfs.undelete_file(&file1.id).await.unwrap());
+        let get_file1 = gvfs.get_file(&file1.id).await.unwrap().unwrap();
+        assert_eq!(get_file1, file1_data2);
+        let err = gvfs.undelete_file(&file1.id).await.unwrap_err();
+        assert!(err.to_string().contains(CREATE_DUPE_MSG));
+
+        // Nothing in the trash is older than the retention period, so nothing
+        // should be purged. A zero retention period purges everything.
+        gvfs.delete_file(&file2.id).await.unwrap();
+        assert_eq!(gvfs.purge_trash(TRASH_RETENTION).await.unwrap(), 0);
+        assert_eq!(gvfs.purge_trash(Duration::ZERO).await.unwrap(), 1);
+        assert!(!gvfs.undelete_file(&file2.id).await.unwrap());
+
+        // Only file1 is left, which is 1 byte large.
+        let status = gvfs.storage_status().await.unwrap();
+        assert_eq!(status.num_files, 1);
+        assert_eq!(status.lexe_bytes, 1);
+        assert_eq!(status.largest_files[0].bytes, 1);
+        assert!(status.quota_used_bytes >= status.lexe_bytes);
     }
 
     /// Initialize a [`GoogleVfs`] with a [`GvfsRoot`] whose [`GFileId`] is
@@ -654,6 +1141,26 @@ mod test {
         assert_eq!(get_file1, file1);
     }
 
+    #[test]
+    fn trashed_gfile_from_name() {
+        let gfile = |name: &str| GFile {
+            id: GFileId("gid".to_owned()),
+            name: name.to_owned(),
+            mime_type: api::BINARY_MIME_TYPE.to_owned(),
+            size: None,
+            head_revision_id: None,
+        };
+
+        let trashed =
+            TrashedGFile::try_from(gfile("1700000000000-dir/file-1")).unwrap();
+        assert_eq!(trashed.deleted_at.as_i64(), 1700000000000);
+        assert_eq!(trashed.gvfile_id.as_parts(), ("dir", "file-1"));
+
+        for bad in ["dir/file", "-dir/file", "1700000000000-dirfile", "x-d/f"] {
+            assert!(TrashedGFile::try_from(gfile(bad)).is_err(), "{bad}");
+        }
+    }
+
     /// Checks that the `dirname` contained in a [`VfsFileId`] takes precedence
     /// in [`VfsFileId`]'s [`Ord`] implementation, in case a Lexe dev
     /// accidentally reorders the fields or something. This invariant is relied
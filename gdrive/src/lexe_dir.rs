@@ -12,6 +12,9 @@
 //!         |   |___"channel_monitors/deadcafe" (vfs subdir file, AES encrypted)
 //!         |   |___"channel_monitors/baddecaf" (vfs subdir file, AES encrypted)
 //!         |   |___...
+//!         |___"bitcoin.trash" (deleted mainnet gvfs files, until purged)
+//!         |   |___"<deleted_at>-channel_monitors/deadcafe"
+//!         |   |___...
 //!         |___"testnet" (testnet gvfs root)
 //!         |   |___"./encrypted_root_seed" (singleton file, password-encrypted)
 //!         |   |___"./channel_manager" (singleton file, AES encrypted)
@@ -90,6 +93,7 @@ pub(crate) async fn find_lexe_dir(
         // Order by creation time, ascending.
         order_by: Some("createdTime".into()),
         page_token: None,
+        fields: None,
     };
 
     let mut resp =
@@ -126,6 +126,8 @@ pub enum Error {
     WrongAccessType { access_type: String },
     #[error("Token had a token_type other than 'Bearer': {token_type}")]
     WrongTokenType { token_type: String },
+    #[error("Bad OAuth2 redirect: {0}")]
+    BadRedirect(String),
 
     // -- API error -- //
     #[error("API returned error response ({code}). Response: {resp_str}")]
@@ -136,4 +138,6 @@ pub enum Error {
     SerdeJson(#[from] serde_json::Error),
     #[error("Reqwest error: {0:#}")]
     Reqwest(#[from] reqwest::Error),
+    #[error("IO error: {0:#}")]
+    Io(#[from] std::io::Error),
 }
@@ -3,7 +3,7 @@ use std::{
     fmt::{self, Display},
 };
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 
 /// The metadata associated with a Google Drive "File".
 /// NOTE: GDrive "files" include folders as well.
@@ -20,6 +20,13 @@ pub struct GFile {
     pub id: GFileId,
     pub name: String,
     pub mime_type: String,
+    /// The size of the file contents in bytes. Only populated for blob files,
+    /// and only if requested via [`ListFiles::fields`].
+    #[serde(default, deserialize_with = "deserialize_int64_opt")]
+    pub size: Option<u64>,
+    /// The ID of the file's current content revision. Only populated for blob
+    /// files, and only if requested via the `fields` parameter.
+    pub head_revision_id: Option<String>,
     // kind: String, // Always "drive#file"
 }
 
@@ -72,6 +79,12 @@ pub struct ListFiles<'a> {
     /// This should be set to the value of 'nextPageToken' from the
     /// previous response." Is [`None`] if there are no more results.
     pub page_token: Option<String>,
+    /// Which fields to include in the response. Defaults to a few basic file
+    /// fields which don't include e.g. `size`.
+    ///
+    /// Example: "nextPageToken,files(id,name,size)".
+    /// More info: <https://developers.google.com/drive/api/guides/fields-parameter>
+    pub fields: Option<Cow<'a, str>>,
 }
 
 #[derive(Deserialize)]
@@ -86,6 +99,60 @@ pub struct ListFilesResponse {
     // kind: String,
 }
 
+/// GET /files/{fileId}
+///
+/// <https://developers.google.com/drive/api/reference/rest/v3/files/get>
+#[derive(Serialize)]
+pub struct GetFile<'a> {
+    /// Which fields to include in the response.
+    pub fields: Cow<'a, str>,
+}
+
+/// GET /about
+///
+/// <https://developers.google.com/drive/api/reference/rest/v3/about/get>
+#[derive(Serialize)]
+pub struct GetAbout<'a> {
+    /// Required; the about endpoint doesn't return anything by default.
+    pub fields: Cow<'a, str>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct About {
+    pub storage_quota: StorageQuota,
+}
+
+/// The user's storage limit and usage, which is shared across Google Drive,
+/// Gmail, and Google Photos.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageQuota {
+    /// The usage limit in bytes. Not present if the user has unlimited
+    /// storage.
+    #[serde(default, deserialize_with = "deserialize_int64_opt")]
+    pub limit: Option<u64>,
+    /// The total usage across all services in bytes.
+    #[serde(deserialize_with = "deserialize_int64")]
+    pub usage: u64,
+}
+
+/// Google encodes int64 fields as JSON strings.
+fn deserialize_int64<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u64, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(de::Error::custom)
+}
+
+fn deserialize_int64_opt<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    <Option<String>>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(de::Error::custom))
+        .transpose()
+}
+
 impl Display for GFileId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
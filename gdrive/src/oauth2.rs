@@ -2,18 +2,29 @@
 use std::env;
 use std::{
     fmt,
+    net::Ipv4Addr,
     ops::Deref,
     time::{Duration, SystemTime},
 };
 
+#[cfg(any(test, feature = "test-utils"))]
+use common::env::config::{Config, ConfigLayers, ConfigReader};
 #[cfg(test)]
 use common::test_utils::arbitrary;
-use common::{const_assert, constants};
+use common::{
+    const_assert, constants,
+    rng::{Crng, RngExt},
+    sha256,
+};
 #[cfg(test)]
 use proptest_derive::Arbitrary;
-use reqwest::StatusCode;
+use reqwest::{StatusCode, Url};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, instrument, trace};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, info, instrument, trace, warn};
 
 use crate::{Error, API_SCOPE};
 
@@ -26,6 +37,11 @@ const ACCESS_TYPE: &str = "offline";
 /// The expected value of `token_type`.
 // For the foreseeable future we are only interested in bearer auth tokens.
 const TOKEN_TYPE: &str = "Bearer";
+/// Google's OAuth2 authorization endpoint.
+const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+/// The maximum size of a redirect request we'll read from the loopback
+/// listener. Browsers send far less than this.
+const MAX_REDIRECT_REQUEST_LEN: usize = 8192;
 /// The minimum amount of time that access tokens are guaranteed to be valid
 /// after a call to `refresh_if_necessary`. If an access token will expire in
 /// time less than this (or if the token has already expired),
@@ -92,29 +108,26 @@ impl GDriveCredentials {
     /// ```
     #[cfg(any(test, feature = "test-utils"))]
     pub fn from_env() -> anyhow::Result<Self> {
-        use std::str::FromStr;
-
-        use anyhow::Context;
+        let (credentials, _effective) =
+            ConfigLayers::new().with_env("GOOGLE_").load::<Self>()?;
+        Ok(credentials)
+    }
+}
 
-        let client_id = env::var("GOOGLE_CLIENT_ID")
-            .context("Missing 'GOOGLE_CLIENT_ID' in env")?;
-        let client_secret = env::var("GOOGLE_CLIENT_SECRET")
-            .context("Missing 'GOOGLE_CLIENT_SECRET' in env")?;
-        let refresh_token = env::var("GOOGLE_REFRESH_TOKEN")
-            .context("Missing 'GOOGLE_REFRESH_TOKEN' in env")?;
-        let access_token = env::var("GOOGLE_ACCESS_TOKEN")
-            .context("Missing 'GOOGLE_ACCESS_TOKEN' in env")?;
-        let expires_at_str = env::var("GOOGLE_ACCESS_TOKEN_EXPIRY")
-            .context("Missing 'GOOGLE_ACCESS_TOKEN_EXPIRY' in env")?;
-        let expires_at = u64::from_str(&expires_at_str)
-            .context("Invalid GOOGLE_ACCESS_TOKEN_EXPIRY")?;
-
-        Ok(Self {
-            client_id,
-            client_secret,
-            refresh_token,
-            access_token,
-            expires_at,
+#[cfg(any(test, feature = "test-utils"))]
+impl Config for GDriveCredentials {
+    fn read(r: &mut ConfigReader<'_>) -> Option<Self> {
+        let client_id = r.required("client_id");
+        let client_secret = r.secret("client_secret");
+        let refresh_token = r.secret("refresh_token");
+        let access_token = r.secret("access_token");
+        let expires_at = r.required("access_token_expiry");
+        Some(Self {
+            client_id: client_id?,
+            client_secret: client_secret?,
+            refresh_token: refresh_token?,
+            access_token: access_token?,
+            expires_at: expires_at?,
         })
     }
 }
@@ -144,8 +157,200 @@ fn verify_response_scope(scope: String) -> Result<(), Error> {
     }
 }
 
+/// A PKCE code verifier and its `S256` code challenge, which prevents an
+/// intercepted auth code from being exchanged by anyone else.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc7636>
+pub struct PkceChallenge {
+    /// Sent with the auth code in [`auth_code_for_token`].
+    pub code_verifier: String,
+    /// Sent in the [`authorization_url`].
+    pub code_challenge: String,
+}
+
+impl PkceChallenge {
+    pub fn new(rng: &mut impl Crng) -> Self {
+        // 32 random bytes => 43 base64url chars, the minimum allowed length
+        let code_verifier = base64::encode_config(
+            rng.gen_bytes::<32>(),
+            base64::URL_SAFE_NO_PAD,
+        );
+        Self::from_verifier(code_verifier)
+    }
+
+    fn from_verifier(code_verifier: String) -> Self {
+        let code_challenge = base64::encode_config(
+            sha256::digest(code_verifier.as_bytes()),
+            base64::URL_SAFE_NO_PAD,
+        );
+        Self {
+            code_verifier,
+            code_challenge,
+        }
+    }
+}
+
+/// Builds the URL at which the user grants us access to their Google Drive.
+/// After the user consents, Google redirects to `redirect_uri` with `code` and
+/// `state` query params.
+///
+/// <https://developers.google.com/identity/protocols/oauth2/native-app#step-2:-send-a-request-to-googles-oauth-2.0-server>
+pub fn authorization_url(
+    client_id: &str,
+    redirect_uri: &str,
+    pkce: &PkceChallenge,
+    state: &str,
+) -> String {
+    let params = [
+        ("client_id", client_id),
+        ("redirect_uri", redirect_uri),
+        ("response_type", "code"),
+        ("scope", API_SCOPE),
+        ("access_type", ACCESS_TYPE),
+        ("code_challenge", pkce.code_challenge.as_str()),
+        ("code_challenge_method", "S256"),
+        ("state", state),
+    ];
+    Url::parse_with_params(AUTH_URL, params)
+        .expect("AUTH_URL is a valid URL")
+        .into()
+}
+
+/// Runs the complete OAuth2 flow for installed apps (CLIs, desktop apps,
+/// tests) and returns ready-to-use [`GDriveCredentials`]:
+///
+/// 1. Spins up an HTTP listener on a random localhost port to receive the
+///    redirect.
+/// 2. Calls `open_browser` with the [`authorization_url`], which the caller
+///    should open in the user's browser (or print, if it can't).
+/// 3. Waits for the redirect, checking its `state` param.
+/// 4. Exchanges the auth code, along with the [`PkceChallenge`] verifier.
+///
+/// `client_id` must be a "Desktop app" OAuth client, which Google allows to
+/// redirect to any loopback port. This fn waits indefinitely for the user, so
+/// callers may want to wrap it in a timeout.
+///
+/// <https://developers.google.com/identity/protocols/oauth2/native-app#redirect-uri_loopback>
+pub async fn installed_app_flow(
+    client: &ReqwestClient,
+    rng: &mut impl Crng,
+    client_id: String,
+    client_secret: String,
+    open_browser: impl FnOnce(&str),
+) -> Result<GDriveCredentials, Error> {
+    let pkce = PkceChallenge::new(rng);
+    let state =
+        base64::encode_config(rng.gen_bytes::<16>(), base64::URL_SAFE_NO_PAD);
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let port = listener.local_addr()?.port();
+    let redirect_uri = format!("http://{}:{port}", Ipv4Addr::LOCALHOST);
+
+    open_browser(&authorization_url(&client_id, &redirect_uri, &pkce, &state));
+
+    info!("Waiting for OAuth2 redirect on {redirect_uri}");
+    let code = loop {
+        let (stream, _addr) = listener.accept().await?;
+        match handle_redirect(stream, &state).await {
+            Ok(Some(code)) => break code,
+            // Unrelated request, e.g. for /favicon.ico
+            Ok(None) => continue,
+            // The user denied access or the state didn't match
+            Err(e @ Error::BadRedirect(_)) => return Err(e),
+            // The browser hung up or sent garbage; keep waiting
+            Err(e) => warn!("Error handling redirect request: {e:#}"),
+        }
+    };
+
+    auth_code_for_token(
+        client,
+        client_id,
+        client_secret,
+        &redirect_uri,
+        &code,
+        Some(&pkce.code_verifier),
+    )
+    .await
+}
+
+/// Reads a single HTTP request from the loopback listener and responds with a
+/// short message for the user. Returns the auth code if this was the redirect.
+async fn handle_redirect(
+    mut stream: TcpStream,
+    expected_state: &str,
+) -> Result<Option<String>, Error> {
+    // We only need the request line, which is in the first read in practice.
+    let mut buf = vec![0u8; MAX_REDIRECT_REQUEST_LEN];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let target = request
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("GET "))
+        .and_then(|rest| rest.split(' ').next())
+        .unwrap_or_default();
+
+    let result = parse_redirect(target, expected_state);
+    let (status, body) = match &result {
+        Ok(Some(_)) => (
+            "200 OK",
+            "Authorization complete. You may close this window.",
+        ),
+        Ok(None) => ("404 Not Found", "Not found."),
+        Err(_) => ("400 Bad Request", "Authorization failed."),
+    };
+    let len = body.len();
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Length: {len}\r\n\
+         Connection: close\r\n\r\n\
+         {body}"
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    result
+}
+
+/// Parses the auth code from a redirect request target like
+/// `/?state=...&code=...`. Returns [`None`] if the request isn't a redirect.
+fn parse_redirect(
+    target: &str,
+    expected_state: &str,
+) -> Result<Option<String>, Error> {
+    let url = Url::parse("http://localhost")
+        .and_then(|base| base.join(target))
+        .map_err(|e| Error::BadRedirect(format!("Invalid target: {e}")))?;
+
+    let (mut code, mut state, mut error) = (None, None, None);
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "code" => code = Some(value.into_owned()),
+            "state" => state = Some(value.into_owned()),
+            "error" => error = Some(value.into_owned()),
+            _ => (),
+        }
+    }
+
+    if let Some(error) = error {
+        return Err(Error::BadRedirect(format!("Google returned: {error}")));
+    }
+    let code = match code {
+        Some(code) => code,
+        None => return Ok(None),
+    };
+    if state.as_deref() != Some(expected_state) {
+        return Err(Error::BadRedirect("Mismatched state".to_owned()));
+    }
+
+    Ok(Some(code))
+}
+
 /// Exchanges the auth `code` (and other info) for the `access_token`,
 /// returning the full [`GDriveCredentials`] which can then be persisted.
+/// `code_verifier` is required if a [`PkceChallenge`] was used to obtain the
+/// `code`.
 ///
 /// <https://developers.google.com/identity/protocols/oauth2/native-app#exchange-authorization-code>
 pub async fn auth_code_for_token(
@@ -154,6 +359,7 @@ pub async fn auth_code_for_token(
     client_secret: String,
     redirect_uri: &str,
     code: &str,
+    code_verifier: Option<&str>,
 ) -> Result<GDriveCredentials, Error> {
     #[derive(Serialize)]
     struct Request<'a> {
@@ -161,6 +367,8 @@ pub async fn auth_code_for_token(
         client_secret: &'a str,
         redirect_uri: &'a str,
         code: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code_verifier: Option<&'a str>,
         grant_type: &'static str,
     }
 
@@ -178,6 +386,7 @@ pub async fn auth_code_for_token(
         client_secret: &client_secret,
         redirect_uri,
         code,
+        code_verifier,
         grant_type: "authorization_code",
     };
 
@@ -414,6 +623,35 @@ mod test {
         roundtrip::json_value_roundtrip_proptest::<GDriveCredentials>();
     }
 
+    #[test]
+    fn pkce_test_vector() {
+        // RFC 7636 Appendix B
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r-wW1gFWFOEjXk";
+        let pkce = PkceChallenge::from_verifier(verifier.to_owned());
+        assert_eq!(
+            pkce.code_challenge,
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn parse_redirect_params() {
+        let state = "abc";
+
+        let code = parse_redirect("/?state=abc&code=4%2F0Ab&scope=x", state);
+        assert_eq!(code.unwrap().as_deref(), Some("4/0Ab"));
+
+        assert_eq!(parse_redirect("/favicon.ico", state).unwrap(), None);
+        assert!(matches!(
+            parse_redirect("/?state=xyz&code=4%2F0Ab", state),
+            Err(Error::BadRedirect(_)),
+        ));
+        assert!(matches!(
+            parse_redirect("/?error=access_denied&state=abc", state),
+            Err(Error::BadRedirect(_)),
+        ));
+    }
+
     /// ```bash
     /// export GOOGLE_CLIENT_ID="<client_id>"
     /// export GOOGLE_CLIENT_SECRET="<client_secret>"
@@ -516,6 +754,7 @@ mod test {
             client_secret,
             redirect_uri,
             &code,
+            None,
         )
         .await
         .unwrap();
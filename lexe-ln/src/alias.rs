@@ -20,7 +20,7 @@ use lightning_transaction_sync::EsploraSyncClient;
 
 use crate::{
     esplora::LexeEsplora, keys_manager::LexeKeysManager,
-    logger::LexeTracingLogger,
+    logger::LexeTracingLogger, route::LexeRouter,
 };
 
 pub type SignerType = InMemorySigner;
@@ -84,7 +84,9 @@ pub type LexePeerManagerType<CHANNEL_MANAGER> = PeerManager<
     Arc<LexeKeysManager>,
 >;
 
-pub type RouterType = DefaultRouter<
+pub type RouterType = LexeRouter;
+
+pub type DefaultRouterType = DefaultRouter<
     Arc<NetworkGraphType>,
     LexeTracingLogger,
     Arc<Mutex<ProbabilisticScorerType>>,
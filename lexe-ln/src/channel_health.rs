@@ -0,0 +1,377 @@
+//! Stale channel detection.
+//!
+//! LDK doesn't record when a channel was last used, so we sample our channels
+//! periodically and keep a persisted [`ChannelActivityLog`] of when each
+//! channel's balance last changed and when its counterparty was last online.
+//! [`analyze`] then flags channels which are inactive, whose counterparty has
+//! been offline for a long time, or whose balance is too small to be worth
+//! much after fees, and recommends which of them to close.
+//!
+//! User nodes only run for short periods, so a counterparty's offline time is
+//! only measured between samples which observed it: the time between the last
+//! sample where it was online and the latest sample where it was offline. The
+//! time since our latest sample, e.g. while our node wasn't running, never
+//! counts. Channels with the LSP are never analyzed, since the LSP is our only
+//! route to the rest of the network.
+
+use std::time::Duration;
+
+use common::{
+    api::{
+        command::{ChannelHealth, ChannelIssue, ChannelRecommendation},
+        NodePk,
+    },
+    ln::{
+        amount::Amount,
+        channel::{ChannelId, LxChannelDetails},
+    },
+    time::TimestampMs,
+};
+use serde::{Deserialize, Serialize};
+
+/// A channel whose balance hasn't changed in this long is [`Inactive`].
+///
+/// [`Inactive`]: ChannelIssue::Inactive
+pub const INACTIVE_THRESHOLD: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+/// A channel whose counterparty was observed offline for this long, with no
+/// sample in between observing it online, has a [`PeerOffline`] issue.
+///
+/// [`PeerOffline`]: ChannelIssue::PeerOffline
+pub const OFFLINE_THRESHOLD: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+/// A channel is [`Uneconomic`] if a cooperative close would cost more than
+/// 1/this of our balance.
+///
+/// [`Uneconomic`]: ChannelIssue::Uneconomic
+const UNECONOMIC_FEE_DIVISOR: u64 = 10;
+
+/// The approximate weight of a cooperative close tx: one 2-of-2 P2WSH input
+/// and two P2WPKH outputs.
+const COOP_CLOSE_WEIGHT: u64 = 672;
+/// The approximate weight of a commitment tx without HTLCs, plus the tx which
+/// sweeps our `to_local` output after the force close delay.
+const FORCE_CLOSE_WEIGHT: u64 = 724 + 484;
+
+/// Our record of each channel's activity, persisted across node restarts.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChannelActivityLog {
+    channels: Vec<ChannelActivity>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelActivity {
+    pub channel_id: ChannelId,
+    /// When we first sampled this channel.
+    pub first_seen: TimestampMs,
+    /// Our balance when we last sampled this channel.
+    pub balance: Amount,
+    /// When we last saw our balance change, or `first_seen` if it hasn't.
+    pub last_activity: TimestampMs,
+    /// When we were last connected to our counterparty, if ever.
+    pub last_online: Option<TimestampMs>,
+    /// When we last observed our counterparty offline, if ever.
+    #[serde(default)]
+    pub last_offline: Option<TimestampMs>,
+}
+
+impl ChannelActivity {
+    /// How long our samples have observed our counterparty being offline, if
+    /// it was offline in our latest sample.
+    pub fn observed_offline(&self) -> Option<Duration> {
+        let last_offline = self.last_offline?;
+        let online_at = self.last_online.unwrap_or(self.first_seen);
+        if last_offline <= online_at {
+            return None;
+        }
+        let offline = last_offline
+            .into_duration()
+            .saturating_sub(online_at.into_duration());
+        Some(offline)
+    }
+}
+
+/// The parts of a [`LxChannelDetails`] which we need for the analysis.
+#[derive(Clone, Debug)]
+pub struct ChannelSample {
+    pub channel_id: ChannelId,
+    pub counterparty_node_id: NodePk,
+    pub channel_value: Amount,
+    pub balance: Amount,
+    pub is_ready: bool,
+    pub is_usable: bool,
+}
+
+impl From<&LxChannelDetails> for ChannelSample {
+    fn from(details: &LxChannelDetails) -> Self {
+        Self {
+            channel_id: details.channel_id,
+            counterparty_node_id: details.counterparty_node_id,
+            channel_value: details.channel_value,
+            balance: details.balance,
+            is_ready: details.is_ready,
+            is_usable: details.is_usable,
+        }
+    }
+}
+
+impl ChannelActivityLog {
+    pub fn get(&self, channel_id: &ChannelId) -> Option<&ChannelActivity> {
+        self.channels.iter().find(|c| c.channel_id == *channel_id)
+    }
+
+    /// Record a sample of our current channels, dropping any channels which
+    /// have since closed. Returns whether the log changed.
+    ///
+    /// `peers_settled` is whether we've had a chance to reconnect to our peers
+    /// since startup; if not, an unusable channel doesn't tell us that its
+    /// counterparty is offline.
+    pub fn update(
+        &mut self,
+        samples: &[ChannelSample],
+        peers_settled: bool,
+        now: TimestampMs,
+    ) -> bool {
+        let before = self.channels.clone();
+
+        self.channels
+            .retain(|c| samples.iter().any(|s| s.channel_id == c.channel_id));
+
+        for sample in samples {
+            let last_online = sample.is_usable.then_some(now);
+            let last_offline =
+                (peers_settled && !sample.is_usable).then_some(now);
+            let existing = self
+                .channels
+                .iter_mut()
+                .find(|c| c.channel_id == sample.channel_id);
+            match existing {
+                Some(activity) => {
+                    if activity.balance != sample.balance {
+                        activity.balance = sample.balance;
+                        activity.last_activity = now;
+                    }
+                    if last_online.is_some() {
+                        activity.last_online = last_online;
+                    }
+                    if last_offline.is_some() {
+                        activity.last_offline = last_offline;
+                    }
+                }
+                None => self.channels.push(ChannelActivity {
+                    channel_id: sample.channel_id,
+                    first_seen: now,
+                    balance: sample.balance,
+                    last_activity: now,
+                    last_online,
+                    last_offline,
+                }),
+            }
+        }
+
+        self.channels != before
+    }
+}
+
+/// Analyze the health of each of our channels other than those with the LSP.
+/// `sat_per_kw` is the current feerate for a close to confirm in a reasonable
+/// time.
+pub fn analyze(
+    samples: &[ChannelSample],
+    log: &ChannelActivityLog,
+    lsp_node_pk: &NodePk,
+    sat_per_kw: u32,
+    now: TimestampMs,
+) -> Vec<ChannelHealth> {
+    let fee = |weight: u64| {
+        let sats = weight.saturating_mul(u64::from(sat_per_kw)) / 1000;
+        Amount::try_from_sats_u64(sats).unwrap_or(Amount::MAX)
+    };
+    let est_coop_close_fee = fee(COOP_CLOSE_WEIGHT);
+    let est_force_close_fee = fee(FORCE_CLOSE_WEIGHT);
+    let older_than = |time: TimestampMs, threshold: Duration| {
+        now.into_duration().saturating_sub(time.into_duration()) >= threshold
+    };
+
+    samples
+        .iter()
+        .filter(|sample| sample.counterparty_node_id != *lsp_node_pk)
+        .map(|sample| {
+            let activity = log.get(&sample.channel_id);
+            let mut issues = Vec::new();
+
+            // We can only judge channels we've been tracking, and which aren't
+            // still being opened or closed.
+            if let Some(activity) = activity.filter(|_| sample.is_ready) {
+                if older_than(activity.last_activity, INACTIVE_THRESHOLD) {
+                    issues.push(ChannelIssue::Inactive);
+                }
+                let offline = activity
+                    .observed_offline()
+                    .is_some_and(|offline| offline >= OFFLINE_THRESHOLD);
+                if !sample.is_usable && offline {
+                    issues.push(ChannelIssue::PeerOffline);
+                }
+            }
+            let max_fee = sample
+                .balance
+                .checked_div(UNECONOMIC_FEE_DIVISOR.into())
+                .unwrap_or(Amount::ZERO);
+            if sample.is_ready && est_coop_close_fee > max_fee {
+                issues.push(ChannelIssue::Uneconomic);
+            }
+
+            let peer_offline = issues.contains(&ChannelIssue::PeerOffline);
+            let close_fee = if peer_offline {
+                est_force_close_fee
+            } else {
+                est_coop_close_fee
+            };
+            let recommendation = if sample.balance <= close_fee {
+                // Closing wouldn't recover anything.
+                ChannelRecommendation::Keep
+            } else if peer_offline {
+                ChannelRecommendation::ForceClose
+            } else if issues.contains(&ChannelIssue::Inactive) {
+                ChannelRecommendation::CooperativeClose
+            } else {
+                ChannelRecommendation::Keep
+            };
+
+            ChannelHealth {
+                channel_id: sample.channel_id,
+                counterparty_node_id: sample.counterparty_node_id,
+                channel_value: sample.channel_value,
+                balance: sample.balance,
+                last_activity: activity.map(|a| a.last_activity),
+                last_online: activity.and_then(|a| a.last_online),
+                issues,
+                recommendation,
+                est_coop_close_fee,
+                est_force_close_fee,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+    /// The secp256k1 generator point.
+    const NODE_PK: &str =
+        "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    /// Twice the secp256k1 generator point.
+    const LSP_PK: &str =
+        "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+
+    fn at_day(day: i64) -> TimestampMs {
+        TimestampMs::try_from(day * DAY_MS).unwrap()
+    }
+
+    fn sample(id: u8, balance_sats: u32, is_usable: bool) -> ChannelSample {
+        ChannelSample {
+            channel_id: ChannelId([id; 32]),
+            counterparty_node_id: NodePk::from_str(NODE_PK).unwrap(),
+            channel_value: Amount::from_sats_u32(1_000_000),
+            balance: Amount::from_sats_u32(balance_sats),
+            is_ready: true,
+            is_usable,
+        }
+    }
+
+    #[test]
+    fn update_tracks_activity() {
+        let mut log = ChannelActivityLog::default();
+        assert!(log.update(&[sample(1, 500_000, true)], true, at_day(0)));
+        // Nothing changed but the time we were last online
+        assert!(log.update(&[sample(1, 500_000, true)], true, at_day(1)));
+        // Before our peers reconnect, an unusable channel tells us nothing
+        assert!(!log.update(&[sample(1, 500_000, false)], false, at_day(2)));
+
+        let activity = log.get(&ChannelId([1; 32])).unwrap();
+        assert_eq!(activity.last_activity, at_day(0));
+        assert_eq!(activity.last_online, Some(at_day(1)));
+        assert_eq!(activity.last_offline, None);
+
+        assert!(log.update(&[sample(1, 400_000, false)], true, at_day(3)));
+        let activity = log.get(&ChannelId([1; 32])).unwrap();
+        assert_eq!(activity.last_activity, at_day(3));
+        assert_eq!(activity.first_seen, at_day(0));
+        assert_eq!(
+            activity.observed_offline(),
+            Some(Duration::from_millis(2 * DAY_MS as u64))
+        );
+
+        // Closed channels are dropped
+        assert!(log.update(&[], true, at_day(4)));
+        assert_eq!(log.get(&ChannelId([1; 32])), None);
+    }
+
+    #[test]
+    fn recommendations() {
+        let samples = [
+            // Healthy
+            sample(1, 500_000, true),
+            // Inactive
+            sample(2, 500_000, true),
+            // Inactive, peer offline
+            sample(3, 500_000, false),
+            // Inactive, peer offline, balance smaller than the close fee
+            sample(4, 1_000, false),
+            // Inactive, peer only observed offline for a few days
+            sample(5, 500_000, false),
+            // Inactive, but with the LSP
+            ChannelSample {
+                counterparty_node_id: NodePk::from_str(LSP_PK).unwrap(),
+                ..sample(6, 500_000, false)
+            },
+        ];
+        let channels = samples
+            .iter()
+            .map(|s| ChannelActivity {
+                channel_id: s.channel_id,
+                first_seen: at_day(0),
+                balance: s.balance,
+                last_activity: if s.channel_id.0[0] == 1 {
+                    at_day(99)
+                } else {
+                    at_day(0)
+                },
+                last_online: Some(at_day(0)),
+                last_offline: match s.channel_id.0[0] {
+                    3 | 4 | 6 => Some(at_day(99)),
+                    5 => Some(at_day(5)),
+                    _ => None,
+                },
+            })
+            .collect();
+        let log = ChannelActivityLog { channels };
+
+        // 10 sat/vB
+        let sat_per_kw = 2500;
+        let lsp_node_pk = NodePk::from_str(LSP_PK).unwrap();
+        let health =
+            analyze(&samples, &log, &lsp_node_pk, sat_per_kw, at_day(100));
+        let summary = health
+            .iter()
+            .map(|h| (h.issues.clone(), h.recommendation))
+            .collect::<Vec<_>>();
+
+        use ChannelIssue::*;
+        use ChannelRecommendation::*;
+        assert_eq!(
+            summary,
+            [
+                (vec![], Keep),
+                (vec![Inactive], CooperativeClose),
+                (vec![Inactive, PeerOffline], ForceClose),
+                (vec![Inactive, PeerOffline, Uneconomic], Keep),
+                (vec![Inactive], CooperativeClose),
+            ]
+        );
+        assert_eq!(health[0].est_coop_close_fee, Amount::from_sats_u32(1680));
+        assert_eq!(health[0].est_force_close_fee, Amount::from_sats_u32(3020));
+    }
+}
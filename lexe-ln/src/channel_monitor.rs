@@ -7,14 +7,18 @@ use std::{
 };
 
 use common::{
-    ln::channel::LxOutPoint, shutdown::ShutdownChannel, task::LxTask, Apply,
+    ln::channel::LxOutPoint, metered::MeteredReceiver,
+    shutdown::ShutdownChannel, task::LxTask, test_event::TestEvent, Apply,
 };
 use lightning::chain::{chainmonitor::MonitorUpdateId, transaction::OutPoint};
 use thiserror::Error;
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, info, warn};
 
-use crate::{alias::LexeChainMonitorType, traits::LexePersister};
+use crate::{
+    alias::LexeChainMonitorType, test_event::TestEventSender,
+    traits::LexePersister,
+};
 
 /// How long we'll wait to receive a reply from the background processor that
 /// event processing is complete.
@@ -61,14 +65,30 @@ impl Display for ChannelMonitorUpdateKind {
     }
 }
 
+/// A queued [`LxChannelMonitorUpdate`] along with the ids of any older updates
+/// for the same channel which it supersedes.
+struct CoalescedUpdate {
+    update: LxChannelMonitorUpdate,
+    /// Updates whose persist futures were dropped because `update` persists a
+    /// newer version of the same channel monitor. These are marked complete
+    /// once `update` is persisted.
+    superseded: Vec<MonitorUpdateId>,
+}
+
 /// Spawns a task which executes channel monitor persistence calls in serial.
 /// This prevent a race conditions where two monitor updates come in quick
 /// succession and the newer channel monitor state is overwritten by the older
 /// channel monitor state.
+///
+/// If multiple updates for the same channel are queued up (e.g. because the
+/// API calls are slow), only the newest one is persisted, since each update
+/// contains the full channel monitor state. This keeps a slow or flaky backend
+/// from causing a growing backlog of redundant persists.
 pub fn spawn_channel_monitor_persister_task<PS>(
     chain_monitor: Arc<LexeChainMonitorType<PS>>,
-    mut channel_monitor_persister_rx: mpsc::Receiver<LxChannelMonitorUpdate>,
+    mut channel_monitor_persister_rx: MeteredReceiver<LxChannelMonitorUpdate>,
     process_events_tx: mpsc::Sender<oneshot::Sender<()>>,
+    test_event_tx: TestEventSender,
     mut shutdown: ShutdownChannel,
 ) -> LxTask<()>
 where
@@ -80,13 +100,20 @@ where
         loop {
             tokio::select! {
                 Some(update) = channel_monitor_persister_rx.recv() => {
-                    idx += 1;
+                    // Drain any other queued updates so we can coalesce them.
+                    let mut batch = vec![update];
+                    while let Ok(next) =
+                        channel_monitor_persister_rx.try_recv()
+                    {
+                        batch.push(next);
+                    }
 
-                    let handle_res = handle_update(
+                    let handle_res = handle_batch(
                         chain_monitor.as_ref(),
-                        update,
-                        idx,
+                        batch,
+                        &mut idx,
                         &process_events_tx,
+                        &test_event_tx,
                         &mut shutdown,
                     ).await;
 
@@ -136,6 +163,77 @@ enum Error {
     EventsProcessRecv,
 }
 
+/// Coalesces a batch of queued updates so that there is at most one update per
+/// channel, keeping the newest. Channels are kept in order of first appearance.
+fn coalesce_updates(
+    batch: Vec<LxChannelMonitorUpdate>,
+) -> Vec<CoalescedUpdate> {
+    coalesce_by_key(batch, |update| update.funding_txo)
+        .into_iter()
+        .map(|(update, superseded)| CoalescedUpdate {
+            update,
+            superseded: superseded.into_iter().map(|u| u.update_id).collect(),
+        })
+        .collect()
+}
+
+/// Keeps only the last item for each key, along with the older items it
+/// supersedes (oldest first). Keys are kept in order of first appearance.
+fn coalesce_by_key<T, K: PartialEq>(
+    items: Vec<T>,
+    key: impl Fn(&T) -> K,
+) -> Vec<(T, Vec<T>)> {
+    let mut coalesced = Vec::<(T, Vec<T>)>::with_capacity(items.len());
+    for item in items {
+        let existing = coalesced
+            .iter_mut()
+            .find(|(newest, _)| key(newest) == key(&item));
+        match existing {
+            Some((newest, superseded)) => {
+                let older = std::mem::replace(newest, item);
+                superseded.push(older);
+            }
+            None => coalesced.push((item, Vec::new())),
+        }
+    }
+    coalesced
+}
+
+/// Coalesces and handles a batch of updates drained from the queue.
+async fn handle_batch<PS: LexePersister>(
+    chain_monitor: &LexeChainMonitorType<PS>,
+    batch: Vec<LxChannelMonitorUpdate>,
+    idx: &mut usize,
+    process_events_tx: &mpsc::Sender<oneshot::Sender<()>>,
+    test_event_tx: &TestEventSender,
+    shutdown: &mut ShutdownChannel,
+) -> Result<(), Error> {
+    let batch_len = batch.len();
+    let coalesced = coalesce_updates(batch);
+    if batch_len > 1 {
+        let num_persists = coalesced.len();
+        debug!(
+            "Monitor update queue depth {batch_len}; {num_persists} persists"
+        );
+    }
+
+    for CoalescedUpdate { update, superseded } in coalesced {
+        *idx += 1;
+        handle_update(
+            chain_monitor,
+            update,
+            superseded,
+            *idx,
+            process_events_tx,
+            test_event_tx,
+            shutdown,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
 /// A helper to prevent [`spawn_channel_monitor_persister_task`]'s control flow
 /// from getting too complex.
 ///
@@ -144,8 +242,10 @@ enum Error {
 async fn handle_update<PS: LexePersister>(
     chain_monitor: &LexeChainMonitorType<PS>,
     update: LxChannelMonitorUpdate,
+    superseded: Vec<MonitorUpdateId>,
     idx: usize,
     process_events_tx: &mpsc::Sender<oneshot::Sender<()>>,
+    test_event_tx: &TestEventSender,
     shutdown: &mut ShutdownChannel,
 ) -> Result<(), Error> {
     debug!("Handling channel monitor update #{idx}");
@@ -165,15 +265,21 @@ async fn handle_update<PS: LexePersister>(
     }
 
     // Update the chain monitor with the update id and funding txo the channel
-    // monitor update.
-    let chain_monitor_update_res = chain_monitor.channel_monitor_updated(
-        OutPoint::from(update.funding_txo),
-        update.update_id,
-    );
-    if let Err(e) = chain_monitor_update_res {
-        // If the update wasn't accepted, the channel is disabled, so no
-        // transactions can be made. Just return err and shut down.
-        return Err(Error::ChainMonitor(e));
+    // monitor update. The superseded updates were persisted along with this
+    // one, so mark them as complete too.
+    let funding_txo = OutPoint::from(update.funding_txo);
+    let num_superseded = superseded.len();
+    for update_id in superseded.into_iter().chain([update.update_id]) {
+        let chain_monitor_update_res =
+            chain_monitor.channel_monitor_updated(funding_txo, update_id);
+        if let Err(e) = chain_monitor_update_res {
+            // If the update wasn't accepted, the channel is disabled, so no
+            // transactions can be made. Just return err and shut down.
+            return Err(Error::ChainMonitor(e));
+        }
+    }
+    if num_superseded > 0 {
+        debug!("Coalesced {num_superseded} older updates into #{idx}");
     }
 
     // Trigger the background processor to reprocess events, as the completed
@@ -189,6 +295,49 @@ async fn handle_update<PS: LexePersister>(
         .map_err(|_| Error::EventsProcessRecv)?;
 
     info!("Success: persisted {kind} channel #{idx}");
+    test_event_tx.record(TestEvent::ChannelMonitorPersisted);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Coalesces `(channel, update_id)` pairs by channel, returning the update
+    /// ids for each channel.
+    fn coalesce(updates: &[(char, u64)]) -> Vec<(char, u64, Vec<u64>)> {
+        coalesce_by_key(updates.to_vec(), |(channel, _)| *channel)
+            .into_iter()
+            .map(|((channel, id), superseded)| {
+                let superseded = superseded.into_iter().map(|(_, id)| id);
+                (channel, id, superseded.collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn coalesce_no_duplicates() {
+        assert_eq!(coalesce(&[]), vec![]);
+        assert_eq!(
+            coalesce(&[('a', 1), ('b', 2), ('c', 3)]),
+            vec![('a', 1, vec![]), ('b', 2, vec![]), ('c', 3, vec![])],
+        );
+    }
+
+    #[test]
+    fn coalesce_keeps_newest_per_channel() {
+        // The newest update for each channel is kept, and supersedes all
+        // older updates for that channel, oldest first.
+        assert_eq!(
+            coalesce(&[('a', 1), ('a', 2), ('a', 3)]),
+            vec![('a', 3, vec![1, 2])],
+        );
+
+        // Channels stay in the order of their first update.
+        assert_eq!(
+            coalesce(&[('a', 1), ('b', 2), ('a', 3), ('c', 4), ('b', 5)]),
+            vec![('a', 3, vec![1]), ('b', 5, vec![2]), ('c', 4, vec![])],
+        );
+    }
+}
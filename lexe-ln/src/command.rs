@@ -1,11 +1,16 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::{anyhow, bail, Context};
+use anyhow::{anyhow, bail, ensure, Context};
+use bdk::FeeRate;
 use bitcoin::bech32::ToBase32;
 use bitcoin_hashes::{sha256, Hash};
 use common::{
     api::{
         command::{
+            BumpReceiveRequest, BumpReceiveResponse, ChannelHealthResponse,
             CreateInvoiceRequest, CreateInvoiceResponse, NodeInfo,
             PayInvoiceRequest, PayInvoiceResponse, PayOnchainRequest,
             PayOnchainResponse, PreflightPayInvoiceRequest,
@@ -16,17 +21,25 @@ use common::{
     },
     cli::{LspInfo, Network},
     enclave::Measurement,
-    ln::{amount::Amount, channel::LxChannelDetails, invoice::LxInvoice},
+    ln::{
+        amount::Amount,
+        channel::LxChannelDetails,
+        hashes::LxTxid,
+        invoice::LxInvoice,
+        payments::{LxPaymentHash, LxPaymentId},
+    },
+    time::TimestampMs,
 };
 use lightning::{
+    chain::chaininterface::{ConfirmationTarget, FeeEstimator},
     ln::{
         channelmanager::{
-            PaymentId, RecipientOnionFields, RetryableSendFailure,
-            MIN_FINAL_CLTV_EXPIRY_DELTA,
+            PaymentId, PaymentSendFailure, RecipientOnionFields,
+            RetryableSendFailure, MIN_FINAL_CLTV_EXPIRY_DELTA,
         },
         PaymentHash,
     },
-    routing::router::{PaymentParameters, RouteHint, RouteParameters, Router},
+    routing::router::{PaymentParameters, Route, RouteHint, RouteParameters},
     sign::{NodeSigner, Recipient},
 };
 use lightning_invoice::{Bolt11Invoice, Currency, InvoiceBuilder};
@@ -34,18 +47,22 @@ use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, info, instrument};
 
 use crate::{
-    alias::{LexeChainMonitorType, RouterType},
+    alias::{LexeChainMonitorType, NetworkGraphType, RouterType},
+    channel_health::{self, ChannelActivityLog, ChannelSample},
     esplora::LexeEsplora,
+    fee_oracle::MAX_FEERATE_SAT_PER_VB,
     keys_manager::LexeKeysManager,
     payments::{
         inbound::InboundInvoicePayment,
         manager::PaymentsManager,
+        onchain::{OnchainCpfp, OnchainReceiveStatus},
         outbound::{
             LxOutboundPaymentFailure, OutboundInvoicePayment,
             OUTBOUND_PAYMENT_RETRY_STRATEGY,
         },
         Payment,
     },
+    route::{self, RouteBlacklist},
     traits::{LexeChannelManager, LexePeerManager, LexePersister},
     wallet::LexeWallet,
 };
@@ -127,6 +144,35 @@ where
         .collect::<Vec<_>>()
 }
 
+/// Analyzes our channels for staleness using the given [`ChannelActivityLog`],
+/// with close fees estimated at the current [`ConfirmationTarget::Normal`]
+/// feerate.
+pub fn channel_health<CM, PS>(
+    channel_manager: CM,
+    esplora: &LexeEsplora,
+    activity_log: &ChannelActivityLog,
+    lsp_node_pk: &NodePk,
+) -> ChannelHealthResponse
+where
+    CM: LexeChannelManager<PS>,
+    PS: LexePersister,
+{
+    let samples = list_channels(channel_manager)
+        .iter()
+        .map(ChannelSample::from)
+        .collect::<Vec<_>>();
+    let sat_per_kw =
+        esplora.get_est_sat_per_1000_weight(ConfirmationTarget::Normal);
+    let channels = channel_health::analyze(
+        &samples,
+        activity_log,
+        lsp_node_pk,
+        sat_per_kw,
+        TimestampMs::now(),
+    );
+    ChannelHealthResponse { channels }
+}
+
 /// Uses the given `[bdk|ldk]_resync_tx` to retrigger BDK and LDK sync, and
 /// returns once sync has either completed or timed out.
 pub async fn resync(
@@ -177,10 +223,7 @@ where
     let cltv_expiry = MIN_FINAL_CLTV_EXPIRY_DELTA;
     info!("Handling create_invoice command for {amount:?} msats");
 
-    // TODO(max): We should set some sane maximum for the invoice expiry time,
-    // e.g. 180 days. This will not cause LDK state to blow up since
-    // create_inbound_payment derives its payment preimages and hashes, but it
-    // could bloat Lexe's DB with fairly large `LxInvoice`s.
+    req.validate().context("Invalid create invoice request")?;
 
     // We use ChannelManager::create_inbound_payment because this method allows
     // the channel manager to store the hash and preimage for us, instead of
@@ -209,9 +252,14 @@ where
     // Add most parts of the invoice, except for the route hints.
     // This is modeled after lightning_invoice's internal utility function
     // _create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash
+    // D: False -> True. Either a description or description hash is required.
+    let builder = InvoiceBuilder::new(currency);
+    let builder = match req.description_hash {
+        Some(hash) => builder.description_hash(sha256::Hash::from_inner(hash)),
+        None => builder.description(req.description.unwrap_or_default()),
+    };
     #[rustfmt::skip] // Nicer for the generic annotations to be aligned
-    let mut builder = InvoiceBuilder::new(currency)          // <D, H, T, C, S>
-        .description(req.description.unwrap_or_default())    // D: False -> True
+    let mut builder = builder                                // <D, H, T, C, S>
         .payment_hash(sha256_hash)                           // H: False -> True
         .current_timestamp()                                 // T: False -> True
         .min_final_cltv_expiry_delta(u64::from(cltv_expiry)) // C: False -> True
@@ -243,8 +291,12 @@ where
     }
 
     // Build, sign, and return the invoice
-    let raw_invoice =
-        builder.build_raw().context("Could not build raw invoice")?;
+    // Setting the payment metadata changes the builder's type, so do it last.
+    let raw_invoice = match req.payment_metadata {
+        Some(metadata) => builder.payment_metadata(metadata).build_raw(),
+        None => builder.build_raw(),
+    }
+    .context("Could not build raw invoice")?;
     let hr_part_str = raw_invoice.hrp.to_string();
     let data_part_base32 = raw_invoice.data.to_base32();
     let recipient = Recipient::Node;
@@ -283,6 +335,8 @@ where
 pub async fn pay_invoice<CM, PS>(
     req: PayInvoiceRequest,
     router: Arc<RouterType>,
+    network_graph: Arc<NetworkGraphType>,
+    route_blacklist: Arc<RouteBlacklist>,
     channel_manager: CM,
     payments_manager: PaymentsManager<CM, PS>,
 ) -> anyhow::Result<PayInvoiceResponse>
@@ -295,9 +349,12 @@ where
         payment,
         route_params,
         recipient_fields,
+        pinned_route,
     } = preflight_pay_invoice_inner(
         req,
         router,
+        &network_graph,
+        &route_blacklist,
         &channel_manager,
         &payments_manager,
     )
@@ -314,6 +371,18 @@ where
         .await
         .context("Already tried to pay this invoice")?;
 
+    if let Some(route) = pinned_route {
+        return send_pinned_payment(
+            &route,
+            payment_hash,
+            recipient_fields,
+            created_at,
+            &channel_manager,
+            &payments_manager,
+        )
+        .await;
+    }
+
     // Send the payment, letting LDK handle payment retries, and match on the
     // result, registering a failure with the payments manager if appropriate.
     match channel_manager.send_payment(
@@ -361,10 +430,52 @@ where
     }
 }
 
+/// Send a payment along the given route without retries, registering a
+/// failure with the payments manager if LDK didn't send any part of it.
+async fn send_pinned_payment<CM, PS>(
+    route: &Route,
+    payment_hash: LxPaymentHash,
+    recipient_fields: RecipientOnionFields,
+    created_at: TimestampMs,
+    channel_manager: &CM,
+    payments_manager: &PaymentsManager<CM, PS>,
+) -> anyhow::Result<PayInvoiceResponse>
+where
+    CM: LexeChannelManager<PS>,
+    PS: LexePersister,
+{
+    match channel_manager.send_payment_with_route(
+        route,
+        PaymentHash::from(payment_hash),
+        recipient_fields,
+        PaymentId::from(payment_hash),
+    ) {
+        // If only some paths failed, LDK still tracks the payment and will
+        // emit a PaymentSent or PaymentFailed later.
+        Ok(()) | Err(PaymentSendFailure::PartialFailure { .. }) => {
+            info!(hash = %payment_hash, "Success: pinned OIP initiated");
+            Ok(PayInvoiceResponse { created_at })
+        }
+        Err(PaymentSendFailure::DuplicatePayment) => Err(anyhow!(
+            "Somehow got DuplicatePayment error (OIP {payment_hash})"
+        )),
+        Err(e) => {
+            // No part of the payment was sent, so LDK doesn't track it.
+            payments_manager
+                .payment_failed(payment_hash, LxOutboundPaymentFailure::NoRoute)
+                .await
+                .context("(PaymentSendFailure) Could not register failure")?;
+            Err(anyhow!("Could not send OIP {payment_hash}: {e:?}"))
+        }
+    }
+}
+
 #[instrument(skip_all, name = "(preflight-pay-invoice)")]
 pub async fn preflight_pay_invoice<CM, PS>(
     req: PreflightPayInvoiceRequest,
     router: Arc<RouterType>,
+    network_graph: Arc<NetworkGraphType>,
+    route_blacklist: Arc<RouteBlacklist>,
     channel_manager: CM,
     payments_manager: PaymentsManager<CM, PS>,
 ) -> anyhow::Result<PreflightPayInvoiceResponse>
@@ -377,10 +488,13 @@ where
         fallback_amount: req.fallback_amount,
         // User note not relevant for pre-flight.
         note: None,
+        route_controls: req.route_controls,
     };
     let preflight = preflight_pay_invoice_inner(
         req,
         router,
+        &network_graph,
+        &route_blacklist,
         &channel_manager,
         &payments_manager,
     )
@@ -443,6 +557,64 @@ where
     Ok(PayOnchainResponse { created_at, txid })
 }
 
+#[instrument(skip_all, name = "(bump-receive)")]
+pub async fn bump_receive<CM, PS>(
+    req: BumpReceiveRequest,
+    wallet: LexeWallet,
+    esplora: Arc<LexeEsplora>,
+    payments_manager: PaymentsManager<CM, PS>,
+) -> anyhow::Result<BumpReceiveResponse>
+where
+    CM: LexeChannelManager<PS>,
+    PS: LexePersister,
+{
+    ensure!(
+        req.fee_rate_sat_per_vb <= MAX_FEERATE_SAT_PER_VB,
+        "Fee rate can't exceed {MAX_FEERATE_SAT_PER_VB} sat/vB",
+    );
+    let package_feerate =
+        FeeRate::from_sat_per_vb(req.fee_rate_sat_per_vb as f32);
+
+    let id = LxPaymentId::OnchainRecv(req.txid);
+    let onchain_recv = payments_manager
+        .pending_onchain_receive(&id)
+        .await
+        .context("Could not find onchain receive")?;
+    ensure!(
+        onchain_recv.status == OnchainReceiveStatus::Zeroconf,
+        "Onchain receive is not awaiting confirmation",
+    );
+
+    // Create and sign the child tx.
+    let (tx, fees) = wallet
+        .create_cpfp_child(
+            &onchain_recv.tx,
+            package_feerate,
+            onchain_recv.cpfp.as_ref(),
+        )
+        .await
+        .context("Error while creating CPFP tx")?;
+    let txid = LxTxid(tx.txid());
+
+    // Broadcast.
+    esplora
+        .broadcast_tx(&tx)
+        .await
+        .context("Failed to broadcast tx")?;
+
+    // Only record the child once it's been broadcast, since the next bump
+    // must replace whichever child is actually in the mempool. Unlike with
+    // `pay_onchain`, nothing is lost if this fails: the child only moves our
+    // own funds, and the receive itself is already tracked.
+    let cpfp = OnchainCpfp { txid, fees };
+    payments_manager
+        .onchain_receive_bumped(&id, cpfp)
+        .await
+        .context("Could not register CPFP tx")?;
+
+    Ok(BumpReceiveResponse { txid, fees })
+}
+
 #[instrument(skip_all, name = "(estimate-fee-send-onchain)")]
 pub async fn preflight_pay_onchain(
     req: PreflightPayOnchainRequest,
@@ -464,13 +636,21 @@ struct PreflightedPayInvoice {
     payment: OutboundInvoicePayment,
     route_params: RouteParameters,
     recipient_fields: RecipientOnionFields,
+    /// Set if the route controls restrict which of our channels the payment
+    /// may leave through. LDK's retries may use any of our usable channels, so
+    /// these payments are sent along this route without retries.
+    pinned_route: Option<Route>,
 }
 
 // Preflight (validate and route) a new potential BOLT11 invoice that we might
-// pay.
+// pay. Fails with a `FeeExceedsPolicy` error if the routing fees are higher
+// than the user's `FeePolicy` allows. The router also checks the fees of any
+// routes found when LDK retries the payment.
 async fn preflight_pay_invoice_inner<CM, PS>(
     req: PayInvoiceRequest,
     router: Arc<RouterType>,
+    network_graph: &NetworkGraphType,
+    route_blacklist: &RouteBlacklist,
     channel_manager: &CM,
     payments_manager: &PaymentsManager<CM, PS>,
 ) -> anyhow::Result<PreflightedPayInvoice>
@@ -525,6 +705,20 @@ where
             .map_err(|()| anyhow!("(features) Wrong payment param kind"))?;
     }
 
+    // LDK's router won't use any of these channels, and neither will any
+    // retries, since LDK only ever adds to this list.
+    payment_params.previously_failed_channels = route::excluded_channels(
+        &req.route_controls,
+        &channel_manager.list_channels(),
+        network_graph,
+        route_blacklist,
+        Instant::now(),
+    );
+    let usable_channels = channel_manager.list_usable_channels();
+    let first_hops = route::first_hops(&req.route_controls, &usable_channels)
+        .context("Invalid route controls")?;
+    let restricts_first_hops = first_hops.len() != usable_channels.len();
+
     let route_params = RouteParameters {
         payment_params,
         final_value_msat: amount.msat(),
@@ -532,12 +726,14 @@ where
 
     // Find a Route so we can estimate the fees to be paid. Modeled after
     // `lightning::ln::outbound_payment::OutboundPayments::pay_internal`.
-    let usable_channels = channel_manager.list_usable_channels();
-    let refs_usable_channels = usable_channels.iter().collect::<Vec<_>>();
-    let first_hops = Some(refs_usable_channels.as_slice());
     let in_flight_htlcs = channel_manager.compute_inflight_htlcs();
     let route = router
-        .find_route(&payer_pubkey, &route_params, first_hops, in_flight_htlcs)
+        .find_route_unchecked(
+            &payer_pubkey,
+            &route_params,
+            Some(first_hops.as_slice()),
+            in_flight_htlcs,
+        )
         .map_err(|e| anyhow!("Could not find route to recipient: {}", e.err))?;
 
     let payment_secret = invoice.payment_secret().into();
@@ -547,9 +743,12 @@ where
     };
 
     let payment = OutboundInvoicePayment::new(invoice, &route, req.note);
+    router.check_fees(payment.amount, payment.fees)?;
+
     Ok(PreflightedPayInvoice {
         payment,
         route_params,
         recipient_fields,
+        pinned_route: restricts_first_hops.then_some(route),
     })
 }
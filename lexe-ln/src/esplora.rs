@@ -1,21 +1,24 @@
 use std::{
-    cmp,
     collections::HashMap,
     sync::{
         atomic::{AtomicU32, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     time::{Duration, SystemTime},
 };
 
 use anyhow::{anyhow, Context};
 use bdk::FeeRate;
-use bitcoin::{blockdata::transaction::Transaction, OutPoint};
+use bitcoin::{blockdata::transaction::Transaction, BlockHash, OutPoint, Txid};
 use common::{
-    constants, ln::hashes::LxTxid, shutdown::ShutdownChannel, task::LxTask,
+    cli::node::FeeOracleConfig, constants, iter::IteratorExt,
+    ln::hashes::LxTxid, shutdown::ShutdownChannel, task::LxTask,
     test_event::TestEvent, Apply,
 };
-use esplora_client::{api::OutputStatus, AsyncClient};
+use esplora_client::{
+    api::{OutputStatus, TxStatus},
+    AsyncClient,
+};
 use lightning::chain::chaininterface::{
     BroadcasterInterface, ConfirmationTarget, FeeEstimator,
     FEERATE_FLOOR_SATS_PER_KW,
@@ -23,7 +26,13 @@ use lightning::chain::chaininterface::{
 use tokio::time;
 use tracing::{debug, error, info, instrument, warn};
 
-use crate::test_event::TestEventSender;
+use crate::{
+    fee_oracle::{
+        EsploraFeeOracle, FeeBounds, FeeEstimates, FeeOracle, FixedFeeOracle,
+        MempoolSpaceFeeOracle,
+    },
+    test_event::TestEventSender,
+};
 
 /// The interval at which we refresh estimated fee rates.
 // Since we want to reduce the number of API calls made to our (external)
@@ -41,14 +50,6 @@ const ESPLORA_CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 const BITCOIN_CORE_MEMPOOL_EXPIRY: Duration =
     Duration::from_secs(60 * 60 * 24 * 14);
 
-/// Enumerates all [`ConfirmationTarget`]s.
-const ALL_CONF_TARGETS: [ConfirmationTarget; 4] = [
-    ConfirmationTarget::HighPriority,
-    ConfirmationTarget::Normal,
-    ConfirmationTarget::Background,
-    ConfirmationTarget::MempoolMinimum,
-];
-
 /// The minimum information about a [`bitcoin::Transaction`] required to query
 /// Esplora for if the transaction has been confirmed or replaced.
 pub struct TxConfQuery {
@@ -89,6 +90,11 @@ pub enum TxConfStatus {
 pub struct LexeEsplora {
     client: AsyncClient,
     test_event_tx: TestEventSender,
+    fee_oracle: Arc<dyn FeeOracle>,
+    fee_bounds: FeeBounds,
+    /// An extra floor on all estimates, set by [`Self::set_min_feerate`].
+    min_feerate_sat_per_kw: AtomicU32,
+    cache: ResponseCache,
 
     // --- Cached fee estimations --- //
     high_prio_fees: AtomicU32,
@@ -98,8 +104,11 @@ pub struct LexeEsplora {
 }
 
 impl LexeEsplora {
+    /// Initializes the Esplora client. Fee estimates are fetched from the
+    /// [`FeeOracle`] given by `fee_oracle_config`.
     pub async fn init(
         esplora_url: String,
+        fee_oracle_config: &FeeOracleConfig,
         test_event_tx: TestEventSender,
         shutdown: ShutdownChannel,
     ) -> anyhow::Result<(Arc<Self>, LxTask<()>)> {
@@ -114,12 +123,26 @@ impl LexeEsplora {
         let reqwest_client = reqwest11::ClientBuilder::new()
             .add_root_certificate(google_ca_cert)
             .add_root_certificate(letsencrypt_ca_cert)
+            // Only trust the pinned roots, even though `PayjoinClient`
+            // enables the built-in webpki roots for this reqwest version.
+            .tls_built_in_root_certs(false)
             .timeout(ESPLORA_CLIENT_TIMEOUT)
             .build()
             .context("Failed to build reqwest client")?;
 
         // Initialize inner esplora client
-        let client = AsyncClient::from_client(esplora_url, reqwest_client);
+        let client =
+            AsyncClient::from_client(esplora_url, reqwest_client.clone());
+        let fee_oracle: Arc<dyn FeeOracle> = match fee_oracle_config {
+            FeeOracleConfig::Esplora =>
+                Arc::new(EsploraFeeOracle::new(client.clone())),
+            FeeOracleConfig::MempoolSpace { url } => Arc::new(
+                MempoolSpaceFeeOracle::new(reqwest_client, url.clone())?,
+            ),
+            FeeOracleConfig::Fixed { sat_per_vb } =>
+                Arc::new(FixedFeeOracle::from_sat_per_vb(*sat_per_vb as f32)),
+        };
+        let fee_bounds = FeeBounds::default();
 
         // Initialize the fee rate estimates to some sane default values
         let high_prio_fees = AtomicU32::new(13_000); // 13 sat/vB
@@ -131,6 +154,10 @@ impl LexeEsplora {
         let esplora = Arc::new(Self {
             client,
             test_event_tx,
+            fee_oracle,
+            fee_bounds,
+            min_feerate_sat_per_kw: AtomicU32::new(FEERATE_FLOOR_SATS_PER_KW),
+            cache: ResponseCache::default(),
             high_prio_fees,
             normal_fees,
             background_fees,
@@ -185,64 +212,70 @@ impl LexeEsplora {
         &self.client
     }
 
-    /// Refreshes all current fee estimates.
-    async fn refresh_all_fee_estimates(&self) -> anyhow::Result<()> {
-        // Why does this return `HashMap<String, _>`???
-        let esplora_estimates = self
-            .client
-            .get_fee_estimates()
-            .await
-            .context("Could not fetch esplora's fee estimates")?;
+    /// Sets a floor on all fee estimates, in sats per 1000 weight units, e.g.
+    /// from the Lexe-signed remote config. The current estimates are raised
+    /// to the new floor immediately.
+    pub fn set_min_feerate(&self, min_feerate_sat_per_kw: u32) {
+        self.min_feerate_sat_per_kw
+            .store(min_feerate_sat_per_kw, Ordering::Release);
+        let current = FeeEstimates {
+            high_prio: self.high_prio_fees.load(Ordering::Acquire),
+            normal: self.normal_fees.load(Ordering::Acquire),
+            background: self.background_fees.load(Ordering::Acquire),
+            mempool_minimum: self.mempool_minimum_fees.load(Ordering::Acquire),
+        };
+        self.store_fee_estimates(self.fee_bounds().clamp(current));
+    }
 
-        for conf_target in ALL_CONF_TARGETS {
-            self.refresh_single_fee_estimate(conf_target, &esplora_estimates)
-                .with_context(|| {
-                    format!("Could not refresh fees for {conf_target:?}")
-                })?;
+    /// Our [`FeeBounds`], with the floor raised to the minimum fee rate set
+    /// by [`Self::set_min_feerate`].
+    fn fee_bounds(&self) -> FeeBounds {
+        let min_feerate = self.min_feerate_sat_per_kw.load(Ordering::Acquire);
+        FeeBounds {
+            floor_sats_per_kw: self
+                .fee_bounds
+                .floor_sats_per_kw
+                .max(min_feerate),
+            ..self.fee_bounds
         }
-
-        Ok(())
     }
 
-    /// Refreshes the current fee estimate for a [`ConfirmationTarget`] given a
-    /// `HashMap<String, f64>` returned by [`AsyncClient::get_fee_estimates`].
-    /// Returns the `u32` sats per 1000 weight that was stored in the cache.
-    fn refresh_single_fee_estimate(
-        &self,
-        conf_target: ConfirmationTarget,
-        esplora_estimates: &HashMap<String, f64>,
-    ) -> anyhow::Result<u32> {
-        // Convert the conf target to a target number of blocks.
-        let num_blocks_target = match conf_target {
-            ConfirmationTarget::HighPriority => 1,
-            ConfirmationTarget::Normal => 3,
-            ConfirmationTarget::Background => 72,
-            ConfirmationTarget::MempoolMinimum => 1008,
-        };
-
-        // Munge with units to get to sats per 1000 weight unit required by LDK
-        let feerate_satsvbyte =
-            convert_fee_rate(num_blocks_target, esplora_estimates)
-                .context("Could not convert feerate to sats/vbytes")?;
-        let bdk_feerate = FeeRate::from_sat_per_vb(feerate_satsvbyte);
-        let feerate_sats_per_1000_weight = bdk_feerate.fee_wu(1000) as u32;
-
-        // Ensure we don't fall below the minimum feerate required by LDK.
-        let feerate_sats_per_1000_weight =
-            cmp::max(feerate_sats_per_1000_weight, FEERATE_FLOOR_SATS_PER_KW);
-
-        // Get a reference to the AtomicU32 we need to store the result in
-        let ref_atomic_u32 = match conf_target {
-            ConfirmationTarget::HighPriority => &self.high_prio_fees,
-            ConfirmationTarget::Normal => &self.normal_fees,
-            ConfirmationTarget::Background => &self.background_fees,
-            ConfirmationTarget::MempoolMinimum => &self.mempool_minimum_fees,
-        };
+    /// Refreshes all current fee estimates from the [`FeeOracle`], clamping
+    /// them to our [`FeeBounds`].
+    async fn refresh_all_fee_estimates(&self) -> anyhow::Result<()> {
+        let oracle_name = self.fee_oracle.name();
+        let estimates =
+            self.fee_oracle.fee_estimates().await.with_context(|| {
+                format!("Could not fetch fee estimates from {oracle_name}")
+            })?;
+
+        // Raising estimates to the floor is routine, but hitting the ceiling
+        // means the oracle is probably broken.
+        let fee_bounds = self.fee_bounds();
+        let clamped = fee_bounds.clamp(estimates);
+        if fee_bounds.exceeds_ceiling(&estimates) {
+            warn!(
+                "Absurd fee estimates from {oracle_name}: \
+                {estimates:?}, clamped to {clamped:?}"
+            );
+        }
 
-        // Store the result and return
-        ref_atomic_u32.store(feerate_sats_per_1000_weight, Ordering::Release);
+        self.store_fee_estimates(clamped);
+        Ok(())
+    }
 
-        Ok(feerate_sats_per_1000_weight)
+    fn store_fee_estimates(&self, estimates: FeeEstimates) {
+        let FeeEstimates {
+            high_prio,
+            normal,
+            background,
+            mempool_minimum,
+        } = estimates;
+        self.high_prio_fees.store(high_prio, Ordering::Release);
+        self.normal_fees.store(normal, Ordering::Release);
+        self.background_fees.store(background, Ordering::Release);
+        self.mempool_minimum_fees
+            .store(mempool_minimum, Ordering::Release);
     }
 
     pub fn get_bdk_feerate(&self, conf_target: ConfirmationTarget) -> FeeRate {
@@ -296,7 +329,7 @@ impl LexeEsplora {
         }
 
         if !err_msgs.is_empty() {
-            let joined_msgs = err_msgs.join("; ");
+            let joined_msgs = err_msgs.iter().join_display("; ");
             error!("Batch broadcast failed: {joined_msgs}");
             return Err(anyhow!("Batch broadcast failed: {joined_msgs}"));
         }
@@ -307,6 +340,9 @@ impl LexeEsplora {
     }
 
     /// Returns the [`TxConfStatus`]es for a list of [`TxConfQuery`]s.
+    ///
+    /// Responses are cached until the chain tip changes, so repeated checks
+    /// within the same block only cost a single request for the tip hash.
     #[instrument(skip_all, name = "(get-tx-conf-statuses)")]
     pub async fn get_tx_conf_statuses<'query>(
         &self,
@@ -314,12 +350,29 @@ impl LexeEsplora {
     ) -> anyhow::Result<Vec<TxConfStatus>> {
         let now = SystemTime::now();
 
-        // Get the block height of our best-known chain tip.
-        let best_height = self
+        // Get the block height of our best-known chain tip, invalidating the
+        // cache if the tip has changed.
+        let tip_hash = self
             .client
-            .get_height()
+            .get_tip_hash()
             .await
-            .context("Could not fetch block height")?;
+            .context("Could not fetch tip hash")?;
+        let best_height = match self.cache.best_height(&tip_hash) {
+            Some(height) => height,
+            None => {
+                // If another block arrives in between these two requests, we
+                // cache a height one block too high for this tip until the
+                // next tip, which is acceptable for the same reason as the
+                // TOCTTOU race below.
+                let height = self
+                    .client
+                    .get_height()
+                    .await
+                    .context("Could not fetch block height")?;
+                self.cache.set_tip(tip_hash, height);
+                height
+            }
+        };
 
         // Concurrently get the tx conf status for all input `TxConfQuery`s,
         // quitting early if any return an error.
@@ -341,15 +394,22 @@ impl LexeEsplora {
         query: &'query TxConfQuery,
     ) -> anyhow::Result<TxConfStatus> {
         // Fetch the tx status.
-        let tx_status = self
-            .client
-            .get_tx_status(&query.txid.0)
-            .await
-            .context("Could not fetch tx status")?
-            // The extra Option<_> is an esplora_client bug; Esplora always
-            // returns Some for this endpoint.
-            // https://github.com/bitcoindevkit/rust-esplora-client/pull/46
-            .context("Txid somehow not found")?;
+        let tx_status = match self.cache.tx_status(&query.txid.0) {
+            Some(tx_status) => tx_status,
+            None => {
+                let tx_status = self
+                    .client
+                    .get_tx_status(&query.txid.0)
+                    .await
+                    .context("Could not fetch tx status")?
+                    // The extra Option<_> is an esplora_client bug; Esplora
+                    // always returns Some for this endpoint.
+                    // https://github.com/bitcoindevkit/rust-esplora-client/pull/46
+                    .context("Txid somehow not found")?;
+                self.cache.insert_tx_status(query.txid.0, tx_status.clone());
+                tx_status
+            }
+        };
 
         // This is poorly documented, but the `GET /tx/:txid/status` handler in
         // Blockstream/electrs returns `Some(_)` if and only if (1) the tx has
@@ -374,18 +434,23 @@ impl LexeEsplora {
 
         // Fetch the output status for every input.
         let output_status_futs = query.inputs.iter().map(|outpoint| async {
+            if let Some(output_status) = self.cache.output_status(outpoint) {
+                return Ok(output_status);
+            }
             let output_status = self
                 .client
                 .get_output_status(&outpoint.txid, outpoint.vout.into())
                 .await
                 .context("Could not fetch output status")?
                 .context("Input tx was not found")?;
+            self.cache
+                .insert_output_status(*outpoint, output_status.clone());
             Ok(output_status)
         });
         let output_statuses = futures::future::join_all(output_status_futs)
             .await
             .into_iter()
-            .collect::<anyhow::Result<Vec<OutputStatus>>>()?;
+            .try_collect_vec()?;
 
         // Map each output to its replacement (`rp_`) txid and # of confs,
         // then find and return the most confirmed of these if one exists.
@@ -424,6 +489,82 @@ impl LexeEsplora {
     }
 }
 
+/// Caches Esplora responses, keyed on endpoint + params, for as long as the
+/// chain tip stays the same.
+///
+/// Everything we cache can only change when a new block arrives (or a reorg
+/// replaces the tip), so we invalidate the whole cache whenever we observe a
+/// new tip hash. Unconfirmed spends of an output can change in between, but we
+/// only act on confirmed spends.
+#[derive(Default)]
+struct ResponseCache {
+    inner: Mutex<ResponseCacheInner>,
+}
+
+#[derive(Default)]
+struct ResponseCacheInner {
+    /// The tip hash that everything in the cache is valid for, and its height.
+    tip: Option<(BlockHash, u32)>,
+    /// `GET /tx/:txid/status`
+    tx_statuses: HashMap<Txid, TxStatus>,
+    /// `GET /tx/:txid/outspend/:vout`
+    output_statuses: HashMap<OutPoint, OutputStatus>,
+}
+
+impl ResponseCache {
+    /// Returns the cached height of the given tip, if it is still our tip.
+    fn best_height(&self, tip_hash: &BlockHash) -> Option<u32> {
+        let inner = self.inner.lock().unwrap();
+        match inner.tip {
+            Some((hash, height)) if hash == *tip_hash => Some(height),
+            _ => None,
+        }
+    }
+
+    /// Sets the current tip, clearing the cache if the tip changed.
+    fn set_tip(&self, tip_hash: BlockHash, height: u32) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.tip.map(|(hash, _)| hash) != Some(tip_hash) {
+            inner.tx_statuses.clear();
+            inner.output_statuses.clear();
+        }
+        inner.tip = Some((tip_hash, height));
+    }
+
+    fn tx_status(&self, txid: &Txid) -> Option<TxStatus> {
+        self.inner.lock().unwrap().tx_statuses.get(txid).cloned()
+    }
+
+    fn insert_tx_status(&self, txid: Txid, tx_status: TxStatus) {
+        self.inner
+            .lock()
+            .unwrap()
+            .tx_statuses
+            .insert(txid, tx_status);
+    }
+
+    fn output_status(&self, outpoint: &OutPoint) -> Option<OutputStatus> {
+        self.inner
+            .lock()
+            .unwrap()
+            .output_statuses
+            .get(outpoint)
+            .cloned()
+    }
+
+    fn insert_output_status(
+        &self,
+        outpoint: OutPoint,
+        output_status: OutputStatus,
+    ) {
+        self.inner
+            .lock()
+            .unwrap()
+            .output_statuses
+            .insert(outpoint, output_status);
+    }
+}
+
 impl BroadcasterInterface for LexeEsplora {
     fn broadcast_transactions(&self, txs: &[&Transaction]) {
         // We can't make LexeEsplora clonable because LDK's API requires a
@@ -464,66 +605,39 @@ impl FeeEstimator for LexeEsplora {
     }
 }
 
-/// A version of [`esplora_client::convert_fee_rate`] which avoids cloning the
-/// entire HashMap when computing the feerate in sats/vbytes.
-///
-/// Functionality: Given a desired target number of blocks by which a tx is
-/// confirmed, and the return value of [`AsyncClient::get_fee_estimates`] which
-/// maps string-encoded (why?) [`usize`] conf targets (in number of blocks) to
-/// the [`f64`] estimated fee rates (in sats per vbyte), extracts the estimated
-/// feerate whose corresponding target is the largest of all targets less than
-/// or equal to our desired target, or defaults to 1 sat per vbyte if our
-/// desired target was lower than the smallest target with a fee estimate.
-fn convert_fee_rate(
-    target: usize,
-    esplora_estimates: &HashMap<String, f64>,
-) -> anyhow::Result<f32> {
-    let fee_val = {
-        let mut pairs = esplora_estimates
-            .iter()
-            .filter_map(|(k, v)| Some((k.parse::<usize>().ok()?, v)))
-            .collect::<Vec<_>>();
-        pairs.sort_unstable_by_key(|(k, _)| std::cmp::Reverse(*k));
-        pairs
-            .into_iter()
-            .find(|(k, _)| k <= &target)
-            .map(|(_, v)| v)
-            .unwrap_or(&1.0)
-    };
-
-    Ok(*fee_val as f32)
-}
-
-#[cfg(all(test, not(target_env = "sgx")))]
+#[cfg(test)]
 mod test {
-    use std::collections::HashMap;
+    use bitcoin::hashes::Hash;
 
-    use proptest::{arbitrary::any, prop_assert_eq, proptest};
+    use super::*;
 
-    /// Check that our [`convert_fee_rate`] function is equivalent to
-    /// [`esplora_client`]'s.
     #[test]
-    fn convert_fee_rate_equiv() {
-        proptest!(|(
-            parsed_estimates in any::<HashMap<usize, f64>>(),
-            target in any::<usize>(),
-        )| {
-            let estimates = parsed_estimates
-                .into_iter()
-                .map(|(k, v)| (k.to_string(), v))
-                .collect::<HashMap<String, f64>>();
-
-            let our_feerate_res = super::convert_fee_rate(target, &estimates);
-            let their_feerate_res =
-                esplora_client::convert_fee_rate(target, estimates);
-
-            match (our_feerate_res, their_feerate_res) {
-                (Err(_), Err(_)) => {
-                    // Both errored, good; don't compare the error types
-                }
-                (Ok(ours), Ok(theirs)) => prop_assert_eq!(ours, theirs),
-                _ => panic!("Results did not match"),
-            }
-        })
+    fn response_cache_invalidation() {
+        let cache = ResponseCache::default();
+        let tip1 = BlockHash::from_inner([1; 32]);
+        let tip2 = BlockHash::from_inner([2; 32]);
+        let txid = Txid::from_inner([3; 32]);
+        let tx_status = TxStatus {
+            confirmed: false,
+            block_height: None,
+            block_hash: None,
+            block_time: None,
+        };
+
+        assert_eq!(cache.best_height(&tip1), None);
+        cache.set_tip(tip1, 100);
+        assert_eq!(cache.best_height(&tip1), Some(100));
+        cache.insert_tx_status(txid, tx_status);
+
+        // Same tip: the cache is kept
+        cache.set_tip(tip1, 100);
+        assert!(cache.tx_status(&txid).is_some());
+
+        // New tip: the cache is cleared
+        assert_eq!(cache.best_height(&tip2), None);
+        cache.set_tip(tip2, 101);
+        assert_eq!(cache.best_height(&tip1), None);
+        assert_eq!(cache.best_height(&tip2), Some(101));
+        assert!(cache.tx_status(&txid).is_none());
     }
 }
@@ -1,3 +1,5 @@
+use std::{io::Cursor, time::Duration};
+
 use anyhow::{anyhow, Context};
 use bitcoin::{
     blockdata::{
@@ -7,14 +9,20 @@ use bitcoin::{
     secp256k1,
 };
 use common::{
+    api::command::QuarantinedEvent,
+    hexstr_or_bytes,
     rng::{Crng, SysRng},
+    sha256,
     test_event::TestEvent,
+    time::TimestampMs,
 };
 use lightning::{
     chain::chaininterface::{ConfirmationTarget, FeeEstimator},
     events::Event,
     sign::SpendableOutputDescriptor,
+    util::ser::{MaybeReadable, Writeable},
 };
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::{debug, warn};
 
@@ -63,6 +71,194 @@ pub fn get_event_name(event: &Event) -> &'static str {
     }
 }
 
+/// Configuration for a [`DeadLetterQueue`].
+#[derive(Copy, Clone, Debug)]
+pub struct DeadLetterConfig {
+    /// Quarantine an event once it has failed this many times.
+    pub max_attempts: u32,
+    /// Quarantine an event which is still failing this long after its first
+    /// failure, regardless of the # of attempts.
+    pub max_age: Duration,
+}
+
+impl Default for DeadLetterConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            max_age: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// Tracks [`Event`]s whose handling failed with [`EventHandleError::Fatal`].
+///
+/// A fatal error shuts down the node without losing the event, so that it is
+/// replayed on the next boot. But an event which fails deterministically (e.g.
+/// because it is malformed) would then wedge the node forever. Once an event
+/// has failed too many times or for too long, the queue quarantines it: the
+/// caller should skip the event, while the queue keeps a copy which operators
+/// can inspect and re-inject later.
+///
+/// The queue should be persisted after every change so that failed attempts
+/// are counted across restarts.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetterQueue {
+    /// Events which have failed but haven't been quarantined (yet).
+    failing: Vec<FailingEvent>,
+    quarantined: Vec<QuarantinedEvent>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct FailingEvent {
+    #[serde(with = "hexstr_or_bytes")]
+    id: [u8; 32],
+    attempts: u32,
+    first_failed_at: TimestampMs,
+}
+
+/// What to do with an event whose handling failed fatally.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeadLetterVerdict {
+    /// Shut down without losing the event, so that it's replayed on next boot.
+    Retry,
+    /// The event has been quarantined; skip it.
+    Quarantine,
+}
+
+/// A stable id for an [`Event`], so that replays of the same event share an
+/// id while distinct events don't.
+///
+/// The serialization alone isn't enough, since LDK doesn't serialize some
+/// events (e.g. [`Event::FundingGenerationReady`]) at all. For these, we also
+/// hash the fields which identify the event: the (temporary) channel id, the
+/// counterparty, and LDK's `user_channel_id` counter.
+pub fn event_id(event: &Event) -> [u8; 32] {
+    let mut key = get_event_name(event).as_bytes().to_vec();
+    match event {
+        Event::OpenChannelRequest {
+            temporary_channel_id,
+            counterparty_node_id,
+            funding_satoshis,
+            push_msat,
+            channel_type: _,
+        } => {
+            key.extend_from_slice(temporary_channel_id);
+            key.extend_from_slice(&counterparty_node_id.serialize());
+            key.extend_from_slice(&funding_satoshis.to_le_bytes());
+            key.extend_from_slice(&push_msat.to_le_bytes());
+        }
+        Event::FundingGenerationReady {
+            temporary_channel_id,
+            counterparty_node_id,
+            channel_value_satoshis,
+            output_script: _,
+            user_channel_id,
+        } => {
+            key.extend_from_slice(temporary_channel_id);
+            key.extend_from_slice(&counterparty_node_id.serialize());
+            key.extend_from_slice(&channel_value_satoshis.to_le_bytes());
+            key.extend_from_slice(&user_channel_id.to_le_bytes());
+        }
+        _ => (),
+    }
+    key.extend_from_slice(&event.encode());
+    sha256::digest(&key).into_inner()
+}
+
+/// Whether `event` may be quarantined at all. Skipping a
+/// [`Event::SpendableOutputs`] or [`Event::PaymentClaimable`] would lose the
+/// user's funds, so these are always retried, even if that blocks the node
+/// until an operator intervenes.
+fn can_quarantine(event: &Event) -> bool {
+    !matches!(
+        event,
+        Event::SpendableOutputs { .. } | Event::PaymentClaimable { .. }
+    )
+}
+
+/// Deserialize the event stored in a [`QuarantinedEvent`].
+pub fn read_event(bytes: &[u8]) -> anyhow::Result<Event> {
+    let mut reader = Cursor::new(bytes);
+    <Event as MaybeReadable>::read(&mut reader)
+        .map_err(|e| anyhow!("Failed to deserialize event: {e:?}"))?
+        .context("This event type can't be re-injected")
+}
+
+impl DeadLetterQueue {
+    /// Record a fatal failure to handle `event`, returning whether the event
+    /// should be retried or has been quarantined.
+    pub fn record_failure(
+        &mut self,
+        config: &DeadLetterConfig,
+        event: &Event,
+        now: TimestampMs,
+    ) -> DeadLetterVerdict {
+        if !can_quarantine(event) {
+            return DeadLetterVerdict::Retry;
+        }
+        let id = event_id(event);
+
+        // Already quarantined, e.g. a re-injected event failed again.
+        if let Some(quarantined) =
+            self.quarantined.iter_mut().find(|q| q.id == id)
+        {
+            quarantined.attempts += 1;
+            return DeadLetterVerdict::Quarantine;
+        }
+
+        let idx = match self.failing.iter().position(|f| f.id == id) {
+            Some(idx) => idx,
+            None => {
+                self.failing.push(FailingEvent {
+                    id,
+                    attempts: 0,
+                    first_failed_at: now,
+                });
+                self.failing.len() - 1
+            }
+        };
+        let failing = &mut self.failing[idx];
+        failing.attempts += 1;
+
+        let age = now
+            .into_duration()
+            .saturating_sub(failing.first_failed_at.into_duration());
+        if failing.attempts < config.max_attempts && age < config.max_age {
+            return DeadLetterVerdict::Retry;
+        }
+
+        let failing = self.failing.remove(idx);
+        self.quarantined.push(QuarantinedEvent {
+            id,
+            event_name: get_event_name(event).to_owned(),
+            attempts: failing.attempts,
+            first_failed_at: failing.first_failed_at,
+            quarantined_at: now,
+            event: event.encode(),
+        });
+        DeadLetterVerdict::Quarantine
+    }
+
+    /// Record that the event with the given id was handled (i.e., it won't be
+    /// replayed), removing it from the queue. Returns whether the queue
+    /// changed and thus needs to be persisted.
+    pub fn record_handled(&mut self, id: &[u8; 32]) -> bool {
+        let len_before = self.failing.len() + self.quarantined.len();
+        self.failing.retain(|f| f.id != *id);
+        self.quarantined.retain(|q| q.id != *id);
+        self.failing.len() + self.quarantined.len() != len_before
+    }
+
+    /// All quarantined events, oldest first.
+    pub fn quarantined(&self) -> &[QuarantinedEvent] {
+        &self.quarantined
+    }
+
+    pub fn get_quarantined(&self, id: &[u8; 32]) -> Option<&QuarantinedEvent> {
+        self.quarantined.iter().find(|q| q.id == *id)
+    }
+}
+
 /// Handles a [`Event::FundingGenerationReady`].
 pub async fn handle_funding_generation_ready<CM, PS>(
     wallet: &LexeWallet,
@@ -161,3 +357,125 @@ where
     test_event_tx.send(TestEvent::SpendableOutputs);
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use lightning::events::HTLCDestination;
+
+    use super::*;
+
+    fn event(n: u8) -> Event {
+        Event::HTLCHandlingFailed {
+            prev_channel_id: [n; 32],
+            failed_next_destination: HTLCDestination::UnknownNextHop {
+                requested_forward_scid: 42,
+            },
+        }
+    }
+
+    #[test]
+    fn dead_letter_quarantine_after_max_attempts() {
+        let config = DeadLetterConfig {
+            max_attempts: 3,
+            max_age: Duration::from_secs(3600),
+        };
+        let now = TimestampMs::from(1_000_000);
+        let mut dlq = DeadLetterQueue::default();
+
+        let (event1, event2) = (event(1), event(2));
+        for _ in 0..2 {
+            let verdict = dlq.record_failure(&config, &event1, now);
+            assert_eq!(verdict, DeadLetterVerdict::Retry);
+        }
+        let verdict = dlq.record_failure(&config, &event2, now);
+        assert_eq!(verdict, DeadLetterVerdict::Retry);
+        let verdict = dlq.record_failure(&config, &event1, now);
+        assert_eq!(verdict, DeadLetterVerdict::Quarantine);
+
+        let quarantined = dlq.quarantined();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].id, event_id(&event1));
+        assert_eq!(quarantined[0].attempts, 3);
+        assert_eq!(quarantined[0].event_name, "HTLCHandlingFailed");
+
+        // Re-injected event can be read back with the same id.
+        let reinjected = read_event(&quarantined[0].event).unwrap();
+        assert_eq!(reinjected, event1);
+        assert_eq!(event_id(&reinjected), event_id(&event1));
+
+        // Failing again keeps it quarantined.
+        let verdict = dlq.record_failure(&config, &reinjected, now);
+        assert_eq!(verdict, DeadLetterVerdict::Quarantine);
+        assert_eq!(dlq.quarantined()[0].attempts, 4);
+
+        // Successfully handling events removes them from the queue.
+        assert!(dlq.record_handled(&event_id(&event1)));
+        assert!(dlq.record_handled(&event_id(&event2)));
+        assert!(!dlq.record_handled(&event_id(&event2)));
+        assert_eq!(dlq, DeadLetterQueue::default());
+    }
+
+    #[test]
+    fn dead_letter_quarantine_after_max_age() {
+        let config = DeadLetterConfig {
+            max_attempts: 100,
+            max_age: Duration::from_secs(3600),
+        };
+        let t0 = TimestampMs::from(1_000_000);
+        let t1 = TimestampMs::try_from(
+            t0.into_duration() + Duration::from_secs(3600),
+        )
+        .unwrap();
+        let mut dlq = DeadLetterQueue::default();
+
+        let event = event(1);
+        let verdict = dlq.record_failure(&config, &event, t0);
+        assert_eq!(verdict, DeadLetterVerdict::Retry);
+        let verdict = dlq.record_failure(&config, &event, t1);
+        assert_eq!(verdict, DeadLetterVerdict::Quarantine);
+        assert_eq!(dlq.quarantined()[0].first_failed_at, t0);
+        assert_eq!(dlq.quarantined()[0].quarantined_at, t1);
+
+        // JSON roundtrip, since the queue is persisted as JSON.
+        let json = serde_json::to_string(&dlq).unwrap();
+        let dlq2 = serde_json::from_str::<DeadLetterQueue>(&json).unwrap();
+        assert_eq!(dlq, dlq2);
+    }
+
+    #[test]
+    fn unserialized_events_have_distinct_ids() {
+        let secp_ctx = secp256k1::Secp256k1::signing_only();
+        let secret_key = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let counterparty_node_id =
+            secp256k1::PublicKey::from_secret_key(&secp_ctx, &secret_key);
+        let funding_ready = |n: u8| Event::FundingGenerationReady {
+            temporary_channel_id: [n; 32],
+            counterparty_node_id,
+            channel_value_satoshis: 100_000,
+            output_script: Script::new(),
+            user_channel_id: u128::from(n),
+        };
+
+        assert_eq!(event_id(&funding_ready(1)), event_id(&funding_ready(1)));
+        assert_ne!(event_id(&funding_ready(1)), event_id(&funding_ready(2)));
+    }
+
+    #[test]
+    fn never_quarantine_funds_critical_events() {
+        let config = DeadLetterConfig {
+            max_attempts: 1,
+            max_age: Duration::ZERO,
+        };
+        let now = TimestampMs::from(1_000_000);
+        let mut dlq = DeadLetterQueue::default();
+
+        let event = Event::SpendableOutputs {
+            outputs: Vec::new(),
+        };
+        for _ in 0..3 {
+            let verdict = dlq.record_failure(&config, &event, now);
+            assert_eq!(verdict, DeadLetterVerdict::Retry);
+        }
+        assert_eq!(dlq, DeadLetterQueue::default());
+    }
+}
@@ -0,0 +1,396 @@
+//! Fee rate estimation sources and sanity bounds.
+//!
+//! [`LexeEsplora`] caches fee rate estimates which are used both when the
+//! wallet builds txs and when LDK negotiates channel closes. Those estimates
+//! come from a [`FeeOracle`], and every estimate passes through
+//! [`FeeBounds::clamp`] before it is cached, so a single bad response (e.g. an
+//! esplora instance suggesting 50,000 sat/vB) can't make us burn the user's
+//! funds on fees.
+//!
+//! [`LexeEsplora`]: crate::esplora::LexeEsplora
+
+use std::{cmp, collections::HashMap, time::Duration};
+
+use anyhow::{ensure, Context};
+use async_trait::async_trait;
+use bdk::FeeRate;
+use esplora_client::AsyncClient;
+use lightning::chain::chaininterface::{
+    ConfirmationTarget, FEERATE_FLOOR_SATS_PER_KW,
+};
+use serde::Deserialize;
+
+/// The duration after which requests to the mempool.space API will time out.
+const MEMPOOL_SPACE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// We never use a fee rate above this many sat/vB, no matter what the oracle
+/// says. Even the most extreme fee spikes so far have stayed well below this.
+pub const MAX_FEERATE_SAT_PER_VB: u32 = 1_000;
+
+/// A source of fee rate estimates.
+#[async_trait]
+pub trait FeeOracle: Send + Sync {
+    /// A short name for this oracle, used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Fetch the current fee rate estimates. The returned estimates are not
+    /// yet clamped; callers should apply [`FeeBounds::clamp`].
+    async fn fee_estimates(&self) -> anyhow::Result<FeeEstimates>;
+}
+
+/// Fee rate estimates for each [`ConfirmationTarget`], in sats per 1000 weight
+/// units as required by LDK.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FeeEstimates {
+    pub high_prio: u32,
+    pub normal: u32,
+    pub background: u32,
+    pub mempool_minimum: u32,
+}
+
+/// Global sanity bounds applied to every fee rate estimate.
+#[derive(Copy, Clone, Debug)]
+pub struct FeeBounds {
+    /// The minimum fee rate in sats per 1000 weight units. Can't be lower
+    /// than LDK's [`FEERATE_FLOOR_SATS_PER_KW`] (the min relay fee).
+    pub floor_sats_per_kw: u32,
+    /// The maximum fee rate in sats per 1000 weight units.
+    pub ceiling_sats_per_kw: u32,
+}
+
+impl FeeEstimates {
+    /// Get the estimate for the given [`ConfirmationTarget`].
+    pub fn get(&self, conf_target: ConfirmationTarget) -> u32 {
+        match conf_target {
+            ConfirmationTarget::HighPriority => self.high_prio,
+            ConfirmationTarget::Normal => self.normal,
+            ConfirmationTarget::Background => self.background,
+            ConfirmationTarget::MempoolMinimum => self.mempool_minimum,
+        }
+    }
+}
+
+impl Default for FeeBounds {
+    fn default() -> Self {
+        Self {
+            floor_sats_per_kw: FEERATE_FLOOR_SATS_PER_KW,
+            ceiling_sats_per_kw: sat_per_vb_to_sats_per_kw(
+                MAX_FEERATE_SAT_PER_VB as f32,
+            ),
+        }
+    }
+}
+
+impl FeeBounds {
+    /// Clamp each estimate to these bounds. Also ensures that estimates for
+    /// more urgent targets are never lower than those for less urgent ones,
+    /// since an oracle returning otherwise is clearly confused.
+    pub fn clamp(&self, estimates: FeeEstimates) -> FeeEstimates {
+        let floor = cmp::max(self.floor_sats_per_kw, FEERATE_FLOOR_SATS_PER_KW);
+        let ceiling = cmp::max(self.ceiling_sats_per_kw, floor);
+        let clamp = |feerate: u32| feerate.clamp(floor, ceiling);
+
+        let mempool_minimum = clamp(estimates.mempool_minimum);
+        let background = cmp::max(clamp(estimates.background), mempool_minimum);
+        let normal = cmp::max(clamp(estimates.normal), background);
+        let high_prio = cmp::max(clamp(estimates.high_prio), normal);
+
+        FeeEstimates {
+            high_prio,
+            normal,
+            background,
+            mempool_minimum,
+        }
+    }
+
+    /// Whether any of the given estimates is above our ceiling.
+    pub fn exceeds_ceiling(&self, estimates: &FeeEstimates) -> bool {
+        let max = [
+            estimates.high_prio,
+            estimates.normal,
+            estimates.background,
+            estimates.mempool_minimum,
+        ]
+        .into_iter()
+        .max()
+        .expect("Not empty");
+        max > self.ceiling_sats_per_kw
+    }
+}
+
+// --- Esplora --- //
+
+/// Fetches fee estimates from an Esplora server's `/fee-estimates` endpoint.
+pub struct EsploraFeeOracle {
+    client: AsyncClient,
+}
+
+impl EsploraFeeOracle {
+    pub fn new(client: AsyncClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl FeeOracle for EsploraFeeOracle {
+    fn name(&self) -> &'static str {
+        "esplora"
+    }
+
+    async fn fee_estimates(&self) -> anyhow::Result<FeeEstimates> {
+        // Why does this return `HashMap<String, _>`???
+        let esplora_estimates = self
+            .client
+            .get_fee_estimates()
+            .await
+            .context("Could not fetch esplora's fee estimates")?;
+
+        let estimate = |num_blocks_target| {
+            let sat_per_vb =
+                convert_fee_rate(num_blocks_target, &esplora_estimates);
+            checked_sats_per_kw(sat_per_vb).with_context(|| {
+                format!("Bad estimate for {num_blocks_target} blocks")
+            })
+        };
+
+        Ok(FeeEstimates {
+            high_prio: estimate(1)?,
+            normal: estimate(3)?,
+            background: estimate(72)?,
+            mempool_minimum: estimate(1008)?,
+        })
+    }
+}
+
+// --- mempool.space --- //
+
+/// Fetches fee estimates from a mempool.space instance's
+/// `/v1/fees/recommended` endpoint.
+pub struct MempoolSpaceFeeOracle {
+    client: reqwest11::Client,
+    /// e.g. "https://mempool.space/api"
+    api_url: String,
+}
+
+/// The response from `/v1/fees/recommended`, in sat/vB.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecommendedFees {
+    fastest_fee: f64,
+    half_hour_fee: f64,
+    economy_fee: f64,
+    minimum_fee: f64,
+}
+
+impl MempoolSpaceFeeOracle {
+    pub fn new(
+        client: reqwest11::Client,
+        api_url: String,
+    ) -> anyhow::Result<Self> {
+        ensure!(
+            api_url.starts_with("https://"),
+            "mempool.space url must use https: {api_url}"
+        );
+        let api_url = api_url.trim_end_matches('/').to_owned();
+        Ok(Self { client, api_url })
+    }
+}
+
+#[async_trait]
+impl FeeOracle for MempoolSpaceFeeOracle {
+    fn name(&self) -> &'static str {
+        "mempool.space"
+    }
+
+    async fn fee_estimates(&self) -> anyhow::Result<FeeEstimates> {
+        let url = format!("{}/v1/fees/recommended", self.api_url);
+        let bytes = self
+            .client
+            .get(url)
+            .timeout(MEMPOOL_SPACE_TIMEOUT)
+            .send()
+            .await
+            .context("mempool.space request failed")?
+            .error_for_status()
+            .context("mempool.space returned an error")?
+            .bytes()
+            .await
+            .context("Failed to read mempool.space response")?;
+        let fees = serde_json::from_slice::<RecommendedFees>(&bytes)
+            .context("Invalid mempool.space response")?;
+
+        Ok(FeeEstimates {
+            high_prio: checked_sats_per_kw(fees.fastest_fee)?,
+            normal: checked_sats_per_kw(fees.half_hour_fee)?,
+            background: checked_sats_per_kw(fees.economy_fee)?,
+            mempool_minimum: checked_sats_per_kw(fees.minimum_fee)?,
+        })
+    }
+}
+
+// --- Fixed --- //
+
+/// Always returns the same estimates. Useful for tests, or as a manual
+/// override if all other oracles are misbehaving.
+pub struct FixedFeeOracle(pub FeeEstimates);
+
+impl FixedFeeOracle {
+    /// Use the same fee rate (in sat/vB) for every [`ConfirmationTarget`].
+    pub fn from_sat_per_vb(sat_per_vb: f32) -> Self {
+        let feerate = sat_per_vb_to_sats_per_kw(sat_per_vb);
+        Self(FeeEstimates {
+            high_prio: feerate,
+            normal: feerate,
+            background: feerate,
+            mempool_minimum: feerate,
+        })
+    }
+}
+
+#[async_trait]
+impl FeeOracle for FixedFeeOracle {
+    fn name(&self) -> &'static str {
+        "fixed"
+    }
+
+    async fn fee_estimates(&self) -> anyhow::Result<FeeEstimates> {
+        Ok(self.0)
+    }
+}
+
+// --- Helpers --- //
+
+/// Munge with units to get to sats per 1000 weight unit required by LDK.
+fn sat_per_vb_to_sats_per_kw(sat_per_vb: f32) -> u32 {
+    FeeRate::from_sat_per_vb(sat_per_vb).fee_wu(1000) as u32
+}
+
+/// Like [`sat_per_vb_to_sats_per_kw`], but rejects values which are negative,
+/// NaN, or infinite rather than letting them saturate.
+fn checked_sats_per_kw(sat_per_vb: f64) -> anyhow::Result<u32> {
+    ensure!(
+        sat_per_vb.is_finite() && sat_per_vb >= 0.0,
+        "Invalid fee rate: {sat_per_vb} sat/vB"
+    );
+    Ok(sat_per_vb_to_sats_per_kw(sat_per_vb as f32))
+}
+
+/// A version of [`esplora_client::convert_fee_rate`] which avoids cloning the
+/// entire HashMap when computing the feerate in sats/vbytes.
+///
+/// Functionality: Given a desired target number of blocks by which a tx is
+/// confirmed, and the return value of [`AsyncClient::get_fee_estimates`] which
+/// maps string-encoded (why?) [`usize`] conf targets (in number of blocks) to
+/// the [`f64`] estimated fee rates (in sats per vbyte), extracts the estimated
+/// feerate whose corresponding target is the largest of all targets less than
+/// or equal to our desired target, or defaults to 1 sat per vbyte if our
+/// desired target was lower than the smallest target with a fee estimate.
+fn convert_fee_rate(
+    target: usize,
+    esplora_estimates: &HashMap<String, f64>,
+) -> f64 {
+    let mut pairs = esplora_estimates
+        .iter()
+        .filter_map(|(k, v)| Some((k.parse::<usize>().ok()?, *v)))
+        .collect::<Vec<_>>();
+    pairs.sort_unstable_by_key(|(k, _)| cmp::Reverse(*k));
+    pairs
+        .into_iter()
+        .find(|(k, _)| k <= &target)
+        .map(|(_, v)| v)
+        .unwrap_or(1.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn estimates(
+        high_prio: u32,
+        normal: u32,
+        background: u32,
+        mempool_minimum: u32,
+    ) -> FeeEstimates {
+        FeeEstimates {
+            high_prio,
+            normal,
+            background,
+            mempool_minimum,
+        }
+    }
+
+    #[test]
+    fn clamp_estimates() {
+        let bounds = FeeBounds::default();
+        let ceiling = bounds.ceiling_sats_per_kw;
+        assert_eq!(ceiling, 250_000);
+
+        // Sane estimates are untouched
+        let sane = estimates(5_000, 2_500, 1_000, 253);
+        assert_eq!(bounds.clamp(sane), sane);
+
+        // Absurd estimates are capped, tiny ones are raised to the floor
+        let absurd = estimates(12_500_000, 2_500, 0, 0);
+        assert_eq!(bounds.clamp(absurd), estimates(ceiling, 2_500, 253, 253));
+        assert!(bounds.exceeds_ceiling(&absurd));
+        assert!(!bounds.exceeds_ceiling(&sane));
+
+        // Less urgent targets never exceed more urgent ones
+        let inverted = estimates(1_000, 2_000, 3_000, 253);
+        assert_eq!(bounds.clamp(inverted), estimates(3_000, 3_000, 3_000, 253),);
+
+        // The floor can't go below LDK's floor
+        let bounds = FeeBounds {
+            floor_sats_per_kw: 0,
+            ceiling_sats_per_kw: 10_000,
+        };
+        let zeroes = estimates(0, 0, 0, 0);
+        let floor = FEERATE_FLOOR_SATS_PER_KW;
+        assert_eq!(bounds.clamp(zeroes), estimates(floor, floor, floor, floor));
+    }
+
+    #[test]
+    fn checked_sats_per_kw_rejects_garbage() {
+        assert_eq!(checked_sats_per_kw(1.0).unwrap(), 250);
+        assert!(checked_sats_per_kw(-1.0).is_err());
+        assert!(checked_sats_per_kw(f64::NAN).is_err());
+        assert!(checked_sats_per_kw(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn mempool_space_response() {
+        let json = r#"{
+            "fastestFee": 12,
+            "halfHourFee": 8,
+            "hourFee": 6,
+            "economyFee": 3,
+            "minimumFee": 1
+        }"#;
+        let fees = serde_json::from_str::<RecommendedFees>(json).unwrap();
+        assert_eq!(fees.fastest_fee, 12.0);
+        assert_eq!(fees.minimum_fee, 1.0);
+    }
+
+    /// Check that our [`convert_fee_rate`] function is equivalent to
+    /// [`esplora_client`]'s.
+    #[cfg(not(target_env = "sgx"))]
+    #[test]
+    fn convert_fee_rate_equiv() {
+        use proptest::{arbitrary::any, prop_assert_eq, proptest};
+
+        proptest!(|(
+            parsed_estimates in any::<HashMap<usize, f64>>(),
+            target in any::<usize>(),
+        )| {
+            let estimates = parsed_estimates
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect::<HashMap<String, f64>>();
+
+            let ours = convert_fee_rate(target, &estimates) as f32;
+            let theirs = esplora_client::convert_fee_rate(target, estimates)
+                .unwrap();
+            prop_assert_eq!(ours.to_bits(), theirs.to_bits());
+        })
+    }
+}
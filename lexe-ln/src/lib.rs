@@ -17,6 +17,8 @@ pub mod alias;
 pub mod background_processor;
 /// Shared functionality relating to opening, closing, managing channels.
 pub mod channel;
+/// Stale channel detection and close recommendations.
+pub mod channel_health;
 /// Channel monitor
 pub mod channel_monitor;
 /// Top level commands that can be initiated by the user.
@@ -25,16 +27,26 @@ pub mod command;
 pub mod esplora;
 /// Event helpers.
 pub mod event;
+/// Fee rate oracles and sanity bounds.
+pub mod fee_oracle;
 /// Keys manager
 pub mod keys_manager;
 /// LDK + SGX compatible logger
 pub mod logger;
 /// Shared functionality relating to LN P2P.
 pub mod p2p;
+/// BIP78 payjoin sender.
+pub mod payjoin;
 /// Payments types.
 pub mod payments;
+/// Per-peer connection health tracking and reconnect backoff.
+pub mod peer_health;
 /// Shared persisted logic.
 pub mod persister;
+/// Rapid gossip sync.
+pub mod rgs;
+/// Per-payment routing controls and the route blacklist.
+pub mod route;
 /// Chain sync.
 pub mod sync;
 /// `TestEvent` channels and utils.
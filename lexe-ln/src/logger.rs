@@ -32,11 +32,26 @@
 //! * `foo` (bare TARGET)
 //! * `foo=trace` (TARGET=LEVEL)
 //! * `foo[{bar,baz}]=info` (TARGET[{FIELD,+}]=LEVEL)
-
-use std::{ops::Deref, str::FromStr};
+//!
+//! ### Runtime Changes
+//!
+//! Lexe operators can change the levels of specific targets on a running node
+//! via [`set_target_levels`], e.g. to see `lightning::routing=debug` logs from
+//! a single misbehaving node. These apply on top of the `RUST_LOG` filter and
+//! last until the node restarts.
+
+use std::{
+    collections::BTreeMap,
+    ops::Deref,
+    str::FromStr,
+    sync::{Mutex, OnceLock, RwLock},
+};
 
 use anyhow::anyhow;
-use common::{api::trace, define_trace_id_fns};
+use common::{
+    api::{log_capture::LogCaptureLayer, trace},
+    define_trace_id_fns,
+};
 use lazy_lock::LazyLock;
 use lightning::util::logger::{Level as LdkLevel, Logger, Record};
 use tracing_core::{
@@ -47,12 +62,13 @@ use tracing_core::{
     Callsite, Event, Kind, Level, Metadata,
 };
 use tracing_subscriber::{
-    filter::{Filtered, Targets},
+    filter::{Filtered, LevelFilter, Targets},
     fmt::{
         format::{Compact, DefaultFields, Format},
         Layer,
     },
     layer::{Layered, SubscriberExt},
+    reload,
     util::SubscriberInitExt,
     Layer as LayerTrait, Registry,
 };
@@ -80,12 +96,35 @@ pub fn init_for_testing() {
     let _ = try_init();
 }
 
+/// The target of all LDK logs; see [`InnerTracingLogger`].
+const LDK_TARGET: &str = "ldk";
+
+/// The filters which can be changed at runtime via [`set_target_levels`].
+static LOG_FILTERS: OnceLock<Mutex<LogFilters>> = OnceLock::new();
+
+/// If set, LDK logs are also filtered by their module path, since they all
+/// share the [`LDK_TARGET`]. See [`set_target_levels`].
+static LDK_MODULE_FILTER: RwLock<Option<Targets>> = RwLock::new(None);
+
+struct LogFilters {
+    /// The filter configured via `RUST_LOG`.
+    base: Targets,
+    /// The levels set at runtime, by target.
+    overrides: BTreeMap<String, LevelFilter>,
+    capture_handle: reload::Handle<Targets, Registry>,
+    stdout_handle: reload::Handle<Targets, CaptureSubscriberType>,
+}
+
 /// Try to initialize a global logger. Will return an `Err` if there is another
 /// global logger already set.
 pub fn try_init() -> anyhow::Result<()> {
-    subscriber()
+    let (subscriber, filters) = subscriber();
+    subscriber
         .try_init()
         .context("Logger already initialized")?;
+    LOG_FILTERS
+        .set(Mutex::new(filters))
+        .map_err(|_| anyhow!("LOG_FILTERS already set"))?;
     define_trace_id_fns!(SubscriberType);
     trace::GET_TRACE_ID_FN
         .set(get_trace_id_from_span)
@@ -96,22 +135,112 @@ pub fn try_init() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Change the levels of specific targets at runtime, on top of the filter
+/// configured via `RUST_LOG`. A `None` level reverts the target to the
+/// configured filter. Changes last until the process exits.
+///
+/// LDK logs all share the [`LDK_TARGET`], so targets starting with `lightning`
+/// (e.g. `lightning::routing`) are matched against the LDK module path instead.
+pub fn set_target_levels(
+    levels: impl IntoIterator<Item = (String, Option<LevelFilter>)>,
+) -> anyhow::Result<()> {
+    let mut filters = LOG_FILTERS
+        .get()
+        .ok_or_else(|| anyhow!("Logger not initialized"))?
+        .lock()
+        .unwrap();
+    for (target, level) in levels {
+        match level {
+            Some(level) => filters.overrides.insert(target, level),
+            None => filters.overrides.remove(&target),
+        };
+    }
+
+    let (targets, ldk_module_filter) =
+        build_filters(&filters.base, &filters.overrides);
+    *LDK_MODULE_FILTER.write().unwrap() = ldk_module_filter;
+    filters
+        .capture_handle
+        .reload(targets.clone())
+        .map_err(|e| anyhow!("Failed to reload log capture filter: {e}"))?;
+    filters
+        .stdout_handle
+        .reload(targets)
+        .map_err(|e| anyhow!("Failed to reload stdout log filter: {e}"))?;
+    Ok(())
+}
+
+/// Applies the runtime `overrides` on top of the `base` filter. Returns the
+/// new [`Targets`] filter, along with the LDK module filter if any `lightning`
+/// targets were overridden.
+fn build_filters(
+    base: &Targets,
+    overrides: &BTreeMap<String, LevelFilter>,
+) -> (Targets, Option<Targets>) {
+    let targets = base.clone().with_targets(overrides.clone());
+
+    let ldk_overrides = overrides
+        .iter()
+        .filter(|(target, _)| target.starts_with("lightning"))
+        .map(|(target, level)| (target.clone(), *level))
+        .collect::<Vec<_>>();
+    if ldk_overrides.is_empty() {
+        return (targets, None);
+    }
+
+    // LDK modules without an override keep the level set for `LDK_TARGET`,
+    // but we need to let through any LDK logs which an override might enable.
+    let ldk_level = max_level(&targets, LDK_TARGET);
+    let max_ldk_level = ldk_overrides
+        .iter()
+        .map(|(_, level)| *level)
+        .fold(ldk_level, Ord::max);
+    let ldk_module_filter = Targets::new()
+        .with_default(ldk_level)
+        .with_targets(ldk_overrides);
+    let targets = targets.with_target(LDK_TARGET, max_ldk_level);
+    (targets, Some(ldk_module_filter))
+}
+
+/// The most verbose level which `filter` enables for `target`.
+fn max_level(filter: &Targets, target: &str) -> LevelFilter {
+    [
+        Level::TRACE,
+        Level::DEBUG,
+        Level::INFO,
+        Level::WARN,
+        Level::ERROR,
+    ]
+    .into_iter()
+    .find(|level| filter.would_enable(target, level))
+    .map(LevelFilter::from_level)
+    .unwrap_or(LevelFilter::OFF)
+}
+
+/// The [`Registry`] with just the log capture layer, which the stdout layer is
+/// layered on top of.
+type CaptureSubscriberType = Layered<
+    Filtered<LogCaptureLayer, reload::Layer<Targets, Registry>, Registry>,
+    Registry,
+>;
+
 /// The full type of our subscriber which is downcasted to when recovering
 /// `TraceId`s. If having trouble naming this correctly, change this to some
 /// dummy value (e.g. `u32`) and the compiler will tell you what it should be.
 type SubscriberType = Layered<
     Filtered<
-        Layer<Registry, DefaultFields, Format<Compact>>,
-        Targets,
-        Registry,
+        Layer<CaptureSubscriberType, DefaultFields, Format<Compact>>,
+        reload::Layer<Targets, CaptureSubscriberType>,
+        CaptureSubscriberType,
     >,
-    Registry,
+    CaptureSubscriberType,
 >;
 
-/// Generates our [`tracing::Subscriber`] impl. This function is extracted so
-/// that we can check the correctness of the `SubscriberType` type alias, which
-/// allows us to downcast back to our subscriber to recover `TraceId`s.
-fn subscriber() -> SubscriberType {
+/// Generates our [`tracing::Subscriber`] impl, along with the handles needed
+/// to change its filters later. This function is extracted so that we can
+/// check the correctness of the `SubscriberType` type alias, which allows us
+/// to downcast back to our subscriber to recover `TraceId`s.
+fn subscriber() -> (SubscriberType, LogFilters) {
     // For the node, just parse a simplified target filter from the env. The
     // `env_filter` feature pulls in too many dependencies (like regex) for SGX.
     //
@@ -121,6 +250,10 @@ fn subscriber() -> SubscriberType {
         .ok()
         .and_then(|rust_log| Targets::from_str(&rust_log).ok())
         .unwrap_or_else(|| Targets::new().with_default(Level::INFO));
+    let (capture_filter, capture_handle) =
+        reload::Layer::new(rust_log_filter.clone());
+    let (stdout_filter, stdout_handle) =
+        reload::Layer::new(rust_log_filter.clone());
 
     let stdout_log = tracing_subscriber::fmt::layer()
         .compact()
@@ -130,9 +263,19 @@ fn subscriber() -> SubscriberType {
         // TODO(max): This should be disabled when outputting to files - a
         //            second subscriber is probably needed.
         .with_ansi(true)
-        .with_filter(rust_log_filter);
-
-    tracing_subscriber::registry().with(stdout_log)
+        .with_filter(stdout_filter);
+    let capture_log = LogCaptureLayer.with_filter(capture_filter);
+
+    let subscriber = tracing_subscriber::registry()
+        .with(capture_log)
+        .with(stdout_log);
+    let filters = LogFilters {
+        base: rust_log_filter,
+        overrides: BTreeMap::new(),
+        capture_handle,
+        stdout_handle,
+    };
+    (subscriber, filters)
 }
 
 // -- LexeTracingLogger -- //
@@ -186,6 +329,11 @@ impl Logger for InnerTracingLogger {
             if !dispatch.enabled(meta) {
                 return;
             }
+            if let Some(filter) = LDK_MODULE_FILTER.read().unwrap().as_ref() {
+                if !filter.would_enable(record.module_path, meta.level()) {
+                    return;
+                }
+            }
 
             let current_span = tracing::Span::current();
 
@@ -235,7 +383,7 @@ macro_rules! log_cs {
         static $cs: $ty = $ty;
         static $meta: Metadata<'static> = Metadata::new(
             "ldk log event",
-            "ldk",
+            LDK_TARGET,
             $level,
             None,
             None,
@@ -350,6 +498,34 @@ mod test {
         });
     }
 
+    #[test]
+    fn ldk_module_overrides() {
+        let base = Targets::new().with_default(Level::INFO);
+        let overrides = BTreeMap::from_iter([
+            ("lightning::routing".to_owned(), LevelFilter::TRACE),
+            ("node".to_owned(), LevelFilter::DEBUG),
+        ]);
+        let (targets, ldk_filter) = build_filters(&base, &overrides);
+        let ldk_filter = ldk_filter.unwrap();
+
+        assert!(targets.would_enable("node::run", &Level::DEBUG));
+        assert!(!targets.would_enable("lexe_ln", &Level::DEBUG));
+        // LDK logs are let through, then filtered by module path.
+        assert!(targets.would_enable(LDK_TARGET, &Level::TRACE));
+        let router = "lightning::routing::router";
+        assert!(ldk_filter.would_enable(router, &Level::TRACE));
+        let chanman = "lightning::ln::channelmanager";
+        assert!(ldk_filter.would_enable(chanman, &Level::INFO));
+        assert!(!ldk_filter.would_enable(chanman, &Level::DEBUG));
+
+        // No LDK module filter without `lightning` overrides.
+        let overrides =
+            BTreeMap::from_iter([("node".to_owned(), LevelFilter::DEBUG)]);
+        let (targets, ldk_filter) = build_filters(&base, &overrides);
+        assert!(ldk_filter.is_none());
+        assert!(!targets.would_enable(LDK_TARGET, &Level::DEBUG));
+    }
+
     #[test]
     fn get_and_insert_trace_ids() {
         let _ = try_init();
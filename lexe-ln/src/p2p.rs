@@ -1,4 +1,7 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
 use anyhow::{bail, Context};
 use common::{
@@ -12,12 +15,17 @@ use futures::future;
 use tokio::{net::TcpStream, sync::mpsc, time};
 use tracing::{debug, info, info_span, warn, Instrument};
 
-use crate::traits::{LexeChannelManager, LexePeerManager, LexePersister};
+use crate::{
+    peer_health::{PeerHealthTracker, PeerKind},
+    traits::{LexeChannelManager, LexePeerManager, LexePersister},
+};
 
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 /// The maximum amount of time we'll allow LDK to complete the P2P handshake.
 const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
-const P2P_RECONNECT_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the reconnector checks for disconnected peers. Each peer's
+/// [`PeerHealthTracker`] backoff determines whether it is actually retried.
+const P2P_RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
 
 /// Every time a channel peer is added or removed, a [`ChannelPeerUpdate`] is
 /// generated and sent to the [p2p reconnector task] via an [`mpsc`] channel.
@@ -182,6 +190,9 @@ where
 /// If you do NOT wish to immediately reconnect to a given channel peer (e.g.
 /// LSP should not reconnect to user nodes which are still offline), simply do
 /// not send the [`ChannelPeerUpdate::Add`] until the peer (user node) is ready.
+///
+/// Reconnect attempts to each peer are rate limited by a [`PeerHealthTracker`],
+/// which backs off from peers that fail to connect or keep disconnecting.
 pub fn spawn_p2p_reconnector<CM, PM, PS>(
     peer_manager: PM,
     initial_channel_peers: Vec<ChannelPeer>,
@@ -197,12 +208,17 @@ where
         "p2p reconnectooor",
         async move {
             let mut interval = time::interval(P2P_RECONNECT_INTERVAL);
+            let mut health_tracker = PeerHealthTracker::new();
 
             // The current set of `ChannelPeer`s, indexed by their `NodePk`.
             let mut channel_peers = initial_channel_peers
                 .into_iter()
                 .map(|cp| (cp.node_pk, cp))
                 .collect::<HashMap<NodePk, ChannelPeer>>();
+            let now = Instant::now();
+            for node_pk in channel_peers.keys() {
+                health_tracker.add_peer(*node_pk, PeerKind::Channel, now);
+            }
 
             loop {
                 // Retry reconnect when timer ticks or we get an update
@@ -213,10 +229,18 @@ where
                         // We received a ChannelPeerUpdate; update our HashMap of
                         // current channel peers accordingly.
                         match cp_update {
-                            ChannelPeerUpdate::Add(cp) =>
-                                channel_peers.insert(cp.node_pk, cp),
-                            ChannelPeerUpdate::Remove(cp) =>
-                                channel_peers.remove(&cp.node_pk),
+                            ChannelPeerUpdate::Add(cp) => {
+                                health_tracker.add_peer(
+                                    cp.node_pk,
+                                    PeerKind::Channel,
+                                    Instant::now(),
+                                );
+                                channel_peers.insert(cp.node_pk, cp);
+                            }
+                            ChannelPeerUpdate::Remove(cp) => {
+                                health_tracker.remove_peer(&cp.node_pk);
+                                channel_peers.remove(&cp.node_pk);
+                            }
                         };
                         // TODO(max): We should also update the channel peers
                         // that are persisted, but only after differentiating
@@ -226,24 +250,30 @@ where
                     () = shutdown.recv() => break,
                 }
 
-                // Generate futures to reconnect to all disconnected peers.
-                let mut disconnected_peers = channel_peers.clone();
-                for (pk, _addr) in peer_manager.get_peer_node_ids() {
-                    disconnected_peers.remove(&NodePk(pk));
-                }
-                let reconnect_futs = disconnected_peers
-                    .into_values()
+                // Update peer health with the set of connected peers, then
+                // get the disconnected peers which are due for a reconnect,
+                // highest priority first.
+                let connected_peers = peer_manager
+                    .get_peer_node_ids()
+                    .into_iter()
+                    .map(|(pk, _addr)| NodePk(pk))
+                    .collect::<HashSet<NodePk>>();
+                health_tracker
+                    .observe_connected(&connected_peers, Instant::now());
+                let reconnect_futs = health_tracker
+                    .peers_to_reconnect(Instant::now())
+                    .into_iter()
+                    .filter_map(|node_pk| channel_peers.get(&node_pk).cloned())
                     .map(|peer| {
                         let peer_manager_clone = peer_manager.clone();
                         let reconnect_fut = async move {
+                            let start = Instant::now();
                             let res = do_connect_peer(
                                 peer_manager_clone,
                                 peer.clone(),
                             )
                             .await;
-                            if let Err(e) = res {
-                                warn!("Couldn't reconnect to {peer}: {e:#}");
-                            }
+                            (peer, res.map(|()| start.elapsed()))
                         };
 
                         reconnect_fut.in_current_span()
@@ -251,9 +281,47 @@ where
                     .collect::<Vec<_>>();
 
                 // Do the reconnect(s), quit early if shutting down
-                tokio::select! {
-                    _ = future::join_all(reconnect_futs) => (),
+                let results = tokio::select! {
+                    results = future::join_all(reconnect_futs) => results,
                     () = shutdown.recv() => break,
+                };
+
+                // Record the results. Only log repeated failures at debug so we
+                // don't flood the logs while e.g. the LSP is down for
+                // maintenance.
+                for (peer, res) in results {
+                    let node_pk = &peer.node_pk;
+                    match res {
+                        Ok(latency) => {
+                            let prior_failures = health_tracker
+                                .record_connect_success(node_pk, latency);
+                            if prior_failures > 0 {
+                                info!(
+                                    "Reconnected to {peer} after \
+                                     {prior_failures} failed attempts"
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            let (failures, backoff) = health_tracker
+                                .record_connect_failure(
+                                    node_pk,
+                                    Instant::now(),
+                                );
+                            if failures == 1 {
+                                warn!(
+                                    "Couldn't reconnect to {peer}; \
+                                     retrying in {backoff:?}: {e:#}"
+                                );
+                            } else {
+                                debug!(
+                                    "Couldn't reconnect to {peer} \
+                                     ({failures} failures); \
+                                     retrying in {backoff:?}: {e:#}"
+                                );
+                            }
+                        }
+                    }
                 }
             }
 
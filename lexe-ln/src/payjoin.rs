@@ -0,0 +1,498 @@
+//! BIP78 payjoin sender.
+//!
+//! If the BIP21 URI we're paying includes a `pj=` endpoint, the receiver
+//! supports payjoin: we send them our signed "original" tx as a PSBT, and they
+//! respond with a "proposal" PSBT which adds some of their own inputs to the
+//! tx (increasing their output by the same amount). This breaks the
+//! common-input-ownership heuristic used by chain analysis.
+//!
+//! The original tx is a valid payment in its own right, so if anything about
+//! the negotiation fails, we simply broadcast the original tx instead.
+//!
+//! <https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki>
+
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{anyhow, ensure, Context};
+use bdk::FeeRate;
+use bitcoin::{
+    consensus::encode,
+    util::psbt::{Input, PartiallySignedTransaction},
+    OutPoint, Script, TxIn, TxOut, Witness,
+};
+
+/// How long we'll wait for the receiver's proposal. This must be well below
+/// the app's request timeout, since we still need to broadcast afterwards.
+const PAYJOIN_TIMEOUT: Duration = Duration::from_secs(8);
+/// The vsize of a P2WPKH input. We let the receiver deduct the fees for one
+/// such input from our change, so that adding their input doesn't lower the
+/// feerate of the tx.
+const P2WPKH_INPUT_VSIZE: usize = 68;
+/// The max size of a proposal we'll accept from the receiver.
+const MAX_PROPOSAL_BYTES: usize = 1 << 20;
+
+/// Sends payjoin requests to receivers' payjoin endpoints.
+#[derive(Clone)]
+pub struct PayjoinClient(reqwest11::Client);
+
+/// The params of a payjoin request, which the proposal is validated against.
+pub(crate) struct PayjoinParams {
+    /// The index of our change output in the original tx, if there is one.
+    /// The receiver may deduct fees for their inputs from this output.
+    pub fee_output_index: Option<usize>,
+    /// The max fee (in sats) the receiver may deduct from our change output.
+    pub max_additional_fee: u64,
+    /// The feerate of our original tx.
+    pub min_feerate: FeeRate,
+}
+
+impl PayjoinClient {
+    pub fn new() -> anyhow::Result<Self> {
+        // Payjoin endpoints can be hosted anywhere, so (unlike our other
+        // clients) we trust the standard webpki roots here.
+        let client = reqwest11::ClientBuilder::new()
+            .tls_built_in_root_certs(true)
+            .timeout(PAYJOIN_TIMEOUT)
+            .build()
+            .context("Failed to build reqwest client")?;
+        Ok(Self(client))
+    }
+
+    /// Sends our signed `original` PSBT to the receiver's `endpoint`, returning
+    /// their (unvalidated) payjoin proposal.
+    pub(crate) async fn request_proposal(
+        &self,
+        endpoint: &str,
+        original: &PartiallySignedTransaction,
+        params: &PayjoinParams,
+    ) -> anyhow::Result<PartiallySignedTransaction> {
+        let mut query = vec![
+            ("v", "1".to_owned()),
+            // We never let the receiver substitute the payment output.
+            ("disableoutputsubstitution", "true".to_owned()),
+            ("minfeerate", params.min_feerate.as_sat_per_vb().to_string()),
+        ];
+        if let Some(index) = params.fee_output_index {
+            query.push(("additionalfeeoutputindex", index.to_string()));
+            query.push((
+                "maxadditionalfeecontribution",
+                params.max_additional_fee.to_string(),
+            ));
+        }
+
+        let body = base64::encode(encode::serialize(&strip_keypaths(original)));
+        let resp = self
+            .0
+            .post(endpoint)
+            .query(&query)
+            .header("content-type", "text/plain")
+            .body(body)
+            .send()
+            .await
+            .context("Payjoin request failed")?;
+
+        let status = resp.status();
+        let body = read_proposal_body(resp, MAX_PROPOSAL_BYTES).await?;
+        let text = String::from_utf8_lossy(&body);
+        ensure!(
+            status.is_success(),
+            "Payjoin receiver returned {status}: {text}"
+        );
+
+        let bytes = base64::decode(text.trim()).context("Invalid base64")?;
+        encode::deserialize(&bytes).context("Invalid proposal PSBT")
+    }
+}
+
+/// Reads the body of `resp`, bailing as soon as it exceeds `max_bytes` rather
+/// than buffering an arbitrarily large response from the receiver.
+async fn read_proposal_body(
+    mut resp: reqwest11::Response,
+    max_bytes: usize,
+) -> anyhow::Result<Vec<u8>> {
+    if let Some(content_length) = resp.content_length() {
+        ensure!(
+            content_length <= max_bytes as u64,
+            "Proposal too large: {content_length} bytes"
+        );
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) =
+        resp.chunk().await.context("Failed to read response")?
+    {
+        ensure!(
+            body.len() + chunk.len() <= max_bytes,
+            "Proposal too large: over {max_bytes} bytes"
+        );
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Checks the receiver's `proposal` against our `original` PSBT, following
+/// the sender checklist in BIP78. `is_mine` reports whether a script is ours.
+/// Returns our total contribution to the proposal tx, i.e. the value of our
+/// inputs minus the value of our outputs.
+pub(crate) fn validate_proposal(
+    original: &PartiallySignedTransaction,
+    proposal: &PartiallySignedTransaction,
+    params: &PayjoinParams,
+    is_mine: impl Fn(&Script) -> anyhow::Result<bool>,
+) -> anyhow::Result<u64> {
+    let original_tx = &original.unsigned_tx;
+    let proposal_tx = &proposal.unsigned_tx;
+    ensure!(
+        proposal_tx.version == original_tx.version,
+        "Proposal changed the tx version"
+    );
+    ensure!(
+        proposal_tx.lock_time == original_tx.lock_time,
+        "Proposal changed the tx locktime"
+    );
+    let sequence = original_tx
+        .input
+        .first()
+        .context("Original tx has no inputs")?
+        .sequence;
+
+    // Inputs: all of ours must be there unchanged, and the receiver's inputs
+    // must be signed and not spend any of our outputs.
+    let mut our_inputs = original_tx
+        .input
+        .iter()
+        .zip(&original.inputs)
+        .map(|(txin, input)| (txin.previous_output, input))
+        .collect::<HashMap<_, _>>();
+    let mut total_in = 0;
+    let mut our_in = 0;
+    for (txin, input) in proposal_tx.input.iter().zip(&proposal.inputs) {
+        let outpoint = txin.previous_output;
+        ensure!(txin.sequence == sequence, "Proposal changed input sequence");
+        match our_inputs.remove(&outpoint) {
+            Some(original_input) => {
+                let value = spent_txout(original_input, &outpoint)?.value;
+                total_in += value;
+                our_in += value;
+            }
+            None => {
+                ensure!(
+                    input.final_script_sig.is_some()
+                        || input.final_script_witness.is_some(),
+                    "Receiver input {outpoint} isn't signed"
+                );
+                let txout = spent_txout(input, &outpoint)?;
+                ensure!(
+                    !is_mine(&txout.script_pubkey)?,
+                    "Receiver input {outpoint} spends one of our outputs"
+                );
+                total_in += txout.value;
+            }
+        }
+    }
+    ensure!(
+        our_inputs.is_empty(),
+        "Proposal is missing some of our inputs"
+    );
+
+    // Outputs: all original outputs must be there, with only our change
+    // output decreased, and by no more than we allowed.
+    let mut change_decrease = 0;
+    for (index, original_txout) in original_tx.output.iter().enumerate() {
+        let txout = proposal_tx
+            .output
+            .iter()
+            .find(|o| o.script_pubkey == original_txout.script_pubkey)
+            .with_context(|| format!("Proposal is missing output {index}"))?;
+        let min_value = if params.fee_output_index == Some(index) {
+            original_txout
+                .value
+                .saturating_sub(params.max_additional_fee)
+        } else {
+            original_txout.value
+        };
+        ensure!(
+            txout.value >= min_value,
+            "Proposal decreased output {index} by too much"
+        );
+        if params.fee_output_index == Some(index) {
+            change_decrease = original_txout.value.saturating_sub(txout.value);
+        }
+    }
+    let mut total_out = 0;
+    let mut our_out = 0;
+    for txout in &proposal_tx.output {
+        total_out += txout.value;
+        if is_mine(&txout.script_pubkey)? {
+            our_out += txout.value;
+        }
+    }
+
+    // Fees: the receiver can't make the tx pay less in fees, nor make us pay
+    // more than the fee contribution we allowed.
+    let original_fee = fee(original)?;
+    let proposal_fee = total_in
+        .checked_sub(total_out)
+        .context("Proposal outputs exceed its inputs")?;
+    ensure!(
+        proposal_fee >= original_fee,
+        "Proposal pays less in fees than our original tx"
+    );
+    // Anything deducted from our change must go towards the fee, not to the
+    // receiver.
+    ensure!(
+        change_decrease <= proposal_fee - original_fee,
+        "Proposal decreased our change by more than the fee increase"
+    );
+    let proposal_vsize = estimate_vsize(proposal, &original_tx.input);
+    let proposal_feerate = proposal_fee as f32 / proposal_vsize as f32;
+    ensure!(
+        proposal_feerate >= params.min_feerate.as_sat_per_vb(),
+        "Proposal feerate is below our min feerate"
+    );
+    let mut original_our_out = 0;
+    for txout in &original_tx.output {
+        if is_mine(&txout.script_pubkey)? {
+            original_our_out += txout.value;
+        }
+    }
+    let original_contribution = total_value_in(original)? - original_our_out;
+    let contribution = our_in
+        .checked_sub(our_out)
+        .context("Proposal pays us more than we put in")?;
+    ensure!(
+        contribution <= original_contribution + params.max_additional_fee,
+        "Proposal makes us pay more than we allowed"
+    );
+
+    Ok(contribution)
+}
+
+/// Returns the max fee contribution we'll allow the receiver to deduct from
+/// our change output, given the feerate of our original tx.
+pub(crate) fn max_additional_fee(feerate: FeeRate) -> u64 {
+    feerate.fee_vb(P2WPKH_INPUT_VSIZE)
+}
+
+/// Removes the BIP32 key paths from a PSBT, which the receiver doesn't need
+/// and which would reveal info about our wallet.
+fn strip_keypaths(
+    psbt: &PartiallySignedTransaction,
+) -> PartiallySignedTransaction {
+    let mut psbt = psbt.clone();
+    psbt.xpub.clear();
+    for input in &mut psbt.inputs {
+        input.bip32_derivation.clear();
+        input.tap_key_origins.clear();
+    }
+    for output in &mut psbt.outputs {
+        output.bip32_derivation.clear();
+        output.tap_key_origins.clear();
+    }
+    psbt
+}
+
+/// Returns the [`TxOut`] spent by a PSBT input.
+fn spent_txout(input: &Input, outpoint: &OutPoint) -> anyhow::Result<TxOut> {
+    if let Some(txout) = &input.witness_utxo {
+        return Ok(txout.clone());
+    }
+    let prev_tx = input
+        .non_witness_utxo
+        .as_ref()
+        .with_context(|| format!("Input {outpoint} is missing its UTXO"))?;
+    ensure!(prev_tx.txid() == outpoint.txid, "Wrong UTXO for {outpoint}");
+    prev_tx
+        .output
+        .get(outpoint.vout as usize)
+        .cloned()
+        .ok_or_else(|| anyhow!("Wrong UTXO for {outpoint}"))
+}
+
+/// Estimates the vsize of the signed proposal tx. The receiver's inputs are
+/// already signed, while ours (which the receiver strips) will be P2WPKH.
+fn estimate_vsize(
+    proposal: &PartiallySignedTransaction,
+    our_txins: &[TxIn],
+) -> usize {
+    let mut tx = proposal.unsigned_tx.clone();
+    for (txin, input) in tx.input.iter_mut().zip(&proposal.inputs) {
+        let is_ours = our_txins
+            .iter()
+            .any(|our| our.previous_output == txin.previous_output);
+        if is_ours {
+            // A 72 byte signature (incl. sighash flag) and a 33 byte pubkey.
+            txin.witness = Witness::from_vec(vec![vec![0; 72], vec![0; 33]]);
+        } else {
+            if let Some(script_sig) = &input.final_script_sig {
+                txin.script_sig = script_sig.clone();
+            }
+            if let Some(witness) = &input.final_script_witness {
+                txin.witness = witness.clone();
+            }
+        }
+    }
+    (tx.weight() + 3) / 4
+}
+
+fn total_value_in(psbt: &PartiallySignedTransaction) -> anyhow::Result<u64> {
+    let mut total = 0;
+    for (txin, input) in psbt.unsigned_tx.input.iter().zip(&psbt.inputs) {
+        total += spent_txout(input, &txin.previous_output)?.value;
+    }
+    Ok(total)
+}
+
+fn fee(psbt: &PartiallySignedTransaction) -> anyhow::Result<u64> {
+    let total_out = psbt.unsigned_tx.output.iter().map(|o| o.value).sum();
+    total_value_in(psbt)?
+        .checked_sub(total_out)
+        .context("Outputs exceed inputs")
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::{hashes::Hash, PackedLockTime, Sequence, Transaction, Txid};
+
+    use super::*;
+
+    const OURS: [u8; 22] = [1; 22];
+    const CHANGE: [u8; 22] = [2; 22];
+    const PAYEE: [u8; 22] = [3; 22];
+    const THEIRS: [u8; 22] = [4; 22];
+
+    fn is_mine(script: &Script) -> anyhow::Result<bool> {
+        Ok([&OURS[..], &CHANGE[..]].contains(&script.as_bytes()))
+    }
+
+    fn txout(script: [u8; 22], value: u64) -> TxOut {
+        TxOut {
+            value,
+            script_pubkey: Script::from(script.to_vec()),
+        }
+    }
+
+    /// Builds a PSBT spending the given (txid byte, spent txout) inputs.
+    fn psbt(
+        inputs: &[(u8, TxOut)],
+        outputs: &[TxOut],
+    ) -> PartiallySignedTransaction {
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|(txid, _)| TxIn {
+                    previous_output: OutPoint {
+                        txid: Txid::from_inner([*txid; 32]),
+                        vout: 0,
+                    },
+                    script_sig: Script::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs.to_vec(),
+        };
+        let mut psbt =
+            PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        for (input, (txid, txout)) in psbt.inputs.iter_mut().zip(inputs) {
+            input.witness_utxo = Some(txout.clone());
+            if *txid != 0 {
+                input.final_script_witness =
+                    Some(Witness::from_vec(vec![vec![*txid]]));
+            }
+        }
+        psbt
+    }
+
+    #[test]
+    fn proposal_validation() {
+        let params = PayjoinParams {
+            fee_output_index: Some(1),
+            max_additional_fee: 200,
+            min_feerate: FeeRate::from_sat_per_vb(2.0),
+        };
+        let ours = (0, txout(OURS, 100_000));
+        let theirs = (1, txout(THEIRS, 30_000));
+        let original = psbt(
+            &[ours.clone()],
+            &[txout(PAYEE, 50_000), txout(CHANGE, 49_000)],
+        );
+        let validate = |proposal| {
+            validate_proposal(&original, &proposal, &params, is_mine)
+        };
+
+        // The receiver adds their input to the payment, deducting some fees
+        // for it from our change.
+        let proposal = psbt(
+            &[ours.clone(), theirs.clone()],
+            &[txout(PAYEE, 80_000), txout(CHANGE, 48_900)],
+        );
+        assert_eq!(validate(proposal).unwrap(), 51_100);
+
+        // Deducting more than we allowed from our change
+        let proposal = psbt(
+            &[ours.clone(), theirs.clone()],
+            &[txout(PAYEE, 80_200), txout(CHANGE, 48_700)],
+        );
+        validate(proposal).unwrap_err();
+
+        // Deducting more from our change than the fee increase, i.e. paying
+        // part of our change to the receiver
+        let proposal = psbt(
+            &[ours.clone(), theirs.clone()],
+            &[txout(PAYEE, 80_100), txout(CHANGE, 48_850)],
+        );
+        validate(proposal).unwrap_err();
+
+        // Paying less than our min feerate
+        let proposal = psbt(
+            &[ours.clone(), theirs.clone()],
+            &[txout(PAYEE, 80_000), txout(CHANGE, 48_900)],
+        );
+        let high_min_feerate = PayjoinParams {
+            min_feerate: FeeRate::from_sat_per_vb(50.0),
+            ..params
+        };
+        validate_proposal(&original, &proposal, &high_min_feerate, is_mine)
+            .unwrap_err();
+
+        // Decreasing the payment output
+        let proposal = psbt(
+            &[ours.clone(), theirs.clone()],
+            &[
+                txout(PAYEE, 49_000),
+                txout(CHANGE, 49_000),
+                txout(THEIRS, 31_000),
+            ],
+        );
+        validate(proposal).unwrap_err();
+
+        // Dropping our input
+        let proposal = psbt(&[theirs.clone()], &[txout(PAYEE, 29_000)]);
+        validate(proposal).unwrap_err();
+
+        // Unsigned receiver input
+        let mut proposal = psbt(
+            &[ours.clone(), theirs.clone()],
+            &[txout(PAYEE, 80_000), txout(CHANGE, 48_900)],
+        );
+        proposal.inputs[1].final_script_witness = None;
+        validate(proposal).unwrap_err();
+
+        // Receiver input which spends one of our outputs
+        let proposal = psbt(
+            &[ours.clone(), (1, txout(OURS, 30_000))],
+            &[txout(PAYEE, 80_000), txout(CHANGE, 48_900)],
+        );
+        validate(proposal).unwrap_err();
+
+        // Lowering the fee
+        let proposal = psbt(
+            &[ours, theirs],
+            &[txout(PAYEE, 80_900), txout(CHANGE, 49_000)],
+        );
+        validate(proposal).unwrap_err();
+    }
+}
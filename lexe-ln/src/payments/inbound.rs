@@ -329,6 +329,35 @@ impl InboundInvoicePayment {
 
         Some(clone)
     }
+
+    /// Returns when this payment's invoice expires, if it is still awaiting
+    /// payment, i.e. if [`check_invoice_expiry`] could expire it.
+    ///
+    /// [`check_invoice_expiry`]: Self::check_invoice_expiry
+    pub(crate) fn pending_expiry(&self) -> Option<Duration> {
+        match self.status {
+            InboundInvoicePaymentStatus::InvoiceGenerated =>
+                self.invoice.0.expires_at(),
+            _ => None,
+        }
+    }
+
+    /// Whether this payment expired without ever being paid, longer than
+    /// `retention` before `unix_duration`, and can thus be archived.
+    pub(crate) fn is_archivable(
+        &self,
+        unix_duration: Duration,
+        retention: Duration,
+    ) -> bool {
+        let expired_unpaid =
+            matches!(self.status, InboundInvoicePaymentStatus::Expired)
+                && self.recvd_amount.is_none();
+        let past_retention = self
+            .finalized_at
+            .and_then(|t| t.into_duration().checked_add(retention))
+            .is_some_and(|archivable_at| archivable_at < unix_duration);
+        expired_unpaid && past_retention
+    }
 }
 
 // --- Inbound spontaneous payments --- //
@@ -452,7 +481,10 @@ impl InboundSpontaneousPayment {
 
 #[cfg(test)]
 mod test {
-    use common::test_utils::roundtrip::json_unit_enum_backwards_compat;
+    use common::{
+        rng::WeakRng, test_utils::roundtrip::json_unit_enum_backwards_compat,
+    };
+    use proptest::arbitrary::any;
 
     use super::*;
 
@@ -469,4 +501,29 @@ mod test {
             expected_ser,
         );
     }
+
+    #[test]
+    fn archivable() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let retention = 30 * day;
+        let finalized_at = TimestampMs::try_from(100 * day).unwrap();
+
+        let mut rng = WeakRng::from_u64(20241016);
+        let mut iip =
+            arbitrary::gen_value(&mut rng, any::<InboundInvoicePayment>());
+        iip.status = InboundInvoicePaymentStatus::Expired;
+        iip.recvd_amount = None;
+        iip.finalized_at = Some(finalized_at);
+
+        assert!(!iip.is_archivable(120 * day, retention));
+        assert!(iip.is_archivable(131 * day, retention));
+
+        // Never archive payments which received funds or aren't expired.
+        let mut paid = iip.clone();
+        paid.recvd_amount = Some(Amount::from_msat(1000));
+        assert!(!paid.is_archivable(131 * day, retention));
+        let mut completed = iip.clone();
+        completed.status = InboundInvoicePaymentStatus::Completed;
+        assert!(!completed.is_archivable(131 * day, retention));
+    }
 }
@@ -6,19 +6,27 @@ use std::{
 
 use anyhow::{bail, ensure, Context};
 use bdk::TransactionDetails;
+use bitcoin::Address;
 use common::{
-    api::qs::UpdatePaymentNote,
+    api::{
+        command::GetAddressForRequest,
+        qs::{GetNewPayments, UpdatePaymentNote},
+    },
+    constants::MAX_PAYMENTS_BATCH_SIZE,
+    iter::IteratorExt,
     ln::{
         amount::Amount,
         hashes::LxTxid,
         payments::{
-            LxPaymentHash, LxPaymentId, LxPaymentPreimage, PaymentStatus,
+            LxPaymentHash, LxPaymentId, LxPaymentPreimage, PaymentIndex,
+            PaymentStatus,
         },
     },
     notify,
     shutdown::ShutdownChannel,
     task::LxTask,
     test_event::TestEvent,
+    time::TimestampMs,
 };
 use lightning::{events::PaymentPurpose, ln::channelmanager::FailureCode};
 use rust_decimal::Decimal;
@@ -30,7 +38,9 @@ use crate::{
     esplora::{LexeEsplora, TxConfStatus},
     payments::{
         inbound::{InboundSpontaneousPayment, LxPaymentPurpose},
-        onchain::OnchainReceive,
+        onchain::{
+            ExpectedDeposit, OnchainCpfp, OnchainReceive, MAX_EXPECTED_DEPOSITS,
+        },
         Payment,
     },
     test_event::TestEventSender,
@@ -38,8 +48,23 @@ use crate::{
     wallet::LexeWallet,
 };
 
-/// The interval at which we check our pending payments for expired invoices.
+/// The maximum interval at which we check our pending payments for expired
+/// invoices. We also check as soon as the next pending invoice expires.
 const INVOICE_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(120);
+/// Invoices only count as expired strictly after their expiry time, so we
+/// check a little after the next invoice expires.
+const INVOICE_EXPIRY_GRACE: Duration = Duration::from_secs(1);
+/// How long expired, unpaid inbound invoice payments are kept in the payments
+/// DB before they are archived.
+const EXPIRED_INVOICE_RETENTION: Duration =
+    Duration::from_secs(30 * 24 * 60 * 60);
+/// The interval at which we archive long-expired inbound invoice payments.
+const EXPIRED_INVOICE_ARCHIVE_INTERVAL: Duration =
+    Duration::from_secs(6 * 60 * 60);
+/// The maximum # of payment batches scanned for archivable payments in a
+/// single run, to bound the work done at once. The next run continues where
+/// the previous one stopped.
+const MAX_ARCHIVE_BATCHES_PER_RUN: usize = 10;
 /// The interval at which we check our onchain payments for confirmations.
 const ONCHAIN_PAYMENT_CHECK_INTERVAL: Duration = Duration::from_secs(120);
 
@@ -65,9 +90,14 @@ pub struct PersistedPayment(pub Payment);
 #[derive(Clone)]
 pub struct PaymentsManager<CM: LexeChannelManager<PS>, PS: LexePersister> {
     data: Arc<Mutex<PaymentsData>>,
+    /// Addresses issued for deposits which haven't been paid yet.
+    expected_deposits: Arc<Mutex<Vec<ExpectedDeposit>>>,
     persister: PS,
     channel_manager: CM,
     test_event_tx: TestEventSender,
+    /// Notifies the invoice expiry checker of a new payment, whose invoice may
+    /// expire before the checker's next scheduled check.
+    new_payment_tx: notify::Sender,
 }
 
 /// The main payments state machine, exposing private methods available only to
@@ -106,11 +136,12 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
         esplora: Arc<LexeEsplora>,
         pending_payments: Vec<Payment>,
         finalized_payment_ids: Vec<LxPaymentId>,
+        expected_deposits: Vec<ExpectedDeposit>,
         wallet: LexeWallet,
         onchain_recv_rx: notify::Receiver,
         test_event_tx: TestEventSender,
         shutdown: ShutdownChannel,
-    ) -> (Self, [LxTask<()>; 3]) {
+    ) -> (Self, [LxTask<()>; 4]) {
         let pending = pending_payments
             .into_iter()
             // Check that payments are indeed pending before adding to hashmap
@@ -131,16 +162,22 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
         let finalized = finalized_payment_ids.into_iter().collect();
 
         let data = Arc::new(Mutex::new(PaymentsData { pending, finalized }));
+        let expected_deposits = Arc::new(Mutex::new(expected_deposits));
+        let (new_payment_tx, new_payment_rx) = notify::channel();
 
         let myself = Self {
             data,
+            expected_deposits,
             persister,
             channel_manager,
             test_event_tx,
+            new_payment_tx,
         };
 
         let payments_tasks = [
-            myself.spawn_invoice_expiry_checker(shutdown.clone()),
+            myself
+                .spawn_invoice_expiry_checker(new_payment_rx, shutdown.clone()),
+            myself.spawn_expired_invoice_archiver(shutdown.clone()),
             myself.spawn_onchain_confs_checker(esplora, shutdown.clone()),
             myself.spawn_onchain_recv_checker(
                 wallet,
@@ -152,8 +189,13 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
         (myself, payments_tasks)
     }
 
+    /// Spawns a task that calls `check_invoice_expiries` as soon as the next
+    /// pending invoice expires, or after [`INVOICE_EXPIRY_CHECK_INTERVAL`],
+    /// whichever is sooner. The deadline is recomputed whenever a new payment
+    /// is registered.
     fn spawn_invoice_expiry_checker(
         &self,
+        mut new_payment_rx: notify::Receiver,
         mut shutdown: ShutdownChannel,
     ) -> LxTask<()> {
         let payments_manager = self.clone();
@@ -161,18 +203,18 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
             "invoice expiry checker",
             debug_span!("(invoice-expiry-checker)"),
             async move {
-                let mut check_timer =
-                    tokio::time::interval(INVOICE_EXPIRY_CHECK_INTERVAL);
-
                 loop {
+                    let wait =
+                        payments_manager.next_invoice_expiry_wait().await;
                     tokio::select! {
-                        _ = check_timer.tick() => {
+                        () = tokio::time::sleep(wait) => {
                             if let Err(e) = payments_manager
                                 .check_invoice_expiries()
                                 .await {
                                 error!("Error checking invoice expiries: {e:#}");
                             }
                         }
+                        () = new_payment_rx.recv() => continue,
                         () = shutdown.recv() => break,
                     }
                 }
@@ -182,6 +224,42 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
         )
     }
 
+    /// Spawns a task that periodically archives long-expired inbound invoice
+    /// payments.
+    fn spawn_expired_invoice_archiver(
+        &self,
+        mut shutdown: ShutdownChannel,
+    ) -> LxTask<()> {
+        let payments_manager = self.clone();
+        LxTask::spawn_named_with_span(
+            "expired invoice archiver",
+            debug_span!("(expired-invoice-archiver)"),
+            async move {
+                let mut archive_timer =
+                    tokio::time::interval(EXPIRED_INVOICE_ARCHIVE_INTERVAL);
+                let mut cursor = None;
+
+                loop {
+                    tokio::select! {
+                        _ = archive_timer.tick() => {
+                            match payments_manager
+                                .archive_expired_invoices(cursor)
+                                .await {
+                                Ok(next_cursor) => cursor = next_cursor,
+                                Err(e) => error!(
+                                    "Error archiving expired invoices: {e:#}"
+                                ),
+                            }
+                        }
+                        () = shutdown.recv() => break,
+                    }
+                }
+
+                info!("Expired invoice archiver task shutting down");
+            },
+        )
+    }
+
     fn spawn_onchain_confs_checker(
         &self,
         esplora: Arc<LexeEsplora>,
@@ -264,6 +342,7 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
             .context("Could not persist new payment")?;
 
         locked_data.commit(persisted);
+        self.new_payment_tx.send();
 
         Ok(())
     }
@@ -541,13 +620,100 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
 
         // Commit
         for persisted in all_persisted {
+            info!(id = %persisted.0.id(), "Invoice expired");
             locked_data.commit(persisted);
+            self.test_event_tx.send(TestEvent::InvoiceExpired);
         }
 
         debug!("Successfully checked invoice expiries");
         Ok(())
     }
 
+    /// Returns how long the invoice expiry checker should wait before its next
+    /// check.
+    async fn next_invoice_expiry_wait(&self) -> Duration {
+        let unix_duration = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("System time is before UNIX timestamp");
+
+        match self.data.lock().await.next_invoice_expiry() {
+            Some(expires_at) => expires_at
+                .saturating_sub(unix_duration)
+                .saturating_add(INVOICE_EXPIRY_GRACE)
+                .min(INVOICE_EXPIRY_CHECK_INTERVAL),
+            None => INVOICE_EXPIRY_CHECK_INTERVAL,
+        }
+    }
+
+    /// Moves inbound invoice payments which expired unpaid more than
+    /// [`EXPIRED_INVOICE_RETENTION`] ago out of the payments DB and into an
+    /// archive file, keeping the set of payments we track small.
+    ///
+    /// Scans at most [`MAX_ARCHIVE_BATCHES_PER_RUN`] batches of payments,
+    /// starting after `start_index`. Returns the index to continue from in the
+    /// next run, or [`None`] if all payments have been scanned.
+    #[instrument(skip_all, name = "(archive-expired-invoices)")]
+    pub async fn archive_expired_invoices(
+        &self,
+        start_index: Option<PaymentIndex>,
+    ) -> anyhow::Result<Option<PaymentIndex>> {
+        debug!("Archiving expired invoices");
+        let unix_duration = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("System time is before UNIX timestamp");
+
+        let mut archivable = Vec::new();
+        let mut cursor = start_index;
+        let mut exhausted = false;
+        for _ in 0..MAX_ARCHIVE_BATCHES_PER_RUN {
+            let batch = self
+                .persister
+                .read_payments_batch(GetNewPayments {
+                    start_index: cursor,
+                    limit: Some(MAX_PAYMENTS_BATCH_SIZE),
+                })
+                .await
+                .context("Could not fetch payments batch")?;
+            exhausted = batch.len() < usize::from(MAX_PAYMENTS_BATCH_SIZE);
+
+            for payment in batch {
+                cursor = Some(payment.index());
+                if let Payment::InboundInvoice(ref iip) = payment {
+                    if iip
+                        .is_archivable(unix_duration, EXPIRED_INVOICE_RETENTION)
+                    {
+                        archivable.push(payment);
+                    }
+                }
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+        let next_cursor = if exhausted { None } else { cursor };
+
+        if archivable.is_empty() {
+            return Ok(next_cursor);
+        }
+
+        // Archived payments are finalized, so nothing else updates them and we
+        // don't need to hold the lock during the archive I/O. They stay in
+        // `finalized` until they're deleted, so their ids can't be reused.
+        let ids = archivable.iter().map(Payment::id).collect::<Vec<_>>();
+        self.persister
+            .archive_payments(archivable)
+            .await
+            .context("Could not archive payments")?;
+        let mut locked_data = self.data.lock().await;
+        for id in &ids {
+            locked_data.finalized.remove(id);
+        }
+
+        info!("Archived {} expired invoice payments", ids.len());
+        Ok(next_cursor)
+    }
+
     /// Register the successful broadcast of an onchain send tx.
     #[instrument(skip_all, name = "(onchain-send-broadcasted)")]
     pub async fn onchain_send_broadcasted(
@@ -592,6 +758,66 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
         Ok(())
     }
 
+    /// Get a pending [`OnchainReceive`] by its [`LxPaymentId`].
+    pub async fn pending_onchain_receive(
+        &self,
+        id: &LxPaymentId,
+    ) -> anyhow::Result<OnchainReceive> {
+        let locked_data = self.data.lock().await;
+        match locked_data.pending.get(id) {
+            Some(Payment::OnchainReceive(or)) => Ok(or.clone()),
+            Some(_) => bail!("Payment was not an onchain receive"),
+            None if locked_data.finalized.contains(id) =>
+                bail!("Onchain receive was already finalized"),
+            None => bail!("Payment doesn't exist"),
+        }
+    }
+
+    /// Register a CPFP child tx which bumps a pending onchain receive. Should
+    /// be called before the child tx is broadcasted.
+    #[instrument(skip_all, name = "(onchain-receive-bumped)")]
+    pub async fn onchain_receive_bumped(
+        &self,
+        id: &LxPaymentId,
+        cpfp: OnchainCpfp,
+    ) -> anyhow::Result<()> {
+        debug!(%id, "Registering a CPFP bump of an onchain receive");
+        let mut locked_data = self.data.lock().await;
+
+        ensure!(
+            !locked_data.finalized.contains(id),
+            "Onchain receive was already finalized",
+        );
+
+        let pending = locked_data
+            .pending
+            .get(id)
+            .context("Payment doesn't exist")?;
+
+        // Check
+        let checked = match pending {
+            Payment::OnchainReceive(or) => or
+                .bumped(cpfp)
+                .map(Payment::from)
+                .map(CheckedPayment)
+                .context("Invalid state transition")?,
+            _ => bail!("Payment was not an onchain receive"),
+        };
+
+        // Persist
+        let persisted = self
+            .persister
+            .persist_payment(checked)
+            .await
+            .context("Persist failed")?;
+
+        // Commit
+        locked_data.commit(persisted);
+
+        debug!("Successfully registered CPFP bump");
+        Ok(())
+    }
+
     /// Checks the confirmation status of our onchain payments.
     /// This function should be called regularly.
     #[instrument(skip_all, name = "(check-onchain-confs)")]
@@ -657,6 +883,42 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
         Ok(())
     }
 
+    /// Issues a fresh address for a deposit of `req.amount`, recording an
+    /// [`ExpectedDeposit`] so that the [`OnchainReceive`] which pays the
+    /// address can be matched back to the deposit.
+    #[instrument(skip_all, name = "(get-address-for)")]
+    pub async fn get_address_for(
+        &self,
+        wallet: &LexeWallet,
+        req: GetAddressForRequest,
+    ) -> anyhow::Result<Address> {
+        req.validate()?;
+
+        let now = TimestampMs::now();
+        let mut locked_deposits = self.expected_deposits.lock().await;
+        locked_deposits.retain(|deposit| !deposit.is_expired(now));
+        ensure!(
+            locked_deposits.len() < MAX_EXPECTED_DEPOSITS,
+            "Too many unpaid deposits; wait for some to be paid or expire"
+        );
+
+        let address = wallet.get_new_address().await?;
+        let mut updated = locked_deposits.clone();
+        updated.push(ExpectedDeposit {
+            address: address.clone(),
+            amount: req.amount,
+            label: req.label,
+            created_at: now,
+        });
+        self.persister
+            .persist_expected_deposits(&updated)
+            .await
+            .context("Could not persist expected deposits")?;
+        *locked_deposits = updated;
+
+        Ok(address)
+    }
+
     /// Queries the [`bdk::Wallet`] to see if there are any onchain receives
     /// that the [`PaymentsManager`] doesn't yet know about. If so, the
     /// [`OnchainReceive`] is constructed and registered with the
@@ -688,6 +950,15 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
                 .collect::<Vec<TransactionDetails>>()
         };
 
+        // Hold the lock until we've registered the receives so that each
+        // deposit is matched at most once.
+        let mut locked_deposits = self.expected_deposits.lock().await;
+        let now = TimestampMs::now();
+        let expected_deposits = locked_deposits
+            .iter()
+            .filter(|deposit| !deposit.is_expired(now))
+            .collect::<Vec<_>>();
+
         let onchain_recv_futs = unseen_txs
             .iter()
             // Map each to a future which constructs the `OnchainReceive`
@@ -704,7 +975,20 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
                 let amount = Amount::try_from_satoshis(amount_sats)
                     .context("Overflowed")?;
 
-                let or = OnchainReceive::new(raw_tx, amount);
+                let deposit = expected_deposits
+                    .iter()
+                    .find_map(|deposit| deposit.match_tx(&raw_tx));
+                if let Some(deposit) = &deposit {
+                    info!(
+                        txid = %tx_details.txid,
+                        expected = %deposit.expected,
+                        received = %deposit.received,
+                        status = ?deposit.status,
+                        "Matched onchain receive to expected deposit",
+                    );
+                }
+
+                let or = OnchainReceive::new(raw_tx, amount, deposit);
 
                 Ok::<_, anyhow::Error>(or)
             });
@@ -712,6 +996,15 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
         let onchain_recvs = futures::future::try_join_all(onchain_recv_futs)
             .await
             .context("Error constructing new `OnchainReceive`s")?;
+        let paid_deposits = locked_deposits
+            .iter()
+            .filter(|deposit| {
+                onchain_recvs
+                    .iter()
+                    .any(|or| deposit.match_tx(&or.tx).is_some())
+            })
+            .map(|deposit| deposit.address.clone())
+            .collect::<Vec<_>>();
 
         let register_futs = onchain_recvs
             .into_iter()
@@ -721,6 +1014,21 @@ impl<CM: LexeChannelManager<PS>, PS: LexePersister> PaymentsManager<CM, PS> {
             res.context("Failed to register new onchain receive")?;
         }
 
+        // Paid and expired deposits are done; their addresses won't be
+        // reissued.
+        let mut updated = locked_deposits.clone();
+        updated.retain(|deposit| {
+            !paid_deposits.contains(&deposit.address)
+                && !deposit.is_expired(now)
+        });
+        if updated.len() != locked_deposits.len() {
+            self.persister
+                .persist_expected_deposits(&updated)
+                .await
+                .context("Could not persist expected deposits")?;
+            *locked_deposits = updated;
+        }
+
         debug!("Successfully checked for and registered new onchain receives");
         Ok(())
     }
@@ -751,6 +1059,19 @@ impl PaymentsData {
         self.pending.contains_key(id) || self.finalized.contains(id)
     }
 
+    /// Returns the earliest time (as a [`Duration`] since the unix epoch) at
+    /// which a pending invoice payment will expire, if any.
+    fn next_invoice_expiry(&self) -> Option<Duration> {
+        self.pending
+            .values()
+            .filter_map(|payment| match payment {
+                Payment::InboundInvoice(iip) => iip.pending_expiry(),
+                Payment::OutboundInvoice(oip) => oip.pending_expiry(),
+                _ => None,
+            })
+            .min()
+    }
+
     fn check_new_payment(
         &self,
         payment: Payment,
@@ -997,7 +1318,7 @@ impl PaymentsData {
                 Ok(None) => None,
                 Err(e) => Some(Err(e)),
             })
-            .collect::<anyhow::Result<Vec<CheckedPayment>>>()
+            .try_collect_vec()
             .context("Error while checking onchain confs in PaymentsData")
     }
 }
@@ -11,8 +11,8 @@ use common::{
         hashes::LxTxid,
         invoice::LxInvoice,
         payments::{
-            BasicPayment, DbPayment, LxPaymentId, PaymentDirection,
-            PaymentIndex, PaymentKind, PaymentStatus,
+            BasicPayment, DbPayment, DepositMatch, LxPaymentId,
+            PaymentDirection, PaymentIndex, PaymentKind, PaymentStatus,
         },
     },
     rng::Crng,
@@ -44,6 +44,8 @@ pub mod manager;
 pub mod onchain;
 /// Outbound Lightning payments.
 pub mod outbound;
+/// Filtered, paginated payment history queries.
+pub mod query;
 
 // --- The top-level payment type --- //
 
@@ -155,6 +157,7 @@ impl From<Payment> for BasicPayment {
             status_str: p.status_str().to_owned(),
             note: p.note().map(|s| s.to_owned()),
             finalized_at: p.finalized_at(),
+            deposit: p.deposit().cloned(),
         }
     }
 }
@@ -424,6 +427,15 @@ impl Payment {
         }
     }
 
+    /// (Onchain receives only) The expected deposit this payment paid.
+    pub fn deposit(&self) -> Option<&DepositMatch> {
+        match self {
+            Self::OnchainReceive(OnchainReceive { deposit, .. }) =>
+                deposit.as_ref(),
+            _ => None,
+        }
+    }
+
     pub(crate) fn assert_invariants(&self) {
         // Payments should have a finalized_at() iff it has finalized.
         use PaymentStatus::*;
@@ -1,5 +1,7 @@
+use std::time::Duration;
+
 use anyhow::{bail, ensure};
-use bitcoin::Transaction;
+use bitcoin::{Address, Transaction};
 #[cfg(test)]
 use common::test_utils::arbitrary;
 use common::{
@@ -7,7 +9,7 @@ use common::{
     ln::{
         amount::Amount,
         hashes::LxTxid,
-        payments::{ClientPaymentId, LxPaymentId},
+        payments::{ClientPaymentId, DepositMatch, LxPaymentId},
         ConfirmationPriority,
     },
     time::TimestampMs,
@@ -21,6 +23,19 @@ use crate::esplora::{TxConfQuery, TxConfStatus};
 
 /// The number of confirmations a tx needs to before we consider it final.
 const ONCHAIN_CONFIRMATION_THRESHOLD: u32 = 6;
+/// The max # of outstanding [`ExpectedDeposit`]s. Each one advances the
+/// address index by two (see [`LexeWallet::get_new_address`]), so twice this
+/// must stay below [`BDK_WALLET_SYNC_STOP_GAP`], otherwise deposits to the
+/// newest addresses could go undetected.
+///
+/// [`BDK_WALLET_SYNC_STOP_GAP`]: crate::wallet::BDK_WALLET_SYNC_STOP_GAP
+/// [`LexeWallet::get_new_address`]: crate::wallet::LexeWallet::get_new_address
+pub const MAX_EXPECTED_DEPOSITS: usize = 8;
+/// How long we'll match receives to an [`ExpectedDeposit`] after issuing its
+/// address. Receives to an expired deposit's address are still detected, just
+/// without the deposit metadata.
+pub const EXPECTED_DEPOSIT_LIFETIME: Duration =
+    Duration::from_secs(7 * 24 * 60 * 60);
 
 // --- Onchain send --- //
 
@@ -42,6 +57,23 @@ pub struct OnchainSend {
     #[cfg_attr(test, proptest(strategy = "arbitrary::any_option_string()"))]
     pub note: Option<String>,
     pub finalized_at: Option<TimestampMs>,
+    /// Whether this payment was sent as a payjoin.
+    #[serde(default)]
+    pub path: OnchainSendPath,
+}
+
+/// How an [`OnchainSend`] was sent.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub enum OnchainSendPath {
+    /// A normal send; the receiver doesn't support payjoin.
+    #[default]
+    Direct,
+    /// A BIP78 payjoin; the tx includes inputs contributed by the receiver.
+    Payjoin,
+    /// The receiver supports payjoin, but the payjoin failed, so we sent our
+    /// original (non-payjoin) tx instead.
+    PayjoinFallback,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -87,7 +119,12 @@ pub enum OnchainSendStatus {
 }
 
 impl OnchainSend {
-    pub fn new(tx: Transaction, req: PayOnchainRequest, fees: Amount) -> Self {
+    pub fn new(
+        tx: Transaction,
+        req: PayOnchainRequest,
+        fees: Amount,
+        path: OnchainSendPath,
+    ) -> Self {
         Self {
             cid: req.cid,
             txid: LxTxid(tx.txid()),
@@ -100,6 +137,7 @@ impl OnchainSend {
             created_at: TimestampMs::now(),
             note: req.note,
             finalized_at: None,
+            path,
         }
     }
 
@@ -234,6 +272,34 @@ pub struct OnchainReceive {
     #[cfg_attr(test, proptest(strategy = "arbitrary::any_option_string()"))]
     pub note: Option<String>,
     pub finalized_at: Option<TimestampMs>,
+    /// The latest child-pays-for-parent tx we broadcasted to speed up the
+    /// confirmation of this receive, if any.
+    #[serde(default)]
+    pub cpfp: Option<OnchainCpfp>,
+    /// The expected deposit which this tx paid, if any.
+    #[serde(default)]
+    pub deposit: Option<DepositMatch>,
+}
+
+/// An address we issued for a deposit of a specific amount. When a tx paying
+/// the address is detected, the deposit is attached to the resulting
+/// [`OnchainReceive`] as a [`DepositMatch`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ExpectedDeposit {
+    pub address: Address,
+    pub amount: Amount,
+    pub label: Option<String>,
+    pub created_at: TimestampMs,
+}
+
+/// A child tx which spends our outputs of an unconfirmed [`OnchainReceive`]
+/// back to ourselves, paying extra fees so that miners include the parent.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct OnchainCpfp {
+    pub txid: LxTxid,
+    /// The fees paid by the child tx.
+    pub fees: Amount,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -272,7 +338,11 @@ pub enum OnchainReceiveStatus {
 }
 
 impl OnchainReceive {
-    pub(crate) fn new(tx: Transaction, amount: Amount) -> Self {
+    pub(crate) fn new(
+        tx: Transaction,
+        amount: Amount,
+        deposit: Option<DepositMatch>,
+    ) -> Self {
         Self {
             txid: LxTxid(tx.txid()),
             tx,
@@ -283,6 +353,8 @@ impl OnchainReceive {
             created_at: TimestampMs::now(),
             note: None,
             finalized_at: None,
+            cpfp: None,
+            deposit,
         }
     }
 
@@ -291,6 +363,26 @@ impl OnchainReceive {
         LxPaymentId::OnchainRecv(self.txid)
     }
 
+    /// Register a CPFP child tx which bumps this receive. Any previous child
+    /// is assumed to have been replaced by the new one.
+    pub(crate) fn bumped(&self, cpfp: OnchainCpfp) -> anyhow::Result<Self> {
+        use OnchainReceiveStatus::*;
+
+        match self.status {
+            Zeroconf => (),
+            PartiallyConfirmed | FullyConfirmed =>
+                bail!("Tx already has confirmations"),
+            PartiallyReplaced | FullyReplaced => bail!("Tx was replaced"),
+            Dropped => bail!("Tx was dropped"),
+        }
+
+        // Everything ok; return a clone with the updated state
+        let mut clone = self.clone();
+        clone.cpfp = Some(cpfp);
+
+        Ok(clone)
+    }
+
     pub(crate) fn check_onchain_conf(
         &self,
         conf_status: TxConfStatus,
@@ -358,9 +450,40 @@ impl OnchainReceive {
     }
 }
 
+impl ExpectedDeposit {
+    /// Whether we should stop matching receives to this deposit.
+    pub(crate) fn is_expired(&self, now: TimestampMs) -> bool {
+        let age = now
+            .into_duration()
+            .saturating_sub(self.created_at.into_duration());
+        age >= EXPECTED_DEPOSIT_LIFETIME
+    }
+
+    /// If `tx` pays this deposit's address, returns the [`DepositMatch`] to
+    /// attach to the resulting [`OnchainReceive`].
+    pub(crate) fn match_tx(&self, tx: &Transaction) -> Option<DepositMatch> {
+        let script_pubkey = self.address.script_pubkey();
+        let mut outputs = tx
+            .output
+            .iter()
+            .filter(|output| output.script_pubkey == script_pubkey)
+            .peekable();
+        outputs.peek()?;
+        let received_sats = outputs.map(|output| output.value).sum::<u64>();
+        let received =
+            Amount::try_from_sats_u64(received_sats).unwrap_or(Amount::MAX);
+        Some(DepositMatch::new(self.amount, received, self.label.clone()))
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use common::test_utils::roundtrip::json_unit_enum_backwards_compat;
+    use bitcoin::{Network, PackedLockTime, Script, TxOut};
+    use common::{
+        ln::payments::DepositStatus,
+        test_utils::roundtrip::json_unit_enum_backwards_compat,
+    };
+    use proptest::{prop_assert, prop_assert_eq, proptest};
 
     use super::*;
 
@@ -372,4 +495,63 @@ mod test {
         let expected_ser = r#"["Zeroconf","PartiallyConfirmed","PartiallyReplaced","FullyConfirmed","FullyReplaced","Dropped"]"#;
         json_unit_enum_backwards_compat::<OnchainReceiveStatus>(expected_ser);
     }
+
+    #[test]
+    fn onchain_receive_bumped() {
+        proptest!(|(or: OnchainReceive, cpfp: OnchainCpfp)| {
+            let res = or.bumped(cpfp.clone());
+            if or.status == OnchainReceiveStatus::Zeroconf {
+                let bumped = res.unwrap();
+                prop_assert_eq!(bumped.cpfp, Some(cpfp));
+                prop_assert_eq!(bumped.status, or.status);
+            } else {
+                prop_assert!(res.is_err());
+            }
+        })
+    }
+
+    #[test]
+    fn expected_deposit_match_tx() {
+        let address = Address::p2wsh(&Script::new(), Network::Regtest);
+        let other =
+            Address::p2wsh(&Script::new_op_return(&[]), Network::Regtest);
+        let deposit = ExpectedDeposit {
+            address: address.clone(),
+            amount: Amount::from_sats_u32(10_000),
+            label: Some("order-1".to_owned()),
+            created_at: TimestampMs::from(0),
+        };
+        let tx_paying = |outputs: &[(&Address, u64)]| Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: Vec::new(),
+            output: outputs
+                .iter()
+                .map(|(address, value)| TxOut {
+                    value: *value,
+                    script_pubkey: address.script_pubkey(),
+                })
+                .collect(),
+        };
+
+        assert!(deposit.match_tx(&tx_paying(&[(&other, 10_000)])).is_none());
+
+        let exact = deposit.match_tx(&tx_paying(&[(&address, 10_000)]));
+        assert_eq!(exact.unwrap().status, DepositStatus::Exact);
+
+        // Multiple outputs to the same address are summed
+        let tx =
+            tx_paying(&[(&address, 6_000), (&other, 1), (&address, 5_000)]);
+        let overpaid = deposit.match_tx(&tx).unwrap();
+        assert_eq!(overpaid.status, DepositStatus::Overpaid);
+        assert_eq!(overpaid.received, Amount::from_sats_u32(11_000));
+        assert_eq!(overpaid.label, deposit.label);
+
+        let underpaid = deposit.match_tx(&tx_paying(&[(&address, 9_999)]));
+        assert_eq!(underpaid.unwrap().status, DepositStatus::Underpaid);
+
+        assert!(!deposit.is_expired(TimestampMs::from(0)));
+        let expiry = TimestampMs::try_from(EXPECTED_DEPOSIT_LIFETIME).unwrap();
+        assert!(deposit.is_expired(expiry));
+    }
 }
@@ -231,6 +231,18 @@ impl OutboundInvoicePayment {
 
         Some(clone)
     }
+
+    /// Returns when this payment's invoice expires, if [`check_invoice_expiry`]
+    /// could still abandon it.
+    ///
+    /// [`check_invoice_expiry`]: Self::check_invoice_expiry
+    pub(crate) fn pending_expiry(&self) -> Option<Duration> {
+        match self.status {
+            OutboundInvoicePaymentStatus::Pending =>
+                self.invoice.0.expires_at(),
+            _ => None,
+        }
+    }
 }
 
 // --- Outbound spontaneous payments --- //
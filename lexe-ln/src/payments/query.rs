@@ -0,0 +1,210 @@
+//! Filtered, paginated queries over the user's payment history.
+//!
+//! Payments are stored encrypted, so the backend can only page through them
+//! in index order; all of the filtering in a [`QueryPayments`] happens here,
+//! after the payments have been decrypted.
+
+use std::future::Future;
+
+use anyhow::{ensure, Context};
+use common::{
+    api::{
+        command::{QueryPayments, QueryPaymentsResponse},
+        qs::GetNewPayments,
+    },
+    constants::{DEFAULT_PAYMENTS_BATCH_SIZE, MAX_PAYMENTS_BATCH_SIZE},
+    ln::payments::BasicPayment,
+};
+
+/// The maximum # of batches we'll fetch from the backend for a single query.
+/// Bounds the work done for a query whose filter matches few payments; the
+/// client continues from [`QueryPaymentsResponse::next_index`].
+const MAX_BATCHES_PER_QUERY: usize = 10;
+
+/// Runs a [`QueryPayments`], fetching batches of decrypted payments in
+/// ascending index order with `fetch_batch` until either `limit` payments have
+/// matched, all payments have been searched, or [`MAX_BATCHES_PER_QUERY`]
+/// batches have been fetched.
+pub async fn query_payments<F, Fut>(
+    req: QueryPayments,
+    mut fetch_batch: F,
+) -> anyhow::Result<QueryPaymentsResponse>
+where
+    F: FnMut(GetNewPayments) -> Fut,
+    Fut: Future<Output = anyhow::Result<Vec<BasicPayment>>>,
+{
+    let limit = usize::from(req.limit.unwrap_or(DEFAULT_PAYMENTS_BATCH_SIZE));
+    ensure!(
+        (1..=usize::from(MAX_PAYMENTS_BATCH_SIZE)).contains(&limit),
+        "Limit must be between 1 and {MAX_PAYMENTS_BATCH_SIZE}"
+    );
+
+    let mut payments = Vec::new();
+    let mut cursor = req.start_index;
+
+    for _ in 0..MAX_BATCHES_PER_QUERY {
+        let batch = fetch_batch(GetNewPayments {
+            start_index: cursor,
+            limit: Some(MAX_PAYMENTS_BATCH_SIZE),
+        })
+        .await
+        .context("Could not fetch payments batch")?;
+        let exhausted = batch.len() < usize::from(MAX_PAYMENTS_BATCH_SIZE);
+
+        for payment in batch {
+            cursor = Some(*payment.index());
+            if req.filter.matches(&payment) {
+                payments.push(payment);
+                if payments.len() >= limit {
+                    // Resume right after the last returned payment.
+                    return Ok(QueryPaymentsResponse {
+                        payments,
+                        next_index: cursor,
+                    });
+                }
+            }
+        }
+
+        if exhausted {
+            return Ok(QueryPaymentsResponse {
+                payments,
+                next_index: None,
+            });
+        }
+    }
+
+    // Hit the batch limit; resume after the last payment we searched.
+    Ok(QueryPaymentsResponse {
+        payments,
+        next_index: cursor,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::{self, Ready};
+
+    use common::{
+        api::command::PaymentFilter,
+        ln::{
+            amount::Amount,
+            payments::{
+                PaymentDirection, PaymentIndex, PaymentKind, PaymentStatus,
+            },
+        },
+    };
+    use proptest::{
+        arbitrary::any, collection::vec, option, prop_assert_eq, proptest,
+        test_runner::Config,
+    };
+
+    use super::*;
+
+    type Batch = anyhow::Result<Vec<BasicPayment>>;
+
+    /// Serves batches from a sorted list of payments, like the backend would.
+    fn fetch_from(
+        all: &[BasicPayment],
+    ) -> impl FnMut(GetNewPayments) -> Ready<Batch> + '_ {
+        |req| {
+            let limit = usize::from(req.limit.unwrap());
+            let batch = all
+                .iter()
+                .filter(|p| req.start_index.map_or(true, |i| *p.index() > i))
+                .take(limit)
+                .cloned()
+                .collect();
+            future::ready(Ok(batch))
+        }
+    }
+
+    /// A minimal payment; generating arbitrary [`BasicPayment`]s (invoices
+    /// especially) is too slow for the # of payments we need here.
+    fn payment(
+        index: PaymentIndex,
+        direction: PaymentDirection,
+    ) -> BasicPayment {
+        BasicPayment {
+            index,
+            kind: PaymentKind::Spontaneous,
+            direction,
+            invoice: None,
+            replacement: None,
+            amount: Some(Amount::from_sats_u32(1000)),
+            fees: Amount::ZERO,
+            status: PaymentStatus::Completed,
+            status_str: "completed".to_owned(),
+            note: None,
+            finalized_at: None,
+            deposit: None,
+        }
+    }
+
+    /// Paging through every result of a query returns exactly the payments
+    /// which match the filter, in order.
+    #[test]
+    fn query_pages_match_naive_filter() {
+        let config = Config::with_cases(64);
+        let payments =
+            vec((any::<PaymentIndex>(), any::<PaymentDirection>()), 0..1500);
+        let direction = option::of(any::<PaymentDirection>());
+        let limit = option::of(1u16..=MAX_PAYMENTS_BATCH_SIZE);
+        proptest!(config, |(
+            payments in payments,
+            direction in direction,
+            limit in limit,
+        )| {
+            let mut all = payments
+                .into_iter()
+                .map(|(index, direction)| payment(index, direction))
+                .collect::<Vec<_>>();
+            all.sort_unstable_by_key(|p| *p.index());
+            all.dedup_by_key(|p| *p.index());
+            let filter = PaymentFilter {
+                direction,
+                ..Default::default()
+            };
+            let expected = all
+                .iter()
+                .filter(|p| filter.matches(p))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let mut actual = Vec::new();
+            let mut start_index = None::<PaymentIndex>;
+            loop {
+                let req = QueryPayments {
+                    start_index,
+                    limit,
+                    filter: filter.clone(),
+                };
+                let resp =
+                    tokio_test::block_on(query_payments(req, fetch_from(&all)))
+                        .unwrap();
+                actual.extend(resp.payments);
+                match resp.next_index {
+                    Some(next) => {
+                        assert!(start_index < Some(next), "No progress");
+                        start_index = Some(next);
+                    }
+                    None => break,
+                }
+            }
+
+            prop_assert_eq!(actual, expected);
+        });
+    }
+
+    #[test]
+    fn invalid_limit() {
+        for limit in [0, MAX_PAYMENTS_BATCH_SIZE + 1] {
+            let req = QueryPayments {
+                limit: Some(limit),
+                ..Default::default()
+            };
+            let res =
+                tokio_test::block_on(query_payments(req, fetch_from(&[])));
+            assert!(res.is_err());
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! Per-peer connection health tracking, used by the [p2p reconnector task] to
+//! decide which peers to reconnect to and when.
+//!
+//! For each peer we track connect successes and failures, how long the last
+//! successful connect + handshake took, and how often the peer disconnects.
+//! LDK doesn't expose ping round trip times, so the handshake latency serves as
+//! our latency measure.
+//!
+//! Peers which fail to connect (or which repeatedly disconnect shortly after
+//! connecting) are retried with per-peer exponential backoff plus jitter, so
+//! that e.g. an LSP maintenance window doesn't result in all of its users
+//! hammering it (and flooding our logs) the moment it comes back up. When
+//! reconnecting, channel counterparties are prioritized over gossip-only peers.
+//!
+//! [p2p reconnector task]: crate::p2p::spawn_p2p_reconnector
+
+use std::{
+    cmp,
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use common::{
+    api::NodePk,
+    rng::{RngExt, SysRng, WeakRng},
+};
+
+/// The backoff after the first failed connect attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+/// The maximum backoff between connect attempts to a single peer.
+const MAXIMUM_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// Disconnects within this window count towards [`FLAPPING_THRESHOLD`].
+const DISCONNECT_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// If a peer disconnects this many times within [`DISCONNECT_WINDOW`], we
+/// consider the connection to be flapping and back off before reconnecting.
+const FLAPPING_THRESHOLD: usize = 3;
+
+/// What kind of peer this is, in order of reconnect priority.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PeerKind {
+    /// We have (or are opening) a channel with this peer.
+    Channel,
+    /// We only connect to this peer to receive gossip.
+    Gossip,
+}
+
+/// Health statistics for a single peer.
+#[derive(Clone, Debug)]
+pub struct PeerHealth {
+    pub kind: PeerKind,
+    /// Total number of successful connect attempts.
+    pub connect_successes: u64,
+    /// Total number of failed connect attempts.
+    pub connect_failures: u64,
+    /// The number of connect attempts which have failed since the last
+    /// successful connect.
+    pub consecutive_failures: u32,
+    /// How long our last successful connect + P2P handshake took.
+    pub last_connect_latency: Option<Duration>,
+    /// When this peer disconnected within the last [`DISCONNECT_WINDOW`].
+    recent_disconnects: VecDeque<Instant>,
+    /// Whether we were connected to this peer the last time we checked.
+    connected: bool,
+    /// We won't attempt to reconnect to this peer before this time.
+    next_attempt: Instant,
+}
+
+/// Tracks the [`PeerHealth`] of each peer we want to stay connected to.
+pub struct PeerHealthTracker {
+    peers: HashMap<NodePk, PeerHealth>,
+    /// Only used for jitter.
+    rng: WeakRng,
+}
+
+impl PeerHealth {
+    fn new(kind: PeerKind, now: Instant) -> Self {
+        Self {
+            kind,
+            connect_successes: 0,
+            connect_failures: 0,
+            consecutive_failures: 0,
+            last_connect_latency: None,
+            recent_disconnects: VecDeque::new(),
+            connected: false,
+            next_attempt: now,
+        }
+    }
+
+    /// The number of times this peer has disconnected within the last
+    /// [`DISCONNECT_WINDOW`].
+    pub fn recent_disconnects(&self) -> usize {
+        self.recent_disconnects.len()
+    }
+
+    fn prune_disconnects(&mut self, now: Instant) {
+        while let Some(&disconnected_at) = self.recent_disconnects.front() {
+            if now.saturating_duration_since(disconnected_at)
+                < DISCONNECT_WINDOW
+            {
+                break;
+            }
+            self.recent_disconnects.pop_front();
+        }
+    }
+}
+
+impl PeerHealthTracker {
+    pub fn new() -> Self {
+        let rng = WeakRng::from_u64(SysRng::new().gen_u64());
+        Self {
+            peers: HashMap::new(),
+            rng,
+        }
+    }
+
+    /// Start tracking a peer. If the peer is already tracked, its stats are
+    /// kept but any pending backoff is cleared so that it is retried
+    /// immediately, and its [`PeerKind`] is upgraded if it became a channel
+    /// peer.
+    pub fn add_peer(&mut self, node_pk: NodePk, kind: PeerKind, now: Instant) {
+        self.peers
+            .entry(node_pk)
+            .and_modify(|health| {
+                health.kind = cmp::min(health.kind, kind);
+                health.next_attempt = now;
+            })
+            .or_insert_with(|| PeerHealth::new(kind, now));
+    }
+
+    /// Stop tracking a peer.
+    pub fn remove_peer(&mut self, node_pk: &NodePk) {
+        self.peers.remove(node_pk);
+    }
+
+    /// Get the [`PeerHealth`] for a tracked peer.
+    pub fn health(&self, node_pk: &NodePk) -> Option<&PeerHealth> {
+        self.peers.get(node_pk)
+    }
+
+    /// Update our view of which peers are connected, given the full set of
+    /// currently connected peers. Peers which were connected last time but
+    /// aren't anymore are counted as having disconnected.
+    pub fn observe_connected(
+        &mut self,
+        connected: &HashSet<NodePk>,
+        now: Instant,
+    ) {
+        for (node_pk, health) in self.peers.iter_mut() {
+            let is_connected = connected.contains(node_pk);
+            health.prune_disconnects(now);
+
+            if health.connected && !is_connected {
+                health.recent_disconnects.push_back(now);
+                let disconnects = health.recent_disconnects.len();
+                if disconnects >= FLAPPING_THRESHOLD {
+                    let exponent = disconnects - FLAPPING_THRESHOLD;
+                    let backoff = jittered_backoff(&mut self.rng, exponent);
+                    health.next_attempt = now + backoff;
+                }
+            }
+
+            health.connected = is_connected;
+        }
+    }
+
+    /// Get the disconnected peers which are due for a reconnect attempt,
+    /// ordered by priority: channel peers first, then healthier peers first.
+    pub fn peers_to_reconnect(&self, now: Instant) -> Vec<NodePk> {
+        let mut due = self
+            .peers
+            .iter()
+            .filter(|(_, health)| {
+                !health.connected && health.next_attempt <= now
+            })
+            .map(|(node_pk, health)| {
+                let key = (health.kind, health.consecutive_failures);
+                (key, *node_pk)
+            })
+            .collect::<Vec<_>>();
+        due.sort_unstable_by_key(|(key, _)| *key);
+        due.into_iter().map(|(_, node_pk)| node_pk).collect()
+    }
+
+    /// Record a successful connect + handshake which took `latency`.
+    /// Returns the number of consecutive failures prior to this success.
+    pub fn record_connect_success(
+        &mut self,
+        node_pk: &NodePk,
+        latency: Duration,
+    ) -> u32 {
+        let health = match self.peers.get_mut(node_pk) {
+            Some(health) => health,
+            None => return 0,
+        };
+        let prior_failures = health.consecutive_failures;
+        health.connect_successes += 1;
+        health.consecutive_failures = 0;
+        health.last_connect_latency = Some(latency);
+        health.connected = true;
+        prior_failures
+    }
+
+    /// Record a failed connect attempt, scheduling the next attempt after an
+    /// exponential backoff with jitter. Returns the number of consecutive
+    /// failures (including this one) and the backoff.
+    pub fn record_connect_failure(
+        &mut self,
+        node_pk: &NodePk,
+        now: Instant,
+    ) -> (u32, Duration) {
+        let health = match self.peers.get_mut(node_pk) {
+            Some(health) => health,
+            None => return (0, Duration::ZERO),
+        };
+        health.connect_failures += 1;
+        health.consecutive_failures =
+            health.consecutive_failures.saturating_add(1);
+        health.connected = false;
+
+        let exponent = usize::try_from(health.consecutive_failures - 1)
+            .unwrap_or(usize::MAX);
+        let backoff = jittered_backoff(&mut self.rng, exponent);
+        health.next_attempt = now + backoff;
+        (health.consecutive_failures, backoff)
+    }
+}
+
+impl Default for PeerHealthTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `INITIAL_BACKOFF * 2^exponent`, capped at [`MAXIMUM_BACKOFF`], with "equal
+/// jitter": the result is uniformly sampled from `[backoff / 2, backoff]` so
+/// that peers which failed at the same time don't retry in lockstep.
+fn jittered_backoff(rng: &mut WeakRng, exponent: usize) -> Duration {
+    let factor =
+        2u32.saturating_pow(u32::try_from(exponent).unwrap_or(u32::MAX));
+    let backoff = INITIAL_BACKOFF.saturating_mul(factor).min(MAXIMUM_BACKOFF);
+
+    let half_ms = u64::try_from(backoff.as_millis() / 2).unwrap_or(u64::MAX);
+    let jitter_ms = rng.gen_u64() % (half_ms + 1);
+    Duration::from_millis(half_ms + jitter_ms)
+}
+
+#[cfg(test)]
+mod test {
+    use common::root_seed::RootSeed;
+
+    use super::*;
+
+    fn node_pk(n: u8) -> NodePk {
+        let mut rng = WeakRng::from_u64(u64::from(n));
+        RootSeed::from_rng(&mut rng).derive_node_pk(&mut rng)
+    }
+
+    #[test]
+    fn backoff_is_bounded_and_grows() {
+        let mut rng = WeakRng::from_u64(20240801);
+        for exponent in [0, 1, 5, 100, usize::MAX] {
+            let backoff = jittered_backoff(&mut rng, exponent);
+            assert!(backoff <= MAXIMUM_BACKOFF);
+            assert!(backoff >= INITIAL_BACKOFF / 2);
+        }
+        let large = jittered_backoff(&mut rng, 100);
+        assert!(large >= MAXIMUM_BACKOFF / 2);
+    }
+
+    #[test]
+    fn failures_back_off_and_success_resets() {
+        let mut tracker = PeerHealthTracker::new();
+        let now = Instant::now();
+        let pk = node_pk(1);
+        tracker.add_peer(pk, PeerKind::Channel, now);
+        assert_eq!(tracker.peers_to_reconnect(now), vec![pk]);
+
+        let (failures, backoff) = tracker.record_connect_failure(&pk, now);
+        assert_eq!(failures, 1);
+        assert!(tracker.peers_to_reconnect(now).is_empty());
+        assert_eq!(tracker.peers_to_reconnect(now + backoff), vec![pk]);
+
+        let (failures, _) = tracker.record_connect_failure(&pk, now + backoff);
+        assert_eq!(failures, 2);
+
+        let prior =
+            tracker.record_connect_success(&pk, Duration::from_millis(1));
+        assert_eq!(prior, 2);
+        let health = tracker.health(&pk).unwrap();
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.connect_failures, 2);
+        assert_eq!(health.connect_successes, 1);
+    }
+
+    #[test]
+    fn channel_peers_are_prioritized() {
+        let mut tracker = PeerHealthTracker::new();
+        let now = Instant::now();
+        let (gossip, flaky, healthy) = (node_pk(1), node_pk(2), node_pk(3));
+        tracker.add_peer(gossip, PeerKind::Gossip, now);
+        tracker.add_peer(flaky, PeerKind::Channel, now);
+        tracker.add_peer(healthy, PeerKind::Channel, now);
+
+        let (_, backoff) = tracker.record_connect_failure(&flaky, now);
+        let later = now + backoff;
+        assert_eq!(
+            tracker.peers_to_reconnect(later),
+            vec![healthy, flaky, gossip]
+        );
+
+        // Opening a channel with a gossip peer upgrades it
+        tracker.add_peer(gossip, PeerKind::Channel, now);
+        assert_eq!(tracker.health(&gossip).unwrap().kind, PeerKind::Channel);
+    }
+
+    #[test]
+    fn flapping_peer_backs_off() {
+        let mut tracker = PeerHealthTracker::new();
+        let now = Instant::now();
+        let pk = node_pk(1);
+        tracker.add_peer(pk, PeerKind::Channel, now);
+        let connected = HashSet::from_iter([pk]);
+        let disconnected = HashSet::new();
+
+        for i in 0..FLAPPING_THRESHOLD {
+            let t = now + Duration::from_secs(i as u64);
+            tracker.observe_connected(&connected, t);
+            tracker.observe_connected(&disconnected, t);
+        }
+        let t = now + Duration::from_secs(FLAPPING_THRESHOLD as u64);
+        assert_eq!(tracker.health(&pk).unwrap().recent_disconnects(), 3);
+        assert!(tracker.peers_to_reconnect(t).is_empty());
+        assert_eq!(tracker.peers_to_reconnect(t + MAXIMUM_BACKOFF), vec![pk]);
+
+        // Disconnects outside the window are forgotten
+        tracker.observe_connected(&disconnected, t + DISCONNECT_WINDOW * 2);
+        assert_eq!(tracker.health(&pk).unwrap().recent_disconnects(), 0);
+    }
+}
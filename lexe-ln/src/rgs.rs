@@ -0,0 +1,104 @@
+//! Rapid gossip sync (RGS) lets a node download a compact snapshot of the
+//! network graph instead of building it up from P2P gossip, which for a fresh
+//! node can take a long time. Until the graph is reasonably complete, routing
+//! (and thus the node's first payments) is unreliable.
+//!
+//! RGS snapshots don't include the signatures on the original gossip messages,
+//! so the snapshot server is trusted to serve a correct graph. Thus we only
+//! fetch snapshots over TLS from servers using our pinned root CAs. The worst a
+//! malicious server can do is degrade routing, which P2P gossip will fix over
+//! time. If a snapshot can't be fetched or applied, we simply fall back to P2P
+//! gossip alone.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Context};
+use common::constants;
+use lightning_rapid_gossip_sync::RapidGossipSync;
+use tracing::{debug, info, instrument};
+
+use crate::{alias::NetworkGraphType, logger::LexeTracingLogger};
+
+/// The duration after which a snapshot download will time out.
+const RGS_TIMEOUT: Duration = Duration::from_secs(15);
+/// Reject snapshots larger than this. Full mainnet snapshots are currently a
+/// few MiB; incremental snapshots are much smaller.
+const MAX_SNAPSHOT_BYTES: usize = 32 * 1024 * 1024;
+
+/// Fetch the latest RGS snapshot from `rgs_url` and apply it to the given
+/// network graph. If the graph has previously been synced via RGS, only the
+/// changes since the last sync are fetched.
+#[instrument(skip_all, name = "(rgs)")]
+pub async fn sync_network_graph(
+    rgs_url: &str,
+    network_graph: &NetworkGraphType,
+    logger: LexeTracingLogger,
+) -> anyhow::Result<()> {
+    let last_sync_timestamp = network_graph
+        .get_last_rapid_gossip_sync_timestamp()
+        .unwrap_or(0);
+    let snapshot_url = format!("{rgs_url}/{last_sync_timestamp}");
+    debug!(%last_sync_timestamp, "Fetching RGS snapshot");
+
+    let snapshot = fetch_snapshot(&snapshot_url)
+        .await
+        .context("Failed to fetch RGS snapshot")?;
+    let snapshot_bytes = snapshot.len();
+
+    let rgs = RapidGossipSync::new(network_graph, logger);
+    let new_sync_timestamp = rgs
+        .update_network_graph(&snapshot)
+        // LDK GraphSyncError is Debug but doesn't impl std::error::Error
+        .map_err(|e| anyhow!("{e:?}"))
+        .context("Failed to apply RGS snapshot")?;
+
+    let num_channels = network_graph.read_only().channels().len();
+    info!(
+        %new_sync_timestamp, %snapshot_bytes, %num_channels,
+        "Applied RGS snapshot"
+    );
+    Ok(())
+}
+
+async fn fetch_snapshot(snapshot_url: &str) -> anyhow::Result<Vec<u8>> {
+    let google_ca_cert =
+        reqwest11::Certificate::from_der(constants::GTS_ROOT_R1_CA_CERT_DER)
+            .context("Invalid Google CA der cert")?;
+    let letsencrypt_ca_cert = reqwest11::Certificate::from_der(
+        constants::LETSENCRYPT_ROOT_CA_CERT_DER,
+    )
+    .context("Invalid Let's Encrypt CA der cert")?;
+    let client = reqwest11::ClientBuilder::new()
+        .add_root_certificate(google_ca_cert)
+        .add_root_certificate(letsencrypt_ca_cert)
+        // Only trust the pinned roots, even though `PayjoinClient`
+        // enables the built-in webpki roots for this reqwest version.
+        .tls_built_in_root_certs(false)
+        .timeout(RGS_TIMEOUT)
+        .build()
+        .context("Failed to build reqwest client")?;
+
+    let resp = client
+        .get(snapshot_url)
+        .send()
+        .await
+        .context("RGS request failed")?
+        .error_for_status()
+        .context("RGS server returned an error")?;
+
+    if let Some(content_length) = resp.content_length() {
+        ensure!(
+            content_length <= MAX_SNAPSHOT_BYTES as u64,
+            "RGS snapshot too large: {content_length} bytes"
+        );
+    }
+
+    let snapshot = resp.bytes().await.context("Failed to read RGS snapshot")?;
+    ensure!(
+        snapshot.len() <= MAX_SNAPSHOT_BYTES,
+        "RGS snapshot too large: {} bytes",
+        snapshot.len()
+    );
+
+    Ok(snapshot.to_vec())
+}
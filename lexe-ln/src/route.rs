@@ -0,0 +1,314 @@
+//! Per-payment routing controls, a temporary blacklist of failing channels, and
+//! the [`LexeRouter`] which enforces the user's fee policy.
+//!
+//! LDK's router has no notion of excluded nodes or pinned first hops, but it
+//! never routes through any channel listed in
+//! [`PaymentParameters::previously_failed_channels`]. [`excluded_channels`]
+//! translates a payment's [`RouteControls`] (plus any blacklisted channels)
+//! into that list. LDK only appends to the list when retrying, so the
+//! exclusions hold for every retry.
+//!
+//! Our own channels are never put in that list, since excluding e.g. our only
+//! channel with the LSP would make every payment unroutable. Route controls
+//! which apply to our own channels instead restrict the first hops passed to
+//! the router; see [`first_hops`].
+//!
+//! The [`RouteBlacklist`] is populated from `PaymentPathFailed` events for
+//! channels other than our own. Each entry expires after a while, so channels
+//! which failed due to e.g. a temporary lack of liquidity are eventually tried
+//! again, while channels which keep failing are blacklisted for increasingly
+//! long periods.
+//!
+//! LDK finds a new route each time it retries a payment, so checking the fees
+//! of the route found in preflight isn't enough to enforce the user's
+//! [`FeePolicy`]. Instead, the [`LexeRouter`] checks every route it finds.
+//!
+//! [`PaymentParameters::previously_failed_channels`]: lightning::routing::router::PaymentParameters::previously_failed_channels
+
+use std::{
+    cmp,
+    collections::HashMap,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{ensure, Context};
+use bitcoin::secp256k1::PublicKey;
+use common::{
+    api::{command::RouteControls, remote_config::RemoteFeeConfig},
+    ln::{
+        amount::Amount,
+        fee_policy::{FeeExceedsPolicy, FeePolicy},
+    },
+};
+use lightning::{
+    ln::{
+        channelmanager::ChannelDetails,
+        msgs::{ErrorAction, LightningError},
+    },
+    routing::{
+        gossip::NodeId,
+        router::{InFlightHtlcs, Route, RouteParameters, Router},
+    },
+};
+
+use crate::alias::{DefaultRouterType, NetworkGraphType};
+
+/// How long a channel is blacklisted after its first recent failure.
+const INITIAL_BLACKLIST_DURATION: Duration = Duration::from_secs(60);
+/// The maximum time a channel can be blacklisted after a single failure.
+const MAXIMUM_BLACKLIST_DURATION: Duration = Duration::from_secs(30 * 60);
+
+/// A temporary, node-wide blacklist of channels which recently failed to
+/// forward our payments.
+#[derive(Default)]
+pub struct RouteBlacklist {
+    channels: Mutex<HashMap<u64, BlacklistEntry>>,
+}
+
+struct BlacklistEntry {
+    /// The number of failures since this channel was last off the blacklist.
+    strikes: u32,
+    /// The channel is blacklisted until this time.
+    until: Instant,
+}
+
+impl RouteBlacklist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that a payment failed at the channel with the given scid. The
+    /// channel is blacklisted for [`INITIAL_BLACKLIST_DURATION`], doubling
+    /// with each further failure while it is still blacklisted.
+    pub fn record_failure(&self, scid: u64, now: Instant) {
+        let mut channels = self.channels.lock().unwrap();
+        let strikes = match channels.get(&scid) {
+            Some(entry) if now < entry.until => entry.strikes + 1,
+            _ => 1,
+        };
+        let duration = INITIAL_BLACKLIST_DURATION
+            .checked_mul(1 << cmp::min(strikes - 1, 16))
+            .unwrap_or(MAXIMUM_BLACKLIST_DURATION);
+        let until = now + cmp::min(duration, MAXIMUM_BLACKLIST_DURATION);
+        channels.insert(scid, BlacklistEntry { strikes, until });
+    }
+
+    /// The scids of all currently blacklisted channels.
+    pub fn scids(&self, now: Instant) -> Vec<u64> {
+        let mut channels = self.channels.lock().unwrap();
+        channels.retain(|_, entry| now < entry.until);
+        channels.keys().copied().collect()
+    }
+}
+
+/// LDK's [`DefaultRouter`], but rejecting any route whose fees exceed the
+/// user's [`FeePolicy`]. Since LDK also uses the router when retrying, this
+/// covers every attempt of a payment. When retrying part of a payment, the
+/// policy is applied to the amount being retried.
+///
+/// [`DefaultRouter`]: lightning::routing::router::DefaultRouter
+pub struct LexeRouter {
+    inner: DefaultRouterType,
+    /// Applied to users who haven't set a default limit themselves.
+    default_max_fee_ppm: u32,
+    /// The user's policy, with the default limit filled in.
+    fee_policy: RwLock<FeePolicy>,
+}
+
+impl LexeRouter {
+    pub fn new(
+        inner: DefaultRouterType,
+        remote_fee_config: &RemoteFeeConfig,
+        fee_policy: FeePolicy,
+    ) -> Self {
+        let default_max_fee_ppm = remote_fee_config.max_routing_fee_ppm;
+        let fee_policy = fee_policy.or_default_max_fee_ppm(default_max_fee_ppm);
+        Self {
+            inner,
+            default_max_fee_ppm,
+            fee_policy: RwLock::new(fee_policy),
+        }
+    }
+
+    /// Update the policy after the user changed it.
+    pub fn set_fee_policy(&self, fee_policy: FeePolicy) {
+        let fee_policy =
+            fee_policy.or_default_max_fee_ppm(self.default_max_fee_ppm);
+        *self.fee_policy.write().unwrap() = fee_policy;
+    }
+
+    /// Checks that paying `fees` for a payment of `amount` is allowed.
+    pub fn check_fees(
+        &self,
+        amount: Amount,
+        fees: Amount,
+    ) -> Result<(), FeeExceedsPolicy> {
+        self.fee_policy.read().unwrap().check(amount, fees)
+    }
+
+    /// Find a route without checking its fees, so that the caller can
+    /// [`check_fees`] itself and get a [`FeeExceedsPolicy`] error.
+    ///
+    /// [`check_fees`]: Self::check_fees
+    pub fn find_route_unchecked(
+        &self,
+        payer: &PublicKey,
+        route_params: &RouteParameters,
+        first_hops: Option<&[&ChannelDetails]>,
+        inflight_htlcs: InFlightHtlcs,
+    ) -> Result<Route, LightningError> {
+        self.inner
+            .find_route(payer, route_params, first_hops, inflight_htlcs)
+    }
+}
+
+impl Router for LexeRouter {
+    fn find_route(
+        &self,
+        payer: &PublicKey,
+        route_params: &RouteParameters,
+        first_hops: Option<&[&ChannelDetails]>,
+        inflight_htlcs: InFlightHtlcs,
+    ) -> Result<Route, LightningError> {
+        let route = self.find_route_unchecked(
+            payer,
+            route_params,
+            first_hops,
+            inflight_htlcs,
+        )?;
+        let amount = Amount::from_msat(route_params.final_value_msat);
+        let fees = Amount::from_msat(route.get_total_fees());
+        self.check_fees(amount, fees).map_err(|e| LightningError {
+            err: format!("{e:#}"),
+            action: ErrorAction::IgnoreError,
+        })?;
+        Ok(route)
+    }
+}
+
+/// Compute the scids of all channels the router must avoid for a payment with
+/// the given [`RouteControls`]: explicitly excluded channels, all channels of
+/// excluded nodes, and blacklisted channels, except for any of `our_channels`.
+pub fn excluded_channels(
+    controls: &RouteControls,
+    our_channels: &[ChannelDetails],
+    network_graph: &NetworkGraphType,
+    blacklist: &RouteBlacklist,
+    now: Instant,
+) -> Vec<u64> {
+    let mut excluded = controls
+        .exclude_channels
+        .iter()
+        .map(|scid| scid.0)
+        .collect::<Vec<_>>();
+
+    {
+        let graph = network_graph.read_only();
+        for node_pk in &controls.exclude_nodes {
+            if let Some(node) = graph.node(&NodeId::from_pubkey(&node_pk.0)) {
+                excluded.extend_from_slice(&node.channels);
+            }
+        }
+    }
+    excluded.extend(blacklist.scids(now));
+
+    excluded.retain(|scid| !is_our_channel(*scid, our_channels));
+    excluded.sort_unstable();
+    excluded.dedup();
+    excluded
+}
+
+/// Select which of our `usable_channels` the router may use as the first hop
+/// of a payment with the given [`RouteControls`]: the pinned first hop if set,
+/// otherwise all channels which aren't excluded themselves or with an excluded
+/// node.
+pub fn first_hops<'a>(
+    controls: &RouteControls,
+    usable_channels: &'a [ChannelDetails],
+) -> anyhow::Result<Vec<&'a ChannelDetails>> {
+    let is_excluded = |channel: &ChannelDetails| {
+        let excluded_node = controls
+            .exclude_nodes
+            .iter()
+            .any(|node_pk| node_pk.0 == channel.counterparty.node_id);
+        let excluded_channel = controls
+            .exclude_channels
+            .iter()
+            .any(|scid| channel_scids(channel).any(|ours| ours == scid.0));
+        excluded_node || excluded_channel
+    };
+
+    let first_hops = match controls.first_hop {
+        Some(channel_id) => {
+            let channel = usable_channels
+                .iter()
+                .find(|channel| channel.channel_id == channel_id.0)
+                .with_context(|| {
+                    format!("First hop channel {channel_id} is not usable")
+                })?;
+            ensure!(
+                !is_excluded(channel),
+                "First hop channel {channel_id} is also excluded",
+            );
+            vec![channel]
+        }
+        None => usable_channels
+            .iter()
+            .filter(|channel| !is_excluded(channel))
+            .collect(),
+    };
+    Ok(first_hops)
+}
+
+/// Whether `scid` identifies one of `our_channels`, including by alias.
+pub fn is_our_channel(scid: u64, our_channels: &[ChannelDetails]) -> bool {
+    our_channels
+        .iter()
+        .any(|channel| channel_scids(channel).any(|ours| ours == scid))
+}
+
+/// All of the scids which identify a channel.
+fn channel_scids(channel: &ChannelDetails) -> impl Iterator<Item = u64> {
+    [
+        channel.short_channel_id,
+        channel.inbound_scid_alias,
+        channel.outbound_scid_alias,
+    ]
+    .into_iter()
+    .flatten()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blacklist_decays() {
+        let blacklist = RouteBlacklist::new();
+        let start = Instant::now();
+        let after = |secs| start + Duration::from_secs(secs);
+
+        blacklist.record_failure(42, start);
+        assert_eq!(blacklist.scids(after(59)), vec![42]);
+        assert!(blacklist.scids(after(60)).is_empty());
+
+        // Repeated failures while blacklisted double the duration.
+        blacklist.record_failure(42, after(60));
+        blacklist.record_failure(42, after(90));
+        assert_eq!(blacklist.scids(after(90 + 119)), vec![42]);
+        assert!(blacklist.scids(after(90 + 120)).is_empty());
+
+        // Strikes reset once the channel is off the blacklist.
+        blacklist.record_failure(42, after(1000));
+        assert!(blacklist.scids(after(1060)).is_empty());
+
+        // The duration is capped.
+        for i in 0..20 {
+            blacklist.record_failure(7, after(2000 + i));
+        }
+        let max = MAXIMUM_BLACKLIST_DURATION.as_secs();
+        assert_eq!(blacklist.scids(after(2019 + max - 1)), vec![7]);
+        assert!(blacklist.scids(after(2019 + max)).is_empty());
+    }
+}
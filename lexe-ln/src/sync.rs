@@ -1,7 +1,9 @@
 use std::{sync::Arc, time::Instant};
 
 use anyhow::{anyhow, Context};
-use common::{notify, shutdown::ShutdownChannel, task::LxTask};
+use common::{
+    notify, shutdown::ShutdownChannel, task::LxTask, test_event::TestEvent,
+};
 use lightning::chain::Confirm;
 use tokio::{
     sync::{mpsc, oneshot},
@@ -11,6 +13,7 @@ use tracing::{error, info};
 
 use crate::{
     alias::EsploraSyncClientType,
+    test_event::TestEventSender,
     traits::{LexeChainMonitor, LexeChannelManager, LexePersister},
     wallet::LexeWallet,
 };
@@ -31,6 +34,7 @@ pub fn spawn_bdk_sync_task(
     onchain_recv_tx: notify::Sender,
     first_bdk_sync_tx: oneshot::Sender<anyhow::Result<()>>,
     mut bdk_resync_rx: mpsc::Receiver<oneshot::Sender<()>>,
+    test_event_tx: TestEventSender,
     mut shutdown: ShutdownChannel,
 ) -> LxTask<()> {
     LxTask::spawn_named("bdk sync", async move {
@@ -84,6 +88,7 @@ pub fn spawn_bdk_sync_task(
                     match sync_res {
                         Ok(()) => {
                             info!("BDK sync completed <{elapsed}ms>");
+                            test_event_tx.record(TestEvent::BdkSyncCompleted);
                             onchain_recv_tx.send();
                             for tx in synced_txs.drain(..) {
                                 let _ = tx.send(());
@@ -107,6 +112,7 @@ pub fn spawn_ldk_sync_task<CMAN, CMON, PS>(
     ldk_sync_client: Arc<EsploraSyncClientType>,
     first_ldk_sync_tx: oneshot::Sender<anyhow::Result<()>>,
     mut ldk_resync_rx: mpsc::Receiver<oneshot::Sender<()>>,
+    test_event_tx: TestEventSender,
     mut shutdown: ShutdownChannel,
 ) -> LxTask<()>
 where
@@ -171,6 +177,7 @@ where
                     match sync_res {
                         Ok(()) => {
                             info!("LDK sync completed <{elapsed}ms>");
+                            test_event_tx.record(TestEvent::LdkSyncCompleted);
                             for tx in synced_txs.drain(..) {
                                 let _ = tx.send(());
                             }
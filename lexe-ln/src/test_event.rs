@@ -1,20 +1,27 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt::Write,
     mem::{self, Discriminant},
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
 use anyhow::bail;
 use cfg_if::cfg_if;
-use common::test_event::{TestEvent, TestEventOp};
+use common::{
+    test_event::{RecordedTestEvent, TestEvent, TestEventOp},
+    time::TimestampMs,
+};
 use tokio::sync::mpsc;
 use tracing::debug;
 
 // Increase these if needed
 pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
 const TEST_EVENT_CHANNEL_SIZE: usize = 16;
+/// How long sent [`TestEvent`]s are kept in the event history.
+pub const EVENT_HISTORY_WINDOW: Duration = Duration::from_secs(60 * 60);
+/// The max # of events kept in the event history, in case of a burst.
+const MAX_EVENT_HISTORY_LEN: usize = 1024;
 
 /// Creates a [`TestEvent`] channel, returning a `(tx, rx)` tuple.
 pub fn channel(label: &'static str) -> (TestEventSender, TestEventReceiver) {
@@ -63,6 +70,10 @@ pub async fn do_op(
 
 /// Wraps an [`mpsc::Sender<TestEvent>`] to allow actually sending the event to
 /// be cfg'd out in staging/prod.
+///
+/// Every event is also recorded in an in-memory history, which is kept in all
+/// environments so that operators can see the recent sequence of internal
+/// events (syncs, persists, payments) when diagnosing a user's issue.
 #[derive(Clone)]
 pub struct TestEventSender {
     /// A label (e.g. "(user)", "(lsp)") which allows "received test event" log
@@ -72,21 +83,48 @@ pub struct TestEventSender {
     label: &'static str,
     #[cfg(any(test, feature = "test-utils"))]
     tx: mpsc::Sender<TestEvent>,
+    history: Arc<Mutex<EventHistory>>,
+}
+
+/// The events sent in the last [`EVENT_HISTORY_WINDOW`], oldest first.
+#[derive(Default)]
+struct EventHistory {
+    events: VecDeque<RecordedTestEvent>,
 }
 
 impl TestEventSender {
     fn new(label: &'static str, tx: mpsc::Sender<TestEvent>) -> Self {
+        let history = Arc::new(Mutex::new(EventHistory::default()));
         cfg_if! {
             if #[cfg(any(test, feature = "test-utils"))] {
-                Self { label, tx }
+                Self { label, tx, history }
             } else {
                 let _ = tx;
-                Self { label }
+                Self { label, history }
             }
         }
     }
 
+    /// Records the given event in the history without sending it to the
+    /// [`TestEventReceiver`]. Use this for frequent events which tests don't
+    /// wait on, so that they can't crowd other events out of the channel.
+    pub fn record(&self, event: TestEvent) {
+        self.history
+            .lock()
+            .unwrap()
+            .record(event, TimestampMs::now());
+    }
+
+    /// Returns the events sent in the last [`EVENT_HISTORY_WINDOW`], oldest
+    /// first.
+    pub fn history(&self) -> Vec<RecordedTestEvent> {
+        let mut history = self.history.lock().unwrap();
+        history.prune(TimestampMs::now());
+        history.events.iter().cloned().collect()
+    }
+
     pub fn send(&self, event: TestEvent) {
+        self.record(event);
         cfg_if! {
             if #[cfg(any(test, feature = "test-utils"))] {
                 let label = &self.label;
@@ -99,6 +137,26 @@ impl TestEventSender {
     }
 }
 
+impl EventHistory {
+    fn record(&mut self, event: TestEvent, now: TimestampMs) {
+        self.events.push_back(RecordedTestEvent { at: now, event });
+        self.prune(now);
+    }
+
+    /// Drops events older than [`EVENT_HISTORY_WINDOW`], as well as the oldest
+    /// events if we're over [`MAX_EVENT_HISTORY_LEN`].
+    fn prune(&mut self, now: TimestampMs) {
+        let cutoff = now.into_duration().saturating_sub(EVENT_HISTORY_WINDOW);
+        while let Some(oldest) = self.events.front() {
+            let expired = oldest.at.into_duration() < cutoff;
+            if !expired && self.events.len() <= MAX_EVENT_HISTORY_LEN {
+                break;
+            }
+            self.events.pop_front();
+        }
+    }
+}
+
 /// Wraps a [`mpsc::Receiver<TestEvent>`] to provide convenience helpers for
 /// waiting for certain events to occur.
 pub struct TestEventReceiver {
@@ -527,4 +585,28 @@ mod test {
         );
         assert_ready!(task.poll()).unwrap();
     }
+
+    #[test]
+    fn event_history_is_pruned() {
+        let event = TestEvent::LdkSyncCompleted;
+        let mut history = EventHistory::default();
+        let ts = |secs: u64| {
+            TimestampMs::try_from(Duration::from_secs(secs)).unwrap()
+        };
+
+        history.record(event, ts(0));
+        history.record(event, ts(60));
+        let window = EVENT_HISTORY_WINDOW.as_secs();
+        history.prune(ts(window));
+        assert_eq!(history.events.len(), 2);
+        history.prune(ts(window + 1));
+        assert_eq!(history.events.len(), 1);
+        assert_eq!(history.events[0].at, ts(60));
+
+        for _ in 0..MAX_EVENT_HISTORY_LEN {
+            history.record(event, ts(120));
+        }
+        assert_eq!(history.events.len(), MAX_EVENT_HISTORY_LEN);
+        assert!(history.events.iter().all(|e| e.at == ts(120)));
+    }
 }
@@ -2,7 +2,7 @@ use std::{ops::Deref, sync::Mutex};
 
 use async_trait::async_trait;
 use common::{
-    api::vfs::VfsFile,
+    api::{qs::GetNewPayments, vfs::VfsFile},
     ln::{
         payments::{LxPaymentId, PaymentIndex},
         peer::ChannelPeer,
@@ -20,6 +20,7 @@ use crate::{
     },
     payments::{
         manager::{CheckedPayment, PersistedPayment},
+        onchain::ExpectedDeposit,
         Payment,
     },
 };
@@ -85,6 +86,25 @@ pub trait LexeInnerPersister: Persist<SignerType> {
         &self,
         index: PaymentIndex,
     ) -> anyhow::Result<Option<Payment>>;
+
+    /// Fetches a batch of payments in ascending index order.
+    async fn read_payments_batch(
+        &self,
+        req: GetNewPayments,
+    ) -> anyhow::Result<Vec<Payment>>;
+
+    /// Moves the given finalized payments out of the payments DB and into an
+    /// archive file in the VFS.
+    async fn archive_payments(
+        &self,
+        payments: Vec<Payment>,
+    ) -> anyhow::Result<()>;
+
+    /// Persists the full list of unpaid [`ExpectedDeposit`]s.
+    async fn persist_expected_deposits(
+        &self,
+        deposits: &[ExpectedDeposit],
+    ) -> anyhow::Result<()>;
 }
 
 /// A 'trait alias' defining all the requirements of a Lexe persister.
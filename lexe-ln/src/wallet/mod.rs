@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{cmp, collections::HashMap, sync::Arc};
 
 use anyhow::{ensure, Context};
 use bdk::{
@@ -12,7 +12,7 @@ use bdk::{
 };
 use bitcoin::{
     util::{address::Address, psbt::PartiallySignedTransaction},
-    Script, Transaction, Txid,
+    OutPoint, Script, Transaction, Txid,
 };
 use common::{
     api::command::{
@@ -34,7 +34,8 @@ use tracing::{debug, info, instrument, warn};
 
 use crate::{
     esplora::LexeEsplora,
-    payments::onchain::OnchainSend,
+    payjoin::{self, PayjoinClient, PayjoinParams},
+    payments::onchain::{OnchainCpfp, OnchainSend, OnchainSendPath},
     traits::{LexeInnerPersister, LexePersister},
     wallet::db::WalletDb,
 };
@@ -45,7 +46,10 @@ pub mod db;
 /// The 'stop_gap' parameter used by BDK's wallet sync. This seems to configure
 /// the threshold number of blocks after which BDK stops looking for scripts
 /// belonging to the wallet. BDK's default value for this is 20.
-const BDK_WALLET_SYNC_STOP_GAP: usize = 20;
+pub(crate) const BDK_WALLET_SYNC_STOP_GAP: usize = 20;
+/// Bitcoin Core's default incremental relay feerate, which a replacement tx
+/// must pay for its own size on top of the fees of the tx it replaces.
+const INCREMENTAL_RELAY_FEE_SAT_PER_VB: u64 = 1;
 
 type TxBuilderType<'wallet, MODE> =
     TxBuilder<'wallet, WalletDb, DefaultCoinSelectionAlgorithm, MODE>;
@@ -70,6 +74,7 @@ pub struct LexeWallet {
     // - https://github.com/bitcoindevkit/bdk/commit/c5b2f5ac9ac152a7e0658ca99ccaf854b9063727
     // - https://github.com/bitcoindevkit/bdk/commit/ddc84ca1916620d021bae8c467c53555b7c62467
     wallet: Arc<tokio::sync::Mutex<Wallet<WalletDb>>>,
+    payjoin: PayjoinClient,
 }
 
 impl LexeWallet {
@@ -102,8 +107,13 @@ impl LexeWallet {
         .map(tokio::sync::Mutex::new)
         .map(Arc::new)
         .context("bdk::Wallet::new failed")?;
+        let payjoin = PayjoinClient::new()?;
 
-        Ok(Self { esplora, wallet })
+        Ok(Self {
+            esplora,
+            wallet,
+            payjoin,
+        })
     }
 
     /// Syncs the inner [`bdk::Wallet`] using the given Esplora server.
@@ -178,6 +188,27 @@ impl LexeWallet {
             .context("Could not get new address")
     }
 
+    /// Returns a never-before-issued address derived using the external
+    /// descriptor, for callers which need a distinct address per receive.
+    /// [`Self::get_address`] will never return this address.
+    ///
+    /// NOTE: Every call advances the address index by two, so callers must
+    /// limit how many addresses they issue which may never be used; see
+    /// [`BDK_WALLET_SYNC_STOP_GAP`].
+    pub(crate) async fn get_new_address(&self) -> anyhow::Result<Address> {
+        let mut locked_wallet = self.wallet.lock().await;
+        let address = locked_wallet
+            .get_address(AddressIndex::New)
+            .map(|info| info.address)
+            .context("Could not get new address")?;
+        // `LastUnused` returns the address at the current index, so move the
+        // index past this address to keep `get_address` from reissuing it.
+        locked_wallet
+            .get_address(AddressIndex::New)
+            .context("Could not advance address index")?;
+        Ok(address)
+    }
+
     /// Calls [`bdk::Wallet::list_transactions`].
     pub async fn list_transactions(
         &self,
@@ -234,6 +265,9 @@ impl LexeWallet {
 
     /// Create and sign a transaction which sends an [`Amount`] to the given
     /// [`Address`], packaging up all of this info in a new [`OnchainSend`].
+    ///
+    /// If the request includes a payjoin endpoint, we'll try to payjoin with
+    /// the receiver, falling back to our original tx if the payjoin fails.
     pub(crate) async fn create_onchain_send(
         &self,
         req: PayOnchainRequest,
@@ -242,7 +276,7 @@ impl LexeWallet {
         let conf_target = ConfirmationTarget::from(req.priority);
         let bdk_feerate = self.esplora.get_bdk_feerate(conf_target);
 
-        let (tx, fees) = {
+        let (unsigned_psbt, psbt, fees) = {
             let locked_wallet = self.wallet.lock().await;
 
             // Build unsigned tx
@@ -261,19 +295,238 @@ impl LexeWallet {
             );
             let fees =
                 Amount::try_from_sats_u64(fees).context("Bad fee amount")?;
+            let unsigned_psbt = psbt.clone();
 
             // Sign tx
             Self::default_sign_psbt(&locked_wallet, &mut psbt)
                 .context("Could not sign outbound tx")?;
 
-            (psbt.extract_tx(), fees)
+            (unsigned_psbt, psbt, fees)
+        };
+
+        let endpoint = match req.payjoin_endpoint.clone() {
+            Some(endpoint) => endpoint,
+            None => {
+                let tx = psbt.extract_tx();
+                let path = OnchainSendPath::Direct;
+                return Ok(OnchainSend::new(tx, req, fees, path));
+            }
         };
 
-        let onchain_send = OnchainSend::new(tx, req, fees);
+        let payjoin_result = self
+            .try_payjoin(&endpoint, &req, &unsigned_psbt, &psbt, bdk_feerate)
+            .await;
+        let onchain_send = match payjoin_result {
+            Ok((tx, fees)) => {
+                info!("Payjoin succeeded");
+                OnchainSend::new(tx, req, fees, OnchainSendPath::Payjoin)
+            }
+            Err(e) => {
+                warn!("Payjoin failed; sending original tx: {e:#}");
+                let tx = psbt.extract_tx();
+                let path = OnchainSendPath::PayjoinFallback;
+                OnchainSend::new(tx, req, fees, path)
+            }
+        };
 
         Ok(onchain_send)
     }
 
+    /// Negotiates a BIP78 payjoin with the receiver at `endpoint`, given our
+    /// original PSBT both before and after signing. Returns the signed payjoin
+    /// tx along with the fees we paid for it.
+    async fn try_payjoin(
+        &self,
+        endpoint: &str,
+        req: &PayOnchainRequest,
+        unsigned_psbt: &PartiallySignedTransaction,
+        original_psbt: &PartiallySignedTransaction,
+        bdk_feerate: FeeRate,
+    ) -> anyhow::Result<(Transaction, Amount)> {
+        let payee_script = req.address.script_pubkey();
+        let fee_output_index = {
+            let locked_wallet = self.wallet.lock().await;
+            original_psbt.unsigned_tx.output.iter().position(|txout| {
+                txout.script_pubkey != payee_script
+                    && locked_wallet
+                        .is_mine(&txout.script_pubkey)
+                        .unwrap_or(false)
+            })
+        };
+        let params = PayjoinParams {
+            fee_output_index,
+            max_additional_fee: payjoin::max_additional_fee(bdk_feerate),
+            min_feerate: bdk_feerate,
+        };
+
+        // Don't hold the wallet lock while waiting on the receiver.
+        let mut proposal = self
+            .payjoin
+            .request_proposal(endpoint, original_psbt, &params)
+            .await?;
+
+        let locked_wallet = self.wallet.lock().await;
+        let contribution = payjoin::validate_proposal(
+            original_psbt,
+            &proposal,
+            &params,
+            |script| locked_wallet.is_mine(script).context("is_mine failed"),
+        )
+        .context("Invalid payjoin proposal")?;
+
+        // The receiver strips the UTXO info and signatures from our inputs, so
+        // restore our inputs from the unsigned original before signing.
+        let our_inputs = unsigned_psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .zip(&unsigned_psbt.inputs)
+            .collect::<HashMap<_, _>>();
+        let proposal_inputs =
+            proposal.unsigned_tx.input.iter().zip(&mut proposal.inputs);
+        for (txin, input) in proposal_inputs {
+            if let Some(our_input) = our_inputs.get(&txin.previous_output) {
+                *input = (*our_input).clone();
+            }
+        }
+        Self::default_sign_psbt(&locked_wallet, &mut proposal)
+            .context("Could not sign payjoin tx")?;
+
+        let fees = contribution
+            .checked_sub(req.amount.sats_u64())
+            .context("Payjoin contribution is less than the amount sent")?;
+        let fees = Amount::try_from_sats_u64(fees).context("Bad fee amount")?;
+
+        Ok((proposal.extract_tx(), fees))
+    }
+
+    /// Create and sign a child-pays-for-parent (CPFP) tx which spends all of
+    /// our outputs in the given unconfirmed `parent` back to ourselves, paying
+    /// enough fees for the parent + child package to reach `package_feerate`.
+    /// Returns the child tx along with the fees paid by the child.
+    ///
+    /// If `prev_child` already spends the same outputs, the new child replaces
+    /// it, so it pays at least enough to satisfy BIP125: more fees than the
+    /// previous child plus the incremental relay fee for its own size, at a
+    /// higher feerate. This may exceed `package_feerate`.
+    pub(crate) async fn create_cpfp_child(
+        &self,
+        parent: &Transaction,
+        package_feerate: FeeRate,
+        prev_child: Option<&OnchainCpfp>,
+    ) -> anyhow::Result<(Transaction, Amount)> {
+        // Compute the fee the parent already pays from the value of its
+        // prevouts, which may not belong to us.
+        let mut input_sats = 0u64;
+        for txin in &parent.input {
+            let prev_txid = txin.previous_output.txid;
+            let prev_tx = self
+                .esplora
+                .client()
+                .get_tx(&prev_txid)
+                .await
+                .context("Could not fetch parent input tx")?
+                .context("Parent input tx not found")?;
+            let prevout = prev_tx
+                .output
+                .get(txin.previous_output.vout as usize)
+                .context("Parent input prevout index out of bounds")?;
+            input_sats += prevout.value;
+        }
+        let output_sats =
+            parent.output.iter().map(|txout| txout.value).sum::<u64>();
+        let parent_fee = input_sats
+            .checked_sub(output_sats)
+            .context("Parent outputs exceed its inputs")?;
+        let parent_vsize = (parent.weight() as u64 + 3) / 4;
+
+        // The previous child's size, if it's still around to be replaced.
+        let prev_child = match prev_child {
+            Some(prev) => self
+                .esplora
+                .client()
+                .get_tx(&prev.txid.0)
+                .await
+                .context("Could not fetch previous CPFP tx")?
+                .map(|prev_tx| (prev.fees, (prev_tx.weight() as u64 + 3) / 4)),
+            None => None,
+        };
+
+        let sat_per_vb = package_feerate.as_sat_per_vb();
+        let parent_feerate = parent_fee as f32 / parent_vsize as f32;
+        ensure!(
+            sat_per_vb > parent_feerate,
+            "Parent tx already pays {parent_feerate:.1} sat/vB",
+        );
+
+        let locked_wallet = self.wallet.lock().await;
+
+        let parent_txid = parent.txid();
+        let outpoints = parent
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(_, txout)| {
+                locked_wallet.is_mine(&txout.script_pubkey).unwrap_or(false)
+            })
+            .map(|(vout, _)| OutPoint::new(parent_txid, vout as u32))
+            .collect::<Vec<_>>();
+        ensure!(!outpoints.is_empty(), "Parent tx has no outputs to us");
+
+        let drain_script = locked_wallet
+            .get_internal_address(AddressIndex::New)
+            .context("Failed to derive change address")?
+            .script_pubkey();
+
+        // First pass: build the child at the package feerate to get its size.
+        let (_, tx_details) = Self::cpfp_tx_builder(
+            &locked_wallet,
+            package_feerate,
+            &outpoints,
+            drain_script.clone(),
+        )?
+        .finish()
+        .context("Failed to build CPFP tx")?;
+        let first_fee = tx_details
+            .fee
+            .expect("When creating a new tx, bdk always sets the fee value");
+        let child_vsize = (first_fee as f32 / sat_per_vb).ceil() as u64;
+
+        // Second pass: pay for the parent's shortfall as well.
+        let package_fee =
+            (sat_per_vb * (parent_vsize + child_vsize) as f32).ceil() as u64;
+        let mut child_fee =
+            cmp::max(package_fee.saturating_sub(parent_fee), first_fee);
+        if let Some((prev_fees, prev_vsize)) = prev_child {
+            let prev_fee = prev_fees.sats_u64();
+            // BIP125 rules 3 and 4: a higher absolute fee, which also pays
+            // for relaying the replacement.
+            let min_fee =
+                prev_fee + INCREMENTAL_RELAY_FEE_SAT_PER_VB * child_vsize;
+            // BIP125 rule 6: a higher feerate.
+            let min_feerate_fee = prev_fee * child_vsize / prev_vsize + 1;
+            child_fee = cmp::max(child_fee, cmp::max(min_fee, min_feerate_fee));
+        }
+        let mut tx_builder = Self::cpfp_tx_builder(
+            &locked_wallet,
+            package_feerate,
+            &outpoints,
+            drain_script,
+        )?;
+        tx_builder.fee_absolute(child_fee);
+        let (mut psbt, _tx_details) = tx_builder
+            .finish()
+            .context("Our outputs can't cover the CPFP fee")?;
+
+        Self::default_sign_psbt(&locked_wallet, &mut psbt)
+            .context("Could not sign CPFP tx")?;
+
+        let fees =
+            Amount::try_from_sats_u64(child_fee).context("Bad fee amount")?;
+        Ok((psbt.extract_tx(), fees))
+    }
+
     /// Estimate the network fee for a potential onchain send payment. We return
     /// estimates for each [`ConfirmationPriority`] preset.
     ///
@@ -370,6 +623,23 @@ impl LexeWallet {
         tx_builder
     }
 
+    /// Get a [`TxBuilder`] which spends exactly the given `outpoints` to
+    /// `drain_script`, for building CPFP child txs.
+    fn cpfp_tx_builder<'wallet>(
+        wallet: &'wallet Wallet<WalletDb>,
+        bdk_feerate: FeeRate,
+        outpoints: &[OutPoint],
+        drain_script: Script,
+    ) -> anyhow::Result<TxBuilderType<'wallet, CreateTx>> {
+        let mut tx_builder = Self::default_tx_builder(wallet, bdk_feerate);
+        tx_builder
+            .add_utxos(outpoints)
+            .context("Parent outputs are not in our wallet")?
+            .manually_selected_only()
+            .drain_to(drain_script);
+        Ok(tx_builder)
+    }
+
     /// Sign a [`PartiallySignedTransaction`] in the default way.
     fn default_sign_psbt(
         wallet: &Wallet<WalletDb>,
@@ -2,13 +2,19 @@
 //!
 //! See also: the `logger` module in the `public/lexe-ln` crate for log config
 //! in enclaves.
+//!
+//! With the `otlp` feature, spans can also be exported to an OpenTelemetry
+//! collector; see [`otlp`].
 
 use std::str::FromStr;
 
 use anyhow::anyhow;
 #[cfg(doc)]
 use common::api::trace::TraceId;
-use common::{api::trace, define_trace_id_fns};
+use common::{
+    api::{log_capture::LogCaptureLayer, trace},
+    define_trace_id_fns,
+};
 use tracing::Level;
 use tracing_subscriber::{
     filter::{Filtered, Targets},
@@ -21,6 +27,10 @@ use tracing_subscriber::{
     Registry,
 };
 
+/// Optional OpenTelemetry (OTLP) span export.
+#[cfg(feature = "otlp")]
+pub mod otlp;
+
 /// Initialize a global `tracing` logger.
 ///
 /// + The logger will print enabled `tracing` events and spans to stdout.
@@ -29,6 +39,8 @@ use tracing_subscriber::{
 ///   `RUST_LOG` env var set. Read more about the syntax here:
 ///   <https://docs.rs/tracing-subscriber/latest/tracing_subscriber/filter/struct.EnvFilter.html>
 ///
+/// Call [`shutdown`] before the process exits.
+///
 /// Panics if a logger is already initialized. This will fail if used in tests,
 /// since multiple test threads will compete to set the global logger.
 pub fn init() {
@@ -50,35 +62,79 @@ pub fn init_for_testing() {
 /// Try to initialize a global logger. Will return an `Err` if there is another
 /// global logger already set.
 pub fn try_init() -> anyhow::Result<()> {
-    subscriber().try_init().context("Logger already set")?;
+    subscriber()?.try_init().context("Logger already set")?;
 
     define_trace_id_fns!(SubscriberType);
+
+    /// Also use the [`TraceId`] as the OTLP trace id of exported root spans.
+    #[cfg(feature = "otlp")]
+    fn insert_trace_id(
+        id: &tracing::span::Id,
+        dispatch: &tracing::Dispatch,
+        trace_id: TraceId,
+    ) -> anyhow::Result<Option<TraceId>> {
+        let subscriber = dispatch
+            .downcast_ref::<SubscriberType>()
+            .context("Downcast failed")?;
+        otlp::set_otel_trace_id(subscriber, id, &trace_id)?;
+        insert_trace_id_into_span(id, dispatch, trace_id)
+    }
+    #[cfg(not(feature = "otlp"))]
+    let insert_trace_id = insert_trace_id_into_span;
+
     trace::GET_TRACE_ID_FN
         .set(get_trace_id_from_span)
         .map_err(|_| anyhow!("GET_TRACE_ID_FN already set"))?;
     trace::INSERT_TRACE_ID_FN
-        .set(insert_trace_id_into_span)
+        .set(insert_trace_id)
         .map_err(|_| anyhow!("INSERT_TRACE_ID_FN already set"))?;
 
     Ok(())
 }
 
+/// Flush anything the logger has buffered, i.e. spans which haven't been
+/// exported over OTLP yet. Services should call this right before exiting,
+/// including after a graceful shutdown.
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    otlp::shutdown();
+}
+
+/// The per-request log capture part of our subscriber.
+type CaptureSubscriberType =
+    Layered<Filtered<LogCaptureLayer, Targets, Registry>, Registry>;
+
+/// The stdout logging part of our subscriber.
+type StdoutSubscriberType = Layered<
+    Filtered<
+        FmtLayer<CaptureSubscriberType, DefaultFields, Format<Compact>>,
+        Targets,
+        CaptureSubscriberType,
+    >,
+    CaptureSubscriberType,
+>;
+
 /// The full type of our subscriber which is downcasted to when recovering
 /// [`TraceId`]s. If having trouble naming this correctly, change this to some
 /// dummy value (e.g. `u32`) and the compiler will tell you what it should be.
+#[cfg(not(feature = "otlp"))]
+type SubscriberType = StdoutSubscriberType;
+#[cfg(feature = "otlp")]
 type SubscriberType = Layered<
-    Filtered<
-        FmtLayer<Registry, DefaultFields, Format<Compact>>,
-        Targets,
-        Registry,
+    Option<
+        Filtered<
+            otlp::OtlpLayer<StdoutSubscriberType>,
+            Targets,
+            StdoutSubscriberType,
+        >,
     >,
-    Registry,
+    StdoutSubscriberType,
 >;
 
 /// Generates our [`tracing::Subscriber`] impl. This function is extracted so
 /// that we can check the correctness of the `SubscriberType` type alias, which
 /// allows us to downcast back to our subscriber to recover [`TraceId`]s.
-fn subscriber() -> SubscriberType {
+fn subscriber() -> anyhow::Result<SubscriberType> {
     // TODO(phlip9): non-blocking writer for prod
     // see: https://docs.rs/tracing-appender/latest/tracing_appender/non_blocking/index.html
 
@@ -96,9 +152,26 @@ fn subscriber() -> SubscriberType {
         // Enable colored outputs for stdout.
         // NOTE: This should be disabled if outputting to files
         .with_ansi(true)
-        .with_filter(rust_log_filter);
-
-    tracing_subscriber::registry().with(stdout_log)
+        .with_filter(rust_log_filter.clone());
+
+    // Capture the same events for per-request error reports.
+    let capture_log = LogCaptureLayer.with_filter(rust_log_filter.clone());
+
+    let subscriber = tracing_subscriber::registry()
+        .with(capture_log)
+        .with(stdout_log);
+
+    // Export the same spans over OTLP, if configured.
+    #[cfg(feature = "otlp")]
+    let subscriber = {
+        let otlp_layer = otlp::OtlpConfig::from_env()?
+            .map(|config| otlp::layer(&config))
+            .transpose()?
+            .map(|layer| layer.with_filter(rust_log_filter));
+        subscriber.with(otlp_layer)
+    };
+
+    Ok(subscriber)
 }
 
 #[cfg(test)]
@@ -0,0 +1,262 @@
+//! Optional OpenTelemetry (OTLP) span export, enabled by the `otlp` feature.
+//!
+//! Export is configured with the standard OpenTelemetry env vars, and is
+//! disabled at runtime if `OTEL_EXPORTER_OTLP_ENDPOINT` is not set:
+//!
+//! + `OTEL_EXPORTER_OTLP_ENDPOINT`: the OTLP/HTTP collector endpoint of e.g. a
+//!   local Jaeger or Tempo: `http://localhost:4318`.
+//! + `OTEL_SERVICE_NAME`: the `service.name` resource attribute. Defaults to
+//!   "lexe".
+//! + `OTEL_TRACES_SAMPLER_ARG`: the fraction of traces to export, between 0.0
+//!   and 1.0. Defaults to 1.0.
+//!
+//! Sampling is head-based: the decision is made once per trace, when its root
+//! span starts, and is inherited by all of its child spans.
+//!
+//! Spans are exported in batches by a tokio runtime on a dedicated thread, so
+//! the logger can be initialized before the service's own runtime is built,
+//! and buffered spans can still be flushed after it has shut down. Call
+//! [`crate::shutdown`] before the process exits to flush them.
+//!
+//! Root spans which are assigned a [`TraceId`] (e.g. by our API client and
+//! server trace layers) use the same 16 bytes as their OTLP trace id, so the
+//! `trace_id` in our logs can be found in the tracing backend by hex-encoding
+//! it, and requests between our services appear within a single trace.
+
+use std::{future, thread};
+
+use anyhow::Context;
+use common::{
+    api::trace::TraceId,
+    env::config::{Config, ConfigErrors, ConfigLayers, ConfigReader},
+    sha256,
+};
+use opentelemetry::{
+    trace::{
+        self as otel, Link, SamplingDecision, SamplingResult, SpanKind,
+        TraceContextExt,
+    },
+    KeyValue,
+};
+use opentelemetry_sdk::{
+    runtime,
+    trace::{Sampler, ShouldSample, Tracer},
+    Resource,
+};
+use tokio::runtime::Handle;
+use tracing_opentelemetry::{OpenTelemetryLayer, OtelData};
+use tracing_subscriber::registry::LookupSpan;
+
+/// The OTLP export layer, before it is filtered.
+pub(crate) type OtlpLayer<S> = OpenTelemetryLayer<S, Tracer>;
+
+/// OTLP export configuration. See the module docs.
+#[derive(Clone, Debug)]
+pub struct OtlpConfig {
+    pub endpoint: String,
+    pub service_name: String,
+    pub sample_ratio: f64,
+}
+
+impl OtlpConfig {
+    /// Read the config from the standard OpenTelemetry env vars. Returns
+    /// [`None`] if `OTEL_EXPORTER_OTLP_ENDPOINT` is not set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let layers = ConfigLayers::new().with_env("OTEL_");
+        if layers.get("exporter_otlp_endpoint").is_none() {
+            return Ok(None);
+        }
+        let (config, _effective) = layers.load::<Self>()?;
+        Ok(Some(config))
+    }
+}
+
+impl Config for OtlpConfig {
+    fn read(r: &mut ConfigReader<'_>) -> Option<Self> {
+        let endpoint = r.required("exporter_otlp_endpoint");
+        let service_name = r.optional_or("service_name", "lexe".to_owned());
+        let sample_ratio = r.optional_or("traces_sampler_arg", 1.0);
+        Some(Self {
+            endpoint: endpoint?,
+            service_name: service_name?,
+            sample_ratio: sample_ratio?,
+        })
+    }
+
+    fn validate(&self, errors: &mut ConfigErrors) {
+        if !(0.0..=1.0).contains(&self.sample_ratio) {
+            errors.push("traces_sampler_arg", "Must be between 0.0 and 1.0");
+        }
+    }
+}
+
+/// Build the OTLP export layer and install its tracer provider globally.
+/// Spans are exported in batches from a task on the exporter runtime.
+pub(crate) fn layer<S>(config: &OtlpConfig) -> anyhow::Result<OtlpLayer<S>>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_endpoint(&config.endpoint);
+    let sampler = TraceIdSampler::new(config.sample_ratio);
+    let resource = Resource::new([KeyValue::new(
+        "service.name",
+        config.service_name.clone(),
+    )]);
+    let trace_config = opentelemetry_sdk::trace::config()
+        .with_sampler(Sampler::ParentBased(Box::new(sampler)))
+        .with_resource(resource);
+
+    // The batch span processor spawns its task onto the current runtime.
+    let exporter_runtime = spawn_exporter_runtime()?;
+    let _guard = exporter_runtime.enter();
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(trace_config)
+        .install_batch(runtime::Tokio)
+        .context("Failed to install OTLP pipeline")?;
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Start a single-threaded tokio runtime on a dedicated thread, which runs
+/// until the process exits.
+fn spawn_exporter_runtime() -> anyhow::Result<Handle> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to build OTLP exporter runtime")?;
+    let handle = runtime.handle().clone();
+    thread::Builder::new()
+        .name("otlp-exporter".to_owned())
+        .spawn(move || runtime.block_on(future::pending::<()>()))
+        .context("Failed to spawn OTLP exporter thread")?;
+    Ok(handle)
+}
+
+/// Flush any buffered spans and shut down the exporter. See
+/// [`crate::shutdown`].
+pub(crate) fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// If the given span is exported and is the root of its trace, use the given
+/// [`TraceId`] as its OTLP trace id. Child spans inherit the trace id of their
+/// parent, so this must be called before any child spans are created.
+pub(crate) fn set_otel_trace_id<S>(
+    subscriber: &S,
+    id: &tracing::span::Id,
+    trace_id: &TraceId,
+) -> anyhow::Result<()>
+where
+    S: for<'a> LookupSpan<'a>,
+{
+    let span_ref = subscriber.span(id).context("No span ref for id")?;
+    let mut extensions = span_ref.extensions_mut();
+    // Only present if the span passed the OTLP layer's filter
+    if let Some(otel_data) = extensions.get_mut::<OtelData>() {
+        if !otel_data.parent_cx.has_active_span() {
+            otel_data.builder.trace_id = Some(otel_trace_id(trace_id));
+        }
+    }
+    Ok(())
+}
+
+/// The OTLP trace id corresponding to a [`TraceId`]: its 16 bytes, as is.
+pub fn otel_trace_id(trace_id: &TraceId) -> otel::TraceId {
+    let bytes = <[u8; 16]>::try_from(trace_id.as_str().as_bytes())
+        .expect("TraceIds are always 16 bytes");
+    otel::TraceId::from_bytes(bytes)
+}
+
+/// Samples a fixed fraction of traces based on their trace id.
+///
+/// We can't use the SDK's `TraceIdRatioBased` sampler because it assumes
+/// uniformly random trace ids, whereas trace ids derived from our alphanumeric
+/// [`TraceId`]s only use a small range of each byte's values. Hashing the
+/// trace id first restores a uniform distribution, while keeping the decision
+/// deterministic so that all of our services agree on it.
+#[derive(Clone, Debug)]
+struct TraceIdSampler {
+    ratio: f64,
+}
+
+impl TraceIdSampler {
+    fn new(ratio: f64) -> Self {
+        Self { ratio }
+    }
+
+    fn sample(&self, trace_id: otel::TraceId) -> bool {
+        if self.ratio >= 1.0 {
+            return true;
+        }
+        let hash = sha256::digest(&trace_id.to_bytes());
+        let prefix = <[u8; 8]>::try_from(&hash.as_slice()[..8])
+            .expect("Slice is 8 bytes");
+        let threshold = (self.ratio * u64::MAX as f64) as u64;
+        u64::from_le_bytes(prefix) < threshold
+    }
+}
+
+impl ShouldSample for TraceIdSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&opentelemetry::Context>,
+        trace_id: otel::TraceId,
+        _name: &str,
+        _span_kind: &SpanKind,
+        _attributes: &[KeyValue],
+        _links: &[Link],
+    ) -> SamplingResult {
+        let decision = if self.sample(trace_id) {
+            SamplingDecision::RecordAndSample
+        } else {
+            SamplingDecision::Drop
+        };
+        let trace_state = parent_context
+            .map(|cx| cx.span().span_context().trace_state().clone())
+            .unwrap_or_default();
+        SamplingResult {
+            decision,
+            attributes: Vec::new(),
+            trace_state,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trace_id_roundtrip() {
+        let trace_id = TraceId::generate();
+        let otel_trace_id = otel_trace_id(&trace_id);
+        assert_eq!(otel_trace_id.to_bytes(), trace_id.as_str().as_bytes());
+    }
+
+    #[test]
+    fn sampler_ratio() {
+        let trace_ids = (0..10_000)
+            .map(|_| otel_trace_id(&TraceId::generate()))
+            .collect::<Vec<_>>();
+        let num_sampled = |ratio| {
+            let sampler = TraceIdSampler::new(ratio);
+            trace_ids.iter().filter(|id| sampler.sample(**id)).count()
+        };
+
+        assert_eq!(num_sampled(0.0), 0);
+        assert_eq!(num_sampled(1.0), 10_000);
+        // Generous bounds; the expected value is 1000
+        let sampled = num_sampled(0.1);
+        assert!((800..1200).contains(&sampled), "{sampled}");
+
+        // Decisions are deterministic
+        let sampler = TraceIdSampler::new(0.5);
+        for id in &trace_ids {
+            assert_eq!(sampler.sample(*id), sampler.sample(*id));
+        }
+    }
+}
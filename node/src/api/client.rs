@@ -7,12 +7,13 @@ use common::{
             BearerAuthBackendApi, NodeBackendApi, NodeLspApi, NodeRunnerApi,
         },
         error::{BackendApiError, LspApiError, RunnerApiError},
-        ports::Ports,
+        ports::{NodeQuiesced, Ports},
         provision::{SealedSeed, SealedSeedId},
         qs::{
             GetByMeasurement, GetByNodePk, GetByUserPk, GetNewPayments,
             GetPaymentByIndex, GetPaymentsByIds,
         },
+        remote_config::SignedRemoteConfig,
         rest::{RequestBuilderExt, RestClient, POST},
         vfs::{VfsDirectory, VfsFile, VfsFileId},
         Empty, NodePk, Scid, User, UserPk,
@@ -56,6 +57,15 @@ impl NodeRunnerApi for RunnerClient {
         // .bearer_auth(&self.auth_token().await?);
         self.rest.send(req).await
     }
+
+    async fn quiesced(
+        &self,
+        data: &NodeQuiesced,
+    ) -> Result<Empty, RunnerApiError> {
+        let runner = &self.runner_url;
+        let req = self.rest.post(format!("{runner}/node/quiesced"), &data);
+        self.rest.send(req).await
+    }
 }
 
 pub(crate) struct LspClient {
@@ -277,6 +287,19 @@ impl NodeBackendApi for BackendClient {
         self.rest.send(req).await
     }
 
+    async fn get_remote_config(
+        &self,
+        auth: BearerAuthToken,
+    ) -> Result<Option<SignedRemoteConfig>, BackendApiError> {
+        let backend = &self.backend_url;
+        let data = Empty {};
+        let req = self
+            .rest
+            .get(format!("{backend}/node/v1/remote_config"), &data)
+            .bearer_auth(&auth);
+        self.rest.send(req).await
+    }
+
     async fn get_payment(
         &self,
         req: GetPaymentByIndex,
@@ -342,6 +365,19 @@ impl NodeBackendApi for BackendClient {
         self.rest.send(req).await
     }
 
+    async fn delete_payments(
+        &self,
+        req: GetPaymentsByIds,
+        auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        let backend = &self.backend_url;
+        let req = self
+            .rest
+            .post(format!("{backend}/node/v1/payments/delete"), &req)
+            .bearer_auth(&auth);
+        self.rest.send(req).await
+    }
+
     async fn get_new_payments(
         &self,
         req: GetNewPayments,
@@ -18,9 +18,10 @@ use common::{
         error::{
             BackendApiError, BackendErrorKind, LspApiError, RunnerApiError,
         },
-        ports::Ports,
+        ports::{NodeQuiesced, Ports},
         provision::{SealedSeed, SealedSeedId},
         qs::{GetNewPayments, GetPaymentByIndex, GetPaymentsByIds},
+        remote_config::SignedRemoteConfig,
         vfs::{VfsDirectory, VfsFile, VfsFileId},
         Empty, NodePk, Scid, User, UserPk,
     },
@@ -132,6 +133,13 @@ impl NodeRunnerApi for MockRunnerClient {
         let _ = self.notifs_tx.try_send(*ports);
         Ok(Empty {})
     }
+
+    async fn quiesced(
+        &self,
+        _data: &NodeQuiesced,
+    ) -> Result<Empty, RunnerApiError> {
+        Ok(Empty {})
+    }
 }
 
 pub(super) struct MockLspClient;
@@ -258,6 +266,7 @@ impl NodeBackendApi for MockBackendClient {
             return Err(BackendApiError {
                 kind: BackendErrorKind::Duplicate,
                 msg: String::new(),
+                data: None,
             });
         }
 
@@ -288,6 +297,7 @@ impl NodeBackendApi for MockBackendClient {
             Err(BackendApiError {
                 kind: BackendErrorKind::NotFound,
                 msg: String::new(),
+                data: None,
             })
         }
     }
@@ -301,6 +311,14 @@ impl NodeBackendApi for MockBackendClient {
         Ok(files_vec)
     }
 
+    async fn get_remote_config(
+        &self,
+        _auth: BearerAuthToken,
+    ) -> Result<Option<SignedRemoteConfig>, BackendApiError> {
+        // Nodes run with the default config in tests.
+        Ok(None)
+    }
+
     async fn get_payment(
         &self,
         req: GetPaymentByIndex,
@@ -331,6 +349,7 @@ impl NodeBackendApi for MockBackendClient {
             return Err(BackendApiError {
                 kind: BackendErrorKind::Duplicate,
                 msg: String::new(),
+                data: None,
             });
         }
         let maybe_payment = locked_payments.insert(key, payment);
@@ -383,6 +402,19 @@ impl NodeBackendApi for MockBackendClient {
         Ok(payments)
     }
 
+    async fn delete_payments(
+        &self,
+        req: GetPaymentsByIds,
+        _auth: BearerAuthToken,
+    ) -> Result<Empty, BackendApiError> {
+        let ids = req.ids.into_iter().collect::<HashSet<_>>();
+        self.payments
+            .lock()
+            .unwrap()
+            .retain(|_k, p| !ids.contains(&p.id));
+        Ok(Empty {})
+    }
+
     async fn get_new_payments(
         &self,
         req: GetNewPayments,
@@ -6,12 +6,16 @@
 
 use std::collections::{btree_map::Entry, BTreeMap};
 
-use anyhow::ensure;
+use anyhow::{ensure, Context};
 use common::{
-    api::UserPk,
+    api::{
+        release_manifest::{self, SignedReleaseManifest},
+        UserPk,
+    },
     const_assert,
     constants::{YANKED_NODE_MEASUREMENTS, YANKED_NODE_VERSIONS},
     enclave::Measurement,
+    env::DeployEnv,
 };
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
@@ -49,6 +53,11 @@ use crate::SEMVER_VERSION;
 pub(crate) struct ApprovedVersions {
     /// List of currently-approved versions, along with their measurements.
     pub(crate) approved: BTreeMap<semver::Version, Measurement>,
+    /// Whether the user opted into release manifests, by provisioning with
+    /// one. Once set, every later approval requires a valid manifest too, so
+    /// that Lexe can't silently drop the manifest from a provision request.
+    #[serde(default)]
+    pub(crate) require_manifest: bool,
 }
 
 // Implementation assumption
@@ -61,7 +70,10 @@ impl ApprovedVersions {
     /// Get a new [`ApprovedVersions`] which is completely empty.
     pub(crate) fn new() -> Self {
         let approved = BTreeMap::new();
-        Self { approved }
+        Self {
+            approved,
+            require_manifest: false,
+        }
     }
 
     /// Approve the current version/measurement, and revoke any sufficiently old
@@ -71,14 +83,41 @@ impl ApprovedVersions {
     /// (and thus whether it should be (re)persisted), along with the version
     /// and measurement of any versions which were revoked.
     ///
-    /// Errors if the current version is too old to be approved, or if
-    /// [`ApprovedVersions`] contains inconsistent data.
+    /// If a [`SignedReleaseManifest`] is given, the current version is only
+    /// approved if the manifest attests to it, and the user's opt-in is
+    /// recorded. A manifest is required if the user opted in earlier, or if
+    /// [`release_manifest::manifest_required`] in this [`DeployEnv`].
+    ///
+    /// Errors if the current version is too old to be approved, if a required
+    /// release manifest is missing or invalid, or if [`ApprovedVersions`]
+    /// contains inconsistent data.
     pub(crate) fn approve_and_revoke(
         &mut self,
         user_pk: &UserPk,
         cur_measurement: Measurement,
+        maybe_manifest: Option<&SignedReleaseManifest>,
+        deploy_env: DeployEnv,
     ) -> anyhow::Result<(bool, Vec<(semver::Version, Measurement)>)> {
         let mut updated = false;
+
+        // Verify the release manifest on every approval, even if the current
+        // version was approved before.
+        match maybe_manifest {
+            Some(manifest) => {
+                check_release_manifest(manifest, deploy_env, cur_measurement)
+                    .context("Release manifest check failed")?;
+                if !self.require_manifest {
+                    info!(%user_pk, "Opting into release manifests");
+                    self.require_manifest = true;
+                    updated = true;
+                }
+            }
+            None => ensure!(
+                !self.require_manifest
+                    && !release_manifest::manifest_required(deploy_env),
+                "A release manifest is required to approve this version"
+            ),
+        }
         let cur_version =
             semver::Version::parse(SEMVER_VERSION).expect("Checked in tests");
 
@@ -151,6 +190,30 @@ impl ApprovedVersions {
     }
 }
 
+/// Check that the given [`SignedReleaseManifest`] was signed by enough trusted
+/// builders, and that it attests to the current version and measurement. To
+/// be called during provisioning, before approving the current version.
+pub(crate) fn check_release_manifest(
+    manifest: &SignedReleaseManifest,
+    deploy_env: DeployEnv,
+    cur_measurement: Measurement,
+) -> anyhow::Result<()> {
+    let cur_version =
+        semver::Version::parse(SEMVER_VERSION).expect("Checked in tests");
+    let manifest = manifest
+        .verify(deploy_env)
+        .context("Invalid release manifest")?;
+    let expected = manifest.measurement(&cur_version).with_context(|| {
+        format!("Release manifest is missing {cur_version}")
+    })?;
+    ensure!(
+        expected == cur_measurement,
+        "Release manifest measurement mismatch for {cur_version}: \
+        expected {expected}, current {cur_measurement}"
+    );
+    Ok(())
+}
+
 #[cfg(test)]
 mod arbitrary_impl {
     use common::test_utils::arbitrary;
@@ -174,7 +237,12 @@ mod arbitrary_impl {
                 size_range.clone(),
             );
 
-            any_approved.prop_map(|approved| Self { approved }).boxed()
+            (any_approved, any::<bool>())
+                .prop_map(|(approved, require_manifest)| Self {
+                    approved,
+                    require_manifest,
+                })
+                .boxed()
         }
     }
 }
@@ -198,6 +266,110 @@ mod test {
         }
     }
 
+    #[test]
+    fn release_manifest_must_match_current() {
+        use std::collections::BTreeMap;
+
+        use common::{
+            api::release_manifest::{
+                ReleaseManifest, DEV_MANIFEST_SIGNER_SEEDS,
+            },
+            ed25519,
+        };
+
+        let signed_manifest = |measurement| {
+            let version = semver::Version::parse(SEMVER_VERSION).unwrap();
+            let manifest = ReleaseManifest {
+                releases: BTreeMap::from_iter([(version, measurement)]),
+            };
+            let signatures = DEV_MANIFEST_SIGNER_SEEDS
+                .iter()
+                .map(|seed| {
+                    let key_pair = ed25519::KeyPair::from_seed(seed);
+                    manifest.sign(&key_pair).unwrap()
+                })
+                .collect();
+            SignedReleaseManifest { signatures }
+        };
+
+        let cur_measurement = Measurement::new([0x69; 32]);
+        let manifest = signed_manifest(cur_measurement);
+        check_release_manifest(&manifest, DeployEnv::Dev, cur_measurement)
+            .unwrap();
+        check_release_manifest(&manifest, DeployEnv::Prod, cur_measurement)
+            .unwrap_err();
+
+        let other_measurement = Measurement::new([0x42; 32]);
+        check_release_manifest(&manifest, DeployEnv::Dev, other_measurement)
+            .unwrap_err();
+
+        let empty = SignedReleaseManifest { signatures: vec![] };
+        check_release_manifest(&empty, DeployEnv::Dev, cur_measurement)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn release_manifest_opt_in_is_sticky() {
+        use std::collections::BTreeMap;
+
+        use common::{
+            api::release_manifest::{
+                ReleaseManifest, DEV_MANIFEST_SIGNER_SEEDS,
+            },
+            ed25519,
+        };
+
+        let user_pk = UserPk::new([1; 32]);
+        let cur_measurement = Measurement::new([0x69; 32]);
+        let version = semver::Version::parse(SEMVER_VERSION).unwrap();
+        let manifest = ReleaseManifest {
+            releases: BTreeMap::from_iter([(version, cur_measurement)]),
+        };
+        let signatures = DEV_MANIFEST_SIGNER_SEEDS
+            .iter()
+            .map(|seed| {
+                let key_pair = ed25519::KeyPair::from_seed(seed);
+                manifest.sign(&key_pair).unwrap()
+            })
+            .collect();
+        let manifest = SignedReleaseManifest { signatures };
+
+        // Without opting in, no manifest is needed in dev.
+        let mut approved = ApprovedVersions::new();
+        let (updated, _) = approved
+            .approve_and_revoke(&user_pk, cur_measurement, None, DeployEnv::Dev)
+            .unwrap();
+        assert!(updated);
+        assert!(!approved.require_manifest);
+
+        // Approving with a manifest records the opt-in, even if the current
+        // version was already approved.
+        let (updated, _) = approved
+            .approve_and_revoke(
+                &user_pk,
+                cur_measurement,
+                Some(&manifest),
+                DeployEnv::Dev,
+            )
+            .unwrap();
+        assert!(updated);
+        assert!(approved.require_manifest);
+
+        // After opting in, every approval requires a valid manifest.
+        approved
+            .approve_and_revoke(&user_pk, cur_measurement, None, DeployEnv::Dev)
+            .unwrap_err();
+        let empty = SignedReleaseManifest { signatures: vec![] };
+        approved
+            .approve_and_revoke(
+                &user_pk,
+                cur_measurement,
+                Some(&empty),
+                DeployEnv::Dev,
+            )
+            .unwrap_err();
+    }
+
     /// Nonsensical for the current version to be in [`YANKED_NODE_VERSIONS`].
     /// Yanking the current version should be accompanied with a version bump.
     #[test]
@@ -0,0 +1,142 @@
+//! Periodic read-back verification of the user's GDrive backups.
+//!
+//! We write to GDrive on every channel manager and monitor persist, but never
+//! read back from it until the node restarts. Without this task, a corrupted
+//! or deleted backup would only be discovered when the user needs to restore
+//! from it, when it's too late.
+//!
+//! The same goes for the user running out of Drive storage, after which every
+//! backup fails, so this task also warns when the user's quota is nearly full,
+//! and purges files which have been in the GVFS trash past their retention
+//! period.
+
+use std::{sync::Arc, time::Duration};
+
+use common::{
+    cli::Network, iter::IteratorExt, shutdown::ShutdownChannel, task::LxTask,
+};
+use lexe_ln::keys_manager::LexeKeysManager;
+use tokio::time::{self, Instant, MissedTickBehavior};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    metrics::{self, NodeCounters},
+    persister::{BackupReport, NodePersister},
+};
+
+/// Configuration for the backup verifier task.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct BackupVerifierConfig {
+    /// How long to wait after startup before the first verification, so that
+    /// we don't compete with sync for GDrive API quota.
+    pub initial_delay: Duration,
+    /// How often to verify the backups after the first verification.
+    pub interval: Duration,
+}
+
+impl Default for BackupVerifierConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(2 * 60),
+            interval: Duration::from_secs(6 * 60 * 60),
+        }
+    }
+}
+
+/// Spawns a task which periodically calls
+/// [`NodePersister::verify_gdrive_backups`]. Each file which fails
+/// verification is logged at ERROR and counted in
+/// [`NodeCounters::backup_verification_failures`]. A nearly full Drive quota
+/// is logged at WARN and counted in [`NodeCounters::gdrive_nearly_full`].
+/// Also purges old files from the GDrive trash with
+/// [`NodePersister::purge_gdrive_trash`].
+pub(crate) fn spawn_backup_verifier_task(
+    config: BackupVerifierConfig,
+    persister: Arc<NodePersister>,
+    keys_manager: Arc<LexeKeysManager>,
+    network: Network,
+    counters: Arc<NodeCounters>,
+    mut shutdown: ShutdownChannel,
+) -> LxTask<()> {
+    LxTask::spawn_named("backup verifier", async move {
+        let start = Instant::now() + config.initial_delay;
+        let mut timer = time::interval_at(start, config.interval);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = timer.tick() => (),
+                () = shutdown.recv() => break,
+            }
+
+            let try_verify =
+                persister.verify_gdrive_backups(&keys_manager, network);
+            let result = tokio::select! {
+                result = try_verify => result,
+                () = shutdown.recv() => break,
+            };
+
+            match result {
+                Ok(BackupReport {
+                    num_verified,
+                    failures,
+                }) if failures.is_empty() =>
+                    info!("Verified {num_verified} GDrive backup files"),
+                Ok(BackupReport { failures, .. }) => {
+                    let num_failures = failures.len() as u64;
+                    metrics::add(
+                        &counters.backup_verification_failures,
+                        num_failures,
+                    );
+                    let failures = failures.iter().join_display("; ");
+                    error!(
+                        "{num_failures} GDrive backup files failed \
+                        verification: {failures}"
+                    );
+                }
+                // Fetch errors are usually transient; try again next time.
+                Err(e) => warn!("Couldn't verify GDrive backups: {e:#}"),
+            }
+
+            let try_status = persister.gdrive_storage_status();
+            let result = tokio::select! {
+                result = try_status => result,
+                () = shutdown.recv() => break,
+            };
+
+            match result {
+                Ok(status) if status.is_nearly_full() => {
+                    metrics::add(&counters.gdrive_nearly_full, 1);
+                    let used = status.quota_used_bytes;
+                    let limit = status.quota_limit_bytes.unwrap_or_default();
+                    let lexe = status.lexe_bytes;
+                    warn!(
+                        "User's GDrive is nearly full; backups will fail once \
+                        it is: {used}/{limit} bytes used ({lexe} by Lexe)"
+                    );
+                }
+                Ok(status) => debug!(
+                    lexe_bytes = status.lexe_bytes,
+                    quota_remaining = ?status.quota_remaining_bytes(),
+                    "Checked GDrive storage"
+                ),
+                Err(e) => warn!("Couldn't check GDrive storage: {e:#}"),
+            }
+
+            let try_purge = persister.purge_gdrive_trash();
+            let result = tokio::select! {
+                result = try_purge => result,
+                () = shutdown.recv() => break,
+            };
+
+            match result {
+                Ok(0) => (),
+                Ok(num_purged) =>
+                    info!("Purged {num_purged} files from the GDrive trash"),
+                Err(e) => warn!("Couldn't purge GDrive trash: {e:#}"),
+            }
+        }
+
+        info!("backup verifier task shutting down");
+    })
+}
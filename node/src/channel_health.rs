@@ -0,0 +1,83 @@
+//! Periodic sampling of our channels for stale channel detection.
+//!
+//! See [`lexe_ln::channel_health`] for the analysis; this task just keeps the
+//! node's [`ChannelActivityLog`] up to date and persisted, since user nodes
+//! only run for short periods and the activity we care about spans months.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{shutdown::ShutdownChannel, task::LxTask, time::TimestampMs};
+use lexe_ln::channel_health::{ChannelActivityLog, ChannelSample};
+use tokio::time::{self, Instant, MissedTickBehavior};
+use tracing::{info, warn};
+
+use crate::{channel_manager::NodeChannelManager, persister::NodePersister};
+
+/// How long after startup we've had a chance to reconnect to our channel
+/// peers. Samples taken before then don't count unusable channels as offline.
+const PEERS_SETTLED_DELAY: Duration = Duration::from_secs(60);
+/// How often to sample our channels after the peers have settled.
+const INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Spawns a task which loads the persisted [`ChannelActivityLog`] into
+/// `activity_log`, samples our channels into it immediately (since the node
+/// may not run for long), then again once our peers have settled and
+/// periodically after that, persisting any changes.
+pub(crate) fn spawn_channel_health_task(
+    persister: Arc<NodePersister>,
+    channel_manager: NodeChannelManager,
+    activity_log: Arc<Mutex<ChannelActivityLog>>,
+    mut shutdown: ShutdownChannel,
+) -> LxTask<()> {
+    LxTask::spawn_named("channel health", async move {
+        let persisted = tokio::select! {
+            res = persister.read_channel_activity() => res,
+            () = shutdown.recv() => return,
+        };
+        match persisted {
+            Ok(persisted) => *activity_log.lock().unwrap() = persisted,
+            // Don't overwrite the persisted log with one that's missing
+            // history; we'll try again next time the node starts.
+            Err(e) => {
+                warn!("Couldn't read channel activity log: {e:#}");
+                return;
+            }
+        }
+
+        let settled_at = Instant::now() + PEERS_SETTLED_DELAY;
+        let mut timer = time::interval_at(settled_at, INTERVAL);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut peers_settled = false;
+
+        loop {
+            let samples =
+                lexe_ln::command::list_channels(channel_manager.clone())
+                    .iter()
+                    .map(ChannelSample::from)
+                    .collect::<Vec<_>>();
+            let updated = {
+                let mut lock = activity_log.lock().unwrap();
+                lock.update(&samples, peers_settled, TimestampMs::now())
+                    .then(|| lock.clone())
+            };
+
+            if let Some(updated) = updated {
+                if let Err(e) =
+                    persister.persist_channel_activity(&updated).await
+                {
+                    warn!("Couldn't persist channel activity log: {e:#}");
+                }
+            }
+
+            tokio::select! {
+                _ = timer.tick() => peers_settled = true,
+                () = shutdown.recv() => break,
+            }
+        }
+
+        info!("channel health task shutting down");
+    })
+}
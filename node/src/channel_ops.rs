@@ -0,0 +1,280 @@
+//! Progress tracking for channel opens and closes started by the app.
+//!
+//! Opening or closing a channel takes anywhere from seconds to hours, so the
+//! `open_channel` and `close_channel` endpoints return a [`ChannelOperationId`]
+//! right away. The app then polls `channel_operation` to show the user where
+//! the operation is at, which is derived from the channel's current
+//! [`ChannelDetails`] plus the channel events we've seen for it.
+//!
+//! Operations are only tracked in memory, since user nodes are short-lived and
+//! the app can always fall back to `list_channels` after a restart.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use common::{
+    api::command::{
+        ChannelOperation, ChannelOperationId, ChannelOperationKind,
+        ChannelOperationState,
+    },
+    ln::{channel::ChannelId, hashes::LxTxid},
+    rng::{Crng, RngExt},
+    time::TimestampMs,
+};
+use lightning::{events::ClosureReason, ln::channelmanager::ChannelDetails};
+
+/// The max # of operations we keep track of. Once exceeded, the oldest
+/// finished operations are forgotten first.
+const MAX_OPERATIONS: usize = 64;
+
+/// The channel opens and closes started by the app.
+#[derive(Default)]
+pub(crate) struct ChannelOperations {
+    inner: Mutex<HashMap<ChannelOperationId, Operation>>,
+}
+
+struct Operation {
+    kind: ChannelOperationKind,
+    /// Used to match the operation with its [`ChannelDetails`] and events.
+    user_channel_id: u128,
+    channel_id: Option<ChannelId>,
+    started_at: TimestampMs,
+    /// Set once we've seen the `ChannelPending` event for an open.
+    funding_txid: Option<LxTxid>,
+    /// Set once the operation has finished, e.g. the channel has closed.
+    outcome: Option<ChannelOperationState>,
+}
+
+impl ChannelOperations {
+    /// Starts tracking a new operation on the channel with `user_channel_id`.
+    pub(crate) fn start(
+        &self,
+        rng: &mut impl Crng,
+        kind: ChannelOperationKind,
+        user_channel_id: u128,
+        channel_id: Option<ChannelId>,
+    ) -> ChannelOperationId {
+        let id = ChannelOperationId {
+            id: rng.gen_bytes(),
+        };
+        let operation = Operation {
+            kind,
+            user_channel_id,
+            channel_id,
+            started_at: TimestampMs::now(),
+            funding_txid: None,
+            outcome: None,
+        };
+
+        let mut operations = self.inner.lock().unwrap();
+        if operations.len() >= MAX_OPERATIONS {
+            // Forget the oldest operation, preferring finished ones.
+            let oldest = operations
+                .iter()
+                .min_by_key(|(_, op)| (op.outcome.is_none(), op.started_at))
+                .map(|(id, _)| *id);
+            if let Some(oldest) = oldest {
+                operations.remove(&oldest);
+            }
+        }
+        operations.insert(id, operation);
+        id
+    }
+
+    /// Marks an operation as failed, e.g. if the open couldn't be started.
+    pub(crate) fn fail(&self, id: &ChannelOperationId, error: String) {
+        let mut operations = self.inner.lock().unwrap();
+        if let Some(op) = operations.get_mut(id) {
+            op.outcome = Some(ChannelOperationState::Failed { error });
+        }
+    }
+
+    /// Handles a `ChannelPending` event: the funding tx has been broadcast.
+    pub(crate) fn channel_pending(
+        &self,
+        user_channel_id: u128,
+        channel_id: ChannelId,
+        funding_txid: LxTxid,
+    ) {
+        let mut operations = self.inner.lock().unwrap();
+        let matching = operations.values_mut().filter(|op| {
+            op.user_channel_id == user_channel_id
+                && op.kind == ChannelOperationKind::Open
+        });
+        for op in matching {
+            op.channel_id = Some(channel_id);
+            op.funding_txid = Some(funding_txid);
+        }
+    }
+
+    /// Handles a `ChannelReady` event: the channel is now usable.
+    pub(crate) fn channel_ready(&self, user_channel_id: u128) {
+        let mut operations = self.inner.lock().unwrap();
+        let matching = operations.values_mut().filter(|op| {
+            op.user_channel_id == user_channel_id
+                && op.kind == ChannelOperationKind::Open
+                && op.outcome.is_none()
+        });
+        for op in matching {
+            op.outcome = Some(ChannelOperationState::Active);
+        }
+    }
+
+    /// Handles a `ChannelClosed` event.
+    pub(crate) fn channel_closed(
+        &self,
+        user_channel_id: u128,
+        channel_id: ChannelId,
+        reason: &ClosureReason,
+    ) {
+        let mut operations = self.inner.lock().unwrap();
+        let matching = operations.values_mut().filter(|op| {
+            op.user_channel_id == user_channel_id && op.outcome.is_none()
+        });
+        for op in matching {
+            op.channel_id = Some(channel_id);
+            op.outcome = Some(match op.kind {
+                ChannelOperationKind::Open => ChannelOperationState::Failed {
+                    error: format!("Channel closed while opening: {reason:?}"),
+                },
+                ChannelOperationKind::Close => ChannelOperationState::Closed {
+                    reason: format!("{reason:?}"),
+                },
+            });
+        }
+    }
+
+    /// Reports the current progress of an operation, given our current
+    /// `channels`. Returns [`None`] if the operation is unknown.
+    pub(crate) fn get(
+        &self,
+        id: &ChannelOperationId,
+        channels: &[ChannelDetails],
+    ) -> Option<ChannelOperation> {
+        let operations = self.inner.lock().unwrap();
+        let op = operations.get(id)?;
+        let channel = channels
+            .iter()
+            .find(|c| c.user_channel_id == op.user_channel_id);
+
+        let channel_id = op
+            .channel_id
+            .or_else(|| channel.map(|c| ChannelId(c.channel_id)));
+        Some(ChannelOperation {
+            kind: op.kind,
+            channel_id,
+            state: op.state(channel),
+            started_at: op.started_at,
+        })
+    }
+}
+
+impl Operation {
+    fn state(&self, channel: Option<&ChannelDetails>) -> ChannelOperationState {
+        if let Some(outcome) = &self.outcome {
+            return outcome.clone();
+        }
+
+        match (self.kind, channel) {
+            (ChannelOperationKind::Open, Some(channel)) => {
+                let confirmations = channel.confirmations.unwrap_or(0);
+                if channel.is_channel_ready {
+                    ChannelOperationState::Active
+                } else if confirmations > 0 {
+                    ChannelOperationState::Confirming {
+                        confirmations,
+                        required: channel
+                            .confirmations_required
+                            .unwrap_or(confirmations),
+                    }
+                } else if let Some(funding_txid) = self.funding_txid {
+                    ChannelOperationState::FundingBroadcast { funding_txid }
+                } else {
+                    ChannelOperationState::Negotiating
+                }
+            }
+            // Still connecting to the LSP, or LDK hasn't created it yet.
+            (ChannelOperationKind::Open, None) =>
+                ChannelOperationState::Negotiating,
+            // Until the `ChannelClosed` event has been handled, the close is
+            // still in progress, even if the channel is already gone.
+            (ChannelOperationKind::Close, _) => ChannelOperationState::Closing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use common::rng::WeakRng;
+
+    use super::*;
+
+    #[test]
+    fn open_then_close() {
+        let mut rng = WeakRng::from_u64(20240806);
+        let ops = ChannelOperations::default();
+        let channel_id = ChannelId([1; 32]);
+        let txid = LxTxid(bitcoin::Txid::from_inner([2; 32]));
+        let reason = ClosureReason::CooperativeClosure;
+        let state = |id| ops.get(&id, &[]).unwrap().state;
+
+        let open = ops.start(&mut rng, ChannelOperationKind::Open, 7, None);
+        assert_eq!(state(open), ChannelOperationState::Negotiating);
+
+        // Events for other channels are ignored.
+        ops.channel_pending(8, ChannelId([3; 32]), txid);
+        assert_eq!(state(open), ChannelOperationState::Negotiating);
+
+        ops.channel_pending(7, channel_id, txid);
+        let op = ops.get(&open, &[]).unwrap();
+        assert_eq!(op.channel_id, Some(channel_id));
+        assert_eq!(
+            op.state,
+            ChannelOperationState::FundingBroadcast { funding_txid: txid }
+        );
+
+        let close = ops.start(
+            &mut rng,
+            ChannelOperationKind::Close,
+            7,
+            Some(channel_id),
+        );
+        assert_eq!(state(close), ChannelOperationState::Closing);
+
+        ops.channel_ready(7);
+        assert_eq!(state(open), ChannelOperationState::Active);
+
+        // Closing the channel only affects unfinished operations.
+        ops.channel_closed(7, channel_id, &reason);
+        assert_eq!(state(open), ChannelOperationState::Active);
+        assert!(matches!(state(close), ChannelOperationState::Closed { .. }));
+
+        // An open which never became ready has failed.
+        let failed = ops.start(&mut rng, ChannelOperationKind::Open, 9, None);
+        ops.channel_closed(9, ChannelId([4; 32]), &reason);
+        assert!(matches!(
+            state(failed),
+            ChannelOperationState::Failed { .. }
+        ));
+
+        let unknown = ChannelOperationId { id: [0; 16] };
+        assert!(ops.get(&unknown, &[]).is_none());
+    }
+
+    #[test]
+    fn forgets_oldest_finished_first() {
+        let mut rng = WeakRng::from_u64(20240806);
+        let ops = ChannelOperations::default();
+
+        let first = ops.start(&mut rng, ChannelOperationKind::Open, 0, None);
+        let second = ops.start(&mut rng, ChannelOperationKind::Open, 1, None);
+        ops.fail(&second, "oops".to_owned());
+        for i in 2..MAX_OPERATIONS {
+            ops.start(&mut rng, ChannelOperationKind::Open, i as u128, None);
+        }
+
+        ops.start(&mut rng, ChannelOperationKind::Open, 999, None);
+        assert!(ops.get(&first, &[]).is_some());
+        assert!(ops.get(&second, &[]).is_none());
+    }
+}
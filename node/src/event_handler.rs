@@ -3,20 +3,30 @@ use std::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context};
 use common::{
-    api::NodePk, cli::LspInfo, hex, ln::channel::ChannelId,
-    shutdown::ShutdownChannel, task::LxTask, test_event::TestEvent,
+    api::NodePk,
+    cli::LspInfo,
+    hex,
+    ln::{channel::ChannelId, hashes::LxTxid},
+    shutdown::ShutdownChannel,
+    task::LxTask,
+    test_event::TestEvent,
+    time::TimestampMs,
 };
 use lexe_ln::{
     alias::NetworkGraphType,
     esplora::LexeEsplora,
-    event::{self, EventHandleError},
+    event::{
+        self, DeadLetterConfig, DeadLetterQueue, DeadLetterVerdict,
+        EventHandleError,
+    },
     keys_manager::LexeKeysManager,
     payments::outbound::LxOutboundPaymentFailure,
+    route::{self, RouteBlacklist},
     test_event::TestEventSender,
     wallet::LexeWallet,
 };
@@ -24,14 +34,16 @@ use lightning::{
     events::{Event, EventHandler, PaymentFailureReason},
     routing::gossip::NodeId,
 };
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     alias::NodePaymentsManagerType, channel_manager::NodeChannelManager,
+    channel_ops::ChannelOperations, persister::NodePersister,
 };
 
 // We pub(crate) all the fields to prevent having to specify each field two more
 // times in Self::new parameters and in struct init syntax.
+#[derive(Clone)]
 pub struct NodeEventHandler {
     pub(crate) lsp: LspInfo,
     pub(crate) wallet: LexeWallet,
@@ -39,7 +51,11 @@ pub struct NodeEventHandler {
     pub(crate) keys_manager: Arc<LexeKeysManager>,
     pub(crate) esplora: Arc<LexeEsplora>,
     pub(crate) network_graph: Arc<NetworkGraphType>,
+    pub(crate) route_blacklist: Arc<RouteBlacklist>,
     pub(crate) payments_manager: NodePaymentsManagerType,
+    pub(crate) persister: Arc<NodePersister>,
+    pub(crate) channel_ops: Arc<ChannelOperations>,
+    pub(crate) dead_letters: Arc<tokio::sync::Mutex<DeadLetterQueue>>,
     pub(crate) fatal_event: Arc<AtomicBool>,
     pub(crate) test_event_tx: TestEventSender,
     pub(crate) shutdown: ShutdownChannel,
@@ -86,8 +102,12 @@ impl EventHandler for NodeEventHandler {
         let channel_manager = self.channel_manager.clone();
         let esplora = self.esplora.clone();
         let network_graph = self.network_graph.clone();
+        let route_blacklist = self.route_blacklist.clone();
         let keys_manager = self.keys_manager.clone();
         let payments_manager = self.payments_manager.clone();
+        let persister = self.persister.clone();
+        let channel_ops = self.channel_ops.clone();
+        let dead_letters = self.dead_letters.clone();
         let fatal_event = self.fatal_event.clone();
         let test_event_tx = self.test_event_tx.clone();
         let shutdown = self.shutdown.clone();
@@ -114,8 +134,12 @@ impl EventHandler for NodeEventHandler {
                 &channel_manager,
                 &esplora,
                 &network_graph,
+                &route_blacklist,
                 keys_manager.as_ref(),
                 &payments_manager,
+                persister.as_ref(),
+                channel_ops.as_ref(),
+                dead_letters.as_ref(),
                 fatal_event.as_ref(),
                 &test_event_tx,
                 &shutdown,
@@ -134,22 +158,31 @@ pub(crate) async fn handle_event(
     channel_manager: &NodeChannelManager,
     esplora: &LexeEsplora,
     network_graph: &NetworkGraphType,
+    route_blacklist: &RouteBlacklist,
     keys_manager: &LexeKeysManager,
     payments_manager: &NodePaymentsManagerType,
+    persister: &NodePersister,
+    channel_ops: &ChannelOperations,
+    dead_letters: &tokio::sync::Mutex<DeadLetterQueue>,
     fatal_event: &AtomicBool,
     test_event_tx: &TestEventSender,
     shutdown: &ShutdownChannel,
     event: Event,
 ) {
     let event_name = lexe_ln::event::get_event_name(&event);
+    let event_id = event::event_id(&event);
+    // Keep a copy in case handling fails and the event must be quarantined.
+    let event_copy = event.clone();
     let handle_event_res = handle_event_fallible(
         lsp,
         wallet,
         channel_manager,
         esplora,
         network_graph,
+        route_blacklist,
         keys_manager,
         payments_manager,
+        channel_ops,
         test_event_tx,
         shutdown,
         event,
@@ -162,10 +195,55 @@ pub(crate) async fn handle_event(
             warn!("Tolerable error handling {event_name}: {e:#}"),
         Err(EventHandleError::Fatal(e)) => {
             error!("Fatal error handling {event_name}: {e:#}");
-            shutdown.send();
-            // Notify our BGP that a fatal event handling error has occurred and
-            // that the current batch of events MUST not be lost.
-            fatal_event.store(true, Ordering::Release);
+
+            let verdict =
+                record_dead_letter(persister, dead_letters, &event_copy).await;
+            match verdict {
+                DeadLetterVerdict::Retry => {
+                    shutdown.send();
+                    // Notify our BGP that a fatal event handling error has
+                    // occurred and that the current batch of events MUST not
+                    // be lost.
+                    fatal_event.store(true, Ordering::Release);
+                }
+                DeadLetterVerdict::Quarantine => error!(
+                    "Quarantined {event_name} after repeated failures; skipping"
+                ),
+            }
+            return;
+        }
+    }
+
+    // The event won't be replayed, so stop tracking any earlier failures.
+    let mut locked_dead_letters = dead_letters.lock().await;
+    if locked_dead_letters.record_handled(&event_id) {
+        info!("{event_name} recovered after earlier failures");
+        if let Err(e) =
+            persister.persist_dead_letters(&locked_dead_letters).await
+        {
+            warn!("Failed to persist dead letter queue: {e:#}");
+        }
+    }
+}
+
+/// Records a fatal failure to handle `event` in the [`DeadLetterQueue`].
+async fn record_dead_letter(
+    persister: &NodePersister,
+    dead_letters: &tokio::sync::Mutex<DeadLetterQueue>,
+    event: &Event,
+) -> DeadLetterVerdict {
+    let config = DeadLetterConfig::default();
+    let mut locked_dead_letters = dead_letters.lock().await;
+    let verdict =
+        locked_dead_letters.record_failure(&config, event, TimestampMs::now());
+
+    match persister.persist_dead_letters(&locked_dead_letters).await {
+        Ok(()) => verdict,
+        Err(e) => {
+            // If the quarantine copy wasn't saved, skipping the event would
+            // lose it, so retry instead.
+            error!("Failed to persist dead letter queue: {e:#}");
+            DeadLetterVerdict::Retry
         }
     }
 }
@@ -176,8 +254,10 @@ async fn handle_event_fallible(
     channel_manager: &NodeChannelManager,
     esplora: &LexeEsplora,
     network_graph: &NetworkGraphType,
+    route_blacklist: &RouteBlacklist,
     keys_manager: &LexeKeysManager,
     payments_manager: &NodePaymentsManagerType,
+    channel_ops: &ChannelOperations,
     test_event_tx: &TestEventSender,
     shutdown: &ShutdownChannel,
     event: Event,
@@ -257,20 +337,26 @@ async fn handle_event_fallible(
             .map_err(EventHandleError::Fatal)?;
         }
         Event::ChannelPending {
-            channel_id: _,
-            user_channel_id: _,
+            channel_id,
+            user_channel_id,
             former_temporary_channel_id: _,
             counterparty_node_id: _,
-            funding_txo: _,
+            funding_txo,
         } => {
+            channel_ops.channel_pending(
+                user_channel_id,
+                ChannelId(channel_id),
+                LxTxid(funding_txo.txid),
+            );
             test_event_tx.send(TestEvent::ChannelPending);
         }
         Event::ChannelReady {
             channel_id: _,
-            user_channel_id: _,
+            user_channel_id,
             counterparty_node_id: _,
             channel_type: _,
         } => {
+            channel_ops.channel_ready(user_channel_id);
             test_event_tx.send(TestEvent::ChannelReady);
         }
         Event::PaymentClaimable {
@@ -339,7 +425,24 @@ async fn handle_event_fallible(
                 .map_err(EventHandleError::Fatal)?;
         }
         Event::PaymentPathSuccessful { .. } => {}
-        Event::PaymentPathFailed { .. } => {}
+        Event::PaymentPathFailed {
+            payment_failed_permanently,
+            short_channel_id,
+            ..
+        } => {
+            // If the failure was permanent, the recipient rejected the payment
+            // and the intermediate channels aren't to blame. Our own channels
+            // are never blacklisted, since we may have no others.
+            if let (false, Some(scid)) =
+                (payment_failed_permanently, short_channel_id)
+            {
+                let our_channels = channel_manager.list_channels();
+                if !route::is_our_channel(scid, &our_channels) {
+                    debug!("Blacklisting channel {scid} after path failure");
+                    route_blacklist.record_failure(scid, Instant::now());
+                }
+            }
+        }
         Event::ProbeSuccessful { .. } => {}
         Event::ProbeFailed { .. } => {}
         Event::PaymentForwarded {
@@ -444,10 +547,11 @@ async fn handle_event_fallible(
         Event::ChannelClosed {
             channel_id,
             reason,
-            user_channel_id: _,
+            user_channel_id,
         } => {
             let channel_id = ChannelId(channel_id);
             info!(%channel_id, ?reason, "Channel is being closed");
+            channel_ops.channel_closed(user_channel_id, channel_id, &reason);
             test_event_tx.send(TestEvent::ChannelClosed);
         }
         Event::DiscardFunding { .. } => {
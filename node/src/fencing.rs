@@ -0,0 +1,163 @@
+//! Fencing to prevent two instances of the same user node from running at once.
+//!
+//! When the runner migrates a user node to another meganode, the old instance
+//! quiesces and releases its fencing token before the new instance starts. But
+//! if the old instance is unreachable (e.g. a network partition) the scheduler
+//! may start the new instance anyway, and two nodes persisting to the same VFS
+//! would corrupt the user's channel state.
+//!
+//! Each node instance therefore claims a new fencing epoch in the VFS at boot.
+//! Once a node observes that the persisted token isn't its own, it has been
+//! superseded: all further persists are refused, and it shuts down.
+//!
+//! The VFS has no compare-and-swap, so two instances booting at once may both
+//! claim the same epoch. The token therefore also records a random id of the
+//! instance which wrote it, and each instance re-reads the token right before
+//! every channel manager and channel monitor persist, so that the instance
+//! which lost the race is fenced off before it can write any channel state.
+//!
+//! An instance which detects another writer modifying its GDrive files (see
+//! [`GoogleVfs::has_conflict`]) also fences itself off, in case it is a stale
+//! instance which somehow wasn't fenced off by the token.
+//!
+//! [`GoogleVfs::has_conflict`]: gdrive::GoogleVfs::has_conflict
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::ensure;
+use common::{shutdown::ShutdownChannel, task::LxTask};
+use serde::{Deserialize, Serialize};
+use tokio::time::{self, Instant, MissedTickBehavior};
+use tracing::{error, warn};
+
+use crate::persister::NodePersister;
+
+/// How often a running node checks whether it has been superseded.
+const FENCE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The fencing token persisted in the VFS.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct FencingToken {
+    /// Incremented each time a node instance starts.
+    pub epoch: u64,
+    /// Whether the instance holding `epoch` released it by quiescing. If a
+    /// new instance starts while this is `false`, the previous instance
+    /// either crashed or may still be running.
+    pub released: bool,
+    /// A random id of the instance which claimed `epoch`.
+    #[serde(default)]
+    pub instance: u64,
+}
+
+/// A node instance's in-memory view of its fencing token.
+#[derive(Debug, Default)]
+pub(crate) struct Fence {
+    /// The epoch held by this instance; 0 until acquired.
+    epoch: AtomicU64,
+    /// This instance's random id; see [`FencingToken::instance`].
+    instance: AtomicU64,
+    /// Set once this instance has been superseded or released its token.
+    fenced: AtomicBool,
+}
+
+impl Fence {
+    pub(crate) fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn instance(&self) -> u64 {
+        self.instance.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_token(&self, token: &FencingToken) {
+        self.epoch.store(token.epoch, Ordering::Release);
+        self.instance.store(token.instance, Ordering::Release);
+    }
+
+    /// Whether the given persisted token was written by this instance.
+    pub(crate) fn holds(&self, token: &FencingToken) -> bool {
+        token.epoch == self.epoch() && token.instance == self.instance()
+    }
+
+    /// Prevents all further persists by this instance.
+    pub(crate) fn fence(&self) {
+        self.fenced.store(true, Ordering::Release);
+    }
+
+    /// Errors if this instance may no longer persist to the VFS.
+    pub(crate) fn check(&self) -> anyhow::Result<()> {
+        ensure!(
+            !self.fenced.load(Ordering::Acquire),
+            "Node holding fencing epoch {} has been fenced off",
+            self.epoch(),
+        );
+        Ok(())
+    }
+}
+
+/// Spawns a task which periodically checks that this node still holds the
+/// latest fencing epoch, and shuts the node down if it has been superseded.
+pub(crate) fn spawn_fence_check_task(
+    persister: Arc<NodePersister>,
+    mut shutdown: ShutdownChannel,
+) -> LxTask<()> {
+    LxTask::spawn_named("fence check", async move {
+        let start = Instant::now() + FENCE_CHECK_INTERVAL;
+        let mut timer = time::interval_at(start, FENCE_CHECK_INTERVAL);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = timer.tick() => (),
+                () = shutdown.recv() => break,
+            }
+
+            match persister.check_fence().await {
+                Ok(true) => (),
+                Ok(false) => {
+                    error!("Superseded by another instance; shutting down");
+                    shutdown.send();
+                    break;
+                }
+                // Don't shut down just because the backend was unreachable;
+                // persists will fail in that case anyway.
+                Err(e) => warn!("Failed to check fencing token: {e:#}"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fence_check() {
+        let fence = Fence::default();
+        let token = FencingToken {
+            epoch: 2,
+            released: false,
+            instance: 42,
+        };
+        fence.set_token(&token);
+        fence.check().unwrap();
+        assert_eq!(fence.epoch(), 2);
+        assert!(fence.holds(&token));
+
+        // Another instance which raced us to the same epoch
+        let other = FencingToken {
+            instance: 69,
+            ..token
+        };
+        assert!(!fence.holds(&other));
+
+        fence.fence();
+        fence.check().unwrap_err();
+    }
+}
@@ -0,0 +1,122 @@
+//! Freezing the node's Lightning state for a decommissioning state export.
+//!
+//! An exported [`StateArchive`] is only safe to run elsewhere if this node can
+//! no longer update its channel state, otherwise restoring the archive could
+//! publish revoked state and get the user's funds claimed by a penalty tx.
+//! Before snapshotting its state for a decommissioning export, the node
+//! disconnects all peers and shuts down every task which updates Lightning
+//! state (the background processor, the channel monitor persister, chain sync,
+//! the p2p reconnector, and the payments tasks), then waits for them to finish.
+//!
+//! Decommissioning is a separate, explicit step: the node only persists its
+//! decommissioned marker once the app confirms that it has the archive by
+//! sending back the archive's hash. If the export response is lost, the node
+//! is still usable and the app can simply export again.
+//!
+//! [`StateArchive`]: common::api::migration::StateArchive
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{ensure, Context};
+use common::{sha256, shutdown::ShutdownChannel};
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::peer_manager::NodePeerManager;
+
+/// How long we'll wait for the Lightning tasks to finish after freezing.
+const FREEZE_TIME_LIMIT: Duration = Duration::from_secs(15);
+
+pub(crate) struct LnFreeze {
+    /// A child of the node's shutdown channel, which only the tasks that
+    /// update Lightning state listen on.
+    ln_shutdown: ShutdownChannel,
+    /// Set once a freeze was requested, so that the run loop can tell a
+    /// freeze apart from a Lightning task failing and shutting down its
+    /// siblings.
+    requested: AtomicBool,
+    /// Set by the run loop once all Lightning tasks have finished.
+    frozen_tx: watch::Sender<bool>,
+    /// The hash of the last archive exported while frozen.
+    exported: Mutex<Option<sha256::Hash>>,
+}
+
+impl LnFreeze {
+    pub(crate) fn new(shutdown: &ShutdownChannel) -> Self {
+        let (frozen_tx, _) = watch::channel(false);
+        Self {
+            ln_shutdown: shutdown.child(),
+            requested: AtomicBool::new(false),
+            frozen_tx,
+            exported: Mutex::new(None),
+        }
+    }
+
+    /// The shutdown channel to pass to tasks which update Lightning state.
+    pub(crate) fn ln_shutdown(&self) -> ShutdownChannel {
+        self.ln_shutdown.clone()
+    }
+
+    pub(crate) fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn is_frozen(&self) -> bool {
+        *self.frozen_tx.borrow()
+    }
+
+    /// Stops all Lightning tasks and waits until they have finished, after
+    /// which the node's persisted Lightning state can no longer change.
+    pub(crate) async fn freeze(
+        &self,
+        peer_manager: &NodePeerManager,
+    ) -> anyhow::Result<()> {
+        if !self.requested.swap(true, Ordering::AcqRel) {
+            info!("Freezing Lightning state for export");
+        }
+        // Without peers, our channels can't be updated while the tasks stop.
+        peer_manager.disconnect_all_peers();
+        self.ln_shutdown.send();
+
+        let mut frozen_rx = self.frozen_tx.subscribe();
+        tokio::time::timeout(FREEZE_TIME_LIMIT, frozen_rx.wait_for(|f| *f))
+            .await
+            .context("Timed out waiting for Lightning tasks to finish")?
+            .context("Frozen channel closed")?;
+        Ok(())
+    }
+
+    /// Called by the run loop once all Lightning tasks have finished.
+    pub(crate) fn set_frozen(&self) {
+        info!("Lightning state frozen");
+        self.frozen_tx.send_replace(true);
+    }
+
+    /// Records the archive exported while frozen, so that decommissioning can
+    /// check that the app received it.
+    pub(crate) fn record_export(&self, archive: &[u8]) {
+        *self.exported.lock().unwrap() = Some(sha256::digest(archive));
+    }
+
+    /// Checks that `archive_sha256` is the hash of the last archive exported
+    /// while frozen.
+    pub(crate) fn check_export(
+        &self,
+        archive_sha256: &[u8; 32],
+    ) -> anyhow::Result<()> {
+        ensure!(self.is_frozen(), "Node state was not exported");
+        let exported = *self.exported.lock().unwrap();
+        let exported = exported.context("Node state was not exported")?;
+        ensure!(
+            exported.into_inner() == *archive_sha256,
+            "Archive hash doesn't match the last exported archive"
+        );
+        Ok(())
+    }
+}
@@ -17,9 +17,15 @@ pub mod cli;
 mod alias;
 mod api;
 mod approved_versions;
+mod backup_verifier;
+mod channel_health;
 mod channel_manager;
+mod channel_ops;
 mod event_handler;
+mod fencing;
+mod freeze;
 mod inactivity_timer;
+mod metrics;
 mod peer_manager;
 mod persister;
 mod provision;
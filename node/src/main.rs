@@ -13,6 +13,14 @@ pub fn main() -> ExitCode {
 
     logger::init();
 
+    // Record panics in spawned tasks so they show up in the node's metrics.
+    common::task::install_panic_hook();
+    common::task::set_crash_callback(|crash| {
+        let name = &crash.task_name;
+        let message = &crash.message;
+        error!("Task '{name}' panicked: {message}");
+    });
+
     let command = match NodeCommand::from_env() {
         Ok(Some(cmd)) => cmd,
         Ok(None) => return ExitCode::SUCCESS,
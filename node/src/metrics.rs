@@ -0,0 +1,56 @@
+//! Counters reported to the Lexe operators via `GET /lexe/metrics`.
+//!
+//! Every counter is monotonically increasing since the node started, so
+//! operators can compute rates (e.g. persists per second) by diffing
+//! successive scrapes. Since each user node runs in its own process, scraping
+//! every node gives the per-user breakdown needed to spot noisy tenants.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use common::time::TimestampMs;
+
+#[derive(Debug)]
+pub(crate) struct NodeCounters {
+    pub started_at: TimestampMs,
+    /// Requests received by the app server, including rate limited requests.
+    pub app_requests: AtomicU64,
+    /// Payments successfully created or updated in Lexe's DB.
+    pub payments_persisted: AtomicU64,
+    /// Successful persists of new or updated channel monitors. Updates which
+    /// were superseded by a newer queued update aren't counted.
+    pub channel_monitor_persists: AtomicU64,
+    /// Successful persists of all other VFS files, e.g. the channel manager,
+    /// wallet db.
+    pub file_persists: AtomicU64,
+    /// GDrive backup files which failed a periodic read-back verification.
+    pub backup_verification_failures: AtomicU64,
+    /// Periodic GDrive storage checks which found the user's Drive nearly
+    /// full.
+    pub gdrive_nearly_full: AtomicU64,
+}
+
+impl NodeCounters {
+    pub fn new() -> Self {
+        Self {
+            started_at: TimestampMs::now(),
+            app_requests: AtomicU64::new(0),
+            payments_persisted: AtomicU64::new(0),
+            channel_monitor_persists: AtomicU64::new(0),
+            file_persists: AtomicU64::new(0),
+            backup_verification_failures: AtomicU64::new(0),
+            gdrive_nearly_full: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Increment `counter` by `n`.
+#[inline]
+pub(crate) fn add(counter: &AtomicU64, n: u64) {
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+/// Read the current value of `counter`.
+#[inline]
+pub(crate) fn get(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
@@ -4,18 +4,23 @@ use std::{
     ops::Deref,
     str::FromStr,
     sync::{Arc, Mutex},
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
-use anyhow::{anyhow, ensure, Context};
+use anyhow::{anyhow, bail, ensure, Context};
 use async_trait::async_trait;
 use bitcoin::hash_types::BlockHash;
 use common::{
-    aes::AesMasterKey,
+    aes::{self, AesMasterKey},
     api::{
         auth::{BearerAuthToken, BearerAuthenticator},
+        command::{GDriveStorageStatus, QueryPayments, QueryPaymentsResponse},
         qs::{GetNewPayments, GetPaymentByIndex, GetPaymentsByIds},
-        vfs::{VfsDirectory, VfsFile, VfsFileId},
+        remote_config::{RemoteConfig, SignedRemoteConfig},
+        scid_pool::ScidPool,
+        settings::SettingsDoc,
+        user::UserProfile,
+        vfs::{VfsDirectory, VfsFile, VfsFileId, MAX_FILE_VERSIONS},
         Scid, User,
     },
     backoff,
@@ -23,28 +28,41 @@ use common::{
     constants::{
         IMPORTANT_PERSIST_RETRIES, SINGLETON_DIRECTORY, WALLET_DB_FILENAME,
     },
+    env::DeployEnv,
     ln::{
         channel::LxOutPoint,
-        payments::{BasicPayment, DbPayment, LxPaymentId, PaymentIndex},
+        fee_policy::FeePolicy,
+        payments::{
+            BasicPayment, DbPayment, LxPaymentId, PaymentIndex, PaymentStatus,
+        },
         peer::ChannelPeer,
     },
-    rng::{Crng, SysRng},
+    metered::{MeteredSender, QueueMetrics},
+    rng::{Crng, RngExt, SysRng},
     shutdown::ShutdownChannel,
+    task::{Budget, LxTask},
+    time::TimestampMs,
+    tls::shared_seed::rotation::SeedRotationState,
     Apply,
 };
 use futures::future::TryFutureExt;
-use gdrive::{oauth2::GDriveCredentials, GoogleVfs, GvfsRoot};
+use gdrive::{
+    gvfs::TRASH_RETENTION, oauth2::GDriveCredentials, GoogleVfs, GvfsRoot,
+};
 use lexe_ln::{
     alias::{
         BroadcasterType, ChannelMonitorType, FeeEstimatorType,
         NetworkGraphType, ProbabilisticScorerType, RouterType, SignerType,
     },
+    channel_health::ChannelActivityLog,
     channel_monitor::{ChannelMonitorUpdateKind, LxChannelMonitorUpdate},
+    event::DeadLetterQueue,
     keys_manager::LexeKeysManager,
     logger::LexeTracingLogger,
     payments::{
         self,
         manager::{CheckedPayment, PersistedPayment},
+        onchain::ExpectedDeposit,
         Payment,
     },
     persister,
@@ -74,6 +92,8 @@ use crate::{
     api::BackendApiClient,
     approved_versions::ApprovedVersions,
     channel_manager::USER_CONFIG,
+    fencing::{Fence, FencingToken},
+    metrics::{self, NodeCounters},
 };
 
 // Singleton objects use SINGLETON_DIRECTORY with a fixed filename
@@ -82,9 +102,44 @@ const CHANNEL_MANAGER_FILENAME: &str = "channel_manager";
 const SCORER_FILENAME: &str = "scorer";
 const GDRIVE_CREDENTIALS_FILENAME: &str = "gdrive_credentials";
 const GVFS_ROOT_FILENAME: &str = "gvfs_root";
+const REMOTE_CONFIG_FILENAME: &str = "remote_config";
+const USER_PROFILE_FILENAME: &str = "user_profile";
+const SETTINGS_FILENAME: &str = "app_settings";
+const FEE_POLICY_FILENAME: &str = "fee_policy";
+const EXPECTED_DEPOSITS_FILENAME: &str = "expected_deposits";
+const SCID_POOL_FILENAME: &str = "scid_pool";
+/// Marks that this node's state was exported for migration; see
+/// [`NodePersister::persist_decommissioned`].
+const DECOMMISSIONED_FILENAME: &str = "decommissioned";
+const DEAD_LETTERS_FILENAME: &str = "event_dead_letters";
+const CHANNEL_ACTIVITY_FILENAME: &str = "channel_activity";
+const FENCING_TOKEN_FILENAME: &str = "fencing_token";
+
+/// Critical singleton files which keep [`MAX_FILE_VERSIONS`] previous versions
+/// in Lexe's DB (and in GDrive, if the file is persisted there), so that a bad
+/// write (e.g. a serialization bug) doesn't destroy the only copy. See
+/// [`rotate_versions`].
+const VERSIONED_FILENAMES: [&str; 2] =
+    [CHANNEL_MANAGER_FILENAME, WALLET_DB_FILENAME];
+/// How often the versions of a versioned file are rotated. Rotating on every
+/// write would let a bug which keeps writing bad data overwrite every version
+/// within seconds, since e.g. the channel manager is persisted very often.
+/// Persisted in `<filename>.rotated_at` so that restarts don't reset it.
+const FILE_VERSION_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 // Non-singleton objects use a fixed directory with dynamic filenames
 pub(crate) const CHANNEL_MONITORS_DIRECTORY: &str = "channel_monitors";
+/// Finalized payments which were moved out of the payments DB; see
+/// [`LexeInnerPersister::archive_payments`].
+const ARCHIVED_PAYMENTS_DIRECTORY: &str = "archived_payments";
+
+/// The result of [`NodePersister::verify_gdrive_backups`].
+pub(crate) struct BackupReport {
+    /// The # of files which were successfully verified.
+    pub num_verified: usize,
+    /// A description of each file which failed verification.
+    pub failures: Vec<String>,
+}
 
 pub struct NodePersister {
     backend_api: Arc<dyn BackendApiClient + Send + Sync>,
@@ -93,7 +148,12 @@ pub struct NodePersister {
     google_vfs: Option<Arc<GoogleVfs>>,
     user: User,
     shutdown: ShutdownChannel,
-    channel_monitor_persister_tx: mpsc::Sender<LxChannelMonitorUpdate>,
+    channel_monitor_persister_tx: MeteredSender<LxChannelMonitorUpdate>,
+    counters: Arc<NodeCounters>,
+    fence: Arc<Fence>,
+    /// When each versioned file's versions were last rotated, as far as this
+    /// instance knows. The persisted timestamp is authoritative.
+    versions_rotated_at: Arc<Mutex<HashMap<String, TimestampMs>>>,
 }
 
 /// General helper for upserting well-formed [`VfsFile`]s.
@@ -129,6 +189,68 @@ pub(crate) fn encrypt_gdrive_credentials(
     persister::encrypt_json(rng, vfs_master_key, file_id, &credentials)
 }
 
+/// Upsert the files from an imported [`StateArchive`] into Lexe's DB.
+///
+/// Refuses to overwrite any existing Lightning state, since running two nodes
+/// from the same state risks losing funds. The decommissioned marker, if
+/// present, is not imported.
+///
+/// [`StateArchive`]: common::api::migration::StateArchive
+pub(crate) async fn import_files(
+    backend_api: &(dyn BackendApiClient + Send + Sync),
+    authenticator: &BearerAuthenticator,
+    files: Vec<VfsFile>,
+) -> anyhow::Result<()> {
+    let token = authenticator
+        .get_token(backend_api, SystemTime::now())
+        .await
+        .context("Could not get auth token")?;
+
+    let manager_id =
+        VfsFileId::new(SINGLETON_DIRECTORY, CHANNEL_MANAGER_FILENAME);
+    let maybe_manager = backend_api
+        .get_file(&manager_id, token.clone())
+        .await
+        .context("Could not check for existing channel manager")?;
+    ensure!(
+        maybe_manager.is_none(),
+        "User already has a channel manager; refusing to import over it"
+    );
+    let monitors_dir = VfsDirectory::new(CHANNEL_MONITORS_DIRECTORY);
+    let monitors = backend_api
+        .get_directory(&monitors_dir, token.clone())
+        .await
+        .context("Could not check for existing channel monitors")?;
+    ensure!(
+        monitors.is_empty(),
+        "User already has channel monitors; refusing to import over them"
+    );
+
+    let num_files = files.len();
+    for file in files {
+        if file.id.dir.dirname == SINGLETON_DIRECTORY
+            && file.id.filename == DECOMMISSIONED_FILENAME
+        {
+            continue;
+        }
+        backend_api
+            .upsert_file_with_retries(
+                &file,
+                token.clone(),
+                IMPORTANT_PERSIST_RETRIES,
+            )
+            .await
+            .with_context(|| {
+                let dirname = &file.id.dir.dirname;
+                let filename = &file.id.filename;
+                format!("Could not import {dirname}/{filename}")
+            })?;
+    }
+
+    info!("Imported {num_files} files");
+    Ok(())
+}
+
 pub(crate) async fn read_gdrive_credentials(
     backend_api: &(dyn BackendApiClient + Send + Sync),
     authenticator: &BearerAuthenticator,
@@ -212,11 +334,7 @@ pub(crate) async fn password_encrypted_root_seed_exists(
     google_vfs: &GoogleVfs,
     network: Network,
 ) -> bool {
-    // This fn barely does anything, but we want it close to the impl of
-    // `persist_password_encrypted_root_seed` for ez inspection
-    let filename = format!("{network}_root_seed");
-    let file_id = VfsFileId::new(SINGLETON_DIRECTORY, filename);
-    google_vfs.file_exists(&file_id).await
+    google_vfs.file_exists(&root_seed_file_id(network)).await
 }
 
 /// Persists the given password-encrypted [`RootSeed`] to GDrive.
@@ -230,9 +348,10 @@ pub(crate) async fn persist_password_encrypted_root_seed(
     network: Network,
     encrypted_seed: Vec<u8>,
 ) -> anyhow::Result<()> {
-    // We include network in the filename as a safeguard against mixing seeds up
-    let filename = format!("{network}_root_seed");
-    let file = VfsFile::new(SINGLETON_DIRECTORY, filename, encrypted_seed);
+    let file = VfsFile {
+        id: root_seed_file_id(network),
+        data: encrypted_seed,
+    };
 
     google_vfs
         .create_file(file)
@@ -242,6 +361,15 @@ pub(crate) async fn persist_password_encrypted_root_seed(
     Ok(())
 }
 
+/// The [`VfsFileId`] of the password-encrypted [`RootSeed`] backup in GDrive.
+///
+/// [`RootSeed`]: common::root_seed::RootSeed
+fn root_seed_file_id(network: Network) -> VfsFileId {
+    // We include network in the filename as a safeguard against mixing seeds up
+    let filename = format!("{network}_root_seed");
+    VfsFileId::new(SINGLETON_DIRECTORY, filename)
+}
+
 /// Read the [`ApprovedVersions`] list from Google Drive, if it exists.
 pub(crate) async fn read_approved_versions(
     google_vfs: &GoogleVfs,
@@ -304,7 +432,8 @@ impl NodePersister {
         google_vfs: Option<Arc<GoogleVfs>>,
         user: User,
         shutdown: ShutdownChannel,
-        channel_monitor_persister_tx: mpsc::Sender<LxChannelMonitorUpdate>,
+        channel_monitor_persister_tx: MeteredSender<LxChannelMonitorUpdate>,
+        counters: Arc<NodeCounters>,
     ) -> Self {
         Self {
             backend_api,
@@ -314,9 +443,17 @@ impl NodePersister {
             user,
             shutdown,
             channel_monitor_persister_tx,
+            counters,
+            fence: Arc::new(Fence::default()),
+            versions_rotated_at: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
+    /// Metrics for the queue of channel monitor updates awaiting persistence.
+    pub(crate) fn channel_monitor_queue_metrics(&self) -> QueueMetrics {
+        self.channel_monitor_persister_tx.metrics()
+    }
+
     /// Sugar for calling [`persister::encrypt_ldk_writeable`].
     #[inline]
     fn encrypt_ldk_writeable(
@@ -336,12 +473,180 @@ impl NodePersister {
     }
 
     async fn get_token(&self) -> anyhow::Result<BearerAuthToken> {
+        self.fence.check()?;
         self.authenticator
             .get_token(&*self.backend_api, SystemTime::now())
             .await
             .context("Could not get auth token")
     }
 
+    /// Fetches and decrypts the `n`th most recent previous version of a
+    /// versioned file, e.g. to recover from a corrupt write. Returns [`None`]
+    /// if there is no such version.
+    pub(crate) async fn read_file_version(
+        &self,
+        file_id: &VfsFileId,
+        n: u8,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        ensure!(
+            (1..=MAX_FILE_VERSIONS).contains(&n),
+            "Version must be in 1..={MAX_FILE_VERSIONS}"
+        );
+        let token = self.get_token().await?;
+        let maybe_file = self
+            .backend_api
+            .get_file(&file_id.version(n), token)
+            .await
+            .with_context(|| format!("Could not fetch {file_id} v{n}"))?;
+        // Versions are copies of the original file, so decrypt them as such.
+        maybe_file
+            .map(|file| {
+                persister::decrypt_file(&self.vfs_master_key, file_id, file)
+            })
+            .transpose()
+    }
+
+    /// Overwrites the versioned singleton file `filename` with its `n`th most
+    /// recent previous version, after checking that the version decrypts.
+    ///
+    /// Fences off this instance before writing, so that no other persist (e.g.
+    /// of the in-memory wallet db) can overwrite the restored file; the node
+    /// must be restarted afterwards.
+    pub(crate) async fn restore_file_version(
+        &self,
+        filename: &str,
+        n: u8,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            VERSIONED_FILENAMES.contains(&filename),
+            "{filename} is not versioned"
+        );
+        ensure!(
+            (1..=MAX_FILE_VERSIONS).contains(&n),
+            "Version must be in 1..={MAX_FILE_VERSIONS}"
+        );
+        let file_id = VfsFileId::new(SINGLETON_DIRECTORY, filename);
+        let token = self.get_token().await?;
+        let version = self
+            .backend_api
+            .get_file(&file_id.version(n), token.clone())
+            .await
+            .with_context(|| format!("Could not fetch {file_id} v{n}"))?
+            .with_context(|| format!("{file_id} has no v{n}"))?;
+        // Versions are copies of the original file, so decrypt them as such.
+        persister::decrypt_file(
+            &self.vfs_master_key,
+            &file_id,
+            version.clone(),
+        )
+        .with_context(|| format!("{file_id} v{n} is unusable"))?;
+
+        self.fence.fence();
+        let file = VfsFile {
+            id: file_id,
+            data: version.data,
+        };
+        self.backend_api
+            .upsert_file_with_retries(&file, token, IMPORTANT_PERSIST_RETRIES)
+            .await
+            .context("Could not restore file to Lexe")?;
+        // Only the channel manager is persisted to GDrive.
+        if filename == CHANNEL_MANAGER_FILENAME {
+            if let Some(gvfs) = &self.google_vfs {
+                gvfs.upsert_file(file)
+                    .await
+                    .context("Could not restore file to GDrive")?;
+            }
+        }
+
+        warn!(%filename, "Restored file from version {n}");
+        Ok(())
+    }
+
+    /// If `file` is versioned and its versions weren't rotated in the last
+    /// [`FILE_VERSION_INTERVAL`], spawns a task which rotates them; see
+    /// [`rotate_versions`]. Call this after persisting the file, passing the
+    /// [`GoogleVfs`] if the file is also persisted there.
+    ///
+    /// Rotating doesn't block the persist path, and failures are logged and
+    /// retried on the next persist.
+    fn maybe_rotate_versions(
+        &self,
+        file: &VfsFile,
+        maybe_google_vfs: Option<Arc<GoogleVfs>>,
+    ) {
+        let file_id = &file.id;
+        let is_versioned = file_id.dir.dirname == SINGLETON_DIRECTORY
+            && VERSIONED_FILENAMES.contains(&file_id.filename.as_str());
+        if !is_versioned {
+            return;
+        }
+
+        let now = TimestampMs::now();
+        {
+            let mut rotated_at = self.versions_rotated_at.lock().unwrap();
+            if let Some(last) = rotated_at.get(&file_id.filename) {
+                if elapsed_since(*last, now) < FILE_VERSION_INTERVAL {
+                    return;
+                }
+            }
+            // Claim this rotation so that later persists don't start another.
+            rotated_at.insert(file_id.filename.clone(), now);
+        }
+
+        let backend_api = self.backend_api.clone();
+        let authenticator = self.authenticator.clone();
+        let vfs_master_key = self.vfs_master_key.clone();
+        let fence = self.fence.clone();
+        let versions_rotated_at = self.versions_rotated_at.clone();
+        let file = file.clone();
+        LxTask::spawn_named("rotate file versions", async move {
+            let filename = file.id.filename.clone();
+            let try_rotate = rotate_versions(
+                &*backend_api,
+                &authenticator,
+                maybe_google_vfs.as_deref(),
+                &vfs_master_key,
+                &fence,
+                file,
+                now,
+            )
+            .await;
+            let mut rotated_at = versions_rotated_at.lock().unwrap();
+            match try_rotate {
+                Ok(last) => {
+                    rotated_at.insert(filename, last);
+                }
+                Err(e) => {
+                    warn!(%filename, "Failed to rotate file versions: {e:#}");
+                    // Try again on the next persist.
+                    rotated_at.remove(&filename);
+                }
+            }
+        })
+        .detach();
+    }
+
+    /// Readiness check: errors if we can't authenticate with the backend.
+    pub(crate) async fn check_auth(&self) -> anyhow::Result<()> {
+        self.get_token().await.map(|_| ())
+    }
+
+    /// Readiness check: errors if we can't read from the VFS. The file is
+    /// small, and a missing file still means the VFS is readable.
+    pub(crate) async fn check_vfs_readable(&self) -> anyhow::Result<()> {
+        let file_id = VfsFileId::new(
+            SINGLETON_DIRECTORY.to_owned(),
+            REMOTE_CONFIG_FILENAME.to_owned(),
+        );
+        let token = self.get_token().await?;
+        self.backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not read from VFS")?;
+        Ok(())
+    }
+
     pub(crate) async fn read_scid(&self) -> anyhow::Result<Option<Scid>> {
         debug!("Fetching scid");
         let token = self.get_token().await?;
@@ -351,6 +656,49 @@ impl NodePersister {
             .context("Could not fetch scid")
     }
 
+    /// Read the node's [`ScidPool`], if it has one yet. Nodes which predate
+    /// SCID rotation only have the single SCID returned by [`read_scid`].
+    ///
+    /// [`read_scid`]: Self::read_scid
+    pub(crate) async fn read_scid_pool(
+        &self,
+    ) -> anyhow::Result<Option<ScidPool>> {
+        debug!("Reading scid pool");
+        let file_id = VfsFileId::new(
+            SINGLETON_DIRECTORY.to_owned(),
+            SCID_POOL_FILENAME.to_owned(),
+        );
+        let token = self.get_token().await?;
+
+        self.backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not fetch scid pool from DB")?
+            .map(|file| {
+                persister::decrypt_json_file::<ScidPool>(
+                    &self.vfs_master_key,
+                    &file_id,
+                    file,
+                )
+                .context("Failed to decrypt scid pool")
+            })
+            .transpose()
+    }
+
+    /// Persist the node's [`ScidPool`].
+    pub(crate) async fn persist_scid_pool(
+        &self,
+        scid_pool: &ScidPool,
+    ) -> anyhow::Result<()> {
+        debug!("Persisting scid pool");
+        let file = self.encrypt_json(
+            SINGLETON_DIRECTORY,
+            SCID_POOL_FILENAME,
+            scid_pool,
+        );
+        self.persist_file(file, IMPORTANT_PERSIST_RETRIES).await
+    }
+
     pub(crate) async fn read_wallet_db(
         &self,
         wallet_db_persister_tx: mpsc::Sender<()>,
@@ -371,11 +719,22 @@ impl NodePersister {
         let wallet_db = match maybe_file {
             Some(file) => {
                 debug!("Decrypting and deserializing existing wallet db");
-                let db_data = persister::decrypt_json_file::<DbData>(
+                let try_db_data = persister::decrypt_json_file::<DbData>(
                     &self.vfs_master_key,
                     &file_id,
                     file,
-                )?;
+                );
+                let db_data = match try_db_data {
+                    Ok(db_data) => db_data,
+                    // A stale wallet db is safe to use since BDK will resync,
+                    // so fall back to the latest usable previous version.
+                    Err(e) => {
+                        error!("Wallet db is unusable: {e:#}");
+                        self.recover_wallet_db(&file_id)
+                            .await
+                            .context("Wallet db is unusable")?
+                    }
+                };
 
                 WalletDb::from_inner(db_data, wallet_db_persister_tx)
             }
@@ -389,23 +748,46 @@ impl NodePersister {
         Ok(wallet_db)
     }
 
+    /// Returns the most recent previous version of the wallet db which can be
+    /// decrypted and deserialized.
+    async fn recover_wallet_db(
+        &self,
+        file_id: &VfsFileId,
+    ) -> anyhow::Result<DbData> {
+        for n in 1..=MAX_FILE_VERSIONS {
+            let try_db_data = self
+                .read_file_version(file_id, n)
+                .await
+                .and_then(|maybe_bytes| {
+                    maybe_bytes
+                        .map(|bytes| serde_json::from_slice::<DbData>(&bytes))
+                        .transpose()
+                        .context("JSON deserialization failed")
+                });
+            match try_db_data {
+                Ok(Some(db_data)) => {
+                    warn!("Recovered wallet db from version {n}");
+                    return Ok(db_data);
+                }
+                Ok(None) => break,
+                Err(e) => warn!("Wallet db version {n} is unusable: {e:#}"),
+            }
+        }
+        Err(anyhow!("No usable previous version of the wallet db"))
+    }
+
     pub(crate) async fn read_payments_by_ids(
         &self,
         req: GetPaymentsByIds,
     ) -> anyhow::Result<Vec<BasicPayment>> {
         let token = self.get_token().await?;
-        self.backend_api
-            // Fetch `DbPayment`s
+        let db_payments = self
+            .backend_api
             .get_payments_by_ids(req, token)
             .await
-            .context("Could not fetch `DbPayment`s")?
-            .into_iter()
-            // Decrypt into `Payment`s
-            .map(|p| payments::decrypt(&self.vfs_master_key, p))
-            // Convert to `BasicPayment`s
-            .map(|res| res.map(BasicPayment::from))
-            // Convert Vec<Result<T, E>> -> Result<Vec<T>, E>
-            .collect::<anyhow::Result<Vec<BasicPayment>>>()
+            .context("Could not fetch `DbPayment`s")?;
+        let payments = self.decrypt_payments(db_payments).await?;
+        Ok(payments.into_iter().map(BasicPayment::from).collect())
     }
 
     pub(crate) async fn read_new_payments(
@@ -413,18 +795,39 @@ impl NodePersister {
         req: GetNewPayments,
     ) -> anyhow::Result<Vec<BasicPayment>> {
         let token = self.get_token().await?;
-        self.backend_api
-            // Fetch `DbPayment`s
+        let db_payments = self
+            .backend_api
             .get_new_payments(req, token)
             .await
-            .context("Could not fetch `DbPayment`s")?
-            .into_iter()
-            // Decrypt into `Payment`s
-            .map(|p| payments::decrypt(&self.vfs_master_key, p))
-            // Convert to `BasicPayment`s
-            .map(|res| res.map(BasicPayment::from))
-            // Convert Vec<Result<T, E>> -> Result<Vec<T>, E>
-            .collect::<anyhow::Result<Vec<BasicPayment>>>()
+            .context("Could not fetch `DbPayment`s")?;
+        let payments = self.decrypt_payments(db_payments).await?;
+        Ok(payments.into_iter().map(BasicPayment::from).collect())
+    }
+
+    /// Decrypts [`DbPayment`]s into [`Payment`]s, yielding periodically so
+    /// that large batches don't starve other tasks.
+    async fn decrypt_payments(
+        &self,
+        db_payments: Vec<DbPayment>,
+    ) -> anyhow::Result<Vec<Payment>> {
+        let mut budget = Budget::new();
+        let mut decrypted = Vec::with_capacity(db_payments.len());
+        for db_payment in db_payments {
+            decrypted
+                .push(payments::decrypt(&self.vfs_master_key, db_payment)?);
+            budget.tick().await;
+        }
+        Ok(decrypted)
+    }
+
+    pub(crate) async fn query_payments(
+        &self,
+        req: QueryPayments,
+    ) -> anyhow::Result<QueryPaymentsResponse> {
+        payments::query::query_payments(req, |batch_req| {
+            self.read_new_payments(batch_req)
+        })
+        .await
     }
 
     pub(crate) async fn read_channel_manager(
@@ -564,6 +967,8 @@ impl NodePersister {
         };
 
         let mut result = Vec::new();
+        // Deserializing monitors is expensive and users may have many.
+        let mut budget = Budget::new();
 
         for file in all_files {
             let given = LxOutPoint::from_str(&file.id.filename)
@@ -587,96 +992,773 @@ impl NodePersister {
                 .map_err(|e| anyhow!("{:?}", e))
                 .context("Failed to deserialize Channel Monitor")?;
 
-            let (derived, _script) = channel_monitor.get_funding_txo();
-            ensure!(derived.txid == given.txid.0, "outpoint txid don' match");
-            ensure!(derived.index == given.index, "outpoint index don' match");
+            check_funding_txo(&channel_monitor, &given)?;
+
+            result.push((blockhash, channel_monitor));
+            budget.tick().await;
+        }
+
+        Ok(result)
+    }
+
+    pub(crate) async fn read_scorer(
+        &self,
+        graph: Arc<NetworkGraphType>,
+        logger: LexeTracingLogger,
+    ) -> anyhow::Result<ProbabilisticScorerType> {
+        debug!("Reading probabilistic scorer");
+        let params = ProbabilisticScoringDecayParameters::default();
+
+        let file_id = VfsFileId::new(
+            SINGLETON_DIRECTORY.to_owned(),
+            SCORER_FILENAME.to_owned(),
+        );
+        let token = self.get_token().await?;
+
+        let maybe_file = self
+            .backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not fetch probabilistic scorer from DB")?;
+
+        let scorer = match maybe_file {
+            Some(file) => {
+                let data = persister::decrypt_file(
+                    &self.vfs_master_key,
+                    &file_id,
+                    file,
+                )?;
+                let mut state_buf = Cursor::new(&data);
+
+                ProbabilisticScorer::read(
+                    &mut state_buf,
+                    (params, Arc::clone(&graph), logger),
+                )
+                // LDK DecodeError is Debug but doesn't impl std::error::Error
+                .map_err(|e| anyhow!("{:?}", e))
+                .context("Failed to deserialize ProbabilisticScorer")?
+            }
+            None => ProbabilisticScorer::new(params, graph, logger),
+        };
+
+        Ok(scorer)
+    }
+
+    pub(crate) async fn read_network_graph(
+        &self,
+        network: Network,
+        logger: LexeTracingLogger,
+    ) -> anyhow::Result<NetworkGraphType> {
+        debug!("Reading network graph");
+        let file_id = VfsFileId::new(
+            SINGLETON_DIRECTORY.to_owned(),
+            NETWORK_GRAPH_FILENAME.to_owned(),
+        );
+        let token = self.get_token().await?;
+
+        let maybe_file = self
+            .backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not fetch network graph from DB")?;
+
+        let network_graph = match maybe_file {
+            Some(file) => {
+                let data = persister::decrypt_file(
+                    &self.vfs_master_key,
+                    &file_id,
+                    file,
+                )?;
+                let mut state_buf = Cursor::new(&data);
+
+                NetworkGraph::read(&mut state_buf, logger.clone())
+                    // LDK DecodeError is Debug but doesn't impl
+                    // std::error::Error
+                    .map_err(|e| anyhow!("{e:?}"))
+                    .context("Failed to deserialize NetworkGraph")?
+            }
+            None => NetworkGraph::new(network.0, logger),
+        };
+
+        Ok(network_graph)
+    }
+
+    /// Read the latest [`RemoteConfig`], falling back to
+    /// [`RemoteConfig::default`] if none could be found.
+    ///
+    /// The signed config is fetched from Lexe and compared against the copy we
+    /// last persisted in the VFS. If the fetched config is newer, it is
+    /// persisted so that it is still available if the backend is unreachable.
+    /// Configs with invalid signatures are ignored, as is a fetched config
+    /// which is older than our persisted copy.
+    ///
+    /// Only errors if we couldn't authenticate with the backend; a missing or
+    /// bad config should never prevent the node from starting.
+    pub(crate) async fn read_remote_config(
+        &self,
+        deploy_env: DeployEnv,
+    ) -> anyhow::Result<RemoteConfig> {
+        debug!("Reading remote config");
+        let file_id = VfsFileId::new(
+            SINGLETON_DIRECTORY.to_owned(),
+            REMOTE_CONFIG_FILENAME.to_owned(),
+        );
+        let token = self.get_token().await?;
+
+        let (try_maybe_file, try_maybe_fetched) = tokio::join!(
+            self.backend_api.get_file(&file_id, token.clone()),
+            self.backend_api.get_remote_config(token),
+        );
+
+        let maybe_persisted = try_maybe_file
+            .context("Could not fetch remote config file")
+            .and_then(|maybe_file| {
+                maybe_file
+                    .map(|file| {
+                        persister::decrypt_json_file::<SignedRemoteConfig>(
+                            &self.vfs_master_key,
+                            &file_id,
+                            file,
+                        )
+                        .context("Failed to decrypt remote config file")
+                    })
+                    .transpose()
+            })
+            .and_then(|maybe_signed| {
+                maybe_signed
+                    .map(|signed| {
+                        signed
+                            .verify(deploy_env)
+                            .context("Persisted remote config is invalid")
+                    })
+                    .transpose()
+            })
+            .inspect_err(|e| warn!("Ignoring persisted remote config: {e:#}"))
+            .ok()
+            .flatten();
+
+        let maybe_fetched = try_maybe_fetched
+            .context("Could not fetch remote config")
+            .and_then(|maybe_signed| {
+                maybe_signed
+                    .map(|signed| {
+                        let config = signed
+                            .verify(deploy_env)
+                            .context("Fetched remote config is invalid")?;
+                        Ok((signed, config))
+                    })
+                    .transpose()
+            })
+            .inspect_err(|e| warn!("Ignoring fetched remote config: {e:#}"))
+            .ok()
+            .flatten();
+
+        let persisted_version = maybe_persisted.as_ref().map(|c| c.version);
+        let config = match maybe_fetched {
+            Some((signed, fetched))
+                if Some(fetched.version) > persisted_version =>
+            {
+                let file = self.encrypt_json(
+                    SINGLETON_DIRECTORY,
+                    REMOTE_CONFIG_FILENAME,
+                    &signed,
+                );
+                // Not fatal; we'll try again on the next boot.
+                if let Err(e) = self.persist_file(file, 1).await {
+                    warn!("Failed to persist remote config: {e:#}");
+                }
+                fetched
+            }
+            _ => maybe_persisted.unwrap_or_default(),
+        };
+
+        info!(version = config.version, "Using remote config");
+        Ok(config)
+    }
+
+    /// Read the user's [`UserProfile`], returning the default profile if the
+    /// user hasn't set one yet.
+    pub(crate) async fn read_user_profile(
+        &self,
+    ) -> anyhow::Result<UserProfile> {
+        debug!("Reading user profile");
+        let file_id = VfsFileId::new(
+            SINGLETON_DIRECTORY.to_owned(),
+            USER_PROFILE_FILENAME.to_owned(),
+        );
+        let token = self.get_token().await?;
+
+        let maybe_file = self
+            .backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not fetch user profile from DB")?;
+
+        match maybe_file {
+            Some(file) => persister::decrypt_json_file::<UserProfile>(
+                &self.vfs_master_key,
+                &file_id,
+                file,
+            )
+            .context("Failed to decrypt user profile"),
+            None => Ok(UserProfile::default()),
+        }
+    }
+
+    /// Persist the user's [`UserProfile`], replacing any existing profile.
+    pub(crate) async fn persist_user_profile(
+        &self,
+        profile: &UserProfile,
+    ) -> anyhow::Result<()> {
+        debug!("Persisting user profile");
+        let file = self.encrypt_json(
+            SINGLETON_DIRECTORY,
+            USER_PROFILE_FILENAME,
+            profile,
+        );
+        self.persist_file(file, IMPORTANT_PERSIST_RETRIES).await
+    }
+
+    /// Read the app's synced [`SettingsDoc`], returning an empty doc if the
+    /// app hasn't synced any settings yet.
+    pub(crate) async fn read_settings(&self) -> anyhow::Result<SettingsDoc> {
+        debug!("Reading app settings");
+        let file_id = VfsFileId::new(
+            SINGLETON_DIRECTORY.to_owned(),
+            SETTINGS_FILENAME.to_owned(),
+        );
+        let token = self.get_token().await?;
+
+        let maybe_file = self
+            .backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not fetch app settings from DB")?;
+
+        match maybe_file {
+            Some(file) => persister::decrypt_json_file::<SettingsDoc>(
+                &self.vfs_master_key,
+                &file_id,
+                file,
+            )
+            .context("Failed to decrypt app settings"),
+            None => Ok(SettingsDoc::default()),
+        }
+    }
+
+    /// Persist the app's [`SettingsDoc`], replacing the existing doc.
+    pub(crate) async fn persist_settings(
+        &self,
+        settings: &SettingsDoc,
+    ) -> anyhow::Result<()> {
+        debug!("Persisting app settings");
+        let file =
+            self.encrypt_json(SINGLETON_DIRECTORY, SETTINGS_FILENAME, settings);
+        self.persist_file(file, IMPORTANT_PERSIST_RETRIES).await
+    }
+
+    /// Read the user's [`FeePolicy`], or the default (no limits) if the user
+    /// hasn't set one.
+    pub(crate) async fn read_fee_policy(&self) -> anyhow::Result<FeePolicy> {
+        debug!("Reading fee policy");
+        let file_id = VfsFileId::new(
+            SINGLETON_DIRECTORY.to_owned(),
+            FEE_POLICY_FILENAME.to_owned(),
+        );
+        let token = self.get_token().await?;
+
+        let maybe_file = self
+            .backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not fetch fee policy from DB")?;
+
+        match maybe_file {
+            Some(file) => persister::decrypt_json_file::<FeePolicy>(
+                &self.vfs_master_key,
+                &file_id,
+                file,
+            )
+            .context("Failed to decrypt fee policy"),
+            None => Ok(FeePolicy::default()),
+        }
+    }
+
+    /// Persist the user's [`FeePolicy`].
+    pub(crate) async fn persist_fee_policy(
+        &self,
+        fee_policy: &FeePolicy,
+    ) -> anyhow::Result<()> {
+        debug!("Persisting fee policy");
+        let file = self.encrypt_json(
+            SINGLETON_DIRECTORY,
+            FEE_POLICY_FILENAME,
+            fee_policy,
+        );
+        self.persist_file(file, IMPORTANT_PERSIST_RETRIES).await
+    }
+
+    /// Read the addresses issued for deposits which haven't been paid yet.
+    pub(crate) async fn read_expected_deposits(
+        &self,
+    ) -> anyhow::Result<Vec<ExpectedDeposit>> {
+        debug!("Reading expected deposits");
+        let file_id = VfsFileId::new(
+            SINGLETON_DIRECTORY.to_owned(),
+            EXPECTED_DEPOSITS_FILENAME.to_owned(),
+        );
+        let token = self.get_token().await?;
+
+        let maybe_file = self
+            .backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not fetch expected deposits from DB")?;
+
+        match maybe_file {
+            Some(file) => persister::decrypt_json_file::<Vec<ExpectedDeposit>>(
+                &self.vfs_master_key,
+                &file_id,
+                file,
+            )
+            .context("Failed to decrypt expected deposits"),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Read every VFS file this node persists in Lexe's DB, for inclusion in
+    /// a [`StateArchive`]. The network graph and scorer are skipped since
+    /// they're large and can be rebuilt from gossip.
+    ///
+    /// [`StateArchive`]: common::api::migration::StateArchive
+    pub(crate) async fn read_all_files(&self) -> anyhow::Result<Vec<VfsFile>> {
+        debug!("Reading all files for export");
+        let token = self.get_token().await?;
+
+        let mut files = Vec::new();
+        for dirname in [SINGLETON_DIRECTORY, CHANNEL_MONITORS_DIRECTORY] {
+            let dir = VfsDirectory::new(dirname);
+            let mut dir_files = self
+                .backend_api
+                .get_directory(&dir, token.clone())
+                .await
+                .with_context(|| format!("Could not fetch '{dirname}' dir"))?;
+            dir_files.retain(|file| {
+                let filename = file.id.filename.as_str();
+                filename != NETWORK_GRAPH_FILENAME
+                    && filename != SCORER_FILENAME
+            });
+            files.append(&mut dir_files);
+        }
+
+        Ok(files)
+    }
+
+    /// Permanently mark this node's state as exported for migration. Nodes
+    /// refuse to start once this is set (see [`read_decommissioned`]), so that
+    /// two nodes are never run from the same state.
+    ///
+    /// [`read_decommissioned`]: Self::read_decommissioned
+    pub(crate) async fn persist_decommissioned(&self) -> anyhow::Result<()> {
+        warn!("Decommissioning node");
+        let file = self.encrypt_json(
+            SINGLETON_DIRECTORY,
+            DECOMMISSIONED_FILENAME,
+            &TimestampMs::now(),
+        );
+        self.persist_file(file, IMPORTANT_PERSIST_RETRIES).await
+    }
+
+    /// Returns when this node was decommissioned, if it has been.
+    pub(crate) async fn read_decommissioned(
+        &self,
+    ) -> anyhow::Result<Option<TimestampMs>> {
+        debug!("Reading decommissioned marker");
+        let file_id =
+            VfsFileId::new(SINGLETON_DIRECTORY, DECOMMISSIONED_FILENAME);
+        let token = self.get_token().await?;
+
+        let maybe_file = self
+            .backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not fetch decommissioned marker from DB")?;
+
+        maybe_file
+            .map(|file| {
+                persister::decrypt_json_file::<TimestampMs>(
+                    &self.vfs_master_key,
+                    &file_id,
+                    file,
+                )
+                .context("Failed to decrypt decommissioned marker")
+            })
+            .transpose()
+    }
+
+    /// Read the event handler's [`DeadLetterQueue`], or an empty queue if none
+    /// has been persisted yet.
+    pub(crate) async fn read_dead_letters(
+        &self,
+    ) -> anyhow::Result<DeadLetterQueue> {
+        debug!("Reading dead letter queue");
+        let file_id =
+            VfsFileId::new(SINGLETON_DIRECTORY, DEAD_LETTERS_FILENAME);
+        let token = self.get_token().await?;
+
+        let maybe_file = self
+            .backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not fetch dead letter queue from DB")?;
+
+        match maybe_file {
+            Some(file) => persister::decrypt_json_file::<DeadLetterQueue>(
+                &self.vfs_master_key,
+                &file_id,
+                file,
+            )
+            .context("Failed to decrypt dead letter queue"),
+            None => Ok(DeadLetterQueue::default()),
+        }
+    }
+
+    pub(crate) async fn persist_dead_letters(
+        &self,
+        dead_letters: &DeadLetterQueue,
+    ) -> anyhow::Result<()> {
+        debug!("Persisting dead letter queue");
+        let file = self.encrypt_json(
+            SINGLETON_DIRECTORY,
+            DEAD_LETTERS_FILENAME,
+            dead_letters,
+        );
+        self.persist_file(file, IMPORTANT_PERSIST_RETRIES).await
+    }
+
+    /// Read the [`ChannelActivityLog`], or an empty log if none has been
+    /// persisted yet.
+    pub(crate) async fn read_channel_activity(
+        &self,
+    ) -> anyhow::Result<ChannelActivityLog> {
+        debug!("Reading channel activity log");
+        let file_id =
+            VfsFileId::new(SINGLETON_DIRECTORY, CHANNEL_ACTIVITY_FILENAME);
+        let token = self.get_token().await?;
+
+        let maybe_file = self
+            .backend_api
+            .get_file(&file_id, token)
+            .await
+            .context("Could not fetch channel activity log from DB")?;
+
+        match maybe_file {
+            Some(file) => persister::decrypt_json_file::<ChannelActivityLog>(
+                &self.vfs_master_key,
+                &file_id,
+                file,
+            )
+            .context("Failed to decrypt channel activity log"),
+            None => Ok(ChannelActivityLog::default()),
+        }
+    }
+
+    pub(crate) async fn persist_channel_activity(
+        &self,
+        activity_log: &ChannelActivityLog,
+    ) -> anyhow::Result<()> {
+        debug!("Persisting channel activity log");
+        let file = self.encrypt_json(
+            SINGLETON_DIRECTORY,
+            CHANNEL_ACTIVITY_FILENAME,
+            activity_log,
+        );
+        // Only used for recommendations; we'll persist again next sample.
+        self.persist_file(file, 1).await
+    }
+
+    async fn read_fencing_token(&self) -> anyhow::Result<FencingToken> {
+        self.fence.check()?;
+        read_fencing_token(
+            &*self.backend_api,
+            &self.authenticator,
+            &self.vfs_master_key,
+        )
+        .await
+    }
 
-            result.push((blockhash, channel_monitor));
+    async fn persist_fencing_token(
+        &self,
+        fencing_token: FencingToken,
+    ) -> anyhow::Result<()> {
+        let file = self.encrypt_json(
+            SINGLETON_DIRECTORY,
+            FENCING_TOKEN_FILENAME,
+            &fencing_token,
+        );
+        self.persist_file(file, IMPORTANT_PERSIST_RETRIES).await
+    }
+
+    /// Claims the next fencing epoch for this node instance. Must be called
+    /// at boot before anything else is persisted; any instance holding an
+    /// older epoch will stop persisting and shut down once it notices.
+    pub(crate) async fn acquire_fence(&self) -> anyhow::Result<u64> {
+        let previous = self.read_fencing_token().await?;
+        if previous.epoch > 0 && !previous.released {
+            warn!(
+                "Previous instance (epoch {}) didn't release its fencing \
+                 token; it may have crashed or still be running",
+                previous.epoch,
+            );
         }
 
-        Ok(result)
+        let epoch = previous.epoch + 1;
+        let token = FencingToken {
+            epoch,
+            released: false,
+            instance: SysRng::new().gen_u64(),
+        };
+        self.fence.set_token(&token);
+        self.persist_fencing_token(token)
+            .await
+            .context("Failed to persist fencing token")?;
+
+        // There's no compare-and-swap, so check that another instance didn't
+        // claim the same epoch concurrently. If it overwrites our token after
+        // this check, we'll notice before our next channel state persist.
+        ensure!(
+            self.check_fence().await?,
+            "Lost the race for fencing epoch {epoch} to another instance"
+        );
+        info!("Acquired fencing epoch {epoch}");
+
+        Ok(epoch)
     }
 
-    pub(crate) async fn read_scorer(
-        &self,
-        graph: Arc<NetworkGraphType>,
-        logger: LexeTracingLogger,
-    ) -> anyhow::Result<ProbabilisticScorerType> {
-        debug!("Reading probabilistic scorer");
-        let params = ProbabilisticScoringDecayParameters::default();
+    /// Returns whether this instance still holds the latest fencing epoch.
+    /// If it doesn't, all further persists by this instance are refused.
+    pub(crate) async fn check_fence(&self) -> anyhow::Result<bool> {
+        if self.fence.check().is_err() {
+            return Ok(false);
+        }
+        // Another writer modified our GDrive files, so we may be a stale
+        // instance that the Lexe fencing token didn't catch.
+        if self
+            .google_vfs
+            .as_ref()
+            .is_some_and(|gvfs| gvfs.has_conflict())
+        {
+            error!("Another writer modified our GDrive files; fencing off");
+            self.fence.fence();
+            return Ok(false);
+        }
+        let latest = self.read_fencing_token().await?;
+        let held = self.fence.holds(&latest);
+        if !held {
+            self.fence.fence();
+        }
+        Ok(held)
+    }
 
-        let file_id = VfsFileId::new(
-            SINGLETON_DIRECTORY.to_owned(),
-            SCORER_FILENAME.to_owned(),
+    /// Releases this instance's fencing epoch, signaling that it is safe to
+    /// start the next instance. Must be called only after all persistence has
+    /// been flushed; all further persists by this instance are refused.
+    pub(crate) async fn release_fence(&self) -> anyhow::Result<u64> {
+        let epoch = self.fence.epoch();
+        ensure!(
+            self.check_fence().await?,
+            "Can't release fencing epoch {epoch}: superseded by another \
+             instance",
         );
+
+        let token = FencingToken {
+            epoch,
+            released: true,
+            instance: self.fence.instance(),
+        };
+        self.persist_fencing_token(token)
+            .await
+            .context("Failed to persist released fencing token")?;
+        self.fence.fence();
+        info!("Released fencing epoch {epoch}");
+
+        Ok(epoch)
+    }
+
+    /// Read the [`SeedRotationState`], if the user's root seed is currently
+    /// being rotated.
+    pub(crate) async fn read_seed_rotation(
+        &self,
+    ) -> anyhow::Result<Option<SeedRotationState>> {
+        debug!("Reading seed rotation state");
+        let file_id = SeedRotationState::vfs_file_id();
         let token = self.get_token().await?;
 
         let maybe_file = self
             .backend_api
             .get_file(&file_id, token)
             .await
-            .context("Could not fetch probabilistic scorer from DB")?;
+            .context("Could not fetch seed rotation state from DB")?;
 
-        let scorer = match maybe_file {
-            Some(file) => {
-                let data = persister::decrypt_file(
+        maybe_file
+            .map(|file| {
+                persister::decrypt_json_file::<SeedRotationState>(
                     &self.vfs_master_key,
                     &file_id,
                     file,
-                )?;
-                let mut state_buf = Cursor::new(&data);
-
-                ProbabilisticScorer::read(
-                    &mut state_buf,
-                    (params, Arc::clone(&graph), logger),
                 )
-                // LDK DecodeError is Debug but doesn't impl std::error::Error
-                .map_err(|e| anyhow!("{:?}", e))
-                .context("Failed to deserialize ProbabilisticScorer")?
-            }
-            None => ProbabilisticScorer::new(params, graph, logger),
-        };
+                .context("Failed to decrypt seed rotation state")
+            })
+            .transpose()
+    }
 
-        Ok(scorer)
+    /// Reports the user's GDrive storage usage and remaining quota.
+    /// See [`GoogleVfs::storage_status`].
+    pub(crate) async fn gdrive_storage_status(
+        &self,
+    ) -> anyhow::Result<GDriveStorageStatus> {
+        let gvfs = self.google_vfs.as_deref().context("No GoogleVfs")?;
+        gvfs.storage_status().await
     }
 
-    pub(crate) async fn read_network_graph(
+    /// Permanently deletes files which have been in the GDrive trash for longer
+    /// than [`TRASH_RETENTION`]. Returns the number of files purged.
+    /// See [`GoogleVfs::purge_trash`].
+    pub(crate) async fn purge_gdrive_trash(&self) -> anyhow::Result<usize> {
+        let gvfs = self.google_vfs.as_deref().context("No GoogleVfs")?;
+        gvfs.purge_trash(TRASH_RETENTION).await
+    }
+
+    /// Read back the critical files in the user's Google Drive (channel
+    /// manager, channel monitors, password-encrypted root seed) and check that
+    /// each can be decrypted and deserialized. Returns an error only if the
+    /// files couldn't be fetched.
+    ///
+    /// Unlike [`read_channel_manager`] and [`read_channel_monitors`], this
+    /// doesn't fix any discrepancies; it only detects them, so that a corrupted
+    /// backup is noticed before the user has to restore from it.
+    ///
+    /// [`read_channel_manager`]: Self::read_channel_manager
+    /// [`read_channel_monitors`]: Self::read_channel_monitors
+    pub(crate) async fn verify_gdrive_backups(
         &self,
+        keys_manager: &LexeKeysManager,
         network: Network,
-        logger: LexeTracingLogger,
-    ) -> anyhow::Result<NetworkGraphType> {
-        debug!("Reading network graph");
-        let file_id = VfsFileId::new(
-            SINGLETON_DIRECTORY.to_owned(),
-            NETWORK_GRAPH_FILENAME.to_owned(),
-        );
+    ) -> anyhow::Result<BackupReport> {
+        let gvfs = self.google_vfs.as_deref().context("No GoogleVfs")?;
         let token = self.get_token().await?;
 
-        let maybe_file = self
-            .backend_api
-            .get_file(&file_id, token)
-            .await
-            .context("Could not fetch network graph from DB")?;
-
-        let network_graph = match maybe_file {
-            Some(file) => {
-                let data = persister::decrypt_file(
+        let manager_id =
+            VfsFileId::new(SINGLETON_DIRECTORY, CHANNEL_MANAGER_FILENAME);
+        let monitors_dir = VfsDirectory::new(CHANNEL_MONITORS_DIRECTORY);
+        let root_seed_id = root_seed_file_id(network);
+        let (
+            try_google_manager,
+            try_lexe_manager,
+            try_google_monitors,
+            try_lexe_monitors,
+            try_root_seed,
+        ) = tokio::join!(
+            gvfs.get_file(&manager_id),
+            self.backend_api.get_file(&manager_id, token.clone()),
+            gvfs.get_directory(&monitors_dir),
+            self.backend_api.get_directory(&monitors_dir, token),
+            gvfs.get_file(&root_seed_id),
+        );
+        // Failing to fetch is not a verification failure; just try again later
+        let google_manager = try_google_manager
+            .context("Failed to fetch manager from Google")?;
+        let lexe_manager =
+            try_lexe_manager.context("Failed to fetch manager from Lexe")?;
+        let google_monitors = try_google_monitors
+            .context("Failed to fetch monitors from Google")?;
+        let lexe_monitors =
+            try_lexe_monitors.context("Failed to fetch monitors from Lexe")?;
+        let root_seed =
+            try_root_seed.context("Failed to fetch root seed from Google")?;
+
+        let mut num_verified = 0;
+        let mut failures = Vec::new();
+
+        // Fully deserializing the channel manager requires the channel monitors
+        // and most of the node, but VFS files are authenticated-encrypted, so
+        // successful decryption proves that the file isn't corrupted.
+        match (google_manager, lexe_manager) {
+            (Some(file), _) => {
+                match persister::decrypt_file(
                     &self.vfs_master_key,
-                    &file_id,
+                    &manager_id,
                     file,
-                )?;
-                let mut state_buf = Cursor::new(&data);
+                ) {
+                    Ok(bytes) if !bytes.is_empty() => num_verified += 1,
+                    Ok(_) => failures.push("channel manager: empty".to_owned()),
+                    Err(e) => failures.push(format!("channel manager: {e:#}")),
+                }
+            }
+            (None, Some(_)) =>
+                failures.push("channel manager: missing".to_owned()),
+            // The node hasn't persisted a channel manager yet.
+            (None, None) => (),
+        }
 
-                NetworkGraph::read(&mut state_buf, logger.clone())
-                    // LDK DecodeError is Debug but doesn't impl
-                    // std::error::Error
-                    .map_err(|e| anyhow!("{e:?}"))
-                    .context("Failed to deserialize NetworkGraph")?
+        let google_filenames = google_monitors
+            .iter()
+            .map(|file| file.id.filename.as_str())
+            .collect::<HashSet<_>>();
+        for lexe_file in &lexe_monitors {
+            let filename = &lexe_file.id.filename;
+            if !google_filenames.contains(filename.as_str()) {
+                failures.push(format!("channel monitor {filename}: missing"));
             }
-            None => NetworkGraph::new(network.0, logger),
-        };
+        }
+        for file in google_monitors {
+            let filename = file.id.filename.clone();
+            match self.verify_channel_monitor(keys_manager, file) {
+                Ok(()) => num_verified += 1,
+                Err(e) =>
+                    failures.push(format!("channel monitor {filename}: {e:#}")),
+            }
+        }
 
-        Ok(network_graph)
+        // We can't decrypt the root seed without the user's password, but we
+        // can at least check that it's there and has the right length.
+        let expected_seed_len = 32 + aes::encrypted_len(32);
+        match root_seed {
+            Some(file) if file.data.len() == expected_seed_len =>
+                num_verified += 1,
+            Some(_) => failures.push("root seed: wrong length".to_owned()),
+            None => failures.push("root seed: missing".to_owned()),
+        }
+
+        Ok(BackupReport {
+            num_verified,
+            failures,
+        })
+    }
+
+    /// Decrypt and deserialize a channel monitor file from GDrive, checking
+    /// that it matches its filename.
+    fn verify_channel_monitor(
+        &self,
+        keys_manager: &LexeKeysManager,
+        file: VfsFile,
+    ) -> anyhow::Result<()> {
+        let given = LxOutPoint::from_str(&file.id.filename)
+            .context("Invalid funding txo string")?;
+        let file_id = file.id.clone();
+        let data =
+            persister::decrypt_file(&self.vfs_master_key, &file_id, file)?;
+
+        let (_blockhash, channel_monitor) =
+            <(BlockHash, ChannelMonitorType)>::read(
+                &mut Cursor::new(&data),
+                (keys_manager, keys_manager),
+            )
+            // LDK DecodeError is Debug but doesn't impl std::error::Error
+            .map_err(|e| anyhow!("{:?}", e))
+            .context("Failed to deserialize Channel Monitor")?;
+
+        check_funding_txo(&channel_monitor, &given)
     }
 
     /// Given the [`Option<VfsFile>`]s for the channel manager returned to us by
@@ -869,21 +1951,48 @@ impl NodePersister {
                     to_append_to_lexe_files.push((*google_file).clone());
                 }
                 (None, Some(lexe_file)) => {
-                    // Google didn't have the file; restore from Lexe's version.
-                    // This case could be triggered by a deletion race where
+                    // Google didn't have the file; restore it from the GDrive
+                    // trash, or from Lexe's version if it isn't there. This
+                    // case could be triggered by a deletion race where
                     // deleting from Google succeeds but the node crashes before
                     // it could be deleted from Lexe's DB. Since this is rare,
-                    // we should restore the monitor from Lexe's version in case
-                    // the deletion from Google was done on accident.
+                    // we should restore the monitor in case the deletion from
+                    // Google was done on accident.
                     // NOTE: if a malicious Lexe is intentionally trying to
                     // resurface a previously-deleted monitor, that 'attack'
                     // doesn't actually accomplish anything.
-                    error!("Restoring monitor from Lexe: {file_id}");
-                    gvfs.create_file((*lexe_file).clone())
+                    let undeleted = gvfs
+                        .undelete_file(file_id)
+                        .await
+                        .with_context(|| format!("{file_id}"))
+                        .context("Failed to undelete from Google")?;
+                    if !undeleted {
+                        error!("Restoring monitor from Lexe: {file_id}");
+                        gvfs.create_file((*lexe_file).clone())
+                            .await
+                            .with_context(|| format!("{file_id}"))
+                            .context("Failed to restore to Google")?;
+                        to_append_to_google_files.push((*lexe_file).clone());
+                        continue;
+                    }
+
+                    error!("Restored monitor from GDrive trash: {file_id}");
+                    let google_file = gvfs
+                        .get_file(file_id)
                         .await
                         .with_context(|| format!("{file_id}"))
-                        .context("Failed to restore to Google")?;
-                    to_append_to_google_files.push((*lexe_file).clone());
+                        .context("Failed to fetch undeleted monitor")?
+                        .with_context(|| format!("{file_id}"))
+                        .context("Undeleted monitor is missing")?;
+                    if &google_file != *lexe_file {
+                        let token = self.get_token().await?;
+                        self.backend_api
+                            .upsert_file(&google_file, token)
+                            .await
+                            .with_context(|| format!("{file_id}"))
+                            .context("Failed to fix Lexe's version")?;
+                    }
+                    to_append_to_google_files.push(google_file);
                 }
                 (None, None) => unreachable!("HashSet was wrong"),
             }
@@ -925,8 +2034,10 @@ impl LexeInnerPersister for NodePersister {
         self.backend_api
             .upsert_file_with_retries(&file, token, retries)
             .await
-            .map(|_| ())
-            .context("Could not persist basic file")
+            .context("Could not persist basic file")?;
+        metrics::add(&self.counters.file_persists, 1);
+        self.maybe_rotate_versions(&file, None);
+        Ok(())
     }
 
     async fn persist_manager<W: Writeable + Send + Sync>(
@@ -945,10 +2056,15 @@ impl LexeInnerPersister for NodePersister {
             self.backend_api.clone(),
             self.authenticator.clone(),
             self.google_vfs.clone(),
-            file,
+            self.vfs_master_key.clone(),
+            self.fence.clone(),
+            file.clone(),
         )
         .await
-        .context("upsert_to_gdrive_and_lexe failed")
+        .context("upsert_to_gdrive_and_lexe failed")?;
+        metrics::add(&self.counters.file_persists, 1);
+        self.maybe_rotate_versions(&file, self.google_vfs.clone());
+        Ok(())
     }
 
     async fn persist_graph(
@@ -967,8 +2083,9 @@ impl LexeInnerPersister for NodePersister {
         self.backend_api
             .upsert_file(&file, token)
             .await
-            .map(|_| ())
-            .context("Could not persist network graph")
+            .context("Could not persist network graph")?;
+        metrics::add(&self.counters.file_persists, 1);
+        Ok(())
     }
 
     async fn persist_scorer(
@@ -987,8 +2104,9 @@ impl LexeInnerPersister for NodePersister {
         self.backend_api
             .upsert_file(&file, token)
             .await
-            .map(|_| ())
-            .context("Could not persist scorer")
+            .context("Could not persist scorer")?;
+        metrics::add(&self.counters.file_persists, 1);
+        Ok(())
     }
 
     async fn persist_channel_peer(
@@ -1002,16 +2120,12 @@ impl LexeInnerPersister for NodePersister {
 
     async fn read_pending_payments(&self) -> anyhow::Result<Vec<Payment>> {
         let token = self.get_token().await?;
-        self.backend_api
-            // Fetch pending `DbPayment`s
+        let db_payments = self
+            .backend_api
             .get_pending_payments(token)
             .await
-            .context("Could not fetch pending `DbPayment`s")?
-            .into_iter()
-            // Decrypt into `Payment`s
-            .map(|p| payments::decrypt(&self.vfs_master_key, p))
-            // Convert Vec<Result<T, E>> -> Result<Vec<T>, E>
-            .collect::<anyhow::Result<Vec<Payment>>>()
+            .context("Could not fetch pending `DbPayment`s")?;
+        self.decrypt_payments(db_payments).await
     }
 
     async fn read_finalized_payment_ids(
@@ -1038,6 +2152,7 @@ impl LexeInnerPersister for NodePersister {
             .create_payment(db_payment, token)
             .await
             .context("create_payment API call failed")?;
+        metrics::add(&self.counters.payments_persisted, 1);
 
         Ok(PersistedPayment(checked.0))
     }
@@ -1056,6 +2171,7 @@ impl LexeInnerPersister for NodePersister {
             .upsert_payment(db_payment, token)
             .await
             .context("upsert_payment API call failed")?;
+        metrics::add(&self.counters.payments_persisted, 1);
 
         Ok(PersistedPayment(checked.0))
     }
@@ -1067,7 +2183,6 @@ impl LexeInnerPersister for NodePersister {
         if checked_batch.is_empty() {
             return Ok(Vec::new());
         }
-
         let mut rng = common::rng::SysRng::new();
         let batch = checked_batch
             .iter()
@@ -1081,6 +2196,8 @@ impl LexeInnerPersister for NodePersister {
             .upsert_payment_batch(batch, token)
             .await
             .context("upsert_payment API call failed")?;
+        let num_payments = checked_batch.len() as u64;
+        metrics::add(&self.counters.payments_persisted, num_payments);
 
         let persisted_batch = checked_batch
             .into_iter()
@@ -1114,6 +2231,64 @@ impl LexeInnerPersister for NodePersister {
 
         Ok(maybe_payment)
     }
+
+    async fn read_payments_batch(
+        &self,
+        req: GetNewPayments,
+    ) -> anyhow::Result<Vec<Payment>> {
+        let token = self.get_token().await?;
+        let db_payments = self
+            .backend_api
+            .get_new_payments(req, token)
+            .await
+            .context("Could not fetch `DbPayment`s")?;
+        self.decrypt_payments(db_payments).await
+    }
+
+    async fn archive_payments(
+        &self,
+        payments: Vec<Payment>,
+    ) -> anyhow::Result<()> {
+        if payments.is_empty() {
+            return Ok(());
+        }
+        ensure!(
+            payments
+                .iter()
+                .all(|p| p.status() != PaymentStatus::Pending),
+            "Can't archive pending payments"
+        );
+
+        // Write the archive before deleting anything so that we never lose a
+        // payment, even if the delete fails.
+        let filename = format!("payments_{}", TimestampMs::now().as_i64());
+        let file =
+            self.encrypt_json(ARCHIVED_PAYMENTS_DIRECTORY, filename, &payments);
+        self.persist_file(file, IMPORTANT_PERSIST_RETRIES)
+            .await
+            .context("Could not persist payments archive")?;
+
+        let ids = payments.iter().map(|p| p.id().to_string()).collect();
+        let token = self.get_token().await?;
+        self.backend_api
+            .delete_payments(GetPaymentsByIds { ids }, token)
+            .await
+            .map(|_| ())
+            .context("Could not delete archived payments")
+    }
+
+    async fn persist_expected_deposits(
+        &self,
+        deposits: &[ExpectedDeposit],
+    ) -> anyhow::Result<()> {
+        debug!("Persisting {} expected deposits", deposits.len());
+        let file = self.encrypt_json(
+            SINGLETON_DIRECTORY,
+            EXPECTED_DEPOSITS_FILENAME,
+            &deposits,
+        );
+        self.persist_file(file, IMPORTANT_PERSIST_RETRIES).await
+    }
 }
 
 impl Persist<SignerType> for NodePersister {
@@ -1139,12 +2314,16 @@ impl Persist<SignerType> for NodePersister {
         // create due to an occasional race where a channel monitor persist
         // succeeds but the node shuts down before the channel manager is
         // repersisted, causing the create_file call to fail at the next boot.
+        let counters = self.counters.clone();
         let api_call_fut = upsert_to_gdrive_and_lexe(
             self.backend_api.clone(),
             self.authenticator.clone(),
             self.google_vfs.clone(),
+            self.vfs_master_key.clone(),
+            self.fence.clone(),
             file,
         )
+        .map_ok(move |()| metrics::add(&counters.channel_monitor_persists, 1))
         .map_err(|e| e.context("Failed to persist new channel monitor"))
         .apply(Box::pin);
 
@@ -1195,12 +2374,16 @@ impl Persist<SignerType> for NodePersister {
 
         // Generate a future for making a few attempts to persist the channel
         // monitor. It will be executed by the channel monitor persistence task.
+        let counters = self.counters.clone();
         let api_call_fut = upsert_to_gdrive_and_lexe(
             self.backend_api.clone(),
             self.authenticator.clone(),
             self.google_vfs.clone(),
+            self.vfs_master_key.clone(),
+            self.fence.clone(),
             file,
         )
+        .map_ok(move |()| metrics::add(&counters.channel_monitor_persists, 1))
         .map_err(|e| e.context("Failed to persist updated channel monitor"))
         .apply(Box::pin);
 
@@ -1233,17 +2416,200 @@ impl Persist<SignerType> for NodePersister {
     }
 }
 
+/// Fetches the persisted [`FencingToken`], or the default if there is none.
+async fn read_fencing_token(
+    backend_api: &(dyn BackendApiClient + Send + Sync),
+    authenticator: &BearerAuthenticator,
+    vfs_master_key: &AesMasterKey,
+) -> anyhow::Result<FencingToken> {
+    let file_id = VfsFileId::new(SINGLETON_DIRECTORY, FENCING_TOKEN_FILENAME);
+    let token = authenticator
+        .get_token(backend_api, SystemTime::now())
+        .await
+        .context("Could not get auth token")?;
+
+    let maybe_file = backend_api
+        .get_file(&file_id, token)
+        .await
+        .context("Could not fetch fencing token from DB")?;
+
+    match maybe_file {
+        Some(file) => persister::decrypt_json_file::<FencingToken>(
+            vfs_master_key,
+            &file_id,
+            file,
+        )
+        .context("Failed to decrypt fencing token"),
+        None => Ok(FencingToken::default()),
+    }
+}
+
+/// Shifts each previous version of `file` back one slot, dropping the oldest,
+/// and saves `file` itself as version 1, both in Lexe's DB and in GDrive if
+/// `maybe_google_vfs` is [`Some`]. Versions are thus hourly snapshots.
+///
+/// Does nothing if the persisted `<filename>.rotated_at` timestamp says the
+/// versions were rotated less than [`FILE_VERSION_INTERVAL`] before `now`.
+/// Returns when the versions were last rotated.
+async fn rotate_versions(
+    backend_api: &(dyn BackendApiClient + Send + Sync),
+    authenticator: &BearerAuthenticator,
+    maybe_google_vfs: Option<&GoogleVfs>,
+    vfs_master_key: &AesMasterKey,
+    fence: &Fence,
+    file: VfsFile,
+    now: TimestampMs,
+) -> anyhow::Result<TimestampMs> {
+    fence.check()?;
+    let token = authenticator
+        .get_token(backend_api, SystemTime::now())
+        .await
+        .context("Could not get token")?;
+
+    let stamp_id = VfsFileId::new(
+        file.id.dir.dirname.clone(),
+        format!("{}.rotated_at", file.id.filename),
+    );
+    let maybe_stamp = backend_api
+        .get_file(&stamp_id, token.clone())
+        .await
+        .context("Could not fetch rotation timestamp")?;
+    if let Some(stamp) = maybe_stamp {
+        let last = persister::decrypt_json_file::<TimestampMs>(
+            vfs_master_key,
+            &stamp_id,
+            stamp,
+        )
+        .context("Could not decrypt rotation timestamp")?;
+        if elapsed_since(last, now) < FILE_VERSION_INTERVAL {
+            return Ok(last);
+        }
+    }
+
+    // Fetch all versions which shift back before writing any of them, so
+    // that we don't overwrite a version before copying it.
+    let shifted_ids = (1..MAX_FILE_VERSIONS)
+        .map(|n| (file.id.version(n), file.id.version(n + 1)))
+        .collect::<Vec<_>>();
+    let rotated = |maybe_files: Vec<Option<VfsFile>>| {
+        maybe_files
+            .into_iter()
+            .zip(&shifted_ids)
+            .filter_map(|(maybe_file, (_, to))| {
+                maybe_file.map(|f| VfsFile {
+                    id: to.clone(),
+                    data: f.data,
+                })
+            })
+            .chain(std::iter::once(VfsFile {
+                id: file.id.version(1),
+                data: file.data.clone(),
+            }))
+            .collect::<Vec<_>>()
+    };
+
+    let do_lexe_rotate = async {
+        let maybe_files = futures::future::try_join_all(
+            shifted_ids
+                .iter()
+                .map(|(from, _)| backend_api.get_file(from, token.clone())),
+        )
+        .await
+        .context("Could not fetch versions from Lexe")?;
+        futures::future::try_join_all(
+            rotated(maybe_files)
+                .iter()
+                .map(|f| backend_api.upsert_file(f, token.clone())),
+        )
+        .await
+        .context("Could not upsert versions to Lexe")?;
+        Ok::<_, anyhow::Error>(())
+    };
+    let do_google_rotate = async {
+        let gvfs = match maybe_google_vfs {
+            Some(gvfs) => gvfs,
+            None => return Ok(()),
+        };
+        let maybe_files = futures::future::try_join_all(
+            shifted_ids.iter().map(|(from, _)| gvfs.get_file(from)),
+        )
+        .await
+        .context("Could not fetch versions from GDrive")?;
+        futures::future::try_join_all(
+            rotated(maybe_files)
+                .into_iter()
+                .map(|f| gvfs.upsert_file(f)),
+        )
+        .await
+        .context("Could not upsert versions to GDrive")?;
+        Ok::<_, anyhow::Error>(())
+    };
+    let (try_lexe_rotate, try_google_rotate) =
+        tokio::join!(do_lexe_rotate, do_google_rotate);
+    try_lexe_rotate?;
+    try_google_rotate?;
+
+    let stamp = persister::encrypt_json(
+        &mut SysRng::new(),
+        vfs_master_key,
+        stamp_id,
+        &now,
+    );
+    backend_api
+        .upsert_file(&stamp, token)
+        .await
+        .context("Could not persist rotation timestamp")?;
+
+    debug!(file_id = %file.id, "Rotated file versions");
+    Ok(now)
+}
+
+/// Checks that a channel monitor's funding txo matches the outpoint it was
+/// persisted under.
+fn check_funding_txo(
+    channel_monitor: &ChannelMonitorType,
+    given: &LxOutPoint,
+) -> anyhow::Result<()> {
+    let (derived, _script) = channel_monitor.get_funding_txo();
+    ensure!(derived.txid == given.txid.0, "Outpoint txids don't match");
+    ensure!(derived.index == given.index, "Outpoint indices don't match");
+    Ok(())
+}
+
+/// The time elapsed from `earlier` until `now`, or zero if `earlier` is later.
+fn elapsed_since(earlier: TimestampMs, now: TimestampMs) -> Duration {
+    now.into_duration().saturating_sub(earlier.into_duration())
+}
+
 /// Helper to upsert an important VFS file to both Google Drive and Lexe's DB.
 ///
 /// - The upsert to GDrive is skipped if `maybe_google_vfs` is [`None`].
 /// - Up to [`IMPORTANT_PERSIST_RETRIES`] additional attempts will be made if
 ///   the first attempt fails.
+/// - Nothing is upserted if this node instance has been fenced off. Since this
+///   is only used for channel state, the persisted [`FencingToken`] is re-read
+///   first to make sure no other instance has claimed the VFS in the meantime.
 async fn upsert_to_gdrive_and_lexe(
     backend_api: Arc<dyn BackendApiClient + Send + Sync>,
     authenticator: Arc<BearerAuthenticator>,
     maybe_google_vfs: Option<Arc<GoogleVfs>>,
+    vfs_master_key: Arc<AesMasterKey>,
+    fence: Arc<Fence>,
     file: VfsFile,
 ) -> anyhow::Result<()> {
+    fence.check()?;
+    let latest =
+        read_fencing_token(&*backend_api, &authenticator, &vfs_master_key)
+            .await?;
+    if !fence.holds(&latest) {
+        fence.fence();
+        bail!(
+            "Refusing to persist: fencing epoch {} was claimed by another \
+             instance",
+            latest.epoch,
+        );
+    }
+
     let do_google_upsert = async {
         match maybe_google_vfs {
             Some(gvfs) => {
@@ -1290,3 +2656,94 @@ async fn upsert_to_gdrive_and_lexe(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use common::{metered, root_seed::RootSeed};
+
+    use super::*;
+    use crate::api::mock::MockBackendClient;
+
+    fn persister(backend_api: Arc<MockBackendClient>) -> NodePersister {
+        let root_seed = RootSeed::from_u64(1);
+        let user = User {
+            user_pk: root_seed.derive_user_pk(),
+            node_pk: root_seed.derive_node_pk(&mut SysRng::new()),
+        };
+        let authenticator = Arc::new(BearerAuthenticator::new(
+            root_seed.derive_user_key_pair(),
+            None,
+        ));
+        let vfs_master_key = Arc::new(root_seed.derive_vfs_master_key());
+        let (channel_monitor_persister_tx, _rx) =
+            metered::channel("channel monitor persister", 8);
+        NodePersister::new(
+            backend_api,
+            authenticator,
+            vfs_master_key,
+            None,
+            user,
+            ShutdownChannel::new(),
+            channel_monitor_persister_tx,
+            Arc::new(NodeCounters::new()),
+        )
+    }
+
+    fn block_on(fut: impl std::future::Future<Output = ()>) {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(fut)
+    }
+
+    #[test]
+    fn fence_acquire_release() {
+        block_on(async {
+            let backend_api = Arc::new(MockBackendClient::new());
+            let node1 = persister(backend_api.clone());
+            let node2 = persister(backend_api);
+
+            assert_eq!(node1.acquire_fence().await.unwrap(), 1);
+            assert!(node1.check_fence().await.unwrap());
+            node1.persist_manager(&42u64).await.unwrap();
+
+            // After a clean handoff, the next instance takes over.
+            assert_eq!(node1.release_fence().await.unwrap(), 1);
+            assert!(!node1.check_fence().await.unwrap());
+            node1.persist_manager(&42u64).await.unwrap_err();
+
+            assert_eq!(node2.acquire_fence().await.unwrap(), 2);
+            node2.persist_manager(&42u64).await.unwrap();
+        })
+    }
+
+    #[test]
+    fn fence_conflict() {
+        block_on(async {
+            let backend_api = Arc::new(MockBackendClient::new());
+            let node1 = persister(backend_api.clone());
+            let node2 = persister(backend_api);
+
+            // A new instance starts without a handoff; the old instance
+            // notices and stops persisting channel state.
+            assert_eq!(node1.acquire_fence().await.unwrap(), 1);
+            assert_eq!(node2.acquire_fence().await.unwrap(), 2);
+            node1.persist_manager(&42u64).await.unwrap_err();
+            assert!(!node1.check_fence().await.unwrap());
+            node1.release_fence().await.unwrap_err();
+            node2.persist_manager(&42u64).await.unwrap();
+
+            // Another instance races node2 to the same epoch and wins; node2
+            // is fenced off before its next channel state persist.
+            let racing_token = FencingToken {
+                epoch: 2,
+                released: false,
+                instance: node2.fence.instance().wrapping_add(1),
+            };
+            node2.persist_fencing_token(racing_token).await.unwrap();
+            node2.persist_manager(&42u64).await.unwrap_err();
+            assert!(!node2.check_fence().await.unwrap());
+        })
+    }
+}
@@ -19,6 +19,7 @@ use std::{net::TcpListener, sync::Arc, time::SystemTime};
 
 use anyhow::Context;
 use axum::{
+    middleware::from_fn_with_state,
     routing::{get, post},
     Router,
 };
@@ -28,10 +29,14 @@ use common::{
         auth::BearerAuthenticator,
         def::{NodeBackendApi, NodeRunnerApi},
         error::{NodeApiError, NodeErrorKind},
+        migration::{ImportStateRequest, StateArchive},
         ports::Ports,
-        provision::{NodeProvisionRequest, SealedSeed},
+        provision::{
+            AttestChallengeRequest, AttestChallengeResponse,
+            NodeProvisionRequest, SealedSeed,
+        },
         qs::GetByMeasurement,
-        server::LayerConfig,
+        server::{middleware, LayerConfig},
         Empty,
     },
     cli::node::ProvisionArgs,
@@ -49,6 +54,13 @@ use crate::{
     persister,
 };
 
+/// The max request body size for the app provision server. State imports
+/// contain the user's (hex-encoded) channel manager and channel monitors.
+const APP_PROVISION_BODY_LIMIT: usize = 8 * 1024 * 1024;
+/// The max request body size for `/app/provision`, which only contains the
+/// user's secrets and a few small fields.
+const PROVISION_REQUEST_BODY_LIMIT: usize = 16 * 1024;
+
 #[derive(Clone)]
 struct RequestContext {
     args: Arc<ProvisionArgs>,
@@ -119,7 +131,10 @@ pub async fn provision_node(
         api::server::spawn_server_task_with_listener(
             app_listener,
             app_router(ctx),
-            LayerConfig::default(),
+            LayerConfig {
+                body_limit: Some(APP_PROVISION_BODY_LIMIT),
+                ..Default::default()
+            },
             Some((Arc::new(app_tls_config), app_dns.as_str())),
             APP_SERVER_SPAN_NAME,
             info_span!(parent: None, APP_SERVER_SPAN_NAME),
@@ -171,7 +186,15 @@ pub async fn provision_node(
 /// [`AppNodeProvisionApi`]: common::api::def::AppNodeProvisionApi
 fn app_router(ctx: RequestContext) -> Router<()> {
     Router::new()
-        .route("/app/provision", post(handlers::provision))
+        .route("/app/attest", post(handlers::attest))
+        .route(
+            "/app/provision",
+            post(handlers::provision).layer(from_fn_with_state(
+                PROVISION_REQUEST_BODY_LIMIT,
+                middleware::limit_body,
+            )),
+        )
+        .route("/app/import_state", post(handlers::import_state))
         .with_state(ctx)
 }
 
@@ -202,6 +225,23 @@ mod handlers {
     use super::*;
     use crate::approved_versions::ApprovedVersions;
 
+    pub(super) async fn attest(
+        State(mut ctx): State<RequestContext>,
+        LxJson(req): LxJson<AttestChallengeRequest>,
+    ) -> Result<LxJson<AttestChallengeResponse>, NodeApiError> {
+        debug!("Received attestation challenge");
+        let node_mode = NodeMode::Provision {
+            mr_short: ctx.measurement.short(),
+        };
+        let quote = tls::attestation::node_challenge_quote(
+            &mut ctx.rng,
+            node_mode,
+            &req.nonce,
+        )
+        .map_err(NodeApiError::provision)?;
+        Ok(LxJson(AttestChallengeResponse { quote }))
+    }
+
     pub(super) async fn provision(
         State(mut ctx): State<RequestContext>,
         LxJson(req): LxJson<NodeProvisionRequest>,
@@ -248,6 +288,7 @@ mod handlers {
             .map_err(|err| NodeApiError {
                 kind: NodeErrorKind::BadAuth,
                 msg: format!("{err:#}"),
+                data: None,
             })?;
 
         // store the sealed seed and new node metadata in the backend
@@ -283,6 +324,7 @@ mod handlers {
                     oauth.client_secret,
                     &oauth.redirect_uri,
                     &code,
+                    None,
                 )
                 .await
                 .context("Couldn't get tokens using code")
@@ -422,9 +464,15 @@ mod handlers {
                     .map_err(NodeApiError::provision)?
                     .unwrap_or_else(ApprovedVersions::new);
 
-            // Approve the current version, revoke old/yanked versions, etc.
+            // Approve the current version (checking the release manifest if
+            // the user supplied or requires one), revoke old/yanked versions.
             let (updated, revoked) = approved_versions
-                .approve_and_revoke(&user_pk, ctx.measurement)
+                .approve_and_revoke(
+                    &user_pk,
+                    ctx.measurement,
+                    req.release_manifest.as_ref(),
+                    req.deploy_env,
+                )
                 .context("Error updating approved versions")
                 .map_err(NodeApiError::provision)?;
 
@@ -454,6 +502,7 @@ mod handlers {
                         .map_err(|e| NodeApiError {
                             kind: NodeErrorKind::BadAuth,
                             msg: format!("{e:#}"),
+                            data: None,
                         })?;
                     let try_delete = ctx
                         .backend_client
@@ -468,6 +517,7 @@ mod handlers {
                         Err(BackendApiError {
                             kind: BackendErrorKind::NotFound,
                             msg,
+                            ..
                         }) => warn!(
                             %user_pk, %revoked_version, %revoked_measurement,
                             "Failed to delete revoked sealed seeds: \
@@ -479,6 +529,7 @@ mod handlers {
                                 msg: format!(
                                     "Error deleting revoked sealed seeds: {e:#}"
                                 ),
+                                data: None,
                             }),
                     }
                 }
@@ -516,6 +567,42 @@ mod handlers {
             .map(|()| LxJson(Empty {}))
     }
 
+    pub(super) async fn import_state(
+        State(ctx): State<RequestContext>,
+        LxJson(req): LxJson<ImportStateRequest>,
+    ) -> Result<LxJson<Empty>, NodeApiError> {
+        debug!("Received import state request");
+        let ImportStateRequest {
+            root_seed,
+            password,
+            archive,
+        } = req;
+
+        let archive = StateArchive::password_decrypt(&password, archive)
+            .map_err(NodeApiError::provision)?;
+        let user_pk = root_seed.derive_user_pk();
+        if archive.manifest.user_pk != user_pk {
+            return Err(NodeApiError::provision(
+                "State archive belongs to a different user",
+            ));
+        }
+
+        let user_key_pair = root_seed.derive_user_key_pair();
+        let authenticator = BearerAuthenticator::new(
+            user_key_pair,
+            None, /* maybe_token */
+        );
+        persister::import_files(
+            ctx.backend_client.as_ref(),
+            &authenticator,
+            archive.files,
+        )
+        .await
+        .map_err(NodeApiError::provision)?;
+
+        Ok(LxJson(Empty {}))
+    }
+
     pub(super) async fn shutdown(
         State(state): State<LexeRouterState>,
         LxQuery(req): LxQuery<GetByMeasurement>,
@@ -531,6 +618,7 @@ mod handlers {
             return Err(NodeApiError {
                 kind: NodeErrorKind::WrongMeasurement,
                 msg: format!("Given: {given_measure}, current: {measurement}"),
+                data: None,
             });
         }
 
@@ -1,6 +1,9 @@
 use std::{
     net::TcpListener,
-    sync::{atomic::AtomicBool, Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
@@ -8,19 +11,26 @@ use anyhow::{anyhow, bail, ensure, Context};
 use common::{
     aes::AesMasterKey,
     api::{
-        auth::BearerAuthenticator, def::NodeRunnerApi, ports::Ports,
-        provision::SealedSeedId, server::LayerConfig, User, UserPk,
+        auth::BearerAuthenticator,
+        def::{NodeLspApi, NodeRunnerApi},
+        log_capture::{self, LogCapture},
+        ports::{NodeQuiesced, Ports},
+        provision::SealedSeedId,
+        scid_pool::ScidPool,
+        server::LayerConfig,
+        NodePk, Scid, User, UserPk,
     },
     cli::{node::RunArgs, LspInfo, Network},
     constants::{DEFAULT_CHANNEL_SIZE, SMALLER_CHANNEL_SIZE},
     ed25519,
     enclave::{self, MachineId, Measurement, MinCpusvn},
     env::DeployEnv,
-    net, notify,
+    metered, net, notify,
     rng::{Crng, SysRng},
     root_seed::RootSeed,
     shutdown::ShutdownChannel,
     task::{self, LxTask},
+    time::TimestampMs,
     tls::{self, attestation::NodeMode},
     Apply,
 };
@@ -36,6 +46,7 @@ use lexe_ln::{
         ProbabilisticScorerType, RouterType,
     },
     background_processor::LexeBackgroundProcessor,
+    channel_health::ChannelActivityLog,
     channel_monitor,
     esplora::LexeEsplora,
     keys_manager::LexeKeysManager,
@@ -43,7 +54,10 @@ use lexe_ln::{
     p2p,
     p2p::ChannelPeerUpdate,
     payments::manager::PaymentsManager,
-    sync, test_event,
+    rgs,
+    route::{LexeRouter, RouteBlacklist},
+    sync,
+    test_event::{self, TestEventSender},
     traits::LexeInnerPersister,
     wallet::{self, LexeWallet},
 };
@@ -64,9 +78,15 @@ use tracing::{debug, error, info, info_span, instrument, warn};
 use crate::{
     alias::{ChainMonitorType, NodePaymentsManagerType},
     api::{self, BackendApiClient},
+    backup_verifier::{self, BackupVerifierConfig},
+    channel_health,
     channel_manager::NodeChannelManager,
+    channel_ops::ChannelOperations,
     event_handler::NodeEventHandler,
+    fencing,
+    freeze::LnFreeze,
     inactivity_timer::InactivityTimer,
+    metrics::NodeCounters,
     peer_manager::NodePeerManager,
     persister::{self, NodePersister},
     server::{self, AppRouterState, LexeRouterState},
@@ -86,8 +106,14 @@ pub struct UserNode {
     deploy_env: DeployEnv,
     ports: Ports,
     tasks: Vec<LxTask<()>>,
+    /// Tasks which update Lightning state; see [`LnFreeze`].
+    ln_tasks: Vec<LxTask<()>>,
+    ln_freeze: Arc<LnFreeze>,
     channel_peer_tx: mpsc::Sender<ChannelPeerUpdate>,
     shutdown: ShutdownChannel,
+    /// Set if the node is shutting down to be migrated to another meganode.
+    quiescing: Arc<AtomicBool>,
+    runner_api: Arc<dyn NodeRunnerApi + Send + Sync>,
 
     // --- Actors --- //
     logger: LexeTracingLogger,
@@ -120,6 +146,7 @@ struct SyncContext {
     onchain_recv_tx: notify::Sender,
     bdk_resync_rx: mpsc::Receiver<oneshot::Sender<()>>,
     ldk_resync_rx: mpsc::Receiver<oneshot::Sender<()>>,
+    test_event_tx: TestEventSender,
 }
 
 impl UserNode {
@@ -140,6 +167,7 @@ impl UserNode {
 
         // Get user_pk, measurement, and HTTP clients used throughout init
         let user_pk = args.user_pk;
+        args.app_limits.validate().context("Invalid app limits")?;
         let measurement = enclave::measurement();
         let machine_id = enclave::machine_id();
         // TODO(phlip9): Compare this with current cpusvn
@@ -157,7 +185,7 @@ impl UserNode {
         // Init channels
         let (activity_tx, activity_rx) = mpsc::channel(DEFAULT_CHANNEL_SIZE);
         let (channel_monitor_persister_tx, channel_monitor_persister_rx) =
-            mpsc::channel(DEFAULT_CHANNEL_SIZE);
+            metered::channel("channel monitor persister", DEFAULT_CHANNEL_SIZE);
         let (channel_peer_tx, channel_peer_rx) =
             mpsc::channel(SMALLER_CHANNEL_SIZE);
         let (bdk_resync_tx, bdk_resync_rx) =
@@ -181,6 +209,7 @@ impl UserNode {
         let (try_esplora, try_fetch) = tokio::join!(
             LexeEsplora::init(
                 args.esplora_url.clone(),
+                &args.fee_oracle,
                 test_event_tx.clone(),
                 shutdown.clone()
             ),
@@ -231,13 +260,6 @@ impl UserNode {
             args.lsp.url.clone(),
         )?;
 
-        // Validate esplora url
-        let esplora_url = &args.esplora_url;
-        info!(%esplora_url);
-        network
-            .validate_esplora_url(esplora_url)
-            .context("Invalid esplora url")?;
-
         // Init LDK transaction sync; share LexeEsplora's connection pool
         // XXX(max): The esplora url passed to LDK is security-critical and thus
         // should use Blockstream.info when `Network` is `Mainnet`.
@@ -271,6 +293,7 @@ impl UserNode {
         };
 
         // Initialize Persister
+        let counters = Arc::new(NodeCounters::new());
         let persister = Arc::new(NodePersister::new(
             backend_api.clone(),
             authenticator,
@@ -279,6 +302,27 @@ impl UserNode {
             user,
             shutdown.clone(),
             channel_monitor_persister_tx,
+            counters.clone(),
+        ));
+
+        // Claim the fencing token before persisting anything, so that any
+        // previous instance of this node which is somehow still running stops
+        // persisting and shuts down.
+        persister
+            .acquire_fence()
+            .await
+            .context("Failed to acquire fencing token")?;
+        // Previous instances are now fenced off, so any GDrive change from
+        // here on which isn't ours comes from a conflicting writer.
+        if let Some(ref google_vfs) = maybe_google_vfs {
+            google_vfs
+                .rebase_revisions()
+                .await
+                .context("Failed to rebase GDrive revisions")?;
+        }
+        tasks.push(fencing::spawn_fence_check_task(
+            persister.clone(),
+            shutdown.clone(),
         ));
 
         // Initialize the chain monitor
@@ -308,16 +352,38 @@ impl UserNode {
             try_network_graph,
             try_wallet_db,
             try_scid,
+            try_scid_pool,
             try_pending_payments,
             try_finalized_payment_ids,
+            try_expected_deposits,
+            try_remote_config,
+            try_fee_policy,
+            try_decommissioned,
+            try_dead_letters,
         ) = tokio::join!(
             read_maybe_approved_versions,
             persister.read_network_graph(network, logger.clone()),
             persister.read_wallet_db(wallet_db_persister_tx),
             persister.read_scid(),
+            persister.read_scid_pool(),
             persister.read_pending_payments(),
             persister.read_finalized_payment_ids(),
+            persister.read_expected_deposits(),
+            persister.read_remote_config(deploy_env),
+            persister.read_fee_policy(),
+            persister.read_decommissioned(),
+            persister.read_dead_letters(),
         );
+        // Running two nodes from the same state risks losing funds, so never
+        // run once our state has been exported for migration.
+        let maybe_decommissioned = try_decommissioned
+            .context("Couldn't check whether node was decommissioned")?;
+        if let Some(decommissioned_at) = maybe_decommissioned {
+            bail!(
+                "Node state was exported for migration at \
+                 {decommissioned_at:?}; refusing to run"
+            );
+        }
         if deploy_env.is_staging_or_prod() {
             let maybe_approved_versions = try_maybe_approved_versions
                 .context("Couldn't read approved versions")?;
@@ -347,18 +413,42 @@ impl UserNode {
             .context("Could not read network graph")?;
         let wallet_db = try_wallet_db.context("Could not read wallet db")?;
         let maybe_scid = try_scid.context("Could not read scid")?;
-        let scid = match maybe_scid {
-            Some(s) => s,
-            // We has not been assigned an scid yet; ask the LSP for one
-            None => lsp_api
-                .get_new_scid(user.node_pk)
-                .await
-                .context("Could not get new scid from LSP")?,
-        };
+        let maybe_scid_pool =
+            try_scid_pool.context("Could not read scid pool")?;
+        let scid_pool = init_scid_pool(
+            &persister,
+            &*lsp_api,
+            user.node_pk,
+            maybe_scid,
+            maybe_scid_pool,
+        )
+        .await
+        .context("Could not init scid pool")?;
+        let scid = scid_pool.current();
         let pending_payments =
             try_pending_payments.context("Could not read pending payments")?;
         let finalized_payment_ids = try_finalized_payment_ids
             .context("Could not read finalized payment ids")?;
+        let expected_deposits = try_expected_deposits
+            .context("Could not read expected deposits")?;
+        let remote_config =
+            try_remote_config.context("Could not read remote config")?;
+        // The user's limits on outbound payment fees
+        let fee_policy = try_fee_policy.context("Failed to read fee policy")?;
+        let dead_letters = try_dead_letters
+            .map(|dlq| Arc::new(tokio::sync::Mutex::new(dlq)))
+            .context("Could not read dead letter queue")?;
+
+        // Validate esplora url. The remote config may allow additional urls.
+        let esplora_url = &args.esplora_url;
+        info!(%esplora_url);
+        if !remote_config.allows_esplora_url(esplora_url) {
+            network
+                .validate_esplora_url(esplora_url)
+                .context("Invalid esplora url")?;
+        }
+        esplora
+            .set_min_feerate(remote_config.fee_config.min_feerate_sat_per_kw);
 
         // Init BDK wallet; share esplora connection pool, spawn persister task
         let wallet = LexeWallet::new(
@@ -409,12 +499,17 @@ impl UserNode {
 
         // Initialize Router
         let scoring_fee_params = ProbabilisticScoringFeeParameters::default();
-        let router = Arc::new(DefaultRouter::new(
+        let default_router = DefaultRouter::new(
             network_graph.clone(),
             logger.clone(),
             keys_manager.get_secure_random_bytes(),
             scorer.clone(),
             scoring_fee_params,
+        );
+        let router = Arc::new(LexeRouter::new(
+            default_router,
+            &remote_config.fee_config,
+            fee_policy.clone(),
         ));
 
         // Read channel manager
@@ -471,17 +566,23 @@ impl UserNode {
             logger.clone(),
         );
 
+        // Tasks which update Lightning state listen on a child shutdown
+        // channel, so that they can be stopped for a state export while the
+        // API servers keep running.
+        let ln_freeze = Arc::new(LnFreeze::new(&shutdown));
+        let mut ln_tasks = Vec::with_capacity(8);
+
         // The LSP is the only peer the p2p reconnector needs to reconnect to,
         // but we do so only *after* we have completed init and sync; it is our
         // signal to the LSP that we are ready to receive messages.
         let initial_channel_peers = Vec::new();
 
         // Spawn the task to regularly reconnect to channel peers
-        tasks.push(p2p::spawn_p2p_reconnector(
+        ln_tasks.push(p2p::spawn_p2p_reconnector(
             peer_manager.clone(),
             initial_channel_peers,
             channel_peer_rx,
-            shutdown.clone(),
+            ln_freeze.ln_shutdown(),
         ));
 
         // Init payments manager
@@ -492,15 +593,22 @@ impl UserNode {
             esplora.clone(),
             pending_payments,
             finalized_payment_ids,
+            expected_deposits,
             wallet.clone(),
             onchain_recv_rx,
             test_event_tx.clone(),
-            shutdown.clone(),
+            ln_freeze.ln_shutdown(),
         );
-        tasks.extend(payments_tasks);
+        ln_tasks.extend(payments_tasks);
+
+        // Channels which recently failed our payments, shared between the
+        // event handler (which populates it) and the payment routers.
+        let route_blacklist = Arc::new(RouteBlacklist::new());
 
         // Initialize the event handler
         let fatal_event = Arc::new(AtomicBool::new(false));
+        let quiescing = Arc::new(AtomicBool::new(false));
+        let channel_ops = Arc::new(ChannelOperations::default());
         let event_handler = NodeEventHandler {
             lsp: args.lsp.clone(),
             wallet: wallet.clone(),
@@ -508,7 +616,11 @@ impl UserNode {
             keys_manager: keys_manager.clone(),
             esplora: esplora.clone(),
             network_graph: network_graph.clone(),
+            route_blacklist: route_blacklist.clone(),
             payments_manager: payments_manager.clone(),
+            persister: persister.clone(),
+            channel_ops: channel_ops.clone(),
+            dead_letters,
             fatal_event: fatal_event.clone(),
             test_event_tx: test_event_tx.clone(),
             shutdown: shutdown.clone(),
@@ -517,22 +629,40 @@ impl UserNode {
         // Set up the channel monitor persistence task
         let (process_events_tx, process_events_rx) =
             mpsc::channel(DEFAULT_CHANNEL_SIZE);
-        tasks.push(channel_monitor::spawn_channel_monitor_persister_task(
+        ln_tasks.push(channel_monitor::spawn_channel_monitor_persister_task(
             chain_monitor.clone(),
             channel_monitor_persister_rx,
             process_events_tx,
-            shutdown.clone(),
+            test_event_tx.clone(),
+            ln_freeze.ln_shutdown(),
         ));
 
         // Start API server for app
+        // Track channel activity for stale channel detection
+        let channel_activity =
+            Arc::new(Mutex::new(ChannelActivityLog::default()));
+        tasks.push(channel_health::spawn_channel_health_task(
+            persister.clone(),
+            channel_manager.clone(),
+            channel_activity.clone(),
+            shutdown.clone(),
+        ));
+
         let app_router_state = Arc::new(AppRouterState {
             version,
+            user_pk: args.user_pk,
             persister: persister.clone(),
             chain_monitor: chain_monitor.clone(),
             wallet: wallet.clone(),
             esplora: esplora.clone(),
             router: router.clone(),
+            network_graph: network_graph.clone(),
+            route_blacklist,
             channel_manager: channel_manager.clone(),
+            channel_activity,
+            channel_ops,
+            settings_lock: tokio::sync::Mutex::new(()),
+            fee_policy: tokio::sync::Mutex::new(fee_policy),
             peer_manager: peer_manager.clone(),
             keys_manager: keys_manager.clone(),
             payments_manager: payments_manager.clone(),
@@ -541,7 +671,23 @@ impl UserNode {
             network,
             measurement,
             activity_tx,
+            counters: counters.clone(),
+            quiescing: quiescing.clone(),
+            ln_freeze: ln_freeze.clone(),
+            shutdown: shutdown.clone(),
         });
+        // Capture the logs of failed requests to the app and lexe servers
+        let (error_report_tx, error_report_rx) =
+            log_capture::error_report_channel();
+        tasks.push(log_capture::spawn_error_report_logger(
+            error_report_rx,
+            shutdown.clone(),
+        ));
+        let server_layer_config = LayerConfig {
+            log_capture: Some(LogCapture::for_env(deploy_env, error_report_tx)),
+            ..Default::default()
+        };
+
         let app_listener =
             TcpListener::bind(net::LOCALHOST_WITH_EPHEMERAL_PORT)
                 .context("Failed to bind app listener")?;
@@ -549,15 +695,23 @@ impl UserNode {
             .local_addr()
             .context("Couldn't get app addr")?
             .port();
+        let seed_rotation = persister
+            .read_seed_rotation()
+            .await
+            .context("Failed to read seed rotation state")?;
         let (app_tls_config, app_dns) =
-            tls::shared_seed::app_node_run_server_config(rng, &root_seed)
-                .context("Failed to build owner service TLS config")?;
+            tls::shared_seed::app_node_run_server_config(
+                rng,
+                &root_seed,
+                seed_rotation.as_ref(),
+            )
+            .context("Failed to build owner service TLS config")?;
         const APP_SERVER_SPAN_NAME: &str = "(app-node-run-server)";
         let (app_server_task, _app_url) =
             common::api::server::spawn_server_task_with_listener(
                 app_listener,
-                server::app_router(app_router_state),
-                LayerConfig::default(),
+                server::app_router(app_router_state, args.app_limits),
+                server_layer_config.clone(),
                 Some((Arc::new(app_tls_config), app_dns.as_str())),
                 APP_SERVER_SPAN_NAME,
                 info_span!(parent: None, APP_SERVER_SPAN_NAME),
@@ -566,16 +720,34 @@ impl UserNode {
             .context("Failed to spawn app node run server task")?;
         tasks.push(app_server_task);
 
+        // Periodically read back the user's GDrive backups
+        if maybe_google_vfs.is_some() {
+            tasks.push(backup_verifier::spawn_backup_verifier_task(
+                BackupVerifierConfig::default(),
+                persister.clone(),
+                keys_manager.clone(),
+                network,
+                counters.clone(),
+                shutdown.clone(),
+            ));
+        }
+
         // Start API server for Lexe operators
         // TODO(phlip9): authenticate lexe<->node
         let lexe_router_state = Arc::new(LexeRouterState {
             user_pk: args.user_pk,
+            deploy_env,
             channel_manager: channel_manager.clone(),
             peer_manager: peer_manager.clone(),
+            counters,
+            persister: persister.clone(),
+            event_handler: event_handler.clone(),
             lsp_info: args.lsp.clone(),
             bdk_resync_tx,
             ldk_resync_tx,
+            test_event_tx: test_event_tx.clone(),
             test_event_rx,
+            quiescing: quiescing.clone(),
             shutdown: shutdown.clone(),
         });
         let lexe_listener =
@@ -588,7 +760,7 @@ impl UserNode {
             common::api::server::spawn_server_task_with_listener(
                 lexe_listener,
                 server::lexe_router(lexe_router_state),
-                LayerConfig::default(),
+                server_layer_config,
                 lexe_tls_and_dns,
                 LEXE_SERVER_SPAN_NAME,
                 info_span!(parent: None, LEXE_SERVER_SPAN_NAME),
@@ -616,9 +788,9 @@ impl UserNode {
             scorer.clone(),
             process_events_rx,
             fatal_event,
-            shutdown.clone(),
+            ln_freeze.ln_shutdown(),
         );
-        tasks.push(bg_processor_task);
+        ln_tasks.push(bg_processor_task);
 
         // Construct (but don't start) the inactivity timer
         let inactivity_timer = InactivityTimer::new(
@@ -638,8 +810,12 @@ impl UserNode {
             deploy_env,
             ports,
             tasks,
+            ln_tasks,
+            ln_freeze,
             channel_peer_tx,
             shutdown,
+            quiescing,
+            runner_api: runner_api.clone(),
 
             // Actors
             logger,
@@ -668,6 +844,7 @@ impl UserNode {
                 onchain_recv_tx,
                 bdk_resync_rx,
                 ldk_resync_rx,
+                test_event_tx,
             }),
         })
     }
@@ -679,12 +856,13 @@ impl UserNode {
 
         // BDK: Do initial wallet sync
         let (first_bdk_sync_tx, first_bdk_sync_rx) = oneshot::channel();
-        self.tasks.push(sync::spawn_bdk_sync_task(
+        self.ln_tasks.push(sync::spawn_bdk_sync_task(
             self.wallet.clone(),
             ctxt.onchain_recv_tx,
             first_bdk_sync_tx,
             ctxt.bdk_resync_rx,
-            self.shutdown.clone(),
+            ctxt.test_event_tx.clone(),
+            self.ln_freeze.ln_shutdown(),
         ));
         let bdk_sync_fut = first_bdk_sync_rx
             .map(|res| res.context("Failed to recv result of first BDK sync"));
@@ -697,14 +875,36 @@ impl UserNode {
             ctxt.ldk_sync_client,
             first_ldk_sync_tx,
             ctxt.ldk_resync_rx,
-            self.shutdown.clone(),
+            ctxt.test_event_tx,
+            self.ln_freeze.ln_shutdown(),
         ));
         let ldk_sync_fut = first_ldk_sync_rx
             .map(|res| res.context("Failed to recv result of first LDK sync"));
 
-        // Sync BDK and LDK concurrently
-        let (try_first_bdk_sync, try_first_ldk_sync) =
-            tokio::try_join!(bdk_sync_fut, ldk_sync_fut)?;
+        // RGS: Populate the network graph from a snapshot if configured. This
+        // is best-effort; if it fails, we fall back to P2P gossip.
+        let rgs_fut = async {
+            let rgs_url = match self.args.rgs_url {
+                Some(ref url) => url,
+                None => return,
+            };
+            let res = rgs::sync_network_graph(
+                rgs_url,
+                &self.network_graph,
+                self.logger.clone(),
+            )
+            .await;
+            if let Err(e) = res {
+                warn!("Rapid gossip sync failed; using P2P gossip: {e:#}");
+            }
+        };
+
+        // Sync BDK, LDK, and RGS concurrently
+        let (try_syncs, ()) = tokio::join!(
+            async { tokio::try_join!(bdk_sync_fut, ldk_sync_fut) },
+            rgs_fut,
+        );
+        let (try_first_bdk_sync, try_first_ldk_sync) = try_syncs?;
         try_first_bdk_sync.context("Initial BDK sync failed")?;
         try_first_ldk_sync.context("Initial LDK sync failed")?;
 
@@ -756,19 +956,51 @@ impl UserNode {
             .into_iter()
             .map(|task| task.with_name())
             .collect::<FuturesUnordered<_>>();
+        let mut ln_tasks = self
+            .ln_tasks
+            .into_iter()
+            .map(|task| task.with_name())
+            .collect::<FuturesUnordered<_>>();
+        let mut ln_shutdown = self.ln_freeze.ln_shutdown();
 
         // Wait for a shutdown signal and poll all tasks so we can (1) propagate
         // panics and (2) detect if a task finished prematurely, in which case a
-        // [partial] failure occurred and we should shut down.
-        tokio::select! {
-            // Mitigate possible select! race after a shutdown signal is sent
-            biased;
-            () = self.shutdown.recv() => (),
-            Some(output) = tasks.next() => {
-                task::log_finished_task(&output, true);
-                self.shutdown.send();
+        // [partial] failure occurred and we should shut down. The Lightning
+        // tasks may also finish because the node is freezing its state for an
+        // export, in which case we keep serving the app until shutdown.
+        loop {
+            tokio::select! {
+                // Mitigate possible select! race after a shutdown signal is
+                // sent
+                biased;
+                () = self.shutdown.recv() => break,
+                Some(output) = tasks.next() => {
+                    task::log_finished_task(&output, true);
+                    self.shutdown.send();
+                    break;
+                }
+                () = ln_shutdown.recv() => {
+                    // A Lightning task shut down its siblings after an error.
+                    if !self.ln_freeze.is_requested() {
+                        self.shutdown.send();
+                        break;
+                    }
+                }
+                Some(output) = ln_tasks.next() => {
+                    let frozen = ln_shutdown.try_recv()
+                        && self.ln_freeze.is_requested();
+                    task::log_finished_task(&output, !frozen);
+                    if !frozen {
+                        self.shutdown.send();
+                        break;
+                    }
+                    if ln_tasks.is_empty() {
+                        self.ln_freeze.set_frozen();
+                    }
+                }
             }
         }
+        tasks.extend(ln_tasks);
 
         // --- Shutdown --- //
         info!("Received shutdown; disconnecting all peers");
@@ -779,6 +1011,7 @@ impl UserNode {
         info!("Waiting on all tasks to finish");
         let timeout = tokio::time::sleep(SHUTDOWN_TIME_LIMIT);
         tokio::pin!(timeout);
+        let mut all_tasks_finished = true;
         while !tasks.is_empty() {
             tokio::select! {
                 () = &mut timeout => {
@@ -791,6 +1024,7 @@ impl UserNode {
                     //               stuck task?
 
                     error!("{} tasks failed to finish: {stuck_tasks:?}", stuck_tasks.len());
+                    all_tasks_finished = false;
                     break;
                 }
                 Some(output) = tasks.next() =>
@@ -798,6 +1032,32 @@ impl UserNode {
             }
         }
 
+        // --- Quiesce --- //
+        if self.quiescing.load(Ordering::Acquire) {
+            // A stuck task may still persist something, so we can't guarantee
+            // that the next instance won't overlap with us. Keep the fencing
+            // token; the next instance will fence us off instead.
+            ensure!(
+                all_tasks_finished,
+                "Can't complete quiesce: not all tasks finished"
+            );
+
+            let fencing_epoch = self
+                .persister
+                .release_fence()
+                .await
+                .context("Failed to release fencing token")?;
+            let quiesced = NodeQuiesced {
+                user_pk: self.args.user_pk,
+                fencing_epoch,
+            };
+            self.runner_api
+                .quiesced(&quiesced)
+                .await
+                .context("Could not notify runner of quiesced status")?;
+            info!("Quiesced for migration");
+        }
+
         Ok(())
     }
 }
@@ -950,6 +1210,73 @@ async fn init_google_vfs(
     Ok((google_vfs, credentials_persister_task))
 }
 
+/// Returns the node's [`ScidPool`], creating it if needed, and rotates to a new
+/// SCID from the LSP if the current one is due for rotation.
+///
+/// Once the LSP has allocated us a new SCID, we use it even if we fail to
+/// persist the updated pool. Otherwise the SCID would be leaked: the LSP keeps
+/// it mapped to us, but we'd never use or expire it.
+async fn init_scid_pool(
+    persister: &NodePersister,
+    lsp_api: &(dyn NodeLspApi + Send + Sync),
+    node_pk: NodePk,
+    maybe_scid: Option<Scid>,
+    maybe_scid_pool: Option<ScidPool>,
+) -> anyhow::Result<ScidPool> {
+    let now = TimestampMs::now();
+    let scid_pool = match maybe_scid_pool {
+        Some(scid_pool) => scid_pool,
+        None => {
+            let scid = match maybe_scid {
+                // Start the pool with the scid we've been using so far.
+                Some(scid) => scid,
+                // We has not been assigned an scid yet; ask the LSP for one
+                None => lsp_api
+                    .get_new_scid(node_pk)
+                    .await
+                    .context("Could not get new scid from LSP")?,
+            };
+            let scid_pool = ScidPool::new(scid, now);
+            if let Err(e) = persister.persist_scid_pool(&scid_pool).await {
+                warn!("Could not persist new scid pool: {e:#}");
+            }
+            return Ok(scid_pool);
+        }
+    };
+
+    if !scid_pool.needs_rotation(now) {
+        return Ok(scid_pool);
+    }
+
+    // Failing to rotate isn't fatal; we'll just keep using the current scid
+    // and try again next time.
+    let new_scid = match lsp_api.get_new_scid(node_pk).await {
+        Ok(scid) => scid,
+        Err(e) => {
+            warn!("Could not get new scid for rotation: {e:#}");
+            return Ok(scid_pool);
+        }
+    };
+    // The LSP may hand back the scid we already have; nothing to rotate.
+    if new_scid == scid_pool.current() {
+        debug!(%new_scid, "LSP returned our current scid; not rotating");
+        return Ok(scid_pool);
+    }
+    let mut rotated = scid_pool.clone();
+    let expired = match rotated.rotate(new_scid, now) {
+        Ok(expired) => expired,
+        Err(e) => {
+            warn!("Could not rotate scid: {e:#}");
+            return Ok(scid_pool);
+        }
+    };
+    info!(%new_scid, num_expired = expired.len(), "Rotated scid");
+    if let Err(e) = persister.persist_scid_pool(&rotated).await {
+        warn!("Could not persist rotated scid pool: {e:#}");
+    }
+    Ok(rotated)
+}
+
 /// Handles the logic of whether to reconnect to Lexe's LSP, taking in account
 /// whether we are intend to mock out the LSP as well.
 ///
@@ -1,25 +1,49 @@
 use std::sync::Arc;
 
+use anyhow::anyhow;
 use axum::extract::State;
 use common::{
     api::{
         command::{
-            CreateInvoiceRequest, CreateInvoiceResponse, NodeInfo,
-            PayInvoiceRequest, PayInvoiceResponse, PayOnchainRequest,
-            PayOnchainResponse, PreflightPayInvoiceRequest,
+            BumpReceiveRequest, BumpReceiveResponse, ChannelHealthResponse,
+            ChannelOperation, ChannelOperationId, ChannelOperationKind,
+            CloseChannelRequest, CreateInvoiceRequest, CreateInvoiceResponse,
+            GDriveStorageStatus, GetAddressForRequest, NodeInfo,
+            OpenChannelRequest, PayInvoiceRequest, PayInvoiceResponse,
+            PayOnchainRequest, PayOnchainResponse, PreflightPayInvoiceRequest,
             PreflightPayInvoiceResponse, PreflightPayOnchainRequest,
-            PreflightPayOnchainResponse,
+            PreflightPayOnchainResponse, QueryPayments, QueryPaymentsResponse,
         },
         error::NodeApiError,
+        migration::{
+            DecommissionRequest, ExportStateRequest, ExportStateResponse,
+            StateArchive,
+        },
         qs::{GetNewPayments, GetPaymentsByIds, UpdatePaymentNote},
-        server::{extract::LxQuery, LxJson},
+        server::{
+            extract::{LxAccept, LxQuery},
+            LxBody, LxJson,
+        },
+        settings::SettingsDoc,
+        user::UserProfile,
+        vfs::RestoreFileVersionRequest,
         Empty,
     },
-    ln::payments::BasicPayment,
+    ln::{
+        fee_policy::{FeeExceedsPolicy, FeePolicy},
+        payments::BasicPayment,
+    },
+    password,
+    rng::{RngExt, SysRng},
+    task::LxTask,
+    time::TimestampMs,
+    tls::attestation::{self, evidence::EvidenceBundle, NodeMode},
 };
-use lexe_ln::command::CreateInvoiceCaller;
+use lexe_ln::{channel::ChannelRelationship, command::CreateInvoiceCaller};
+use tracing::warn;
 
 use super::AppRouterState;
+use crate::channel_manager;
 
 pub(super) async fn node_info(
     State(state): State<Arc<AppRouterState>>,
@@ -65,12 +89,14 @@ pub(super) async fn pay_invoice(
     lexe_ln::command::pay_invoice(
         req,
         state.router.clone(),
+        state.network_graph.clone(),
+        state.route_blacklist.clone(),
         state.channel_manager.clone(),
         state.payments_manager.clone(),
     )
     .await
     .map(LxJson)
-    .map_err(NodeApiError::command)
+    .map_err(pay_error)
 }
 
 pub(super) async fn preflight_pay_invoice(
@@ -80,12 +106,23 @@ pub(super) async fn preflight_pay_invoice(
     lexe_ln::command::preflight_pay_invoice(
         req,
         state.router.clone(),
+        state.network_graph.clone(),
+        state.route_blacklist.clone(),
         state.channel_manager.clone(),
         state.payments_manager.clone(),
     )
     .await
     .map(LxJson)
-    .map_err(NodeApiError::command)
+    .map_err(pay_error)
+}
+
+/// Like [`NodeApiError::command`], but surfaces [`FeeExceedsPolicy`] errors
+/// with their own error kind, so the app can tell the user what happened.
+fn pay_error(error: anyhow::Error) -> NodeApiError {
+    match error.downcast::<FeeExceedsPolicy>() {
+        Ok(error) => NodeApiError::fee_exceeds_policy(error),
+        Err(error) => NodeApiError::command(error),
+    }
 }
 
 pub(super) async fn pay_onchain(
@@ -113,6 +150,21 @@ pub(super) async fn preflight_pay_onchain(
         .map_err(NodeApiError::command)
 }
 
+pub(super) async fn bump_receive(
+    State(state): State<Arc<AppRouterState>>,
+    LxJson(req): LxJson<BumpReceiveRequest>,
+) -> Result<LxJson<BumpReceiveResponse>, NodeApiError> {
+    lexe_ln::command::bump_receive(
+        req,
+        state.wallet.clone(),
+        state.esplora.clone(),
+        state.payments_manager.clone(),
+    )
+    .await
+    .map(LxJson)
+    .map_err(NodeApiError::command)
+}
+
 pub(super) async fn get_address(
     State(state): State<Arc<AppRouterState>>,
 ) -> Result<LxJson<bitcoin::Address>, NodeApiError> {
@@ -122,27 +174,170 @@ pub(super) async fn get_address(
         .map_err(NodeApiError::command)
 }
 
+pub(super) async fn get_address_for(
+    State(state): State<Arc<AppRouterState>>,
+    LxJson(req): LxJson<GetAddressForRequest>,
+) -> Result<LxJson<bitcoin::Address>, NodeApiError> {
+    state
+        .payments_manager
+        .get_address_for(&state.wallet, req)
+        .await
+        .map(LxJson)
+        .map_err(NodeApiError::command)
+}
+
+pub(super) async fn channel_health(
+    State(state): State<Arc<AppRouterState>>,
+) -> Result<LxJson<ChannelHealthResponse>, NodeApiError> {
+    let activity_log = state.channel_activity.lock().unwrap();
+    let resp = lexe_ln::command::channel_health(
+        state.channel_manager.clone(),
+        &state.esplora,
+        &activity_log,
+        &state.lsp_info.node_pk,
+    );
+    Ok(LxJson(resp))
+}
+
+pub(super) async fn gdrive_storage_status(
+    State(state): State<Arc<AppRouterState>>,
+) -> Result<LxJson<GDriveStorageStatus>, NodeApiError> {
+    state
+        .persister
+        .gdrive_storage_status()
+        .await
+        .map(LxJson)
+        .map_err(NodeApiError::command)
+}
+
+pub(super) async fn open_channel(
+    State(state): State<Arc<AppRouterState>>,
+    LxJson(req): LxJson<OpenChannelRequest>,
+) -> Result<LxJson<ChannelOperationId>, NodeApiError> {
+    let mut rng = SysRng::new();
+    let user_channel_id = rng.gen_u128();
+    let kind = ChannelOperationKind::Open;
+    let id = state
+        .channel_ops
+        .start(&mut rng, kind, user_channel_id, None);
+
+    // Connecting to the LSP and negotiating the channel can take a while, so
+    // do it in the background and let the app track the progress instead.
+    // The task is detached, so stop it if the node shuts down or freezes its
+    // Lightning state in the meantime.
+    let mut shutdown = state.ln_freeze.ln_shutdown();
+    LxTask::spawn_named("open channel", async move {
+        let relationship = ChannelRelationship::UserToLsp {
+            lsp_channel_peer: state.lsp_info.channel_peer(),
+        };
+        let open = lexe_ln::channel::open_channel(
+            state.channel_manager.clone(),
+            state.peer_manager.clone(),
+            user_channel_id,
+            req.value,
+            relationship,
+            channel_manager::USER_CONFIG,
+        );
+        let result = tokio::select! {
+            result = open => result,
+            () = shutdown.recv() => Err(anyhow!("Node is shutting down")),
+        };
+        if let Err(e) = result {
+            warn!("Failed to open channel: {e:#}");
+            state.channel_ops.fail(&id, format!("{e:#}"));
+        }
+    })
+    .detach();
+
+    Ok(LxJson(id))
+}
+
+pub(super) async fn close_channel(
+    State(state): State<Arc<AppRouterState>>,
+    LxJson(req): LxJson<CloseChannelRequest>,
+) -> Result<LxJson<ChannelOperationId>, NodeApiError> {
+    let channel_id = req.channel_id;
+    let user_channel_id = state
+        .channel_manager
+        .list_channels()
+        .into_iter()
+        .find(|c| c.channel_id == channel_id.0)
+        .map(|c| c.user_channel_id)
+        .ok_or_else(|| {
+            let msg = format!("No channel exists with id {channel_id}");
+            NodeApiError::command(msg)
+        })?;
+
+    // Start tracking before initiating the close so we can't miss the
+    // `ChannelClosed` event.
+    let kind = ChannelOperationKind::Close;
+    let id = state.channel_ops.start(
+        &mut SysRng::new(),
+        kind,
+        user_channel_id,
+        Some(channel_id),
+    );
+    let result = lexe_ln::channel::close_channel(
+        req,
+        state.channel_manager.clone(),
+        state.peer_manager.clone(),
+    );
+    if let Err(e) = result {
+        state.channel_ops.fail(&id, format!("{e:#}"));
+        return Err(NodeApiError::command(e));
+    }
+
+    Ok(LxJson(id))
+}
+
+pub(super) async fn channel_operation(
+    State(state): State<Arc<AppRouterState>>,
+    LxQuery(req): LxQuery<ChannelOperationId>,
+) -> Result<LxJson<ChannelOperation>, NodeApiError> {
+    let channels = state.channel_manager.list_channels();
+    state
+        .channel_ops
+        .get(&req, &channels)
+        .map(LxJson)
+        .ok_or_else(|| NodeApiError::command("Unknown channel operation"))
+}
+
 pub(super) async fn get_payments_by_ids(
     State(state): State<Arc<AppRouterState>>,
+    LxAccept(format): LxAccept,
     LxJson(req): LxJson<GetPaymentsByIds>,
-) -> Result<LxJson<Vec<BasicPayment>>, NodeApiError> {
+) -> Result<LxBody<Vec<BasicPayment>>, NodeApiError> {
     state
         .persister
         .read_payments_by_ids(req)
         .await
-        .map(LxJson)
+        .map(|value| LxBody { format, value })
         .map_err(NodeApiError::command)
 }
 
 pub(super) async fn get_new_payments(
     State(state): State<Arc<AppRouterState>>,
+    LxAccept(format): LxAccept,
     LxQuery(req): LxQuery<GetNewPayments>,
-) -> Result<LxJson<Vec<BasicPayment>>, NodeApiError> {
+) -> Result<LxBody<Vec<BasicPayment>>, NodeApiError> {
     state
         .persister
         .read_new_payments(req)
         .await
-        .map(LxJson)
+        .map(|value| LxBody { format, value })
+        .map_err(NodeApiError::command)
+}
+
+pub(super) async fn query_payments(
+    State(state): State<Arc<AppRouterState>>,
+    LxAccept(format): LxAccept,
+    LxJson(req): LxJson<QueryPayments>,
+) -> Result<LxBody<QueryPaymentsResponse>, NodeApiError> {
+    state
+        .persister
+        .query_payments(req)
+        .await
+        .map(|value| LxBody { format, value })
         .map_err(NodeApiError::command)
 }
 
@@ -157,3 +352,185 @@ pub(super) async fn update_payment_note(
         .map(|()| LxJson(Empty {}))
         .map_err(NodeApiError::command)
 }
+
+pub(super) async fn get_user_profile(
+    State(state): State<Arc<AppRouterState>>,
+) -> Result<LxJson<UserProfile>, NodeApiError> {
+    state
+        .persister
+        .read_user_profile()
+        .await
+        .map(LxJson)
+        .map_err(NodeApiError::command)
+}
+
+pub(super) async fn update_user_profile(
+    State(state): State<Arc<AppRouterState>>,
+    LxJson(req): LxJson<UserProfile>,
+) -> Result<LxJson<Empty>, NodeApiError> {
+    req.validate().map_err(NodeApiError::command)?;
+    state
+        .persister
+        .persist_user_profile(&req)
+        .await
+        .map(|()| LxJson(Empty {}))
+        .map_err(NodeApiError::command)
+}
+
+pub(super) async fn get_settings(
+    State(state): State<Arc<AppRouterState>>,
+) -> Result<LxJson<SettingsDoc>, NodeApiError> {
+    state
+        .persister
+        .read_settings()
+        .await
+        .map(LxJson)
+        .map_err(NodeApiError::command)
+}
+
+pub(super) async fn sync_settings(
+    State(state): State<Arc<AppRouterState>>,
+    LxJson(req): LxJson<SettingsDoc>,
+) -> Result<LxJson<SettingsDoc>, NodeApiError> {
+    req.validate().map_err(NodeApiError::command)?;
+
+    // Hold the lock so concurrent syncs from multiple devices can't clobber
+    // each other's changes.
+    let _lock = state.settings_lock.lock().await;
+    let mut settings = state
+        .persister
+        .read_settings()
+        .await
+        .map_err(NodeApiError::command)?;
+
+    let merged = settings.merge(&req);
+    let collected = settings.gc_tombstones(TimestampMs::now());
+    if merged || collected {
+        settings.version += 1;
+        settings.validate().map_err(NodeApiError::command)?;
+        state
+            .persister
+            .persist_settings(&settings)
+            .await
+            .map_err(NodeApiError::command)?;
+    }
+
+    Ok(LxJson(settings))
+}
+
+pub(super) async fn get_fee_policy(
+    State(state): State<Arc<AppRouterState>>,
+) -> Result<LxJson<FeePolicy>, NodeApiError> {
+    let fee_policy = state.fee_policy.lock().await.clone();
+    Ok(LxJson(fee_policy))
+}
+
+pub(super) async fn update_fee_policy(
+    State(state): State<Arc<AppRouterState>>,
+    LxJson(req): LxJson<FeePolicy>,
+) -> Result<LxJson<Empty>, NodeApiError> {
+    req.validate().map_err(NodeApiError::command)?;
+
+    let mut fee_policy = state.fee_policy.lock().await;
+    state
+        .persister
+        .persist_fee_policy(&req)
+        .await
+        .map_err(NodeApiError::command)?;
+    state.router.set_fee_policy(req.clone());
+    *fee_policy = req;
+
+    Ok(LxJson(Empty {}))
+}
+
+pub(super) async fn export_state(
+    State(state): State<Arc<AppRouterState>>,
+    LxJson(req): LxJson<ExportStateRequest>,
+) -> Result<LxJson<ExportStateResponse>, NodeApiError> {
+    let ExportStateRequest {
+        password,
+        decommission,
+    } = req;
+    password::validate_password_len(&password)
+        .map_err(NodeApiError::command)?;
+
+    // Stop all Lightning activity first so that the snapshot is our final
+    // Lightning state. The node stays frozen until it restarts.
+    if decommission {
+        state
+            .ln_freeze
+            .freeze(&state.peer_manager)
+            .await
+            .map_err(NodeApiError::command)?;
+    }
+
+    let files = state
+        .persister
+        .read_all_files()
+        .await
+        .map_err(NodeApiError::command)?;
+    let archive = StateArchive::new(state.user_pk, files)
+        .password_encrypt(&mut SysRng::new(), &password)
+        .map_err(NodeApiError::command)?;
+
+    if decommission {
+        state.ln_freeze.record_export(&archive);
+    }
+
+    Ok(LxJson(ExportStateResponse { archive }))
+}
+
+pub(super) async fn decommission(
+    State(state): State<Arc<AppRouterState>>,
+    LxJson(req): LxJson<DecommissionRequest>,
+) -> Result<LxJson<Empty>, NodeApiError> {
+    state
+        .ln_freeze
+        .check_export(&req.archive_sha256)
+        .map_err(NodeApiError::command)?;
+
+    // The app has the final state, so the exported archive can now be run
+    // elsewhere; make sure this node never runs again.
+    state
+        .persister
+        .persist_decommissioned()
+        .await
+        .map_err(NodeApiError::command)?;
+    // Graceful shutdown lets this response finish sending.
+    warn!("Node decommissioned; shutting down");
+    state.shutdown.send();
+
+    Ok(LxJson(Empty {}))
+}
+
+pub(super) async fn restore_file_version(
+    State(state): State<Arc<AppRouterState>>,
+    LxJson(req): LxJson<RestoreFileVersionRequest>,
+) -> Result<LxJson<Empty>, NodeApiError> {
+    // Stop all Lightning activity so that the channel manager we're about to
+    // replace isn't persisted again before we restart.
+    state
+        .ln_freeze
+        .freeze(&state.peer_manager)
+        .await
+        .map_err(NodeApiError::command)?;
+    state
+        .persister
+        .restore_file_version(&req.filename, req.version)
+        .await
+        .map_err(NodeApiError::command)?;
+    // Restart so that the node runs from the restored file.
+    warn!("Restored {} v{}; shutting down", req.filename, req.version);
+    state.shutdown.send();
+
+    Ok(LxJson(Empty {}))
+}
+
+pub(super) async fn get_attestation_evidence(
+) -> Result<LxJson<EvidenceBundle>, NodeApiError> {
+    // The attestation cert was already generated when the node first
+    // connected to Lexe, so this just reuses it.
+    attestation::node_evidence_bundle(&mut SysRng::new(), NodeMode::Run)
+        .map(LxJson)
+        .map_err(NodeApiError::command)
+}
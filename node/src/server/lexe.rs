@@ -1,19 +1,34 @@
-use std::sync::Arc;
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
 use axum::extract::State;
 use common::{
     api::{
-        command::OpenChannelRequest,
-        error::NodeApiError,
+        command::{
+            EventHistory, NodeMetrics, OpenChannelRequest, QuarantinedEvents,
+            ReinjectEventRequest,
+        },
+        error::{NodeApiError, NodeErrorKind},
+        event_history::SignedEventHistoryRequest,
+        log_levels::SignedLogLevels,
         qs::GetByUserPk,
-        server::{extract::LxQuery, LxJson},
+        server::{extract::LxQuery, health::HealthChecks, LxJson},
         Empty,
     },
+    task,
     test_event::TestEventOp,
+    time::TimestampMs,
 };
-use lexe_ln::test_event;
+use lexe_ln::{event, logger, test_event};
+use lightning::events::EventHandler;
+use tracing::info;
+
+use crate::{metrics, persister::NodePersister, server::LexeRouterState};
 
-use crate::server::LexeRouterState;
+/// How long the result of a readiness check is reused; see [`health_checks`].
+const READINESS_CACHE_TIME: Duration = Duration::from_secs(10);
 
 pub(super) async fn status(
     State(state): State<Arc<LexeRouterState>>,
@@ -26,6 +41,106 @@ pub(super) async fn status(
     }
 }
 
+/// The dependency checks run by `/readyz`. Both call Lexe's backend, so their
+/// results are reused for [`READINESS_CACHE_TIME`].
+pub(super) fn health_checks(persister: Arc<NodePersister>) -> HealthChecks {
+    let auth_persister = persister.clone();
+    HealthChecks::new()
+        .with_cached_check("auth", READINESS_CACHE_TIME, move || {
+            let persister = auth_persister.clone();
+            async move { persister.check_auth().await }
+        })
+        .with_cached_check("vfs", READINESS_CACHE_TIME, move || {
+            let persister = persister.clone();
+            async move { persister.check_vfs_readable().await }
+        })
+}
+
+pub(super) async fn metrics(
+    State(state): State<Arc<LexeRouterState>>,
+    LxQuery(req): LxQuery<GetByUserPk>,
+) -> Result<LxJson<NodeMetrics>, NodeApiError> {
+    if state.user_pk != req.user_pk {
+        return Err(NodeApiError::wrong_user_pk(state.user_pk, req.user_pk));
+    }
+
+    let counters = &state.counters;
+    let channels = state.channel_manager.list_channels();
+    let num_usable_channels = channels.iter().filter(|c| c.is_usable).count();
+
+    Ok(LxJson(NodeMetrics {
+        user_pk: state.user_pk,
+        started_at: counters.started_at,
+        app_requests: metrics::get(&counters.app_requests),
+        payments_persisted: metrics::get(&counters.payments_persisted),
+        channel_monitor_persists: metrics::get(
+            &counters.channel_monitor_persists,
+        ),
+        file_persists: metrics::get(&counters.file_persists),
+        backup_verification_failures: metrics::get(
+            &counters.backup_verification_failures,
+        ),
+        gdrive_nearly_full: metrics::get(&counters.gdrive_nearly_full),
+        num_channels: channels.len(),
+        num_usable_channels,
+        num_peers: state.peer_manager.get_peer_node_ids().len(),
+        num_task_crashes: task::recent_crashes().len(),
+        channel_monitor_queue: state.persister.channel_monitor_queue_metrics(),
+    }))
+}
+
+pub(super) async fn quarantined_events(
+    State(state): State<Arc<LexeRouterState>>,
+    LxQuery(req): LxQuery<GetByUserPk>,
+) -> Result<LxJson<QuarantinedEvents>, NodeApiError> {
+    if state.user_pk != req.user_pk {
+        return Err(NodeApiError::wrong_user_pk(state.user_pk, req.user_pk));
+    }
+
+    let dead_letters = state.event_handler.dead_letters.lock().await;
+    let events = dead_letters.quarantined().to_vec();
+    Ok(LxJson(QuarantinedEvents { events }))
+}
+
+pub(super) async fn event_history(
+    State(state): State<Arc<LexeRouterState>>,
+    LxJson(req): LxJson<SignedEventHistoryRequest>,
+) -> Result<LxJson<EventHistory>, NodeApiError> {
+    req.verify(state.deploy_env, &state.user_pk, TimestampMs::now())
+        .map_err(|err| NodeApiError {
+            kind: NodeErrorKind::BadAuth,
+            msg: format!("{err:#}"),
+            data: None,
+        })?;
+
+    let events = state.test_event_tx.history();
+    Ok(LxJson(EventHistory { events }))
+}
+
+pub(super) async fn reinject_event(
+    State(state): State<Arc<LexeRouterState>>,
+    LxJson(req): LxJson<ReinjectEventRequest>,
+) -> Result<LxJson<Empty>, NodeApiError> {
+    if state.user_pk != req.user_pk {
+        return Err(NodeApiError::wrong_user_pk(state.user_pk, req.user_pk));
+    }
+
+    let event = {
+        let dead_letters = state.event_handler.dead_letters.lock().await;
+        let quarantined =
+            dead_letters.get_quarantined(&req.id).ok_or_else(|| {
+                NodeApiError::command("No such quarantined event")
+            })?;
+        event::read_event(&quarantined.event).map_err(NodeApiError::command)?
+    };
+
+    // The event is handled in the background like any other event, and stays
+    // quarantined until it is handled successfully.
+    state.event_handler.handle_event(event);
+
+    Ok(LxJson(Empty {}))
+}
+
 pub(super) async fn resync(
     State(state): State<Arc<LexeRouterState>>,
 ) -> Result<LxJson<Empty>, NodeApiError> {
@@ -94,3 +209,40 @@ pub(super) async fn shutdown(
         Err(NodeApiError::wrong_user_pk(state.user_pk, req.user_pk))
     }
 }
+
+pub(super) async fn quiesce(
+    State(state): State<Arc<LexeRouterState>>,
+    LxQuery(req): LxQuery<GetByUserPk>,
+) -> Result<LxJson<Empty>, NodeApiError> {
+    if state.user_pk != req.user_pk {
+        return Err(NodeApiError::wrong_user_pk(state.user_pk, req.user_pk));
+    }
+
+    // Stop accepting app requests, then shut down as usual. The fencing token
+    // is released and the runner notified at the end of `UserNode::run`.
+    info!("Quiescing for migration");
+    state.quiescing.store(true, Ordering::Release);
+    state.shutdown.send();
+    Ok(LxJson(Empty {}))
+}
+
+pub(super) async fn set_log_levels(
+    State(state): State<Arc<LexeRouterState>>,
+    LxJson(req): LxJson<SignedLogLevels>,
+) -> Result<LxJson<Empty>, NodeApiError> {
+    let log_levels = req
+        .verify(state.deploy_env, &state.user_pk, TimestampMs::now())
+        .map_err(|err| NodeApiError {
+            kind: NodeErrorKind::BadAuth,
+            msg: format!("{err:#}"),
+            data: None,
+        })?;
+
+    info!(levels = ?log_levels.levels, "Setting log levels");
+    let levels = log_levels
+        .levels
+        .into_iter()
+        .map(|(target, level)| (target, level.map(Into::into)));
+    logger::set_target_levels(levels).map_err(NodeApiError::command)?;
+    Ok(LxJson(Empty {}))
+}
@@ -0,0 +1,97 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use common::api::{error::NodeApiError, rate_limit::RateLimiter};
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use crate::freeze::LnFreeze;
+
+/// The key under which all app requests are rate limited. The app server only
+/// accepts client certs derived from the user's root seed, so every
+/// authenticated client shares the same identity.
+const APP_CLIENT_KEY: &str = "app";
+
+/// How long clients should wait before retrying a request rejected because an
+/// endpoint is at its concurrency cap.
+const CONCURRENCY_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Rejects requests with a 429 once the client has exhausted its rate limit.
+pub(super) async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match limiter.check(APP_CLIENT_KEY) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            warn!("Rate limited {}", request.uri().path());
+            too_many_requests(retry_after)
+        }
+    }
+}
+
+/// Rejects requests with a 429 if the endpoint is already handling the
+/// maximum # of concurrent requests. Each capped endpoint gets its own
+/// [`Semaphore`].
+pub(super) async fn concurrency_cap(
+    State(semaphore): State<Arc<Semaphore>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let _permit = match semaphore.try_acquire() {
+        Ok(permit) => permit,
+        Err(_) => {
+            warn!("Concurrency cap reached for {}", request.uri().path());
+            return too_many_requests(CONCURRENCY_RETRY_AFTER);
+        }
+    };
+    next.run(request).await
+}
+
+/// Rejects all requests once the node has started quiescing for a migration.
+pub(super) async fn reject_while_quiescing(
+    State(quiescing): State<Arc<AtomicBool>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if quiescing.load(Ordering::Acquire) {
+        return NodeApiError::migrating().into_response();
+    }
+    next.run(request).await
+}
+
+/// Rejects requests which need the Lightning tasks once the node has frozen
+/// its Lightning state for an export.
+pub(super) async fn reject_while_frozen(
+    State(ln_freeze): State<Arc<LnFreeze>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if ln_freeze.is_requested() {
+        return NodeApiError::migrating().into_response();
+    }
+    next.run(request).await
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    let mut response = NodeApiError::rate_limited(retry_after).into_response();
+    // `Retry-After` only supports whole seconds; round up.
+    let secs =
+        retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(secs));
+    response
+}
@@ -6,29 +6,42 @@
 //! Lexe cannot spend funds on behalf of the user; Lexe's endpoints are either
 //! used purely for maintenance or only enabled in tests.
 
-use std::sync::Arc;
+use std::sync::{atomic::AtomicBool, Arc, Mutex};
 
 use axum::{
+    middleware::from_fn_with_state,
     routing::{get, post, put},
     Router,
 };
 use common::{
-    api::{Scid, UserPk},
-    cli::{LspInfo, Network},
+    api::{rate_limit::RateLimiter, Scid, UserPk},
+    cli::{node::AppLimitsConfig, LspInfo, Network},
     enclave::Measurement,
+    env::DeployEnv,
+    ln::fee_policy::FeePolicy,
     shutdown::ShutdownChannel,
+    version::{ApiRevision, APP_NODE_R1, APP_NODE_R2},
 };
 use lexe_ln::{
-    alias::RouterType, esplora::LexeEsplora, keys_manager::LexeKeysManager,
-    test_event::TestEventReceiver, wallet::LexeWallet,
+    alias::{NetworkGraphType, RouterType},
+    channel_health::ChannelActivityLog,
+    esplora::LexeEsplora,
+    keys_manager::LexeKeysManager,
+    route::RouteBlacklist,
+    test_event::{TestEventReceiver, TestEventSender},
+    wallet::LexeWallet,
 };
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, Semaphore};
 use tower::util::MapRequestLayer;
 use tracing::debug;
 
 use crate::{
     alias::{ChainMonitorType, NodePaymentsManagerType},
     channel_manager::NodeChannelManager,
+    channel_ops::ChannelOperations,
+    event_handler::NodeEventHandler,
+    freeze::LnFreeze,
+    metrics::{self, NodeCounters},
     peer_manager::NodePeerManager,
     persister::NodePersister,
 };
@@ -37,15 +50,29 @@ use crate::{
 mod app;
 /// Handlers for commands that can only be initiated by the Lexe operators.
 mod lexe;
+/// Rate limiting, concurrency caps, and quiescing for the app server.
+mod limits;
+/// API revision negotiation and deprecation for the app server.
+mod revision;
 
 pub(crate) struct AppRouterState {
     pub version: semver::Version,
+    pub user_pk: UserPk,
     pub persister: Arc<NodePersister>,
     pub chain_monitor: Arc<ChainMonitorType>,
     pub wallet: LexeWallet,
     pub esplora: Arc<LexeEsplora>,
     pub router: Arc<RouterType>,
+    pub network_graph: Arc<NetworkGraphType>,
+    pub route_blacklist: Arc<RouteBlacklist>,
     pub channel_manager: NodeChannelManager,
+    pub channel_activity: Arc<Mutex<ChannelActivityLog>>,
+    pub channel_ops: Arc<ChannelOperations>,
+    /// Serializes settings syncs, which read, merge, then write the doc.
+    pub settings_lock: tokio::sync::Mutex<()>,
+    /// The user's limits on outbound payment fees. Held while persisting
+    /// updates so the in-memory copy matches the persisted one.
+    pub fee_policy: tokio::sync::Mutex<FeePolicy>,
     pub peer_manager: NodePeerManager,
     pub keys_manager: Arc<LexeKeysManager>,
     pub payments_manager: NodePaymentsManagerType,
@@ -54,43 +81,113 @@ pub(crate) struct AppRouterState {
     pub network: Network,
     pub measurement: Measurement,
     pub activity_tx: mpsc::Sender<()>,
+    pub counters: Arc<NodeCounters>,
+    /// Set once the node has started quiescing for a migration.
+    pub quiescing: Arc<AtomicBool>,
+    /// Stops the Lightning tasks for a decommissioning state export.
+    pub ln_freeze: Arc<LnFreeze>,
+    pub shutdown: ShutdownChannel,
 }
 
 /// Implements [`AppNodeRunApi`] - endpoints only callable by the app.
 ///
+/// Each [`ApiRevision`] in [`APP_NODE_REVISIONS`] is served under its own
+/// route tree, e.g. `/v2/app/node_info`. The unprefixed `/app/...` routes used
+/// by apps which predate API versioning are served as [`APP_NODE_R1`].
+///
 /// [`AppNodeRunApi`]: common::api::def::AppNodeRunApi
-pub(crate) fn app_router(state: Arc<AppRouterState>) -> Router<()> {
+pub(crate) fn app_router(
+    state: Arc<AppRouterState>,
+    limits: AppLimitsConfig,
+) -> Router<()> {
     let activity_tx = state.activity_tx.clone();
+    let counters = state.counters.clone();
+    let quiescing = state.quiescing.clone();
+    let ln_freeze = state.ln_freeze.clone();
+    let rate_limiter = Arc::new(RateLimiter::new(limits.rate_limit));
+    // Each expensive endpoint gets its own concurrency cap.
+    let cap = || {
+        let semaphore = Semaphore::new(limits.max_concurrent_expensive);
+        from_fn_with_state(Arc::new(semaphore), limits::concurrency_cap)
+    };
+    // The routes shared by all revisions. When a revision changes an endpoint,
+    // build its tree from the routes shared with the other revisions, then add
+    // the changed endpoint's handler for that revision.
     #[rustfmt::skip]
-    let router = Router::new()
+    let routes = Router::new()
         .route("/app/node_info", get(app::node_info))
         .route("/app/create_invoice", post(app::create_invoice))
-        .route("/app/pay_invoice", post(app::pay_invoice))
-        .route("/app/preflight_pay_invoice", post(app::preflight_pay_invoice))
-        .route("/app/pay_onchain", post(app::pay_onchain))
-        .route("/app/preflight_pay_onchain", post(app::preflight_pay_onchain))
+        .route("/app/pay_invoice", post(app::pay_invoice).layer(cap()))
+        .route("/app/preflight_pay_invoice", post(app::preflight_pay_invoice).layer(cap()))
+        .route("/app/pay_onchain", post(app::pay_onchain).layer(cap()))
+        .route("/app/preflight_pay_onchain", post(app::preflight_pay_onchain).layer(cap()))
+        .route("/app/bump_receive", post(app::bump_receive).layer(cap()))
         .route("/app/get_address", post(app::get_address))
+        .route("/app/get_address_for", post(app::get_address_for))
+        .route("/app/channel_health", get(app::channel_health))
+        .route("/app/gdrive_storage_status", get(app::gdrive_storage_status))
+        .route("/app/open_channel", post(app::open_channel))
+        .route("/app/close_channel", post(app::close_channel))
+        .route("/app/channel_operation", get(app::channel_operation))
         .route("/app/payments/ids", post(app::get_payments_by_ids))
         .route("/app/payments/new", get(app::get_new_payments))
+        .route("/app/payments/query", post(app::query_payments))
         .route("/app/payments/note", put(app::update_payment_note))
+        .route("/app/profile", get(app::get_user_profile).put(app::update_user_profile))
+        .route("/app/settings", get(app::get_settings).put(app::sync_settings))
+        .route("/app/fee_policy", get(app::get_fee_policy).put(app::update_fee_policy))
+        .route("/app/restore_file_version", post(app::restore_file_version))
+        // Once the node is frozen for an export, only the routes below work.
+        .route_layer(from_fn_with_state(ln_freeze, limits::reject_while_frozen))
+        .route("/app/export_state", post(app::export_state))
+        .route("/app/decommission", post(app::decommission))
+        .route("/app/attestation_evidence", get(app::get_attestation_evidence));
+    // Cloning the routes shares the concurrency caps across revisions.
+    let tree = |api_revision: ApiRevision| {
+        routes
+            .clone()
+            .layer(from_fn_with_state(api_revision, revision::negotiate))
+    };
+
+    let router = Router::new()
+        .merge(tree(APP_NODE_R1))
+        .nest(&APP_NODE_R1.path_prefix(), tree(APP_NODE_R1))
+        .nest(&APP_NODE_R2.path_prefix(), tree(APP_NODE_R2))
         .with_state(state)
+        // Stop serving the app once we've started quiescing for a migration.
+        .layer(from_fn_with_state(
+            quiescing,
+            limits::reject_while_quiescing,
+        ))
         // Send an activity event anytime an /app endpoint is hit
         .layer(MapRequestLayer::new(move |request| {
             debug!("Sending activity event");
             let _ = activity_tx.try_send(());
+            metrics::add(&counters.app_requests, 1);
             request
-        }));
+        }))
+        // Reject requests from clients which exceed their rate limit. This is
+        // the outermost layer so rejected requests don't count as activity.
+        .layer(from_fn_with_state(rate_limiter, limits::rate_limit));
     router
 }
 
 pub(crate) struct LexeRouterState {
     pub user_pk: UserPk,
+    pub deploy_env: DeployEnv,
     pub channel_manager: NodeChannelManager,
     pub peer_manager: NodePeerManager,
+    pub counters: Arc<NodeCounters>,
+    pub persister: Arc<NodePersister>,
+    pub event_handler: NodeEventHandler,
     pub lsp_info: LspInfo,
     pub bdk_resync_tx: mpsc::Sender<oneshot::Sender<()>>,
     pub ldk_resync_tx: mpsc::Sender<oneshot::Sender<()>>,
+    /// Also used to read the node's recent event history.
+    pub test_event_tx: TestEventSender,
     pub test_event_rx: Arc<tokio::sync::Mutex<TestEventReceiver>>,
+    /// Shared with [`AppRouterState::quiescing`].
+    pub quiescing: Arc<AtomicBool>,
     pub shutdown: ShutdownChannel,
 }
 
@@ -98,11 +195,19 @@ pub(crate) struct LexeRouterState {
 ///
 /// [`LexeNodeRunApi`]: common::api::def::LexeNodeRunApi
 pub(crate) fn lexe_router(state: Arc<LexeRouterState>) -> Router<()> {
+    let health_checks = lexe::health_checks(state.persister.clone());
     Router::new()
         .route("/lexe/status", get(lexe::status))
+        .route("/lexe/metrics", get(lexe::metrics))
+        .route("/lexe/quarantined_events", get(lexe::quarantined_events))
+        .route("/lexe/event_history", post(lexe::event_history))
+        .route("/lexe/reinject_event", post(lexe::reinject_event))
         .route("/lexe/resync", post(lexe::resync))
         .route("/lexe/open_channel", post(lexe::open_channel))
         .route("/lexe/test_event", post(lexe::test_event))
         .route("/lexe/shutdown", get(lexe::shutdown))
+        .route("/lexe/quiesce", get(lexe::quiesce))
+        .route("/lexe/log_levels", post(lexe::set_log_levels))
         .with_state(state)
+        .merge(health_checks.into_router())
 }
@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use common::{
+    api::error::NodeApiError,
+    version::{
+        ApiRevision, ApiRevisions, API_REVISIONS_HEADER, APP_NODE_DEPRECATED,
+        APP_NODE_REVISIONS,
+    },
+};
+use tracing::warn;
+
+/// The `deprecation` response header, see RFC 9745.
+const DEPRECATION: &str = "deprecation";
+
+/// Applied to each app route tree with the [`ApiRevision`] it serves.
+///
+/// - Rejects the request if the client sent the [`ApiRevisions`] it supports
+///   and they don't include this revision.
+/// - Adds the revisions this node serves to the response, along with a
+///   `deprecation` header if this revision is deprecated.
+pub(super) async fn negotiate(
+    State(revision): State<ApiRevision>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(value) = request.headers().get(&API_REVISIONS_HEADER) {
+        let check = ApiRevisions::from_header_value(value).and_then(|client| {
+            anyhow::ensure!(
+                client.contains(revision),
+                "Client supports {}-{}, but requested {revision}",
+                client.min,
+                client.max,
+            );
+            Ok(())
+        });
+        if let Err(e) = check {
+            warn!("Rejecting {}: {e:#}", request.uri().path());
+            return NodeApiError::unsupported_revision(e).into_response();
+        }
+    }
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(
+        API_REVISIONS_HEADER.clone(),
+        APP_NODE_REVISIONS.to_header_value(),
+    );
+    if APP_NODE_DEPRECATED.contains(&revision) {
+        headers.insert(DEPRECATION, HeaderValue::from_static("true"));
+    }
+    response
+}
@@ -15,10 +15,11 @@
 
 use std::{borrow::Cow, fmt, str::FromStr};
 
-use anyhow::ensure;
+use anyhow::{bail, ensure};
 use common::{
     cli::Network,
     ln::{amount::Amount, invoice::LxInvoice, offer::LxOffer},
+    time::TimestampMs,
 };
 #[cfg(test)]
 use common::{ln::amount, test_utils::arbitrary};
@@ -196,6 +197,16 @@ impl PaymentUri {
 
         Ok(best)
     }
+
+    /// Resolve the best [`PaymentMethod`] for `network`, then summarize it for
+    /// display, e.g. on a payment confirmation screen.
+    ///
+    /// The preview only ever describes the method we'll actually pay, so
+    /// fields from other methods in the URI (e.g. a BIP21 label next to a
+    /// BOLT11 invoice) are ignored.
+    pub fn preview(self, network: Network) -> anyhow::Result<PaymentPreview> {
+        self.resolve_best(network)?.preview()
+    }
 }
 
 impl fmt::Display for PaymentUri {
@@ -228,6 +239,7 @@ fn flatten_invoice_into(invoice: LxInvoice, out: &mut Vec<PaymentMethod>) {
                 amount,
                 label: None,
                 message: description.clone(),
+                payjoin: None,
             }));
         }
     }
@@ -268,6 +280,54 @@ impl PaymentMethod {
             Self::Offer(x) => x.supports_network(network),
         }
     }
+
+    /// Summarize this payment method for display, e.g. on a payment
+    /// confirmation screen.
+    pub fn preview(&self) -> anyhow::Result<PaymentPreview> {
+        let preview = match self {
+            Self::Onchain(onchain) => PaymentPreview {
+                kind: PaymentMethodKind::Onchain,
+                amount: onchain.amount,
+                description: onchain.message.clone(),
+                payee_name: onchain.label.clone(),
+                expires_at: None,
+            },
+            Self::Invoice(invoice) => PaymentPreview {
+                kind: PaymentMethodKind::Invoice,
+                amount: invoice.amount(),
+                description: invoice.description_str().map(str::to_owned),
+                payee_name: None,
+                expires_at: Some(invoice.saturating_expires_at()),
+            },
+            // TODO(phlip9): remove when BOLT12 support
+            Self::Offer(_) =>
+                bail!("Lexe doesn't currently support Lightning BOLT12 Offers"),
+        };
+        Ok(preview)
+    }
+}
+
+/// The kind of a payable [`PaymentMethod`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PaymentMethodKind {
+    Onchain,
+    Invoice,
+}
+
+/// A normalized summary of a single [`PaymentMethod`], suitable for rendering a
+/// payment confirmation screen. See [`PaymentUri::preview`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaymentPreview {
+    /// The kind of the payment method.
+    pub kind: PaymentMethodKind,
+    /// The requested amount. `None` if the payer chooses the amount.
+    pub amount: Option<Amount>,
+    /// The payment description, e.g. "Coffee x2".
+    pub description: Option<String>,
+    /// The recipient/payee name, from a BIP21 label.
+    pub payee_name: Option<String>,
+    /// When the payment request expires, if ever.
+    pub expires_at: Option<TimestampMs>,
 }
 
 /// An onchain payment method, usually parsed from a standalone BTC address or
@@ -289,6 +349,17 @@ pub struct Onchain {
 
     /// The payment description.
     pub message: Option<String>,
+
+    /// The receiver's BIP78 payjoin endpoint, from the `pj` param. Only
+    /// `https://` and `http://*.onion` endpoints are accepted.
+    ///
+    /// <https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki>
+    #[cfg_attr(
+        test,
+        proptest(strategy = "arbitrary::any_option_simple_string()\
+            .prop_map(|s| s.map(|s| format!(\"https://{s}\")))")
+    )]
+    pub payjoin: Option<String>,
 }
 
 impl Onchain {
@@ -305,10 +376,30 @@ impl From<bitcoin::Address> for Onchain {
             amount: None,
             label: None,
             message: None,
+            payjoin: None,
         }
     }
 }
 
+/// Returns the payjoin endpoint if it uses a transport BIP78 allows, i.e. TLS
+/// or a Tor hidden service, since the endpoint sees our original tx.
+fn parse_payjoin_endpoint(s: &str) -> Option<String> {
+    let is_onion = |s: &str| {
+        let host = s.split(['/', '?', '#']).next().unwrap_or("");
+        let host = host.rsplit_once(':').map_or(host, |(host, _port)| host);
+        host.ends_with(".onion")
+    };
+    let allowed = match s.get(..8) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("https://") => true,
+        _ => match s.get(..7) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("http://") =>
+                is_onion(&s[7..]),
+            _ => false,
+        },
+    };
+    allowed.then(|| s.to_owned())
+}
+
 /// Parse an onchain amount in BTC, e.g. "1.0024" => 1_0024_0000 sats. This
 /// parser also rounds to the nearest satoshi amount, since on-chain payments
 /// are limited to satoshi precision.
@@ -425,6 +516,7 @@ impl Bip21Uri {
                 let mut amount = None;
                 let mut label = None;
                 let mut message = None;
+                let mut payjoin = None;
 
                 for param in uri.params {
                     match param.key.as_ref() {
@@ -434,6 +526,8 @@ impl Bip21Uri {
                             label = Some(param.value.into_owned()),
                         "message" if message.is_none() =>
                             message = Some(param.value.into_owned()),
+                        "pj" if payjoin.is_none() =>
+                            payjoin = parse_payjoin_endpoint(&param.value),
 
                         // ignore duplicates or other keys
                         _ => {}
@@ -445,6 +539,7 @@ impl Bip21Uri {
                     amount,
                     label,
                     message,
+                    payjoin,
                 });
             }
         }
@@ -485,6 +580,13 @@ impl Bip21Uri {
                     value: Cow::Borrowed(message),
                 });
             }
+
+            if let Some(payjoin) = &onchain.payjoin {
+                out.params.push(UriParam {
+                    key: Cow::Borrowed("pj"),
+                    value: Cow::Borrowed(payjoin),
+                });
+            }
         }
 
         // BOLT11 invoice param
@@ -794,6 +896,7 @@ mod test {
                     amount: None,
                     label: None,
                     message: None,
+                    payjoin: None,
                 }),
                 invoice: None,
                 offer: None,
@@ -811,6 +914,7 @@ mod test {
                     amount: None,
                     label: None,
                     message: None,
+                    payjoin: None,
                 }),
                 invoice: None,
                 offer: None,
@@ -831,6 +935,7 @@ mod test {
                     amount: Some(Amount::from_sats_u32(23_4560_0000)),
                     label: None,
                     message: None,
+                    payjoin: None,
                 }),
                 invoice: None,
                 offer: None,
@@ -851,6 +956,7 @@ mod test {
                     amount: None,
                     label: Some("Luke Jr".to_owned()),
                     message: None,
+                    payjoin: None,
                 }),
                 invoice: None,
                 offer: None,
@@ -871,6 +977,7 @@ mod test {
                     amount: Some(Amount::from_sats_u32(1)),
                     label: None,
                     message: Some("hello world".to_owned()),
+                    payjoin: None,
                 }),
                 invoice: None,
                 offer: None,
@@ -889,6 +996,30 @@ mod test {
             }),
         );
 
+        // BIP78 payjoin endpoint; plaintext http is only allowed for onions
+        let address = bitcoin::Address::from_str(
+            "bc1qm9r9x9h2c9wptaz0873vyfv8ckx2lcdx8f48ucttzqft7r0q2yasxkt2lw",
+        )
+        .unwrap();
+        let payjoin = |pj: &str| {
+            Bip21Uri::parse(&format!("bitcoin:{address}?pj={pj}"))
+                .unwrap()
+                .onchain
+                .unwrap()
+                .payjoin
+        };
+        assert_eq!(
+            payjoin("https://example.com/pj?x=1"),
+            Some("https://example.com/pj?x=1".to_owned()),
+        );
+        assert_eq!(
+            payjoin("HTTP://abcd.onion:8080/pj"),
+            Some("HTTP://abcd.onion:8080/pj".to_owned()),
+        );
+        assert_eq!(payjoin("http://example.com/pj"), None);
+        assert_eq!(payjoin("http://example.com/abcd.onion"), None);
+        assert_eq!(payjoin("ftp://example.com"), None);
+
         // BOLT12 offer
         let address_str =
             "bc1qm9r9x9h2c9wptaz0873vyfv8ckx2lcdx8f48ucttzqft7r0q2yasxkt2lw";
@@ -902,6 +1033,7 @@ mod test {
                 amount: None,
                 label: None,
                 message: None,
+                payjoin: None,
             }),
             invoice: None,
             offer: Some(offer.clone()),
@@ -930,7 +1062,7 @@ mod test {
     fn test_bip21_uri_prop_append_junk() {
         proptest!(|(address in any_mainnet_address(), junk: String)| {
             let uri = Bip21Uri {
-                onchain: Some(Onchain { address, amount: None, label: None, message: None }),
+                onchain: Some(Onchain { address, amount: None, label: None, message: None, payjoin: None }),
                 invoice: None,
                 offer: None,
             };
@@ -1047,6 +1179,79 @@ mod test {
         assert_eq!(offer.fiat_amount(), None);
     }
 
+    #[test]
+    fn test_preview() {
+        let address_str =
+            "bc1qm9r9x9h2c9wptaz0873vyfv8ckx2lcdx8f48ucttzqft7r0q2yasxkt2lw";
+        let offer_str =
+            "lno1pgqpvggzfyqv8gg09k4q35tc5mkmzr7re2nm20gw5qp5d08r3w5s6zzu4t5q";
+        let preview = |s: &str, network: Network| {
+            PaymentUri::parse(s).unwrap().preview(network)
+        };
+        let mainnet = Network::MAINNET;
+
+        // just an address
+        assert_eq!(
+            preview(address_str, mainnet).unwrap(),
+            PaymentPreview {
+                kind: PaymentMethodKind::Onchain,
+                amount: None,
+                description: None,
+                payee_name: None,
+                expires_at: None,
+            }
+        );
+
+        // BIP21 with amount, label, message, and an offer
+        assert_eq!(
+            preview(
+                &format!(
+                    "bitcoin:{address_str}?amount=0.00000001&label=Lexe&message=hello%20world&b12={offer_str}"
+                ),
+                mainnet,
+            )
+            .unwrap(),
+            PaymentPreview {
+                kind: PaymentMethodKind::Onchain,
+                amount: Some(Amount::from_sats_u32(1)),
+                description: Some("hello world".to_owned()),
+                payee_name: Some("Lexe".to_owned()),
+                expires_at: None,
+            }
+        );
+
+        // standalone offer
+        assert!(preview(offer_str, mainnet).is_err());
+
+        // wrong network
+        assert!(preview(address_str, Network::TESTNET).is_err());
+
+        // invoice is preferred, and only the invoice's fields are used
+        let invoice_str = "lnbc1gcssw9pdqqpp54dkfmzgm5cqz4hzz24mpl7xtgz55dsuh430ap4rlugvywlm4syhqsp5qqtk8n0x2wa6ajl32mp6hj8u9vs55s5lst4s2rws3he4622w08es9qyysgqcqypt3ffpp36sw424yacusmj3hy32df9g97nlwm0a3e0yxw4nd8uau2zdw85lfl5w0h3mggd5g3qswxr9lje0el8g98vul9yec59gf0zxu3eg9rhda09ducxpupsfh36ks9jez7aamsn7hpkxqpw2xyek";
+        let invoice = LxInvoice::from_str(invoice_str).unwrap();
+        assert_eq!(
+            preview(
+                &format!(
+                    "bitcoin:{address_str}?amount=0.00000001&label=Lexe&lightning={invoice_str}"
+                ),
+                mainnet,
+            )
+            .unwrap(),
+            PaymentPreview {
+                kind: PaymentMethodKind::Invoice,
+                amount: None,
+                description: None,
+                payee_name: None,
+                expires_at: Some(invoice.saturating_expires_at()),
+            }
+        );
+
+        // no payment methods we understand
+        let empty =
+            preview(&format!("bitcoin:{address_str}?req-foo=bar"), mainnet);
+        assert!(empty.is_err());
+    }
+
     #[test]
     fn test_lightning_uri_roundtrip() {
         proptest!(|(uri: LightningUri)| {
@@ -33,7 +33,8 @@ fn main() {
     let pubkey = ed25519::PublicKey::new([69; 32]);
     println!("fake pubkey we're attesting to: {pubkey}");
 
-    let evidence = attestation::quote::quote_enclave(&mut rng, &pubkey)
+    let report_data = attestation::quote::ReportData::from_cert_pk(&pubkey);
+    let evidence = attestation::quote::quote_enclave(&mut rng, &report_data)
         .expect("Failed to produce remote attestation");
 
     println!("SGX DER-serialized evidence:");
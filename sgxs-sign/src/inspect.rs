@@ -0,0 +1,261 @@
+//! Inspect and compare [`Sigstruct`]s without Intel's `sgx_sign` binary.
+//!
+//! [`parse_sigstruct`] decodes a `.sigstruct` file into a [`SigstructInfo`],
+//! which pretty-prints the fields a reviewer cares about (measurements,
+//! attributes, date, product id / svn), and [`diff`] lists the fields in which
+//! two [`SigstructInfo`]s differ, e.g. to compare a reproduced build against a
+//! Lexe release.
+
+use std::fmt;
+
+use anyhow::{ensure, format_err, Context};
+use common::{enclave::Measurement, sha256};
+use rsa::traits::SignatureScheme;
+use sgx_isa::{AttributesFlags, Miscselect};
+use sgxs::sigstruct::Sigstruct;
+
+use crate::{signer_measurement, KeyPair};
+
+/// The fixed `header` and `header2` fields of every [`Sigstruct`].
+const HEADER: [u8; 16] = [6, 0, 0, 0, 0xe1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0];
+const HEADER2: [u8; 16] =
+    [1, 1, 0, 0, 0x60, 0, 0, 0, 0x60, 0, 0, 0, 1, 0, 0, 0];
+/// The `vendor` value used by Intel-signed (architectural) enclaves.
+const VENDOR_INTEL: u32 = 0x8086;
+
+/// The decoded contents of a [`Sigstruct`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SigstructInfo {
+    /// The enclave measurement (MRENCLAVE).
+    pub enclave: Measurement,
+    /// The signer measurement (MRSIGNER), i.e. the hash of the signing key.
+    pub signer: Measurement,
+    /// Whether the signature is valid for the signing key in the sigstruct.
+    pub signature_valid: bool,
+    pub vendor: u32,
+    /// The signing date as `(year, month, day)`, or the raw value if it isn't
+    /// a valid `0xYYYYMMDD` BCD date.
+    pub date: Result<(u16, u8, u8), u32>,
+    pub attributes_flags: AttributesFlags,
+    pub attributes_xfrm: u64,
+    /// The attribute (flags, xfrm) bits which must match the values above.
+    pub attributes_mask: [u64; 2],
+    pub miscselect: Miscselect,
+    pub miscmask: u32,
+    pub isvprodid: u16,
+    pub isvsvn: u16,
+}
+
+/// A field in which two [`SigstructInfo`]s differ.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub left: String,
+    pub right: String,
+}
+
+/// Parse and check the signature of a serialized [`Sigstruct`], e.g. the
+/// contents of a `.sigstruct` file.
+pub fn parse_sigstruct(bytes: &[u8]) -> anyhow::Result<SigstructInfo> {
+    let sigstruct = Sigstruct::try_copy_from(bytes).with_context(|| {
+        format!("Sigstruct must be 1808 bytes, got {}", bytes.len())
+    })?;
+    ensure!(
+        sigstruct.header == HEADER && sigstruct.header2 == HEADER2,
+        "Invalid sigstruct header"
+    );
+    Ok(SigstructInfo::from(&sigstruct))
+}
+
+/// List the fields in which `left` and `right` differ. Empty if they're
+/// equivalent.
+pub fn diff(left: &SigstructInfo, right: &SigstructInfo) -> Vec<FieldDiff> {
+    left.fields()
+        .into_iter()
+        .zip(right.fields())
+        .filter(|((_, left), (_, right))| left != right)
+        .map(|((field, left), (_, right))| FieldDiff { field, left, right })
+        .collect()
+}
+
+impl SigstructInfo {
+    /// Whether this is a debug enclave, whose memory can be inspected by the
+    /// host, and which must never be trusted with user funds.
+    pub fn is_debug(&self) -> bool {
+        self.attributes_flags.contains(AttributesFlags::DEBUG)
+    }
+
+    /// The human-readable value of each field, in display order.
+    fn fields(&self) -> [(&'static str, String); 10] {
+        let signer = match self.signer {
+            Measurement::PROD_SIGNER => format!("{} (Lexe prod)", self.signer),
+            Measurement::DEV_SIGNER => format!("{} (Lexe dev)", self.signer),
+            signer => signer.to_string(),
+        };
+        let signature = if self.signature_valid {
+            "valid"
+        } else {
+            "INVALID"
+        };
+        let vendor = match self.vendor {
+            VENDOR_INTEL => "Intel".to_owned(),
+            vendor => format!("{vendor:#x}"),
+        };
+        let date = match self.date {
+            Ok((year, month, day)) => format!("{year:04}-{month:02}-{day:02}"),
+            Err(raw) => format!("invalid ({raw:#010x})"),
+        };
+        let [flags_mask, xfrm_mask] = self.attributes_mask;
+
+        [
+            ("enclave", self.enclave.to_string()),
+            ("signer", signer),
+            ("signature", signature.to_owned()),
+            ("vendor", vendor),
+            ("date", date),
+            ("debug", self.is_debug().to_string()),
+            (
+                "attributes",
+                format!(
+                    "flags={:?} (mask {flags_mask:#x}), \
+                     xfrm={:#x} (mask {xfrm_mask:#x})",
+                    self.attributes_flags, self.attributes_xfrm,
+                ),
+            ),
+            (
+                "miscselect",
+                format!("{:?} (mask {:#x})", self.miscselect, self.miscmask),
+            ),
+            ("isvprodid", self.isvprodid.to_string()),
+            ("isvsvn", self.isvsvn.to_string()),
+        ]
+    }
+}
+
+impl From<&Sigstruct> for SigstructInfo {
+    fn from(sigstruct: &Sigstruct) -> Self {
+        Self {
+            enclave: Measurement::new(sigstruct.enclavehash),
+            signer: signer_measurement(&sigstruct.modulus),
+            signature_valid: verify_signature(sigstruct).is_ok(),
+            vendor: sigstruct.vendor,
+            date: decode_bcd_date(sigstruct.date).ok_or(sigstruct.date),
+            attributes_flags: sigstruct.attributes.flags,
+            attributes_xfrm: sigstruct.attributes.xfrm,
+            attributes_mask: sigstruct.attributemask,
+            miscselect: sigstruct.miscselect,
+            miscmask: sigstruct.miscmask,
+            isvprodid: sigstruct.isvprodid,
+            isvsvn: sigstruct.isvsvn,
+        }
+    }
+}
+
+impl fmt::Display for SigstructInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (field, value) in self.fields() {
+            writeln!(f, "{field:>10}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { field, left, right } = self;
+        write!(f, "{field}: {left} != {right}")
+    }
+}
+
+/// Verify the sigstruct's signature against the RSA pubkey it contains.
+fn verify_signature(sigstruct: &Sigstruct) -> anyhow::Result<()> {
+    let n = rsa::BigUint::from_bytes_le(&sigstruct.modulus);
+    let e = rsa::BigUint::from(sigstruct.exponent);
+    let pubkey = rsa::RsaPublicKey::new(n, e)
+        .map_err(|err| format_err!("Invalid signing key: {err}"))?;
+
+    let (tbs1, tbs2) = sigstruct.signature_data();
+    let tbs_hash = sha256::digest_many(&[tbs1, tbs2]);
+    // SGX signatures are little-endian.
+    let mut signature = sigstruct.signature.to_vec();
+    signature.reverse();
+
+    KeyPair::padding_scheme()
+        .verify(&pubkey, tbs_hash.as_slice(), &signature)
+        .map_err(|err| format_err!("Invalid signature: {err}"))
+}
+
+/// Decode a `0xYYYYMMDD` binary-coded decimal date.
+fn decode_bcd_date(date: u32) -> Option<(u16, u8, u8)> {
+    let mut value = 0u32;
+    for shift in (0..8).rev() {
+        let digit = (date >> (shift * 4)) & 0xf;
+        if digit > 9 {
+            return None;
+        }
+        value = value * 10 + digit;
+    }
+    let (year, month, day) = (value / 10000, value / 100 % 100, value % 100);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some((year as u16, month as u8, day as u8))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sign(is_debug: bool, date_ymd: (u16, u8, u8)) -> Sigstruct {
+        let measurement = Measurement::new([0x69; 32]);
+        KeyPair::dev_signer()
+            .sign_sgxs(measurement, is_debug, Some(date_ymd))
+            .unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let sigstruct = sign(false, (2024, 3, 4));
+        let info = parse_sigstruct(sigstruct.as_ref()).unwrap();
+
+        assert_eq!(info.enclave, Measurement::new([0x69; 32]));
+        assert_eq!(info.signer, Measurement::DEV_SIGNER);
+        assert!(info.signature_valid);
+        assert_eq!(info.date, Ok((2024, 3, 4)));
+        assert!(!info.is_debug());
+        assert_eq!((info.isvprodid, info.isvsvn), (0, 0));
+
+        let mut corrupted = sigstruct.as_ref().to_vec();
+        // Flip a bit in the enclave hash
+        corrupted[960] ^= 1;
+        let info = parse_sigstruct(&corrupted).unwrap();
+        assert!(!info.signature_valid);
+
+        assert!(parse_sigstruct(&corrupted[1..]).is_err());
+        assert!(parse_sigstruct(&[0; 1808]).is_err());
+    }
+
+    #[test]
+    fn diff_fields() {
+        let prod = parse_sigstruct(sign(false, (2024, 3, 4)).as_ref()).unwrap();
+        let debug = parse_sigstruct(sign(true, (2024, 3, 4)).as_ref()).unwrap();
+        let later =
+            parse_sigstruct(sign(false, (2024, 3, 5)).as_ref()).unwrap();
+
+        assert_eq!(diff(&prod, &prod), []);
+
+        let fields = |diffs: Vec<FieldDiff>| {
+            diffs.into_iter().map(|d| d.field).collect::<Vec<_>>()
+        };
+        assert_eq!(fields(diff(&prod, &debug)), ["debug", "attributes"]);
+        assert_eq!(fields(diff(&prod, &later)), ["date"]);
+    }
+
+    #[test]
+    fn bcd_date() {
+        assert_eq!(decode_bcd_date(0x2024_0304), Some((2024, 3, 4)));
+        assert_eq!(decode_bcd_date(0x2024_0a04), None);
+        assert_eq!(decode_bcd_date(0x2024_1304), None);
+        assert_eq!(decode_bcd_date(0), None);
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! 1. signing SGX enclave binaries (`*.sgxs` files)
 //! 2. generating and manipulating the non-standard RSA keys used for (1.)
+//! 3. inspecting and comparing the resulting [`Sigstruct`]s
 //!
 //! We sign `*.sgxs` binaries with a Lexe key pair so user clients can verify
 //! their enclaves were created by Lexe.
@@ -25,6 +26,9 @@
 //! 3072-RSA+SHA256 nor exposes enough low-level primitives to derive `q1` and
 //! `q2`.
 
+/// Parse, pretty-print, and diff [`Sigstruct`]s.
+pub mod inspect;
+
 use std::fmt;
 
 use anyhow::{ensure, format_err};
@@ -153,12 +157,7 @@ impl KeyPair {
     ///
     /// See: <https://github.com/intel/linux-sgx/blob/sgx_2.23/sdk/sign_tool/SignTool/manage_metadata.cpp#L1807>
     pub fn signer_measurement(&self) -> enclave::Measurement {
-        let modulus = self.n();
-        let mut modulus_buf = [0u8; 384];
-        modulus_buf[..modulus.len()].copy_from_slice(&modulus);
-
-        let measurement = sha256::digest(&modulus_buf);
-        enclave::Measurement::new(measurement.into_inner())
+        signer_measurement(&self.n())
     }
 
     fn padding_scheme() -> Pkcs1v15Sign {
@@ -279,6 +278,16 @@ impl SgxRsaOps for KeyPair {
     }
 }
 
+/// The SHA-256 hash of a 3072-bit RSA pubkey modulus given in little endian
+/// byte order. See [`KeyPair::signer_measurement`].
+fn signer_measurement(modulus_le: &[u8]) -> enclave::Measurement {
+    let mut modulus_buf = [0u8; 384];
+    modulus_buf[..modulus_le.len()].copy_from_slice(modulus_le);
+
+    let measurement = sha256::digest(&modulus_buf);
+    enclave::Measurement::new(measurement.into_inner())
+}
+
 /// Compute the `q1` and `q2` values from the RSA pubkey modulus, `n`, and the
 /// signature, `sig_slice`. These values then go into their corresponding
 /// [`Sigstruct`] fields.